@@ -36,11 +36,12 @@ export class SQLServer {
       port: options.sqlPort,
       nonce: options.sqlNonce,
       checkAuth: async ({ request, user }) => {
-        const { password } = await checkSqlAuth(request, user);
+        const { password, superuser } = await checkSqlAuth(request, user);
 
         // Strip securityContext to improve speed deserialization
         return {
-          password
+          password,
+          superuser: superuser || false
         };
       },
       meta: async ({ request, user }) => {
@@ -47,7 +47,8 @@ type CheckAuthFn =
  */
 type CheckSQLAuthSuccessResponse = {
   password: string | null,
-  securityContext?: any
+  securityContext?: any,
+  superuser?: boolean
 };
 
 /**
@@ -42,6 +42,8 @@ struct CheckAuthRequest {
 #[derive(Debug, Deserialize)]
 struct CheckAuthResponse {
     password: Option<String>,
+    #[serde(default)]
+    superuser: bool,
 }
 
 #[async_trait]
@@ -69,6 +71,8 @@ impl SqlAuthService for NodeBridgeAuthService {
             AuthContext {
                 access_token: user.unwrap_or_else(|| "fake".to_string()),
                 base_path: "fake".to_string(),
+                claims: None,
+                superuser: response.superuser,
             },
             response.password,
         ))
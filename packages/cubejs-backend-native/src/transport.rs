@@ -1,4 +1,4 @@
-use log::{debug, error, trace};
+use log::{error, trace};
 use neon::prelude::*;
 
 use async_trait::async_trait;
@@ -77,55 +77,44 @@ impl TransportService for NodeBridgeTransport {
     ) -> Result<V1LoadResponse, CubeError> {
         trace!("[transport] Request ->");
 
-        let request_id = Uuid::new_v4().to_string();
-        let mut span_counter: u32 = 1;
-
-        loop {
-            let extra = serde_json::to_string(&LoadRequest {
-                request: TransportRequest {
-                    id: format!("{}-span-{}", request_id, span_counter),
-                },
-                user: Some(ctx.access_token.clone()),
-                query: query.clone(),
-            })?;
-
-            let response: serde_json::Value = call_js_with_channel_as_callback(
-                self.channel.clone(),
-                self.on_load.clone(),
-                Some(extra),
-            )
-            .await?;
-            trace!("[transport] Request <- {:?}", response);
-
-            let load_err = match serde_json::from_value::<V1LoadResponse>(response.clone()) {
-                Ok(r) => {
-                    return Ok(r);
-                }
-                Err(err) => err,
-            };
-
-            if let Ok(res) = serde_json::from_value::<V1Error>(response) {
-                if res.error.to_lowercase() == *"continue wait" {
-                    debug!(
-                        "[transport] load - retrying request (continue wait) requestId: {}, span: {}",
-                        request_id, span_counter
-                    );
-
-                    span_counter += 1;
-
-                    continue;
-                } else {
-                    error!(
-                        "[transport] load - strange response, success which contains error: {:?}",
-                        res
-                    );
-
-                    return Err(CubeError::internal(res.error));
-                }
-            };
-
-            return Err(CubeError::user(load_err.to_string()));
-        }
+        let extra = serde_json::to_string(&LoadRequest {
+            request: TransportRequest {
+                id: format!("{}-span-1", Uuid::new_v4()),
+            },
+            user: Some(ctx.access_token.clone()),
+            query,
+        })?;
+
+        let response: serde_json::Value = call_js_with_channel_as_callback(
+            self.channel.clone(),
+            self.on_load.clone(),
+            Some(extra),
+        )
+        .await?;
+        trace!("[transport] Request <- {:?}", response);
+
+        let load_err = match serde_json::from_value::<V1LoadResponse>(response.clone()) {
+            Ok(r) => {
+                return Ok(r);
+            }
+            Err(err) => err,
+        };
+
+        if let Ok(res) = serde_json::from_value::<V1Error>(response) {
+            // Request is repeated by CubeScan with backoff
+            if res.error.to_lowercase() == *"continue wait" {
+                return Err(CubeError::continue_wait());
+            }
+
+            error!(
+                "[transport] load - strange response, success which contains error: {:?}",
+                res
+            );
+
+            return Err(CubeError::internal(res.error));
+        };
+
+        Err(CubeError::user(load_err.to_string()))
     }
 }
 
@@ -30,6 +30,7 @@ export class CubeToMetaTransformer {
         name: cube.name,
         title: cubeTitle,
         description: cube.description,
+        meta: cube.meta,
         connectedComponent: this.joinGraph.connectedComponents()[cube.name],
         measures: R.compose(
           R.map((nameToMetric) => ({
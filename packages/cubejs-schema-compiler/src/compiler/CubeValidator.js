@@ -406,6 +406,7 @@ const cubeSchema = Joi.object().keys({
   sqlAlias: Joi.string(),
   dataSource: Joi.string(),
   description: Joi.string(),
+  meta: Joi.any(),
   rewriteQueries: Joi.boolean().strict(),
   joins: Joi.object().pattern(identifierRegex, Joi.object().keys({
     sql: Joi.func().required(),
@@ -10,6 +10,10 @@ pub struct V1CubeMeta {
     pub dimensions: Vec<crate::models::V1CubeMetaDimension>,
     #[serde(rename = "segments")]
     pub segments: Vec<crate::models::V1CubeMetaSegment>,
+    #[serde(rename = "joins", skip_serializing_if = "Option::is_none")]
+    pub joins: Option<Vec<crate::models::V1CubeMetaJoin>>,
+    #[serde(rename = "meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
 }
 
 impl V1CubeMeta {
@@ -25,6 +29,8 @@ impl V1CubeMeta {
             measures,
             dimensions,
             segments,
+            joins: None,
+            meta: None,
         }
     }
 }
@@ -16,6 +16,8 @@ pub struct V1LoadRequestQuery {
     pub offset: Option<i32>,
     #[serde(rename = "filters", skip_serializing_if = "Option::is_none")]
     pub filters: Option<Vec<crate::models::V1LoadRequestQueryFilterItem>>,
+    #[serde(rename = "ungrouped", skip_serializing_if = "Option::is_none")]
+    pub ungrouped: Option<bool>,
 }
 
 impl V1LoadRequestQuery {
@@ -29,6 +31,7 @@ impl V1LoadRequestQuery {
             limit: None,
             offset: None,
             filters: None,
+            ungrouped: None,
         }
     }
 }
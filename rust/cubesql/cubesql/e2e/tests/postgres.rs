@@ -9,8 +9,8 @@ use rust_decimal::prelude::*;
 use tokio::time::sleep;
 
 use super::utils::escape_snapshot_name;
-use chrono::{DateTime, NaiveDateTime, Utc};
-use tokio_postgres::{NoTls, Row};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use tokio_postgres::{error::SqlState, NoTls, Row};
 
 use super::basic::{AsyncTestConstructorResult, AsyncTestSuite, RunResult};
 
@@ -109,6 +109,10 @@ impl PostgresIntegrationTestSuite {
                         let value: i64 = row.get(idx);
                         values.push(value.to_string());
                     }
+                    21 => {
+                        let value: i16 = row.get(idx);
+                        values.push(value.to_string());
+                    }
                     23 => {
                         let value: i32 = row.get(idx);
                         values.push(value.to_string());
@@ -121,10 +125,19 @@ impl PostgresIntegrationTestSuite {
                         let value: bool = row.get(idx);
                         values.push(value.to_string());
                     }
+                    700 => {
+                        let value: f32 = row.get(idx);
+                        values.push(value.to_string());
+                    }
                     701 => {
                         let value: f64 = row.get(idx);
                         values.push(value.to_string());
                     }
+                    // date
+                    1082 => {
+                        let value: NaiveDate = row.get(idx);
+                        values.push(value.to_string());
+                    }
                     // timestamp
                     1114 => {
                         let value: NaiveDateTime = row.get(idx);
@@ -234,6 +247,56 @@ impl PostgresIntegrationTestSuite {
         Ok(())
     }
 
+    // Prepared statements request all result columns in binary format
+    async fn test_prepare_binary_types(&self) -> RunResult<()> {
+        let stmt = self
+            .client
+            .prepare(
+                r#"SELECT
+                    CAST(1 AS smallint) AS i2,
+                    CAST(2 AS int) AS i4,
+                    CAST(3 AS bigint) AS i8,
+                    CAST(1.5 AS real) AS f4,
+                    2.5 AS f8,
+                    true AS b,
+                    'test' AS t,
+                    CAST('2022-04-25' AS date) AS d,
+                    CAST('2022-04-25 16:25:01.164774' AS timestamp) AS tsmp,
+                    CAST(123.45 AS decimal(10, 2)) AS n
+                "#,
+            )
+            .await
+            .unwrap();
+
+        let row = self.client.query_one(&stmt, &[]).await.unwrap();
+
+        assert_eq!(
+            row.columns()
+                .into_iter()
+                .map(|col| col.type_().oid())
+                .collect::<Vec<u32>>(),
+            vec![21, 23, 20, 700, 701, 16, 25, 1082, 1114, 1700]
+        );
+        assert_eq!(row.get::<_, i16>(0), 1);
+        assert_eq!(row.get::<_, i32>(1), 2);
+        assert_eq!(row.get::<_, i64>(2), 3);
+        assert_eq!(row.get::<_, f32>(3), 1.5);
+        assert_eq!(row.get::<_, f64>(4), 2.5);
+        assert_eq!(row.get::<_, bool>(5), true);
+        assert_eq!(row.get::<_, String>(6), "test".to_string());
+        assert_eq!(row.get::<_, NaiveDate>(7), NaiveDate::from_ymd(2022, 4, 25));
+        assert_eq!(
+            row.get::<_, NaiveDateTime>(8),
+            NaiveDate::from_ymd(2022, 4, 25).and_hms_micro(16, 25, 1, 164774)
+        );
+        assert_eq!(
+            row.get::<_, Decimal>(9),
+            Decimal::from_str("123.45").unwrap()
+        );
+
+        Ok(())
+    }
+
     // This test tests paging on the service side which uses stream of RecordBatches to stream this query
     async fn test_stream_all(&self) -> RunResult<()> {
         let stmt = self
@@ -274,6 +337,33 @@ impl PostgresIntegrationTestSuite {
 
         Ok(())
     }
+
+    // Slow query is canceled from another connection by CancelRequest
+    async fn test_cancel_query(&self) -> RunResult<()> {
+        let cancel_token = self.client.cancel_token();
+        let cancel_handle = tokio::spawn(async move {
+            sleep(Duration::from_millis(250)).await;
+
+            cancel_token
+                .cancel_query(NoTls)
+                .await
+                .expect("Unable to send cancel request");
+        });
+
+        let res = self
+            .client
+            .simple_query(
+                "SELECT COUNT(*) FROM information_schema.testing_dataset a, information_schema.testing_dataset b",
+            )
+            .await;
+
+        cancel_handle.await.unwrap();
+
+        let err = res.expect_err("Query must be canceled");
+        assert_eq!(err.code(), Some(&SqlState::QUERY_CANCELED));
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -285,8 +375,10 @@ impl AsyncTestSuite for PostgresIntegrationTestSuite {
     async fn run(&mut self) -> RunResult<()> {
         self.test_prepare().await?;
         self.test_prepare_empty_query().await?;
+        self.test_prepare_binary_types().await?;
         self.test_stream_all().await?;
         self.test_stream_single().await?;
+        self.test_cancel_query().await?;
         self.test_snapshot_execute_query(
             "SELECT COUNT(*) count, status FROM Orders GROUP BY status".to_string(),
             None,
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 use cubeclient::models::{V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure, V1CubeMetaSegment};
 use regex::Regex;
@@ -19,63 +19,254 @@ pub enum Selection {
     Segment(V1CubeMetaSegment),
 }
 
+/// Reference to a member from query, it can be qualified by relation (alias or name of cube)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberReference {
+    pub relation: Option<String>,
+    pub name: String,
+}
+
+impl MemberReference {
+    pub fn unqualified(name: String) -> Self {
+        Self {
+            relation: None,
+            name,
+        }
+    }
+
+    pub fn from_identifiers(identifiers: &[ast::Ident]) -> CompilationResult<Self> {
+        match identifiers {
+            [name] => Ok(Self::unqualified(name.value.to_string())),
+            [relation, name] => Ok(Self {
+                relation: Some(relation.value.to_string()),
+                name: name.value.to_string(),
+            }),
+            _ => Err(CompilationError::Unsupported(format!(
+                "Unsupported compound identifier: {}",
+                identifiers
+                    .iter()
+                    .map(|i| i.value.to_string())
+                    .collect::<Vec<_>>()
+                    .join(".")
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for MemberReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.relation {
+            Some(relation) => write!(f, "{}.{}", relation, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Cube which is used in FROM or JOIN of query
+#[derive(Debug, Clone)]
+pub struct QueryRelation {
+    pub alias: Option<String>,
+    pub meta: V1CubeMeta,
+}
+
+impl QueryRelation {
+    pub fn new(alias: Option<String>, meta: &V1CubeMeta) -> Self {
+        Self {
+            alias,
+            meta: meta.clone(),
+        }
+    }
+
+    fn is_named(&self, name: &str) -> bool {
+        match &self.alias {
+            Some(alias) => alias.eq_ignore_ascii_case(name),
+            None => self.meta.name.eq_ignore_ascii_case(name),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct QueryContext {
+    /// Cube from FROM
     pub meta: V1CubeMeta,
+    /// All cubes of query, including cube from FROM
+    relations: Vec<QueryRelation>,
     aliases: HashMap<String, Selection>,
 }
 
 impl QueryContext {
     pub fn new(meta: &V1CubeMeta) -> QueryContext {
-        QueryContext {
-            meta: meta.clone(),
+        Self::new_with_relations(vec![QueryRelation::new(None, meta)])
+            .expect("Single relation must be valid")
+    }
+
+    /// Context for cubes from FROM (the first one) and JOINs, they are queried in one request
+    pub fn new_with_relations(relations: Vec<QueryRelation>) -> CompilationResult<QueryContext> {
+        let meta = relations
+            .first()
+            .ok_or_else(|| CompilationError::Internal("Query without relations".to_string()))?
+            .meta
+            .clone();
+
+        for (idx, relation) in relations.iter().enumerate() {
+            for previous in relations[..idx].iter() {
+                if previous.meta.name == relation.meta.name {
+                    return Err(CompilationError::Unsupported(format!(
+                        "Unable to join cube '{}' with itself",
+                        relation.meta.name
+                    )));
+                }
+
+                if let Some(alias) = &relation.alias {
+                    if previous.is_named(alias) {
+                        return Err(CompilationError::User(format!(
+                            "table name \"{}\" specified more than once",
+                            alias
+                        )));
+                    }
+                }
+            }
+        }
+
+        Ok(QueryContext {
+            meta,
+            relations,
             aliases: HashMap::new(),
+        })
+    }
+
+    /// Cubes which can be referenced by the member: the single one for qualified reference or all
+    pub fn find_relations(&self, relation: &Option<String>) -> CompilationResult<Vec<&V1CubeMeta>> {
+        match relation {
+            None => Ok(self.relations.iter().map(|r| &r.meta).collect()),
+            Some(name) => {
+                // Name of cube can be used for the cube with alias too
+                let found = self
+                    .relations
+                    .iter()
+                    .find(|r| r.is_named(name))
+                    .or_else(|| {
+                        self.relations
+                            .iter()
+                            .find(|r| r.meta.name.eq_ignore_ascii_case(name))
+                    });
+
+                match found {
+                    Some(relation) => Ok(vec![&relation.meta]),
+                    None => Err(CompilationError::User(format!(
+                        "missing FROM-clause entry for table \"{}\"",
+                        name
+                    ))),
+                }
+            }
         }
     }
 
-    pub fn find_selection_for_identifier(
+    /// Member of the referenced cube, unqualified name must match members of the single cube
+    fn find_in_relations<T>(
         &self,
-        identifier: &String,
-        check_alias: bool,
-    ) -> Option<Selection> {
-        for dimension in self.meta.dimensions.iter() {
+        reference: &MemberReference,
+        find: impl Fn(&V1CubeMeta) -> Option<T>,
+    ) -> CompilationResult<Option<T>> {
+        let mut result = None;
+
+        for meta in self.find_relations(&reference.relation)? {
+            if let Some(found) = find(meta) {
+                if result.is_some() {
+                    return Err(CompilationError::AmbiguousColumn(format!(
+                        "column reference \"{}\" is ambiguous",
+                        reference
+                    )));
+                }
+
+                result = Some(found);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn find_selection_in_cube(meta: &V1CubeMeta, identifier: &String) -> Option<Selection> {
+        for dimension in meta.dimensions.iter() {
             if dimension.get_real_name().eq(identifier) {
                 return Some(Selection::Dimension(dimension.clone()));
             }
         }
 
-        for measure in self.meta.measures.iter() {
+        for measure in meta.measures.iter() {
             if measure.get_real_name().eq(identifier) {
                 return Some(Selection::Measure(measure.clone()));
             }
         }
 
-        for segment in self.meta.segments.iter() {
+        for segment in meta.segments.iter() {
             if segment.get_real_name().eq(identifier) {
                 return Some(Selection::Segment(segment.clone()));
             }
         }
 
-        if check_alias {
-            self.aliases
-                .get(identifier)
-                .map(|selection| selection.clone())
+        None
+    }
+
+    pub fn find_selection_for_reference(
+        &self,
+        reference: &MemberReference,
+        check_alias: bool,
+    ) -> CompilationResult<Option<Selection>> {
+        let alias = if check_alias && reference.relation.is_none() {
+            self.aliases.get(&reference.name)
         } else {
             None
+        };
+
+        match self.find_in_relations(reference, |meta| {
+            Self::find_selection_in_cube(meta, &reference.name)
+        }) {
+            Ok(Some(selection)) => Ok(Some(selection)),
+            Ok(None) => Ok(alias.cloned()),
+            // Output column is used for ambiguous name as in ORDER BY of PostgreSQL
+            Err(CompilationError::AmbiguousColumn(_)) if alias.is_some() => Ok(alias.cloned()),
+            Err(err) => Err(err),
         }
     }
 
-    pub fn find_dimension_for_identifier(
+    pub fn find_selection_for_identifier(
         &self,
         identifier: &String,
-    ) -> Option<V1CubeMetaDimension> {
-        for dimension in self.meta.dimensions.iter() {
-            if dimension.get_real_name().eq_ignore_ascii_case(identifier) {
-                return Some(dimension.clone());
-            }
-        }
+        check_alias: bool,
+    ) -> CompilationResult<Option<Selection>> {
+        self.find_selection_for_reference(
+            &MemberReference::unqualified(identifier.clone()),
+            check_alias,
+        )
+    }
 
-        None
+    pub fn find_dimension_for_identifier(
+        &self,
+        reference: &MemberReference,
+    ) -> CompilationResult<Option<V1CubeMetaDimension>> {
+        self.find_in_relations(reference, |meta| {
+            meta.dimensions
+                .iter()
+                .find(|dimension| {
+                    dimension
+                        .get_real_name()
+                        .eq_ignore_ascii_case(&reference.name)
+                })
+                .cloned()
+        })
+    }
+
+    /// Dimensions of cubes for wildcard: all cubes or the single one for qualified wildcard
+    pub fn find_dimensions_for_wildcard(
+        &self,
+        relation: &Option<String>,
+    ) -> CompilationResult<Vec<V1CubeMetaDimension>> {
+        Ok(self
+            .find_relations(relation)?
+            .into_iter()
+            .flat_map(|meta| meta.dimensions.iter().cloned())
+            .collect())
     }
 
     fn find_selection_for_binary_op(
@@ -89,7 +280,9 @@ impl QueryContext {
             if let Some(identifiers) = left_regexp.captures(expr_as_str) {
                 let identifier = identifiers.name("column").unwrap().as_str();
                 let result = self
-                    .find_dimension_for_identifier(&identifier.to_string())
+                    .find_dimension_for_identifier(&MemberReference::unqualified(
+                        identifier.to_string(),
+                    ))?
                     .map(|dimension| Selection::TimeDimension(dimension, "quarter".to_string()));
 
                 return Ok(result);
@@ -105,18 +298,10 @@ impl QueryContext {
         match expr {
             ast::Expr::Function(f) => Ok(Some(self.find_selection_for_function(f)?)),
             ast::Expr::CompoundIdentifier(i) => {
-                // @todo We need a context with main table rel
-                if i.len() == 2 {
-                    Ok(self.find_selection_for_identifier(&i[1].value.to_string(), true))
-                } else {
-                    Err(CompilationError::Unsupported(format!(
-                        "Unsupported compound identifier: {:?}",
-                        expr
-                    )))
-                }
+                self.find_selection_for_reference(&MemberReference::from_identifiers(i)?, true)
             }
             ast::Expr::Identifier(i) => {
-                Ok(self.find_selection_for_identifier(&i.value.to_string(), true))
+                self.find_selection_for_identifier(&i.value.to_string(), true)
             }
             _ => {
                 return Err(CompilationError::Unsupported(format!(
@@ -136,18 +321,10 @@ impl QueryContext {
             ast::Expr::BinaryOp { .. } => self.find_selection_for_binary_op(&expr.to_string()),
             ast::Expr::Function(f) => Ok(Some(self.find_selection_for_function(f)?)),
             ast::Expr::CompoundIdentifier(i) => {
-                // @todo We need a context with main table rel
-                if i.len() == 2 {
-                    Ok(self.find_selection_for_identifier(&i[1].value.to_string(), false))
-                } else {
-                    Err(CompilationError::Unsupported(format!(
-                        "Unsupported compound identifier: {:?}",
-                        expr
-                    )))
-                }
+                self.find_selection_for_reference(&MemberReference::from_identifiers(i)?, false)
             }
             ast::Expr::Identifier(i) => {
-                Ok(self.find_selection_for_identifier(&i.value.to_string(), false))
+                self.find_selection_for_identifier(&i.value.to_string(), false)
             }
             _ => {
                 return Err(CompilationError::Unsupported(format!(
@@ -158,20 +335,24 @@ impl QueryContext {
         }
     }
 
-    fn unpack_identifier_from_arg(&self, arg: &ast::FunctionArg) -> CompilationResult<String> {
+    fn unpack_identifier_from_arg(
+        &self,
+        arg: &ast::FunctionArg,
+    ) -> CompilationResult<MemberReference> {
         let argument = match arg {
             ast::FunctionArg::Named { arg, .. } => arg,
             ast::FunctionArg::Unnamed(expr) => expr,
         };
 
         let identifier = match argument {
-            ast::FunctionArgExpr::Wildcard => "*".to_string(),
+            ast::FunctionArgExpr::Wildcard => MemberReference::unqualified("*".to_string()),
             ast::FunctionArgExpr::Expr(expr) => match expr {
-                ast::Expr::Identifier(i) => i.value.to_string().to_lowercase(),
+                ast::Expr::Identifier(i) => {
+                    MemberReference::unqualified(i.value.to_string().to_lowercase())
+                }
                 ast::Expr::CompoundIdentifier(i) => {
-                    // @todo We need a context with main table rel
                     if i.len() == 2 {
-                        i[1].value.to_string()
+                        MemberReference::from_identifiers(i)?
                     } else {
                         return Err(CompilationError::Unsupported(format!(
                             "Unsupported compound identifier in argument: {:?}",
@@ -234,7 +415,7 @@ impl QueryContext {
 
                 let possible_dimension_name = self.unpack_identifier_from_arg(&f.args[0])?;
 
-                self.find_dimension_for_identifier(&possible_dimension_name)
+                self.find_dimension_for_identifier(&possible_dimension_name)?
             }
             _ => {
                 return Err(CompilationError::User(format!(
@@ -284,8 +465,12 @@ impl QueryContext {
         f: &ast::Function,
     ) -> CompilationResult<Selection> {
         match f.args.as_slice() {
-            [ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(ast::Expr::Value(ast::Value::SingleQuotedString(granularity)))), ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(ast::Expr::Identifier(column)))] => {
-                let possible_dimension_name = column.value.to_string();
+            [ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(ast::Expr::Value(ast::Value::SingleQuotedString(granularity)))), ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(column @ (ast::Expr::Identifier(_) | ast::Expr::CompoundIdentifier(_))))] => {
+                let possible_dimension_name = match column {
+                    ast::Expr::CompoundIdentifier(i) => MemberReference::from_identifiers(i)?,
+                    ast::Expr::Identifier(i) => MemberReference::unqualified(i.value.to_string()),
+                    _ => unreachable!("Column must be identifier"),
+                };
 
                 match granularity.as_str() {
                     "second" | "minute" | "hour" | "day" | "week" | "month" | "quarter" | "year" => (),
@@ -297,7 +482,7 @@ impl QueryContext {
                     }
                 };
 
-                if let Some(r) = self.find_dimension_for_identifier(&possible_dimension_name) {
+                if let Some(r) = self.find_dimension_for_identifier(&possible_dimension_name)? {
                     if r.is_time() {
                         Ok(Selection::TimeDimension(r, granularity.clone()))
                     } else {
@@ -363,7 +548,7 @@ impl QueryContext {
 
                 let possible_dimension_name = self.unpack_identifier_from_arg(&date_sub.args[0])?;
 
-                if let Some(r) = self.find_dimension_for_identifier(&possible_dimension_name) {
+                if let Some(r) = self.find_dimension_for_identifier(&possible_dimension_name)? {
                     if r.is_time() {
                         Ok(Selection::TimeDimension(r, granularity))
                     } else {
@@ -382,7 +567,7 @@ impl QueryContext {
             [ast::FunctionArg::Unnamed(_)] => {
                 let possible_dimension_name = self.unpack_identifier_from_arg(&f.args[0])?;
 
-                if let Some(r) = self.find_dimension_for_identifier(&possible_dimension_name) {
+                if let Some(r) = self.find_dimension_for_identifier(&possible_dimension_name)? {
                     Ok(Selection::TimeDimension(r, "day".to_string()))
                 } else {
                     return Err(CompilationError::User(format!(
@@ -420,7 +605,7 @@ impl QueryContext {
         };
 
         let measure_name = match argument {
-            ast::FunctionArgExpr::Wildcard => "*".to_string(),
+            ast::FunctionArgExpr::Wildcard => MemberReference::unqualified("*".to_string()),
             ast::FunctionArgExpr::Expr(expr) => match expr {
                 ast::Expr::Value(ast::Value::Number(n, is_negative)) => {
                     let prefix = if *is_negative {
@@ -438,13 +623,12 @@ impl QueryContext {
                         )));
                     }
 
-                    "*".to_string()
+                    MemberReference::unqualified("*".to_string())
                 }
-                ast::Expr::Identifier(i) => i.value.to_string(),
+                ast::Expr::Identifier(i) => MemberReference::unqualified(i.value.to_string()),
                 ast::Expr::CompoundIdentifier(i) => {
-                    // @todo We need a context with main table rel
                     if i.len() == 2 {
-                        i[1].value.to_string()
+                        MemberReference::from_identifiers(i)?
                     } else {
                         return Err(CompilationError::Unsupported(format!(
                             "Unsupported compound identifier in argument: {:?}",
@@ -469,7 +653,8 @@ impl QueryContext {
 
         let fn_name = f.name.to_string().to_ascii_lowercase();
         let (selection_opt, call_agg_type) = if fn_name.eq(&"count".to_string()) && !f.distinct {
-            if &measure_name == "*" {
+            if &measure_name.name == "*" {
+                // COUNT(*) is a count measure of the cube from FROM
                 let measure_for_argument = self.meta.measures.iter().find(|measure| {
                     if measure.agg_type.is_some() {
                         let agg_type = measure.agg_type.clone().unwrap();
@@ -492,7 +677,7 @@ impl QueryContext {
                 }
             } else {
                 (
-                    self.find_selection_for_identifier(&measure_name, true),
+                    self.find_selection_for_reference(&measure_name, true)?,
                     "count".to_string(),
                 )
             }
@@ -503,7 +688,7 @@ impl QueryContext {
                 call_agg_type += &"Distinct".to_string();
             };
 
-            if measure_name == "*" {
+            if &measure_name.name == "*" {
                 return Err(CompilationError::User(format!(
                     "Unable to use '{}' as argument to aggregation function '{}()' (only COUNT() supported)",
                     measure_name,
@@ -512,7 +697,7 @@ impl QueryContext {
             }
 
             (
-                self.find_selection_for_identifier(&measure_name, true),
+                self.find_selection_for_reference(&measure_name, true)?,
                 call_agg_type,
             )
         };
@@ -560,12 +745,17 @@ impl QueryContext {
         if f.args.len() == 1 {
             let possible_measure_name = self.unpack_identifier_from_arg(&f.args[0])?;
 
-            if let Some(r) = self.meta.measures.iter().find(|measure| {
-                measure
-                    .get_real_name()
-                    .eq_ignore_ascii_case(&possible_measure_name)
-            }) {
-                Ok(Selection::Measure(r.clone()))
+            if let Some(r) = self.find_in_relations(&possible_measure_name, |meta| {
+                meta.measures
+                    .iter()
+                    .find(|measure| {
+                        measure
+                            .get_real_name()
+                            .eq_ignore_ascii_case(&possible_measure_name.name)
+                    })
+                    .cloned()
+            })? {
+                Ok(Selection::Measure(r))
             } else {
                 Err(CompilationError::User(format!(
                     "Unable to find measure with name '{}' for {}",
@@ -80,3 +80,47 @@ pub fn least_coercion(lhs_type: &DataType, rhs_type: &DataType) -> Option<DataTy
 
     hack_ty.or_else(|| numerical_coercion(lhs_type, rhs_type))
 }
+
+fn signed_integer_width(dt: &DataType) -> Option<u8> {
+    match dt {
+        DataType::Int8 => Some(8),
+        DataType::Int16 => Some(16),
+        DataType::Int32 => Some(32),
+        DataType::Int64 => Some(64),
+        _ => None,
+    }
+}
+
+fn is_float(dt: &DataType) -> bool {
+    matches!(
+        dt,
+        DataType::Float16 | DataType::Float32 | DataType::Float64
+    )
+}
+
+/// Unified type of COALESCE arguments, NULL literals don't affect it
+pub fn coalesce_coercion(types: &[DataType]) -> Option<DataType> {
+    let mut result = DataType::Null;
+
+    for ty in types.iter().filter(|ty| **ty != DataType::Null) {
+        if *ty == result {
+            continue;
+        }
+
+        result = if result == DataType::Null {
+            ty.clone()
+        } else if is_numeric(&result) && is_numeric(ty) {
+            match (signed_integer_width(&result), signed_integer_width(ty)) {
+                // int2 and int4 are unified to int4, like in PostgreSQL
+                (Some(lhs), Some(rhs)) if lhs >= rhs => result,
+                (Some(_), Some(_)) => ty.clone(),
+                _ if is_float(&result) || is_float(ty) => DataType::Float64,
+                _ => numerical_coercion(&result, ty).unwrap_or(DataType::Int64),
+            }
+        } else {
+            if_coercion(&result, ty)?
+        };
+    }
+
+    Some(result)
+}
@@ -0,0 +1,571 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use datafusion::error::{DataFusionError, Result};
+
+/// Converts nanoseconds since the epoch to datetime
+pub fn timestamp_ns_to_datetime(nanos: i64) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp(
+        nanos.div_euclid(1_000_000_000),
+        nanos.rem_euclid(1_000_000_000) as u32,
+    )
+}
+
+/// Canonical name of timestamp field with all spellings accepted by PostgreSQL
+pub fn timestamp_field(field: &str) -> Option<&'static str> {
+    let field = match field.to_lowercase().as_str() {
+        "microseconds" | "microsecond" | "us" | "usec" | "usecs" => "microseconds",
+        "milliseconds" | "millisecond" | "ms" | "msec" | "msecs" => "milliseconds",
+        "second" | "seconds" | "s" | "sec" | "secs" => "second",
+        "minute" | "minutes" | "m" | "min" | "mins" => "minute",
+        "hour" | "hours" | "h" | "hr" | "hrs" => "hour",
+        "day" | "days" | "d" => "day",
+        "week" | "weeks" | "w" => "week",
+        "month" | "months" | "mon" | "mons" => "month",
+        "quarter" | "qtr" => "quarter",
+        "year" | "years" | "y" | "yr" | "yrs" => "year",
+        "decade" | "decades" | "dec" => "decade",
+        "century" | "centuries" | "c" | "cent" => "century",
+        "millennium" | "millenniums" | "millenium" | "mil" | "mils" => "millennium",
+        "epoch" => "epoch",
+        "dow" => "dow",
+        "isodow" => "isodow",
+        "doy" => "doy",
+        "isoyear" => "isoyear",
+        _ => return None,
+    };
+
+    Some(field)
+}
+
+fn unsupported_field(field: &str) -> DataFusionError {
+    DataFusionError::Execution(format!("timestamp units \"{}\" not recognized", field))
+}
+
+/// date_trunc of PostgreSQL: weeks start on Monday, centuries and millenniums start on year 1
+pub fn date_trunc(field: &str, nanos: i64) -> Result<i64> {
+    let value = timestamp_ns_to_datetime(nanos);
+    let date = value.date();
+    let year = value.year();
+    let year_start = |year: i32| NaiveDate::from_ymd_opt(year, 1, 1);
+
+    let truncated = match timestamp_field(field) {
+        Some("microseconds") => return Ok(nanos - nanos.rem_euclid(1_000)),
+        Some("milliseconds") => return Ok(nanos - nanos.rem_euclid(1_000_000)),
+        Some("second") => return Ok(nanos - nanos.rem_euclid(1_000_000_000)),
+        Some("minute") => date.and_hms_opt(value.hour(), value.minute(), 0),
+        Some("hour") => date.and_hms_opt(value.hour(), 0, 0),
+        Some("day") => date.and_hms_opt(0, 0, 0),
+        Some("week") => (date - Duration::days(date.weekday().num_days_from_monday() as i64))
+            .and_hms_opt(0, 0, 0),
+        Some("month") => NaiveDate::from_ymd_opt(year, date.month(), 1)
+            .and_then(|date| date.and_hms_opt(0, 0, 0)),
+        Some("quarter") => NaiveDate::from_ymd_opt(year, date.month0() / 3 * 3 + 1, 1)
+            .and_then(|date| date.and_hms_opt(0, 0, 0)),
+        Some("year") => year_start(year).and_then(|date| date.and_hms_opt(0, 0, 0)),
+        Some("decade") => {
+            year_start(year - year.rem_euclid(10)).and_then(|date| date.and_hms_opt(0, 0, 0))
+        }
+        Some("century") => year_start((year - 1).div_euclid(100) * 100 + 1)
+            .and_then(|date| date.and_hms_opt(0, 0, 0)),
+        Some("millennium") => year_start((year - 1).div_euclid(1000) * 1000 + 1)
+            .and_then(|date| date.and_hms_opt(0, 0, 0)),
+        _ => return Err(unsupported_field(field)),
+    };
+
+    truncated
+        .map(|value| value.timestamp_nanos())
+        .ok_or_else(|| DataFusionError::Execution("timestamp out of range".to_string()))
+}
+
+/// date_part and EXTRACT of PostgreSQL, seconds include the fractional part
+pub fn date_part(field: &str, nanos: i64) -> Result<f64> {
+    let value = timestamp_ns_to_datetime(nanos);
+    let year = value.year() as i64;
+    let seconds = value.second() as f64 + value.nanosecond() as f64 / 1_000_000_000.0;
+
+    let part = match timestamp_field(field) {
+        Some("microseconds") => seconds * 1_000_000.0,
+        Some("milliseconds") => seconds * 1_000.0,
+        Some("second") => seconds,
+        Some("minute") => value.minute() as f64,
+        Some("hour") => value.hour() as f64,
+        Some("day") => value.day() as f64,
+        Some("week") => value.iso_week().week() as f64,
+        Some("month") => value.month() as f64,
+        Some("quarter") => (value.month0() / 3 + 1) as f64,
+        Some("year") => year as f64,
+        Some("decade") => year.div_euclid(10) as f64,
+        Some("century") => ((year - 1).div_euclid(100) + 1) as f64,
+        Some("millennium") => ((year - 1).div_euclid(1000) + 1) as f64,
+        Some("epoch") => {
+            nanos.div_euclid(1_000_000_000) as f64
+                + nanos.rem_euclid(1_000_000_000) as f64 / 1_000_000_000.0
+        }
+        Some("dow") => value.weekday().num_days_from_sunday() as f64,
+        Some("isodow") => value.weekday().number_from_monday() as f64,
+        Some("doy") => value.ordinal() as f64,
+        Some("isoyear") => value.iso_week().year() as f64,
+        _ => return Err(unsupported_field(field)),
+    };
+
+    Ok(part)
+}
+
+/// Template patterns of to_char and to_timestamp, longer patterns go first
+const TEMPLATE_PATTERNS: [&str; 40] = [
+    "HH24", "HH12", "HH", "MI", "SS", "MS", "US", "AM", "PM", "am", "pm", "IYYY", "YYYY", "YYY",
+    "YY", "Y", "IW", "ID", "MONTH", "Month", "month", "MON", "Mon", "mon", "MM", "DAY", "Day",
+    "day", "DDD", "DD", "DY", "Dy", "dy", "D", "WW", "Q", "TZ", "tz", "OF", "SSSS",
+];
+
+/// Names of months and days are case sensitive, numeric patterns aren't
+fn match_pattern(rest: &str) -> Option<&'static str> {
+    TEMPLATE_PATTERNS
+        .iter()
+        .filter(|pattern| {
+            let is_name = pattern.chars().any(|c| c.is_ascii_lowercase())
+                || matches!(
+                    **pattern,
+                    "MONTH" | "MON" | "DAY" | "DY" | "AM" | "PM" | "TZ"
+                );
+            match rest.get(..pattern.len()) {
+                Some(prefix) if is_name => prefix == **pattern,
+                Some(prefix) => prefix.eq_ignore_ascii_case(pattern),
+                None => false,
+            }
+        })
+        .max_by_key(|pattern| pattern.len())
+        .copied()
+}
+
+enum TemplateToken<'a> {
+    Pattern {
+        pattern: &'static str,
+        fill_mode: bool,
+    },
+    Literal(&'a str),
+}
+
+/// Splits template into patterns and literal text: FM prefix suppresses padding, text in double
+/// quotes and characters after backslash are literal
+fn tokenize_template(template: &str) -> Vec<TemplateToken<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    while !rest.is_empty() {
+        let fill_mode = rest
+            .get(..2)
+            .map_or(false, |p| p.eq_ignore_ascii_case("FM"));
+        let after_modifier = if fill_mode { &rest[2..] } else { rest };
+
+        if let Some(pattern) = match_pattern(after_modifier) {
+            tokens.push(TemplateToken::Pattern { pattern, fill_mode });
+            rest = &after_modifier[pattern.len()..];
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            tokens.push(TemplateToken::Literal(&quoted[..end]));
+            rest = quoted.get(end + 1..).unwrap_or("");
+        } else {
+            let skip = if rest.starts_with('\\') && rest.len() > 1 {
+                1
+            } else {
+                0
+            };
+            let length = rest[skip..].chars().next().map_or(0, |c| c.len_utf8());
+            tokens.push(TemplateToken::Literal(&rest[skip..skip + length]));
+            rest = &rest[skip + length..];
+        }
+    }
+
+    tokens
+}
+
+fn pad_number(value: i64, width: usize, fill_mode: bool) -> String {
+    if fill_mode {
+        value.to_string()
+    } else {
+        format!("{:0width$}", value, width = width)
+    }
+}
+
+/// Full names are blank-padded to 9 characters, the longest month name
+fn pad_name(name: String, pattern: &str, pad: bool) -> String {
+    let name = if pattern.chars().all(|c| c.is_ascii_uppercase()) {
+        name.to_uppercase()
+    } else if pattern.chars().all(|c| c.is_ascii_lowercase()) {
+        name.to_lowercase()
+    } else {
+        name
+    };
+
+    if !pad {
+        name
+    } else {
+        format!("{:9}", name)
+    }
+}
+
+/// Formats timestamp by template patterns of to_char in PostgreSQL
+pub fn to_char(value: &NaiveDateTime, template: &str) -> String {
+    let mut result = String::new();
+    for token in tokenize_template(template) {
+        let (pattern, fill_mode) = match token {
+            TemplateToken::Pattern { pattern, fill_mode } => (pattern, fill_mode),
+            TemplateToken::Literal(text) => {
+                result.push_str(text);
+                continue;
+            }
+        };
+
+        let hour12 = (value.hour() + 11) % 12 + 1;
+        let text = match pattern.to_uppercase().as_str() {
+            "HH24" => pad_number(value.hour() as i64, 2, fill_mode),
+            "HH12" | "HH" => pad_number(hour12 as i64, 2, fill_mode),
+            "MI" => pad_number(value.minute() as i64, 2, fill_mode),
+            "SS" => pad_number(value.second() as i64, 2, fill_mode),
+            "SSSS" => pad_number(value.num_seconds_from_midnight() as i64, 1, fill_mode),
+            "MS" => pad_number((value.nanosecond() / 1_000_000) as i64, 3, fill_mode),
+            "US" => pad_number((value.nanosecond() / 1_000) as i64, 6, fill_mode),
+            "AM" | "PM" => {
+                let meridiem = if value.hour() < 12 { "AM" } else { "PM" };
+                if pattern.chars().all(|c| c.is_ascii_lowercase()) {
+                    meridiem.to_lowercase()
+                } else {
+                    meridiem.to_string()
+                }
+            }
+            "YYYY" => pad_number(value.year() as i64, 4, fill_mode),
+            "YYY" => pad_number(value.year().rem_euclid(1000) as i64, 3, fill_mode),
+            "YY" => pad_number(value.year().rem_euclid(100) as i64, 2, fill_mode),
+            "Y" => pad_number(value.year().rem_euclid(10) as i64, 1, fill_mode),
+            "IYYY" => pad_number(value.iso_week().year() as i64, 4, fill_mode),
+            "IW" => pad_number(value.iso_week().week() as i64, 2, fill_mode),
+            "ID" => value.weekday().number_from_monday().to_string(),
+            "MONTH" => pad_name(value.format("%B").to_string(), pattern, !fill_mode),
+            "MON" => pad_name(value.format("%b").to_string(), pattern, false),
+            "MM" => pad_number(value.month() as i64, 2, fill_mode),
+            "DAY" => pad_name(value.format("%A").to_string(), pattern, !fill_mode),
+            "DY" => pad_name(value.format("%a").to_string(), pattern, false),
+            "DDD" => pad_number(value.ordinal() as i64, 3, fill_mode),
+            "DD" => pad_number(value.day() as i64, 2, fill_mode),
+            "D" => value.weekday().number_from_sunday().to_string(),
+            "WW" => pad_number((value.ordinal0() / 7 + 1) as i64, 2, fill_mode),
+            "Q" => (value.month0() / 3 + 1).to_string(),
+            // Timestamps are formatted in UTC
+            "TZ" => pad_name("UTC".to_string(), pattern, false),
+            "OF" => "+00".to_string(),
+            _ => String::new(),
+        };
+        result.push_str(&text);
+    }
+
+    result
+}
+
+/// Reads up to `width` digits, leading spaces are skipped
+fn parse_number<'a>(input: &'a str, width: usize, pattern: &str) -> Result<(i64, &'a str)> {
+    let input = input.trim_start();
+    let (sign, unsigned) = match input.strip_prefix('-') {
+        Some(unsigned) => (-1, unsigned),
+        None => (1, input),
+    };
+    let length = unsigned
+        .chars()
+        .take(width)
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+
+    match unsigned[..length].parse::<i64>() {
+        Ok(value) => Ok((sign * value, &unsigned[length..])),
+        Err(_) => Err(DataFusionError::Execution(format!(
+            "invalid value \"{}\" for \"{}\"",
+            input.chars().take(width).collect::<String>(),
+            pattern
+        ))),
+    }
+}
+
+/// Reads one of the names: full names are tried first, then abbreviations
+fn parse_name<'a>(input: &'a str, names: &[&str], pattern: &str) -> Result<(u32, &'a str)> {
+    let input = input.trim_start();
+    for length in [usize::MAX, 3] {
+        for (index, name) in names.iter().enumerate() {
+            let name = &name[..name.len().min(length)];
+            match input.get(..name.len()) {
+                Some(prefix) if prefix.eq_ignore_ascii_case(name) => {
+                    return Ok((index as u32, &input[name.len()..]))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Err(DataFusionError::Execution(format!(
+        "invalid value \"{}\" for \"{}\"",
+        input, pattern
+    )))
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const DAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// Parses timestamp by template patterns of to_timestamp in PostgreSQL: fields which are not in
+/// template default to 0001-01-01 00:00:00, literal characters of template skip one character
+pub fn to_timestamp(input: &str, template: &str) -> Result<NaiveDateTime> {
+    let (mut year, mut month, mut day, mut ordinal) = (1_i64, 1_i64, 1_i64, None);
+    let (mut hour, mut minute, mut second, mut nanos, mut pm) = (0_i64, 0_i64, 0_i64, 0_i64, None);
+
+    let mut rest = input;
+    for token in tokenize_template(template) {
+        let pattern = match token {
+            TemplateToken::Pattern { pattern, .. } => pattern,
+            TemplateToken::Literal(text) => {
+                for _ in text.chars() {
+                    let mut chars = rest.chars();
+                    if chars.next().is_some() {
+                        rest = chars.as_str();
+                    }
+                }
+                continue;
+            }
+        };
+
+        let (target, width) = match pattern.to_uppercase().as_str() {
+            "YYYY" | "IYYY" => (&mut year, 4),
+            "YYY" | "YY" | "Y" => {
+                let (value, next) = parse_number(rest, pattern.len(), pattern)?;
+                // Short years are adjusted to the nearest year to 2020
+                year = match pattern.len() {
+                    3 if value >= 100 => 1000 + value,
+                    2 if value >= 70 => 1900 + value,
+                    _ => 2000 + value,
+                };
+                rest = next;
+                continue;
+            }
+            "MM" => (&mut month, 2),
+            "DD" => (&mut day, 2),
+            "HH24" | "HH12" | "HH" => (&mut hour, 2),
+            "MI" => (&mut minute, 2),
+            "SS" => (&mut second, 2),
+            "MS" => {
+                let (value, next) = parse_number(rest, 3, pattern)?;
+                nanos = value * 1_000_000;
+                rest = next;
+                continue;
+            }
+            "US" => {
+                let (value, next) = parse_number(rest, 6, pattern)?;
+                nanos = value * 1_000;
+                rest = next;
+                continue;
+            }
+            "DDD" => {
+                let (value, next) = parse_number(rest, 3, pattern)?;
+                ordinal = Some(value);
+                rest = next;
+                continue;
+            }
+            "AM" | "PM" => {
+                let meridiem = rest.trim_start().get(..2).unwrap_or("");
+                pm = match meridiem.to_uppercase().as_str() {
+                    "AM" => Some(false),
+                    "PM" => Some(true),
+                    _ => {
+                        return Err(DataFusionError::Execution(format!(
+                            "invalid value \"{}\" for \"{}\"",
+                            meridiem, pattern
+                        )))
+                    }
+                };
+                rest = &rest.trim_start()[2..];
+                continue;
+            }
+            "MONTH" | "MON" => {
+                let (index, next) = parse_name(rest, &MONTH_NAMES, pattern)?;
+                month = index as i64 + 1;
+                rest = next;
+                continue;
+            }
+            "DAY" | "DY" => {
+                let (_, next) = parse_name(rest, &DAY_NAMES, pattern)?;
+                rest = next;
+                continue;
+            }
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "formatting field \"{}\" is only supported in to_char",
+                    pattern
+                )))
+            }
+        };
+
+        let (value, next) = parse_number(rest, width, pattern)?;
+        *target = value;
+        rest = next;
+    }
+
+    match pm {
+        Some(true) if hour < 12 => hour += 12,
+        Some(false) if hour == 12 => hour = 0,
+        _ => {}
+    }
+
+    let date = match ordinal {
+        Some(ordinal) => NaiveDate::from_yo_opt(year as i32, ordinal as u32),
+        None => NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32),
+    };
+
+    date.and_then(|date| {
+        date.and_hms_nano_opt(hour as u32, minute as u32, second as u32, nanos as u32)
+    })
+    .ok_or_else(|| {
+        DataFusionError::Execution(format!("date/time field value out of range: \"{}\"", input))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").unwrap()
+    }
+
+    fn trunc(field: &str, value: &str) -> String {
+        timestamp_ns_to_datetime(date_trunc(field, ts(value).timestamp_nanos()).unwrap())
+            .format("%Y-%m-%d %H:%M:%S%.f")
+            .to_string()
+    }
+
+    fn part(field: &str, value: &str) -> f64 {
+        date_part(field, ts(value).timestamp_nanos()).unwrap()
+    }
+
+    #[test]
+    fn test_date_trunc() {
+        // Weeks start on Monday, the first week of 2021 begins in 2020
+        assert_eq!(trunc("week", "2021-01-01 10:00:00"), "2020-12-28 00:00:00");
+        assert_eq!(trunc("week", "2023-01-01 23:59:59"), "2022-12-26 00:00:00");
+        assert_eq!(trunc("week", "2023-01-02 00:00:00"), "2023-01-02 00:00:00");
+        assert_eq!(
+            trunc("quarter", "2022-12-31 10:00:00"),
+            "2022-10-01 00:00:00"
+        );
+        assert_eq!(
+            trunc("decade", "2029-05-05 10:00:00"),
+            "2020-01-01 00:00:00"
+        );
+        assert_eq!(
+            trunc("century", "2000-05-05 10:00:00"),
+            "1901-01-01 00:00:00"
+        );
+        assert_eq!(
+            trunc("century", "2001-05-05 10:00:00"),
+            "2001-01-01 00:00:00"
+        );
+        assert_eq!(
+            trunc("millennium", "2022-05-05 10:00:00"),
+            "2001-01-01 00:00:00"
+        );
+        assert_eq!(trunc("MONTH", "2022-02-28 10:11:12"), "2022-02-01 00:00:00");
+        assert_eq!(trunc("hours", "2022-02-28 10:11:12"), "2022-02-28 10:00:00");
+        assert_eq!(
+            trunc("milliseconds", "2022-02-28 10:11:12.345678"),
+            "2022-02-28 10:11:12.345"
+        );
+        assert_eq!(
+            trunc("microseconds", "2022-02-28 10:11:12.345678901"),
+            "2022-02-28 10:11:12.345678"
+        );
+
+        assert_eq!(
+            date_trunc("fortnight", 0).unwrap_err().to_string(),
+            "Execution error: timestamp units \"fortnight\" not recognized"
+        );
+    }
+
+    #[test]
+    fn test_date_part() {
+        assert_eq!(part("epoch", "2022-01-01 00:00:00.5"), 1640995200.5);
+        // 2021-01-01 is Friday of the last ISO week of 2020
+        assert_eq!(part("dow", "2021-01-01 00:00:00"), 5.0);
+        assert_eq!(part("isodow", "2021-01-03 00:00:00"), 7.0);
+        assert_eq!(part("dow", "2021-01-03 00:00:00"), 0.0);
+        assert_eq!(part("week", "2021-01-01 00:00:00"), 53.0);
+        assert_eq!(part("isoyear", "2021-01-01 00:00:00"), 2020.0);
+        assert_eq!(part("doy", "2020-12-31 00:00:00"), 366.0);
+        assert_eq!(part("quarter", "2022-04-01 00:00:00"), 2.0);
+        assert_eq!(part("second", "2022-04-01 00:00:12.25"), 12.25);
+        assert_eq!(part("milliseconds", "2022-04-01 00:00:12.25"), 12250.0);
+        assert_eq!(part("century", "2000-01-01 00:00:00"), 20.0);
+        assert_eq!(part("millennium", "2001-01-01 00:00:00"), 3.0);
+    }
+
+    #[test]
+    fn test_to_char() {
+        let value = ts("2021-01-03 14:05:09.123456");
+
+        assert_eq!(
+            to_char(&value, "YYYY-MM-DD HH24:MI:SS"),
+            "2021-01-03 14:05:09"
+        );
+        assert_eq!(to_char(&value, "HH12:MI AM"), "02:05 PM");
+        assert_eq!(
+            to_char(&value, "Day, DD Mon YYYY"),
+            "Sunday   , 03 Jan 2021"
+        );
+        assert_eq!(to_char(&value, "FMDay, FMDD FMMonth"), "Sunday, 3 January");
+        assert_eq!(to_char(&value, "DAY dy MONTH"), "SUNDAY    sun JANUARY  ");
+        assert_eq!(to_char(&value, "IYYY-IW-ID"), "2020-53-7");
+        assert_eq!(to_char(&value, "YYYY \"Q\"Q D DDD"), "2021 Q1 1 003");
+        assert_eq!(to_char(&value, "SS.MS.US"), "09.123.123456");
+        assert_eq!(to_char(&value, "yyyy/mm/dd"), "2021/01/03");
+    }
+
+    #[test]
+    fn test_to_timestamp() {
+        assert_eq!(
+            to_timestamp("2022-03-05 17:04:01", "YYYY-MM-DD HH24:MI:SS").unwrap(),
+            ts("2022-03-05 17:04:01")
+        );
+        assert_eq!(
+            to_timestamp("05 Mar 2022 05:04 PM", "DD Mon YYYY HH12:MI AM").unwrap(),
+            ts("2022-03-05 17:04:00")
+        );
+        assert_eq!(
+            to_timestamp("March 5, 22", "Month DD, YY").unwrap(),
+            ts("2022-03-05 00:00:00")
+        );
+        assert_eq!(
+            to_timestamp("20220305.250", "YYYYMMDD.MS").unwrap(),
+            ts("2022-03-05 00:00:00.25")
+        );
+        assert_eq!(
+            to_timestamp("2022-02-30", "YYYY-MM-DD")
+                .unwrap_err()
+                .to_string(),
+            "Execution error: date/time field value out of range: \"2022-02-30\""
+        );
+    }
+}
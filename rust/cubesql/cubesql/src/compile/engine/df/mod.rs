@@ -1,5 +1,7 @@
 pub mod coerce;
 pub mod columar;
+pub mod datetime;
 pub mod intervals;
+pub mod numeric;
 pub mod planner;
 pub mod scan;
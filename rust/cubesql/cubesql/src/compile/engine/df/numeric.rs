@@ -0,0 +1,243 @@
+#[derive(Debug, PartialEq)]
+enum NumberToken {
+    /// 9 is replaced with blank for leading zeros, 0 is always printed
+    Digit {
+        zero: bool,
+    },
+    Point,
+    Group,
+    Sign(&'static str),
+    Literal(String),
+}
+
+/// Splits numeric template of to_char into patterns and literal text, FM is applied to the whole
+/// template
+fn tokenize_template(template: &str) -> (Vec<NumberToken>, bool) {
+    let mut tokens = Vec::new();
+    let mut fill_mode = false;
+    let mut has_point = false;
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        let prefix = rest.get(..2).map(|p| p.to_uppercase());
+        match prefix.as_deref() {
+            Some("FM") => {
+                fill_mode = true;
+                rest = &rest[2..];
+                continue;
+            }
+            Some(sign @ ("MI" | "PL" | "SG")) => {
+                tokens.push(NumberToken::Sign(match sign {
+                    "MI" => "MI",
+                    "PL" => "PL",
+                    _ => "SG",
+                }));
+                rest = &rest[2..];
+                continue;
+            }
+            _ => {}
+        }
+
+        let token = match c {
+            '9' => NumberToken::Digit { zero: false },
+            '0' => NumberToken::Digit { zero: true },
+            '.' | 'D' | 'd' if !has_point => {
+                has_point = true;
+                NumberToken::Point
+            }
+            ',' | 'G' | 'g' => NumberToken::Group,
+            'S' | 's' => NumberToken::Sign("S"),
+            '"' => {
+                let quoted = &rest[1..];
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                tokens.push(NumberToken::Literal(quoted[..end].to_string()));
+                rest = quoted.get(end + 1..).unwrap_or("");
+                continue;
+            }
+            '\\' if rest.len() > 1 => {
+                let escaped = rest[1..].chars().next().unwrap();
+                tokens.push(NumberToken::Literal(escaped.to_string()));
+                rest = &rest[1 + escaped.len_utf8()..];
+                continue;
+            }
+            c => NumberToken::Literal(c.to_string()),
+        };
+        tokens.push(token);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    (tokens, fill_mode)
+}
+
+/// Formats number by template patterns of to_char in PostgreSQL: sign takes a position before
+/// the number unless template has sign pattern, digits which don't fit are printed as #
+pub fn to_char(value: f64, template: &str) -> String {
+    let (tokens, fill_mode) = tokenize_template(template);
+    let point = tokens
+        .iter()
+        .position(|token| token == &NumberToken::Point)
+        .unwrap_or(tokens.len());
+    let is_digit = |token: &NumberToken| matches!(token, NumberToken::Digit { .. });
+    let int_positions = tokens[..point].iter().filter(|t| is_digit(t)).count();
+    let frac_positions = tokens[point..].iter().filter(|t| is_digit(t)).count();
+
+    let formatted = format!("{:.*}", frac_positions, value.abs());
+    let (int_part, frac_part) = match formatted.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, frac_part),
+        None => (formatted.as_str(), ""),
+    };
+    let int_part = match int_part {
+        "0" if frac_positions > 0 => "",
+        int_part => int_part,
+    };
+    let negative = value < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0');
+    let overflow = !value.is_finite() || int_part.len() > int_positions;
+
+    // Trailing zeros of fraction are removed in fill mode up to the first 0 pattern
+    let mut frac_len = frac_part.len();
+    if fill_mode {
+        let frac_zeros = tokens[point..]
+            .iter()
+            .filter_map(|token| match token {
+                NumberToken::Digit { zero } => Some(*zero),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        while frac_len > 0
+            && !frac_zeros[frac_len - 1]
+            && frac_part.as_bytes()[frac_len - 1] == b'0'
+        {
+            frac_len -= 1;
+        }
+    }
+
+    let sign_before = tokens[..point]
+        .iter()
+        .take_while(|token| !is_digit(token))
+        .any(|token| token == &NumberToken::Sign("S"));
+    let has_sign = tokens
+        .iter()
+        .any(|token| matches!(token, NumberToken::Sign(_)));
+    let mut pending_sign = match (has_sign, sign_before, negative) {
+        (false, _, true) => Some("-"),
+        (false, _, false) if !fill_mode => Some(" "),
+        (true, true, true) => Some("-"),
+        (true, true, false) => Some("+"),
+        _ => None,
+    };
+
+    let mut result = String::new();
+    let mut push_number = |result: &mut String, text: &str| {
+        if let Some(sign) = pending_sign.take() {
+            result.push_str(sign);
+        }
+        result.push_str(text);
+    };
+    let blank = |result: &mut String| {
+        if !fill_mode {
+            result.push(' ');
+        }
+    };
+
+    let leading = int_positions.saturating_sub(int_part.len());
+    let zero_from = tokens[..point]
+        .iter()
+        .filter(|t| is_digit(t))
+        .position(|token| token == &NumberToken::Digit { zero: true })
+        .unwrap_or(int_positions);
+    let (mut int_index, mut frac_index) = (0, 0);
+    let mut started = false;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            NumberToken::Digit { .. } if index < point => {
+                let position = int_index;
+                int_index += 1;
+                if overflow {
+                    push_number(&mut result, "#");
+                } else if position >= leading {
+                    let digit = &int_part[position - leading..position - leading + 1];
+                    push_number(&mut result, digit);
+                } else if position >= zero_from {
+                    push_number(&mut result, "0");
+                } else {
+                    blank(&mut result);
+                    continue;
+                }
+                started = true;
+            }
+            NumberToken::Digit { .. } => {
+                let position = frac_index;
+                frac_index += 1;
+                if overflow {
+                    push_number(&mut result, "#");
+                } else if position < frac_len {
+                    push_number(&mut result, &frac_part[position..position + 1]);
+                } else {
+                    blank(&mut result);
+                }
+                started = true;
+            }
+            NumberToken::Point => {
+                push_number(&mut result, ".");
+                started = true;
+            }
+            NumberToken::Group if started => push_number(&mut result, ","),
+            NumberToken::Group => blank(&mut result),
+            NumberToken::Sign("S") if sign_before && index < point => {}
+            NumberToken::Sign(pattern) => {
+                let sign = match (*pattern, negative) {
+                    ("MI", true) | ("S", true) | ("SG", true) => "-",
+                    ("PL", false) | ("S", false) | ("SG", false) => "+",
+                    _ if fill_mode => "",
+                    _ => " ",
+                };
+                result.push_str(sign);
+            }
+            NumberToken::Literal(text) => result.push_str(text),
+        }
+    }
+
+    if let Some(sign) = pending_sign {
+        result.push_str(sign);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_char() {
+        assert_eq!(to_char(123.456, "999.99"), " 123.46");
+        assert_eq!(to_char(-123.456, "999.99"), "-123.46");
+        assert_eq!(to_char(12.0, "9999"), "   12");
+        assert_eq!(to_char(-12.0, "9999"), "  -12");
+        assert_eq!(to_char(5.0, "000"), " 005");
+        assert_eq!(to_char(5.0, "0999"), " 0005");
+        assert_eq!(to_char(0.5, "9.99"), "  .50");
+        assert_eq!(to_char(0.5, "0.99"), " 0.50");
+        assert_eq!(to_char(0.0, "999"), "   0");
+        assert_eq!(to_char(12345.0, "999"), " ###");
+        assert_eq!(to_char(1234567.0, "9,999,999"), " 1,234,567");
+        assert_eq!(to_char(12.0, "9,999"), "    12");
+        assert_eq!(to_char(1234.5, "9G999D99"), " 1,234.50");
+    }
+
+    #[test]
+    fn test_to_char_fill_mode_and_sign() {
+        assert_eq!(to_char(1234567.891, "FM9,999,999.00"), "1,234,567.89");
+        assert_eq!(to_char(1.5, "FM9.99"), "1.5");
+        assert_eq!(to_char(1.0, "FM9.99"), "1.");
+        assert_eq!(to_char(1.0, "FM0.00"), "1.00");
+        assert_eq!(to_char(0.1, "FM9.99"), ".1");
+        assert_eq!(to_char(-42.0, "FM999"), "-42");
+        assert_eq!(to_char(42.0, "FM999"), "42");
+        assert_eq!(to_char(-12.0, "999MI"), " 12-");
+        assert_eq!(to_char(12.0, "999MI"), " 12 ");
+        assert_eq!(to_char(12.0, "S999"), " +12");
+        assert_eq!(to_char(-12.0, "999S"), " 12-");
+        assert_eq!(to_char(12.0, "PL999"), "+ 12");
+        assert_eq!(to_char(75.0, "FM999\"%\""), "75%");
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use datafusion::{
@@ -8,17 +8,30 @@ use datafusion::{
     physical_plan::{planner::DefaultPhysicalPlanner, ExecutionPlan, PhysicalPlanner},
 };
 
-use crate::transport::TransportService;
+use crate::{sql, transport::TransportService};
 
 use super::scan::CubeScanExtensionPlanner;
 
 pub struct CubeQueryPlanner {
     pub transport: Arc<dyn TransportService>,
+    pub session: Arc<sql::SessionState>,
+    pub page_size: usize,
+    pub continue_wait_timeout: Duration,
 }
 
 impl CubeQueryPlanner {
-    pub fn new(transport: Arc<dyn TransportService>) -> Self {
-        Self { transport }
+    pub fn new(
+        transport: Arc<dyn TransportService>,
+        session: Arc<sql::SessionState>,
+        page_size: usize,
+        continue_wait_timeout: Duration,
+    ) -> Self {
+        Self {
+            transport,
+            session,
+            page_size,
+            continue_wait_timeout,
+        }
     }
 }
 
@@ -35,6 +48,9 @@ impl QueryPlanner for CubeQueryPlanner {
         let physical_planner = DefaultPhysicalPlanner::with_extension_planners(vec![Arc::new(
             CubeScanExtensionPlanner {
                 transport: self.transport.clone(),
+                session: self.session.clone(),
+                page_size: self.page_size,
+                continue_wait_timeout: self.continue_wait_timeout,
             },
         )]);
         // Delegate most work of physical planning to the default physical planner
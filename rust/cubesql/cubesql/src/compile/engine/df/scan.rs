@@ -3,15 +3,16 @@ use std::{
     fmt,
     sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
-use cubeclient::models::{V1LoadRequestQuery, V1LoadResult};
+use cubeclient::models::{V1LoadRequestQuery, V1LoadResponse, V1LoadResult};
 use datafusion::{
     arrow::{
         array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder},
         datatypes::{DataType, SchemaRef},
-        error::Result as ArrowResult,
+        error::{ArrowError, Result as ArrowResult},
         record_batch::RecordBatch,
     },
     error::{DataFusionError, Result},
@@ -22,11 +23,19 @@ use datafusion::{
         Partitioning, PhysicalPlanner, RecordBatchStream, SendableRecordBatchStream, Statistics,
     },
 };
-use futures::Stream;
-use log::{error, warn};
+use futures::{
+    stream::{BoxStream, StreamExt, TryStreamExt},
+    Stream,
+};
+use log::{debug, error, warn};
+use tokio_util::sync::CancellationToken;
 
-use crate::{sql::AuthContext, transport::TransportService};
-use chrono::{TimeZone, Utc};
+use crate::{
+    sql::{self, AuthContext},
+    transport::TransportService,
+    CubeError,
+};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
 use datafusion::arrow::array::TimestampNanosecondBuilder;
 use datafusion::arrow::datatypes::TimeUnit;
 use datafusion::execution::context::TaskContext;
@@ -101,6 +110,9 @@ impl UserDefinedLogicalNode for CubeScanNode {
 //  the logical plan node.
 pub struct CubeScanExtensionPlanner {
     pub transport: Arc<dyn TransportService>,
+    pub session: Arc<sql::SessionState>,
+    pub page_size: usize,
+    pub continue_wait_timeout: Duration,
 }
 
 impl ExtensionPlanner for CubeScanExtensionPlanner {
@@ -125,6 +137,13 @@ impl ExtensionPlanner for CubeScanExtensionPlanner {
                     transport: self.transport.clone(),
                     request: scan_node.request.clone(),
                     auth_context: scan_node.auth_context.clone(),
+                    cancel: self.session.query_cancellation(),
+                    page_size: self.page_size,
+                    // statement_timeout of the session cancels the whole query anyway
+                    continue_wait_timeout: match self.session.statement_timeout() {
+                        Some(timeout) => timeout.min(self.continue_wait_timeout),
+                        None => self.continue_wait_timeout,
+                    },
                 }))
             } else {
                 None
@@ -133,7 +152,7 @@ impl ExtensionPlanner for CubeScanExtensionPlanner {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct CubeScanExecutionPlan {
     // Options from logical node
     schema: SchemaRef,
@@ -142,9 +161,154 @@ struct CubeScanExecutionPlan {
     auth_context: Arc<AuthContext>,
     // Shared references which will be injected by extension planner
     transport: Arc<dyn TransportService>,
+    // Cancellation of the query, which is executing in the session
+    cancel: CancellationToken,
+    // Max number of rows in one load request
+    page_size: usize,
+    // Max time of repeating requests, which Cube answers with "Continue wait"
+    continue_wait_timeout: Duration,
 }
 
+const CONTINUE_WAIT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const CONTINUE_WAIT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
 impl CubeScanExecutionPlan {
+    /// Request of the page which starts after `fetched` rows, None if the limit is reached.
+    /// Limit which fits into one page is requested as is, other queries are requested page by
+    /// page with increasing offset and ordered by dimensions, when the order is not specified
+    fn page_request(&self, fetched: usize) -> Option<V1LoadRequestQuery> {
+        let limit = match self.request.limit {
+            Some(limit) if fetched == 0 && limit.max(0) as usize <= self.page_size => {
+                return Some(self.request.clone())
+            }
+            Some(limit) => match (limit.max(0) as usize).saturating_sub(fetched) {
+                0 => return None,
+                remaining => remaining.min(self.page_size),
+            },
+            None => self.page_size,
+        };
+
+        let mut request = self.request.clone();
+        request.limit = Some(limit as i32);
+        if fetched > 0 {
+            request.offset = Some(self.request.offset.unwrap_or(0) + fetched as i32);
+        }
+
+        // Pages of unordered query may overlap or miss rows, the order over all dimensions
+        // makes them stable
+        if request.order.iter().all(|order| order.is_empty()) {
+            let order = request
+                .dimensions
+                .iter()
+                .flatten()
+                .cloned()
+                .chain(
+                    request
+                        .time_dimensions
+                        .iter()
+                        .flatten()
+                        .filter(|time_dimension| time_dimension.granularity.is_some())
+                        .map(|time_dimension| time_dimension.dimension.clone()),
+                )
+                .map(|member| vec![member, "asc".to_string()])
+                .collect::<Vec<_>>();
+            if !order.is_empty() {
+                request.order = Some(order);
+            }
+        }
+
+        Some(request)
+    }
+
+    /// Sends the request to Cube. Requests, which Cube answers with "Continue wait", are
+    /// repeated with exponential backoff till continue_wait_timeout
+    async fn load(
+        &self,
+        request: V1LoadRequestQuery,
+    ) -> Result<std::result::Result<V1LoadResponse, CubeError>> {
+        let canceled =
+            || DataFusionError::Execution("canceling statement due to user request".to_string());
+        let started = Instant::now();
+        let mut backoff = CONTINUE_WAIT_INITIAL_BACKOFF;
+        let mut retries = 0;
+
+        loop {
+            let result = tokio::select! {
+                biased;
+                _ = self.cancel.cancelled() => return Err(canceled()),
+                result = self.transport.load(request.clone(), self.auth_context.clone()) => result,
+            };
+
+            match result {
+                Err(err) if err.is_continue_wait() => {
+                    if started.elapsed() + backoff > self.continue_wait_timeout {
+                        return Err(DataFusionError::Execution(format!(
+                            "Cube didn't finish processing of the query in {:?} ({} retries of continue wait)",
+                            self.continue_wait_timeout, retries
+                        )));
+                    }
+
+                    retries += 1;
+                    debug!(
+                        "[cube scan] continue wait, retry {} in {:?}",
+                        retries, backoff
+                    );
+
+                    tokio::select! {
+                        biased;
+                        _ = self.cancel.cancelled() => return Err(canceled()),
+                        _ = tokio::time::sleep(backoff) => {}
+                    };
+                    backoff = (backoff * 2).min(CONTINUE_WAIT_MAX_BACKOFF);
+                }
+                result => {
+                    if retries > 0 {
+                        debug!(
+                            "[cube scan] query is processed after {} retries of continue wait",
+                            retries
+                        );
+                    }
+
+                    return Ok(result);
+                }
+            }
+        }
+    }
+
+    /// Loads the page which starts after `fetched` rows. Returns its rows and the position of
+    /// the next page, which is None when Cube returns less rows than requested
+    async fn load_page(&self, fetched: usize) -> Result<Option<(RecordBatch, Option<usize>)>> {
+        let request = match self.page_request(fetched) {
+            Some(request) => request,
+            None => return Ok(None),
+        };
+        let requested = request.limit;
+
+        let mut response = self.load(request).await?.map_err(|err| match self.request.ungrouped {
+            Some(true) => DataFusionError::Execution(format!(
+                "Cube rejected ungrouped query: {}. Ungrouped queries can be disabled by CUBESQL_UNGROUPED_QUERIES=false",
+                err
+            )),
+            _ => DataFusionError::Execution(err.to_string()),
+        })?;
+
+        let result = if let Some(data) = response.results.pop() {
+            data
+        } else {
+            return Err(DataFusionError::Execution(format!(
+                "Unable to extract result from Cube.js response",
+            )));
+        };
+
+        let rows = result.data.len();
+        let next = match requested {
+            Some(limit) if rows > 0 && rows >= limit as usize => Some(fetched + rows),
+            _ => None,
+        };
+
+        Ok(Some((self.transform_response(result)?, next)))
+    }
+
     // This methods transform response from Cube.js to RecordBatch which stores
     // schema and array of columns.
     fn transform_response(&self, response: V1LoadResult) -> Result<RecordBatch> {
@@ -157,12 +321,7 @@ impl CubeScanExecutionPlan {
                     let mut builder = StringBuilder::new(100);
 
                     for row in response.data.iter() {
-                        let value = row.as_object().unwrap().get(field_name).ok_or(
-                            DataFusionError::Internal(
-                                "Unexpected response from Cube.js, rows are not objects"
-                                    .to_string(),
-                            ),
-                        )?;
+                        let value = row_value(row, field_name)?;
                         match &value {
                             serde_json::Value::Null => builder.append_null()?,
                             serde_json::Value::String(v) => builder.append_value(v)?,
@@ -187,22 +346,26 @@ impl CubeScanExecutionPlan {
                     let mut builder = Int64Builder::new(100);
 
                     for row in response.data.iter() {
-                        let value = row.as_object().unwrap().get(field_name).ok_or(
-                            DataFusionError::Internal(
-                                "Unexpected response from Cube.js, rows are not objects"
-                                    .to_string(),
-                            ),
-                        )?;
+                        let value = row_value(row, field_name)?;
                         match &value {
                             serde_json::Value::Null => builder.append_null()?,
-                            serde_json::Value::Number(number) => match number.as_i64() {
+                            serde_json::Value::Number(number) => match number
+                                .as_i64()
+                                .or_else(|| number.as_f64().and_then(integral_f64_to_i64))
+                            {
                                 Some(v) => builder.append_value(v)?,
                                 None => builder.append_null()?,
                             },
-                            serde_json::Value::String(s) => match s.parse::<i64>() {
-                                Ok(v) => builder.append_value(v)?,
-                                Err(error) => {
-                                    warn!("Unable to parse value as i64: {}", error.to_string());
+                            // Big numbers are returned as strings, they are parsed as is to
+                            // keep the precision
+                            serde_json::Value::String(s) => match s
+                                .parse::<i64>()
+                                .ok()
+                                .or_else(|| s.parse::<f64>().ok().and_then(integral_f64_to_i64))
+                            {
+                                Some(v) => builder.append_value(v)?,
+                                None => {
+                                    warn!("Unable to parse value as i64: {}", s);
 
                                     builder.append_null()?
                                 }
@@ -224,12 +387,7 @@ impl CubeScanExecutionPlan {
                     let mut builder = Float64Builder::new(100);
 
                     for row in response.data.iter() {
-                        let value = row.as_object().unwrap().get(field_name).ok_or(
-                            DataFusionError::Internal(
-                                "Unexpected response from Cube.js, rows are not objects"
-                                    .to_string(),
-                            ),
-                        )?;
+                        let value = row_value(row, field_name)?;
                         match &value {
                             serde_json::Value::Null => builder.append_null()?,
                             serde_json::Value::Number(number) => match number.as_f64() {
@@ -261,12 +419,7 @@ impl CubeScanExecutionPlan {
                     let mut builder = BooleanBuilder::new(100);
 
                     for row in response.data.iter() {
-                        let value = row.as_object().unwrap().get(field_name).ok_or(
-                            DataFusionError::Internal(
-                                "Unexpected response from Cube.js, rows are not objects"
-                                    .to_string(),
-                            ),
-                        )?;
+                        let value = row_value(row, field_name)?;
                         match &value {
                             serde_json::Value::Null => builder.append_null()?,
                             serde_json::Value::Bool(v) => builder.append_value(*v)?,
@@ -300,24 +453,17 @@ impl CubeScanExecutionPlan {
                     let mut builder = TimestampNanosecondBuilder::new(response.data.len());
 
                     for row in response.data.iter() {
-                        let value = row.as_object().unwrap().get(field_name).ok_or(
-                            DataFusionError::Internal(
-                                "Unexpected response from Cube.js, rows are not objects"
-                                    .to_string(),
-                            ),
-                        )?;
+                        let value = row_value(row, field_name)?;
                         match &value {
                             serde_json::Value::Null => builder.append_null()?,
                             serde_json::Value::String(s) => {
-                                let timestamp = Utc
-                                    .datetime_from_str(s.as_str(), "%Y-%m-%dT%H:%M:%S.%f")
-                                    .map_err(|e| {
-                                        DataFusionError::Execution(format!(
-                                            "Can't parse timestamp: '{}': {}",
-                                            s, e
-                                        ))
-                                    })?;
-                                builder.append_value(timestamp.timestamp_nanos())?;
+                                let timestamp = parse_timestamp(s.as_str()).ok_or_else(|| {
+                                    DataFusionError::Execution(format!(
+                                        "Can't parse timestamp: '{}'",
+                                        s
+                                    ))
+                                })?;
+                                builder.append_value(timestamp)?;
                             }
                             v => {
                                 error!(
@@ -347,6 +493,47 @@ impl CubeScanExecutionPlan {
     }
 }
 
+static NULL_VALUE: serde_json::Value = serde_json::Value::Null;
+
+/// Value of the member in the row of Cube's response, missing members are nulls
+fn row_value<'a>(row: &'a serde_json::Value, member: &str) -> Result<&'a serde_json::Value> {
+    match row {
+        serde_json::Value::Object(row) => Ok(row.get(member).unwrap_or(&NULL_VALUE)),
+        _ => Err(DataFusionError::Internal(
+            "Unexpected response from Cube.js, rows are not objects".to_string(),
+        )),
+    }
+}
+
+/// Numbers like 5.0 or 1e3 are returned by some drivers for integer columns
+fn integral_f64_to_i64(value: f64) -> Option<i64> {
+    if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 {
+        Some(value as i64)
+    } else {
+        None
+    }
+}
+
+/// Nanoseconds since epoch of the time in Cube's response. Time with a timezone
+/// (`2022-01-01T10:00:00.000Z`, `+03:00`) is converted to UTC, time without it is taken as is
+fn parse_timestamp(value: &str) -> Option<i64> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Some(timestamp.timestamp_nanos());
+    }
+    if let Ok(timestamp) = DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z") {
+        return Some(timestamp.timestamp_nanos());
+    }
+    for format in &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(timestamp) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(timestamp.timestamp_nanos());
+        }
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.and_hms(0, 0, 0).timestamp_nanos())
+}
+
 #[async_trait]
 impl ExecutionPlan for CubeScanExecutionPlan {
     /// Return a reference to Any that can be used for downcasting
@@ -385,25 +572,29 @@ impl ExecutionPlan for CubeScanExecutionPlan {
         _partition: usize,
         _context: Arc<TaskContext>,
     ) -> Result<SendableRecordBatchStream> {
-        let result = self
-            .transport
-            .load(self.request.clone(), self.auth_context.clone())
-            .await;
-
-        let mut response = result.map_err(|err| DataFusionError::Execution(err.to_string()))?;
-
-        let result = if let Some(data) = response.results.pop() {
-            data
-        } else {
-            return Err(DataFusionError::Execution(format!(
-                "Unable to extract result from Cube.js response",
-            )));
-        };
+        // Errors of the first page are reported by execution, next pages are loaded on demand
+        let (first, next) = self.load_page(0).await?.ok_or_else(|| {
+            DataFusionError::Internal("Unable to build request for Cube.js".to_string())
+        })?;
+
+        let plan = self.clone();
+        let pages = futures::stream::try_unfold(next, move |next| {
+            let plan = plan.clone();
+            async move {
+                match next {
+                    Some(fetched) => plan.load_page(fetched).await,
+                    None => Ok(None),
+                }
+            }
+        });
 
-        Ok(Box::pin(CubeScanMemoryStream::new(
-            // @todo Pagination?)
-            vec![self.transform_response(result)?],
+        Ok(Box::pin(CubeScanStream::new(
+            futures::stream::once(async move { Ok(first) })
+                .chain(pages)
+                .map_err(|err| ArrowError::ExternalError(Box::new(err)))
+                .boxed(),
             self.schema.clone(),
+            self.cancel.clone(),
         )))
     }
 
@@ -425,48 +616,61 @@ impl ExecutionPlan for CubeScanExecutionPlan {
     }
 }
 
-struct CubeScanMemoryStream {
-    /// Vector of record batches
-    data: Vec<RecordBatch>,
+struct CubeScanStream {
+    /// Record batches of pages, in the order of the result
+    pages: BoxStream<'static, ArrowResult<RecordBatch>>,
     /// Schema representing the data
     schema: SchemaRef,
-    /// Index into the data
-    index: usize,
+    /// Set when all pages are read or the stream is stopped
+    done: bool,
+    /// Stops the stream when query is canceled or session is dropped
+    cancel: CancellationToken,
 }
 
-impl CubeScanMemoryStream {
-    pub fn new(data: Vec<RecordBatch>, schema: SchemaRef) -> Self {
+impl CubeScanStream {
+    pub fn new(
+        pages: BoxStream<'static, ArrowResult<RecordBatch>>,
+        schema: SchemaRef,
+        cancel: CancellationToken,
+    ) -> Self {
         Self {
-            data,
+            pages,
             schema,
-            index: 0,
+            done: false,
+            cancel,
         }
     }
 }
 
-impl Stream for CubeScanMemoryStream {
+impl Stream for CubeScanStream {
     type Item = ArrowResult<RecordBatch>;
 
     fn poll_next(
         mut self: std::pin::Pin<&mut Self>,
-        _: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        Poll::Ready(if self.index < self.data.len() {
-            self.index += 1;
-            let batch = &self.data[self.index - 1];
+        if self.done {
+            return Poll::Ready(None);
+        }
 
-            Some(Ok(batch.clone()))
-        } else {
-            None
-        })
-    }
+        if self.cancel.is_cancelled() {
+            self.done = true;
+
+            return Poll::Ready(Some(Err(ArrowError::ComputeError(
+                "canceling statement due to user request".to_string(),
+            ))));
+        }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.data.len(), Some(self.data.len()))
+        let poll = self.pages.poll_next_unpin(cx);
+        if let Poll::Ready(None) = poll {
+            self.done = true;
+        }
+
+        poll
     }
 }
 
-impl RecordBatchStream for CubeScanMemoryStream {
+impl RecordBatchStream for CubeScanStream {
     /// Get the schema
     fn schema(&self) -> SchemaRef {
         self.schema.clone()
@@ -475,10 +679,12 @@ impl RecordBatchStream for CubeScanMemoryStream {
 
 #[cfg(test)]
 mod tests {
-    use cubeclient::models::V1LoadResponse;
+    use cubeclient::models::{V1LoadResponse, V1LoadResultAnnotation};
     use datafusion::{
         arrow::{
-            array::{BooleanArray, Float64Array, StringArray},
+            array::{
+                BooleanArray, Float64Array, Int64Array, StringArray, TimestampNanosecondArray,
+            },
             datatypes::{Field, Schema},
         },
         execution::{
@@ -487,59 +693,96 @@ mod tests {
         },
         physical_plan::common,
     };
-    use std::collections::HashMap;
+    use futures::future::{BoxFuture, FutureExt};
+    use std::{
+        collections::HashMap,
+        future::Future,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
 
     use super::*;
     use crate::{compile::MetaContext, CubeError};
+    use serde_json::json;
     use std::result::Result;
 
-    fn get_test_transport() -> Arc<dyn TransportService> {
-        #[derive(Debug)]
-        struct TestConnectionTransport {}
-
-        #[async_trait]
-        impl TransportService for TestConnectionTransport {
-            // Load meta information about cubes
-            async fn meta(&self, _ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError> {
-                panic!("It's a fake transport");
-            }
+    type LoadFn = dyn Fn(V1LoadRequestQuery) -> BoxFuture<'static, Result<V1LoadResponse, CubeError>>
+        + Send
+        + Sync;
 
-            // Execute load query
-            async fn load(
-                &self,
-                _query: V1LoadRequestQuery,
-                _ctx: Arc<AuthContext>,
-            ) -> Result<V1LoadResponse, CubeError> {
-                let response = r#"
-                    {
-                        "annotation": {
-                            "measures": [],
-                            "dimensions": [],
-                            "segments": [],
-                            "timeDimensions": []
-                        },
-                        "data": [
-                            {"KibanaSampleDataEcommerce.count": null, "KibanaSampleDataEcommerce.maxPrice": null, "KibanaSampleDataEcommerce.isBool": null},
-                            {"KibanaSampleDataEcommerce.count": 5, "KibanaSampleDataEcommerce.maxPrice": 5.05, "KibanaSampleDataEcommerce.isBool": true},
-                            {"KibanaSampleDataEcommerce.count": "5", "KibanaSampleDataEcommerce.maxPrice": "5.05", "KibanaSampleDataEcommerce.isBool": false},
-                            {"KibanaSampleDataEcommerce.count": null, "KibanaSampleDataEcommerce.maxPrice": null, "KibanaSampleDataEcommerce.isBool": "true"},
-                            {"KibanaSampleDataEcommerce.count": null, "KibanaSampleDataEcommerce.maxPrice": null, "KibanaSampleDataEcommerce.isBool": "false"}
-                        ]
-                    }
-                "#;
+    /// Transport which answers load requests by the given function, CubeScan doesn't request meta
+    struct TestTransport {
+        load: Box<LoadFn>,
+    }
 
-                let result: V1LoadResult = serde_json::from_str(response).unwrap();
+    impl fmt::Debug for TestTransport {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("TestTransport")
+        }
+    }
 
-                Ok(V1LoadResponse {
-                    pivot_query: None,
-                    slow_query: None,
-                    query_type: None,
-                    results: vec![result],
-                })
-            }
+    #[async_trait]
+    impl TransportService for TestTransport {
+        async fn meta(&self, _ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError> {
+            panic!("It's a fake transport");
+        }
+
+        async fn load(
+            &self,
+            query: V1LoadRequestQuery,
+            _ctx: Arc<AuthContext>,
+        ) -> Result<V1LoadResponse, CubeError> {
+            (self.load)(query).await
         }
+    }
+
+    fn test_transport<F, R>(load: F) -> Arc<dyn TransportService>
+    where
+        F: Fn(V1LoadRequestQuery) -> R + Send + Sync + 'static,
+        R: Future<Output = Result<V1LoadResponse, CubeError>> + Send + 'static,
+    {
+        Arc::new(TestTransport {
+            load: Box::new(move |query| load(query).boxed()),
+        })
+    }
+
+    fn load_response(data: Vec<serde_json::Value>) -> V1LoadResponse {
+        V1LoadResponse {
+            pivot_query: None,
+            slow_query: None,
+            query_type: None,
+            results: vec![V1LoadResult::new(
+                V1LoadResultAnnotation::new(json!([]), json!([]), json!([]), json!([])),
+                data,
+            )],
+        }
+    }
+
+    fn execution_context() -> Arc<TaskContext> {
+        let runtime = Arc::new(
+            RuntimeEnv::new(RuntimeConfig::new()).expect("Unable to create RuntimeEnv for testing"),
+        );
+
+        Arc::new(TaskContext::new(
+            "test".to_string(),
+            "session".to_string(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            runtime,
+        ))
+    }
 
-        Arc::new(TestConnectionTransport {})
+    fn get_test_transport() -> Arc<dyn TransportService> {
+        test_transport(|_| async move {
+            Ok(load_response(vec![
+                json!({"KibanaSampleDataEcommerce.count": null, "KibanaSampleDataEcommerce.maxPrice": null, "KibanaSampleDataEcommerce.isBool": null}),
+                json!({"KibanaSampleDataEcommerce.count": 5, "KibanaSampleDataEcommerce.maxPrice": 5.05, "KibanaSampleDataEcommerce.isBool": true}),
+                json!({"KibanaSampleDataEcommerce.count": "5", "KibanaSampleDataEcommerce.maxPrice": "5.05", "KibanaSampleDataEcommerce.isBool": false}),
+                json!({"KibanaSampleDataEcommerce.count": null, "KibanaSampleDataEcommerce.maxPrice": null, "KibanaSampleDataEcommerce.isBool": "true"}),
+                json!({"KibanaSampleDataEcommerce.count": null, "KibanaSampleDataEcommerce.maxPrice": null, "KibanaSampleDataEcommerce.isBool": "false"}),
+            ]))
+        })
     }
 
     #[tokio::test]
@@ -570,25 +813,21 @@ mod tests {
                 limit: None,
                 offset: None,
                 filters: None,
+                ungrouped: None,
             },
             auth_context: Arc::new(AuthContext {
                 access_token: "access_token".to_string(),
                 base_path: "base_path".to_string(),
+                claims: None,
+                superuser: false,
             }),
             transport: get_test_transport(),
+            cancel: CancellationToken::new(),
+            page_size: 50_000,
+            continue_wait_timeout: Duration::from_secs(300),
         };
 
-        let runtime = Arc::new(
-            RuntimeEnv::new(RuntimeConfig::new()).expect("Unable to create RuntimeEnv for testing"),
-        );
-        let task = Arc::new(TaskContext::new(
-            "test".to_string(),
-            "session".to_string(),
-            HashMap::new(),
-            HashMap::new(),
-            HashMap::new(),
-            runtime,
-        ));
+        let task = execution_context();
         let stream = scan_node.execute(0, task).await.unwrap();
         let batches = common::collect(stream).await.unwrap();
 
@@ -623,4 +862,509 @@ mod tests {
             .unwrap()
         )
     }
+
+    #[tokio::test]
+    async fn test_df_cube_scan_execute_typed_values() {
+        // Nulls, missing members, big integers and time with and without timezone
+        let transport = test_transport(|_| async move {
+            Ok(load_response(vec![
+                json!({
+                    "Orders.count": 9007199254740993u64,
+                    "Orders.amount": 1.5,
+                    "Orders.status": "new",
+                    "Orders.createdAt": "2022-01-01T10:00:00.000"
+                }),
+                json!({
+                    "Orders.count": "9223372036854775807",
+                    "Orders.amount": "2.5",
+                    "Orders.status": null,
+                    "Orders.createdAt": "2022-01-01T13:00:00.000+03:00"
+                }),
+                json!({
+                    "Orders.count": 5.0,
+                    "Orders.amount": null,
+                    "Orders.createdAt": "2022-01-01T10:00:00Z"
+                }),
+                json!({
+                    "Orders.count": null,
+                    "Orders.createdAt": null
+                }),
+            ]))
+        });
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("Orders.count", DataType::Int64, true),
+            Field::new("Orders.amount", DataType::Float64, true),
+            Field::new("Orders.status", DataType::Utf8, true),
+            Field::new(
+                "Orders.createdAt",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                true,
+            ),
+        ]));
+
+        let scan_node = CubeScanExecutionPlan {
+            schema: schema.clone(),
+            member_fields: schema
+                .fields()
+                .iter()
+                .map(|f| f.name().to_string())
+                .collect(),
+            request: V1LoadRequestQuery {
+                measures: None,
+                dimensions: None,
+                segments: None,
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            },
+            auth_context: Arc::new(AuthContext {
+                access_token: "access_token".to_string(),
+                base_path: "base_path".to_string(),
+                claims: None,
+                superuser: false,
+            }),
+            transport,
+            cancel: CancellationToken::new(),
+            page_size: 50_000,
+            continue_wait_timeout: Duration::from_secs(300),
+        };
+
+        let task = execution_context();
+        let stream = scan_node.execute(0, task).await.unwrap();
+        let batches = common::collect(stream).await.unwrap();
+
+        // 2022-01-01T10:00:00 UTC
+        let created_at = 1_641_031_200_000_000_000;
+        assert_eq!(
+            batches[0],
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from(vec![
+                        Some(9007199254740993),
+                        Some(i64::MAX),
+                        Some(5),
+                        None
+                    ])) as ArrayRef,
+                    Arc::new(Float64Array::from(vec![Some(1.5), Some(2.5), None, None]))
+                        as ArrayRef,
+                    Arc::new(StringArray::from(vec![Some("new"), None, None, None])) as ArrayRef,
+                    Arc::new(TimestampNanosecondArray::from(vec![
+                        Some(created_at),
+                        Some(created_at),
+                        Some(created_at),
+                        None
+                    ])) as ArrayRef,
+                ],
+            )
+            .unwrap()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_df_cube_scan_execute_ungrouped() {
+        // Rows are returned as is, without grouping by dimensions
+        let transport = |supported: bool| {
+            test_transport(move |query| async move {
+                assert_eq!(query.ungrouped, Some(true));
+                if !supported {
+                    return Err(CubeError::user(
+                        "Error: Unknown query property: ungrouped".to_string(),
+                    ));
+                }
+
+                Ok(load_response(vec![
+                    json!({"KibanaSampleDataEcommerce.customer_gender": "female"}),
+                    json!({"KibanaSampleDataEcommerce.customer_gender": "female"}),
+                    json!({"KibanaSampleDataEcommerce.customer_gender": "male"}),
+                ]))
+            })
+        };
+
+        let task = execution_context();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "KibanaSampleDataEcommerce.customer_gender",
+            DataType::Utf8,
+            false,
+        )]));
+        let scan_node = |supported: bool| CubeScanExecutionPlan {
+            schema: schema.clone(),
+            member_fields: vec!["KibanaSampleDataEcommerce.customer_gender".to_string()],
+            request: V1LoadRequestQuery {
+                measures: Some(vec![]),
+                dimensions: Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]),
+                segments: None,
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: Some(true),
+            },
+            auth_context: Arc::new(AuthContext {
+                access_token: "access_token".to_string(),
+                base_path: "base_path".to_string(),
+                claims: None,
+                superuser: false,
+            }),
+            transport: transport(supported),
+            cancel: CancellationToken::new(),
+            page_size: 50_000,
+            continue_wait_timeout: Duration::from_secs(300),
+        };
+
+        let stream = scan_node(true).execute(0, task.clone()).await.unwrap();
+        let batches = common::collect(stream).await.unwrap();
+        assert_eq!(
+            batches[0],
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(StringArray::from(vec!["female", "female", "male"])) as ArrayRef],
+            )
+            .unwrap()
+        );
+
+        match scan_node(false).execute(0, task).await {
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "Execution error: Cube rejected ungrouped query: Error: Unknown query property: ungrouped. Ungrouped queries can be disabled by CUBESQL_UNGROUPED_QUERIES=false"
+            ),
+            Ok(_) => panic!("Ungrouped query must be rejected"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_df_cube_scan_execute_pages() {
+        // Rows are sliced by limit and offset of the request
+        type Requests =
+            Arc<std::sync::Mutex<Vec<(Option<i32>, Option<i32>, Option<Vec<Vec<String>>>)>>>;
+        let transport = |requests: Requests| {
+            test_transport(move |query| {
+                requests
+                    .lock()
+                    .unwrap()
+                    .push((query.limit, query.offset, query.order.clone()));
+
+                let offset = query.offset.unwrap_or(0) as usize;
+                let limit = query.limit.unwrap_or(10_000) as usize;
+                let data = vec!["a", "b", "c", "d", "e"]
+                    .into_iter()
+                    .skip(offset)
+                    .take(limit)
+                    .map(|v| json!({ "KibanaSampleDataEcommerce.customer_gender": v }))
+                    .collect();
+
+                async move { Ok(load_response(data)) }
+            })
+        };
+
+        let task = execution_context();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "KibanaSampleDataEcommerce.customer_gender",
+            DataType::Utf8,
+            false,
+        )]));
+
+        let dimension_order = || {
+            Some(vec![vec![
+                "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                "asc".to_string(),
+            ]])
+        };
+        let explicit_order = || {
+            Some(vec![vec![
+                "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                "desc".to_string(),
+            ]])
+        };
+
+        let cases = vec![
+            // Pages are ordered by dimensions to be stable
+            (
+                None,
+                None,
+                vec![
+                    (Some(2), None, dimension_order()),
+                    (Some(2), Some(2), dimension_order()),
+                    (Some(2), Some(4), dimension_order()),
+                ],
+                vec!["a", "b", "c", "d", "e"],
+            ),
+            // Limit of the plan is split into pages
+            (
+                Some(3),
+                None,
+                vec![
+                    (Some(2), None, dimension_order()),
+                    (Some(1), Some(2), dimension_order()),
+                ],
+                vec!["a", "b", "c"],
+            ),
+            // Limit which fits into one page is requested as is
+            (Some(2), None, vec![(Some(2), None, None)], vec!["a", "b"]),
+            // Order of the query is kept
+            (
+                Some(3),
+                explicit_order(),
+                vec![
+                    (Some(2), None, explicit_order()),
+                    (Some(1), Some(2), explicit_order()),
+                ],
+                vec!["a", "b", "c"],
+            ),
+        ];
+        for (limit, order, expected_requests, expected_rows) in cases {
+            let requests = Arc::new(std::sync::Mutex::new(vec![]));
+            let scan_node = CubeScanExecutionPlan {
+                schema: schema.clone(),
+                member_fields: vec!["KibanaSampleDataEcommerce.customer_gender".to_string()],
+                request: V1LoadRequestQuery {
+                    measures: Some(vec![]),
+                    dimensions: Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]),
+                    segments: None,
+                    time_dimensions: None,
+                    order,
+                    limit,
+                    offset: None,
+                    filters: None,
+                    ungrouped: None,
+                },
+                auth_context: Arc::new(AuthContext {
+                    access_token: "access_token".to_string(),
+                    base_path: "base_path".to_string(),
+                    claims: None,
+                    superuser: false,
+                }),
+                transport: transport(requests.clone()),
+                cancel: CancellationToken::new(),
+                page_size: 2,
+                continue_wait_timeout: Duration::from_secs(300),
+            };
+
+            let stream = scan_node.execute(0, task.clone()).await.unwrap();
+            let batches = common::collect(stream).await.unwrap();
+            let rows = batches
+                .iter()
+                .flat_map(|batch| {
+                    let array = batch
+                        .column(0)
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .unwrap();
+                    (0..array.len())
+                        .map(|i| array.value(i).to_string())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+
+            assert_eq!(*requests.lock().unwrap(), expected_requests);
+            assert_eq!(rows, expected_rows);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_df_cube_scan_execute_session_dropped() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let session = sql::SessionState::new(
+            1,
+            "127.0.0.1".to_string(),
+            sql::session::DatabaseProtocol::PostgreSQL,
+            None,
+        );
+
+        let scan_node = CubeScanExecutionPlan {
+            schema: Arc::new(Schema::new(vec![Field::new(
+                "KibanaSampleDataEcommerce.count",
+                DataType::Utf8,
+                false,
+            )])),
+            member_fields: vec!["KibanaSampleDataEcommerce.count".to_string()],
+            request: V1LoadRequestQuery {
+                measures: None,
+                dimensions: None,
+                segments: None,
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            },
+            auth_context: Arc::new(AuthContext {
+                access_token: "access_token".to_string(),
+                base_path: "base_path".to_string(),
+                claims: None,
+                superuser: false,
+            }),
+            // Emulates long query, Cube is polled until the result is ready
+            transport: test_transport({
+                let polls = polls.clone();
+                move |_| {
+                    let polls = polls.clone();
+                    async move {
+                        loop {
+                            polls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                        }
+                    }
+                }
+            }),
+            cancel: session.begin_query(),
+            page_size: 50_000,
+            continue_wait_timeout: Duration::from_secs(300),
+        };
+
+        let task = execution_context();
+
+        // Execution is detached from the client, like DataFusion's spawned tasks
+        let execution = tokio::spawn(async move { scan_node.execute(0, task).await.is_err() });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(polls.load(Ordering::SeqCst) > 0);
+
+        // What happens on client disconnect, AsyncPostgresShim is dropped
+        session.terminate();
+
+        let res = tokio::time::timeout(Duration::from_millis(1000), execution).await;
+        assert!(matches!(res, Ok(Ok(true))), "Execution must be aborted");
+
+        let polls_after_drop = polls.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(polls.load(Ordering::SeqCst), polls_after_drop);
+    }
+
+    #[tokio::test]
+    async fn test_df_cube_scan_execute_continue_wait() {
+        // Emulates building of pre-aggregations, first requests are answered with continue wait
+        let transport = |waits: usize, requests: Arc<AtomicUsize>| {
+            test_transport(move |_| {
+                let result = if requests.fetch_add(1, Ordering::SeqCst) < waits {
+                    Err(CubeError::continue_wait())
+                } else {
+                    Ok(load_response(vec![
+                        json!({"KibanaSampleDataEcommerce.count": 5}),
+                    ]))
+                };
+
+                async move { result }
+            })
+        };
+
+        let task = execution_context();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "KibanaSampleDataEcommerce.count",
+            DataType::Utf8,
+            false,
+        )]));
+        let scan_node =
+            |waits: usize, requests: Arc<AtomicUsize>, timeout: Duration| CubeScanExecutionPlan {
+                schema: schema.clone(),
+                member_fields: vec!["KibanaSampleDataEcommerce.count".to_string()],
+                request: V1LoadRequestQuery {
+                    measures: None,
+                    dimensions: None,
+                    segments: None,
+                    time_dimensions: None,
+                    order: None,
+                    limit: None,
+                    offset: None,
+                    filters: None,
+                    ungrouped: None,
+                },
+                auth_context: Arc::new(AuthContext {
+                    access_token: "access_token".to_string(),
+                    base_path: "base_path".to_string(),
+                    claims: None,
+                    superuser: false,
+                }),
+                transport: transport(waits, requests),
+                cancel: CancellationToken::new(),
+                page_size: 50_000,
+                continue_wait_timeout: timeout,
+            };
+
+        // Request is repeated till Cube returns data
+        let requests = Arc::new(AtomicUsize::new(0));
+        let stream = scan_node(2, requests.clone(), Duration::from_secs(300))
+            .execute(0, task.clone())
+            .await
+            .unwrap();
+        let batches = common::collect(stream).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            batches[0],
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(StringArray::from(vec![Some("5")])) as ArrayRef],
+            )
+            .unwrap()
+        );
+
+        // Waiting is limited by timeout
+        let requests = Arc::new(AtomicUsize::new(0));
+        match scan_node(usize::MAX, requests.clone(), Duration::from_millis(350))
+            .execute(0, task)
+            .await
+        {
+            Err(err) => assert!(
+                err.to_string().contains("2 retries of continue wait"),
+                "{}",
+                err
+            ),
+            Ok(_) => panic!("Waiting must be limited by timeout"),
+        };
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_df_cube_scan_execute_canceled() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "KibanaSampleDataEcommerce.count",
+            DataType::Utf8,
+            false,
+        )]));
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let scan_node = CubeScanExecutionPlan {
+            schema: schema.clone(),
+            member_fields: vec!["KibanaSampleDataEcommerce.count".to_string()],
+            request: V1LoadRequestQuery {
+                measures: None,
+                dimensions: None,
+                segments: None,
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            },
+            auth_context: Arc::new(AuthContext {
+                access_token: "access_token".to_string(),
+                base_path: "base_path".to_string(),
+                claims: None,
+                superuser: false,
+            }),
+            transport: get_test_transport(),
+            cancel,
+            page_size: 50_000,
+            continue_wait_timeout: Duration::from_secs(300),
+        };
+
+        let task = execution_context();
+
+        match scan_node.execute(0, task).await {
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "Execution error: canceling statement due to user request"
+            ),
+            Ok(_) => panic!("Load must be canceled"),
+        }
+    }
 }
@@ -316,10 +316,8 @@ impl InfoSchemaColumnsProvider {
         let mut builder = InformationSchemaColumnsBuilder::new();
 
         for cube in cubes {
-            let position = 0;
-
-            for column in cube.get_columns() {
-                builder.add_column("def", "db", cube.name.clone(), &column, position)
+            for (position, column) in cube.get_columns().iter().enumerate() {
+                builder.add_column("def", "db", cube.name.clone(), column, position as u32 + 1)
             }
         }
 
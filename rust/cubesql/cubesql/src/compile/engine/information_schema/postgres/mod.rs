@@ -20,6 +20,7 @@ mod pg_namespace;
 mod pg_proc;
 mod pg_range;
 mod pg_settings;
+mod pg_stat_activity;
 mod pg_tables;
 mod pg_type;
 pub mod testing_dataset;
@@ -37,5 +38,6 @@ pub use pg_namespace::*;
 pub use pg_proc::*;
 pub use pg_range::*;
 pub use pg_settings::*;
+pub use pg_stat_activity::*;
 pub use pg_tables::*;
 pub use pg_type::*;
@@ -1,7 +1,8 @@
-use std::{any::Any, sync::Arc};
+use std::{any::Any, collections::HashMap, sync::Arc};
 
 use async_trait::async_trait;
 
+use crate::compile::macros::SqlMacro;
 use datafusion::{
     arrow::{
         array::{Array, ArrayRef, BooleanBuilder, Int32Builder, StringBuilder, UInt32Builder},
@@ -13,10 +14,13 @@ use datafusion::{
     logical_plan::Expr,
     physical_plan::{memory::MemoryExec, ExecutionPlan},
 };
+use itertools::Itertools;
+use pg_srv::PgTypeId;
 
 struct PgProc {
     oid: u32,
     proname: String,
+    prolang: u32,
     prokind: String,
     proleakproof: bool,
     proisstrict: bool,
@@ -32,7 +36,82 @@ struct PgProc {
     prosrc: String,
 }
 
+impl PgProc {
+    /// Definition in the same shape as pg_get_functiondef returns it
+    fn definition(&self) -> String {
+        let (schema, language) = match self.prolang {
+            14 => ("public", "sql"),
+            _ => ("pg_catalog", "internal"),
+        };
+
+        let arg_types = self
+            .proargtypes
+            .split_whitespace()
+            .filter_map(|oid| oid.parse::<u32>().ok())
+            .map(type_name_by_oid)
+            .collect::<Vec<_>>();
+        let arg_names = self
+            .proargnames
+            .trim_start_matches('{')
+            .trim_end_matches('}')
+            .split(',')
+            .collect::<Vec<_>>();
+        let args = arg_types
+            .iter()
+            .enumerate()
+            .map(|(idx, arg_type)| match arg_names.get(idx) {
+                Some(name) if !name.is_empty() => format!("{} {}", name, arg_type),
+                _ => arg_type.clone(),
+            })
+            .join(", ");
+
+        let mut attributes = vec![];
+        match self.provolatile.as_str() {
+            "i" => attributes.push("IMMUTABLE"),
+            "s" => attributes.push("STABLE"),
+            _ => (),
+        };
+        if self.proleakproof {
+            attributes.push("LEAKPROOF");
+        }
+        match self.proparallel.as_str() {
+            "s" => attributes.push("PARALLEL SAFE"),
+            "r" => attributes.push("PARALLEL RESTRICTED"),
+            _ => (),
+        };
+        if self.proisstrict {
+            attributes.push("STRICT");
+        }
+
+        let mut definition = format!(
+            "CREATE OR REPLACE FUNCTION {}.{}({})\n RETURNS {}{}\n LANGUAGE {}\n",
+            schema,
+            self.proname,
+            args,
+            if self.proretset { "SETOF " } else { "" },
+            type_name_by_oid(self.prorettype),
+            language
+        );
+        if !attributes.is_empty() {
+            definition.push_str(&format!(" {}\n", attributes.join(" ")));
+        }
+        definition.push_str(&format!("AS $function${}$function$\n", self.prosrc));
+
+        definition
+    }
+}
+
+fn type_name_by_oid(oid: u32) -> String {
+    match PgTypeId::from_oid(oid) {
+        Some(type_id) => type_id.to_type().typname.to_string(),
+        None => oid.to_string(),
+    }
+}
+
 struct PgCatalogProcBuilder {
+    // Definitions of functions by oid, are used by pg_get_functiondef
+    definitions: HashMap<u32, String>,
+
     oid: UInt32Builder,
     proname: StringBuilder,
     pronamespace: UInt32Builder,
@@ -70,6 +149,7 @@ impl PgCatalogProcBuilder {
         let capacity = 10;
 
         Self {
+            definitions: HashMap::new(),
             oid: UInt32Builder::new(capacity),
             proname: StringBuilder::new(capacity),
             pronamespace: UInt32Builder::new(capacity),
@@ -104,11 +184,15 @@ impl PgCatalogProcBuilder {
     }
 
     fn add_proc(&mut self, proc: &PgProc) {
+        self.definitions
+            .entry(proc.oid)
+            .or_insert_with(|| proc.definition());
+
         self.oid.append_value(proc.oid).unwrap();
         self.proname.append_value(proc.proname.clone()).unwrap();
         self.pronamespace.append_value(11).unwrap();
         self.proowner.append_value(10).unwrap();
-        self.prolang.append_value(12).unwrap();
+        self.prolang.append_value(proc.prolang).unwrap();
         self.procost.append_value(1).unwrap();
         self.prorows.append_value(0).unwrap();
         self.provariadic.append_value(0).unwrap();
@@ -188,15 +272,17 @@ impl PgCatalogProcBuilder {
 
 pub struct PgCatalogProcProvider {
     data: Arc<Vec<ArrayRef>>,
+    definitions: HashMap<u32, String>,
 }
 
 impl PgCatalogProcProvider {
-    pub fn new() -> Self {
+    pub fn new(sql_macros: &HashMap<String, SqlMacro>) -> Self {
         let mut builder = PgCatalogProcBuilder::new();
 
         builder.add_proc(&PgProc {
             oid: 89,
             proname: "version".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -215,6 +301,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 1191,
             proname: "generate_subscripts".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -233,6 +320,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 1191,
             proname: "generate_subscripts".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -251,6 +339,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 1192,
             proname: "generate_subscripts".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -269,6 +358,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 2331,
             proname: "unnest".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -287,6 +377,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 1081,
             proname: "format_type".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: false,
@@ -305,6 +396,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 1402,
             proname: "current_schema".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -323,6 +415,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 1387,
             proname: "pg_get_constraintdef".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -341,6 +434,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 1716,
             proname: "pg_get_expr".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -359,6 +453,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 2080,
             proname: "pg_type_is_visible".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -374,6 +469,25 @@ impl PgCatalogProcProvider {
             prosrc: "pg_type_is_visible".to_string(),
         });
 
+        builder.add_proc(&PgProc {
+            oid: 2098,
+            proname: "pg_get_functiondef".to_string(),
+            prolang: 12,
+            prokind: "f".to_string(),
+            proleakproof: false,
+            proisstrict: true,
+            proretset: false,
+            provolatile: "s".to_string(),
+            proparallel: "s".to_string(),
+            pronargs: 1,
+            prorettype: 25,
+            proargtypes: "26".to_string(),
+            proallargtypes: "".to_string(),
+            proargmodes: "".to_string(),
+            proargnames: "".to_string(),
+            prosrc: "pg_get_functiondef".to_string(),
+        });
+
         for (oid, prorettype) in [
             (2115, 20),
             (2116, 23),
@@ -401,6 +515,7 @@ impl PgCatalogProcProvider {
             builder.add_proc(&PgProc {
                 oid: *oid,
                 proname: "max".to_string(),
+                prolang: 12,
                 prokind: "a".to_string(),
                 proleakproof: false,
                 proisstrict: false,
@@ -420,6 +535,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 2508,
             proname: "pg_get_constraintdef".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -438,6 +554,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 2509,
             proname: "pg_get_expr".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -456,6 +573,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 3322,
             proname: "unnest".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -474,6 +592,7 @@ impl PgCatalogProcProvider {
         builder.add_proc(&PgProc {
             oid: 1293,
             proname: "unnest".to_string(),
+            prolang: 12,
             prokind: "f".to_string(),
             proleakproof: false,
             proisstrict: true,
@@ -489,10 +608,56 @@ impl PgCatalogProcProvider {
             prosrc: "multirange_unnest".to_string(),
         });
 
+        // Session functions, which were declared by CREATE FUNCTION
+        for definition in sql_macros
+            .values()
+            .sorted_by_key(|definition| definition.oid)
+        {
+            builder.add_proc(&PgProc {
+                oid: definition.oid,
+                proname: definition.name.clone(),
+                prolang: 14,
+                prokind: "f".to_string(),
+                proleakproof: false,
+                proisstrict: false,
+                proretset: false,
+                provolatile: "v".to_string(),
+                proparallel: "u".to_string(),
+                pronargs: definition.args.len() as i32,
+                prorettype: definition.return_type_oid,
+                proargtypes: definition
+                    .args
+                    .iter()
+                    .map(|arg| arg.type_oid.to_string())
+                    .join(" "),
+                proallargtypes: "".to_string(),
+                proargmodes: "".to_string(),
+                proargnames: match definition.args.iter().any(|arg| arg.name.is_some()) {
+                    true => format!(
+                        "{{{}}}",
+                        definition
+                            .args
+                            .iter()
+                            .map(|arg| arg.name.clone().unwrap_or_default())
+                            .join(",")
+                    ),
+                    false => "".to_string(),
+                },
+                prosrc: definition.source.clone(),
+            });
+        }
+
+        let definitions = std::mem::take(&mut builder.definitions);
+
         Self {
             data: Arc::new(builder.finish()),
+            definitions,
         }
     }
+
+    pub fn function_definition(&self, oid: u32) -> Option<String> {
+        self.definitions.get(&oid).cloned()
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,264 @@
+use std::{
+    any::Any,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{
+            Array, Int32Builder, Int64Builder, StringBuilder, TimestampNanosecondBuilder,
+            UInt32Builder,
+        },
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::sql::{SessionManager, SessionProcessList, TransactionState};
+
+struct PgCatalogStatActivityBuilder {
+    datid: UInt32Builder,
+    datname: StringBuilder,
+    pid: Int32Builder,
+    leader_pid: Int32Builder,
+    usesysid: UInt32Builder,
+    usename: StringBuilder,
+    application_name: StringBuilder,
+    client_addr: StringBuilder,
+    client_hostname: StringBuilder,
+    client_port: Int32Builder,
+    backend_start: TimestampNanosecondBuilder,
+    xact_start: TimestampNanosecondBuilder,
+    query_start: TimestampNanosecondBuilder,
+    state_change: TimestampNanosecondBuilder,
+    wait_event_type: StringBuilder,
+    wait_event: StringBuilder,
+    state: StringBuilder,
+    backend_xid: UInt32Builder,
+    backend_xmin: UInt32Builder,
+    query_id: Int64Builder,
+    query: StringBuilder,
+    backend_type: StringBuilder,
+}
+
+fn timestamp_nanos(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as i64)
+        .unwrap_or(0)
+}
+
+impl PgCatalogStatActivityBuilder {
+    fn new() -> Self {
+        let capacity = 10;
+
+        Self {
+            datid: UInt32Builder::new(capacity),
+            datname: StringBuilder::new(capacity),
+            pid: Int32Builder::new(capacity),
+            leader_pid: Int32Builder::new(capacity),
+            usesysid: UInt32Builder::new(capacity),
+            usename: StringBuilder::new(capacity),
+            application_name: StringBuilder::new(capacity),
+            client_addr: StringBuilder::new(capacity),
+            client_hostname: StringBuilder::new(capacity),
+            client_port: Int32Builder::new(capacity),
+            backend_start: TimestampNanosecondBuilder::new(capacity),
+            xact_start: TimestampNanosecondBuilder::new(capacity),
+            query_start: TimestampNanosecondBuilder::new(capacity),
+            state_change: TimestampNanosecondBuilder::new(capacity),
+            wait_event_type: StringBuilder::new(capacity),
+            wait_event: StringBuilder::new(capacity),
+            state: StringBuilder::new(capacity),
+            backend_xid: UInt32Builder::new(capacity),
+            backend_xmin: UInt32Builder::new(capacity),
+            query_id: Int64Builder::new(capacity),
+            query: StringBuilder::new(capacity),
+            backend_type: StringBuilder::new(capacity),
+        }
+    }
+
+    fn add_session(&mut self, session: SessionProcessList) {
+        self.datid.append_null().unwrap();
+        match session.database {
+            Some(database) => self.datname.append_value(database).unwrap(),
+            None => self.datname.append_null().unwrap(),
+        }
+        self.pid.append_value(session.id as i32).unwrap();
+        self.leader_pid.append_null().unwrap();
+        self.usesysid.append_null().unwrap();
+        match session.user {
+            Some(user) => self.usename.append_value(user).unwrap(),
+            None => self.usename.append_null().unwrap(),
+        }
+        self.application_name
+            .append_value(session.application_name)
+            .unwrap();
+        self.client_addr.append_value(session.host).unwrap();
+        self.client_hostname.append_null().unwrap();
+        self.client_port.append_null().unwrap();
+        self.backend_start
+            .append_value(timestamp_nanos(session.started_at))
+            .unwrap();
+        self.xact_start.append_null().unwrap();
+        match session.query_started_at {
+            Some(started_at) => {
+                self.query_start
+                    .append_value(timestamp_nanos(started_at))
+                    .unwrap();
+                self.state_change
+                    .append_value(timestamp_nanos(started_at))
+                    .unwrap();
+            }
+            None => {
+                self.query_start.append_null().unwrap();
+                self.state_change.append_null().unwrap();
+            }
+        }
+        self.wait_event_type.append_null().unwrap();
+        self.wait_event.append_null().unwrap();
+
+        let state = match (session.query_started_at, session.transaction) {
+            (Some(_), _) => "active",
+            (None, TransactionState::None) => "idle",
+            (None, TransactionState::Active) => "idle in transaction",
+            (None, TransactionState::Failed) => "idle in transaction (aborted)",
+        };
+        self.state.append_value(state).unwrap();
+
+        self.backend_xid.append_null().unwrap();
+        self.backend_xmin.append_null().unwrap();
+        self.query_id.append_null().unwrap();
+        self.query
+            .append_value(session.query.unwrap_or_default())
+            .unwrap();
+        self.backend_type.append_value("client backend").unwrap();
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+        columns.push(Arc::new(self.datid.finish()));
+        columns.push(Arc::new(self.datname.finish()));
+        columns.push(Arc::new(self.pid.finish()));
+        columns.push(Arc::new(self.leader_pid.finish()));
+        columns.push(Arc::new(self.usesysid.finish()));
+        columns.push(Arc::new(self.usename.finish()));
+        columns.push(Arc::new(self.application_name.finish()));
+        columns.push(Arc::new(self.client_addr.finish()));
+        columns.push(Arc::new(self.client_hostname.finish()));
+        columns.push(Arc::new(self.client_port.finish()));
+        columns.push(Arc::new(self.backend_start.finish()));
+        columns.push(Arc::new(self.xact_start.finish()));
+        columns.push(Arc::new(self.query_start.finish()));
+        columns.push(Arc::new(self.state_change.finish()));
+        columns.push(Arc::new(self.wait_event_type.finish()));
+        columns.push(Arc::new(self.wait_event.finish()));
+        columns.push(Arc::new(self.state.finish()));
+        columns.push(Arc::new(self.backend_xid.finish()));
+        columns.push(Arc::new(self.backend_xmin.finish()));
+        columns.push(Arc::new(self.query_id.finish()));
+        columns.push(Arc::new(self.query.finish()));
+        columns.push(Arc::new(self.backend_type.finish()));
+
+        columns
+    }
+}
+
+/// Live sessions of SQL API, they are read at execution, so the session which runs the query
+/// is active
+pub struct PgCatalogStatActivityProvider {
+    sessions: Arc<SessionManager>,
+}
+
+impl PgCatalogStatActivityProvider {
+    pub fn new(sessions: Arc<SessionManager>) -> Self {
+        Self { sessions }
+    }
+}
+
+#[async_trait]
+impl TableProvider for PgCatalogStatActivityProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("datid", DataType::UInt32, true),
+            Field::new("datname", DataType::Utf8, true),
+            Field::new("pid", DataType::Int32, false),
+            Field::new("leader_pid", DataType::Int32, true),
+            Field::new("usesysid", DataType::UInt32, true),
+            Field::new("usename", DataType::Utf8, true),
+            Field::new("application_name", DataType::Utf8, false),
+            Field::new("client_addr", DataType::Utf8, false),
+            Field::new("client_hostname", DataType::Utf8, true),
+            Field::new("client_port", DataType::Int32, true),
+            Field::new(
+                "backend_start",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+            Field::new(
+                "xact_start",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                true,
+            ),
+            Field::new(
+                "query_start",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                true,
+            ),
+            Field::new(
+                "state_change",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                true,
+            ),
+            Field::new("wait_event_type", DataType::Utf8, true),
+            Field::new("wait_event", DataType::Utf8, true),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("backend_xid", DataType::UInt32, true),
+            Field::new("backend_xmin", DataType::UInt32, true),
+            Field::new("query_id", DataType::Int64, true),
+            Field::new("query", DataType::Utf8, false),
+            Field::new("backend_type", DataType::Utf8, false),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let mut builder = PgCatalogStatActivityBuilder::new();
+
+        for session in self.sessions.process_list() {
+            builder.add_session(session);
+        }
+
+        let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
@@ -5,7 +5,10 @@ use async_trait::async_trait;
 use crate::transport::CubeMetaTable;
 use datafusion::{
     arrow::{
-        array::{Array, ArrayRef, BooleanBuilder, Int16Builder, StringBuilder, UInt32Builder},
+        array::{
+            Array, ArrayRef, BooleanBuilder, Int16Builder, Int32Builder, StringBuilder,
+            UInt32Builder,
+        },
         datatypes::{DataType, Field, Schema, SchemaRef},
         record_batch::RecordBatch,
     },
@@ -14,7 +17,7 @@ use datafusion::{
     logical_plan::Expr,
     physical_plan::{memory::MemoryExec, ExecutionPlan},
 };
-use pg_srv::PgType;
+use pg_srv::{PgType, PgTypeId};
 
 struct PgCatalogTypeBuilder {
     oid: UInt32Builder,
@@ -43,10 +46,10 @@ struct PgCatalogTypeBuilder {
     typalign: StringBuilder,
     typstorage: StringBuilder,
     typnotnull: BooleanBuilder,
-    typbasetype: StringBuilder,
-    typtypmod: StringBuilder,
-    typndims: StringBuilder,
-    typcollation: StringBuilder,
+    typbasetype: UInt32Builder,
+    typtypmod: Int32Builder,
+    typndims: Int32Builder,
+    typcollation: UInt32Builder,
     typdefaultbin: StringBuilder,
     typdefault: StringBuilder,
     typacl: StringBuilder,
@@ -83,10 +86,10 @@ impl PgCatalogTypeBuilder {
             typalign: StringBuilder::new(capacity),
             typstorage: StringBuilder::new(capacity),
             typnotnull: BooleanBuilder::new(capacity),
-            typbasetype: StringBuilder::new(capacity),
-            typtypmod: StringBuilder::new(capacity),
-            typndims: StringBuilder::new(capacity),
-            typcollation: StringBuilder::new(capacity),
+            typbasetype: UInt32Builder::new(capacity),
+            typtypmod: Int32Builder::new(capacity),
+            typndims: Int32Builder::new(capacity),
+            typcollation: UInt32Builder::new(capacity),
             typdefaultbin: StringBuilder::new(capacity),
             typdefault: StringBuilder::new(capacity),
             typacl: StringBuilder::new(capacity),
@@ -109,26 +112,80 @@ impl PgCatalogTypeBuilder {
         self.typsubscript.append_value(typ.typsubscript).unwrap();
         self.typelem.append_value(typ.typelem).unwrap();
         self.typarray.append_value(typ.typarray).unwrap();
-        // TODO: Check
-        self.typinput.append_null().unwrap();
-        self.typoutput.append_null().unwrap();
-        self.typreceive.append_null().unwrap();
-        self.typsend.append_null().unwrap();
+        // Drivers detect arrays and composite types by their I/O functions
+        match Self::io_functions(typ) {
+            Some([input, output, receive, send]) => {
+                self.typinput.append_value(input).unwrap();
+                self.typoutput.append_value(output).unwrap();
+                self.typreceive.append_value(receive).unwrap();
+                self.typsend.append_value(send).unwrap();
+            }
+            None => {
+                self.typinput.append_null().unwrap();
+                self.typoutput.append_null().unwrap();
+                self.typreceive.append_null().unwrap();
+                self.typsend.append_null().unwrap();
+            }
+        }
         self.typmodin.append_null().unwrap();
         self.typmodout.append_null().unwrap();
         self.typanalyze.append_null().unwrap();
         self.typalign.append_value(typ.typalign).unwrap();
         self.typstorage.append_value(typ.typstorage).unwrap();
         self.typnotnull.append_value(false).unwrap();
-        self.typbasetype.append_null().unwrap();
-        self.typtypmod.append_null().unwrap();
-        self.typndims.append_null().unwrap();
-        self.typcollation.append_null().unwrap();
+        self.typbasetype
+            .append_value(Self::domain_base_type(typ).map_or(0, |base| base.oid))
+            .unwrap();
+        self.typtypmod.append_value(-1).unwrap();
+        self.typndims.append_value(0).unwrap();
+        self.typcollation
+            .append_value(Self::collation(typ))
+            .unwrap();
         self.typdefaultbin.append_null().unwrap();
         self.typdefault.append_null().unwrap();
         self.typacl.append_null().unwrap();
     }
 
+    fn io_functions(typ: &PgType) -> Option<[&'static str; 4]> {
+        match (typ.typtype, typ.typcategory, typ.typname) {
+            (_, "A", _) => Some(["array_in", "array_out", "array_recv", "array_send"]),
+            ("c", _, _) | (_, _, "record") => {
+                Some(["record_in", "record_out", "record_recv", "record_send"])
+            }
+            _ => None,
+        }
+    }
+
+    /// Domains of information_schema
+    fn domain_base_type(typ: &PgType) -> Option<&'static PgType<'static>> {
+        match typ.typname {
+            "character_data" => Some(PgType::get_by_tid(PgTypeId::VARCHAR)),
+            "sql_identifier" => Some(PgType::get_by_tid(PgTypeId::NAME)),
+            _ => None,
+        }
+    }
+
+    /// OID of default collation for string types (C collation for name), arrays and domains use
+    /// collation of their elements and base types
+    fn collation(typ: &PgType) -> u32 {
+        let typ = match typ.typcategory {
+            "A" => match PgType::get_all().into_iter().find(|t| t.oid == typ.typelem) {
+                Some(elem) => elem,
+                None => return 0,
+            },
+            _ => match Self::domain_base_type(typ) {
+                Some(base) => base,
+                None => typ,
+            },
+        };
+
+        match (typ.typname, typ.typcategory) {
+            ("name", _) => 950,
+            (_, "S") => 100,
+            _ => 0,
+        }
+    }
+
     fn finish(mut self) -> Vec<Arc<dyn Array>> {
         let mut columns: Vec<Arc<dyn Array>> = vec![];
         columns.push(Arc::new(self.oid.finish()));
@@ -266,10 +323,10 @@ impl TableProvider for PgCatalogTypeProvider {
             Field::new("typalign", DataType::Utf8, true),
             Field::new("typstorage", DataType::Utf8, true),
             Field::new("typnotnull", DataType::Boolean, true),
-            Field::new("typbasetype", DataType::Utf8, true),
-            Field::new("typtypmod", DataType::Utf8, true),
-            Field::new("typndims", DataType::Utf8, true),
-            Field::new("typcollation", DataType::Utf8, true),
+            Field::new("typbasetype", DataType::UInt32, false),
+            Field::new("typtypmod", DataType::Int32, false),
+            Field::new("typndims", DataType::Int32, false),
+            Field::new("typcollation", DataType::UInt32, false),
             Field::new("typdefaultbin", DataType::Utf8, true),
             Field::new("typdefault", DataType::Utf8, true),
             Field::new("typacl", DataType::Utf8, true),
@@ -34,7 +34,8 @@ use super::information_schema::postgres::{
     PgCatalogAttributeProvider, PgCatalogClassProvider, PgCatalogConstraintProvider,
     PgCatalogDependProvider, PgCatalogDescriptionProvider, PgCatalogIndexProvider,
     PgCatalogNamespaceProvider, PgCatalogProcProvider, PgCatalogRangeProvider,
-    PgCatalogSettingsProvider, PgCatalogTableProvider, PgCatalogTypeProvider,
+    PgCatalogSettingsProvider, PgCatalogStatActivityProvider, PgCatalogTableProvider,
+    PgCatalogTypeProvider,
 };
 
 use crate::compile::engine::information_schema::postgres::testing_dataset::InfoSchemaTestingDatasetProvider;
@@ -309,6 +310,10 @@ impl DatabaseProtocol {
             "pg_catalog.pg_depend".to_string()
         } else if let Some(_) = any.downcast_ref::<PgCatalogAmProvider>() {
             "pg_catalog.pg_am".to_string()
+        } else if let Some(_) = any.downcast_ref::<PgCatalogStatActivityProvider>() {
+            "pg_catalog.pg_stat_activity".to_string()
+        } else if let Some(_) = any.downcast_ref::<MySqlSchemaProcesslistProvider>() {
+            "information_schema.processlist".to_string()
         } else if let Some(_) = any.downcast_ref::<InfoSchemaTestingDatasetProvider>() {
             "information_schema.testing_dataset".to_string()
         } else {
@@ -396,6 +401,12 @@ impl DatabaseProtocol {
                 "testing_dataset" => {
                     return Some(Arc::new(InfoSchemaTestingDatasetProvider::new(5, 1000)))
                 }
+                // Simpler view of live sessions, the same as in MySQL
+                "processlist" => {
+                    return Some(Arc::new(MySqlSchemaProcesslistProvider::new(
+                        context.sessions.clone(),
+                    )))
+                }
                 _ => return None,
             },
             "pg_catalog" => match table.as_str() {
@@ -417,19 +428,30 @@ impl DatabaseProtocol {
                 "pg_class" => {
                     return Some(Arc::new(PgCatalogClassProvider::new(&context.meta.tables)))
                 }
-                "pg_proc" => return Some(Arc::new(PgCatalogProcProvider::new())),
+                "pg_proc" => {
+                    return Some(Arc::new(PgCatalogProcProvider::new(
+                        &context.session_state.sql_macros(),
+                    )))
+                }
                 "pg_settings" => {
                     return Some(Arc::new(PgCatalogSettingsProvider::new(
-                        context
-                            .sessions
-                            .server
-                            .all_variables(context.session_state.protocol.clone()),
+                        context.session_state.visible_variables(
+                            context
+                                .sessions
+                                .server
+                                .all_variables(context.session_state.protocol.clone()),
+                        ),
                     )))
                 }
                 "pg_description" => return Some(Arc::new(PgCatalogDescriptionProvider::new())),
                 "pg_constraint" => return Some(Arc::new(PgCatalogConstraintProvider::new())),
                 "pg_depend" => return Some(Arc::new(PgCatalogDependProvider::new())),
                 "pg_am" => return Some(Arc::new(PgCatalogAmProvider::new())),
+                "pg_stat_activity" => {
+                    return Some(Arc::new(PgCatalogStatActivityProvider::new(
+                        context.sessions.clone(),
+                    )))
+                }
                 _ => return None,
             },
             _ => return None,
@@ -1,28 +1,31 @@
 use std::any::type_name;
+use std::convert::TryFrom;
 use std::sync::Arc;
 
-use chrono::{Duration, NaiveDateTime};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
 use datafusion::{
     arrow::datatypes::UInt32Type,
     arrow::{
         array::{
             new_null_array, Array, ArrayBuilder, ArrayRef, BooleanArray, BooleanBuilder,
-            Float64Array, GenericStringArray, Int64Array, Int64Builder, IntervalDayTimeArray,
-            IntervalDayTimeBuilder, ListArray, ListBuilder, PrimitiveArray, PrimitiveBuilder,
-            StringArray, StringBuilder, StructBuilder, TimestampNanosecondArray, UInt32Builder,
+            Date32Array, Float64Array, GenericStringArray, Int32Builder, Int64Array, Int64Builder,
+            IntervalDayTimeArray, IntervalDayTimeBuilder, ListArray, ListBuilder, PrimitiveArray,
+            PrimitiveBuilder, StringArray, StringBuilder, StructBuilder, TimestampNanosecondArray,
+            UInt32Builder,
         },
         compute::{cast, concat},
         datatypes::{
-            DataType, Field, Float64Type, Int32Type, Int64Type, IntervalDayTimeType, IntervalUnit,
-            TimeUnit, TimestampNanosecondType, UInt64Type,
+            DataType, Field, Float64Type, Int32Type, Int64Type, IntervalDayTimeType,
+            IntervalMonthDayNanoType, IntervalUnit, IntervalYearMonthType, TimeUnit,
+            TimestampNanosecondType, UInt64Type,
         },
     },
     error::{DataFusionError, Result},
     logical_plan::{create_udaf, create_udf},
     physical_plan::{
         functions::{
-            datetime_expressions::date_trunc, make_scalar_function, make_table_function, Signature,
-            TypeSignature, Volatility,
+            datetime_expressions::{date_trunc, to_timestamp},
+            make_scalar_function, make_table_function, Signature, TypeSignature, Volatility,
         },
         udaf::AggregateUDF,
         udf::ScalarUDF,
@@ -33,11 +36,16 @@ use datafusion::{
 };
 
 use crate::{
-    compile::engine::df::{
-        coerce::{if_coercion, least_coercion},
-        columar::if_then_else,
+    compile::engine::{
+        df::{
+            coerce::{coalesce_coercion, if_coercion, least_coercion},
+            columar::if_then_else,
+            datetime, numeric,
+        },
+        information_schema::postgres::PgCatalogProcProvider,
     },
-    sql::SessionState,
+    sql::{database_variables::DatabaseVariables, SessionManager, SessionState},
+    CubeError, CubeErrorKind,
 };
 
 pub type ReturnTypeFunction = Arc<dyn Fn(&[DataType]) -> Result<Arc<DataType>> + Send + Sync>;
@@ -137,10 +145,31 @@ pub fn create_connection_id_udf(state: Arc<SessionState>) -> ScalarUDF {
     )
 }
 
+pub fn create_session_user_udf(name: &str, state: Arc<SessionState>) -> ScalarUDF {
+    let session_user = make_scalar_function(move |_args: &[ArrayRef]| {
+        let mut builder = StringBuilder::new(1);
+        if let Some(user) = &state.user() {
+            builder.append_value(user.clone()).unwrap();
+        } else {
+            builder.append_null()?;
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    create_udf(
+        name,
+        vec![],
+        Arc::new(DataType::Utf8),
+        Volatility::Immutable,
+        session_user,
+    )
+}
+
 pub fn create_pg_backend_pid(state: Arc<SessionState>) -> ScalarUDF {
     let version = make_scalar_function(move |_args: &[ArrayRef]| {
-        let mut builder = UInt32Builder::new(1);
-        builder.append_value(state.connection_id).unwrap();
+        let mut builder = Int32Builder::new(1);
+        builder.append_value(state.connection_id as i32).unwrap();
 
         Ok(Arc::new(builder.finish()) as ArrayRef)
     });
@@ -148,17 +177,41 @@ pub fn create_pg_backend_pid(state: Arc<SessionState>) -> ScalarUDF {
     create_udf(
         "pg_backend_pid",
         vec![],
-        Arc::new(DataType::UInt32),
+        Arc::new(DataType::Int32),
         Volatility::Immutable,
         version,
     )
 }
 
-pub fn create_current_schema_udf() -> ScalarUDF {
+/// Schemas which exist in pg_namespace
+const PG_SCHEMAS: [&str; 3] = ["pg_catalog", "public", "information_schema"];
+
+/// Resolves search_path to the list of existing schemas, the same way as PostgreSQL does
+pub fn search_path_schemas(search_path: &str, user: Option<String>) -> Vec<String> {
+    search_path
+        .split(',')
+        .map(|schema| {
+            let schema = schema.trim();
+            if schema == "$user" || schema == "\"$user\"" {
+                user.clone().unwrap_or_default()
+            } else if schema.len() > 1 && schema.starts_with('"') && schema.ends_with('"') {
+                schema[1..schema.len() - 1].replace("\"\"", "\"")
+            } else {
+                schema.to_lowercase()
+            }
+        })
+        .filter(|schema| PG_SCHEMAS.contains(&schema.as_str()))
+        .collect()
+}
+
+pub fn create_current_schema_udf(schemas: Vec<String>) -> ScalarUDF {
     let current_schema = make_scalar_function(move |_args: &[ArrayRef]| {
         let mut builder = StringBuilder::new(1);
 
-        builder.append_value("public").unwrap();
+        match schemas.first() {
+            Some(schema) => builder.append_value(schema).unwrap(),
+            None => builder.append_null()?,
+        }
 
         Ok(Arc::new(builder.finish()) as ArrayRef)
     });
@@ -225,6 +278,69 @@ macro_rules! downcast_list_arg {
 // Returns the position of the first occurrence of substring substr in string str.
 // This is the same as the two-argument form of LOCATE(), except that the order of
 // the arguments is reversed.
+/// current_setting(name [, missing_ok]) by the visible variables of session, unknown setting
+/// is NULL when missing_ok is true
+pub fn create_current_setting_udf(variables: DatabaseVariables) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let names = downcast_string_arg!(args[0], "name", i32);
+        let missing_ok = match args.get(1) {
+            Some(arg) => Some(downcast_boolean_arr!(arg, "missing_ok")),
+            None => None,
+        };
+
+        let mut builder = StringBuilder::new(names.len());
+        for i in 0..names.len() {
+            if names.is_null(i) {
+                builder.append_null()?;
+                continue;
+            }
+
+            let name = names.value(i);
+            let variable = variables
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, variable)| variable);
+
+            match variable.map(|variable| &variable.value) {
+                Some(ScalarValue::Utf8(value)) => {
+                    builder.append_value(value.clone().unwrap_or_default())?
+                }
+                Some(value) => builder.append_value(value.to_string())?,
+                None => {
+                    let is_missing_ok = missing_ok
+                        .map(|arr| !arr.is_null(i) && arr.value(i))
+                        .unwrap_or(false);
+                    if !is_missing_ok {
+                        return Err(DataFusionError::Execution(format!(
+                            "unrecognized configuration parameter \"{}\"",
+                            name
+                        )));
+                    }
+
+                    builder.append_null()?
+                }
+            }
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        "current_setting",
+        &Signature::one_of(
+            vec![
+                TypeSignature::Exact(vec![DataType::Utf8]),
+                TypeSignature::Exact(vec![DataType::Utf8, DataType::Boolean]),
+            ],
+            Volatility::Stable,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
 pub fn create_instr_udf() -> ScalarUDF {
     let fun = make_scalar_function(move |args: &[ArrayRef]| {
         assert!(args.len() == 2);
@@ -489,6 +605,66 @@ pub fn create_least_udf() -> ScalarUDF {
     )
 }
 
+// COALESCE() returns the first of its arguments that is not null, the result has the unified type of arguments.
+pub fn create_coalesce_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(!args.is_empty());
+
+        let types = args
+            .iter()
+            .map(|arg| arg.data_type().clone())
+            .collect::<Vec<_>>();
+        let return_type = coalesce_coercion(&types).ok_or_else(|| {
+            DataFusionError::Execution(format!(
+                "COALESCE types cannot be matched, actual: {:?}",
+                types
+            ))
+        })?;
+
+        if return_type == DataType::Null {
+            return Ok(new_null_array(&DataType::Null, args[0].len()));
+        }
+
+        let mut result = cast(&args[0], &return_type)?;
+        for arg in args.iter().skip(1) {
+            let mut is_not_null = BooleanBuilder::new(result.len());
+            for i in 0..result.len() {
+                is_not_null.append_value(!result.is_null(i))?;
+            }
+
+            result = if_then_else(
+                &is_not_null.finish(),
+                result,
+                cast(arg, &return_type)?,
+                &return_type,
+            )?;
+        }
+
+        Ok(result)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |types| {
+        let base_type = coalesce_coercion(types).ok_or_else(|| {
+            DataFusionError::Execution(format!(
+                "COALESCE types cannot be matched, actual: {:?}",
+                types
+            ))
+        })?;
+
+        Ok(Arc::new(base_type))
+    });
+
+    ScalarUDF::new(
+        "coalesce",
+        &Signature::one_of(
+            (1..=16).map(TypeSignature::Any).collect(),
+            Volatility::Immutable,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
 // CONVERT_TZ() converts a datetime value dt from the time zone given by from_tz to the time zone given by to_tz and returns the resulting value.
 pub fn create_convert_tz_udf() -> ScalarUDF {
     let fun = make_scalar_function(move |args: &[ArrayRef]| {
@@ -1005,18 +1181,21 @@ pub fn create_str_to_date() -> ScalarUDF {
     )
 }
 
-pub fn create_current_schemas_udf() -> ScalarUDF {
+pub fn create_current_schemas_udf(schemas: Vec<String>) -> ScalarUDF {
     let current_schemas = make_scalar_function(move |args: &[ArrayRef]| {
         assert!(args.len() == 1);
 
-        let primitive_builder = StringBuilder::new(2);
+        let primitive_builder = StringBuilder::new(schemas.len() + 1);
         let mut builder = ListBuilder::new(primitive_builder);
 
         let including_implicit = downcast_boolean_arr!(&args[0], "implicit").value(0);
-        if including_implicit {
+        // pg_catalog is always searched first, unless it's placed in search_path explicitly
+        if including_implicit && !schemas.iter().any(|schema| schema == "pg_catalog") {
             builder.values().append_value("pg_catalog").unwrap();
         }
-        builder.values().append_value("public").unwrap();
+        for schema in schemas.iter() {
+            builder.values().append_value(schema).unwrap();
+        }
         builder.append(true).unwrap();
 
         Ok(Arc::new(builder.finish()) as ArrayRef)
@@ -1263,6 +1442,153 @@ pub fn create_pg_get_expr_udf() -> ScalarUDF {
     )
 }
 
+pub fn create_pg_get_functiondef_udf(name: &str, state: Arc<SessionState>) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 1);
+
+        let oids = cast(&args[0], &DataType::UInt32)?;
+        let oids_arr = downcast_primitive_arg!(oids, "oid", UInt32Type);
+
+        let provider = PgCatalogProcProvider::new(&state.sql_macros());
+
+        let result = oids_arr
+            .iter()
+            .map(|oid| oid.and_then(|oid| provider.function_definition(oid)))
+            .collect::<StringArray>();
+
+        Ok(Arc::new(result))
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        name,
+        &Signature::one_of(
+            vec![
+                TypeSignature::Exact(vec![DataType::UInt32]),
+                TypeSignature::Exact(vec![DataType::Int64]),
+            ],
+            Volatility::Stable,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
+/// Human-readable size as pg_size_pretty formats it: bytes below 10 kB, then the largest unit in
+/// which the value is below 20480, with half-up rounding
+fn pg_size_pretty(size: i64) -> String {
+    const LIMIT: i64 = 10 * 1024;
+    const HALF_LIMIT: i64 = 2 * LIMIT - 1;
+
+    if size.abs() < LIMIT {
+        return format!("{} bytes", size);
+    }
+
+    // One extra bit is kept for rounding
+    let mut size = size >> 9;
+    for unit in ["kB", "MB", "GB"] {
+        if size.abs() < HALF_LIMIT {
+            return format!("{} {}", (size + if size < 0 { 0 } else { 1 }) / 2, unit);
+        }
+        size >>= 10;
+    }
+
+    format!("{} TB", (size + if size < 0 { 0 } else { 1 }) / 2)
+}
+
+pub fn create_pg_size_pretty_udf(name: &str) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 1);
+
+        let sizes = cast(&args[0], &DataType::Int64)?;
+        let sizes = downcast_primitive_arg!(sizes, "size", Int64Type);
+
+        let result = sizes
+            .iter()
+            .map(|size| size.map(pg_size_pretty))
+            .collect::<StringArray>();
+
+        Ok(Arc::new(result) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        name,
+        &Signature::any(1, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+// Cube has no sequences, so there is no sequence for any column
+pub fn create_pg_get_serial_sequence_udf(name: &str) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 2);
+
+        let mut builder = StringBuilder::new(args[0].len());
+        for _ in 0..args[0].len() {
+            builder.append_null()?;
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        name,
+        &Signature::exact(vec![DataType::Utf8, DataType::Utf8], Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// Terminates the session with the given pid, only superuser is allowed to do it
+pub fn create_pg_terminate_backend_udf(
+    name: &str,
+    state: Arc<SessionState>,
+    session_manager: Arc<SessionManager>,
+) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 1);
+
+        let superuser = state
+            .auth_context()
+            .map(|ctx| ctx.superuser)
+            .unwrap_or(false);
+        if !superuser {
+            return Err(DataFusionError::External(Box::new(
+                CubeError::user("permission denied to terminate process".to_string())
+                    .with_kind(CubeErrorKind::InsufficientPrivilege),
+            )));
+        }
+
+        let pids = downcast_primitive_arg!(args[0], "pid", Int64Type);
+        let result = pids
+            .iter()
+            .map(|pid| {
+                pid.map(|pid| {
+                    u32::try_from(pid)
+                        .map(|pid| session_manager.terminate_session(pid))
+                        .unwrap_or(false)
+                })
+            })
+            .collect::<BooleanArray>();
+
+        Ok(Arc::new(result) as ArrayRef)
+    });
+
+    create_udf(
+        name,
+        vec![DataType::Int64],
+        Arc::new(DataType::Boolean),
+        Volatility::Volatile,
+        fun,
+    )
+}
+
 pub fn pg_table_is_visible() -> ScalarUDF {
     let fun = make_scalar_function(move |args: &[ArrayRef]| {
         assert!(args.len() == 1);
@@ -1350,21 +1676,26 @@ macro_rules! generate_series_udtf {
 
             let mut builder = PrimitiveBuilder::<$TYPE>::new(1);
             for (i, (start, end)) in l_arr.iter().zip(r_arr.iter()).enumerate() {
-                let step = if step_arr.len() > i {
-                    step_arr.value(i)
+                let step_index = if step_arr.len() > i { i } else { 0 };
+                let step = if step_arr.is_null(step_index) {
+                    None
                 } else {
-                    step_arr.value(0)
+                    Some(step_arr.value(step_index))
                 };
 
-                let start = start.unwrap();
-                let end = end.unwrap();
                 let mut section_size: i64 = 0;
-                if start <= end && step > 0 as $PRIMITIVE_TYPE {
+                // Series with NULL argument is empty
+                if let (Some(start), Some(end), Some(step)) = (start, end, step) {
+                    if step == 0 as $PRIMITIVE_TYPE {
+                        return Err(DataFusionError::Execution(
+                            "step size cannot equal zero".to_string(),
+                        ));
+                    }
+
                     let mut current = start;
-                    loop {
-                        if current > end {
-                            break;
-                        }
+                    while (step > 0 as $PRIMITIVE_TYPE && current <= end)
+                        || (step < 0 as $PRIMITIVE_TYPE && current >= end)
+                    {
                         builder.append_value(current).unwrap();
 
                         section_size += 1;
@@ -1379,6 +1710,377 @@ macro_rules! generate_series_udtf {
     }};
 }
 
+/// Timestamp argument of date functions: dates, strings and timestamps with time zone are cast
+/// to timestamp, values with time zone are processed in UTC
+fn timestamp_arg(array: &ArrayRef) -> Result<ArrayRef> {
+    Ok(cast(
+        array,
+        &DataType::Timestamp(TimeUnit::Nanosecond, None),
+    )?)
+}
+
+fn timestamp_return_type(arg_type: &DataType) -> DataType {
+    match arg_type {
+        DataType::Timestamp(_, tz) => DataType::Timestamp(TimeUnit::Nanosecond, tz.clone()),
+        _ => DataType::Timestamp(TimeUnit::Nanosecond, None),
+    }
+}
+
+/// date_trunc with all fields of PostgreSQL, DataFusion's date_trunc only supports second to year
+pub fn create_pg_date_trunc_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 2);
+
+        let fields = cast(&args[0], &DataType::Utf8)?;
+        let fields = downcast_string_arg!(fields, "field", i32);
+        let timestamps = timestamp_arg(&args[1])?;
+        let timestamps = downcast_primitive_arg!(timestamps, "source", TimestampNanosecondType);
+
+        let result = fields
+            .iter()
+            .zip(timestamps.iter())
+            .map(|(field, value)| match (field, value) {
+                (Some(field), Some(value)) => datetime::date_trunc(field, value).map(Some),
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let tz = match timestamp_return_type(args[1].data_type()) {
+            DataType::Timestamp(_, tz) => tz,
+            _ => None,
+        };
+
+        Ok(Arc::new(TimestampNanosecondArray::from_opt_vec(result, tz)) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |types| {
+        assert!(types.len() == 2);
+
+        Ok(Arc::new(timestamp_return_type(&types[1])))
+    });
+
+    ScalarUDF::new(
+        "pg_date_trunc",
+        &Signature::any(2, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// date_part with all fields of PostgreSQL, including epoch, dow, isodow, doy and quarter
+pub fn create_pg_date_part_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 2);
+
+        let fields = cast(&args[0], &DataType::Utf8)?;
+        let fields = downcast_string_arg!(fields, "field", i32);
+        let timestamps = timestamp_arg(&args[1])?;
+        let timestamps = downcast_primitive_arg!(timestamps, "source", TimestampNanosecondType);
+
+        let result = fields
+            .iter()
+            .zip(timestamps.iter())
+            .map(|(field, value)| match (field, value) {
+                (Some(field), Some(value)) => datetime::date_part(field, value).map(Some),
+                _ => Ok(None),
+            })
+            .collect::<Result<Float64Array>>()?;
+
+        Ok(Arc::new(result) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Float64)));
+
+    ScalarUDF::new(
+        "pg_date_part",
+        &Signature::any(2, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// to_char of PostgreSQL: numbers are formatted by numeric template patterns, anything else is
+/// converted to timestamp
+pub fn create_to_char_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 2);
+
+        let templates = cast(&args[1], &DataType::Utf8)?;
+        let templates = downcast_string_arg!(templates, "format", i32);
+
+        let result = match args[0].data_type() {
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64 => {
+                let numbers = cast(&args[0], &DataType::Float64)?;
+                let numbers = downcast_primitive_arg!(numbers, "number", Float64Type);
+
+                numbers
+                    .iter()
+                    .zip(templates.iter())
+                    .map(|(value, template)| match (value, template) {
+                        (Some(value), Some(template)) => Some(numeric::to_char(value, template)),
+                        _ => None,
+                    })
+                    .collect::<StringArray>()
+            }
+            _ => {
+                let timestamps = timestamp_arg(&args[0])?;
+                let timestamps =
+                    downcast_primitive_arg!(timestamps, "timestamp", TimestampNanosecondType);
+
+                timestamps
+                    .iter()
+                    .zip(templates.iter())
+                    .map(|(value, template)| match (value, template) {
+                        (Some(value), Some(template)) => Some(datetime::to_char(
+                            &datetime::timestamp_ns_to_datetime(value),
+                            template,
+                        )),
+                        _ => None,
+                    })
+                    .collect::<StringArray>()
+            }
+        };
+
+        Ok(Arc::new(result) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        "to_char",
+        &Signature::any(2, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// to_date of PostgreSQL: text is parsed by template patterns of to_timestamp, time is dropped
+pub fn create_to_date_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 2);
+
+        let values = cast(&args[0], &DataType::Utf8)?;
+        let values = downcast_string_arg!(values, "text", i32);
+        let templates = cast(&args[1], &DataType::Utf8)?;
+        let templates = downcast_string_arg!(templates, "format", i32);
+
+        let epoch = NaiveDate::from_ymd(1970, 1, 1);
+        let result = values
+            .iter()
+            .zip(templates.iter())
+            .map(|(value, template)| match (value, template) {
+                (Some(value), Some(template)) => datetime::to_timestamp(value, template)
+                    .map(|value| Some(value.date().signed_duration_since(epoch).num_days() as i32)),
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Arc::new(Date32Array::from(result)) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Date32)));
+
+    ScalarUDF::new(
+        "to_date",
+        &Signature::any(2, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// to_timestamp of PostgreSQL: seconds since the epoch or text parsed by template, both return
+/// timestamp with time zone. Single text argument is parsed as DataFusion's to_timestamp does
+pub fn create_pg_to_timestamp_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let result: Vec<Option<i64>> = match args {
+            [values] if values.data_type() == &DataType::Utf8 => {
+                return match to_timestamp(&[ColumnarValue::Array(values.clone())])? {
+                    ColumnarValue::Array(array) => Ok(array),
+                    ColumnarValue::Scalar(scalar) => Ok(scalar.to_array()),
+                }
+            }
+            [values] => {
+                let seconds = cast(values, &DataType::Float64)?;
+                let seconds = downcast_primitive_arg!(seconds, "seconds", Float64Type);
+
+                seconds
+                    .iter()
+                    .map(|value| value.map(|value| (value * 1_000_000_000.0).round() as i64))
+                    .collect()
+            }
+            [values, templates] => {
+                let values = cast(values, &DataType::Utf8)?;
+                let values = downcast_string_arg!(values, "text", i32);
+                let templates = cast(templates, &DataType::Utf8)?;
+                let templates = downcast_string_arg!(templates, "format", i32);
+
+                values
+                    .iter()
+                    .zip(templates.iter())
+                    .map(|(value, template)| match (value, template) {
+                        (Some(value), Some(template)) => datetime::to_timestamp(value, template)
+                            .map(|value| Some(value.timestamp_nanos())),
+                        _ => Ok(None),
+                    })
+                    .collect::<Result<_>>()?
+            }
+            _ => {
+                return Err(DataFusionError::Execution(
+                    "to_timestamp expects one or two arguments".to_string(),
+                ))
+            }
+        };
+
+        let result = TimestampNanosecondArray::from_opt_vec(result, Some("UTC".to_string()));
+
+        Ok(Arc::new(result) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |types| match types {
+        [DataType::Utf8] => Ok(Arc::new(DataType::Timestamp(TimeUnit::Nanosecond, None))),
+        _ => Ok(Arc::new(DataType::Timestamp(
+            TimeUnit::Nanosecond,
+            Some("UTC".to_string()),
+        ))),
+    });
+
+    ScalarUDF::new(
+        "pg_to_timestamp",
+        &Signature::one_of(
+            vec![TypeSignature::Any(1), TypeSignature::Any(2)],
+            Volatility::Immutable,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
+/// Adds interval to timestamp as PostgreSQL does: months first, clamping the day to the end of
+/// month (Jan 31 + 1 month = Feb 28), then days and the time part
+fn timestamp_add_interval(
+    timestamp: NaiveDateTime,
+    months: i32,
+    days: i32,
+    nanoseconds: i64,
+) -> Option<NaiveDateTime> {
+    let timestamp = if months != 0 {
+        let total_months = timestamp.year() * 12 + timestamp.month0() as i32 + months;
+        let (year, month) = (
+            total_months.div_euclid(12),
+            total_months.rem_euclid(12) as u32 + 1,
+        );
+        let last_day = (28..=31)
+            .rev()
+            .find(|day| NaiveDate::from_ymd_opt(year, month, *day).is_some())?;
+
+        NaiveDate::from_ymd_opt(year, month, timestamp.day().min(last_day))?
+            .and_time(timestamp.time())
+    } else {
+        timestamp
+    };
+
+    timestamp
+        .checked_add_signed(Duration::days(days as i64))?
+        .checked_add_signed(Duration::nanoseconds(nanoseconds))
+}
+
+/// Months, days and nanoseconds of interval array value
+fn interval_value(array: &ArrayRef, index: usize) -> Result<(i32, i32, i64)> {
+    match array.data_type() {
+        DataType::Interval(IntervalUnit::YearMonth) => {
+            let arr = downcast_primitive_arg!(array, "step", IntervalYearMonthType);
+            Ok((arr.value(index), 0, 0))
+        }
+        DataType::Interval(IntervalUnit::DayTime) => {
+            let arr = downcast_primitive_arg!(array, "step", IntervalDayTimeType);
+            let value = arr.value(index);
+            Ok((0, (value >> 32) as i32, (value as i32) as i64 * 1_000_000))
+        }
+        DataType::Interval(IntervalUnit::MonthDayNano) => {
+            let arr = downcast_primitive_arg!(array, "step", IntervalMonthDayNanoType);
+            let value = arr.value(index);
+            Ok(((value >> 96) as i32, (value >> 64) as i32, value as i64))
+        }
+        dt => Err(DataFusionError::Execution(format!(
+            "step argument must be an Interval, actual: {}",
+            dt
+        ))),
+    }
+}
+
+fn generate_series_timestamps(args: &[ArrayRef]) -> Result<(ArrayRef, Vec<usize>)> {
+    if args.len() != 3 {
+        return Err(DataFusionError::Execution(
+            "generate_series over timestamps requires an interval step".to_string(),
+        ));
+    }
+
+    let l_arr = downcast_primitive_arg!(args[0], "left", TimestampNanosecondType);
+    let r_arr = downcast_primitive_arg!(args[1], "right", TimestampNanosecondType);
+    let step_arr = &args[2];
+
+    let to_datetime = |nanos: i64| {
+        NaiveDateTime::from_timestamp(
+            nanos.div_euclid(1_000_000_000),
+            nanos.rem_euclid(1_000_000_000) as u32,
+        )
+    };
+
+    let mut section_sizes: Vec<usize> = Vec::new();
+    let mut builder = TimestampNanosecondArray::builder(1);
+    for (i, (start, end)) in l_arr.iter().zip(r_arr.iter()).enumerate() {
+        let step_index = if step_arr.len() > i { i } else { 0 };
+
+        let mut section_size = 0;
+        if let (Some(start), Some(end), false) = (start, end, step_arr.is_null(step_index)) {
+            let (months, days, nanoseconds) = interval_value(step_arr, step_index)?;
+            let start = to_datetime(start);
+            let end = to_datetime(end);
+
+            // Direction of the series is the direction of the first step
+            let ascending = match timestamp_add_interval(start, months, days, nanoseconds) {
+                Some(next) if next > start => true,
+                Some(next) if next < start => false,
+                Some(_) => {
+                    return Err(DataFusionError::Execution(
+                        "step size cannot equal zero".to_string(),
+                    ))
+                }
+                None => {
+                    return Err(DataFusionError::Execution(
+                        "timestamp out of range".to_string(),
+                    ))
+                }
+            };
+
+            // Step is added to the previous value, as it's in PostgreSQL
+            let mut current = Some(start);
+            while let Some(value) = current {
+                if (ascending && value > end) || (!ascending && value < end) {
+                    break;
+                }
+
+                builder.append_value(value.timestamp_nanos())?;
+                section_size += 1;
+
+                current = timestamp_add_interval(value, months, days, nanoseconds);
+            }
+        }
+        section_sizes.push(section_size);
+    }
+
+    Ok((Arc::new(builder.finish()) as ArrayRef, section_sizes))
+}
+
 pub fn create_generate_series_udtf(with_catalog_prefix: bool) -> TableUDF {
     let fun = make_table_function(move |args: &[ArrayRef]| {
         assert!(args.len() == 2 || args.len() == 3);
@@ -1387,6 +2089,12 @@ pub fn create_generate_series_udtf(with_catalog_prefix: bool) -> TableUDF {
             generate_series_udtf!(args, Int64Type, i64)
         } else if args[0].as_any().downcast_ref::<Float64Array>().is_some() {
             generate_series_udtf!(args, Float64Type, f64)
+        } else if args[0]
+            .as_any()
+            .downcast_ref::<TimestampNanosecondArray>()
+            .is_some()
+        {
+            return generate_series_timestamps(args);
         }
 
         Err(DataFusionError::Execution(format!("Unsupported type")))
@@ -1406,21 +2114,31 @@ pub fn create_generate_series_udtf(with_catalog_prefix: bool) -> TableUDF {
         }
     });
 
+    let mut signatures = vec![
+        TypeSignature::Exact(vec![DataType::Int64, DataType::Int64]),
+        TypeSignature::Exact(vec![DataType::Int64, DataType::Int64, DataType::Int64]),
+        TypeSignature::Exact(vec![DataType::Float64, DataType::Float64]),
+        TypeSignature::Exact(vec![
+            DataType::Float64,
+            DataType::Float64,
+            DataType::Float64,
+        ]),
+    ];
+    for unit in vec![
+        IntervalUnit::YearMonth,
+        IntervalUnit::DayTime,
+        IntervalUnit::MonthDayNano,
+    ] {
+        signatures.push(TypeSignature::Exact(vec![
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            DataType::Timestamp(TimeUnit::Nanosecond, None),
+            DataType::Interval(unit),
+        ]));
+    }
+
     TableUDF::new(
         fun_name,
-        &Signature::one_of(
-            vec![
-                TypeSignature::Exact(vec![DataType::Int64, DataType::Int64]),
-                TypeSignature::Exact(vec![DataType::Int64, DataType::Int64, DataType::Int64]),
-                TypeSignature::Exact(vec![DataType::Float64, DataType::Float64]),
-                TypeSignature::Exact(vec![
-                    DataType::Float64,
-                    DataType::Float64,
-                    DataType::Float64,
-                ]),
-            ],
-            Volatility::Immutable,
-        ),
+        &Signature::one_of(signatures, Volatility::Immutable),
         &return_type,
         &fun,
     )
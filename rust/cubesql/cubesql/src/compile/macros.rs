@@ -0,0 +1,859 @@
+use std::collections::HashMap;
+
+use pg_srv::PgType;
+use regex::Regex;
+use sqlparser::ast;
+
+use crate::{compile::parser::parse_sql_to_statement, sql::session::DatabaseProtocol};
+
+use super::{CompilationError, CompilationResult};
+
+lazy_static! {
+    static ref FUNCTION_STATEMENT_RE: Regex =
+        Regex::new(r"(?is)^\s*(CREATE\s+(OR\s+REPLACE\s+)?|DROP\s+)FUNCTION\b").unwrap();
+    static ref CREATE_FUNCTION_RE: Regex = Regex::new(
+        r"(?is)^\s*CREATE\s+(?P<replace>OR\s+REPLACE\s+)?FUNCTION\s+(?P<name>[a-z_][a-z0-9_]*)\s*\((?P<args>[^)]*)\)\s*RETURNS\s+(?P<returns>.+?)\s+(?P<options>(AS|LANGUAGE)\b.*?)\s*;?\s*$"
+    )
+    .unwrap();
+    static ref DROP_FUNCTION_RE: Regex = Regex::new(
+        r"(?is)^\s*DROP\s+FUNCTION\s+(?P<exists>IF\s+EXISTS\s+)?(?P<name>[a-z_][a-z0-9_]*)\s*(\([^)]*\))?\s*;?\s*$"
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlMacroArgument {
+    // Unnamed arguments are accessible only by position ($1, $2...)
+    pub name: Option<String>,
+    pub type_oid: u32,
+}
+
+/// User-defined SQL function, which is declared by CREATE FUNCTION ... LANGUAGE SQL
+/// and lives until the end of the session. Calls are replaced by the body expression
+/// before planning, so the result is still pushed down to Cube.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlMacro {
+    // Assigned by session on creation
+    pub oid: u32,
+    pub name: String,
+    pub args: Vec<SqlMacroArgument>,
+    pub return_type_oid: u32,
+    // Original body text, is used for pg_proc.prosrc
+    pub source: String,
+    pub body: ast::Expr,
+}
+
+#[derive(Debug)]
+pub enum SqlMacroStatement {
+    Create {
+        or_replace: bool,
+        definition: Box<SqlMacro>,
+    },
+    Drop {
+        if_exists: bool,
+        name: String,
+    },
+}
+
+/// Recognizes CREATE FUNCTION / DROP FUNCTION statements, which are not supported by SQL parser.
+/// Returns None for all other statements.
+pub fn parse_sql_macro_statement(query: &str) -> CompilationResult<Option<SqlMacroStatement>> {
+    if !FUNCTION_STATEMENT_RE.is_match(query) {
+        return Ok(None);
+    }
+
+    if let Some(captures) = DROP_FUNCTION_RE.captures(query) {
+        return Ok(Some(SqlMacroStatement::Drop {
+            if_exists: captures.name("exists").is_some(),
+            name: captures["name"].to_lowercase(),
+        }));
+    }
+
+    let captures = CREATE_FUNCTION_RE.captures(query).ok_or_else(|| {
+        CompilationError::Unsupported(format!("Unsupported function definition: {}", query))
+    })?;
+
+    let name = captures["name"].to_lowercase();
+    let returns = captures["returns"].trim();
+    if strip_keyword(returns, "SETOF").is_some() || strip_keyword(returns, "TABLE").is_some() {
+        return Err(CompilationError::Unsupported(format!(
+            "Only scalar SQL functions are supported, function {} returns {}",
+            name, returns
+        )));
+    }
+
+    let args = parse_arguments(&captures["args"])?;
+    let return_type_oid = type_oid_by_name(returns)?;
+    let (language, source) = parse_options(&captures["options"])?;
+
+    if !language.eq_ignore_ascii_case("sql") {
+        return Err(CompilationError::Unsupported(format!(
+            "Unsupported language for function {}: {}",
+            name, language
+        )));
+    }
+
+    let body = parse_body(&source)?;
+
+    Ok(Some(SqlMacroStatement::Create {
+        or_replace: captures.name("replace").is_some(),
+        definition: Box::new(SqlMacro {
+            oid: 0,
+            name,
+            args,
+            return_type_oid,
+            source: source.trim().to_string(),
+            body,
+        }),
+    }))
+}
+
+fn parse_arguments(args: &str) -> CompilationResult<Vec<SqlMacroArgument>> {
+    let mut result = vec![];
+    if args.trim().is_empty() {
+        return Ok(result);
+    }
+
+    for arg in args.split(',') {
+        let mut words = arg.split_whitespace().collect::<Vec<_>>();
+        if words.is_empty() {
+            return Err(CompilationError::User(format!(
+                "Invalid function arguments: {}",
+                args
+            )));
+        }
+
+        match words[0].to_uppercase().as_str() {
+            "IN" => {
+                words.remove(0);
+            }
+            "OUT" | "INOUT" | "VARIADIC" => {
+                return Err(CompilationError::Unsupported(format!(
+                    "Unsupported function argument mode: {}",
+                    arg.trim()
+                )))
+            }
+            _ => {}
+        };
+
+        if words
+            .iter()
+            .any(|w| w.eq_ignore_ascii_case("DEFAULT") || w.contains('='))
+        {
+            return Err(CompilationError::Unsupported(format!(
+                "Default values for function arguments are not supported: {}",
+                arg.trim()
+            )));
+        }
+
+        // Argument can be declared without name, for example, double precision
+        let argument = match type_oid_by_name(&words.join(" ")) {
+            Ok(type_oid) => SqlMacroArgument {
+                name: None,
+                type_oid,
+            },
+            Err(_) if words.len() > 1 => SqlMacroArgument {
+                name: Some(words[0].to_lowercase()),
+                type_oid: type_oid_by_name(&words[1..].join(" "))?,
+            },
+            Err(err) => return Err(err),
+        };
+
+        result.push(argument);
+    }
+
+    Ok(result)
+}
+
+pub fn type_oid_by_name(name: &str) -> CompilationResult<u32> {
+    let name = name.trim().to_lowercase();
+    // Type modifiers are ignored: numeric(10, 2), varchar(255)
+    let name = match name.find('(') {
+        Some(pos) => name[0..pos].trim_end().to_string(),
+        None => name,
+    };
+
+    let typname = match name.as_str() {
+        "int" | "integer" => "int4",
+        "bigint" => "int8",
+        "smallint" => "int2",
+        "boolean" => "bool",
+        "decimal" => "numeric",
+        "real" => "float4",
+        "double precision" | "double" | "float" => "float8",
+        "character varying" => "varchar",
+        "timestamp without time zone" => "timestamp",
+        "timestamp with time zone" => "timestamptz",
+        other => other,
+    };
+
+    PgType::get_all()
+        .into_iter()
+        .find(|t| t.typname == typname)
+        .map(|t| t.oid)
+        .ok_or_else(|| CompilationError::User(format!("type \"{}\" does not exist", name)))
+}
+
+fn strip_keyword<'a>(input: &'a str, keyword: &str) -> Option<&'a str> {
+    let prefix = input.get(0..keyword.len())?;
+    let rest = &input[keyword.len()..];
+
+    if prefix.eq_ignore_ascii_case(keyword)
+        && !rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+    {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+fn parse_options(options: &str) -> CompilationResult<(String, String)> {
+    let mut language: Option<String> = None;
+    let mut body: Option<String> = None;
+
+    let mut rest = options.trim_start();
+    while !rest.is_empty() {
+        if let Some(after) = strip_keyword(rest, "LANGUAGE") {
+            let after = after.trim_start_matches('\'');
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            language = Some(after[0..end].to_string());
+            rest = after[end..].trim_start_matches('\'');
+        } else if let Some(after) = strip_keyword(rest, "AS") {
+            let (literal, after) = parse_body_literal(after)?;
+            body = Some(literal);
+            rest = after;
+        } else if let Some(after) = ["IMMUTABLE", "STABLE", "VOLATILE", "STRICT"]
+            .iter()
+            .find_map(|keyword| strip_keyword(rest, keyword))
+        {
+            rest = after;
+        } else {
+            return Err(CompilationError::Unsupported(format!(
+                "Unsupported function option: {}",
+                rest
+            )));
+        }
+
+        rest = rest.trim_start();
+    }
+
+    match (language, body) {
+        (Some(language), Some(body)) => Ok((language, body)),
+        (None, _) => Err(CompilationError::User(
+            "No language specified for function".to_string(),
+        )),
+        (_, None) => Err(CompilationError::User(
+            "No function body specified".to_string(),
+        )),
+    }
+}
+
+/// Parses dollar-quoted ($$...$$, $tag$...$tag$) or single-quoted string literal
+fn parse_body_literal(input: &str) -> CompilationResult<(String, &str)> {
+    let unterminated = || CompilationError::User("Unterminated function body".to_string());
+
+    if let Some(after_dollar) = input.strip_prefix('$') {
+        let tag_end = after_dollar.find('$').ok_or_else(unterminated)? + 2;
+        let tag = &input[0..tag_end];
+        if !tag[1..tag_end - 1]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(CompilationError::User(format!(
+                "Invalid dollar-quote tag: {}",
+                tag
+            )));
+        }
+
+        let content = &input[tag_end..];
+        let close = content.find(tag).ok_or_else(unterminated)?;
+
+        Ok((content[0..close].to_string(), &content[close + tag.len()..]))
+    } else if let Some(after_quote) = input.strip_prefix('\'') {
+        let mut literal = String::new();
+        let mut chars = after_quote.char_indices().peekable();
+
+        while let Some((pos, c)) = chars.next() {
+            if c == '\'' {
+                if let Some((_, '\'')) = chars.peek() {
+                    chars.next();
+                    literal.push('\'');
+                } else {
+                    return Ok((literal, &input[pos + 2..]));
+                }
+            } else {
+                literal.push(c);
+            }
+        }
+
+        Err(unterminated())
+    } else {
+        Err(CompilationError::Unsupported(format!(
+            "Unsupported function body: {}",
+            input
+        )))
+    }
+}
+
+fn parse_body(source: &str) -> CompilationResult<ast::Expr> {
+    let unsupported = || {
+        CompilationError::Unsupported(format!(
+            "Only single expression SELECT without FROM is supported as SQL function body: {}",
+            source.trim()
+        ))
+    };
+
+    let query = source.trim().trim_end_matches(';').to_string();
+    let stmt = parse_sql_to_statement(&query, DatabaseProtocol::PostgreSQL)?;
+
+    let query = match stmt {
+        ast::Statement::Query(query) => query,
+        _ => return Err(unsupported()),
+    };
+
+    if query.with.is_some()
+        || !query.order_by.is_empty()
+        || query.limit.is_some()
+        || query.offset.is_some()
+    {
+        return Err(unsupported());
+    }
+
+    let mut select = match query.body {
+        ast::SetExpr::Select(select) => select,
+        _ => return Err(unsupported()),
+    };
+
+    if select.distinct
+        || !select.from.is_empty()
+        || select.selection.is_some()
+        || !select.group_by.is_empty()
+        || select.having.is_some()
+        || select.projection.len() != 1
+    {
+        return Err(unsupported());
+    }
+
+    match select.projection.remove(0) {
+        ast::SelectItem::UnnamedExpr(expr) => Ok(expr),
+        ast::SelectItem::ExprWithAlias { expr, .. } => Ok(expr),
+        _ => Err(unsupported()),
+    }
+}
+
+trait ExprRewriter {
+    /// Called for every expression after its children were visited
+    fn rewrite_expr(&mut self, expr: &mut ast::Expr) -> CompilationResult<()>;
+
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> CompilationResult<()> {
+        match expr {
+            ast::Expr::IsNull(e)
+            | ast::Expr::IsNotNull(e)
+            | ast::Expr::Nested(e)
+            | ast::Expr::UnaryOp { expr: e, .. }
+            | ast::Expr::Cast { expr: e, .. }
+            | ast::Expr::TryCast { expr: e, .. }
+            | ast::Expr::Extract { expr: e, .. }
+            | ast::Expr::Collate { expr: e, .. } => self.visit_expr(e)?,
+            ast::Expr::IsDistinctFrom(left, right)
+            | ast::Expr::IsNotDistinctFrom(left, right)
+            | ast::Expr::BinaryOp { left, right, .. } => {
+                self.visit_expr(left)?;
+                self.visit_expr(right)?;
+            }
+            ast::Expr::InList { expr, list, .. } => {
+                self.visit_expr(expr)?;
+                for e in list.iter_mut() {
+                    self.visit_expr(e)?;
+                }
+            }
+            ast::Expr::InSubquery { expr, subquery, .. } => {
+                self.visit_expr(expr)?;
+                self.visit_query(subquery)?;
+            }
+            ast::Expr::Between {
+                expr, low, high, ..
+            } => {
+                self.visit_expr(expr)?;
+                self.visit_expr(low)?;
+                self.visit_expr(high)?;
+            }
+            ast::Expr::Substring {
+                expr,
+                substring_from,
+                substring_for,
+            } => {
+                self.visit_expr(expr)?;
+                if let Some(e) = substring_from {
+                    self.visit_expr(e)?;
+                }
+                if let Some(e) = substring_for {
+                    self.visit_expr(e)?;
+                }
+            }
+            ast::Expr::Function(function) => {
+                for arg in function.args.iter_mut() {
+                    match arg {
+                        ast::FunctionArg::Named {
+                            arg: ast::FunctionArgExpr::Expr(e),
+                            ..
+                        }
+                        | ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(e)) => {
+                            self.visit_expr(e)?
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            ast::Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => {
+                if let Some(e) = operand {
+                    self.visit_expr(e)?;
+                }
+                for e in conditions.iter_mut().chain(results.iter_mut()) {
+                    self.visit_expr(e)?;
+                }
+                if let Some(e) = else_result {
+                    self.visit_expr(e)?;
+                }
+            }
+            ast::Expr::Tuple(list) => {
+                for e in list.iter_mut() {
+                    self.visit_expr(e)?;
+                }
+            }
+            ast::Expr::Exists(subquery) | ast::Expr::Subquery(subquery) => {
+                self.visit_query(subquery)?
+            }
+            _ => {}
+        };
+
+        self.rewrite_expr(expr)
+    }
+
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) -> CompilationResult<()> {
+        match factor {
+            ast::TableFactor::Derived { subquery, .. } => self.visit_query(subquery),
+            ast::TableFactor::NestedJoin(twj) => self.visit_table_with_joins(twj),
+            _ => Ok(()),
+        }
+    }
+
+    fn visit_table_with_joins(&mut self, twj: &mut ast::TableWithJoins) -> CompilationResult<()> {
+        self.visit_table_factor(&mut twj.relation)?;
+
+        for join in twj.joins.iter_mut() {
+            self.visit_table_factor(&mut join.relation)?;
+
+            match &mut join.join_operator {
+                ast::JoinOperator::Inner(ast::JoinConstraint::On(e))
+                | ast::JoinOperator::LeftOuter(ast::JoinConstraint::On(e))
+                | ast::JoinOperator::RightOuter(ast::JoinConstraint::On(e))
+                | ast::JoinOperator::FullOuter(ast::JoinConstraint::On(e)) => self.visit_expr(e)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_select_item(&mut self, item: &mut ast::SelectItem) -> CompilationResult<()> {
+        match item {
+            ast::SelectItem::UnnamedExpr(expr) => self.visit_expr(expr),
+            ast::SelectItem::ExprWithAlias { expr, .. } => self.visit_expr(expr),
+            _ => Ok(()),
+        }
+    }
+
+    fn visit_select(&mut self, select: &mut ast::Select) -> CompilationResult<()> {
+        for item in select.projection.iter_mut() {
+            self.visit_select_item(item)?;
+        }
+
+        for from in select.from.iter_mut() {
+            self.visit_table_with_joins(from)?;
+        }
+
+        if let Some(selection) = &mut select.selection {
+            self.visit_expr(selection)?;
+        }
+
+        for e in select.group_by.iter_mut() {
+            self.visit_expr(e)?;
+        }
+
+        if let Some(having) = &mut select.having {
+            self.visit_expr(having)?;
+        }
+
+        Ok(())
+    }
+
+    fn visit_set_expr(&mut self, body: &mut ast::SetExpr) -> CompilationResult<()> {
+        match body {
+            ast::SetExpr::Select(select) => self.visit_select(select),
+            ast::SetExpr::Query(query) => self.visit_query(query),
+            ast::SetExpr::SetOperation { left, right, .. } => {
+                self.visit_set_expr(left)?;
+                self.visit_set_expr(right)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn visit_query(&mut self, query: &mut ast::Query) -> CompilationResult<()> {
+        if let Some(with) = &mut query.with {
+            for cte in with.cte_tables.iter_mut() {
+                self.visit_query(&mut cte.query)?;
+            }
+        }
+
+        self.visit_set_expr(&mut query.body)?;
+
+        for order_by in query.order_by.iter_mut() {
+            self.visit_expr(&mut order_by.expr)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces references to function arguments (by name or $N) in the function body
+struct SqlMacroArgumentsBinder<'a> {
+    args: &'a [SqlMacroArgument],
+    values: &'a [ast::Expr],
+}
+
+impl<'a> ExprRewriter for SqlMacroArgumentsBinder<'a> {
+    fn rewrite_expr(&mut self, expr: &mut ast::Expr) -> CompilationResult<()> {
+        let position = match expr {
+            ast::Expr::Identifier(ident) => {
+                let name = match ident.quote_style {
+                    Some(_) => ident.value.clone(),
+                    None => ident.value.to_lowercase(),
+                };
+
+                self.args
+                    .iter()
+                    .position(|arg| arg.name.as_ref() == Some(&name))
+            }
+            ast::Expr::Value(ast::Value::Placeholder(placeholder)) => {
+                let position = placeholder
+                    .strip_prefix('$')
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .filter(|n| *n >= 1 && *n <= self.args.len());
+
+                match position {
+                    Some(n) => Some(n - 1),
+                    None => {
+                        return Err(CompilationError::User(format!(
+                            "there is no parameter {}",
+                            placeholder
+                        )))
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(position) = position {
+            *expr = match &self.values[position] {
+                value @ (ast::Expr::Identifier(_)
+                | ast::Expr::CompoundIdentifier(_)
+                | ast::Expr::Value(_)
+                | ast::Expr::Function(_)
+                | ast::Expr::Nested(_)) => value.clone(),
+                // Keep operator precedence of the argument
+                value => ast::Expr::Nested(Box::new(value.clone())),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces calls of session SQL functions by their bodies
+pub struct SqlMacroExpander<'a> {
+    macros: &'a HashMap<String, SqlMacro>,
+    // Functions which are expanded right now, is used to detect recursion
+    stack: Vec<String>,
+}
+
+impl<'a> SqlMacroExpander<'a> {
+    pub fn new(macros: &'a HashMap<String, SqlMacro>) -> Self {
+        Self {
+            macros,
+            stack: vec![],
+        }
+    }
+
+    pub fn expand_statement(&mut self, stmt: &mut ast::Statement) -> CompilationResult<()> {
+        match stmt {
+            ast::Statement::Query(query) => self.visit_query(query),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that function body can be expanded, it's used to reject recursive definitions
+    pub fn expand_definition(&mut self, definition: &SqlMacro) -> CompilationResult<()> {
+        self.stack.push(definition.name.clone());
+        let result = self.visit_expr(&mut definition.body.clone());
+        self.stack.pop();
+
+        result
+    }
+
+    fn find_macro(&self, function: &ast::Function) -> Option<&'a SqlMacro> {
+        match function.name.0.as_slice() {
+            [ident] => match ident.quote_style {
+                Some(_) => self.macros.get(&ident.value),
+                None => self.macros.get(&ident.value.to_lowercase()),
+            },
+            _ => None,
+        }
+    }
+}
+
+impl<'a> ExprRewriter for SqlMacroExpander<'a> {
+    fn rewrite_expr(&mut self, expr: &mut ast::Expr) -> CompilationResult<()> {
+        let (definition, function) = match expr {
+            ast::Expr::Function(function) => match self.find_macro(function) {
+                Some(definition) => (definition, function),
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+
+        if function.over.is_some() || function.distinct {
+            return Err(CompilationError::Unsupported(format!(
+                "Unsupported call of SQL function {}: {}",
+                definition.name, function
+            )));
+        }
+
+        let values = function
+            .args
+            .iter()
+            .map(|arg| match arg {
+                ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(e)) => Ok(e.clone()),
+                _ => Err(CompilationError::Unsupported(format!(
+                    "Only positional arguments are supported for SQL function {}: {}",
+                    definition.name, function
+                ))),
+            })
+            .collect::<CompilationResult<Vec<_>>>()?;
+
+        if values.len() != definition.args.len() {
+            return Err(CompilationError::User(format!(
+                "function {} with {} argument(s) does not exist",
+                definition.name,
+                values.len()
+            )));
+        }
+
+        if self.stack.contains(&definition.name) {
+            return Err(CompilationError::Unsupported(format!(
+                "Recursive SQL function is not supported: {}",
+                self.stack
+                    .iter()
+                    .chain(std::iter::once(&definition.name))
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            )));
+        }
+
+        let mut body = definition.body.clone();
+        SqlMacroArgumentsBinder {
+            args: &definition.args,
+            values: &values,
+        }
+        .visit_expr(&mut body)?;
+
+        self.stack.push(definition.name.clone());
+        let result = self.visit_expr(&mut body);
+        self.stack.pop();
+        result?;
+
+        *expr = ast::Expr::Nested(Box::new(body));
+
+        Ok(())
+    }
+
+    fn visit_select_item(&mut self, item: &mut ast::SelectItem) -> CompilationResult<()> {
+        // Column is named after the function, like in PostgreSQL
+        if let ast::SelectItem::UnnamedExpr(ast::Expr::Function(function)) = item {
+            if let Some(definition) = self.find_macro(function) {
+                *item = ast::SelectItem::ExprWithAlias {
+                    expr: ast::Expr::Function(function.clone()),
+                    alias: ast::Ident::new(definition.name.clone()),
+                };
+            }
+        }
+
+        match item {
+            ast::SelectItem::UnnamedExpr(expr) => self.visit_expr(expr),
+            ast::SelectItem::ExprWithAlias { expr, .. } => self.visit_expr(expr),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(query: &str) -> SqlMacro {
+        match parse_sql_macro_statement(query).unwrap() {
+            Some(SqlMacroStatement::Create { definition, .. }) => *definition,
+            _ => panic!("CREATE FUNCTION was expected: {}", query),
+        }
+    }
+
+    fn expand(macros: &[SqlMacro], query: &str) -> CompilationResult<String> {
+        let macros = macros
+            .iter()
+            .map(|m| (m.name.clone(), m.clone()))
+            .collect::<HashMap<_, _>>();
+
+        let mut stmt = parse_sql_to_statement(&query.to_string(), DatabaseProtocol::PostgreSQL)?;
+        SqlMacroExpander::new(&macros).expand_statement(&mut stmt)?;
+
+        Ok(stmt.to_string())
+    }
+
+    #[test]
+    fn test_parse_create_function() {
+        let definition = create(
+            "CREATE OR REPLACE FUNCTION Margin(revenue numeric, cost numeric) RETURNS numeric AS $$ SELECT (revenue - cost) / revenue $$ LANGUAGE SQL;",
+        );
+        assert_eq!(definition.name, "margin".to_string());
+        assert_eq!(
+            definition.args,
+            vec![
+                SqlMacroArgument {
+                    name: Some("revenue".to_string()),
+                    type_oid: 1700,
+                },
+                SqlMacroArgument {
+                    name: Some("cost".to_string()),
+                    type_oid: 1700,
+                }
+            ]
+        );
+        assert_eq!(definition.return_type_oid, 1700);
+        assert_eq!(definition.body.to_string(), "(revenue - cost) / revenue");
+
+        let definition = create(
+            "CREATE FUNCTION is_female(text) RETURNS boolean LANGUAGE sql IMMUTABLE AS 'SELECT $1 = ''female'''",
+        );
+        assert_eq!(definition.args.len(), 1);
+        assert_eq!(definition.args[0].name, None);
+        assert_eq!(definition.body.to_string(), "$1 = 'female'");
+    }
+
+    #[test]
+    fn test_parse_drop_function() {
+        match parse_sql_macro_statement("DROP FUNCTION IF EXISTS margin(numeric, numeric)") {
+            Ok(Some(SqlMacroStatement::Drop { if_exists, name })) => {
+                assert!(if_exists);
+                assert_eq!(name, "margin".to_string());
+            }
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        match parse_sql_macro_statement("SELECT 1") {
+            Ok(None) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_parse_create_function_unsupported() {
+        for query in [
+            "CREATE FUNCTION f(a int) RETURNS int AS $$ SELECT a FROM t $$ LANGUAGE SQL",
+            "CREATE FUNCTION f(a int) RETURNS int AS $$ SELECT a, a $$ LANGUAGE SQL",
+            "CREATE FUNCTION f(a int) RETURNS int AS $$ BEGIN RETURN a; END $$ LANGUAGE plpgsql",
+            "CREATE FUNCTION f(OUT a int) RETURNS int AS $$ SELECT 1 $$ LANGUAGE SQL",
+            "CREATE FUNCTION f(a int) RETURNS SETOF int AS $$ SELECT a $$ LANGUAGE SQL",
+        ] {
+            match parse_sql_macro_statement(query) {
+                Err(CompilationError::Unsupported(_)) => {}
+                r => panic!("Unexpected result for {}: {:?}", query, r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_expand() -> CompilationResult<()> {
+        let margin = create(
+            "CREATE FUNCTION margin(revenue numeric, cost numeric) RETURNS numeric AS $$ SELECT (revenue - cost) / revenue $$ LANGUAGE SQL",
+        );
+        let double = create(
+            "CREATE FUNCTION double(numeric) RETURNS numeric AS $$ SELECT $1 * 2 $$ LANGUAGE SQL",
+        );
+        let macros = vec![margin, double];
+
+        assert_eq!(
+            expand(
+                &macros,
+                "SELECT margin(maxPrice, minPrice + 1) FROM KibanaSampleDataEcommerce WHERE double(maxPrice) > 10"
+            )?,
+            "SELECT ((maxPrice - (minPrice + 1)) / maxPrice) AS margin FROM KibanaSampleDataEcommerce WHERE (maxPrice * 2) > 10"
+        );
+        assert_eq!(
+            expand(&macros, "SELECT double(margin(a, b)) AS x FROM t")?,
+            "SELECT (((a - b) / a) * 2) AS x FROM t"
+        );
+        assert_eq!(
+            expand(&macros, "SELECT max(a) FROM t")?,
+            "SELECT max(a) FROM t"
+        );
+
+        match expand(&macros, "SELECT double(a, b) FROM t") {
+            Err(CompilationError::User(_)) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_recursion() {
+        let first = create(
+            "CREATE FUNCTION first(a int) RETURNS int AS $$ SELECT second(a) + 1 $$ LANGUAGE SQL",
+        );
+        let second = create(
+            "CREATE FUNCTION second(a int) RETURNS int AS $$ SELECT first(a) $$ LANGUAGE SQL",
+        );
+
+        match expand(&[first.clone(), second.clone()], "SELECT first(1)") {
+            Err(CompilationError::Unsupported(message)) => assert!(
+                message.contains("first -> second -> first"),
+                "Unexpected message: {}",
+                message
+            ),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        // Validation on creation, second is not defined yet
+        let macros = vec![(first.name.clone(), first.clone())]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        assert!(SqlMacroExpander::new(&macros)
+            .expand_definition(&first)
+            .is_ok());
+
+        let mut macros = macros;
+        macros.insert(second.name.clone(), second.clone());
+        assert!(SqlMacroExpander::new(&macros)
+            .expand_definition(&second)
+            .is_err());
+    }
+}
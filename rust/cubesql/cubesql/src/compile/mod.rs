@@ -6,13 +6,14 @@ use chrono::{prelude::*, Duration};
 
 use datafusion::{
     arrow::datatypes::DataType,
+    error::DataFusionError,
     execution::context::{
         default_session_builder, SessionConfig as DFSessionConfig,
         SessionContext as DFSessionContext,
     },
     logical_plan::plan::{Extension, Projection},
     logical_plan::LogicalPlan,
-    logical_plan::{DFField, DFSchema, DFSchemaRef, Expr},
+    logical_plan::{DFField, DFSchema, DFSchemaRef, Expr, PlanVisitor},
     prelude::*,
     scalar::ScalarValue,
     sql::parser::Statement as DFStatement,
@@ -40,30 +41,43 @@ use self::{
     engine::information_schema::mysql::ext::CubeColumnMySqlExt,
     engine::provider::CubeContext,
     engine::udf::{
-        create_connection_id_udf, create_convert_tz_udf, create_current_schema_udf,
-        create_current_schemas_udf, create_current_user_udf, create_db_udf, create_format_type_udf,
+        create_coalesce_udf, create_connection_id_udf, create_convert_tz_udf,
+        create_current_schema_udf, create_current_schemas_udf, create_current_setting_udf,
+        create_current_user_udf, create_db_udf, create_format_type_udf,
         create_generate_series_udtf, create_if_udf, create_instr_udf, create_isnull_udf,
         create_least_udf, create_locate_udf, create_pg_datetime_precision_udf,
-        create_pg_expandarray_udtf, create_pg_get_expr_udf, create_pg_get_userbyid_udf,
-        create_pg_numeric_precision_udf, create_pg_numeric_scale_udf, create_time_format_udf,
-        create_timediff_udf, create_ucase_udf, create_user_udf, create_version_udf,
+        create_pg_expandarray_udtf, create_pg_get_expr_udf, create_pg_get_functiondef_udf,
+        create_pg_get_serial_sequence_udf, create_pg_get_userbyid_udf,
+        create_pg_numeric_precision_udf, create_pg_numeric_scale_udf, create_pg_size_pretty_udf,
+        create_pg_terminate_backend_udf, create_time_format_udf, create_timediff_udf,
+        create_ucase_udf, create_user_udf, create_version_udf,
     },
-    parser::parse_sql_to_statement,
+    macros::{parse_sql_macro_statement, SqlMacroExpander, SqlMacroStatement},
+    parser::{parse_sql_to_statement, parse_transaction_command, TransactionCommand},
 };
 use crate::compile::engine::udf::{
-    create_generate_subscripts_udtf, create_unnest_udtf, pg_get_userbyid, pg_table_is_visible,
+    create_generate_subscripts_udtf, create_session_user_udf, create_unnest_udtf, pg_get_userbyid,
+    pg_table_is_visible, search_path_schemas,
 };
 use crate::{
     compile::builder::QueryBuilder,
     compile::engine::udf::{
         create_date_add_udf, create_date_sub_udf, create_date_udf, create_dayofmonth_udf,
         create_dayofweek_udf, create_dayofyear_udf, create_hour_udf, create_makedate_udf,
-        create_measure_udaf, create_minute_udf, create_pg_backend_pid, create_quarter_udf,
-        create_second_udf, create_str_to_date, create_year_udf,
+        create_measure_udaf, create_minute_udf, create_pg_backend_pid, create_pg_date_part_udf,
+        create_pg_date_trunc_udf, create_pg_to_timestamp_udf, create_quarter_udf,
+        create_second_udf, create_str_to_date, create_to_char_udf, create_to_date_udf,
+        create_year_udf,
     },
     compile::rewrite::converter::LogicalPlanToLanguageConverter,
     sql::database_variables::{DatabaseVariable, DatabaseVariables},
-    sql::session::DatabaseProtocol,
+    sql::session::{DatabaseProtocol, SessionChange, VariablesChange},
+    sql::statement::{
+        StatementArrayComparisonsReplacer, StatementCastsReplacer,
+        StatementDateTimeFunctionsReplacer, StatementExpressionsCounter, StatementLateralFinder,
+        StatementSqlValueFunctionsReplacer, StatementSubqueryDepthFinder,
+        StatementTableFunctionsReplacer,
+    },
     sql::types::CommandCompletion,
     sql::{
         dataframe, types::StatusFlags, ColumnFlags, ColumnType, Session, SessionManager,
@@ -71,15 +85,17 @@ use crate::{
     },
     transport::{df_data_type_by_column_type, V1CubeMetaExt},
     transport::{V1CubeMetaDimensionExt, V1CubeMetaMeasureExt, V1CubeMetaSegmentExt},
-    CubeError,
+    CubeError, CubeErrorKind,
 };
 
 pub mod builder;
 pub mod context;
 pub mod engine;
+pub mod macros;
 pub mod parser;
 pub mod rewrite;
 pub mod service;
+pub mod validation;
 
 #[derive(Debug, PartialEq)]
 pub enum CompilationError {
@@ -87,6 +103,12 @@ pub enum CompilationError {
     User(String),
     Unsupported(String),
     Unknown(String),
+    ProgramLimitExceeded(String),
+    AmbiguousColumn(String),
+    /// Message and position of the error in the query, if it's known
+    SyntaxError(String, Option<usize>),
+    UndefinedTable(String),
+    StatementTooComplex(String),
 }
 
 pub type CompilationResult<T> = std::result::Result<T, CompilationError>;
@@ -106,6 +128,51 @@ impl fmt::Display for CompilationError {
             CompilationError::Unknown(message) => {
                 write!(f, "SQLCompilationError: Unknown {}", message)
             }
+            CompilationError::ProgramLimitExceeded(message) => {
+                write!(f, "SQLCompilationError: Program limit exceeded {}", message)
+            }
+            CompilationError::AmbiguousColumn(message) => {
+                write!(f, "SQLCompilationError: Ambiguous column {}", message)
+            }
+            CompilationError::SyntaxError(message, _) => {
+                write!(f, "SQLCompilationError: Syntax error {}", message)
+            }
+            CompilationError::UndefinedTable(message) => {
+                write!(f, "SQLCompilationError: Undefined table {}", message)
+            }
+            CompilationError::StatementTooComplex(message) => {
+                write!(f, "SQLCompilationError: Statement too complex {}", message)
+            }
+        }
+    }
+}
+
+impl CompilationError {
+    /// Class of the error, it's the only mapping to SQLSTATE for all paths of the connection
+    pub fn kind(&self) -> Option<CubeErrorKind> {
+        match self {
+            CompilationError::Unsupported(_) => Some(CubeErrorKind::FeatureNotSupported),
+            CompilationError::SyntaxError(..) => Some(CubeErrorKind::SyntaxError),
+            CompilationError::UndefinedTable(_) => Some(CubeErrorKind::UndefinedTable),
+            CompilationError::StatementTooComplex(_) => Some(CubeErrorKind::StatementTooComplex),
+            CompilationError::ProgramLimitExceeded(_) => Some(CubeErrorKind::ProgramLimitExceeded),
+            CompilationError::AmbiguousColumn(_) => Some(CubeErrorKind::AmbiguousColumn),
+            _ => None,
+        }
+    }
+
+    /// Message without the class of the error
+    pub fn message(&self) -> &str {
+        match self {
+            CompilationError::Internal(message)
+            | CompilationError::User(message)
+            | CompilationError::Unsupported(message)
+            | CompilationError::Unknown(message)
+            | CompilationError::ProgramLimitExceeded(message)
+            | CompilationError::AmbiguousColumn(message)
+            | CompilationError::SyntaxError(message, _)
+            | CompilationError::UndefinedTable(message)
+            | CompilationError::StatementTooComplex(message) => message,
         }
     }
 }
@@ -452,7 +519,7 @@ fn compile_expression(
 ) -> CompilationResult<CompiledExpression> {
     match expr {
         ast::Expr::Identifier(ident) => {
-            if let Some(selection) = ctx.find_selection_for_identifier(&ident.value, true) {
+            if let Some(selection) = ctx.find_selection_for_identifier(&ident.value, true)? {
                 Ok(CompiledExpression::Selection(selection))
             } else {
                 Err(CompilationError::User(format!(
@@ -462,9 +529,8 @@ fn compile_expression(
             }
         }
         ast::Expr::CompoundIdentifier(i) => {
-            // @todo We need a context with main table rel
-            let identifier = if i.len() == 2 {
-                i[1].value.to_string()
+            let reference = if i.len() == 2 {
+                MemberReference::from_identifiers(i)?
             } else {
                 return Err(CompilationError::Unsupported(format!(
                     "Unsupported compound identifier in argument: {}",
@@ -472,12 +538,12 @@ fn compile_expression(
                 )));
             };
 
-            if let Some(selection) = ctx.find_selection_for_identifier(&identifier, true) {
+            if let Some(selection) = ctx.find_selection_for_reference(&reference, true)? {
                 Ok(CompiledExpression::Selection(selection))
             } else {
                 Err(CompilationError::User(format!(
                     "Unable to find selection for: {:?}",
-                    identifier
+                    reference.to_string()
                 )))
             }
         }
@@ -591,14 +657,57 @@ fn compile_expression(
     }
 }
 
+/// Clause of the query which filters are compiled for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FilterClause {
+    Where,
+    Having,
+}
+
+impl fmt::Display for FilterClause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterClause::Where => write!(f, "WHERE"),
+            FilterClause::Having => write!(f, "HAVING"),
+        }
+    }
+}
+
+/// Operand of the comparison, aggregates are resolved to measures (as in the projection) only in HAVING
+fn compile_filter_operand(
+    expr: &ast::Expr,
+    clause: FilterClause,
+    ctx: &QueryContext,
+) -> CompilationResult<CompiledExpression> {
+    if let ast::Expr::Function(f) = expr {
+        match f.name.to_string().to_lowercase().as_str() {
+            "measure" | "sum" | "min" | "max" | "avg" | "count" => {
+                return match clause {
+                    FilterClause::Having => Ok(CompiledExpression::Selection(
+                        ctx.find_selection_for_function(f)?,
+                    )),
+                    FilterClause::Where => Err(CompilationError::User(format!(
+                        "aggregate functions are not allowed in {}",
+                        clause
+                    ))),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    compile_expression(expr, ctx)
+}
+
 fn compiled_binary_op_expr(
     left: &Box<ast::Expr>,
     op: &ast::BinaryOperator,
     right: &Box<ast::Expr>,
+    clause: FilterClause,
     ctx: &QueryContext,
 ) -> CompilationResult<CompiledFilterTree> {
-    let left_ce = compile_expression(left, ctx)?;
-    let right_ce = compile_expression(right, ctx)?;
+    let left_ce = compile_filter_operand(left, clause, ctx)?;
+    let right_ce = compile_filter_operand(right, clause, ctx)?;
 
     // Group selection to left, expr for filtering to right
     let (selection_to_filter, filter_expr) = match (left_ce, right_ce) {
@@ -786,10 +895,11 @@ fn compiled_binary_op_logical(
     left: &Box<ast::Expr>,
     op: &ast::BinaryOperator,
     right: &Box<ast::Expr>,
+    clause: FilterClause,
     ctx: &QueryContext,
 ) -> CompilationResult<CompiledFilterTree> {
-    let left = compile_where_expression(left, ctx)?;
-    let right = compile_where_expression(right, ctx)?;
+    let left = compile_where_expression(left, clause, ctx)?;
+    let right = compile_where_expression(right, clause, ctx)?;
 
     match op {
         ast::BinaryOperator::And => Ok(binary_op_create_node_and(left, right)?),
@@ -803,16 +913,17 @@ fn compiled_binary_op_logical(
 
 fn compile_where_expression(
     expr: &ast::Expr,
+    clause: FilterClause,
     ctx: &QueryContext,
 ) -> CompilationResult<CompiledFilterTree> {
     match expr {
         // Unwrap from brackets
-        ast::Expr::Nested(nested) => compile_where_expression(nested, ctx),
+        ast::Expr::Nested(nested) => compile_where_expression(nested, clause, ctx),
         ast::Expr::BinaryOp { left, right, op } => match op {
             ast::BinaryOperator::And | ast::BinaryOperator::Or => {
-                compiled_binary_op_logical(left, op, right, ctx)
+                compiled_binary_op_logical(left, op, right, clause, ctx)
             }
-            _ => compiled_binary_op_expr(left, op, right, ctx),
+            _ => compiled_binary_op_expr(left, op, right, clause, ctx),
         },
         ast::Expr::IsNull(expr) => {
             let compiled_expr = compile_expression(expr, ctx)?;
@@ -1190,13 +1301,16 @@ enum CompiledFilterTree {
 
 fn compile_group(
     grouping: &Vec<ast::Expr>,
+    projection: &Vec<ast::SelectItem>,
     ctx: &QueryContext,
     _builder: &mut QueryBuilder,
 ) -> CompilationResult<()> {
     for group in grouping.iter() {
-        match &group {
+        let group = resolve_projection_ordinal(group, projection, "GROUP BY")?.unwrap_or(group);
+
+        match group {
             ast::Expr::Identifier(i) => {
-                if let Some(selection) = ctx.find_selection_for_identifier(&i.to_string(), true) {
+                if let Some(selection) = ctx.find_selection_for_identifier(&i.to_string(), true)? {
                     match selection {
                         Selection::Segment(s) => {
                             return Err(CompilationError::User(format!(
@@ -1219,6 +1333,25 @@ fn compile_where(
     selection: &ast::Expr,
     ctx: &QueryContext,
     builder: &mut QueryBuilder,
+) -> CompilationResult<()> {
+    compile_filters(selection, FilterClause::Where, ctx, builder)
+}
+
+/// HAVING is pushed down to Cube as filters, which are applied to measures after aggregation
+fn compile_having(
+    having: &ast::Expr,
+    ctx: &QueryContext,
+    builder: &mut QueryBuilder,
+) -> CompilationResult<()> {
+    compile_filters(having, FilterClause::Having, ctx, builder)
+}
+
+/// Filters of the clause are added to the filters of the query, the top-level ones are joined by AND
+fn compile_filters(
+    selection: &ast::Expr,
+    clause: FilterClause,
+    ctx: &QueryContext,
+    builder: &mut QueryBuilder,
 ) -> CompilationResult<()> {
     let filters = match &selection {
         binary @ ast::Expr::BinaryOp { left, right, op } => match op {
@@ -1229,35 +1362,37 @@ fn compile_where(
             | ast::BinaryOperator::Gt
             | ast::BinaryOperator::GtEq
             | ast::BinaryOperator::Eq
-            | ast::BinaryOperator::NotEq => compile_where_expression(binary, ctx)?,
+            | ast::BinaryOperator::NotEq => compile_where_expression(binary, clause, ctx)?,
             ast::BinaryOperator::And => {
-                let left_compiled = compile_where_expression(left, ctx)?;
-                let right_compiled = compile_where_expression(right, ctx)?;
+                let left_compiled = compile_where_expression(left, clause, ctx)?;
+                let right_compiled = compile_where_expression(right, clause, ctx)?;
 
                 binary_op_create_node_and(left_compiled, right_compiled)?
             }
             ast::BinaryOperator::Or => {
-                let left_compiled = compile_where_expression(left, ctx)?;
-                let right_compiled = compile_where_expression(right, ctx)?;
+                let left_compiled = compile_where_expression(left, clause, ctx)?;
+                let right_compiled = compile_where_expression(right, clause, ctx)?;
 
                 CompiledFilterTree::Or(Box::new(left_compiled), Box::new(right_compiled))
             }
             _ => {
                 return Err(CompilationError::Unsupported(format!(
-                    "Operator for binary expression in WHERE clause: {:?}",
-                    selection
+                    "Operator for binary expression in {} clause: {:?}",
+                    clause, selection
                 )));
             }
         },
-        ast::Expr::Nested(nested) => compile_where_expression(nested, ctx)?,
-        inlist @ ast::Expr::InList { .. } => compile_where_expression(inlist, ctx)?,
-        isnull @ ast::Expr::IsNull { .. } => compile_where_expression(isnull, ctx)?,
-        isnotnull @ ast::Expr::IsNotNull { .. } => compile_where_expression(isnotnull, ctx)?,
-        between @ ast::Expr::Between { .. } => compile_where_expression(between, ctx)?,
+        ast::Expr::Nested(nested) => compile_where_expression(nested, clause, ctx)?,
+        inlist @ ast::Expr::InList { .. } => compile_where_expression(inlist, clause, ctx)?,
+        isnull @ ast::Expr::IsNull { .. } => compile_where_expression(isnull, clause, ctx)?,
+        isnotnull @ ast::Expr::IsNotNull { .. } => {
+            compile_where_expression(isnotnull, clause, ctx)?
+        }
+        between @ ast::Expr::Between { .. } => compile_where_expression(between, clause, ctx)?,
         _ => {
             return Err(CompilationError::Unsupported(format!(
-                "Expression in WHERE clause: {:?}",
-                selection
+                "Expression in {} clause: {:?}",
+                clause, selection
             )));
         }
     };
@@ -1268,14 +1403,44 @@ fn compile_where(
     trace!("Filters (after optimization): {:?}", filters);
 
     if let Some(optimized_filter) = filters {
-        builder.with_filters(convert_where_filters(optimized_filter)?);
+        for filter in convert_where_filters(optimized_filter)? {
+            builder.with_filter(filter);
+        }
     }
 
     Ok(())
 }
 
+/// Resolves `ORDER BY 1`-like ordinal reference to the expression of the projection
+fn resolve_projection_ordinal<'a>(
+    expr: &'a ast::Expr,
+    projection: &'a Vec<ast::SelectItem>,
+    clause: &str,
+) -> CompilationResult<Option<&'a ast::Expr>> {
+    let position = match expr {
+        ast::Expr::Value(ast::Value::Number(n, _)) => n.parse::<usize>().map_err(|_| {
+            CompilationError::User(format!("non-integer constant in {}: {}", clause, n))
+        })?,
+        _ => return Ok(None),
+    };
+
+    match position.checked_sub(1).and_then(|i| projection.get(i)) {
+        Some(ast::SelectItem::UnnamedExpr(expr)) => Ok(Some(expr)),
+        Some(ast::SelectItem::ExprWithAlias { expr, .. }) => Ok(Some(expr)),
+        Some(_) => Err(CompilationError::Unsupported(format!(
+            "Unable to use wildcard position {} in {}",
+            position, clause
+        ))),
+        None => Err(CompilationError::User(format!(
+            "{} position {} is not in select list",
+            clause, position
+        ))),
+    }
+}
+
 fn compile_order(
     order_by: &Vec<ast::OrderByExpr>,
+    projection: &Vec<ast::SelectItem>,
     ctx: &QueryContext,
     builder: &mut QueryBuilder,
 ) -> CompilationResult<()> {
@@ -1284,8 +1449,11 @@ fn compile_order(
     };
 
     for order_expr in order_by.iter() {
-        let order_selection = ctx
-            .compile_selection(&order_expr.expr.clone())?
+        let order_selection =
+            match resolve_projection_ordinal(&order_expr.expr, projection, "ORDER BY")? {
+                Some(expr) => ctx.compile_selection_from_projection(expr)?,
+                None => ctx.compile_selection(&order_expr.expr.clone())?,
+            }
             .ok_or_else(|| {
                 CompilationError::Unsupported(format!(
                     "Unsupported expression in order: {:?}",
@@ -1327,8 +1495,15 @@ fn compile_select(expr: &ast::Select, ctx: &mut QueryContext) -> CompilationResu
     if !expr.projection.is_empty() {
         for projection in expr.projection.iter() {
             match projection {
-                ast::SelectItem::Wildcard => {
-                    for dimension in ctx.meta.dimensions.iter() {
+                ast::SelectItem::Wildcard | ast::SelectItem::QualifiedWildcard(_) => {
+                    let relation = match projection {
+                        ast::SelectItem::QualifiedWildcard(name) => {
+                            name.0.last().map(|i| i.value.clone())
+                        }
+                        _ => None,
+                    };
+
+                    for dimension in ctx.find_dimensions_for_wildcard(&relation)?.iter() {
                         builder.with_dimension(
                             dimension.name.clone(),
                             CompiledQueryFieldMeta {
@@ -1388,6 +1563,17 @@ impl QueryPlanner {
         stmt: &ast::Statement,
         q: &Box<ast::Query>,
     ) -> CompilationResult<QueryPlan> {
+        if validation::is_validate_model_query(q) {
+            return Ok(QueryPlan::MetaTabular(
+                StatusFlags::empty(),
+                Box::new(validation::validate_model_for_state(
+                    self.meta.clone(),
+                    self.state.clone(),
+                    self.session_manager.clone(),
+                )),
+            ));
+        }
+
         // TODO move CUBESQL_REWRITE_ENGINE env to config
         let rewrite_engine = env::var("CUBESQL_REWRITE_ENGINE")
             .ok()
@@ -1412,52 +1598,7 @@ impl QueryPlanner {
             return self.create_df_logical_plan(stmt.clone());
         };
 
-        let (db_name, schema_name, table_name) = match &from_table.relation {
-            ast::TableFactor::Table { name, .. } => match name {
-                ast::ObjectName(identifiers) => {
-                    match identifiers.len() {
-                        // db.`KibanaSampleDataEcommerce`
-                        2 => match self.state.protocol {
-                            DatabaseProtocol::MySQL => (
-                                identifiers[0].value.clone(),
-                                "public".to_string(),
-                                identifiers[1].value.clone(),
-                            ),
-                            DatabaseProtocol::PostgreSQL => (
-                                "db".to_string(),
-                                identifiers[0].value.clone(),
-                                identifiers[1].value.clone(),
-                            ),
-                        },
-                        // `KibanaSampleDataEcommerce`
-                        1 => match self.state.protocol {
-                            DatabaseProtocol::MySQL => (
-                                "db".to_string(),
-                                "public".to_string(),
-                                identifiers[0].value.clone(),
-                            ),
-                            DatabaseProtocol::PostgreSQL => (
-                                "db".to_string(),
-                                "public".to_string(),
-                                identifiers[0].value.clone(),
-                            ),
-                        },
-                        _ => {
-                            return Err(CompilationError::Unsupported(format!(
-                                "Table identifier: {:?}",
-                                identifiers
-                            )));
-                        }
-                    }
-                }
-            },
-            factor => {
-                return Err(CompilationError::Unsupported(format!(
-                    "table factor: {:?}",
-                    factor
-                )));
-            }
-        };
+        let (db_name, schema_name, table_name) = self.table_factor_to_name(&from_table.relation)?;
 
         match self.state.protocol {
             DatabaseProtocol::MySQL => {
@@ -1484,11 +1625,11 @@ impl QueryPlanner {
             )));
         }
 
-        if !select.from[0].joins.is_empty() {
-            return Err(CompilationError::Unsupported(
-                "Query with JOIN instruction(s)".to_string(),
-            ));
-        }
+        let joined_relations = from_table
+            .joins
+            .iter()
+            .map(|join| self.join_to_relation(join))
+            .collect::<CompilationResult<Vec<_>>>()?;
 
         if q.with.is_some() {
             return Err(CompilationError::Unsupported(
@@ -1508,12 +1649,6 @@ impl QueryPlanner {
             ));
         }
 
-        if select.having.is_some() {
-            return Err(CompilationError::Unsupported(
-                "Query with HAVING instruction(s)".to_string(),
-            ));
-        }
-
         // @todo Better solution?
         // Metabase
         if q.to_string()
@@ -1536,7 +1671,16 @@ impl QueryPlanner {
         };
 
         if let Some(cube) = self.meta.find_cube_with_name(table_name.clone()) {
-            let mut ctx = QueryContext::new(&cube);
+            let alias = match &from_table.relation {
+                ast::TableFactor::Table { alias, .. } => {
+                    alias.as_ref().map(|a| a.name.value.clone())
+                }
+                _ => None,
+            };
+            let mut relations = vec![QueryRelation::new(alias, &cube)];
+            relations.extend(joined_relations);
+
+            let mut ctx = QueryContext::new_with_relations(relations)?;
             let mut builder = compile_select(select, &mut ctx)?;
 
             if let Some(limit_expr) = &q.limit {
@@ -1561,13 +1705,17 @@ impl QueryPlanner {
                 builder.with_offset(offset);
             }
 
-            compile_group(&select.group_by, &ctx, &mut builder)?;
-            compile_order(&q.order_by, &ctx, &mut builder)?;
+            compile_group(&select.group_by, &select.projection, &ctx, &mut builder)?;
+            compile_order(&q.order_by, &select.projection, &ctx, &mut builder)?;
 
             if let Some(selection) = &select.selection {
                 compile_where(selection, &ctx, &mut builder)?;
             }
 
+            if let Some(having) = &select.having {
+                compile_having(having, &ctx, &mut builder)?;
+            }
+
             let query = builder.build();
             let schema = query.meta_as_df_schema();
 
@@ -1608,13 +1756,124 @@ impl QueryPlanner {
         }
     }
 
+    /// Database, schema and name of the table from FROM or JOIN
+    fn table_factor_to_name(
+        &self,
+        factor: &ast::TableFactor,
+    ) -> CompilationResult<(String, String, String)> {
+        let name = match factor {
+            ast::TableFactor::Table { name, .. } => match name {
+                ast::ObjectName(identifiers) => {
+                    match identifiers.len() {
+                        // db.`KibanaSampleDataEcommerce`
+                        2 => match self.state.protocol {
+                            DatabaseProtocol::MySQL => (
+                                identifiers[0].value.clone(),
+                                "public".to_string(),
+                                identifiers[1].value.clone(),
+                            ),
+                            DatabaseProtocol::PostgreSQL => (
+                                "db".to_string(),
+                                identifiers[0].value.clone(),
+                                identifiers[1].value.clone(),
+                            ),
+                        },
+                        // `KibanaSampleDataEcommerce`
+                        1 => match self.state.protocol {
+                            DatabaseProtocol::MySQL => (
+                                "db".to_string(),
+                                "public".to_string(),
+                                identifiers[0].value.clone(),
+                            ),
+                            DatabaseProtocol::PostgreSQL => (
+                                "db".to_string(),
+                                "public".to_string(),
+                                identifiers[0].value.clone(),
+                            ),
+                        },
+                        _ => {
+                            return Err(CompilationError::Unsupported(format!(
+                                "Table identifier: {:?}",
+                                identifiers
+                            )));
+                        }
+                    }
+                }
+            },
+            factor => {
+                return Err(CompilationError::Unsupported(format!(
+                    "table factor: {:?}",
+                    factor
+                )));
+            }
+        };
+
+        Ok(name)
+    }
+
+    /// Cube from JOIN, its members are requested together with members of the cube from FROM.
+    /// Cubes are joined by the data model, so condition of join is not used
+    fn join_to_relation(&self, join: &ast::Join) -> CompilationResult<QueryRelation> {
+        match &join.join_operator {
+            ast::JoinOperator::Inner(constraint) | ast::JoinOperator::LeftOuter(constraint) => {
+                match constraint {
+                    ast::JoinConstraint::On(_) | ast::JoinConstraint::None => {}
+                    ast::JoinConstraint::Using(_) => {
+                        return Err(CompilationError::Unsupported(
+                            "JOIN with USING clause, please use JOIN with ON clause".to_string(),
+                        ));
+                    }
+                    ast::JoinConstraint::Natural => {
+                        return Err(CompilationError::Unsupported(
+                            "NATURAL JOIN, please use JOIN with ON clause".to_string(),
+                        ));
+                    }
+                }
+            }
+            ast::JoinOperator::CrossJoin => {}
+            operator => {
+                return Err(CompilationError::Unsupported(format!(
+                    "JOIN operator: {:?}",
+                    operator
+                )));
+            }
+        }
+
+        let (db_name, schema_name, table_name) = self.table_factor_to_name(&join.relation)?;
+        if db_name.to_lowercase() != "db" || schema_name.to_lowercase() != "public" {
+            return Err(CompilationError::Unsupported(format!(
+                "JOIN of cube with table {}.{}",
+                schema_name, table_name
+            )));
+        }
+
+        let alias = match &join.relation {
+            ast::TableFactor::Table { alias, .. } => alias.as_ref().map(|a| a.name.value.clone()),
+            _ => None,
+        };
+
+        match self.meta.find_cube_with_name(table_name.clone()) {
+            Some(cube) => Ok(QueryRelation::new(alias, &cube)),
+            None => Err(CompilationError::Unknown(format!(
+                "Unknown cube '{}'. Please ensure your schema files are valid.",
+                table_name,
+            ))),
+        }
+    }
+
     pub fn plan(&self, stmt: &ast::Statement) -> CompilationResult<QueryPlan> {
         match (stmt, &self.state.protocol) {
             (ast::Statement::Query(q), _) => self.select_to_plan(stmt, q),
-            (ast::Statement::SetTransaction { .. }, _) => Ok(QueryPlan::MetaTabular(
-                StatusFlags::empty(),
-                Box::new(dataframe::DataFrame::new(vec![], vec![])),
-            )),
+            (
+                ast::Statement::SetTransaction { modes, session, .. },
+                DatabaseProtocol::PostgreSQL,
+            ) => self.set_transaction_to_plan(modes, *session),
+            (ast::Statement::SetTransaction { .. }, DatabaseProtocol::MySQL) => {
+                Ok(QueryPlan::MetaTabular(
+                    StatusFlags::empty(),
+                    Box::new(dataframe::DataFrame::new(vec![], vec![])),
+                ))
+            }
             (ast::Statement::SetNames { charset_name, .. }, DatabaseProtocol::MySQL) => {
                 if !(charset_name.eq_ignore_ascii_case("utf8")
                     || charset_name.eq_ignore_ascii_case("utf8mb4"))
@@ -1694,6 +1953,15 @@ impl QueryPlanner {
                     CommandCompletion::Rollback,
                 ))
             }
+            (ast::Statement::Savepoint { .. }, DatabaseProtocol::PostgreSQL) => Ok(
+                QueryPlan::MetaOk(StatusFlags::empty(), CommandCompletion::Savepoint),
+            ),
+            (
+                ast::Statement::Insert { .. }
+                | ast::Statement::Update { .. }
+                | ast::Statement::Delete { .. },
+                DatabaseProtocol::PostgreSQL,
+            ) if self.session_manager.server.configuration.noop_dml => self.noop_dml_to_plan(stmt),
             _ => Err(CompilationError::Unsupported(format!(
                 "Unsupported query type: {}",
                 stmt.to_string()
@@ -1701,6 +1969,69 @@ impl QueryPlanner {
         }
     }
 
+    fn set_transaction_to_plan(
+        &self,
+        modes: &Vec<ast::TransactionMode>,
+        session: bool,
+    ) -> CompilationResult<QueryPlan> {
+        // SET SESSION CHARACTERISTICS changes defaults for the following transactions
+        let prefix = if session { "default_" } else { "" };
+
+        let mut variables = DatabaseVariables::new();
+        for mode in modes.iter() {
+            let (name, value) = match mode {
+                ast::TransactionMode::AccessMode(access_mode) => (
+                    "transaction_read_only",
+                    match access_mode {
+                        ast::TransactionAccessMode::ReadOnly => "on",
+                        ast::TransactionAccessMode::ReadWrite => "off",
+                    },
+                ),
+                ast::TransactionMode::IsolationLevel(level) => (
+                    "transaction_isolation",
+                    match level {
+                        ast::TransactionIsolationLevel::ReadUncommitted => "read uncommitted",
+                        ast::TransactionIsolationLevel::ReadCommitted => "read committed",
+                        ast::TransactionIsolationLevel::RepeatableRead => "repeatable read",
+                        ast::TransactionIsolationLevel::Serializable => "serializable",
+                    },
+                ),
+            };
+
+            let name = format!("{}{}", prefix, name);
+            variables.insert(
+                name.clone(),
+                DatabaseVariable::system(name, ScalarValue::Utf8(Some(value.to_string())), None),
+            );
+        }
+
+        Ok(QueryPlan::MetaChange(
+            StatusFlags::empty(),
+            CommandCompletion::Set,
+            SessionChange::Variables(VariablesChange {
+                session: variables,
+                ..VariablesChange::default()
+            }),
+        ))
+    }
+
+    fn noop_dml_to_plan(&self, stmt: &ast::Statement) -> CompilationResult<QueryPlan> {
+        // Nothing is written, that's why no rows are affected
+        let completion = match stmt {
+            ast::Statement::Insert { .. } => CommandCompletion::Insert(0),
+            ast::Statement::Update { .. } => CommandCompletion::Update(0),
+            ast::Statement::Delete { .. } => CommandCompletion::Delete(0),
+            _ => {
+                return Err(CompilationError::Internal(format!(
+                    "Unexpected statement for no-op DML: {}",
+                    stmt.to_string()
+                )))
+            }
+        };
+
+        Ok(QueryPlan::MetaOk(StatusFlags::empty(), completion))
+    }
+
     fn show_variable_to_plan(&self, variable: &Vec<Ident>) -> CompilationResult<QueryPlan> {
         let name = variable.to_vec()[0].value.clone();
         if self.state.protocol == DatabaseProtocol::PostgreSQL {
@@ -1709,38 +2040,84 @@ impl QueryPlanner {
                 "transaction_isolation_level" => "transaction_isolation",
                 x => x,
             };
-            let stmt = if name.eq_ignore_ascii_case("all") {
-                parse_sql_to_statement(
-                    &"SELECT name, setting, short_desc as description FROM pg_catalog.pg_settings"
-                        .to_string(),
-                    self.state.protocol.clone(),
-                )?
-            } else {
-                parse_sql_to_statement(
-                    // TODO: column name might be expected to match variable name
-                    &format!(
-                        "SELECT setting FROM pg_catalog.pg_settings where name = '{}'",
-                        escape_single_quote_string(full_variable),
-                    ),
-                    self.state.protocol.clone(),
-                )?
+            let variables = self.pg_visible_variables();
+            if name.eq_ignore_ascii_case("all") {
+                let mut rows = variables
+                    .values()
+                    .map(|variable| {
+                        dataframe::Row::new(vec![
+                            dataframe::TableValue::String(
+                                pg_variable_name(&variable.name).to_string(),
+                            ),
+                            dataframe::TableValue::String(pg_variable_value(&variable.value)),
+                            dataframe::TableValue::Null,
+                        ])
+                    })
+                    .collect::<Vec<_>>();
+                rows.sort_by_key(|row| row.values()[0].to_string().to_lowercase());
+
+                return Ok(QueryPlan::MetaTabular(
+                    StatusFlags::empty(),
+                    Box::new(dataframe::DataFrame::new(
+                        vec![
+                            dataframe::Column::new(
+                                "name".to_string(),
+                                ColumnType::String,
+                                ColumnFlags::empty(),
+                            ),
+                            dataframe::Column::new(
+                                "setting".to_string(),
+                                ColumnType::String,
+                                ColumnFlags::empty(),
+                            ),
+                            dataframe::Column::new(
+                                "description".to_string(),
+                                ColumnType::String,
+                                ColumnFlags::empty(),
+                            ),
+                        ],
+                        rows,
+                    )),
+                ));
+            }
+
+            let value = match variables.get(full_variable) {
+                Some(variable) => pg_variable_value(&variable.value),
+                None => {
+                    return Err(CompilationError::User(format!(
+                        "unrecognized configuration parameter \"{}\"",
+                        full_variable
+                    )))
+                }
             };
 
-            self.create_df_logical_plan(stmt)
-        } else if name.eq_ignore_ascii_case("databases") || name.eq_ignore_ascii_case("schemas") {
             Ok(QueryPlan::MetaTabular(
                 StatusFlags::empty(),
                 Box::new(dataframe::DataFrame::new(
                     vec![dataframe::Column::new(
-                        "Database".to_string(),
+                        pg_variable_name(full_variable).to_string(),
                         ColumnType::String,
                         ColumnFlags::empty(),
                     )],
-                    vec![
-                        dataframe::Row::new(vec![dataframe::TableValue::String("db".to_string())]),
-                        dataframe::Row::new(vec![dataframe::TableValue::String(
-                            "information_schema".to_string(),
-                        )]),
+                    vec![dataframe::Row::new(vec![dataframe::TableValue::String(
+                        value,
+                    )])],
+                )),
+            ))
+        } else if name.eq_ignore_ascii_case("databases") || name.eq_ignore_ascii_case("schemas") {
+            Ok(QueryPlan::MetaTabular(
+                StatusFlags::empty(),
+                Box::new(dataframe::DataFrame::new(
+                    vec![dataframe::Column::new(
+                        "Database".to_string(),
+                        ColumnType::String,
+                        ColumnFlags::empty(),
+                    )],
+                    vec![
+                        dataframe::Row::new(vec![dataframe::TableValue::String("db".to_string())]),
+                        dataframe::Row::new(vec![dataframe::TableValue::String(
+                            "information_schema".to_string(),
+                        )]),
                         dataframe::Row::new(vec![dataframe::TableValue::String(
                             "mysql".to_string(),
                         )]),
@@ -2039,6 +2416,54 @@ WHERE `TABLE_SCHEMA` = '{}'",
         self.create_df_logical_plan(stmt)
     }
 
+    fn sql_macro_to_plan(&self, statement: SqlMacroStatement) -> CompilationResult<QueryPlan> {
+        match statement {
+            SqlMacroStatement::Create {
+                or_replace,
+                definition,
+            } => {
+                let mut macros = self.state.sql_macros();
+                macros.insert(definition.name.clone(), (*definition).clone());
+                SqlMacroExpander::new(&macros).expand_definition(&definition)?;
+
+                // Existing function is checked by execution
+                Ok(QueryPlan::MetaChange(
+                    StatusFlags::empty(),
+                    CommandCompletion::CreateFunction,
+                    SessionChange::CreateSqlMacro {
+                        definition,
+                        or_replace,
+                    },
+                ))
+            }
+            SqlMacroStatement::Drop { if_exists, name } => Ok(QueryPlan::MetaChange(
+                StatusFlags::empty(),
+                CommandCompletion::DropFunction,
+                SessionChange::DropSqlMacro { name, if_exists },
+            )),
+        }
+    }
+
+    fn transaction_command_to_plan(
+        &self,
+        command: TransactionCommand,
+    ) -> CompilationResult<QueryPlan> {
+        match command {
+            TransactionCommand::SetLocal(key_values) => {
+                self.pg_set_variable_to_plan(&key_values, true)
+            }
+            // Savepoints are not tracked, the transaction state is changed by the connection
+            TransactionCommand::ReleaseSavepoint(_) => Ok(QueryPlan::MetaOk(
+                StatusFlags::empty(),
+                CommandCompletion::Release,
+            )),
+            TransactionCommand::RollbackToSavepoint(_) => Ok(QueryPlan::MetaOk(
+                StatusFlags::empty(),
+                CommandCompletion::RollbackToSavepoint,
+            )),
+        }
+    }
+
     fn explain_table_to_plan(
         &self,
         table_name: &ast::ObjectName,
@@ -2086,45 +2511,8 @@ WHERE `TABLE_SCHEMA` = '{}'",
 
         let mut session_columns_to_update: DatabaseVariables = DatabaseVariables::new();
         let mut global_columns_to_update: DatabaseVariables = DatabaseVariables::new();
-
         match self.state.protocol {
-            DatabaseProtocol::PostgreSQL => {
-                for key_value in key_values.iter() {
-                    let value: String = match &key_value.value[0] {
-                        ast::Expr::Identifier(ident) => ident.value.to_string(),
-                        ast::Expr::Value(val) => match val {
-                            ast::Value::SingleQuotedString(single_quoted_str) => {
-                                single_quoted_str.to_string()
-                            }
-                            ast::Value::DoubleQuotedString(double_quoted_str) => {
-                                double_quoted_str.to_string()
-                            }
-                            ast::Value::Number(number, _) => number.to_string(),
-                            _ => {
-                                return Err(CompilationError::User(format!(
-                                    "invalid {} variable format",
-                                    key_value.key.value
-                                )))
-                            }
-                        },
-                        _ => {
-                            return Err(CompilationError::User(format!(
-                                "invalid {} variable format",
-                                key_value.key.value
-                            )))
-                        }
-                    };
-
-                    global_columns_to_update.insert(
-                        key_value.key.value.to_lowercase(),
-                        DatabaseVariable::system(
-                            key_value.key.value.to_lowercase(),
-                            ScalarValue::Utf8(Some(value.clone())),
-                            None,
-                        ),
-                    );
-                }
-            }
+            DatabaseProtocol::PostgreSQL => return self.pg_set_variable_to_plan(key_values, false),
             DatabaseProtocol::MySQL => {
                 for key_value in key_values.iter() {
                     if key_value.key.value.to_lowercase() == "autocommit".to_string() {
@@ -2211,9 +2599,115 @@ WHERE `TABLE_SCHEMA` = '{}'",
         ))
     }
 
+    /// Variables are stored in the session, SET LOCAL is stored till the end of transaction
+    /// block. SET ... TO DEFAULT and RESET restore the default value. The session is changed
+    /// when the plan is executed
+    fn pg_set_variable_to_plan(
+        &self,
+        key_values: &Vec<ast::SetVariableKeyValue>,
+        is_local: bool,
+    ) -> Result<QueryPlan, CompilationError> {
+        let mut change = VariablesChange::default();
+
+        let known_variables = self.pg_visible_variables();
+
+        for key_value in key_values.iter() {
+            let key = key_value.key.value.to_lowercase();
+
+            if let [ast::Expr::Identifier(ident)] = key_value.value.as_slice() {
+                if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("default") {
+                    if key == "all" {
+                        change.reset_all = true;
+                    } else {
+                        change.reset.push(key);
+                    }
+
+                    continue;
+                }
+            }
+
+            let values = key_value
+                .value
+                .iter()
+                .map(|value| match value {
+                    ast::Expr::Identifier(ident) => Ok(ident.value.to_string()),
+                    ast::Expr::Value(ast::Value::SingleQuotedString(single_quoted_str)) => {
+                        Ok(single_quoted_str.to_string())
+                    }
+                    ast::Expr::Value(ast::Value::DoubleQuotedString(double_quoted_str)) => {
+                        Ok(double_quoted_str.to_string())
+                    }
+                    ast::Expr::Value(ast::Value::Number(number, _)) => Ok(number.to_string()),
+                    ast::Expr::UnaryOp {
+                        op: ast::UnaryOperator::Minus,
+                        expr,
+                    } => match expr.as_ref() {
+                        ast::Expr::Value(ast::Value::Number(number, _)) => {
+                            Ok(format!("-{}", number))
+                        }
+                        _ => Err(CompilationError::User(format!(
+                            "invalid {} variable format",
+                            key
+                        ))),
+                    },
+                    _ => Err(CompilationError::User(format!(
+                        "invalid {} variable format",
+                        key
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if !known_variables.contains_key(&key) {
+                self.state.add_warning(format!(
+                    "unrecognized configuration parameter \"{}\" is set, but it's not used",
+                    key
+                ));
+            }
+
+            let columns_to_update = if is_local {
+                &mut change.local
+            } else {
+                &mut change.session
+            };
+            columns_to_update.insert(
+                key.clone(),
+                DatabaseVariable::system(
+                    key.clone(),
+                    // SET search_path TO a, b
+                    ScalarValue::Utf8(Some(values.join(", "))),
+                    None,
+                ),
+            );
+        }
+
+        Ok(QueryPlan::MetaChange(
+            StatusFlags::SERVER_STATE_CHANGED,
+            CommandCompletion::Set,
+            SessionChange::Variables(change),
+        ))
+    }
+
+    /// Server variables overridden by variables of session
+    fn pg_visible_variables(&self) -> DatabaseVariables {
+        self.state.visible_variables(
+            self.session_manager
+                .server
+                .all_variables(self.state.protocol.clone()),
+        )
+    }
+
     fn create_execution_ctx(&self) -> DFSessionContext {
         let query_planner = Arc::new(CubeQueryPlanner::new(
             self.session_manager.server.transport.clone(),
+            self.state.clone(),
+            self.session_manager
+                .server
+                .configuration
+                .cube_scan_page_size,
+            self.session_manager
+                .server
+                .configuration
+                .continue_wait_timeout,
         ));
         let mut ctx = DFSessionContext::with_state(
             default_session_builder(
@@ -2239,24 +2733,44 @@ WHERE `TABLE_SCHEMA` = '{}'",
         }
 
         // udf
+        let mut search_path = "public".to_string();
         if self.state.protocol == DatabaseProtocol::MySQL {
             ctx.register_udf(create_version_udf("8.0.25".to_string()));
+            ctx.register_udf(create_current_user_udf(self.state.clone()));
         } else if self.state.protocol == DatabaseProtocol::PostgreSQL {
-            ctx.register_udf(create_version_udf(
-                "PostgreSQL 14.1 on x86_64-cubesql".to_string(),
+            let variables = self.pg_visible_variables();
+            // version() must be consistent with server_version reported by ParameterStatus
+            let server_version = variables
+                .get("server_version")
+                .map(|var| var.value.to_string())
+                .unwrap_or_default();
+            ctx.register_udf(create_version_udf(format!(
+                "PostgreSQL {} on x86_64-cubesql",
+                server_version
+            )));
+            if let Some(var) = variables.get("search_path") {
+                search_path = var.value.to_string();
+            }
+            ctx.register_udf(create_current_setting_udf(variables));
+            ctx.register_udf(create_session_user_udf("current_user", self.state.clone()));
+            ctx.register_udf(create_session_user_udf("session_user", self.state.clone()));
+            ctx.register_udf(create_db_udf(
+                "current_database".to_string(),
+                self.state.clone(),
             ));
         }
+        let schemas = search_path_schemas(&search_path, self.state.user());
         ctx.register_udf(create_db_udf("database".to_string(), self.state.clone()));
         ctx.register_udf(create_db_udf("schema".to_string(), self.state.clone()));
         ctx.register_udf(create_connection_id_udf(self.state.clone()));
         ctx.register_udf(create_pg_backend_pid(self.state.clone()));
         ctx.register_udf(create_user_udf(self.state.clone()));
-        ctx.register_udf(create_current_user_udf(self.state.clone()));
         ctx.register_udf(create_instr_udf());
         ctx.register_udf(create_ucase_udf());
         ctx.register_udf(create_isnull_udf());
         ctx.register_udf(create_if_udf());
         ctx.register_udf(create_least_udf());
+        ctx.register_udf(create_coalesce_udf());
         ctx.register_udf(create_convert_tz_udf());
         ctx.register_udf(create_timediff_udf());
         ctx.register_udf(create_time_format_udf());
@@ -2274,8 +2788,13 @@ WHERE `TABLE_SCHEMA` = '{}'",
         ctx.register_udf(create_date_sub_udf());
         ctx.register_udf(create_date_add_udf());
         ctx.register_udf(create_str_to_date());
-        ctx.register_udf(create_current_schema_udf());
-        ctx.register_udf(create_current_schemas_udf());
+        ctx.register_udf(create_pg_date_trunc_udf());
+        ctx.register_udf(create_pg_date_part_udf());
+        ctx.register_udf(create_to_char_udf());
+        ctx.register_udf(create_pg_to_timestamp_udf());
+        ctx.register_udf(create_to_date_udf());
+        ctx.register_udf(create_current_schema_udf(schemas.clone()));
+        ctx.register_udf(create_current_schemas_udf(schemas));
         ctx.register_udf(create_format_type_udf("format_type"));
         ctx.register_udf(create_format_type_udf("pg_catalog.format_type"));
         ctx.register_udf(create_pg_datetime_precision_udf());
@@ -2283,6 +2802,30 @@ WHERE `TABLE_SCHEMA` = '{}'",
         ctx.register_udf(create_pg_numeric_scale_udf());
         ctx.register_udf(create_pg_get_userbyid_udf(self.state.clone()));
         ctx.register_udf(create_pg_get_expr_udf());
+        ctx.register_udf(create_pg_get_functiondef_udf(
+            "pg_get_functiondef",
+            self.state.clone(),
+        ));
+        ctx.register_udf(create_pg_get_functiondef_udf(
+            "pg_catalog.pg_get_functiondef",
+            self.state.clone(),
+        ));
+        ctx.register_udf(create_pg_size_pretty_udf("pg_size_pretty"));
+        ctx.register_udf(create_pg_size_pretty_udf("pg_catalog.pg_size_pretty"));
+        ctx.register_udf(create_pg_get_serial_sequence_udf("pg_get_serial_sequence"));
+        ctx.register_udf(create_pg_get_serial_sequence_udf(
+            "pg_catalog.pg_get_serial_sequence",
+        ));
+        ctx.register_udf(create_pg_terminate_backend_udf(
+            "pg_terminate_backend",
+            self.state.clone(),
+            self.session_manager.clone(),
+        ));
+        ctx.register_udf(create_pg_terminate_backend_udf(
+            "pg_catalog.pg_terminate_backend",
+            self.state.clone(),
+            self.session_manager.clone(),
+        ));
         ctx.register_udf(pg_table_is_visible());
         ctx.register_udf(pg_get_userbyid());
 
@@ -2313,8 +2856,11 @@ WHERE `TABLE_SCHEMA` = '{}'",
 
         let plan = df_query_planner
             .statement_to_plan(DFStatement::Statement(Box::new(stmt)))
-            .map_err(|err| {
-                CompilationError::Internal(format!("Initial planning error: {}", err))
+            .map_err(|err| match err {
+                DataFusionError::Plan(message) if message.starts_with("Table or CTE with name") => {
+                    CompilationError::UndefinedTable(message)
+                }
+                err => CompilationError::Internal(format!("Initial planning error: {}", err)),
             })?;
 
         let optimized_plan = plan;
@@ -2341,13 +2887,142 @@ WHERE `TABLE_SCHEMA` = '{}'",
     }
 }
 
+/// PostgreSQL reports some variables in mixed case, the same names are used by ParameterStatus
+fn pg_variable_name(name: &str) -> &str {
+    match name {
+        "datestyle" => "DateStyle",
+        "intervalstyle" => "IntervalStyle",
+        "timezone" => "TimeZone",
+        name => name,
+    }
+}
+
+/// Text representation of the variable value, as it's shown by SHOW
+fn pg_variable_value(value: &ScalarValue) -> String {
+    match value {
+        ScalarValue::Utf8(value) => value.clone().unwrap_or_default(),
+        value => value.to_string(),
+    }
+}
+
 pub fn convert_statement_to_cube_query(
     stmt: &ast::Statement,
     meta: Arc<MetaContext>,
     session: Arc<Session>,
 ) -> CompilationResult<QueryPlan> {
-    let planner = QueryPlanner::new(session.state.clone(), meta, session.session_manager.clone());
-    planner.plan(stmt)
+    // Checked first, as other checks visit the statement recursively
+    let max_subquery_depth = session.server.configuration.max_subquery_depth;
+    if StatementSubqueryDepthFinder::new(max_subquery_depth).find(stmt) > max_subquery_depth {
+        return Err(CompilationError::StatementTooComplex(format!(
+            "Subqueries are nested too deeply, maximum allowed depth is {}",
+            max_subquery_depth
+        )));
+    }
+
+    let max_expressions = session.server.configuration.max_query_expressions;
+    let expressions = StatementExpressionsCounter::new().count(stmt);
+    if expressions > max_expressions {
+        return Err(CompilationError::ProgramLimitExceeded(format!(
+            "Query contains too many expressions: {}, maximum allowed is {}",
+            expressions, max_expressions
+        )));
+    }
+
+    if let Some(subquery) = StatementLateralFinder::new().find(stmt) {
+        return Err(CompilationError::Unsupported(format!(
+            "LATERAL subqueries are not supported: {}. Rewrite the query with a regular JOIN \
+            to the subquery or with a scalar subquery in the projection",
+            subquery
+        )));
+    }
+
+    let replaced_stmt;
+    let stmt = if session.state.protocol == DatabaseProtocol::PostgreSQL {
+        replaced_stmt = StatementCastsReplacer::new().replace(
+            &StatementArrayComparisonsReplacer::new().replace(
+                &StatementDateTimeFunctionsReplacer::new().replace(
+                    &StatementTableFunctionsReplacer::new()
+                        .replace(&StatementSqlValueFunctionsReplacer::new().replace(stmt)),
+                ),
+            ),
+        );
+        &replaced_stmt
+    } else {
+        stmt
+    };
+
+    let planner = QueryPlanner::new(
+        session.state.clone(),
+        meta.clone(),
+        session.session_manager.clone(),
+    );
+
+    let macros = session.state.sql_macros();
+    let plan = if macros.is_empty() {
+        planner.plan(stmt)?
+    } else {
+        // Calls are replaced before planning to push down the expanded expressions
+        let mut stmt = stmt.clone();
+        SqlMacroExpander::new(&macros).expand_statement(&mut stmt)?;
+
+        planner.plan(&stmt)?
+    };
+
+    check_unbounded_scans(&plan, &meta)?;
+
+    Ok(plan)
+}
+
+/// Large cubes (see V1CubeMetaExt::is_large) can be queried only with a filter, segment,
+/// date range or LIMIT
+fn check_unbounded_scans(plan: &QueryPlan, meta: &MetaContext) -> CompilationResult<()> {
+    for request in plan.load_requests() {
+        let is_bounded = request.limit.map(|limit| limit > 0).unwrap_or(false)
+            || request
+                .filters
+                .as_ref()
+                .map(|filters| !filters.is_empty())
+                .unwrap_or(false)
+            || request
+                .segments
+                .as_ref()
+                .map(|segments| !segments.is_empty())
+                .unwrap_or(false)
+            || request
+                .time_dimensions
+                .iter()
+                .flatten()
+                .any(|td| td.date_range.is_some());
+        if is_bounded {
+            continue;
+        }
+
+        let members = request
+            .measures
+            .iter()
+            .flatten()
+            .chain(request.dimensions.iter().flatten())
+            .chain(
+                request
+                    .time_dimensions
+                    .iter()
+                    .flatten()
+                    .map(|td| &td.dimension),
+            );
+        for member in members {
+            let cube_name = member.split('.').next().unwrap_or(member);
+            if let Some(cube) = meta.cubes.iter().find(|cube| cube.name == cube_name) {
+                if cube.is_large() {
+                    return Err(CompilationError::User(format!(
+                        "Query to the large cube {} must contain a filter or LIMIT",
+                        cube.name
+                    )));
+                }
+            }
+        }
+    }
+
+    Ok(())
 }
 
 #[derive(Debug, PartialEq, Serialize)]
@@ -2377,8 +3052,22 @@ impl CompiledQuery {
         let mut fields: Vec<DFField> = Vec::new();
 
         for meta_field in self.meta.iter() {
+            // Names of columns from joined cubes can be the same (as in PostgreSQL),
+            // such fields are qualified by the cube
+            let duplicated = self
+                .meta
+                .iter()
+                .filter(|f| f.column_to == meta_field.column_to)
+                .count()
+                > 1;
+            let qualifier = if duplicated {
+                meta_field.column_from.split('.').next()
+            } else {
+                None
+            };
+
             fields.push(DFField::new(
-                None,
+                qualifier,
                 meta_field.column_to.as_str(),
                 df_data_type_by_column_type(meta_field.column_type.clone()),
                 false,
@@ -2419,6 +3108,8 @@ pub enum QueryPlan {
     // Meta will not be executed in DF,
     // we already knows how respond to it
     MetaOk(StatusFlags, CommandCompletion),
+    // The same as MetaOk, the session is changed when it's executed (postgresql)
+    MetaChange(StatusFlags, CommandCompletion, SessionChange),
     MetaTabular(StatusFlags, Box<dataframe::DataFrame>),
     // Query will be executed via Data Fusion
     DataFusionSelect(StatusFlags, LogicalPlan, DFSessionContext),
@@ -2428,12 +3119,44 @@ impl QueryPlan {
     pub fn as_logical_plan(self) -> LogicalPlan {
         match self {
             QueryPlan::DataFusionSelect(_, plan, _) => plan,
-            QueryPlan::MetaOk(_, _) | QueryPlan::MetaTabular(_, _) => {
+            QueryPlan::MetaOk(_, _)
+            | QueryPlan::MetaChange(_, _, _)
+            | QueryPlan::MetaTabular(_, _) => {
                 panic!("This query doesnt have a plan, because it already has values for response")
             }
         }
     }
 
+    /// Load queries of all CubeScan nodes in the plan
+    pub fn load_requests(&self) -> Vec<V1LoadRequestQuery> {
+        struct CubeScanRequestsVisitor(Vec<V1LoadRequestQuery>);
+
+        impl PlanVisitor for CubeScanRequestsVisitor {
+            type Error = CubeError;
+
+            fn pre_visit(&mut self, plan: &LogicalPlan) -> Result<bool, Self::Error> {
+                if let LogicalPlan::Extension(ext) = plan {
+                    if let Some(scan_node) = ext.node.as_any().downcast_ref::<CubeScanNode>() {
+                        self.0.push(scan_node.request.clone());
+                    }
+                }
+                Ok(true)
+            }
+        }
+
+        match self {
+            QueryPlan::DataFusionSelect(_, plan, _) => {
+                let mut visitor = CubeScanRequestsVisitor(vec![]);
+                // Visitor doesn't return errors
+                plan.accept(&mut visitor).unwrap();
+                visitor.0
+            }
+            QueryPlan::MetaOk(_, _)
+            | QueryPlan::MetaChange(_, _, _)
+            | QueryPlan::MetaTabular(_, _) => vec![],
+        }
+    }
+
     pub fn print(&self, pretty: bool) -> Result<String, CubeError> {
         match self {
             QueryPlan::DataFusionSelect(_, plan, _) => {
@@ -2443,7 +3166,9 @@ impl QueryPlan {
                     Ok(plan.display().to_string())
                 }
             }
-            QueryPlan::MetaOk(_, _) | QueryPlan::MetaTabular(_, _) => Ok(
+            QueryPlan::MetaOk(_, _)
+            | QueryPlan::MetaChange(_, _, _)
+            | QueryPlan::MetaTabular(_, _) => Ok(
                 "This query doesnt have a plan, because it already has values for response"
                     .to_string(),
             ),
@@ -2456,15 +3181,41 @@ pub fn convert_sql_to_cube_query(
     meta: Arc<MetaContext>,
     session: Arc<Session>,
 ) -> CompilationResult<QueryPlan> {
+    if session.state.protocol == DatabaseProtocol::PostgreSQL {
+        if let Some(statement) = parse_sql_macro_statement(query)? {
+            let planner =
+                QueryPlanner::new(session.state.clone(), meta, session.session_manager.clone());
+
+            return planner.sql_macro_to_plan(statement);
+        }
+
+        if let Some(command) = parse_transaction_command(query)? {
+            let planner =
+                QueryPlanner::new(session.state.clone(), meta, session.session_manager.clone());
+
+            return planner.transaction_command_to_plan(command);
+        }
+    }
+
     let stmt = parse_sql_to_statement(&query, session.state.protocol.clone())?;
     convert_statement_to_cube_query(&stmt, meta, session)
 }
 
+/// Dry run: the query is compiled up to load queries, which are returned instead of execution
+pub fn dry_run_sql_to_cube_query(
+    query: &String,
+    meta: Arc<MetaContext>,
+    session: Arc<Session>,
+) -> CompilationResult<Vec<V1LoadRequestQuery>> {
+    Ok(convert_sql_to_cube_query(query, meta, session)?.load_requests())
+}
+
 #[cfg(test)]
 mod tests {
     use async_trait::async_trait;
     use cubeclient::models::{
-        V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure, V1CubeMetaSegment, V1LoadResponse,
+        V1CubeMeta, V1CubeMetaDimension, V1CubeMetaJoin, V1CubeMetaMeasure, V1CubeMetaSegment,
+        V1LoadResponse,
     };
     use datafusion::dataframe::DataFrame as DFDataFrame;
     use pretty_assertions::assert_eq;
@@ -2472,13 +3223,14 @@ mod tests {
     use super::*;
     use crate::{
         sql::{
-            dataframe::batch_to_dataframe, server_manager::ServerConfiguration, types::StatusFlags,
-            AuthContext, AuthenticateResponse, ServerManager, SqlAuthService,
+            dataframe::batch_to_dataframe, df_type_to_pg_tid, server_manager::ServerConfiguration,
+            statement::StatementParamsBinder, types::StatusFlags, AuthContext,
+            AuthenticateResponse, ServerManager, SqlAuthService, TransactionState,
         },
         transport::TransportService,
     };
-    use datafusion::logical_plan::PlanVisitor;
     use log::Level;
+    use pg_srv::{protocol::Format, BindValue, PgTypeId};
     use simple_logger::SimpleLogger;
 
     lazy_static! {
@@ -2505,6 +3257,8 @@ mod tests {
             V1CubeMeta {
                 name: "KibanaSampleDataEcommerce".to_string(),
                 title: None,
+                meta: None,
+                joins: None,
                 dimensions: vec![
                     V1CubeMetaDimension {
                         name: "KibanaSampleDataEcommerce.order_date".to_string(),
@@ -2561,6 +3315,8 @@ mod tests {
             V1CubeMeta {
                 name: "Logs".to_string(),
                 title: None,
+                meta: None,
+                joins: None,
                 dimensions: vec![],
                 measures: vec![
                     V1CubeMetaMeasure {
@@ -2586,10 +3342,25 @@ mod tests {
     }
 
     fn get_test_session(protocol: DatabaseProtocol) -> Arc<Session> {
+        get_test_session_with_config(protocol, ServerConfiguration::default())
+    }
+
+    fn get_test_session_with_config(
+        protocol: DatabaseProtocol,
+        configuration: ServerConfiguration,
+    ) -> Arc<Session> {
+        get_test_session_with_transport(protocol, configuration, get_test_transport())
+    }
+
+    fn get_test_session_with_transport(
+        protocol: DatabaseProtocol,
+        configuration: ServerConfiguration,
+        transport: Arc<dyn TransportService>,
+    ) -> Arc<Session> {
         let server = Arc::new(ServerManager {
             auth: get_test_auth(),
-            transport: get_test_transport(),
-            configuration: ServerConfiguration::default(),
+            transport,
+            configuration,
             nonce: None,
         });
 
@@ -2602,11 +3373,27 @@ mod tests {
         session.state.set_auth_context(Some(AuthContext {
             access_token: "access_token".to_string(),
             base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
         }));
 
         session
     }
 
+    /// Plans the query and applies its change of the session, as the connection does by
+    /// execution of the statement
+    fn execute_session_query(query: &str, session: &Arc<Session>) -> Result<QueryPlan, CubeError> {
+        match convert_sql_to_cube_query(&query.to_string(), get_test_tenant_ctx(), session.clone())?
+        {
+            QueryPlan::MetaChange(flags, completion, change) => {
+                session.state.apply_change(change)?;
+
+                Ok(QueryPlan::MetaOk(flags, completion))
+            }
+            plan => Ok(plan),
+        }
+    }
+
     fn get_test_auth() -> Arc<dyn SqlAuthService> {
         #[derive(Debug)]
         struct TestSqlAuth {}
@@ -2621,6 +3408,8 @@ mod tests {
                     context: AuthContext {
                         access_token: "fake".to_string(),
                         base_path: "fake".to_string(),
+                        claims: None,
+                        superuser: false,
                     },
                     password: None,
                 })
@@ -2721,7 +3510,8 @@ mod tests {
                 order: None,
                 limit: None,
                 offset: None,
-                filters: None
+                filters: None,
+                ungrouped: None,
             }
         );
     }
@@ -2746,55 +3536,344 @@ mod tests {
                 order: None,
                 limit: None,
                 offset: None,
-                filters: None
+                filters: None,
+                ungrouped: None,
             }
         );
     }
 
+    fn get_join_test_cube(name: &str, dimensions: Vec<&str>, joins: Vec<&str>) -> V1CubeMeta {
+        V1CubeMeta {
+            name: name.to_string(),
+            title: None,
+            meta: None,
+            joins: Some(
+                joins
+                    .into_iter()
+                    .map(|join| V1CubeMetaJoin {
+                        name: join.to_string(),
+                        relationship: "belongsTo".to_string(),
+                    })
+                    .collect(),
+            ),
+            dimensions: dimensions
+                .into_iter()
+                .map(|dimension| V1CubeMetaDimension {
+                    name: format!("{}.{}", name, dimension),
+                    _type: "string".to_string(),
+                })
+                .collect(),
+            measures: vec![V1CubeMetaMeasure {
+                name: format!("{}.count", name),
+                title: None,
+                _type: "number".to_string(),
+                agg_type: Some("count".to_string()),
+            }],
+            segments: vec![],
+        }
+    }
+
+    fn get_join_test_meta() -> Arc<MetaContext> {
+        Arc::new(MetaContext::new(vec![
+            get_join_test_cube("Orders", vec!["status"], vec![]),
+            get_join_test_cube("Customers", vec!["status", "city"], vec![]),
+        ]))
+    }
+
+    /// Orders belong to Customers, Customers belong to Cities
+    fn get_rewrite_join_test_meta() -> Arc<MetaContext> {
+        Arc::new(MetaContext::new(vec![
+            get_join_test_cube("Orders", vec!["status", "customer_id"], vec!["Customers"]),
+            get_join_test_cube("Customers", vec!["id", "city", "city_id"], vec!["Cities"]),
+            get_join_test_cube("Cities", vec!["id", "name"], vec![]),
+        ]))
+    }
+
     #[test]
-    fn test_select_measure_aggregate_functions() {
-        let query_plan = convert_select_to_query_plan(
-            "SELECT MAX(maxPrice), MIN(minPrice), AVG(avgPrice) FROM KibanaSampleDataEcommerce"
+    fn test_select_join_qualified_members() {
+        let query_plan = convert_sql_to_cube_query(
+            &"SELECT o.status, c.status AS customer_status, COUNT(*), MEASURE(c.count) AS customers_count \
+            FROM Orders o JOIN Customers c ON o.customer_id = c.id \
+            WHERE Customers.city = 'Berlin' GROUP BY 1, 2"
                 .to_string(),
-            DatabaseProtocol::MySQL,
-        );
+            get_join_test_meta(),
+            get_test_session(DatabaseProtocol::MySQL),
+        )
+        .unwrap();
 
         let logical_plan = query_plan.as_logical_plan();
         assert_eq!(
             logical_plan.find_cube_scan().request,
             V1LoadRequestQuery {
                 measures: Some(vec![
-                    "KibanaSampleDataEcommerce.maxPrice".to_string(),
-                    "KibanaSampleDataEcommerce.minPrice".to_string(),
-                    "KibanaSampleDataEcommerce.avgPrice".to_string(),
+                    "Orders.count".to_string(),
+                    "Customers.count".to_string(),
                 ]),
                 segments: Some(vec![]),
-                dimensions: Some(vec![]),
+                dimensions: Some(vec![
+                    "Orders.status".to_string(),
+                    "Customers.status".to_string(),
+                ]),
                 time_dimensions: None,
                 order: None,
                 limit: None,
                 offset: None,
-                filters: None
+                filters: Some(vec![V1LoadRequestQueryFilterItem {
+                    member: Some("Customers.city".to_string()),
+                    operator: Some("equals".to_string()),
+                    values: Some(vec!["Berlin".to_string()]),
+                    or: None,
+                    and: None,
+                }]),
+                ungrouped: None,
             }
         );
-
         assert_eq!(
             logical_plan
                 .schema()
                 .fields()
                 .iter()
-                .map(|f| f.data_type().clone())
+                .map(|f| f.name().to_string())
                 .collect::<Vec<_>>(),
-            vec![DataType::Float64, DataType::Float64, DataType::Float64]
+            vec!["status", "customer_status", "count", "customers_count"]
         );
-    }
 
-    #[test]
-    fn test_order_alias_for_measure_default() {
-        let query_plan = convert_select_to_query_plan(
-            "SELECT COUNT(*) as cnt FROM KibanaSampleDataEcommerce ORDER BY cnt".to_string(),
-            DatabaseProtocol::MySQL,
-        );
+        // Columns with the same names are qualified by cubes
+        let query_plan = convert_sql_to_cube_query(
+            &"SELECT o.status, c.* FROM Orders o CROSS JOIN Customers c".to_string(),
+            get_join_test_meta(),
+            get_test_session(DatabaseProtocol::MySQL),
+        )
+        .unwrap();
+
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request.dimensions,
+            Some(vec![
+                "Orders.status".to_string(),
+                "Customers.status".to_string(),
+                "Customers.city".to_string(),
+            ])
+        );
+        assert_eq!(
+            logical_plan
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.qualified_name())
+                .collect::<Vec<_>>(),
+            vec!["Orders.status", "Customers.status", "city"]
+        );
+    }
+
+    #[test]
+    fn test_select_join_error() {
+        let variants = vec![
+            (
+                "SELECT status FROM Orders JOIN Customers ON Orders.id = Customers.id",
+                CompilationError::AmbiguousColumn(
+                    "column reference \"status\" is ambiguous".to_string(),
+                ),
+            ),
+            (
+                "SELECT city FROM Orders o JOIN Customers c ON true WHERE status = 'new'",
+                CompilationError::AmbiguousColumn(
+                    "column reference \"status\" is ambiguous".to_string(),
+                ),
+            ),
+            (
+                "SELECT x.status FROM Orders o JOIN Customers c ON true",
+                CompilationError::User("missing FROM-clause entry for table \"x\"".to_string()),
+            ),
+            (
+                "SELECT o.status FROM Orders o JOIN Customers c USING (id)",
+                CompilationError::Unsupported(
+                    "JOIN with USING clause, please use JOIN with ON clause".to_string(),
+                ),
+            ),
+            (
+                "SELECT o.status FROM Orders o NATURAL JOIN Customers c",
+                CompilationError::Unsupported(
+                    "NATURAL JOIN, please use JOIN with ON clause".to_string(),
+                ),
+            ),
+            (
+                "SELECT o.status FROM Orders o JOIN Orders o2 ON true",
+                CompilationError::Unsupported(
+                    "Unable to join cube 'Orders' with itself".to_string(),
+                ),
+            ),
+            (
+                "SELECT o.status FROM Orders o JOIN Customers o ON true",
+                CompilationError::User("table name \"o\" specified more than once".to_string()),
+            ),
+        ];
+
+        for (input_query, expected_error) in variants.iter() {
+            let query = convert_sql_to_cube_query(
+                &input_query.to_string(),
+                get_join_test_meta(),
+                get_test_session(DatabaseProtocol::MySQL),
+            );
+
+            match query {
+                Ok(_) => panic!("Query ({}) should return error", input_query),
+                Err(e) => assert_eq!(&e, expected_error, "for {}", input_query),
+            }
+        }
+    }
+
+    #[test]
+    fn test_join_cubes_as_single_query() {
+        init_logger();
+
+        let plan = |query: &str| {
+            convert_sql_to_cube_query(
+                &query.to_string(),
+                get_rewrite_join_test_meta(),
+                get_test_session(DatabaseProtocol::PostgreSQL),
+            )
+            .unwrap()
+        };
+
+        let logical_plan = plan(
+            "SELECT c.city, COUNT(*) FROM Orders o \
+            JOIN Customers c ON o.customer_id = c.id GROUP BY 1",
+        )
+        .as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec!["Orders.count".to_string()]),
+                segments: Some(vec![]),
+                dimensions: Some(vec!["Customers.city".to_string()]),
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            }
+        );
+
+        // Joins are chained by the data model
+        let logical_plan = plan(
+            "SELECT ci.name, o.status, MEASURE(c.count) FROM Orders o \
+            JOIN Customers c ON o.customer_id = c.id \
+            JOIN Cities ci ON c.city_id = ci.id GROUP BY 1, 2",
+        )
+        .as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec!["Customers.count".to_string()]),
+                segments: Some(vec![]),
+                dimensions: Some(vec!["Cities.name".to_string(), "Orders.status".to_string(),]),
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_join_cubes_fallback() {
+        init_logger();
+
+        // Orders and Cities aren't joined in the data model, JOIN is executed by SQL API
+        let logical_plan = convert_sql_to_cube_query(
+            &"SELECT o.status, ci.name \
+            FROM (SELECT status, customer_id FROM Orders) o \
+            JOIN (SELECT id, name FROM Cities) ci ON o.customer_id = ci.id"
+                .to_string(),
+            get_rewrite_join_test_meta(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        )
+        .unwrap()
+        .as_logical_plan();
+
+        let plan_text = logical_plan.display_indent().to_string();
+        let nodes = plan_text
+            .lines()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes
+                .iter()
+                .filter(|node| node.starts_with("Join:"))
+                .count(),
+            1,
+            "{}",
+            plan_text
+        );
+        assert_eq!(
+            nodes
+                .iter()
+                .filter(|node| node.starts_with("CubeScan:"))
+                .count(),
+            2,
+            "{}",
+            plan_text
+        );
+        assert_eq!(
+            logical_plan
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().to_string())
+                .collect::<Vec<_>>(),
+            vec!["status", "name"]
+        );
+    }
+
+    #[test]
+    fn test_select_measure_aggregate_functions() {
+        let query_plan = convert_select_to_query_plan(
+            "SELECT MAX(maxPrice), MIN(minPrice), AVG(avgPrice) FROM KibanaSampleDataEcommerce"
+                .to_string(),
+            DatabaseProtocol::MySQL,
+        );
+
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec![
+                    "KibanaSampleDataEcommerce.maxPrice".to_string(),
+                    "KibanaSampleDataEcommerce.minPrice".to_string(),
+                    "KibanaSampleDataEcommerce.avgPrice".to_string(),
+                ]),
+                segments: Some(vec![]),
+                dimensions: Some(vec![]),
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            }
+        );
+
+        assert_eq!(
+            logical_plan
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.data_type().clone())
+                .collect::<Vec<_>>(),
+            vec![DataType::Float64, DataType::Float64, DataType::Float64]
+        );
+    }
+
+    #[test]
+    fn test_order_alias_for_measure_default() {
+        let query_plan = convert_select_to_query_plan(
+            "SELECT COUNT(*) as cnt FROM KibanaSampleDataEcommerce ORDER BY cnt".to_string(),
+            DatabaseProtocol::MySQL,
+        );
 
         assert_eq!(
             query_plan.as_logical_plan().find_cube_scan().request,
@@ -2809,7 +3888,8 @@ mod tests {
                 ]]),
                 limit: None,
                 offset: None,
-                filters: None
+                filters: None,
+                ungrouped: None,
             }
         )
     }
@@ -2833,7 +3913,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    ungrouped: None,
                 }
             ),
             // test_order_indentifier_default
@@ -2852,7 +3933,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    ungrouped: None,
                 }
             ),
             // test_order_compound_identifier_default
@@ -2871,7 +3953,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    ungrouped: None,
                 }
             ),
             // test_order_indentifier_asc
@@ -2890,7 +3973,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    ungrouped: None,
                 }
             ),
             // test_order_indentifier_desc
@@ -2909,7 +3993,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    ungrouped: None,
                 }
             ),
             // test_order_identifer_alias_ident_no_escape
@@ -2928,7 +4013,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    ungrouped: None,
                 }
             ),
             // test_order_identifer_alias_ident_escape
@@ -2947,7 +4033,29 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    ungrouped: None,
+                }
+            ),
+            // test_order_ordinal_desc
+            (
+                "SELECT customer_gender, taxful_total_price FROM KibanaSampleDataEcommerce ORDER BY 2 DESC".to_string(),
+                V1LoadRequestQuery {
+                    measures: Some(vec![]),
+                    segments: Some(vec![]),
+                    dimensions: Some(vec![
+                        "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                        "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+                    ]),
+                    time_dimensions: None,
+                    order: Some(vec![vec![
+                        "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+                        "desc".to_string(),
+                    ]]),
+                    limit: None,
+                    offset: None,
+                    filters: None,
+                    ungrouped: None,
                 }
             ),
         ];
@@ -2989,7 +4097,8 @@ mod tests {
                 ]]),
                 limit: None,
                 offset: None,
-                filters: None
+                filters: None,
+                ungrouped: None,
             }
         );
 
@@ -3016,7 +4125,8 @@ mod tests {
                 ]]),
                 limit: None,
                 offset: None,
-                filters: None
+                filters: None,
+                ungrouped: None,
             }
         );
     }
@@ -3084,6 +4194,7 @@ mod tests {
                 limit: None,
                 offset: None,
                 filters: None,
+                ungrouped: None,
             }
         )
     }
@@ -3110,6 +4221,7 @@ mod tests {
                 limit: None,
                 offset: None,
                 filters: None,
+                ungrouped: None,
             }
         );
 
@@ -3158,74 +4270,74 @@ mod tests {
                 limit: Some(1),
                 offset: None,
                 filters: None,
+                ungrouped: None,
             }
         );
     }
 
     #[test]
-    fn tableau_min_max() {
+    fn test_order_by_post_aggregation_expression() {
         init_logger();
 
         let query_plan = convert_select_to_query_plan(
-            "SELECT MIN(\"KibanaSampleDataEcommerce\".\"order_date\") AS \"tmn:timestamp:min\", MAX(\"KibanaSampleDataEcommerce\".\"order_date\") AS \"tmn:timestamp:max\"\nFROM \"public\".\"KibanaSampleDataEcommerce\" \"KibanaSampleDataEcommerce\"".to_string(),
+            "SELECT customer_gender, SUM(count) / MAX(maxPrice) AS ratio FROM KibanaSampleDataEcommerce WHERE customer_gender <> 'unknown' GROUP BY 1 ORDER BY ratio DESC LIMIT 10".to_string(),
             DatabaseProtocol::PostgreSQL,
         );
 
         let logical_plan = query_plan.as_logical_plan();
+        // Members and filters are pushed down, order and limit are applied locally
         assert_eq!(
             logical_plan.find_cube_scan().request,
             V1LoadRequestQuery {
-                measures: Some(vec![]),
+                measures: Some(vec![
+                    "KibanaSampleDataEcommerce.count".to_string(),
+                    "KibanaSampleDataEcommerce.maxPrice".to_string(),
+                ]),
                 segments: Some(vec![]),
-                dimensions: Some(vec![]),
-                time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
-                    dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
-                    granularity: Some("month".to_string()),
-                    date_range: None,
-                }]),
+                dimensions: Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]),
+                time_dimensions: None,
                 order: None,
                 limit: None,
                 offset: None,
-                filters: None,
+                filters: Some(vec![V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                    operator: Some("notEquals".to_string()),
+                    values: Some(vec!["unknown".to_string()]),
+                    or: None,
+                    and: None,
+                }]),
+                ungrouped: None,
             }
         );
-    }
 
-    #[test]
-    fn tableau_group_by_month() {
-        init_logger();
+        let plan = logical_plan.display_indent().to_string();
+        let nodes = plan.lines().map(|line| line.trim()).collect::<Vec<_>>();
+        assert!(nodes[0].starts_with("Limit: 10"), "{}", plan);
+        assert!(nodes[1].starts_with("Sort: #ratio DESC"), "{}", plan);
+        assert!(
+            nodes.iter().any(|node| node.starts_with("CubeScan")),
+            "{}",
+            plan
+        );
 
+        // Sort by raw member alongside the expression is not pushed down partially
         let query_plan = convert_select_to_query_plan(
-            "SELECT COUNT(\"KibanaSampleDataEcommerce\".\"count\") AS \"sum:bytesBilled:ok\",\n  DATE_TRUNC( 'MONTH', CAST(\"KibanaSampleDataEcommerce\".\"order_date\" AS TIMESTAMP) ) AS \"tmn:timestamp:ok\"\nFROM \"public\".\"KibanaSampleDataEcommerce\" \"KibanaSampleDataEcommerce\"\nGROUP BY 2".to_string(),
+            "SELECT customer_gender, SUM(count) / MAX(maxPrice) AS ratio FROM KibanaSampleDataEcommerce GROUP BY 1 ORDER BY customer_gender, ratio DESC LIMIT 10".to_string(),
             DatabaseProtocol::PostgreSQL,
         );
 
         let logical_plan = query_plan.as_logical_plan();
-        assert_eq!(
-            logical_plan.find_cube_scan().request,
-            V1LoadRequestQuery {
-                measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string(),]),
-                segments: Some(vec![]),
-                dimensions: Some(vec![]),
-                time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
-                    dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
-                    granularity: Some("month".to_string()),
-                    date_range: None,
-                }]),
-                order: None,
-                limit: None,
-                offset: None,
-                filters: None,
-            }
-        );
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(request.order, None);
+        assert_eq!(request.limit, None);
     }
 
     #[test]
-    fn tableau_group_by_month_and_dimension() {
+    fn test_order_by_limit_pushdown() {
         init_logger();
 
         let query_plan = convert_select_to_query_plan(
-            "SELECT CAST(\"KibanaSampleDataEcommerce\".\"customer_gender\" AS TEXT) AS \"query\",\n  SUM(\"KibanaSampleDataEcommerce\".\"count\") AS \"sum:bytesBilled:ok\"\nFROM \"public\".\"KibanaSampleDataEcommerce\" \"KibanaSampleDataEcommerce\"\nGROUP BY 1".to_string(),
+            "SELECT customer_gender, COUNT(*) AS cnt FROM KibanaSampleDataEcommerce GROUP BY 1 ORDER BY cnt DESC LIMIT 100".to_string(),
             DatabaseProtocol::PostgreSQL,
         );
 
@@ -3233,44 +4345,414 @@ mod tests {
         assert_eq!(
             logical_plan.find_cube_scan().request,
             V1LoadRequestQuery {
-                measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string(),]),
+                measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string()]),
                 segments: Some(vec![]),
                 dimensions: Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]),
                 time_dimensions: None,
-                order: None,
-                limit: None,
+                order: Some(vec![vec![
+                    "KibanaSampleDataEcommerce.count".to_string(),
+                    "desc".to_string(),
+                ]]),
+                limit: Some(100),
                 offset: None,
                 filters: None,
+                ungrouped: None,
             }
         );
+
+        let plan = logical_plan.display_indent().to_string();
+        assert!(
+            plan.lines()
+                .map(|line| line.trim())
+                .all(|node| !node.starts_with("Limit") && !node.starts_with("Sort")),
+            "{}",
+            plan
+        );
+
+        // Limit without order
+        let query_plan = convert_select_to_query_plan(
+            "SELECT customer_gender FROM KibanaSampleDataEcommerce GROUP BY 1 LIMIT 50".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        );
+
+        let request = query_plan.as_logical_plan().find_cube_scan().request;
+        assert_eq!(request.order, None);
+        assert_eq!(request.limit, Some(50));
     }
 
-    #[test]
-    fn tableau_extract_year() {
+    #[tokio::test]
+    async fn test_order_by_ordinal() -> Result<(), CubeError> {
         init_logger();
 
         let query_plan = convert_select_to_query_plan(
-            "SELECT CAST(TRUNC(EXTRACT(YEAR FROM \"KibanaSampleDataEcommerce\".\"order_date\")) AS INTEGER) AS \"yr:timestamp:ok\"\nFROM \"public\".\"KibanaSampleDataEcommerce\" \"KibanaSampleDataEcommerce\"\nGROUP BY 1".to_string(),
+            "SELECT customer_gender, COUNT(*) AS cnt FROM KibanaSampleDataEcommerce GROUP BY 1 ORDER BY 2 DESC, 1".to_string(),
             DatabaseProtocol::PostgreSQL,
         );
 
-        let logical_plan = query_plan.as_logical_plan();
         assert_eq!(
-            logical_plan.find_cube_scan().request,
-            V1LoadRequestQuery {
-                measures: Some(vec![]),
-                segments: Some(vec![]),
-                dimensions: Some(vec![]),
-                time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
-                    dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
-                    granularity: Some("year".to_string()),
-                    date_range: None,
-                }]),
-                order: None,
-                limit: None,
-                offset: None,
-                filters: None,
-            }
+            query_plan.as_logical_plan().find_cube_scan().request.order,
+            Some(vec![
+                vec![
+                    "KibanaSampleDataEcommerce.count".to_string(),
+                    "desc".to_string(),
+                ],
+                vec![
+                    "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                    "asc".to_string(),
+                ],
+            ])
+        );
+
+        insta::assert_snapshot!(
+            "order_by_ordinal",
+            execute_query(
+                "SELECT * FROM (SELECT 1 AS a, 'c' AS b UNION ALL SELECT 2, 'a' UNION ALL SELECT 3, 'b') t ORDER BY 2 DESC".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by_ordinal() {
+        init_logger();
+
+        for db in [DatabaseProtocol::MySQL, DatabaseProtocol::PostgreSQL] {
+            let query_plan = convert_select_to_query_plan(
+                "SELECT customer_gender, taxful_total_price, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1, 2".to_string(),
+                db.clone(),
+            );
+
+            let request = query_plan.as_logical_plan().find_cube_scan().request;
+            assert_eq!(
+                request.measures,
+                Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+            );
+            assert_eq!(
+                request.dimensions,
+                Some(vec![
+                    "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                    "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+                ])
+            );
+        }
+
+        let result = convert_sql_to_cube_query(
+            &"SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 3"
+                .to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::MySQL),
+        );
+        match result {
+            Err(CompilationError::User(message)) => {
+                assert_eq!(message, "GROUP BY position 3 is not in select list")
+            }
+            _ => panic!("GROUP BY position out of the select list must be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_having_filter_on_measure() {
+        init_logger();
+
+        let filter = |member: &str, operator: &str, value: &str| V1LoadRequestQueryFilterItem {
+            member: Some(member.to_string()),
+            operator: Some(operator.to_string()),
+            values: Some(vec![value.to_string()]),
+            or: None,
+            and: None,
+        };
+
+        for (query, expected_filters) in [
+            (
+                "SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce \
+                GROUP BY customer_gender HAVING COUNT(*) > 10",
+                vec![filter("KibanaSampleDataEcommerce.count", "gt", "10")],
+            ),
+            // Alias of the projection and measure which is not in the projection
+            (
+                "SELECT customer_gender, COUNT(*) AS cnt FROM KibanaSampleDataEcommerce \
+                GROUP BY 1 HAVING cnt <= 100 AND MEASURE(maxPrice) > 5",
+                vec![
+                    filter("KibanaSampleDataEcommerce.count", "lte", "100"),
+                    filter("KibanaSampleDataEcommerce.maxPrice", "gt", "5"),
+                ],
+            ),
+            // Filters of WHERE are kept
+            (
+                "SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce \
+                WHERE customer_gender = 'female' GROUP BY 1 HAVING COUNT(*) >= 3",
+                vec![
+                    filter(
+                        "KibanaSampleDataEcommerce.customer_gender",
+                        "equals",
+                        "female",
+                    ),
+                    filter("KibanaSampleDataEcommerce.count", "gte", "3"),
+                ],
+            ),
+        ] {
+            let query_plan =
+                convert_select_to_query_plan(query.to_string(), DatabaseProtocol::MySQL);
+
+            assert_eq!(
+                query_plan
+                    .as_logical_plan()
+                    .find_cube_scan()
+                    .request
+                    .filters,
+                Some(expected_filters),
+                "{}",
+                query
+            );
+        }
+
+        // HAVING of the rewrite engine is pushed down too or applied to the result of aggregation
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce \
+            GROUP BY customer_gender HAVING COUNT(*) > 10"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(
+            request.measures,
+            Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+        );
+        assert!(
+            request.filters == Some(vec![filter("KibanaSampleDataEcommerce.count", "gt", "10")])
+                || format!("{:?}", logical_plan).contains("Filter:"),
+            "{:?}",
+            logical_plan
+        );
+    }
+
+    #[test]
+    fn test_ungrouped_select() {
+        init_logger();
+
+        let mut configuration = ServerConfiguration::default();
+        configuration.ungrouped_queries = true;
+        let session = get_test_session_with_config(DatabaseProtocol::PostgreSQL, configuration);
+        let plan = |query: &str| {
+            convert_sql_to_cube_query(&query.to_string(), get_test_tenant_ctx(), session.clone())
+                .unwrap()
+        };
+
+        let query_plan = plan(
+            "SELECT customer_gender, taxful_total_price FROM KibanaSampleDataEcommerce \
+            WHERE customer_gender = 'female' ORDER BY customer_gender LIMIT 500",
+        );
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec![]),
+                segments: Some(vec![]),
+                dimensions: Some(vec![
+                    "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                    "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+                ]),
+                time_dimensions: None,
+                order: Some(vec![vec![
+                    "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                    "asc".to_string(),
+                ]]),
+                limit: Some(500),
+                offset: None,
+                filters: Some(vec![V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                    operator: Some("equals".to_string()),
+                    values: Some(vec!["female".to_string()]),
+                    or: None,
+                    and: None,
+                }]),
+                ungrouped: Some(true),
+            }
+        );
+
+        let plan_text = logical_plan.display_indent().to_string();
+        assert!(
+            plan_text
+                .lines()
+                .map(|line| line.trim())
+                .all(|node| !node.starts_with("Limit") && !node.starts_with("Sort")),
+            "{}",
+            plan_text
+        );
+
+        // Aggregation is done by Cube as before
+        let request =
+            plan("SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1")
+                .as_logical_plan()
+                .find_cube_scan()
+                .request;
+        assert_eq!(request.ungrouped, None);
+
+        // Ungrouped queries are disabled by default
+        let request = convert_select_to_query_plan(
+            "SELECT customer_gender FROM KibanaSampleDataEcommerce".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan()
+        .find_cube_scan()
+        .request;
+        assert_eq!(
+            request.dimensions,
+            Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()])
+        );
+        assert_eq!(request.ungrouped, None);
+    }
+
+    #[test]
+    fn tableau_min_max() {
+        init_logger();
+
+        let query_plan = convert_select_to_query_plan(
+            "SELECT MIN(\"KibanaSampleDataEcommerce\".\"order_date\") AS \"tmn:timestamp:min\", MAX(\"KibanaSampleDataEcommerce\".\"order_date\") AS \"tmn:timestamp:max\"\nFROM \"public\".\"KibanaSampleDataEcommerce\" \"KibanaSampleDataEcommerce\"".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        );
+
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec![]),
+                segments: Some(vec![]),
+                dimensions: Some(vec![]),
+                time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
+                    dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                    granularity: Some("month".to_string()),
+                    date_range: None,
+                }]),
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            }
+        );
+    }
+
+    #[test]
+    fn tableau_group_by_month() {
+        init_logger();
+
+        let query_plan = convert_select_to_query_plan(
+            "SELECT COUNT(\"KibanaSampleDataEcommerce\".\"count\") AS \"sum:bytesBilled:ok\",\n  DATE_TRUNC( 'MONTH', CAST(\"KibanaSampleDataEcommerce\".\"order_date\" AS TIMESTAMP) ) AS \"tmn:timestamp:ok\"\nFROM \"public\".\"KibanaSampleDataEcommerce\" \"KibanaSampleDataEcommerce\"\nGROUP BY 2".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        );
+
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string(),]),
+                segments: Some(vec![]),
+                dimensions: Some(vec![]),
+                time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
+                    dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                    granularity: Some("month".to_string()),
+                    date_range: None,
+                }]),
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_date_trunc_granularity_pushdown_postgres() {
+        init_logger();
+
+        for (field, granularity) in [
+            ("month", "month"),
+            ("MONTH", "month"),
+            ("weeks", "week"),
+            ("qtr", "quarter"),
+            ("Day", "day"),
+        ] {
+            let query_plan = convert_select_to_query_plan(
+                format!(
+                    "SELECT date_trunc('{}', order_date) AS d, COUNT(*) AS cnt FROM KibanaSampleDataEcommerce GROUP BY 1",
+                    field
+                ),
+                DatabaseProtocol::PostgreSQL,
+            );
+
+            let request = query_plan.as_logical_plan().find_cube_scan().request;
+            assert_eq!(
+                serde_json::to_value(&request).unwrap()["timeDimensions"],
+                json!([{
+                    "dimension": "KibanaSampleDataEcommerce.order_date",
+                    "granularity": granularity,
+                }]),
+                "date_trunc('{}', ...)",
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn tableau_group_by_month_and_dimension() {
+        init_logger();
+
+        let query_plan = convert_select_to_query_plan(
+            "SELECT CAST(\"KibanaSampleDataEcommerce\".\"customer_gender\" AS TEXT) AS \"query\",\n  SUM(\"KibanaSampleDataEcommerce\".\"count\") AS \"sum:bytesBilled:ok\"\nFROM \"public\".\"KibanaSampleDataEcommerce\" \"KibanaSampleDataEcommerce\"\nGROUP BY 1".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        );
+
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec!["KibanaSampleDataEcommerce.count".to_string(),]),
+                segments: Some(vec![]),
+                dimensions: Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()]),
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            }
+        );
+    }
+
+    #[test]
+    fn tableau_extract_year() {
+        init_logger();
+
+        let query_plan = convert_select_to_query_plan(
+            "SELECT CAST(TRUNC(EXTRACT(YEAR FROM \"KibanaSampleDataEcommerce\".\"order_date\")) AS INTEGER) AS \"yr:timestamp:ok\"\nFROM \"public\".\"KibanaSampleDataEcommerce\" \"KibanaSampleDataEcommerce\"\nGROUP BY 1".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        );
+
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec![]),
+                segments: Some(vec![]),
+                dimensions: Some(vec![]),
+                time_dimensions: Some(vec![V1LoadRequestQueryTimeDimension {
+                    dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                    granularity: Some("year".to_string()),
+                    date_range: None,
+                }]),
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                ungrouped: None,
+            }
         );
 
         let query_plan = convert_select_to_query_plan(
@@ -3294,6 +4776,7 @@ mod tests {
                 limit: None,
                 offset: None,
                 filters: None,
+                ungrouped: None,
             }
         );
     }
@@ -3325,6 +4808,7 @@ mod tests {
                     or: None,
                     and: None,
                 }]),
+                ungrouped: None,
             }
         );
     }
@@ -3343,6 +4827,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    ungrouped: None,
                 },
             ),
             (
@@ -3356,6 +4841,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    ungrouped: None,
                 },
             ),
             (
@@ -3369,6 +4855,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    ungrouped: None,
                 },
             ),
             (
@@ -3382,6 +4869,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    ungrouped: None,
                 },
             ),
             (
@@ -3395,6 +4883,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    ungrouped: None,
                 },
             ),
             (
@@ -3408,6 +4897,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    ungrouped: None,
                 },
             ),
             (
@@ -3421,6 +4911,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    ungrouped: None,
                 },
             ),
         ];
@@ -3562,7 +5053,8 @@ mod tests {
                     order: None,
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    ungrouped: None,
                 }
             );
 
@@ -3635,7 +5127,8 @@ mod tests {
                     order: None,
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    ungrouped: None,
                 }
             )
         }
@@ -3774,20 +5267,145 @@ mod tests {
     }
 
     #[test]
-    fn test_where_filter_or() {
-        let query_plan = convert_select_to_query_plan(
-            "SELECT
-                COUNT(*), DATE(order_date) AS __timestamp
-                FROM KibanaSampleDataEcommerce
-                WHERE order_date >= STR_TO_DATE('2021-08-31 00:00:00.000000', '%Y-%m-%d %H:%i:%s.%f') OR order_date < STR_TO_DATE('2021-09-07 00:00:00.000000', '%Y-%m-%d %H:%i:%s.%f')
-                GROUP BY __timestamp"
-            .to_string(), DatabaseProtocol::MySQL
-        );
+    fn test_where_filter_pushdown_with_residual() {
+        init_logger();
+
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT COUNT(*), customer_gender
+            FROM KibanaSampleDataEcommerce
+            WHERE customer_gender IN ('female', 'male')
+                AND order_date BETWEEN STR_TO_DATE('2021-08-31 00:00:00.000000', '%Y-%m-%d %H:%i:%s.%f') AND STR_TO_DATE('2021-09-07 00:00:00.000000', '%Y-%m-%d %H:%i:%s.%f')
+                AND taxful_total_price BETWEEN 5 AND 100
+            GROUP BY customer_gender"
+                .to_string(),
+            DatabaseProtocol::MySQL,
+        )
+        .as_logical_plan();
 
+        let request = logical_plan.find_cube_scan().request;
         assert_eq!(
-            query_plan
-                .as_logical_plan()
-                .find_cube_scan()
+            request.filters,
+            Some(vec![
+                V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                    operator: Some("equals".to_string()),
+                    values: Some(vec!["female".to_string(), "male".to_string()]),
+                    or: None,
+                    and: None,
+                },
+                V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.taxful_total_price".to_string()),
+                    operator: Some("gte".to_string()),
+                    values: Some(vec!["5".to_string()]),
+                    or: None,
+                    and: None,
+                },
+                V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.taxful_total_price".to_string()),
+                    operator: Some("lte".to_string()),
+                    values: Some(vec!["100".to_string()]),
+                    or: None,
+                    and: None,
+                },
+            ])
+        );
+        assert_eq!(
+            request.time_dimensions,
+            Some(vec![V1LoadRequestQueryTimeDimension {
+                dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                granularity: None,
+                date_range: Some(json!(vec![
+                    "2021-08-31T00:00:00.000Z".to_string(),
+                    "2021-09-07T00:00:00.000Z".to_string()
+                ])),
+            }])
+        );
+        assert!(!format!("{:?}", logical_plan).contains("Filter:"));
+
+        // Predicates which can't be translated to Cube filters are evaluated locally
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT COUNT(*), customer_gender
+            FROM KibanaSampleDataEcommerce
+            WHERE customer_gender = 'female' AND LOWER(customer_gender) LIKE 'f%'
+            GROUP BY customer_gender"
+                .to_string(),
+            DatabaseProtocol::MySQL,
+        )
+        .as_logical_plan();
+
+        assert_eq!(
+            logical_plan.find_cube_scan().request.filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                operator: Some("equals".to_string()),
+                values: Some(vec!["female".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+        assert!(format!("{:?}", logical_plan).contains("Filter: lower("));
+    }
+
+    #[test]
+    fn test_where_filter_any_array_param() {
+        init_logger();
+
+        let cases = vec![
+            ("customer_gender = ANY($1)", "equals"),
+            ("customer_gender <> ALL($1)", "notEquals"),
+        ];
+        for (predicate, operator) in cases.iter() {
+            let mut stmt = parse_sql_to_statement(
+                &format!(
+                    "SELECT COUNT(*), customer_gender
+                    FROM KibanaSampleDataEcommerce
+                    WHERE {}
+                    GROUP BY customer_gender",
+                    predicate
+                ),
+                DatabaseProtocol::PostgreSQL,
+            )
+            .unwrap();
+            let value =
+                BindValue::decode(b"{female,male}", PgTypeId::ArrayText, Format::Text).unwrap();
+            StatementParamsBinder::new(vec![value]).bind(&mut stmt);
+
+            let logical_plan = convert_statement_to_cube_query(
+                &stmt,
+                get_test_tenant_ctx(),
+                get_test_session(DatabaseProtocol::PostgreSQL),
+            )
+            .unwrap()
+            .as_logical_plan();
+
+            assert_eq!(
+                logical_plan.find_cube_scan().request.filters,
+                Some(vec![V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                    operator: Some(operator.to_string()),
+                    values: Some(vec!["female".to_string(), "male".to_string()]),
+                    or: None,
+                    and: None,
+                }])
+            );
+        }
+    }
+
+    #[test]
+    fn test_where_filter_or() {
+        let query_plan = convert_select_to_query_plan(
+            "SELECT
+                COUNT(*), DATE(order_date) AS __timestamp
+                FROM KibanaSampleDataEcommerce
+                WHERE order_date >= STR_TO_DATE('2021-08-31 00:00:00.000000', '%Y-%m-%d %H:%i:%s.%f') OR order_date < STR_TO_DATE('2021-09-07 00:00:00.000000', '%Y-%m-%d %H:%i:%s.%f')
+                GROUP BY __timestamp"
+            .to_string(), DatabaseProtocol::MySQL
+        );
+
+        assert_eq!(
+            query_plan
+                .as_logical_plan()
+                .find_cube_scan()
                 .request
                 .filters,
             Some(vec![V1LoadRequestQueryFilterItem {
@@ -4149,6 +5767,15 @@ mod tests {
                 "order_date BETWEEN '2021-01-01' AND 'WRONG_DATE'".to_string(),
                 CompilationError::User("Unable to compare time dimension \"order_date\" with not a date value: WRONG_DATE".to_string()),
             ),
+            // Aggregates are filtered by HAVING only
+            (
+                "COUNT(*) > 10".to_string(),
+                CompilationError::User("aggregate functions are not allowed in WHERE".to_string()),
+            ),
+            (
+                "customer_gender = 'female' AND MEASURE(maxPrice) > 5".to_string(),
+                CompilationError::User("aggregate functions are not allowed in WHERE".to_string()),
+            ),
         ];
 
         for (sql, expected_error) in to_check.iter() {
@@ -4555,7 +6182,7 @@ mod tests {
             QueryPlan::MetaTabular(flags, frame) => {
                 return Ok((frame.print(), flags));
             }
-            QueryPlan::MetaOk(flags, _) => {
+            QueryPlan::MetaOk(flags, _) | QueryPlan::MetaChange(flags, _, _) => {
                 return Ok(("".to_string(), flags));
             }
         }
@@ -5277,6 +6904,741 @@ mod tests {
         Ok(())
     }
 
+    fn query_pg_types(query: &str) -> Vec<PgTypeId> {
+        let plan = convert_sql_to_cube_query(
+            &query.to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        )
+        .unwrap();
+
+        match plan {
+            QueryPlan::DataFusionSelect(_, plan, _) => plan
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| df_type_to_pg_tid(field.data_type()).unwrap())
+                .collect(),
+            _ => panic!("Unexpected plan for {}", query),
+        }
+    }
+
+    #[test]
+    fn test_case_type_postgres() {
+        // Type of CASE is the unified type of its branches, it's reported by RowDescription
+        assert_eq!(
+            query_pg_types(
+                "SELECT CASE WHEN 1 = 1 THEN 1 ELSE 2 END AS i, CASE WHEN 1 = 1 THEN 'a' ELSE 'b' END AS s, CASE WHEN 1 = 1 THEN true ELSE false END AS b"
+            ),
+            vec![PgTypeId::INT8, PgTypeId::TEXT, PgTypeId::BOOL]
+        );
+        assert_eq!(
+            query_pg_types(
+                "SELECT CASE WHEN 1 = 1 THEN 1 END AS i, CASE WHEN 1 = 1 THEN 1 ELSE NULL END AS n"
+            ),
+            vec![PgTypeId::INT8, PgTypeId::INT8]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_null_literal_postgres() -> Result<(), CubeError> {
+        assert_eq!(query_pg_types("SELECT NULL AS x"), vec![PgTypeId::TEXT]);
+        assert_eq!(
+            query_pg_types(
+                "SELECT CAST(NULL AS timestamp) AS t, CAST(NULL AS int) AS i, CAST(NULL AS boolean) AS b"
+            ),
+            vec![PgTypeId::TIMESTAMP, PgTypeId::INT4, PgTypeId::BOOL]
+        );
+        // NULL takes the type unified from the other branches, it's text only if all of them are NULL
+        assert_eq!(
+            query_pg_types("SELECT NULL AS x UNION ALL SELECT 1 AS x"),
+            vec![PgTypeId::INT8]
+        );
+        assert_eq!(
+            query_pg_types("SELECT 1 AS x UNION ALL SELECT NULL AS x"),
+            vec![PgTypeId::INT8]
+        );
+        assert_eq!(
+            query_pg_types("SELECT NULL AS x UNION ALL SELECT NULL AS x"),
+            vec![PgTypeId::TEXT]
+        );
+        assert_eq!(
+            query_pg_types(
+                "SELECT CASE WHEN 1 = 1 THEN NULL ELSE 1 END AS i, CASE WHEN 1 = 1 THEN NULL ELSE 'a' END AS s, CASE WHEN 1 = 1 THEN NULL END AS n"
+            ),
+            vec![PgTypeId::INT8, PgTypeId::TEXT, PgTypeId::TEXT]
+        );
+        assert_eq!(
+            query_pg_types("SELECT COALESCE(NULL, 1) AS i, COALESCE(NULL, NULL) AS n"),
+            vec![PgTypeId::INT8, PgTypeId::TEXT]
+        );
+
+        insta::assert_snapshot!(
+            "null_union_postgres",
+            execute_query(
+                "SELECT x FROM (SELECT NULL AS x UNION ALL SELECT 1 AS x) t ORDER BY x NULLS LAST;"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+        insta::assert_snapshot!(
+            "null_case_postgres",
+            execute_query(
+                "SELECT CASE WHEN 1 = 1 THEN NULL ELSE 1 END AS i, CASE WHEN 1 = 2 THEN NULL ELSE 1 END AS j, COALESCE(NULL, 1) AS c;"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        insta::assert_snapshot!(
+            "null_literal_postgres",
+            execute_query(
+                "SELECT NULL AS x, CAST(NULL AS timestamp) AS t;".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sql_macro_pushdown() {
+        init_logger();
+
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let plan = |query: &str| {
+            convert_sql_to_cube_query(&query.to_string(), get_test_tenant_ctx(), session.clone())
+        };
+        let execute = |query: &str| execute_session_query(query, &session);
+
+        let definition = "CREATE FUNCTION gender_is(gender text, value text) RETURNS boolean AS $$ SELECT gender = value $$ LANGUAGE SQL";
+        match plan(definition) {
+            Ok(QueryPlan::MetaChange(_, CommandCompletion::CreateFunction, _)) => {}
+            _ => panic!("CREATE FUNCTION must be planned as MetaChange"),
+        }
+        // Function is created by execution, not by planning
+        assert!(session.state.sql_macros().is_empty());
+
+        execute(definition).unwrap();
+        match execute(definition) {
+            Err(err) => assert_eq!(err.message, "function \"gender_is\" already exists"),
+            _ => panic!("Function with the same name can not be created twice"),
+        }
+
+        let logical_plan = plan(
+            "SELECT COUNT(*) FROM KibanaSampleDataEcommerce WHERE gender_is(customer_gender, 'FEMALE')",
+        )
+        .unwrap()
+        .as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request.filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                operator: Some("equals".to_string()),
+                values: Some(vec!["FEMALE".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+
+        match execute("DROP FUNCTION gender_is(text, text)") {
+            Ok(QueryPlan::MetaOk(_, CommandCompletion::DropFunction)) => {}
+            _ => panic!("DROP FUNCTION must be planned as MetaChange"),
+        }
+        match execute("DROP FUNCTION gender_is") {
+            Err(err) => assert_eq!(err.message, "function gender_is does not exist"),
+            _ => panic!("Function was already dropped"),
+        }
+        execute("DROP FUNCTION IF EXISTS gender_is").unwrap();
+    }
+
+    #[test]
+    fn test_sql_macro_recursion() {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let plan = |query: &str| {
+            convert_sql_to_cube_query(&query.to_string(), get_test_tenant_ctx(), session.clone())
+        };
+
+        execute_session_query(
+            "CREATE FUNCTION f1(a int) RETURNS int AS $$ SELECT f2(a) + 1 $$ LANGUAGE SQL",
+            &session,
+        )
+        .unwrap();
+
+        match plan("CREATE FUNCTION f2(a int) RETURNS int AS $$ SELECT f1(a) $$ LANGUAGE SQL") {
+            Err(CompilationError::Unsupported(_)) => {}
+            _ => panic!("Recursive function must be rejected"),
+        }
+        match plan("CREATE FUNCTION f3(a int) RETURNS SETOF int AS $$ SELECT a $$ LANGUAGE SQL") {
+            Err(CompilationError::Unsupported(_)) => {}
+            _ => panic!("Set-returning function must be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_noop_dml_affected_rows() {
+        let mut configuration = ServerConfiguration::default();
+        configuration.noop_dml = true;
+
+        let session = get_test_session_with_config(DatabaseProtocol::PostgreSQL, configuration);
+        let plan = |query: &str| {
+            convert_sql_to_cube_query(&query.to_string(), get_test_tenant_ctx(), session.clone())
+        };
+
+        for (query, expected_tag) in vec![
+            (
+                "UPDATE KibanaSampleDataEcommerce SET customer_gender = 'male' WHERE count > 0",
+                "UPDATE 0",
+            ),
+            (
+                "INSERT INTO KibanaSampleDataEcommerce (customer_gender) VALUES ('male')",
+                "INSERT 0 0",
+            ),
+            ("DELETE FROM KibanaSampleDataEcommerce", "DELETE 0"),
+        ] {
+            match plan(query).unwrap() {
+                QueryPlan::MetaOk(_, completion) => match completion.to_pg_command() {
+                    pg_srv::protocol::CommandComplete::Plain(tag) => assert_eq!(tag, expected_tag),
+                    _ => panic!("Unexpected command tag for: {}", query),
+                },
+                _ => panic!("No-op DML must be planned as MetaOk: {}", query),
+            }
+        }
+
+        // Disabled by default
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        match convert_sql_to_cube_query(
+            &"UPDATE KibanaSampleDataEcommerce SET customer_gender = 'male'".to_string(),
+            get_test_tenant_ctx(),
+            session,
+        ) {
+            Err(CompilationError::Unsupported(_)) => {}
+            _ => panic!("DML must be rejected without no-op mode"),
+        }
+    }
+
+    #[test]
+    fn test_max_query_expressions() {
+        let mut configuration = ServerConfiguration::default();
+        configuration.max_query_expressions = 100;
+
+        let session = get_test_session_with_config(DatabaseProtocol::PostgreSQL, configuration);
+        let plan = |n: usize| {
+            let projection = (0..n)
+                .map(|i| format!("{} AS c{}", i, i))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            convert_sql_to_cube_query(
+                &format!("SELECT {}", projection),
+                get_test_tenant_ctx(),
+                session.clone(),
+            )
+        };
+
+        plan(100).expect("Query within the limit must be planned");
+
+        match plan(101) {
+            Err(CompilationError::ProgramLimitExceeded(message)) => assert_eq!(
+                message,
+                "Query contains too many expressions: 101, maximum allowed is 100"
+            ),
+            _ => panic!("Query over the limit must be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_max_subquery_depth() {
+        let mut configuration = ServerConfiguration::default();
+        configuration.max_subquery_depth = 3;
+
+        let session = get_test_session_with_config(DatabaseProtocol::PostgreSQL, configuration);
+        let plan = |depth: usize| {
+            let query = (0..depth).fold(
+                "SELECT customer_gender FROM KibanaSampleDataEcommerce".to_string(),
+                |query, i| format!("SELECT * FROM ({}) AS q{}", query, i),
+            );
+
+            convert_sql_to_cube_query(&query, get_test_tenant_ctx(), session.clone())
+        };
+
+        // Query within the limit may still fail for other reasons
+        assert!(!matches!(
+            plan(3),
+            Err(CompilationError::StatementTooComplex(_))
+        ));
+
+        match plan(4) {
+            Err(CompilationError::StatementTooComplex(message)) => assert_eq!(
+                message,
+                "Subqueries are nested too deeply, maximum allowed depth is 3"
+            ),
+            _ => panic!("Query over the limit must be rejected"),
+        }
+
+        // Subqueries of expressions are nested too
+        match convert_sql_to_cube_query(
+            &"SELECT * FROM KibanaSampleDataEcommerce WHERE customer_gender IN \
+            (SELECT (SELECT MAX(customer_gender) FROM (SELECT * FROM (SELECT customer_gender \
+            FROM KibanaSampleDataEcommerce) a) b))"
+                .to_string(),
+            get_test_tenant_ctx(),
+            session.clone(),
+        ) {
+            Err(CompilationError::StatementTooComplex(_)) => {}
+            _ => panic!("Query over the limit must be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_lateral_subquery_is_rejected() {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let plan = |query: &str| {
+            convert_sql_to_cube_query(&query.to_string(), get_test_tenant_ctx(), session.clone())
+        };
+
+        match plan(
+            "SELECT k.customer_gender, l.cnt FROM KibanaSampleDataEcommerce k \
+            CROSS JOIN LATERAL (SELECT COUNT(*) AS cnt FROM KibanaSampleDataEcommerce) AS l",
+        ) {
+            Err(CompilationError::Unsupported(message)) => assert_eq!(
+                message,
+                "LATERAL subqueries are not supported: l. Rewrite the query with a regular JOIN \
+                to the subquery or with a scalar subquery in the projection"
+            ),
+            _ => panic!("LATERAL subquery must be rejected"),
+        }
+
+        // Nested into the derived table
+        match plan(
+            "SELECT * FROM (SELECT * FROM KibanaSampleDataEcommerce k, \
+            LATERAL (SELECT 1 AS one) x) t",
+        ) {
+            Err(CompilationError::Unsupported(message)) => {
+                assert!(message.starts_with("LATERAL subqueries are not supported: x."))
+            }
+            _ => panic!("LATERAL subquery must be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_pg_set_transaction_characteristics() {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let plan = |query: &str| {
+            convert_sql_to_cube_query(&query.to_string(), get_test_tenant_ctx(), session.clone())
+        };
+
+        // Sequence sent by pgjdbc for setReadOnly(true) and setAutoCommit(false)
+        for (query, expected_tag) in vec![
+            (
+                "SET SESSION CHARACTERISTICS AS TRANSACTION READ ONLY",
+                "SET",
+            ),
+            (
+                "SET SESSION CHARACTERISTICS AS TRANSACTION ISOLATION LEVEL READ COMMITTED",
+                "SET",
+            ),
+            ("BEGIN", "BEGIN"),
+            ("SET TRANSACTION READ ONLY", "SET"),
+            ("COMMIT", "COMMIT"),
+        ] {
+            let completion = match plan(query).unwrap() {
+                QueryPlan::MetaOk(_, completion) => completion,
+                QueryPlan::MetaChange(_, completion, change) => {
+                    session.state.apply_change(change).unwrap();
+
+                    completion
+                }
+                _ => panic!("Transaction statement must be planned as MetaOk: {}", query),
+            };
+            match completion.to_pg_command() {
+                pg_srv::protocol::CommandComplete::Plain(tag) => assert_eq!(tag, expected_tag),
+                _ => panic!("Unexpected command tag for: {}", query),
+            }
+        }
+
+        let variables = session.state.all_variables();
+        for (name, expected) in vec![
+            ("default_transaction_read_only", "on"),
+            ("default_transaction_isolation", "read committed"),
+            ("transaction_read_only", "on"),
+        ] {
+            assert_eq!(
+                variables.get(name).map(|v| v.value.to_string()),
+                Some(expected.to_string()),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_reject_unbounded_scans_of_large_cubes() {
+        let mut cubes = get_test_meta().cubes.clone();
+        for cube in cubes.iter_mut() {
+            if cube.name == "KibanaSampleDataEcommerce" {
+                cube.meta = Some(json!({ "large": true }));
+            }
+        }
+        let meta = Arc::new(MetaContext::new(cubes));
+
+        let convert = |query: &str| {
+            convert_sql_to_cube_query(
+                &query.to_string(),
+                meta.clone(),
+                get_test_session(DatabaseProtocol::MySQL),
+            )
+        };
+
+        match convert("SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1")
+        {
+            Err(CompilationError::User(message)) => assert_eq!(
+                message,
+                "Query to the large cube KibanaSampleDataEcommerce must contain a filter or LIMIT"
+            ),
+            result => panic!("Unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        for query in [
+            "SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1 LIMIT 10",
+            "SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce WHERE customer_gender = 'female' GROUP BY 1",
+            "SELECT COUNT(DISTINCT agentCount) FROM Logs",
+        ] {
+            if let Err(err) = convert(query) {
+                panic!("Query {} was rejected: {}", query, err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dry_run_and_validate_model() {
+        let requests = dry_run_sql_to_cube_query(
+            &"SELECT COUNT(*) FROM KibanaSampleDataEcommerce".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::MySQL),
+        )
+        .unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].measures,
+            Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+        );
+
+        let checks = validation::validate_model(
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::MySQL),
+        );
+        for check in vec![
+            "star",
+            "dimension KibanaSampleDataEcommerce.customer_gender",
+            "measure KibanaSampleDataEcommerce.count",
+            "time dimension KibanaSampleDataEcommerce.order_date by month",
+        ] {
+            let result = checks
+                .iter()
+                .find(|c| c.cube == "KibanaSampleDataEcommerce" && c.check == check)
+                .unwrap_or_else(|| panic!("Check {} was not generated", check));
+            assert!(result.is_ok(), "{:?}", result);
+        }
+
+        // Report by SQL function
+        let plan = convert_sql_to_cube_query(
+            &"SELECT * FROM cube_validate_model()".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        )
+        .unwrap();
+        match plan {
+            QueryPlan::MetaTabular(_, frame) => {
+                assert_eq!(
+                    frame
+                        .get_columns()
+                        .iter()
+                        .map(|c| c.get_name())
+                        .collect::<Vec<_>>(),
+                    vec!["cube", "check", "status", "message"]
+                );
+                assert!(frame.len() > 0);
+            }
+            _ => panic!("cube_validate_model must be planned as MetaTabular"),
+        }
+    }
+
+    #[test]
+    fn test_pg_session_variables() {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let plan = |query: &str| {
+            convert_sql_to_cube_query(&query.to_string(), get_test_tenant_ctx(), session.clone())
+        };
+        let show = |name: &str| match plan(&format!("SHOW {}", name)).unwrap() {
+            QueryPlan::MetaTabular(_, frame) => {
+                assert_eq!(frame.get_columns()[0].get_name().to_lowercase(), name);
+                frame.get_rows()[0].values()[0].to_string()
+            }
+            _ => panic!("SHOW must be planned as MetaTabular"),
+        };
+
+        for (name, default) in vec![
+            ("application_name", ""),
+            ("timezone", "GMT"),
+            ("extra_float_digits", "1"),
+            ("search_path", "\"$user\", public"),
+            ("statement_timeout", "0"),
+        ] {
+            assert_eq!(show(name), default, "{}", name);
+        }
+
+        let execute = |query: &str| match plan(query).unwrap() {
+            QueryPlan::MetaChange(_, CommandCompletion::Set, change) => {
+                session.state.apply_change(change).unwrap()
+            }
+            _ => panic!("SET must be planned as MetaChange: {}", query),
+        };
+
+        for query in vec![
+            "SET extra_float_digits = 3",
+            "SET SESSION application_name = 'Metabase'",
+            "SET statement_timeout = 0",
+            "SET unknown_variable = 'accepted'",
+        ] {
+            execute(query);
+        }
+
+        assert_eq!(show("extra_float_digits"), "3");
+        // Planning doesn't change the session, till the plan is executed
+        plan("SET extra_float_digits = 2").unwrap();
+        plan("RESET ALL").unwrap();
+        assert_eq!(show("extra_float_digits"), "3");
+        assert_eq!(show("application_name"), "Metabase");
+        assert_eq!(show("transaction_isolation"), "read committed");
+
+        // Variables are not shared between sessions
+        let another_session = get_test_session(DatabaseProtocol::PostgreSQL);
+        assert_eq!(
+            another_session
+                .state
+                .visible_variables(DatabaseVariables::new())
+                .get("extra_float_digits")
+                .map(|v| v.value.to_string()),
+            Some("1".to_string())
+        );
+
+        execute("RESET extra_float_digits");
+        assert_eq!(show("extra_float_digits"), "1");
+        assert_eq!(show("application_name"), "Metabase");
+
+        execute("RESET ALL");
+        assert_eq!(show("application_name"), "");
+        assert!(plan("SHOW unknown_variable").is_err());
+
+        // Drivers probe these variables at connect time
+        assert_eq!(show("standard_conforming_strings"), "on");
+        assert_eq!(show("server_version"), "14.2 (Cube SQL)");
+
+        match plan("SHOW TimeZone").unwrap() {
+            QueryPlan::MetaTabular(_, frame) => {
+                assert_eq!(frame.get_columns()[0].get_name(), "TimeZone");
+            }
+            _ => panic!("SHOW must be planned as MetaTabular"),
+        };
+
+        match plan("SHOW ALL").unwrap() {
+            QueryPlan::MetaTabular(_, frame) => {
+                assert_eq!(
+                    frame
+                        .get_columns()
+                        .iter()
+                        .map(|c| c.get_name())
+                        .collect::<Vec<_>>(),
+                    vec!["name", "setting", "description"]
+                );
+                let settings = frame
+                    .get_rows()
+                    .iter()
+                    .map(|row| (row.values()[0].to_string(), row.values()[1].to_string()))
+                    .collect::<Vec<_>>();
+                assert!(settings.contains(&("DateStyle".to_string(), "ISO".to_string())));
+                assert!(settings.contains(&(
+                    "transaction_isolation".to_string(),
+                    "read committed".to_string()
+                )));
+            }
+            _ => panic!("SHOW ALL must be planned as MetaTabular"),
+        };
+    }
+
+    #[tokio::test]
+    async fn test_pg_set_local_variable() -> Result<(), CubeError> {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let setting = || async {
+            let query = convert_sql_to_cube_query(
+                &"SELECT setting FROM pg_catalog.pg_settings WHERE name = 'extra_float_digits'"
+                    .to_string(),
+                get_test_tenant_ctx(),
+                session.clone(),
+            );
+            match query.unwrap() {
+                QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                    let df = DFDataFrame::new(ctx.state, &plan);
+                    let batches = df.collect().await?;
+                    Ok::<String, CubeError>(batch_to_dataframe(&batches)?.print())
+                }
+                _ => panic!("pg_settings must be planned as DataFusionSelect"),
+            }
+        };
+        let execute = |query: &str| match convert_sql_to_cube_query(
+            &query.to_string(),
+            get_test_tenant_ctx(),
+            session.clone(),
+        )
+        .unwrap()
+        {
+            QueryPlan::MetaChange(_, _, change) => session.state.apply_change(change).unwrap(),
+            _ => panic!("SET LOCAL must be planned as MetaChange: {}", query),
+        };
+
+        let before = setting().await?;
+
+        // Outside of transaction block SET LOCAL has no effect
+        execute("SET LOCAL extra_float_digits = 3");
+        assert_eq!(setting().await?, before);
+
+        session
+            .state
+            .set_transaction_state(TransactionState::Active);
+        execute("SET LOCAL extra_float_digits = 3");
+        assert_eq!(
+            setting().await?,
+            "+---------+\n| setting |\n+---------+\n| 3       |\n+---------+"
+        );
+
+        // COMMIT or ROLLBACK ends transaction block
+        session.state.set_transaction_state(TransactionState::None);
+        assert_eq!(setting().await?, before);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sql_macro_pg_proc() -> Result<(), CubeError> {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        execute_session_query(
+            "CREATE FUNCTION margin(revenue numeric, cost numeric) RETURNS numeric AS $$ SELECT (revenue - cost) / revenue $$ LANGUAGE SQL",
+            &session,
+        )?;
+
+        let plan = convert_sql_to_cube_query(
+            &"SELECT oid, proname, prolang, pronargs, prorettype, proargtypes, proargnames, prosrc FROM pg_catalog.pg_proc WHERE oid >= 16384".to_string(),
+            get_test_tenant_ctx(),
+            session,
+        )?;
+        match plan {
+            QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                let df = DFDataFrame::new(ctx.state, &plan);
+                let batches = df.collect().await?;
+                insta::assert_snapshot!("sql_macro_pg_proc", batch_to_dataframe(&batches)?.print());
+            }
+            _ => panic!("Unexpected plan for pg_proc"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pg_get_functiondef() -> Result<(), CubeError> {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        execute_session_query(
+            "CREATE FUNCTION margin(revenue numeric, cost numeric) RETURNS numeric AS $$ SELECT (revenue - cost) / revenue $$ LANGUAGE SQL",
+            &session,
+        )?;
+
+        let plan = convert_sql_to_cube_query(
+            &"SELECT proname, pg_get_functiondef(oid) FROM pg_catalog.pg_proc WHERE proname = 'pg_get_functiondef' OR proname = 'margin'".to_string(),
+            get_test_tenant_ctx(),
+            session,
+        )?;
+        match plan {
+            QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                let df = DFDataFrame::new(ctx.state, &plan);
+                let frame = batch_to_dataframe(&df.collect().await?)?;
+
+                assert_eq!(
+                    frame
+                        .get_rows()
+                        .iter()
+                        .map(|row| row
+                            .values()
+                            .iter()
+                            .map(|value| value.to_string())
+                            .collect::<Vec<_>>())
+                        .collect::<Vec<_>>(),
+                    vec![
+                        vec![
+                            "pg_get_functiondef".to_string(),
+                            "CREATE OR REPLACE FUNCTION pg_catalog.pg_get_functiondef(oid)\n RETURNS text\n LANGUAGE internal\n STABLE PARALLEL SAFE STRICT\nAS $function$pg_get_functiondef$function$\n".to_string(),
+                        ],
+                        vec![
+                            "margin".to_string(),
+                            "CREATE OR REPLACE FUNCTION public.margin(revenue numeric, cost numeric)\n RETURNS numeric\n LANGUAGE sql\nAS $function$SELECT (revenue - cost) / revenue$function$\n".to_string(),
+                        ],
+                    ]
+                );
+            }
+            _ => panic!("Unexpected plan for pg_get_functiondef"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_type_resolution() -> Result<(), CubeError> {
+        let query = "SELECT COALESCE(NULL, typlen) AS a, COALESCE(typlen, typelem) AS b, COALESCE(NULL, typlen, 2.5) AS c, NULLIF(typname, 'int2') AS d FROM pg_catalog.pg_type WHERE typname = 'bool'";
+        assert_eq!(
+            query_pg_types(query),
+            vec![
+                PgTypeId::INT2,
+                PgTypeId::INT8,
+                PgTypeId::FLOAT8,
+                PgTypeId::TEXT
+            ]
+        );
+
+        let plan = convert_sql_to_cube_query(
+            &query.to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        )?;
+        match plan {
+            QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                let df = DFDataFrame::new(ctx.state, &plan);
+                let frame = batch_to_dataframe(&df.collect().await?)?;
+                assert_eq!(
+                    frame
+                        .get_rows()
+                        .iter()
+                        .map(|row| row
+                            .values()
+                            .iter()
+                            .map(|value| value.to_string())
+                            .collect::<Vec<_>>())
+                        .collect::<Vec<_>>(),
+                    vec![vec![
+                        "1".to_string(),
+                        "1".to_string(),
+                        "1".to_string(),
+                        "bool".to_string()
+                    ]]
+                );
+            }
+            _ => panic!("Unexpected plan for COALESCE"),
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_show_collation() -> Result<(), CubeError> {
         // Simplest syntax
@@ -5319,6 +7681,111 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_order_by_post_aggregation_expression_top_n() -> Result<(), CubeError> {
+        #[derive(Debug)]
+        struct GroupsTransport {
+            // (customer_gender, count, maxPrice)
+            groups: Vec<(String, i64, f64)>,
+        }
+
+        #[async_trait]
+        impl TransportService for GroupsTransport {
+            async fn meta(&self, _ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError> {
+                panic!("It's a fake transport");
+            }
+
+            async fn load(
+                &self,
+                query: V1LoadRequestQuery,
+                _ctx: Arc<AuthContext>,
+            ) -> Result<V1LoadResponse, CubeError> {
+                // Whole result must be requested, otherwise local sort is incorrect
+                assert_eq!(query.order, None);
+                assert_eq!(query.limit, None);
+
+                let data = self
+                    .groups
+                    .iter()
+                    .map(|(gender, count, max_price)| {
+                        serde_json::json!({
+                            "KibanaSampleDataEcommerce.customer_gender": gender,
+                            "KibanaSampleDataEcommerce.count": count,
+                            "KibanaSampleDataEcommerce.maxPrice": max_price,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let result = serde_json::from_value(serde_json::json!({
+                    "annotation": {
+                        "measures": [],
+                        "dimensions": [],
+                        "segments": [],
+                        "timeDimensions": []
+                    },
+                    "data": data,
+                }))
+                .unwrap();
+
+                Ok(V1LoadResponse {
+                    pivot_query: None,
+                    slow_query: None,
+                    query_type: None,
+                    results: vec![result],
+                })
+            }
+        }
+
+        let groups = (0..30)
+            .map(|i| {
+                (
+                    format!("g{}", i),
+                    ((i * 17) % 30 + 1) as i64,
+                    1.0 + (i as f64) * 0.001,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut expected = groups
+            .iter()
+            .map(|(gender, count, max_price)| (gender.clone(), *count as f64 / max_price))
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let expected = expected
+            .into_iter()
+            .take(10)
+            .map(|(gender, _)| gender)
+            .collect::<Vec<_>>();
+
+        let session = get_test_session_with_transport(
+            DatabaseProtocol::PostgreSQL,
+            ServerConfiguration::default(),
+            Arc::new(GroupsTransport { groups }),
+        );
+        let plan = convert_sql_to_cube_query(
+            &"SELECT customer_gender, SUM(count) / MAX(maxPrice) AS ratio FROM KibanaSampleDataEcommerce GROUP BY 1 ORDER BY ratio DESC LIMIT 10".to_string(),
+            get_test_tenant_ctx(),
+            session,
+        )?;
+        match plan {
+            QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                let df = DFDataFrame::new(ctx.state, &plan);
+                let frame = batch_to_dataframe(&df.collect().await?)?;
+
+                assert_eq!(
+                    frame
+                        .get_rows()
+                        .iter()
+                        .map(|row| row.values()[0].to_string())
+                        .collect::<Vec<_>>(),
+                    expected
+                );
+            }
+            _ => panic!("Unexpected plan for post-aggregation order"),
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_explain() -> Result<(), CubeError> {
         // SELECT with no tables (inline eval)
@@ -5438,6 +7905,37 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_metabase_introspection_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "metabase_introspection_tables_postgres",
+            execute_query(
+                "SELECT table_schema, table_name, table_type \
+                FROM information_schema.tables \
+                WHERE table_schema NOT IN ('information_schema', 'pg_catalog') \
+                ORDER BY table_schema, table_name"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        insta::assert_snapshot!(
+            "metabase_introspection_columns_postgres",
+            execute_query(
+                "SELECT column_name, data_type, is_nullable, ordinal_position, character_maximum_length \
+                FROM information_schema.columns \
+                WHERE table_schema = 'db' AND table_name = 'KibanaSampleDataEcommerce' \
+                ORDER BY ordinal_position"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_information_schema_character_sets_postgres() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -5485,7 +7983,35 @@ mod tests {
         insta::assert_snapshot!(
             "information_schema_table_constraints_postgres",
             execute_query(
-                "SELECT * FROM information_schema.table_constraints".to_string(),
+                "SELECT * FROM information_schema.table_constraints".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pgcatalog_pgtables_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "pgcatalog_pgtables_postgres",
+            execute_query(
+                "SELECT * FROM pg_catalog.pg_tables".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pgcatalog_pgtype_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "pgcatalog_pgtype_postgres",
+            execute_query(
+                "SELECT * FROM pg_catalog.pg_type ORDER BY oid ASC".to_string(),
                 DatabaseProtocol::PostgreSQL
             )
             .await?
@@ -5495,25 +8021,33 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_pgcatalog_pgtables_postgres() -> Result<(), CubeError> {
+    async fn test_pgcatalog_pgtype_driver_queries_postgres() -> Result<(), CubeError> {
+        // Base types of domains, as they are loaded by Npgsql
         insta::assert_snapshot!(
-            "pgcatalog_pgtables_postgres",
+            "pgcatalog_pgtype_domains_postgres",
             execute_query(
-                "SELECT * FROM pg_catalog.pg_tables".to_string(),
+                "SELECT typ.oid, typ.typname, base.typname AS basename, typ.typtypmod, typ.typcollation
+                FROM pg_catalog.pg_type AS typ
+                JOIN pg_catalog.pg_namespace AS ns ON ns.oid = typ.typnamespace
+                LEFT JOIN pg_catalog.pg_type AS base ON base.oid = typ.typbasetype
+                WHERE typ.typtype = 'd'
+                ORDER BY typ.oid"
+                    .to_string(),
                 DatabaseProtocol::PostgreSQL
             )
             .await?
         );
 
-        Ok(())
-    }
-
-    #[tokio::test]
-    async fn test_pgcatalog_pgtype_postgres() -> Result<(), CubeError> {
+        // Arrays are detected by their input function
         insta::assert_snapshot!(
-            "pgcatalog_pgtype_postgres",
+            "pgcatalog_pgtype_arrays_postgres",
             execute_query(
-                "SELECT * FROM pg_catalog.pg_type ORDER BY oid ASC".to_string(),
+                "SELECT t.oid, t.typname, e.typname AS elemname
+                FROM pg_catalog.pg_type t
+                JOIN pg_catalog.pg_type e ON t.typelem = e.oid
+                WHERE t.typinput = 'array_in' AND e.typname IN ('int4', 'text', 'varchar')
+                ORDER BY t.oid"
+                    .to_string(),
                 DatabaseProtocol::PostgreSQL
             )
             .await?
@@ -5620,6 +8154,144 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_psql_describe_postgres() -> Result<(), CubeError> {
+        let rows = |query: &str| {
+            let plan = convert_sql_to_cube_query(
+                &query.to_string(),
+                get_test_tenant_ctx(),
+                get_test_session(DatabaseProtocol::PostgreSQL),
+            );
+            async move {
+                match plan? {
+                    QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                        let df = DFDataFrame::new(ctx.state, &plan);
+                        let frame = batch_to_dataframe(&df.collect().await?)?;
+
+                        Ok::<_, CubeError>(
+                            frame
+                                .get_rows()
+                                .iter()
+                                .map(|row| {
+                                    row.values()
+                                        .iter()
+                                        .map(|value| value.to_string())
+                                        .collect::<Vec<_>>()
+                                })
+                                .collect::<Vec<_>>(),
+                        )
+                    }
+                    _ => panic!("psql queries must be planned as DataFusionSelect"),
+                }
+            }
+        };
+
+        // \dt
+        let tables = rows(
+            "SELECT n.nspname as \"Schema\",
+  c.relname as \"Name\",
+  CASE c.relkind WHEN 'r' THEN 'table' WHEN 'v' THEN 'view' WHEN 'm' THEN 'materialized view' WHEN 'i' THEN 'index' WHEN 'S' THEN 'sequence' WHEN 's' THEN 'special' WHEN 't' THEN 'TOAST table' WHEN 'f' THEN 'foreign table' WHEN 'p' THEN 'partitioned table' WHEN 'I' THEN 'partitioned index' END as \"Type\",
+  pg_catalog.pg_get_userbyid(c.relowner) as \"Owner\"
+FROM pg_catalog.pg_class c
+     LEFT JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+     LEFT JOIN pg_catalog.pg_am am ON am.oid = c.relam
+WHERE c.relkind IN ('r','p','')
+      AND n.nspname <> 'pg_catalog'
+      AND n.nspname !~ '^pg_toast'
+      AND n.nspname <> 'information_schema'
+  AND pg_catalog.pg_table_is_visible(c.oid)
+ORDER BY 1,2;",
+        )
+        .await?;
+        assert_eq!(
+            tables
+                .iter()
+                .map(|row| (row[0].as_str(), row[1].as_str(), row[2].as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("public", "KibanaSampleDataEcommerce", "table"),
+                ("public", "Logs", "table")
+            ]
+        );
+
+        // \dn
+        let schemas = rows(
+            "SELECT n.nspname AS \"Name\",
+  pg_catalog.pg_get_userbyid(n.nspowner) AS \"Owner\"
+FROM pg_catalog.pg_namespace n
+WHERE n.nspname !~ '^pg_' AND n.nspname <> 'information_schema'
+ORDER BY 1;",
+        )
+        .await?;
+        assert_eq!(
+            schemas
+                .iter()
+                .map(|row| row[0].as_str())
+                .collect::<Vec<_>>(),
+            vec!["public"]
+        );
+
+        // \d "Logs"
+        let relations = rows(
+            "SELECT c.oid,
+  n.nspname,
+  c.relname
+FROM pg_catalog.pg_class c
+     LEFT JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE c.relname OPERATOR(pg_catalog.~) '^(Logs)$' COLLATE pg_catalog.default
+  AND pg_catalog.pg_table_is_visible(c.oid)
+ORDER BY 2, 3;",
+        )
+        .await?;
+        assert_eq!(
+            relations,
+            vec![vec![
+                "18012".to_string(),
+                "public".to_string(),
+                "Logs".to_string()
+            ]]
+        );
+
+        let relation = rows(
+            "SELECT c.relchecks, c.relkind, c.relhasindex, c.relhasrules, c.relhastriggers, c.relrowsecurity, c.relforcerowsecurity, false AS relhasoids, c.relispartition, pg_catalog.array_to_string(c.reloptions || array(select 'toast.' || x from pg_catalog.unnest(tc.reloptions) x), ', ')
+, c.reltablespace, CASE WHEN c.reloftype = 0 THEN '' ELSE c.reloftype::pg_catalog.regtype::pg_catalog.text END, c.relpersistence, c.relreplident, am.amname
+FROM pg_catalog.pg_class c
+ LEFT JOIN pg_catalog.pg_class tc ON (c.reltoastrelid = tc.oid)
+LEFT JOIN pg_catalog.pg_am am ON (c.relam = am.oid)
+WHERE c.oid = '18012';",
+        )
+        .await?;
+        assert_eq!(relation.len(), 1);
+        assert_eq!(relation[0][1], "r");
+
+        let columns = rows(
+            "SELECT a.attname,
+  pg_catalog.format_type(a.atttypid, a.atttypmod),
+  (SELECT pg_catalog.pg_get_expr(d.adbin, d.adrelid, true)
+   FROM pg_catalog.pg_attrdef d
+   WHERE d.adrelid = a.attrelid AND d.adnum = a.attnum AND a.atthasdef),
+  a.attnotnull,
+  (SELECT c.collname FROM pg_catalog.pg_collation c, pg_catalog.pg_type t
+   WHERE c.oid = a.attcollation AND t.oid = a.atttypid AND a.attcollation <> t.typcollation) AS attcollation,
+  a.attidentity,
+  a.attgenerated
+FROM pg_catalog.pg_attribute a
+WHERE a.attrelid = '18012' AND a.attnum > 0 AND NOT a.attisdropped
+ORDER BY a.attnum;",
+        )
+        .await?;
+        assert_eq!(
+            columns
+                .iter()
+                .take(2)
+                .map(|row| (row[0].as_str(), row[1].as_str()))
+                .collect::<Vec<_>>(),
+            vec![("agentCount", "bigint"), ("agentCountApprox", "bigint")]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_pgcatalog_pgproc_postgres() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -5677,6 +8349,35 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_current_setting_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "current_setting_postgres",
+            execute_query(
+                "SELECT current_setting('extra_float_digits') AS a, current_setting('unknown_setting', true) AS b".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        match execute_query(
+            "SELECT current_setting('unknown_setting', false)".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await
+        {
+            Err(err) => assert!(
+                err.message
+                    .contains("unrecognized configuration parameter \"unknown_setting\""),
+                "{}",
+                err.message
+            ),
+            Ok(result) => panic!("Unexpected result: {}", result),
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_current_schema_postgres() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -5732,6 +8433,101 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_pg_session_functions() -> Result<(), CubeError> {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let execute = |query: &str| {
+            let plan = convert_sql_to_cube_query(
+                &query.to_string(),
+                get_test_tenant_ctx(),
+                session.clone(),
+            );
+            async move {
+                match plan? {
+                    QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                        let types = plan
+                            .schema()
+                            .fields()
+                            .iter()
+                            .map(|field| field.data_type().clone())
+                            .collect::<Vec<_>>();
+                        let df = DFDataFrame::new(ctx.state, &plan);
+                        let frame = batch_to_dataframe(&df.collect().await?)?;
+                        let values = frame.get_rows()[0]
+                            .values()
+                            .iter()
+                            .map(|value| value.to_string())
+                            .collect::<Vec<_>>();
+
+                        Ok::<_, CubeError>((types, values))
+                    }
+                    _ => panic!("Session functions must be planned as DataFusionSelect"),
+                }
+            }
+        };
+
+        let (types, values) = execute(
+            "SELECT version(), pg_backend_pid(), current_database(), current_catalog, current_user, session_user",
+        )
+        .await?;
+        assert_eq!(
+            types,
+            vec![
+                DataType::Utf8,
+                DataType::Int32,
+                DataType::Utf8,
+                DataType::Utf8,
+                DataType::Utf8,
+                DataType::Utf8
+            ]
+        );
+        assert_eq!(
+            values,
+            vec![
+                "PostgreSQL 14.2 (Cube SQL) on x86_64-cubesql".to_string(),
+                session.state.connection_id.to_string(),
+                "db".to_string(),
+                "db".to_string(),
+                "ovr".to_string(),
+                "ovr".to_string(),
+            ]
+        );
+
+        let (_, values) = execute("SELECT current_schema, current_schemas(true)").await?;
+        assert_eq!(
+            values,
+            vec!["public".to_string(), "{pg_catalog,public}".to_string()]
+        );
+
+        // search_path is resolved to the existing schemas, "$user" is the authenticated user
+        let mut variables = DatabaseVariables::new();
+        variables.insert(
+            "search_path".to_string(),
+            DatabaseVariable::system(
+                "search_path".to_string(),
+                ScalarValue::Utf8(Some(
+                    "\"$user\", unknown, information_schema, pg_catalog".to_string(),
+                )),
+                None,
+            ),
+        );
+        session.state.set_variables(variables);
+
+        let (_, values) =
+            execute("SELECT current_schema(), current_schemas(false), current_schemas(true)")
+                .await?;
+        assert_eq!(
+            values,
+            vec![
+                "information_schema".to_string(),
+                "{information_schema,pg_catalog}".to_string(),
+                "{information_schema,pg_catalog}".to_string(),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_format_type_postgres() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -5950,28 +8746,247 @@ mod tests {
             .await?
         );
 
+        // Zero step is an error, as it's in PostgreSQL
+        let err = execute_query(
+            "SELECT generate_series(1, 5, 0);".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            err.message.contains("step size cannot equal zero"),
+            "{}",
+            err.message
+        );
+
+        insta::assert_snapshot!(
+            "pg_catalog_generate_series_i64",
+            execute_query(
+                "SELECT pg_catalog.generate_series(1, 5);".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        insta::assert_snapshot!(
+            "generate_series_from_table",
+            execute_query(
+                "select generate_series(1, oid) from pg_catalog.pg_type where oid in (16,17);"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_returning_functions_in_from_postgres() -> Result<(), CubeError> {
         insta::assert_snapshot!(
-            "generate_series_empty_2",
+            "generate_series_in_from",
             execute_query(
-                "SELECT generate_series(1, 5, 0);".to_string(),
+                "SELECT * FROM generate_series(1, 3)".to_string(),
                 DatabaseProtocol::PostgreSQL
             )
             .await?
         );
 
         insta::assert_snapshot!(
-            "pg_catalog_generate_series_i64",
+            "generate_series_descending",
             execute_query(
-                "SELECT pg_catalog.generate_series(1, 5);".to_string(),
+                "SELECT s.n FROM generate_series(5, 1, -2) AS s(n)".to_string(),
                 DatabaseProtocol::PostgreSQL
             )
             .await?
         );
 
         insta::assert_snapshot!(
-            "generate_series_from_table",
+            "generate_series_timestamp_months",
             execute_query(
-                "select generate_series(1, oid) from pg_catalog.pg_type where oid in (16,17);"
+                "SELECT d FROM generate_series('2022-01-31 00:00:00'::timestamp, '2022-04-30 00:00:00'::timestamp, interval '1 month') AS s(d)"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        insta::assert_snapshot!(
+            "generate_series_timestamp_hours",
+            execute_query(
+                "SELECT d FROM pg_catalog.generate_series('2022-01-01 00:00:00'::timestamp, '2022-01-02 00:00:00'::timestamp, interval '12 hours') AS s(d)"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        insta::assert_snapshot!(
+            "unnest_in_from",
+            execute_query(
+                "SELECT v FROM unnest(ARRAY['a', 'b']) AS t(v)".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_returning_functions_in_from_plan() {
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT * FROM generate_series(1, 3)".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+        assert_eq!(
+            logical_plan
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().to_string())
+                .collect::<Vec<_>>(),
+            vec!["generate_series"]
+        );
+
+        // Series is cross joined with the result of cube query
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT k.cnt, s.n \
+            FROM (SELECT COUNT(*) AS cnt FROM KibanaSampleDataEcommerce) AS k \
+            CROSS JOIN generate_series(1, 2) AS s(n)"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request.measures,
+            Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+        );
+        assert_eq!(
+            logical_plan
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().to_string())
+                .collect::<Vec<_>>(),
+            vec!["cnt", "n"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_date_time_functions_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "date_time_functions_new_year",
+            execute_query(
+                "SELECT d, date_trunc('week', d) AS week, date_trunc('decade', d) AS decade, EXTRACT(DOW FROM d) AS dow, EXTRACT(EPOCH FROM d) AS epoch, to_char(d, 'YYYY-MM-DD Dy') AS formatted FROM generate_series('2020-12-30 00:00:00'::timestamp, '2021-01-04 00:00:00'::timestamp, interval '1 day') AS s(d)"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        insta::assert_snapshot!(
+            "date_time_functions_end_of_year",
+            execute_query(
+                "SELECT date_trunc('quarter', d) AS q, date_trunc('millennium', d) AS mil, EXTRACT(QUARTER FROM d) AS quarter, EXTRACT(DOY FROM d) AS doy, date_part('isodow', d) AS isodow, to_char(d, 'FMDay, DD Mon YYYY HH12:MI:SS AM') AS formatted FROM (SELECT '2022-12-31 23:59:58'::timestamp AS d) AS t"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        insta::assert_snapshot!(
+            "date_time_functions_to_timestamp",
+            execute_query(
+                "SELECT to_timestamp(1650000000) AS epoch, to_timestamp('05 Mar 2022 17:04', 'DD Mon YYYY HH24:MI') AS parsed"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        insta::assert_snapshot!(
+            "date_time_functions_formatting",
+            execute_query(
+                "SELECT to_char('2023-03-15 10:20:30'::timestamp, 'YYYY-MM') AS month, to_char(1234567.891, 'FM9,999,999.00') AS amount, to_char(-42, 'FM000') AS negative, to_date('2023-01-01', 'YYYY-MM-DD') AS d"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        let err = execute_query(
+            "SELECT date_trunc('fortnight', '2022-01-01 00:00:00'::timestamp)".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await
+        .unwrap_err();
+        assert!(err
+            .message
+            .contains("timestamp units \"fortnight\" not recognized"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pg_size_pretty_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "pg_size_pretty",
+            execute_query(
+                "SELECT pg_size_pretty(0) AS zero, pg_size_pretty(1024) AS kb, pg_size_pretty(10240) AS ten_kb, pg_size_pretty(1048576) AS mb, pg_size_pretty(5368709120) AS gb, pg_catalog.pg_size_pretty(10995116277760) AS tb, pg_size_pretty(-123456) AS negative"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pg_stat_activity() -> Result<(), CubeError> {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let query = "SELECT * FROM pg_stat_activity".to_string();
+        session.state.set_current_query(query.clone());
+
+        // The second connection is idle in transaction after its last query
+        let other = session
+            .session_manager
+            .create_session(DatabaseProtocol::PostgreSQL, "127.0.0.2".to_string());
+        other.state.set_user(Some("second".to_string()));
+        other.state.set_current_query("BEGIN".to_string());
+        other.state.set_transaction_state(TransactionState::Active);
+
+        let plan = convert_sql_to_cube_query(
+            &"SELECT pid, usename, client_addr, state, query FROM pg_stat_activity ORDER BY pid"
+                .to_string(),
+            get_test_tenant_ctx(),
+            session.clone(),
+        )?;
+
+        // The query is executed like in the shim
+        session.state.begin_query();
+        let frame = match plan {
+            QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                let df = DFDataFrame::new(ctx.state, &plan);
+                batch_to_dataframe(&df.collect().await?)?
+            }
+            _ => panic!("pg_stat_activity must be a DataFusion query"),
+        };
+        session.state.end_query();
+
+        insta::assert_snapshot!("pg_stat_activity", frame.print());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pg_get_serial_sequence_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "pg_get_serial_sequence",
+            execute_query(
+                "SELECT pg_get_serial_sequence('public.KibanaSampleDataEcommerce', 'count') AS seq, pg_catalog.pg_get_serial_sequence('t', 'id') AS catalog_seq"
                     .to_string(),
                 DatabaseProtocol::PostgreSQL
             )
@@ -5981,6 +8996,29 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_oid_cast_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "oid_cast_literal",
+            execute_query(
+                "SELECT '18000'::oid AS oid, CAST(26 AS oid) AS typ".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        insta::assert_snapshot!(
+            "oid_cast_compare",
+            execute_query(
+                "SELECT typname FROM pg_catalog.pg_type WHERE oid = '1007'::oid".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_pg_get_expr_postgres() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -1,8 +1,15 @@
-use sqlparser::{ast::Statement, dialect::Dialect, dialect::PostgreSqlDialect, parser::Parser};
+use regex::Regex;
+use sqlparser::{
+    ast::{CopyLegacyOption, CopyOption, Ident, SetVariableKeyValue, Statement},
+    dialect::Dialect,
+    dialect::PostgreSqlDialect,
+    parser::{Parser, ParserError},
+};
 
 use crate::{compile::CompilationError, sql::session::DatabaseProtocol};
 
 use super::CompilationResult;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub struct MySqlDialectWithBackTicks {}
@@ -29,10 +36,388 @@ impl Dialect for MySqlDialectWithBackTicks {
     }
 }
 
+lazy_static! {
+    static ref TOKENIZER_ERROR_POSITION_REGEX: Regex =
+        Regex::new(r"at Line: (\d+), Column (\d+)$").unwrap();
+    static ref SET_LOCAL_REGEX: Regex = Regex::new(r"(?i)^\s*SET\s+LOCAL\s+").unwrap();
+    static ref RESET_REGEX: Regex = Regex::new(r"(?i)^\s*RESET\s+([^\s;]+)\s*;?\s*$").unwrap();
+    static ref END_REGEX: Regex =
+        Regex::new(r"(?i)^\s*END(\s+(WORK|TRANSACTION))?\s*;?\s*$").unwrap();
+    static ref RELEASE_SAVEPOINT_REGEX: Regex =
+        Regex::new(r"(?i)^\s*RELEASE\s+(SAVEPOINT\s+)?").unwrap();
+    static ref ROLLBACK_TO_SAVEPOINT_REGEX: Regex =
+        Regex::new(r"(?i)^\s*ROLLBACK\s+((WORK|TRANSACTION)\s+)?TO\s+(SAVEPOINT\s+)?").unwrap();
+    static ref DISCARD_ALL_REGEX: Regex = Regex::new(r"(?i)^\s*DISCARD\s+ALL\s*;?\s*$").unwrap();
+    static ref PREPARED_STATEMENT_COMMAND_REGEX: Regex =
+        Regex::new(r"(?i)^\s*(PREPARE|EXECUTE|DEALLOCATE|DISCARD)\s").unwrap();
+    static ref COPY_TO_STDOUT_REGEX: Regex =
+        Regex::new(r"(?is)^\s*COPY\s*\((?P<query>.*)\)\s*TO\s+STDOUT\b(?P<options>.*?)\s*;?\s*$")
+            .unwrap();
+    static ref COPY_TABLE_TO_STDOUT_REGEX: Regex =
+        Regex::new(r"(?is)^\s*COPY\s+[^\s(].*\bTO\s+STDOUT\b").unwrap();
+    static ref LISTEN_REGEX: Regex = Regex::new(
+        r#"(?i)^\s*(?P<command>LISTEN|UNLISTEN)\s+(?P<channel>"(?:[^"]|"")+"|[a-z_][\w$]*|\*)\s*;?\s*$"#
+    )
+    .unwrap();
+    static ref NOTIFY_REGEX: Regex = Regex::new(
+        r#"(?is)^\s*NOTIFY\s+(?P<channel>"(?:[^"]|"")+"|[a-z_][\w$]*)\s*(,\s*'(?P<payload>(?:[^']|'')*)'\s*)?;?\s*$"#
+    )
+    .unwrap();
+    /// Queries which psql sends for \d: lookup of relations, the table and its columns
+    static ref PSQL_DESCRIBE_QUERIES: Vec<Regex> = vec![
+        Regex::new(r"(?is)^\s*SELECT\s.+\sFROM\s+pg_catalog\.pg_class\s+c\s+LEFT\s+JOIN\s+pg_catalog\.pg_namespace\s+n\s+ON\s+n\.oid\s*=\s*c\.relnamespace\s.*\bpg_catalog\.pg_table_is_visible\(\s*c\.oid\s*\).*$").unwrap(),
+        Regex::new(r"(?is)^\s*SELECT\s+c\.relchecks\s*,.+\sFROM\s+pg_catalog\.pg_class\s+c\s+LEFT\s+JOIN\s+pg_catalog\.pg_class\s+tc\s+ON\s*\(\s*c\.reltoastrelid\s*=\s*tc\.oid\s*\).*\sWHERE\s+c\.oid\s*=\s*'\d+'\s*;?\s*$").unwrap(),
+        Regex::new(r"(?is)^\s*SELECT\s+a\.attname\s*,.+\sFROM\s+pg_catalog\.pg_attribute\s+a\s+WHERE\s+a\.attrelid\s*=\s*'\d+'\s+AND\s+a\.attnum\s*>\s*0\s+AND\s+NOT\s+a\.attisdropped\b.*$").unwrap(),
+    ];
+    /// Parts of psql's \d queries which can't be parsed or planned, with their replacements
+    static ref PSQL_DESCRIBE_WORKAROUNDS: Vec<(Regex, &'static str)> = vec![
+        // OPERATOR(pg_catalog.~) is the same as the bare operator
+        (
+            Regex::new(r"(?i)OPERATOR\(\s*pg_catalog\.([^\s)]+)\s*\)").unwrap(),
+            "$1"
+        ),
+        // Default collation is the only one
+        (
+            Regex::new(r"(?i)\s+COLLATE\s+pg_catalog\.default\b").unwrap(),
+            ""
+        ),
+        // Oids are passed as quoted literals, which can't be compared with numbers
+        (
+            Regex::new(r"(?i)\b(c\.oid|a\.attrelid)\s*=\s*'(\d+)'").unwrap(),
+            "$1 = $2"
+        ),
+        // Cubes have neither options nor a type
+        (
+            Regex::new(r"(?is)pg_catalog\.array_to_string\(\s*c\.reloptions\s*\|\|\s*array\(\s*select\s+'toast\.'\s*\|\|\s*x\s+from\s+pg_catalog\.unnest\(\s*tc\.reloptions\s*\)\s+x\s*\)\s*,\s*',\s*'\s*\)").unwrap(),
+            "''"
+        ),
+        (
+            Regex::new(r"(?is)CASE\s+WHEN\s+c\.reloftype\s*=\s*0\s+THEN\s+''\s+ELSE\s+c\.reloftype::pg_catalog\.regtype::pg_catalog\.text\s+END").unwrap(),
+            "''"
+        ),
+        // There is no pg_collation, columns have the default collation
+        (
+            Regex::new(r"(?is)\(\s*SELECT\s+c\.collname\s+FROM\s+pg_catalog\.pg_collation\s+c\s*,\s*pg_catalog\.pg_type\s+t\s+WHERE\s+c\.oid\s*=\s*a\.attcollation\s+AND\s+t\.oid\s*=\s*a\.atttypid\s+AND\s+a\.attcollation\s*<>\s*t\.typcollation\s*\)").unwrap(),
+            "NULL::text"
+        ),
+    ];
+}
+
+/// Query with replaced parts, positions in it are mapped back to the query before replacements
+struct RewrittenQuery {
+    query: String,
+    // Replacements of every pass: range in the query after the pass and range in the query
+    // before it, in characters
+    passes: Vec<Vec<(Range<usize>, Range<usize>)>>,
+}
+
+impl RewrittenQuery {
+    fn new(query: String) -> Self {
+        Self {
+            query,
+            passes: vec![],
+        }
+    }
+
+    fn replace_all(&mut self, regex: &Regex, replacement: &str) {
+        let mut result = String::new();
+        let mut replacements = vec![];
+        let mut last_end = 0;
+        for captures in regex.captures_iter(&self.query) {
+            let found = captures.get(0).unwrap();
+            result.push_str(&self.query[last_end..found.start()]);
+
+            let old_start = self.query[..found.start()].chars().count();
+            let old_end = old_start + found.as_str().chars().count();
+            let new_start = result.chars().count();
+            captures.expand(replacement, &mut result);
+            let new_end = result.chars().count();
+
+            replacements.push((new_start..new_end, old_start..old_end));
+            last_end = found.end();
+        }
+
+        if !replacements.is_empty() {
+            result.push_str(&self.query[last_end..]);
+            self.query = result;
+            self.passes.push(replacements);
+        }
+    }
+
+    /// Position is 1-based index in characters, position inside a replacement is mapped to the
+    /// start of the replaced part
+    fn original_position(&self, position: usize) -> usize {
+        self.passes
+            .iter()
+            .rev()
+            .fold(position, |position, replacements| {
+                let index = position - 1;
+                let mut shift = 0;
+                for (new, old) in replacements {
+                    if index < new.start {
+                        break;
+                    }
+                    if index < new.end {
+                        return old.start + 1;
+                    }
+                    shift = old.end as isize - new.end as isize;
+                }
+
+                (index as isize + shift) as usize + 1
+            })
+    }
+}
+
+/// COPY (query) TO STDOUT, parser supports COPY of tables only, so the query is parsed separately.
+/// COPY table [(columns)] TO STDOUT is the same as COPY (SELECT columns FROM table) TO STDOUT
+#[derive(Debug)]
+pub struct CopyToStatement {
+    pub query: Statement,
+    pub options: Vec<CopyOption>,
+    pub legacy_options: Vec<CopyLegacyOption>,
+}
+
+/// Recognizes COPY (query) TO STDOUT [options] and COPY table [(columns)] TO STDOUT [options],
+/// returns None for all other statements
+pub fn parse_copy_to_statement(query: &str) -> CompilationResult<Option<CopyToStatement>> {
+    let captures = match COPY_TO_STDOUT_REGEX.captures(query) {
+        Some(captures) => captures,
+        None if COPY_TABLE_TO_STDOUT_REGEX.is_match(query) => {
+            return parse_copy_table_to_statement(query).map(Some)
+        }
+        None => return Ok(None),
+    };
+
+    // Positions of syntax errors are shifted to the inner query, options are parsed as
+    // a synthetic statement without positions
+    let query_offset = query[..captures.name("query").unwrap().start()]
+        .chars()
+        .count();
+    let statement =
+        parse_sql_to_statement(&captures["query"].to_string(), DatabaseProtocol::PostgreSQL)
+            .map_err(|err| match err {
+                CompilationError::SyntaxError(message, position) => CompilationError::SyntaxError(
+                    message,
+                    position.map(|position| position + query_offset),
+                ),
+                err => err,
+            })?;
+    let options = format!("COPY __cubesql_copy TO STDOUT {}", &captures["options"]);
+    let options_statement = parse_sql_to_statement(&options, DatabaseProtocol::PostgreSQL)
+        .map_err(|err| match err {
+            CompilationError::SyntaxError(message, _) => {
+                CompilationError::SyntaxError(message, None)
+            }
+            err => err,
+        })?;
+    match options_statement {
+        Statement::Copy {
+            options,
+            legacy_options,
+            ..
+        } => Ok(Some(CopyToStatement {
+            query: statement,
+            options,
+            legacy_options,
+        })),
+        _ => Err(CompilationError::User(format!(
+            "Unable to parse options of COPY: {}",
+            &captures["options"]
+        ))),
+    }
+}
+
+fn parse_copy_table_to_statement(query: &str) -> CompilationResult<CopyToStatement> {
+    match parse_sql_to_statement(&query.to_string(), DatabaseProtocol::PostgreSQL)? {
+        Statement::Copy {
+            table_name,
+            columns,
+            options,
+            legacy_options,
+            ..
+        } => {
+            let columns = if columns.is_empty() {
+                "*".to_string()
+            } else {
+                columns
+                    .iter()
+                    .map(|column| column.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let statement = parse_sql_to_statement(
+                &format!("SELECT {} FROM {}", columns, table_name),
+                DatabaseProtocol::PostgreSQL,
+            )?;
+
+            Ok(CopyToStatement {
+                query: statement,
+                options,
+                legacy_options,
+            })
+        }
+        _ => Err(CompilationError::User(format!(
+            "Unable to parse COPY: {}",
+            query
+        ))),
+    }
+}
+
+/// LISTEN, UNLISTEN and NOTIFY (postgresql), parser doesn't support them
+#[derive(Debug, PartialEq, Eq)]
+pub enum ListenCommand {
+    Listen(String),
+    // None is UNLISTEN *
+    Unlisten(Option<String>),
+    Notify { channel: String, payload: String },
+}
+
+/// Channel is an identifier, unquoted names are lowercased
+fn parse_channel_name(name: &str) -> String {
+    match name.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => name.to_lowercase(),
+    }
+}
+
+/// Recognizes LISTEN, UNLISTEN and NOTIFY, returns None for all other statements
+pub fn parse_listen_command(query: &str) -> Option<ListenCommand> {
+    if let Some(captures) = LISTEN_REGEX.captures(query) {
+        let channel = &captures["channel"];
+        return match (captures["command"].to_uppercase().as_str(), channel) {
+            ("LISTEN", "*") => None,
+            ("LISTEN", _) => Some(ListenCommand::Listen(parse_channel_name(channel))),
+            (_, "*") => Some(ListenCommand::Unlisten(None)),
+            _ => Some(ListenCommand::Unlisten(Some(parse_channel_name(channel)))),
+        };
+    }
+
+    NOTIFY_REGEX
+        .captures(query)
+        .map(|captures| ListenCommand::Notify {
+            channel: parse_channel_name(&captures["channel"]),
+            payload: captures
+                .name("payload")
+                .map(|payload| payload.as_str().replace("''", "'"))
+                .unwrap_or_default(),
+        })
+}
+
+/// Statements which manage prepared statements of the connection (postgresql)
+#[derive(Debug, PartialEq)]
+pub enum PreparedStatementCommand {
+    /// PREPARE, EXECUTE and DEALLOCATE
+    Statement(Statement),
+    /// Parser doesn't support DISCARD
+    DiscardAll,
+}
+
+/// Recognizes statements which manage prepared statements of the connection (PREPARE, EXECUTE,
+/// DEALLOCATE, DISCARD), they are executed by the connection instead of compilation.
+/// Returns None for all other statements.
+pub fn parse_prepared_statement_command(
+    query: &str,
+) -> CompilationResult<Option<PreparedStatementCommand>> {
+    if !PREPARED_STATEMENT_COMMAND_REGEX.is_match(query) {
+        return Ok(None);
+    }
+
+    if DISCARD_ALL_REGEX.is_match(query) {
+        return Ok(Some(PreparedStatementCommand::DiscardAll));
+    }
+
+    parse_sql_to_statement(&query.to_string(), DatabaseProtocol::PostgreSQL)
+        .map(|statement| Some(PreparedStatementCommand::Statement(statement)))
+}
+
+/// Statements of transaction blocks (postgresql) which parser doesn't support
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionCommand {
+    /// SET LOCAL, variables are set till the end of the transaction block
+    SetLocal(Vec<SetVariableKeyValue>),
+    ReleaseSavepoint(Ident),
+    RollbackToSavepoint(Ident),
+}
+
+/// Name of the savepoint, parser supports only SAVEPOINT, so it's parsed as SAVEPOINT
+fn parse_savepoint_name(query: &str, regex: &Regex) -> CompilationResult<Ident> {
+    let query = regex.replace(query, "SAVEPOINT ").to_string();
+
+    match parse_sql_to_statement(&query, DatabaseProtocol::PostgreSQL)? {
+        Statement::Savepoint { name } => Ok(name),
+        statement => Err(CompilationError::User(format!(
+            "Unable to parse savepoint: {}",
+            statement
+        ))),
+    }
+}
+
+/// Recognizes SET LOCAL, RELEASE and ROLLBACK TO, returns None for all other statements
+pub fn parse_transaction_command(query: &str) -> CompilationResult<Option<TransactionCommand>> {
+    if RELEASE_SAVEPOINT_REGEX.is_match(query) {
+        return parse_savepoint_name(query, &RELEASE_SAVEPOINT_REGEX)
+            .map(|name| Some(TransactionCommand::ReleaseSavepoint(name)));
+    }
+
+    if ROLLBACK_TO_SAVEPOINT_REGEX.is_match(query) {
+        return parse_savepoint_name(query, &ROLLBACK_TO_SAVEPOINT_REGEX)
+            .map(|name| Some(TransactionCommand::RollbackToSavepoint(name)));
+    }
+
+    if SET_LOCAL_REGEX.is_match(query) {
+        let query = SET_LOCAL_REGEX.replace(query, "SET ").to_string();
+
+        return match parse_sql_to_statement(&query, DatabaseProtocol::PostgreSQL)? {
+            Statement::SetVariable { key_values } => {
+                Ok(Some(TransactionCommand::SetLocal(key_values)))
+            }
+            statement => Err(CompilationError::Unsupported(format!(
+                "SET LOCAL of statement: {}",
+                statement
+            ))),
+        };
+    }
+
+    Ok(None)
+}
+
+/// Position of the syntax error in the query, 1-based index in characters. Tokenizer errors
+/// have the line and the column, parser errors have only the unexpected token, it's used
+/// when the query contains it once
+fn syntax_error_position(query: &str, error: &ParserError) -> Option<usize> {
+    if let ParserError::TokenizerError(message) = error {
+        let captures = TOKENIZER_ERROR_POSITION_REGEX.captures(message)?;
+        let line = captures[1].parse::<usize>().ok()?;
+        let column = captures[2].parse::<usize>().ok()?;
+        let line_offset = query
+            .split('\n')
+            .take(line.checked_sub(1)?)
+            .map(|line| line.chars().count() + 1)
+            .sum::<usize>();
+
+        return Some(line_offset + column);
+    }
+
+    if let ParserError::ParserError(message) = error {
+        let (_, found) = message.rsplit_once("found: ")?;
+        if found == "EOF" {
+            return Some(query.trim_end().chars().count() + 1);
+        }
+
+        let mut matches = query.match_indices(found);
+        if let (Some((index, _)), None) = (matches.next(), matches.next()) {
+            return Some(query[..index].chars().count() + 1);
+        }
+    }
+
+    None
+}
+
 pub fn parse_sql_to_statement(
     query: &String,
     protocol: DatabaseProtocol,
 ) -> CompilationResult<Statement> {
+    let original_query = query.as_str();
     // @todo Support without workarounds
     // metabase
     let query = query.clone().replace("IF(TABLE_TYPE='BASE TABLE' or TABLE_TYPE='SYSTEM VERSIONED', 'TABLE', TABLE_TYPE) as TABLE_TYPE", "TABLE_TYPE");
@@ -45,16 +430,58 @@ pub fn parse_sql_to_statement(
     let query = query.replace("unsigned integer", "bigint");
     let query = query.replace("UNSIGNED INTEGER", "bigint");
 
+    let metabase_rewritten = query != original_query;
+
+    // psql, workarounds are applied only to its own queries
+    let mut psql_query = RewrittenQuery::new(query);
+    if protocol == DatabaseProtocol::PostgreSQL
+        && PSQL_DESCRIBE_QUERIES
+            .iter()
+            .any(|regex| regex.is_match(&psql_query.query))
+    {
+        for (regex, replacement) in PSQL_DESCRIBE_WORKAROUNDS.iter() {
+            psql_query.replace_all(regex, replacement);
+        }
+    }
+    let query = psql_query.query.clone();
+
+    // Parser doesn't support RESET, which is the same as SET ... TO DEFAULT
+    let reset = match protocol {
+        DatabaseProtocol::PostgreSQL => RESET_REGEX
+            .captures(&query)
+            .map(|captures| format!("SET {} TO DEFAULT", &captures[1])),
+        DatabaseProtocol::MySQL => None,
+    };
+    let query = reset.unwrap_or(query);
+
+    // END is an alias of COMMIT
+    let query = if protocol == DatabaseProtocol::PostgreSQL && END_REGEX.is_match(&query) {
+        "COMMIT".to_string()
+    } else {
+        query
+    };
+
     let parse_result = match protocol {
         DatabaseProtocol::MySQL => Parser::parse_sql(&MySqlDialectWithBackTicks {}, query.as_str()),
         DatabaseProtocol::PostgreSQL => Parser::parse_sql(&PostgreSqlDialect {}, query.as_str()),
     };
 
     match parse_result {
-        Err(error) => Err(CompilationError::User(format!(
-            "Unable to parse: {:?}",
-            error
-        ))),
+        Err(error) => {
+            // Position in the query with workarounds doesn't match the client one, only
+            // replacements of psql workarounds are tracked
+            let position = if !metabase_rewritten && query == psql_query.query {
+                syntax_error_position(&query, &error)
+                    .map(|position| psql_query.original_position(position))
+            } else {
+                None
+            };
+
+            Err(CompilationError::SyntaxError(
+                format!("Unable to parse: {:?}", error),
+                position,
+            ))
+        }
         Ok(stmts) => {
             if stmts.len() == 1 {
                 Ok(stmts[0].clone())
@@ -76,6 +503,41 @@ pub fn parse_sql_to_statement(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sqlparser::ast::{CopyLegacyCsvOption, Ident};
+
+    #[test]
+    fn test_syntax_error_position() {
+        for (query, expected_position) in [
+            ("SELEC 1", Some(1)),
+            ("SELECT 1 +", Some(11)),
+            ("SELECT 1 +   ", Some(11)),
+        ] {
+            match parse_sql_to_statement(&query.to_string(), DatabaseProtocol::PostgreSQL) {
+                Err(CompilationError::SyntaxError(_, position)) => {
+                    assert_eq!(position, expected_position, "{}", query)
+                }
+                _ => panic!("Syntax error is expected: {}", query),
+            }
+        }
+
+        assert_eq!(
+            syntax_error_position(
+                "SELECT 1,\n'abc",
+                &ParserError::TokenizerError(
+                    "Unterminated string literal at Line: 2, Column 8".to_string()
+                )
+            ),
+            Some(18)
+        );
+        // Token, which is found twice, is ambiguous
+        assert_eq!(
+            syntax_error_position(
+                "SELECT a a",
+                &ParserError::ParserError("Expected end of statement, found: a".to_string())
+            ),
+            None
+        );
+    }
 
     #[test]
     fn test_no_statements_mysql() {
@@ -182,4 +644,265 @@ mod tests {
             Err(err) => panic!("{}", err),
         }
     }
+
+    #[test]
+    fn test_standard_conforming_strings_postgres() {
+        // standard_conforming_strings = on, backslash is a regular character
+        let result =
+            parse_sql_to_statement(&"SELECT 'a\\b'".to_string(), DatabaseProtocol::PostgreSQL)
+                .unwrap();
+
+        assert_eq!(result.to_string(), "SELECT 'a\\b'");
+    }
+
+    #[test]
+    fn test_transaction_statements_postgres() {
+        let parse = |query: &str| {
+            parse_sql_to_statement(&query.to_string(), DatabaseProtocol::PostgreSQL)
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(parse("END TRANSACTION;"), "COMMIT");
+        assert_eq!(parse("SAVEPOINT sp1"), "SAVEPOINT sp1");
+        assert_eq!(parse("ROLLBACK"), "ROLLBACK");
+    }
+
+    #[test]
+    fn test_transaction_command_postgres() {
+        match parse_transaction_command("set local statement_timeout = 0;").unwrap() {
+            Some(TransactionCommand::SetLocal(key_values)) => {
+                assert_eq!(key_values.len(), 1);
+                assert_eq!(key_values[0].key.value, "statement_timeout");
+            }
+            command => panic!("Unexpected command: {:?}", command),
+        }
+
+        assert_eq!(
+            parse_transaction_command("release sp1").unwrap(),
+            Some(TransactionCommand::ReleaseSavepoint(Ident::new("sp1")))
+        );
+        assert_eq!(
+            parse_transaction_command("ROLLBACK WORK TO SAVEPOINT sp1;").unwrap(),
+            Some(TransactionCommand::RollbackToSavepoint(Ident::new("sp1")))
+        );
+
+        for query in [
+            "SET statement_timeout = 0",
+            "SET \"__cubesql_local.statement_timeout\" = 0",
+            "SAVEPOINT \"__cubesql_release.sp1\"",
+            "ROLLBACK",
+        ] {
+            assert_eq!(parse_transaction_command(query).unwrap(), None, "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_prepared_statement_command_postgres() {
+        let parse = |query: &str| match parse_prepared_statement_command(query).unwrap() {
+            Some(PreparedStatementCommand::Statement(statement)) => Some(statement),
+            Some(command) => panic!("Unexpected command: {:?}", command),
+            None => None,
+        };
+
+        match parse("DEALLOCATE PREPARE s1;") {
+            Some(Statement::Deallocate { name, prepare }) => {
+                assert_eq!(name.value, "s1");
+                assert_eq!(prepare, true);
+            }
+            stmt => panic!("Unexpected statement: {:?}", stmt),
+        }
+
+        assert_eq!(
+            parse(" deallocate ALL").map(|stmt| stmt.to_string()),
+            Some("DEALLOCATE ALL".to_string())
+        );
+        assert_eq!(
+            parse_prepared_statement_command("DISCARD ALL;").unwrap(),
+            Some(PreparedStatementCommand::DiscardAll)
+        );
+        // Name of the statement is not a command
+        assert_eq!(
+            parse("DEALLOCATE __cubesql_discard_all").map(|stmt| stmt.to_string()),
+            Some("DEALLOCATE __cubesql_discard_all".to_string())
+        );
+        match parse("PREPARE s1 (int) AS SELECT $1") {
+            Some(Statement::Prepare {
+                name, data_types, ..
+            }) => {
+                assert_eq!(name.value, "s1");
+                assert_eq!(data_types.len(), 1);
+            }
+            stmt => panic!("Unexpected statement: {:?}", stmt),
+        }
+        assert_eq!(
+            parse("EXECUTE s1 (1, 'a')").map(|stmt| stmt.to_string()),
+            Some("EXECUTE s1(1, 'a')".to_string())
+        );
+        assert!(parse("SELECT 1").is_none());
+    }
+
+    #[test]
+    fn test_listen_command_postgres() {
+        assert_eq!(
+            parse_listen_command("LISTEN Orders;"),
+            Some(ListenCommand::Listen("orders".to_string()))
+        );
+        assert_eq!(
+            parse_listen_command("listen \"My \"\"Channel\"\"\""),
+            Some(ListenCommand::Listen("My \"Channel\"".to_string()))
+        );
+        assert_eq!(
+            parse_listen_command("UNLISTEN orders"),
+            Some(ListenCommand::Unlisten(Some("orders".to_string())))
+        );
+        assert_eq!(
+            parse_listen_command("UNLISTEN *;"),
+            Some(ListenCommand::Unlisten(None))
+        );
+        assert_eq!(
+            parse_listen_command("NOTIFY orders, 'it''s new'"),
+            Some(ListenCommand::Notify {
+                channel: "orders".to_string(),
+                payload: "it's new".to_string()
+            })
+        );
+        assert_eq!(
+            parse_listen_command("notify orders;"),
+            Some(ListenCommand::Notify {
+                channel: "orders".to_string(),
+                payload: "".to_string()
+            })
+        );
+        assert_eq!(parse_listen_command("LISTEN *"), None);
+        assert_eq!(parse_listen_command("SELECT 1"), None);
+    }
+
+    #[test]
+    fn test_copy_to_statement_postgres() {
+        let stmt = parse_copy_to_statement(
+            "COPY (SELECT a, (b) FROM t WHERE c = ')') TO STDOUT WITH (FORMAT csv, HEADER true);",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(stmt.query.to_string(), "SELECT a, (b) FROM t WHERE c = ')'");
+        assert_eq!(
+            stmt.options,
+            vec![
+                CopyOption::Format(Ident::new("csv")),
+                CopyOption::Header(true)
+            ]
+        );
+        assert!(stmt.legacy_options.is_empty());
+
+        let stmt = parse_copy_to_statement("copy (select 1) to stdout csv header")
+            .unwrap()
+            .unwrap();
+        assert_eq!(stmt.query.to_string(), "SELECT 1");
+        assert_eq!(
+            stmt.legacy_options,
+            vec![CopyLegacyOption::Csv(vec![CopyLegacyCsvOption::Header])]
+        );
+
+        let stmt = parse_copy_to_statement("COPY (SELECT 1) TO STDOUT")
+            .unwrap()
+            .unwrap();
+        assert!(stmt.options.is_empty() && stmt.legacy_options.is_empty());
+
+        assert!(parse_copy_to_statement("COPY (SELECT 1) TO STDOUT WITH (FORMAT").is_err());
+
+        // Table with columns, as psql \copy sends it
+        let stmt = parse_copy_to_statement(
+            "COPY \"KibanaSampleDataEcommerce\" (customer_gender, count) TO STDOUT WITH (FORMAT csv)",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            stmt.query.to_string(),
+            "SELECT customer_gender, count FROM \"KibanaSampleDataEcommerce\""
+        );
+        assert_eq!(stmt.options, vec![CopyOption::Format(Ident::new("csv"))]);
+
+        let stmt = parse_copy_to_statement("copy pg_catalog.pg_type to stdout")
+            .unwrap()
+            .unwrap();
+        assert_eq!(stmt.query.to_string(), "SELECT * FROM pg_catalog.pg_type");
+        assert!(parse_copy_to_statement("COPY t TO STDOUT")
+            .unwrap()
+            .is_none());
+        assert!(parse_copy_to_statement("SELECT 1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_psql_describe_workarounds_postgres() {
+        let stmt = parse_sql_to_statement(
+            &"SELECT c.oid, n.nspname, c.relname FROM pg_catalog.pg_class c LEFT JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace WHERE c.relname OPERATOR(pg_catalog.~) '^(logs)$' COLLATE pg_catalog.default AND pg_catalog.pg_table_is_visible(c.oid) ORDER BY 2, 3;".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .unwrap();
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT c.oid, n.nspname, c.relname FROM pg_catalog.pg_class AS c LEFT JOIN pg_catalog.pg_namespace AS n ON n.oid = c.relnamespace WHERE c.relname ~ '^(logs)$' AND pg_catalog.pg_table_is_visible(c.oid) ORDER BY 2, 3"
+        );
+
+        let stmt = parse_sql_to_statement(
+            &"SELECT a.attname,
+  pg_catalog.format_type(a.atttypid, a.atttypmod),
+  (SELECT pg_catalog.pg_get_expr(d.adbin, d.adrelid, true)
+   FROM pg_catalog.pg_attrdef d
+   WHERE d.adrelid = a.attrelid AND d.adnum = a.attnum AND a.atthasdef),
+  a.attnotnull,
+  (SELECT c.collname FROM pg_catalog.pg_collation c, pg_catalog.pg_type t
+   WHERE c.oid = a.attcollation AND t.oid = a.atttypid AND a.attcollation <> t.typcollation) AS attcollation,
+  a.attidentity,
+  a.attgenerated
+FROM pg_catalog.pg_attribute a
+WHERE a.attrelid = '18000' AND a.attnum > 0 AND NOT a.attisdropped
+ORDER BY a.attnum;"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .unwrap();
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT a.attname, pg_catalog.format_type(a.atttypid, a.atttypmod), (SELECT pg_catalog.pg_get_expr(d.adbin, d.adrelid, true) FROM pg_catalog.pg_attrdef AS d WHERE d.adrelid = a.attrelid AND d.adnum = a.attnum AND a.atthasdef), a.attnotnull, CAST(NULL AS TEXT) AS attcollation, a.attidentity, a.attgenerated FROM pg_catalog.pg_attribute AS a WHERE a.attrelid = 18000 AND a.attnum > 0 AND NOT a.attisdropped ORDER BY a.attnum"
+        );
+
+        // Other queries are not rewritten, oid can be compared with text
+        let stmt = parse_sql_to_statement(
+            &"SELECT c.relname FROM pg_catalog.pg_class c WHERE c.oid = '18000'".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .unwrap();
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT c.relname FROM pg_catalog.pg_class AS c WHERE c.oid = '18000'"
+        );
+
+        // Position of the syntax error is in the original query
+        let query = "SELECT c.oid, n.nspname, c.relname FROM pg_catalog.pg_class c LEFT JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace WHERE c.relname OPERATOR(pg_catalog.~) '^(logs)$' COLLATE pg_catalog.default AND pg_catalog.pg_table_is_visible(c.oid) ORDER BY 2, 3 garbage";
+        match parse_sql_to_statement(&query.to_string(), DatabaseProtocol::PostgreSQL) {
+            Err(CompilationError::SyntaxError(_, position)) => {
+                assert_eq!(position, Some(query.find("garbage").unwrap() + 1))
+            }
+            result => panic!("Syntax error is expected: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_rewritten_query_original_position() {
+        let mut query = RewrittenQuery::new("SELECT a OPERATOR(pg_catalog.~) b, c".to_string());
+        query.replace_all(
+            &Regex::new(r"OPERATOR\(pg_catalog\.([^)]+)\)").unwrap(),
+            "$1",
+        );
+        query.replace_all(&Regex::new(r"\bb\b").unwrap(), "bbb");
+        assert_eq!(query.query, "SELECT a ~ bbb, c");
+
+        // Before, inside and after replacements
+        assert_eq!(query.original_position(1), 1);
+        assert_eq!(query.original_position(10), 10);
+        assert_eq!(query.original_position(13), 33);
+        assert_eq!(query.original_position(17), 36);
+    }
 }
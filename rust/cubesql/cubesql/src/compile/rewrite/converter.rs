@@ -1,3 +1,4 @@
+use crate::compile::engine::df::coerce::coalesce_coercion;
 use crate::compile::engine::df::scan::CubeScanNode;
 use crate::compile::engine::provider::CubeContext;
 use crate::compile::rewrite::analysis::LogicalPlanAnalysis;
@@ -10,8 +11,11 @@ use crate::compile::rewrite::BetweenExprNegated;
 use crate::compile::rewrite::BinaryExprOp;
 use crate::compile::rewrite::CastExprDataType;
 use crate::compile::rewrite::ColumnExprColumn;
+use crate::compile::rewrite::CubeJoinAliasToCube;
 use crate::compile::rewrite::CubeScanAliases;
 use crate::compile::rewrite::CubeScanLimit;
+use crate::compile::rewrite::CubeScanTableName;
+use crate::compile::rewrite::CubeScanUngrouped;
 use crate::compile::rewrite::DimensionName;
 use crate::compile::rewrite::EmptyRelationProduceOneRow;
 use crate::compile::rewrite::FilterMemberMember;
@@ -60,6 +64,7 @@ use cubeclient::models::{
 };
 use datafusion::arrow::datatypes::{DataType, TimeUnit};
 use datafusion::catalog::TableReference;
+use datafusion::error::DataFusionError;
 use datafusion::logical_plan::build_table_udf_schema;
 use datafusion::logical_plan::plan::Filter;
 use datafusion::logical_plan::plan::Join;
@@ -70,7 +75,7 @@ use datafusion::logical_plan::plan::{Extension, TableUDFs};
 use datafusion::logical_plan::Union;
 use datafusion::logical_plan::{
     build_join_schema, exprlist_to_fields, normalize_cols, DFField, DFSchema, DFSchemaRef, Expr,
-    LogicalPlan, LogicalPlanBuilder,
+    ExprRewritable, ExprRewriter, ExprSchemable, LogicalPlan, LogicalPlanBuilder,
 };
 use datafusion::logical_plan::{CrossJoin, EmptyRelation, Limit, TableScan};
 use datafusion::physical_plan::planner::DefaultPhysicalPlanner;
@@ -841,6 +846,61 @@ impl LanguageToLogicalPlanConverter {
         node_to_expr(node, &self.cube_context, &to_expr, &self.best_expr)
     }
 
+    fn cube_scan_params(&self, id: Id) -> Option<&[Id]> {
+        let node_by_id = &self.best_expr;
+        match node_by_id.index(id) {
+            LogicalPlanLanguage::Extension(params) => match node_by_id.index(params[0]) {
+                LogicalPlanLanguage::CubeScan(cube_scan_params) => Some(&cube_scan_params[..]),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Cubes scanned by CubeScan with the given id, if it's a CubeScan
+    fn cube_scan_cubes(&self, id: Id) -> Option<String> {
+        let cube_scan_params = self.cube_scan_params(id)?;
+        match self.best_expr.index(cube_scan_params[0]) {
+            LogicalPlanLanguage::TableScanSourceTableName(TableScanSourceTableName(cube)) => {
+                Some(cube.to_string())
+            }
+            LogicalPlanLanguage::CubeJoinAliasToCube(CubeJoinAliasToCube(alias_to_cube)) => {
+                Some(alias_to_cube.iter().map(|(_, cube)| cube).join(", "))
+            }
+            _ => None,
+        }
+    }
+
+    /// Fields of CubeScan aren't qualified, so the scan is aliased by its table name to be
+    /// referenced by JOIN condition and nodes above it
+    fn join_input(&self, id: Id) -> Result<Arc<LogicalPlan>, CubeError> {
+        let input = Arc::new(self.to_logical_plan(id)?);
+        let table_name = match self.cube_scan_params(id) {
+            Some(cube_scan_params) => {
+                match_data_node!(self.best_expr, cube_scan_params[7], CubeScanTableName)
+            }
+            None => return Ok(input),
+        };
+        let expr = input
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| Expr::Column(field.qualified_column()))
+            .collect::<Vec<_>>();
+        let schema = input
+            .schema()
+            .as_ref()
+            .clone()
+            .replace_qualifier(&table_name);
+
+        Ok(Arc::new(LogicalPlan::Projection(Projection {
+            expr,
+            input,
+            alias: Some(table_name),
+            schema: DFSchemaRef::new(schema),
+        })))
+    }
+
     pub fn to_logical_plan(&self, id: Id) -> Result<LogicalPlan, CubeError> {
         let node_by_id = &self.best_expr;
         let node = node_by_id.index(id);
@@ -849,6 +909,7 @@ impl LanguageToLogicalPlanConverter {
             LogicalPlanLanguage::Projection(params) => {
                 let expr = match_expr_list_node!(node_by_id, to_expr, params[0], ProjectionExpr);
                 let input = Arc::new(self.to_logical_plan(params[1])?);
+                let expr = cast_null_case_branches(expr, input.schema())?;
                 let alias = match_data_node!(node_by_id, params[2], ProjectionAlias);
                 let input_schema = DFSchema::new_with_metadata(
                     exprlist_to_fields(&expr, input.schema())?,
@@ -914,8 +975,19 @@ impl LanguageToLogicalPlanConverter {
                 LogicalPlan::Sort(Sort { expr, input })
             }
             LogicalPlanLanguage::Join(params) => {
-                let left = Arc::new(self.to_logical_plan(params[0])?);
-                let right = Arc::new(self.to_logical_plan(params[1])?);
+                if let (Some(left_cubes), Some(right_cubes)) = (
+                    self.cube_scan_cubes(params[0]),
+                    self.cube_scan_cubes(params[1]),
+                ) {
+                    log::warn!(
+                        "JOIN of {} and {} can't be pushed down to Cube and is executed by SQL API. \
+                        Cubes should be joined in the data model and compared by dimensions in the ON condition",
+                        left_cubes,
+                        right_cubes
+                    );
+                }
+                let left = self.join_input(params[0])?;
+                let right = self.join_input(params[1])?;
                 let left_on = match_data_node!(node_by_id, params[2], JoinLeftOn);
                 let right_on = match_data_node!(node_by_id, params[3], JoinRightOn);
                 let join_type = match_data_node!(node_by_id, params[4], JoinJoinType);
@@ -1328,6 +1400,18 @@ impl LanguageToLogicalPlanConverter {
                         query.limit =
                             match_data_node!(node_by_id, cube_scan_params[4], CubeScanLimit)
                                 .map(|n| n as i32);
+                        // Measures can't be selected without aggregation, such scan stays grouped
+                        let ungrouped =
+                            match_data_node!(node_by_id, cube_scan_params[8], CubeScanUngrouped);
+                        if ungrouped
+                            && query
+                                .measures
+                                .as_ref()
+                                .map(|m| m.is_empty())
+                                .unwrap_or(true)
+                        {
+                            query.ungrouped = Some(true);
+                        }
 
                         let aliases =
                             match_data_node!(node_by_id, cube_scan_params[6], CubeScanAliases);
@@ -1368,6 +1452,7 @@ impl LanguageToLogicalPlanConverter {
                     .into_iter()
                     .map(|n| self.to_logical_plan(n))
                     .collect::<Result<Vec<_>, _>>()?;
+                let inputs = cast_null_union_columns(inputs)?;
 
                 let schema = inputs[0].schema().as_ref().clone();
 
@@ -1406,3 +1491,138 @@ impl LanguageToLogicalPlanConverter {
         })
     }
 }
+
+/// DataFusion takes the type of CASE from its first branch, so NULL branches are cast
+/// to the type unified from the other branches, like PostgreSQL does
+struct CastNullCaseBranches<'a> {
+    schema: &'a DFSchema,
+}
+
+impl<'a> CastNullCaseBranches<'a> {
+    fn cast_null(
+        &self,
+        expr: Box<Expr>,
+        data_type: &DataType,
+    ) -> Result<Box<Expr>, DataFusionError> {
+        Ok(match expr.get_type(self.schema)? {
+            DataType::Null => Box::new(Expr::Cast {
+                expr,
+                data_type: data_type.clone(),
+            }),
+            _ => expr,
+        })
+    }
+}
+
+impl<'a> ExprRewriter for CastNullCaseBranches<'a> {
+    fn mutate(&mut self, expr: Expr) -> Result<Expr, DataFusionError> {
+        match expr {
+            Expr::Case {
+                expr,
+                when_then_expr,
+                else_expr,
+            } => {
+                let types = when_then_expr
+                    .iter()
+                    .map(|(_, then)| then)
+                    .chain(else_expr.iter())
+                    .map(|e| e.get_type(self.schema))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let data_type = match coalesce_coercion(&types) {
+                    Some(DataType::Null) | None => {
+                        return Ok(Expr::Case {
+                            expr,
+                            when_then_expr,
+                            else_expr,
+                        })
+                    }
+                    Some(data_type) => data_type,
+                };
+
+                Ok(Expr::Case {
+                    expr,
+                    when_then_expr: when_then_expr
+                        .into_iter()
+                        .map(|(when, then)| Ok((when, self.cast_null(then, &data_type)?)))
+                        .collect::<Result<Vec<_>, DataFusionError>>()?,
+                    else_expr: else_expr
+                        .map(|e| self.cast_null(e, &data_type))
+                        .transpose()?,
+                })
+            }
+            e => Ok(e),
+        }
+    }
+}
+
+/// Changed expressions keep their original names to be referenced by the nodes above
+fn cast_null_case_branches(expr: Vec<Expr>, schema: &DFSchema) -> Result<Vec<Expr>, CubeError> {
+    expr.into_iter()
+        .map(|e| -> Result<Expr, CubeError> {
+            let casted = e.clone().rewrite(&mut CastNullCaseBranches { schema })?;
+            Ok(match casted {
+                casted if casted == e => e,
+                casted @ Expr::Alias(_, _) => casted,
+                casted => casted.alias(&e.name(schema)?),
+            })
+        })
+        .collect()
+}
+
+/// DataFusion takes the schema of UNION from its first input, so NULL columns of every input
+/// are cast to the type unified from the other inputs
+fn cast_null_union_columns(inputs: Vec<LogicalPlan>) -> Result<Vec<LogicalPlan>, CubeError> {
+    let column_types = (0..inputs[0].schema().fields().len())
+        .map(|i| {
+            coalesce_coercion(
+                &inputs
+                    .iter()
+                    .filter_map(|input| input.schema().fields().get(i))
+                    .map(|field| field.data_type().clone())
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    inputs
+        .into_iter()
+        .map(|input| -> Result<LogicalPlan, CubeError> {
+            let (expr, fields): (Vec<_>, Vec<_>) = input
+                .schema()
+                .fields()
+                .iter()
+                .zip(column_types.iter())
+                .map(|(field, ty)| match ty {
+                    Some(data_type)
+                        if field.data_type() == &DataType::Null && data_type != &DataType::Null =>
+                    {
+                        (
+                            Expr::Cast {
+                                expr: Box::new(Expr::Column(field.qualified_column())),
+                                data_type: data_type.clone(),
+                            }
+                            .alias(field.name()),
+                            DFField::new(
+                                field.qualifier().map(|q| q.as_str()),
+                                field.name(),
+                                data_type.clone(),
+                                true,
+                            ),
+                        )
+                    }
+                    _ => (Expr::Column(field.qualified_column()), field.clone()),
+                })
+                .unzip();
+            if fields == *input.schema().fields() {
+                return Ok(input);
+            }
+
+            Ok(LogicalPlan::Projection(Projection {
+                expr,
+                input: Arc::new(input),
+                schema: Arc::new(DFSchema::new_with_metadata(fields, HashMap::new())?),
+                alias: None,
+            }))
+        })
+        .collect()
+}
@@ -26,6 +26,9 @@ impl CostFunction<LogicalPlanLanguage> for BestCubePlan {
         let this_replacers = match enode {
             LogicalPlanLanguage::MemberReplacer(_) => 1,
             LogicalPlanLanguage::FilterReplacer(_) => 1,
+            // Sort and projection, which can't be fully translated to Cube members, stay local
+            LogicalPlanLanguage::OrderReplacer(_) => 1,
+            LogicalPlanLanguage::ColumnAliasReplacer(_) => 1,
             LogicalPlanLanguage::TimeDimensionDateRangeReplacer(_) => 1,
             LogicalPlanLanguage::InnerAggregateSplitReplacer(_) => 1,
             LogicalPlanLanguage::OuterProjectionSplitReplacer(_) => 1,
@@ -36,6 +39,8 @@ impl CostFunction<LogicalPlanLanguage> for BestCubePlan {
         let this_cube_structure = match enode {
             // TODO needed to get rid of FilterOpFilters on upper level
             LogicalPlanLanguage::FilterOpFilters(_) => 1,
+            // Filters left above CubeScan are evaluated locally
+            LogicalPlanLanguage::Filter(_) => 1,
             _ => 0,
         };
         enode.children().iter().fold(
@@ -207,6 +207,10 @@ crate::plan_to_language! {
             offset: Option<usize>,
             aliases: Option<Vec<String>>,
             table_name: String,
+            ungrouped: bool,
+        },
+        CubeJoin {
+            alias_to_cube: Vec<(String, String)>,
         },
         Measure {
             name: String,
@@ -579,6 +583,20 @@ fn filter(expr: impl Display, input: impl Display) -> String {
     format!("(Filter {} {})", expr, input)
 }
 
+fn join(
+    left: impl Display,
+    right: impl Display,
+    left_on: impl Display,
+    right_on: impl Display,
+    join_type: impl Display,
+    join_constraint: impl Display,
+) -> String {
+    format!(
+        "(Join {} {} {} {} {} {})",
+        left, right, left_on, right_on, join_type, join_constraint
+    )
+}
+
 fn column_alias_replacer(
     members: impl Display,
     aliases: impl Display,
@@ -708,10 +726,11 @@ fn cube_scan(
     offset: impl Display,
     aliases: impl Display,
     table_name: impl Display,
+    ungrouped: impl Display,
 ) -> String {
     format!(
-        "(Extension (CubeScan {} {} {} {} {} {} {} {}))",
-        source_table_name, members, filters, orders, limit, offset, aliases, table_name
+        "(Extension (CubeScan {} {} {} {} {} {} {} {} {}))",
+        source_table_name, members, filters, orders, limit, offset, aliases, table_name, ungrouped
     )
 }
 
@@ -12,11 +12,11 @@ use crate::compile::rewrite::TimeDimensionDateRangeReplacerDateRange;
 use crate::compile::rewrite::TimeDimensionDateRangeReplacerMember;
 use crate::compile::rewrite::TimeDimensionGranularity;
 use crate::compile::rewrite::TimeDimensionName;
-use crate::compile::rewrite::{between_expr, FilterMemberMember};
 use crate::compile::rewrite::{
-    binary_expr, column_expr, cube_scan, cube_scan_filters, filter, filter_member, filter_op,
-    filter_op_filters, filter_replacer, literal_expr, rewrite, transforming_rewrite,
+    aggregate, binary_expr, column_expr, cube_scan, cube_scan_filters, filter, filter_member,
+    filter_op, filter_op_filters, filter_replacer, literal_expr, rewrite, transforming_rewrite,
 };
+use crate::compile::rewrite::{between_expr, FilterMemberMember};
 use crate::compile::rewrite::{cast_expr, FilterReplacerCube};
 use crate::compile::rewrite::{
     cube_scan_filters_empty_tail, cube_scan_members, dimension_expr, measure_expr,
@@ -32,9 +32,11 @@ use crate::transport::MemberType;
 use crate::var;
 use crate::var_iter;
 use chrono::{SecondsFormat, TimeZone, Utc};
-use datafusion::logical_plan::{Column, Operator};
+use datafusion::logical_plan::{Column, Expr, Operator};
+use datafusion::optimizer::utils::expr_to_columns;
 use datafusion::scalar::ScalarValue;
-use egg::{EGraph, Rewrite, Subst};
+use egg::{EGraph, Id, Rewrite, Subst};
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::ops::Index;
 use std::sync::Arc;
@@ -59,6 +61,7 @@ impl RewriteRules for FilterRules {
                         "?offset",
                         "?aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                 ),
                 cube_scan(
@@ -70,9 +73,160 @@ impl RewriteRules for FilterRules {
                     "?offset",
                     "?aliases",
                     "?table_name",
+                    "?ungrouped",
                 ),
                 self.push_down_filter("?source_table_name", "?expr", "?cube"),
             ),
+            // Conjunctions are split, so predicates which can't be pushed down stay local
+            rewrite(
+                "split-filter-and-left",
+                filter(
+                    binary_expr("?left", "AND", "?right"),
+                    cube_scan(
+                        "?source_table_name",
+                        "?members",
+                        "?filters",
+                        "?order",
+                        "?limit",
+                        "?offset",
+                        "?aliases",
+                        "?table_name",
+                        "?ungrouped",
+                    ),
+                ),
+                filter(
+                    "?right",
+                    filter(
+                        "?left",
+                        cube_scan(
+                            "?source_table_name",
+                            "?members",
+                            "?filters",
+                            "?order",
+                            "?limit",
+                            "?offset",
+                            "?aliases",
+                            "?table_name",
+                            "?ungrouped",
+                        ),
+                    ),
+                ),
+            ),
+            transforming_rewrite(
+                "split-filter-and-right",
+                filter(
+                    binary_expr("?left", "AND", "?right"),
+                    cube_scan(
+                        "?source_table_name",
+                        "?members",
+                        "?filters",
+                        "?order",
+                        "?limit",
+                        "?offset",
+                        "?aliases",
+                        "?table_name",
+                        "?ungrouped",
+                    ),
+                ),
+                filter(
+                    "?left",
+                    filter(
+                        "?right",
+                        cube_scan(
+                            "?source_table_name",
+                            "?members",
+                            "?filters",
+                            "?order",
+                            "?limit",
+                            "?offset",
+                            "?aliases",
+                            "?table_name",
+                            "?ungrouped",
+                        ),
+                    ),
+                ),
+                self.split_filter_and_right("?left"),
+            ),
+            transforming_rewrite(
+                "pull-up-residual-filter-aggregate",
+                aggregate(
+                    filter(
+                        "?expr",
+                        cube_scan(
+                            "?source_table_name",
+                            cube_scan_members_empty_tail(),
+                            "?filters",
+                            "?order",
+                            "?limit",
+                            "?offset",
+                            "?aliases",
+                            "?table_name",
+                            "?ungrouped",
+                        ),
+                    ),
+                    "?group_expr",
+                    "?aggr_expr",
+                ),
+                filter(
+                    "?expr",
+                    aggregate(
+                        cube_scan(
+                            "?source_table_name",
+                            cube_scan_members_empty_tail(),
+                            "?filters",
+                            "?order",
+                            "?limit",
+                            "?offset",
+                            "?aliases",
+                            "?table_name",
+                            "?ungrouped",
+                        ),
+                        "?group_expr",
+                        "?aggr_expr",
+                    ),
+                ),
+                self.pull_up_residual_filter("?expr", "?group_expr"),
+            ),
+            transforming_rewrite(
+                "pull-up-residual-filter-projection",
+                projection(
+                    "?projection_expr",
+                    filter(
+                        "?expr",
+                        cube_scan(
+                            "?source_table_name",
+                            cube_scan_members_empty_tail(),
+                            "?filters",
+                            "?order",
+                            "?limit",
+                            "?offset",
+                            "?aliases",
+                            "?table_name",
+                            "?ungrouped",
+                        ),
+                    ),
+                    "ProjectionAlias:None",
+                ),
+                filter(
+                    "?expr",
+                    projection(
+                        "?projection_expr",
+                        cube_scan(
+                            "?source_table_name",
+                            cube_scan_members_empty_tail(),
+                            "?filters",
+                            "?order",
+                            "?limit",
+                            "?offset",
+                            "?aliases",
+                            "?table_name",
+                            "?ungrouped",
+                        ),
+                        "ProjectionAlias:None",
+                    ),
+                ),
+                self.pull_up_residual_filter("?expr", "?projection_expr"),
+            ),
             transforming_rewrite(
                 "push-down-limit-filter",
                 filter(
@@ -86,6 +240,7 @@ impl RewriteRules for FilterRules {
                         "?offset",
                         "?aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                 ),
                 limit(
@@ -99,6 +254,7 @@ impl RewriteRules for FilterRules {
                         "?offset",
                         "?aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                 ),
                 self.push_down_limit_filter("?literal", "?new_limit", "?new_limit_n"),
@@ -118,6 +274,7 @@ impl RewriteRules for FilterRules {
                             "?offset",
                             "?aliases",
                             "?table_name",
+                            "?ungrouped",
                         ),
                     ),
                 ),
@@ -134,6 +291,7 @@ impl RewriteRules for FilterRules {
                             "?offset",
                             "?aliases",
                             "?table_name",
+                            "?ungrouped",
                         ),
                     ),
                 ),
@@ -153,6 +311,7 @@ impl RewriteRules for FilterRules {
                             "?offset",
                             "?aliases",
                             "?table_name",
+                            "?ungrouped",
                         ),
                     ),
                     "?alias",
@@ -170,6 +329,7 @@ impl RewriteRules for FilterRules {
                             "?offset",
                             "?aliases",
                             "?table_name",
+                            "?ungrouped",
                         ),
                         "?alias",
                     ),
@@ -262,6 +422,38 @@ impl RewriteRules for FilterRules {
                     "?filter_values",
                 ),
             ),
+            transforming_rewrite(
+                "filter-replacer-between-numbers",
+                filter_replacer(
+                    between_expr(column_expr("?column"), "?negated", "?low", "?high"),
+                    "?cube",
+                ),
+                filter_replacer(
+                    binary_expr(
+                        binary_expr(column_expr("?column"), ">=", "?low"),
+                        "AND",
+                        binary_expr(column_expr("?column"), "<=", "?high"),
+                    ),
+                    "?cube",
+                ),
+                self.transform_between_numbers("?column", "?negated", "?cube", false),
+            ),
+            transforming_rewrite(
+                "filter-replacer-not-between-numbers",
+                filter_replacer(
+                    between_expr(column_expr("?column"), "?negated", "?low", "?high"),
+                    "?cube",
+                ),
+                filter_replacer(
+                    binary_expr(
+                        binary_expr(column_expr("?column"), "<", "?low"),
+                        "OR",
+                        binary_expr(column_expr("?column"), ">", "?high"),
+                    ),
+                    "?cube",
+                ),
+                self.transform_between_numbers("?column", "?negated", "?cube", true),
+            ),
             rewrite(
                 "filter-replacer-and",
                 filter_replacer(binary_expr("?left", "AND", "?right"), "?cube"),
@@ -394,6 +586,7 @@ impl RewriteRules for FilterRules {
                     "?offset",
                     "?aliases",
                     "?table_name",
+                    "?ungrouped",
                 ),
                 cube_scan(
                     "?source_table_name",
@@ -408,6 +601,7 @@ impl RewriteRules for FilterRules {
                     "?offset",
                     "?aliases",
                     "?table_name",
+                    "?ungrouped",
                 ),
             ),
             transforming_rewrite(
@@ -670,7 +864,7 @@ impl FilterRules {
                                                 }
                                             }
                                         }
-                                        x => panic!("Unsupported filter scalar: {:?}", x),
+                                        _ => continue,
                                     };
 
                                     subst.insert(
@@ -788,12 +982,16 @@ impl FilterRules {
                     .as_ref()
                     .and_then(|cube| meta_context.find_cube_with_name(cube.to_string()))
                 {
-                    if let Some(list) = &egraph[subst[list_var]].data.constant_in_list {
-                        let values = list
-                            .into_iter()
-                            .map(|literal| FilterRules::scalar_to_value(literal))
-                            .collect::<Vec<_>>();
-
+                    if let Some(values) = egraph[subst[list_var]]
+                        .data
+                        .constant_in_list
+                        .as_ref()
+                        .and_then(|list| {
+                            list.iter()
+                                .map(|literal| FilterRules::scalar_to_value(literal))
+                                .collect::<Option<Vec<_>>>()
+                        })
+                    {
                         for column in var_iter!(egraph[subst[column_var]], ColumnExprColumn) {
                             let member_name = format!("{}.{}", cube.name, column.name);
                             if cube.contains_member(&member_name) {
@@ -838,14 +1036,18 @@ impl FilterRules {
         }
     }
 
-    fn scalar_to_value(literal: &ScalarValue) -> String {
-        match literal {
+    fn scalar_to_value(literal: &ScalarValue) -> Option<String> {
+        Some(match literal {
             ScalarValue::Utf8(Some(value)) => value.to_string(),
             ScalarValue::Int64(Some(value)) => value.to_string(),
             ScalarValue::Boolean(Some(value)) => value.to_string(),
             ScalarValue::Float64(Some(value)) => value.to_string(),
-            x => panic!("Unsupported filter scalar: {:?}", x),
-        }
+            ScalarValue::TimestampNanosecond(Some(value), _) => Utc
+                .timestamp_nanos(*value)
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            // NULL and other values aren't pushed down
+            _ => return None,
+        })
     }
 
     fn transform_is_null(
@@ -935,44 +1137,44 @@ impl FilterRules {
                 {
                     for column in var_iter!(egraph[subst[column_var]], ColumnExprColumn) {
                         let member_name = format!("{}.{}", cube.name, column.name);
-                        if let Some(_) = cube.lookup_dimension(&member_name) {
+                        if let Some(MemberType::Time) = cube.member_type(&member_name) {
                             for negated in var_iter!(egraph[subst[negated_var]], BetweenExprNegated)
                             {
                                 let negated = *negated;
-                                if let Some(low) = &egraph[subst[low_var]].data.constant {
-                                    if let Some(high) = &egraph[subst[high_var]].data.constant {
-                                        let values = vec![
-                                            FilterRules::scalar_to_value(&low),
-                                            FilterRules::scalar_to_value(&high),
-                                        ];
-
-                                        subst.insert(
-                                            filter_member_var,
-                                            egraph.add(LogicalPlanLanguage::FilterMemberMember(
-                                                FilterMemberMember(member_name.to_string()),
-                                            )),
-                                        );
-
-                                        subst.insert(
-                                            filter_op_var,
-                                            egraph.add(LogicalPlanLanguage::FilterMemberOp(
-                                                FilterMemberOp(if negated {
-                                                    "notInDateRange".to_string()
-                                                } else {
-                                                    "inDateRange".to_string()
-                                                }),
-                                            )),
-                                        );
+                                let low = egraph[subst[low_var]].data.constant.as_ref();
+                                let high = egraph[subst[high_var]].data.constant.as_ref();
+                                if let (Some(low), Some(high)) = (
+                                    low.and_then(FilterRules::scalar_to_value),
+                                    high.and_then(FilterRules::scalar_to_value),
+                                ) {
+                                    let values = vec![low, high];
 
-                                        subst.insert(
-                                            filter_values_var,
-                                            egraph.add(LogicalPlanLanguage::FilterMemberValues(
-                                                FilterMemberValues(values),
-                                            )),
-                                        );
+                                    subst.insert(
+                                        filter_member_var,
+                                        egraph.add(LogicalPlanLanguage::FilterMemberMember(
+                                            FilterMemberMember(member_name.to_string()),
+                                        )),
+                                    );
 
-                                        return true;
-                                    }
+                                    subst.insert(
+                                        filter_op_var,
+                                        egraph.add(LogicalPlanLanguage::FilterMemberOp(
+                                            FilterMemberOp(if negated {
+                                                "notInDateRange".to_string()
+                                            } else {
+                                                "inDateRange".to_string()
+                                            }),
+                                        )),
+                                    );
+
+                                    subst.insert(
+                                        filter_values_var,
+                                        egraph.add(LogicalPlanLanguage::FilterMemberValues(
+                                            FilterMemberValues(values),
+                                        )),
+                                    );
+
+                                    return true;
                                 }
                             }
                         }
@@ -984,6 +1186,133 @@ impl FilterRules {
         }
     }
 
+    /// BETWEEN of numbers and strings is pushed down as a pair of comparisons
+    fn transform_between_numbers(
+        &self,
+        column_var: &'static str,
+        negated_var: &'static str,
+        cube_var: &'static str,
+        negated: bool,
+    ) -> impl Fn(&mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>, &mut Subst) -> bool {
+        let column_var = var!(column_var);
+        let negated_var = var!(negated_var);
+        let cube_var = var!(cube_var);
+        let meta_context = self.cube_context.meta.clone();
+        move |egraph, subst| {
+            if !var_iter!(egraph[subst[negated_var]], BetweenExprNegated).any(|n| *n == negated) {
+                return false;
+            }
+
+            for cube in var_iter!(egraph[subst[cube_var]], FilterReplacerCube) {
+                if let Some(cube) = cube
+                    .as_ref()
+                    .and_then(|cube| meta_context.find_cube_with_name(cube.to_string()))
+                {
+                    for column in var_iter!(egraph[subst[column_var]], ColumnExprColumn) {
+                        let member_name = format!("{}.{}", cube.name, column.name);
+                        match cube.member_type(&member_name) {
+                            Some(MemberType::Time) | None => {}
+                            Some(_) => return true,
+                        }
+                    }
+                }
+            }
+
+            false
+        }
+    }
+
+    /// Right-hand side of conjunction is pushed down first only if left-hand side is evaluated
+    /// locally, so the order of pushed down filters is kept otherwise
+    fn split_filter_and_right(
+        &self,
+        left_var: &'static str,
+    ) -> impl Fn(&mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>, &mut Subst) -> bool {
+        let left_var = var!(left_var);
+        move |egraph, subst| {
+            egraph[subst[left_var]]
+                .data
+                .original_expr
+                .as_ref()
+                .map(|expr| Self::is_local_expr(expr))
+                .unwrap_or(false)
+        }
+    }
+
+    fn is_local_expr(expr: &Expr) -> bool {
+        match expr {
+            Expr::Column(_) | Expr::Literal(_) => false,
+            Expr::Cast { expr, .. }
+            | Expr::Not(expr)
+            | Expr::IsNull(expr)
+            | Expr::IsNotNull(expr) => Self::is_local_expr(expr),
+            Expr::BinaryExpr { left, right, .. } => {
+                Self::is_local_expr(left) || Self::is_local_expr(right)
+            }
+            Expr::InList { expr, list, .. } => {
+                Self::is_local_expr(expr) || list.iter().any(|e| Self::is_local_expr(e))
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => Self::is_local_expr(expr) || Self::is_local_expr(low) || Self::is_local_expr(high),
+            _ => true,
+        }
+    }
+
+    /// Filter is moved above the aggregation or projection, if it uses only columns which are
+    /// passed through as is, so the scan below can be pushed down to Cube
+    fn pull_up_residual_filter(
+        &self,
+        expr_var: &'static str,
+        list_var: &'static str,
+    ) -> impl Fn(&mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>, &mut Subst) -> bool {
+        let expr_var = var!(expr_var);
+        let list_var = var!(list_var);
+        move |egraph, subst| {
+            if let Some(expr) = &egraph[subst[expr_var]].data.original_expr {
+                let mut columns = HashSet::new();
+                if expr_to_columns(expr, &mut columns).is_ok() {
+                    let passed_columns = Self::passed_columns(egraph, subst[list_var]);
+
+                    return !passed_columns.is_empty()
+                        && columns.iter().all(|column| passed_columns.contains(column));
+                }
+            }
+
+            false
+        }
+    }
+
+    /// Plain columns of group or projection expressions list
+    fn passed_columns(
+        egraph: &EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>,
+        list: Id,
+    ) -> Vec<Column> {
+        let mut columns = Vec::new();
+        let mut current = list;
+        loop {
+            let params = egraph[current].nodes.iter().find_map(|node| match node {
+                LogicalPlanLanguage::AggregateGroupExpr(params)
+                | LogicalPlanLanguage::ProjectionExpr(params)
+                    if params.len() == 2 =>
+                {
+                    Some((params[0], params[1]))
+                }
+                _ => None,
+            });
+
+            match params {
+                Some((expr, tail)) => {
+                    if let Some(Expr::Column(column)) = &egraph[expr].data.original_expr {
+                        columns.push(column.clone());
+                    }
+                    current = tail;
+                }
+                None => return columns,
+            }
+        }
+    }
+
     fn merge_date_range(
         &self,
         date_range_start_var: &'static str,
@@ -8,10 +8,15 @@ use crate::compile::rewrite::AliasExprAlias;
 use crate::compile::rewrite::ColumnAliasReplacerAliases;
 use crate::compile::rewrite::ColumnAliasReplacerTableName;
 use crate::compile::rewrite::ColumnExprColumn;
+use crate::compile::rewrite::CubeJoinAliasToCube;
 use crate::compile::rewrite::CubeScanAliases;
 use crate::compile::rewrite::CubeScanLimit;
 use crate::compile::rewrite::CubeScanTableName;
+use crate::compile::rewrite::CubeScanUngrouped;
 use crate::compile::rewrite::DimensionName;
+use crate::compile::rewrite::JoinJoinType;
+use crate::compile::rewrite::JoinLeftOn;
+use crate::compile::rewrite::JoinRightOn;
 use crate::compile::rewrite::LimitN;
 use crate::compile::rewrite::LiteralExprValue;
 use crate::compile::rewrite::LogicalPlanLanguage;
@@ -27,8 +32,8 @@ use crate::compile::rewrite::{
     agg_fun_expr, aggr_aggr_expr, aggr_aggr_expr_empty_tail, aggr_group_expr,
     aggr_group_expr_empty_tail, aggregate, alias_expr, column_alias_replacer,
     column_name_to_member_name, cube_scan_members_empty_tail, expr_column_name,
-    expr_column_name_with_relation, fun_expr, limit, member_replacer, projection, projection_expr,
-    projection_expr_empty_tail, sort_expr, udaf_expr, WithColumnRelation,
+    expr_column_name_with_relation, fun_expr, join, limit, member_replacer, projection,
+    projection_expr, projection_expr_empty_tail, sort_expr, udaf_expr, WithColumnRelation,
 };
 use crate::compile::rewrite::{
     binary_expr, column_expr, cube_scan, literal_expr, rewrite, transforming_rewrite,
@@ -38,16 +43,23 @@ use crate::compile::rewrite::{
     time_dimension_expr,
 };
 use crate::compile::rewrite::{cube_scan_order_empty_tail, transforming_chain_rewrite};
-use crate::transport::{V1CubeMetaDimensionExt, V1CubeMetaMeasureExt, V1CubeMetaSegmentExt};
+use crate::transport::{
+    MetaContext, V1CubeMetaDimensionExt, V1CubeMetaExt, V1CubeMetaMeasureExt, V1CubeMetaSegmentExt,
+};
 use crate::var_iter;
 use crate::{var, CubeError};
-use datafusion::logical_plan::{Column, DFSchema};
+use datafusion::logical_plan::{Column, DFSchema, JoinType};
 use datafusion::physical_plan::aggregates::AggregateFunction;
 use datafusion::scalar::ScalarValue;
 use egg::{EGraph, Id, Rewrite, Subst};
 use std::ops::Index;
 use std::sync::Arc;
 
+/// Granularities of time dimensions supported by Cube
+const CUBE_GRANULARITIES: [&str; 8] = [
+    "second", "minute", "hour", "day", "week", "month", "quarter", "year",
+];
+
 pub struct MemberRules {
     cube_context: Arc<CubeContext>,
 }
@@ -73,8 +85,14 @@ impl RewriteRules for MemberRules {
                     "CubeScanOffset:None",
                     "CubeScanAliases:None",
                     "?cube_table_name",
+                    "?ungrouped",
+                ),
+                self.transform_table_scan(
+                    "?source_table_name",
+                    "?table_name",
+                    "?cube_table_name",
+                    "?ungrouped",
                 ),
-                self.transform_table_scan("?source_table_name", "?table_name", "?cube_table_name"),
             ),
             rewrite(
                 "member-replacer-aggr-tail",
@@ -285,6 +303,7 @@ impl RewriteRules for MemberRules {
                         "?offset",
                         "?aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                     "?group_expr",
                     "?aggr_expr",
@@ -301,9 +320,10 @@ impl RewriteRules for MemberRules {
                     "?offset",
                     "?aliases",
                     "?table_name",
+                    "?ungrouped",
                 ),
             ),
-            rewrite(
+            transforming_rewrite(
                 "push-down-projection-to-empty-scan",
                 projection(
                     "?expr",
@@ -316,6 +336,7 @@ impl RewriteRules for MemberRules {
                         "?offset",
                         "?aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                     "?alias",
                 ),
@@ -328,7 +349,9 @@ impl RewriteRules for MemberRules {
                     "?offset",
                     "?aliases",
                     "?table_name",
+                    "?new_ungrouped",
                 ),
+                self.push_down_projection_to_empty_scan("?new_ungrouped"),
             ),
             transforming_rewrite(
                 "push-down-projection",
@@ -343,6 +366,7 @@ impl RewriteRules for MemberRules {
                         "?offset",
                         "?cube_aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                     "?alias",
                 ),
@@ -355,6 +379,7 @@ impl RewriteRules for MemberRules {
                     "?offset",
                     "?cube_aliases",
                     "?new_table_name",
+                    "?ungrouped",
                 ),
                 self.push_down_projection(
                     "?expr",
@@ -367,6 +392,58 @@ impl RewriteRules for MemberRules {
                     "?new_table_name",
                 ),
             ),
+            transforming_rewrite(
+                "push-down-join",
+                join(
+                    cube_scan(
+                        "?left_source_table_name",
+                        cube_scan_members_empty_tail(),
+                        cube_scan_filters_empty_tail(),
+                        cube_scan_order_empty_tail(),
+                        "CubeScanLimit:None",
+                        "CubeScanOffset:None",
+                        "CubeScanAliases:None",
+                        "?left_table_name",
+                        "?ungrouped",
+                    ),
+                    cube_scan(
+                        "?right_source_table_name",
+                        cube_scan_members_empty_tail(),
+                        cube_scan_filters_empty_tail(),
+                        cube_scan_order_empty_tail(),
+                        "CubeScanLimit:None",
+                        "CubeScanOffset:None",
+                        "CubeScanAliases:None",
+                        "?right_table_name",
+                        "?right_ungrouped",
+                    ),
+                    "?left_on",
+                    "?right_on",
+                    "?join_type",
+                    "?join_constraint",
+                ),
+                cube_scan(
+                    "?joined_source_table_name",
+                    cube_scan_members_empty_tail(),
+                    cube_scan_filters_empty_tail(),
+                    cube_scan_order_empty_tail(),
+                    "CubeScanLimit:None",
+                    "CubeScanOffset:None",
+                    "CubeScanAliases:None",
+                    "?left_table_name",
+                    "?ungrouped",
+                ),
+                self.push_down_join(
+                    "?left_source_table_name",
+                    "?left_table_name",
+                    "?right_source_table_name",
+                    "?right_table_name",
+                    "?left_on",
+                    "?right_on",
+                    "?join_type",
+                    "?joined_source_table_name",
+                ),
+            ),
             transforming_rewrite(
                 "limit-push-down",
                 limit(
@@ -380,6 +457,7 @@ impl RewriteRules for MemberRules {
                         "?offset",
                         "?aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                 ),
                 cube_scan(
@@ -391,8 +469,9 @@ impl RewriteRules for MemberRules {
                     "?offset",
                     "?aliases",
                     "?table_name",
+                    "?ungrouped",
                 ),
-                self.push_down_limit("?limit", "?new_limit"),
+                self.push_down_limit("?limit", "?cube_limit", "?new_limit"),
             ),
             rewrite(
                 "alias-replacer-split",
@@ -542,10 +621,12 @@ impl MemberRules {
         var: &'static str,
         table_name_var: &'static str,
         cube_table_name_var: &'static str,
+        ungrouped_var: &'static str,
     ) -> impl Fn(&mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>, &mut Subst) -> bool {
         let var = var!(var);
         let table_name_var = var!(table_name_var);
         let cube_table_name_var = var!(cube_table_name_var);
+        let ungrouped_var = var!(ungrouped_var);
         let meta_context = self.cube_context.meta.clone();
         move |egraph, subst| {
             for name in var_iter!(egraph[subst[var]], TableScanSourceTableName) {
@@ -563,6 +644,12 @@ impl MemberRules {
                                 table_name,
                             ))),
                         );
+                        subst.insert(
+                            ungrouped_var,
+                            egraph.add(LogicalPlanLanguage::CubeScanUngrouped(CubeScanUngrouped(
+                                false,
+                            ))),
+                        );
                         return true;
                     }
                 }
@@ -571,6 +658,30 @@ impl MemberRules {
         }
     }
 
+    /// Projection without aggregation selects rows of the cube as is, it's requested as ungrouped
+    /// query if Cube supports it. Otherwise rows are grouped by dimensions, as before
+    fn push_down_projection_to_empty_scan(
+        &self,
+        ungrouped_var: &'static str,
+    ) -> impl Fn(&mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>, &mut Subst) -> bool {
+        let ungrouped_var = var!(ungrouped_var);
+        let ungrouped = self
+            .cube_context
+            .sessions
+            .server
+            .configuration
+            .ungrouped_queries;
+        move |egraph, subst| {
+            subst.insert(
+                ungrouped_var,
+                egraph.add(LogicalPlanLanguage::CubeScanUngrouped(CubeScanUngrouped(
+                    ungrouped,
+                ))),
+            );
+            true
+        }
+    }
+
     pub fn transform_original_expr_alias(
         original_expr_var: &'static str,
         alias_expr_var: &'static str,
@@ -747,24 +858,92 @@ impl MemberRules {
         }
     }
 
+    fn push_down_join(
+        &self,
+        left_source_var: &'static str,
+        left_table_name_var: &'static str,
+        right_source_var: &'static str,
+        right_table_name_var: &'static str,
+        left_on_var: &'static str,
+        right_on_var: &'static str,
+        join_type_var: &'static str,
+        joined_source_var: &'static str,
+    ) -> impl Fn(&mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>, &mut Subst) -> bool {
+        let left_source_var = var!(left_source_var);
+        let left_table_name_var = var!(left_table_name_var);
+        let right_source_var = var!(right_source_var);
+        let right_table_name_var = var!(right_table_name_var);
+        let left_on_var = var!(left_on_var);
+        let right_on_var = var!(right_on_var);
+        let join_type_var = var!(join_type_var);
+        let joined_source_var = var!(joined_source_var);
+        let meta_context = self.cube_context.meta.clone();
+        move |egraph, subst| {
+            // Cube builds the actual join from the data model, so only INNER and LEFT joins match
+            if !var_iter!(egraph[subst[join_type_var]], JoinJoinType)
+                .any(|join_type| matches!(join_type, JoinType::Inner | JoinType::Left))
+            {
+                return false;
+            }
+
+            let left_cubes =
+                scan_alias_to_cube(egraph, subst[left_source_var], subst[left_table_name_var]);
+            let right_cubes =
+                scan_alias_to_cube(egraph, subst[right_source_var], subst[right_table_name_var]);
+            for left in left_cubes.iter() {
+                for right in right_cubes.iter() {
+                    for left_on in var_iter!(egraph[subst[left_on_var]], JoinLeftOn) {
+                        for right_on in var_iter!(egraph[subst[right_on_var]], JoinRightOn) {
+                            if !is_cube_join(&meta_context, left, right, left_on, right_on) {
+                                continue;
+                            }
+
+                            let alias_to_cube = left.iter().chain(right.iter()).cloned().collect();
+                            subst.insert(
+                                joined_source_var,
+                                egraph.add(LogicalPlanLanguage::CubeJoinAliasToCube(
+                                    CubeJoinAliasToCube(alias_to_cube),
+                                )),
+                            );
+                            return true;
+                        }
+                    }
+                }
+            }
+            false
+        }
+    }
+
     fn push_down_limit(
         &self,
         limit_var: &'static str,
+        cube_limit_var: &'static str,
         new_limit_var: &'static str,
     ) -> impl Fn(&mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>, &mut Subst) -> bool {
         let limit_var = var!(limit_var);
+        let cube_limit_var = var!(cube_limit_var);
         let new_limit_var = var!(new_limit_var);
         move |egraph, subst| {
             for limit in var_iter!(egraph[subst[limit_var]], LimitN) {
                 let limit = *limit;
                 if limit > 0 {
-                    subst.insert(
-                        new_limit_var,
-                        egraph.add(LogicalPlanLanguage::CubeScanLimit(CubeScanLimit(Some(
-                            limit,
-                        )))),
-                    );
-                    return true;
+                    let cube_limit = var_iter!(egraph[subst[cube_limit_var]], CubeScanLimit)
+                        .next()
+                        .cloned();
+                    if let Some(cube_limit) = cube_limit {
+                        // Scan which is already limited can only be narrowed down
+                        let new_limit = match cube_limit {
+                            Some(cube_limit) => limit.min(cube_limit),
+                            None => limit,
+                        };
+                        subst.insert(
+                            new_limit_var,
+                            egraph.add(LogicalPlanLanguage::CubeScanLimit(CubeScanLimit(Some(
+                                new_limit,
+                            )))),
+                        );
+                        return true;
+                    }
                 }
             }
             false
@@ -830,14 +1009,15 @@ impl MemberRules {
         let member_var = member_var.parse().unwrap();
         let meta_context = self.cube_context.meta.clone();
         move |egraph, subst| {
-            for member_name in
-                var_iter!(egraph[subst[column_var]], ColumnExprColumn).map(|c| c.name.to_string())
-            {
-                for cube_name in var_iter!(egraph[subst[cube_var]], TableScanSourceTableName) {
+            for column in var_iter!(egraph[subst[column_var]], ColumnExprColumn).cloned() {
+                let member_name = column.name.to_string();
+                for cube_name in
+                    column_cube_names(egraph, subst[cube_var], column.relation.as_deref())
+                {
                     if let Some(cube) = meta_context
                         .cubes
                         .iter()
-                        .find(|c| c.name.eq_ignore_ascii_case(cube_name))
+                        .find(|c| c.name.eq_ignore_ascii_case(&cube_name))
                     {
                         let column_names = if let Some(alias_var) = &alias_var {
                             var_iter!(egraph[subst[*alias_var]], AliasExprAlias)
@@ -912,16 +1092,16 @@ impl MemberRules {
         let column_var = column_var.parse().unwrap();
         let meta_context = self.cube_context.meta.clone();
         move |egraph, subst| {
-            for member_name in
-                var_iter!(egraph[subst[column_var]], ColumnExprColumn).map(|c| c.name.to_string())
-            {
-                for cube_name in var_iter!(egraph[subst[cube_var]], TableScanSourceTableName) {
+            for column in var_iter!(egraph[subst[column_var]], ColumnExprColumn).cloned() {
+                for cube_name in
+                    column_cube_names(egraph, subst[cube_var], column.relation.as_deref())
+                {
                     if let Some(cube) = meta_context
                         .cubes
                         .iter()
-                        .find(|c| c.name.eq_ignore_ascii_case(cube_name))
+                        .find(|c| c.name.eq_ignore_ascii_case(&cube_name))
                     {
-                        let member_name = format!("{}.{}", cube_name, member_name);
+                        let member_name = format!("{}.{}", cube_name, column.name);
                         if let Some(_) = cube
                             .segments
                             .iter()
@@ -949,12 +1129,12 @@ impl MemberRules {
         let dimension_var = var!(dimension_var);
         let meta_context = self.cube_context.meta.clone();
         move |egraph, subst| {
-            for dimension_name in
-                var_iter!(egraph[subst[column_var]], ColumnExprColumn).map(|c| c.name.to_string())
-            {
-                for cube_name in var_iter!(egraph[subst[cube_var]], TableScanSourceTableName) {
+            for column in var_iter!(egraph[subst[column_var]], ColumnExprColumn).cloned() {
+                for cube_name in
+                    column_cube_names(egraph, subst[cube_var], column.relation.as_deref())
+                {
                     if let Some(cube) = meta_context.find_cube_with_name(cube_name.to_string()) {
-                        let dimension_name = format!("{}.{}", cube_name, dimension_name);
+                        let dimension_name = format!("{}.{}", cube_name, column.name);
                         if let Some(dimension) = cube
                             .dimensions
                             .iter()
@@ -1017,16 +1197,16 @@ impl MemberRules {
         let date_range_var = date_range_var.parse().unwrap();
         let meta_context = self.cube_context.meta.clone();
         move |egraph, subst| {
-            for time_dimension_name in var_iter!(egraph[subst[dimension_var]], ColumnExprColumn)
-                .map(|c| c.name.to_string())
-            {
-                for cube_name in var_iter!(egraph[subst[cube_var]], TableScanSourceTableName) {
+            for column in var_iter!(egraph[subst[dimension_var]], ColumnExprColumn).cloned() {
+                for cube_name in
+                    column_cube_names(egraph, subst[cube_var], column.relation.as_deref())
+                {
                     if let Some(cube) = meta_context
                         .cubes
                         .iter()
-                        .find(|c| c.name.eq_ignore_ascii_case(cube_name))
+                        .find(|c| c.name.eq_ignore_ascii_case(&cube_name))
                     {
-                        let time_dimension_name = format!("{}.{}", cube_name, time_dimension_name);
+                        let time_dimension_name = format!("{}.{}", cube_name, column.name);
                         if let Some(time_dimension) = cube.dimensions.iter().find(|d| {
                             d._type == "time" && d.name.eq_ignore_ascii_case(&time_dimension_name)
                         }) {
@@ -1036,6 +1216,11 @@ impl MemberRules {
                                 match granularity {
                                     ScalarValue::Utf8(Some(granularity_value)) => {
                                         let granularity_value = granularity_value.to_lowercase();
+                                        // Other fields are truncated in memory after the load
+                                        if !CUBE_GRANULARITIES.contains(&granularity_value.as_str())
+                                        {
+                                            continue;
+                                        }
                                         subst.insert(
                                             time_dimension_name_var,
                                             egraph.add(LogicalPlanLanguage::TimeDimensionName(
@@ -1114,19 +1299,20 @@ impl MemberRules {
         let measure_out_var = measure_out_var.parse().unwrap();
         let meta_context = self.cube_context.meta.clone();
         move |egraph, subst| {
-            for measure_name in measure_var
+            for column in measure_var
                 .map(|measure_var| {
                     var_iter!(egraph[subst[measure_var]], ColumnExprColumn)
-                        .map(|c| c.name.to_string())
+                        .cloned()
                         .collect()
                 })
-                .unwrap_or(vec!["count".to_string()])
+                .unwrap_or(vec![Column::from_name("count")])
             {
-                for cube_name in var_iter!(egraph[subst[var]], TableScanSourceTableName) {
+                let measure_name = column.name.to_string();
+                for cube_name in column_cube_names(egraph, subst[var], column.relation.as_deref()) {
                     if let Some(cube) = meta_context
                         .cubes
                         .iter()
-                        .find(|c| c.name.eq_ignore_ascii_case(cube_name))
+                        .find(|c| c.name.eq_ignore_ascii_case(&cube_name))
                     {
                         for distinct in distinct_var
                             .map(|distinct_var| {
@@ -1229,6 +1415,98 @@ impl MemberRules {
     }
 }
 
+/// Aliases of tables and cubes they refer to, which are scanned by CubeScan: either a single cube
+/// or cubes joined by `push-down-join`
+fn scan_alias_to_cube(
+    egraph: &EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>,
+    source_id: Id,
+    table_name_id: Id,
+) -> Vec<Vec<(String, String)>> {
+    let mut result = var_iter!(egraph[source_id], CubeJoinAliasToCube)
+        .cloned()
+        .collect::<Vec<_>>();
+    for cube in var_iter!(egraph[source_id], TableScanSourceTableName) {
+        for table_name in var_iter!(egraph[table_name_id], CubeScanTableName) {
+            result.push(vec![(table_name.to_string(), cube.to_string())]);
+        }
+    }
+    result
+}
+
+/// Every condition of JOIN should compare dimensions of cubes related in the data model, the
+/// actual condition is taken from the data model by Cube then
+fn is_cube_join(
+    meta_context: &MetaContext,
+    left: &[(String, String)],
+    right: &[(String, String)],
+    left_on: &[Column],
+    right_on: &[Column],
+) -> bool {
+    if left_on.is_empty() || left_on.len() != right_on.len() {
+        return false;
+    }
+
+    // The same cube can't be joined twice within one query
+    if right
+        .iter()
+        .any(|(_, cube)| left.iter().any(|(_, c)| c.eq_ignore_ascii_case(cube)))
+    {
+        return false;
+    }
+
+    let dimension_cube = |alias_to_cube: &[(String, String)], column: &Column| {
+        let relation = column.relation.as_ref()?;
+        let (_, cube_name) = alias_to_cube
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(relation))?;
+        let cube = meta_context.find_cube_with_name(cube_name.to_string())?;
+        cube.lookup_dimension(&column.name)?;
+        Some(cube.name)
+    };
+
+    left_on
+        .iter()
+        .zip(right_on.iter())
+        .all(|(left_column, right_column)| {
+            let cubes = match (
+                dimension_cube(left, left_column),
+                dimension_cube(right, right_column),
+            ) {
+                (Some(left_cube), Some(right_cube)) => Some((left_cube, right_cube)),
+                _ => dimension_cube(left, right_column).zip(dimension_cube(right, left_column)),
+            };
+            match cubes {
+                Some((left_cube, right_cube)) => meta_context.cubes_joined(&left_cube, &right_cube),
+                None => false,
+            }
+        })
+}
+
+/// Names of cubes a column of CubeScan can belong to. Columns of joined cubes are resolved by
+/// the relation, unqualified ones are looked up in all of them
+fn column_cube_names(
+    egraph: &EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>,
+    source_id: Id,
+    relation: Option<&str>,
+) -> Vec<String> {
+    let mut cube_names = var_iter!(egraph[source_id], TableScanSourceTableName)
+        .cloned()
+        .collect::<Vec<_>>();
+    for alias_to_cube in var_iter!(egraph[source_id], CubeJoinAliasToCube) {
+        cube_names.extend(
+            alias_to_cube
+                .iter()
+                .filter(|(alias, _)| {
+                    relation
+                        .map(|relation| relation.eq_ignore_ascii_case(alias))
+                        .unwrap_or(true)
+                })
+                .map(|(_, cube)| cube.to_string()),
+        );
+    }
+    cube_names
+}
+
 pub fn add_member_error(
     egraph: &mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>,
     member_error: String,
@@ -35,10 +35,12 @@ impl RewriteRules for OrderRules {
                         "?members",
                         "?filters",
                         "CubeScanOrder",
-                        "?limit",
+                        // Sort over already limited scan is applied locally
+                        "CubeScanLimit:None",
                         "?offset",
                         "?cube_aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                 ),
                 cube_scan(
@@ -46,10 +48,11 @@ impl RewriteRules for OrderRules {
                     "?members",
                     "?filters",
                     order_replacer("?expr", "?aliases", "?cube"),
-                    "?limit",
+                    "CubeScanLimit:None",
                     "?offset",
                     "?cube_aliases",
                     "?table_name",
+                    "?ungrouped",
                 ),
                 self.push_down_sort(
                     "?source_table_name",
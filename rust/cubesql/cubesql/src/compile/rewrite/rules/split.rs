@@ -43,6 +43,7 @@ impl RewriteRules for SplitRules {
                         "?offset",
                         "?aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                     "?group_expr",
                     "?aggr_expr",
@@ -62,6 +63,7 @@ impl RewriteRules for SplitRules {
                             "?offset",
                             "?aliases",
                             "?table_name",
+                            "?ungrouped",
                         ),
                         inner_aggregate_split_replacer("?group_expr", "?inner_aggregate_cube"),
                         inner_aggregate_split_replacer("?aggr_expr", "?inner_aggregate_cube"),
@@ -86,6 +88,7 @@ impl RewriteRules for SplitRules {
                         "?offset",
                         "?aliases",
                         "?table_name",
+                        "?ungrouped",
                     ),
                     "?group_expr",
                     "?aggr_expr",
@@ -101,6 +104,7 @@ impl RewriteRules for SplitRules {
                             "?offset",
                             "?aliases",
                             "?table_name",
+                            "?ungrouped",
                         ),
                         inner_aggregate_split_replacer("?group_expr", "?inner_aggregate_cube"),
                         inner_aggregate_split_replacer("?aggr_expr", "?inner_aggregate_cube"),
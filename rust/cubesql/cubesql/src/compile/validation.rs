@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use cubeclient::models::V1CubeMeta;
+use sqlparser::ast;
+
+use crate::{
+    compile::{dry_run_sql_to_cube_query, MetaContext},
+    sql::{
+        dataframe, session::DatabaseProtocol, ColumnFlags, ColumnType, Session, SessionManager,
+        SessionState,
+    },
+    transport::{V1CubeMetaDimensionExt, V1CubeMetaMeasureExt},
+    CubeError,
+};
+
+/// Name of SQL function which returns the report of validation
+pub const VALIDATE_MODEL_FUNCTION: &str = "cube_validate_model";
+
+const GRANULARITIES: [&str; 8] = [
+    "second", "minute", "hour", "day", "week", "month", "quarter", "year",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelValidationCheck {
+    pub cube: String,
+    pub check: String,
+    // None if check passed
+    pub error: Option<String>,
+}
+
+impl ModelValidationCheck {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Fetches meta and validates the data model, see [validate_model]
+pub async fn fetch_and_validate_model(
+    session: Arc<Session>,
+) -> Result<Vec<ModelValidationCheck>, CubeError> {
+    let auth_context = session.state.auth_context().ok_or_else(|| {
+        CubeError::internal("Unable to validate model without auth context".to_string())
+    })?;
+    let meta = session
+        .server
+        .transport
+        .meta(Arc::new(auth_context))
+        .await?;
+
+    Ok(validate_model(meta, session))
+}
+
+/// Compiles generated queries for each cube of the data model without executing them.
+/// Check passes if the query is compiled into at least one load query
+pub fn validate_model(meta: Arc<MetaContext>, session: Arc<Session>) -> Vec<ModelValidationCheck> {
+    let mut checks = vec![];
+
+    for cube in meta.cubes.iter() {
+        for (check, query) in generate_queries(cube, &session.state.protocol) {
+            let error = match dry_run_sql_to_cube_query(&query, meta.clone(), session.clone()) {
+                Ok(requests) if requests.is_empty() => {
+                    Some("Query was not compiled into load query".to_string())
+                }
+                Ok(_) => None,
+                Err(err) => Some(err.to_string()),
+            };
+
+            checks.push(ModelValidationCheck {
+                cube: cube.name.clone(),
+                check,
+                error,
+            });
+        }
+    }
+
+    checks
+}
+
+/// Report of validation as result set: cube, check, status and error message
+pub fn validate_model_to_dataframe(
+    meta: Arc<MetaContext>,
+    session: Arc<Session>,
+) -> dataframe::DataFrame {
+    let rows = validate_model(meta, session)
+        .into_iter()
+        .map(|check| {
+            dataframe::Row::new(vec![
+                dataframe::TableValue::String(check.cube),
+                dataframe::TableValue::String(check.check),
+                dataframe::TableValue::String(
+                    if check.error.is_none() { "ok" } else { "error" }.to_string(),
+                ),
+                match check.error {
+                    Some(error) => dataframe::TableValue::String(error),
+                    None => dataframe::TableValue::Null,
+                },
+            ])
+        })
+        .collect();
+
+    dataframe::DataFrame::new(
+        vec![
+            dataframe::Column::new(
+                "cube".to_string(),
+                ColumnType::String,
+                ColumnFlags::NOT_NULL,
+            ),
+            dataframe::Column::new(
+                "check".to_string(),
+                ColumnType::String,
+                ColumnFlags::NOT_NULL,
+            ),
+            dataframe::Column::new(
+                "status".to_string(),
+                ColumnType::String,
+                ColumnFlags::NOT_NULL,
+            ),
+            dataframe::Column::new(
+                "message".to_string(),
+                ColumnType::String,
+                ColumnFlags::empty(),
+            ),
+        ],
+        rows,
+    )
+}
+
+/// SELECT cube_validate_model() or SELECT * FROM cube_validate_model()
+pub fn is_validate_model_query(query: &ast::Query) -> bool {
+    let select = match &query.body {
+        ast::SetExpr::Select(select) => select,
+        _ => return false,
+    };
+
+    let is_function_name = |name: &ast::ObjectName| {
+        name.to_string()
+            .eq_ignore_ascii_case(VALIDATE_MODEL_FUNCTION)
+    };
+
+    match (select.from.as_slice(), select.projection.as_slice()) {
+        ([], [ast::SelectItem::UnnamedExpr(ast::Expr::Function(fun))]) => {
+            fun.args.is_empty() && is_function_name(&fun.name)
+        }
+        (
+            [ast::TableWithJoins {
+                relation: ast::TableFactor::Table { name, args, .. },
+                joins,
+            }],
+            [ast::SelectItem::Wildcard],
+        ) => joins.is_empty() && args.is_empty() && is_function_name(name),
+        _ => false,
+    }
+}
+
+fn generate_queries(cube: &V1CubeMeta, protocol: &DatabaseProtocol) -> Vec<(String, String)> {
+    let quote = |name: &str| match protocol {
+        DatabaseProtocol::MySQL => format!("`{}`", name),
+        DatabaseProtocol::PostgreSQL => format!("\"{}\"", name),
+    };
+    let table = quote(&cube.name);
+
+    let mut queries = vec![("star".to_string(), format!("SELECT * FROM {}", table))];
+
+    for dimension in cube.dimensions.iter() {
+        queries.push((
+            format!("dimension {}", dimension.name),
+            format!(
+                "SELECT {} FROM {}",
+                quote(&dimension.get_real_name()),
+                table
+            ),
+        ));
+    }
+
+    for measure in cube.measures.iter() {
+        queries.push((
+            format!("measure {}", measure.name),
+            format!(
+                "SELECT MEASURE({}) FROM {}",
+                quote(&measure.get_real_name()),
+                table
+            ),
+        ));
+    }
+
+    let measure = cube
+        .measures
+        .first()
+        .map(|measure| format!("MEASURE({})", quote(&measure.get_real_name())));
+
+    if let (Some(dimension), Some(measure)) = (
+        cube.dimensions.iter().find(|d| !d.is_time()),
+        measure.as_ref(),
+    ) {
+        let dimension = quote(&dimension.get_real_name());
+        queries.push((
+            "grouped".to_string(),
+            format!(
+                "SELECT {}, {} FROM {} GROUP BY {}",
+                dimension, measure, table, dimension
+            ),
+        ));
+    }
+
+    for dimension in cube.dimensions.iter().filter(|d| d.is_time()) {
+        let column = quote(&dimension.get_real_name());
+        for granularity in GRANULARITIES {
+            let projection = match measure.as_ref() {
+                Some(measure) => format!(
+                    "DATE_TRUNC('{}', {}) AS __timestamp, {}",
+                    granularity, column, measure
+                ),
+                None => format!("DATE_TRUNC('{}', {}) AS __timestamp", granularity, column),
+            };
+
+            queries.push((
+                format!("time dimension {} by {}", dimension.name, granularity),
+                format!("SELECT {} FROM {} GROUP BY __timestamp", projection, table),
+            ));
+        }
+    }
+
+    queries
+}
+
+/// SQL function of validation is planned in the session of connection
+pub(crate) fn validate_model_for_state(
+    meta: Arc<MetaContext>,
+    state: Arc<SessionState>,
+    session_manager: Arc<SessionManager>,
+) -> dataframe::DataFrame {
+    let session = Arc::new(Session {
+        server: session_manager.server.clone(),
+        session_manager,
+        state,
+    });
+
+    validate_model_to_dataframe(meta, session)
+}
@@ -4,19 +4,21 @@ pub mod processing_loop;
 use crate::config::injection::{DIService, Injector};
 use crate::config::processing_loop::ProcessingLoop;
 use crate::sql::{
-    MySqlServer, PostgresServer, ServerManager, SessionManager, SqlAuthDefaultImpl, SqlAuthService,
+    server_manager::ServerConfiguration, MySqlServer, PostgresServer, ServerManager,
+    SessionManager, SqlAuthDefaultImpl, SqlAuthService, TlsConfig,
 };
 use crate::telemetry::{start_track_event_loop, stop_track_event_loop};
 use crate::transport::{HttpTransport, TransportService};
 use crate::CubeError;
 use futures::future::join_all;
 use log::error;
+use pg_srv::protocol::Format;
 
 use mockall::automock;
 
 use std::env;
 
-use std::sync::Arc;
+use std::{num::NonZeroUsize, str::FromStr, sync::Arc, time::Duration};
 
 use tokio::task::JoinHandle;
 
@@ -116,6 +118,50 @@ pub trait ConfigObj: DIService {
 
     fn postgres_bind_address(&self) -> &Option<String>;
 
+    fn postgres_tls_cert_path(&self) -> &Option<String>;
+
+    fn postgres_tls_key_path(&self) -> &Option<String>;
+
+    fn postgres_tls_max_concurrent_handshakes(&self) -> usize;
+
+    fn postgres_tls_handshake_timeout(&self) -> u64;
+
+    fn postgres_require_ssl(&self) -> bool;
+
+    fn postgres_send_buffer_size(&self) -> Option<usize>;
+
+    fn postgres_max_prepared_statements(&self) -> usize;
+
+    fn postgres_max_portals(&self) -> usize;
+
+    fn postgres_query_id_notice(&self) -> bool;
+
+    fn postgres_timing_notice(&self) -> bool;
+
+    fn postgres_meta_warmup(&self) -> bool;
+
+    fn postgres_default_result_formats(&self) -> &Vec<(String, Format)>;
+
+    fn postgres_idle_timeout(&self) -> Option<u64>;
+
+    fn postgres_token_auth(&self) -> bool;
+
+    fn postgres_shutdown_grace_period(&self) -> u64;
+
+    fn stream_flush_rows(&self) -> usize;
+
+    fn noop_dml(&self) -> bool;
+
+    fn max_query_expressions(&self) -> usize;
+
+    fn max_subquery_depth(&self) -> usize;
+
+    fn ungrouped_queries(&self) -> bool;
+
+    fn cube_scan_page_size(&self) -> usize;
+
+    fn continue_wait_timeout(&self) -> u64;
+
     fn query_timeout(&self) -> u64;
 
     fn nonce(&self) -> &Option<Vec<u8>>;
@@ -125,6 +171,28 @@ pub trait ConfigObj: DIService {
 pub struct ConfigObjImpl {
     pub bind_address: Option<String>,
     pub postgres_bind_address: Option<String>,
+    pub postgres_tls_cert_path: Option<String>,
+    pub postgres_tls_key_path: Option<String>,
+    pub postgres_tls_max_concurrent_handshakes: usize,
+    pub postgres_tls_handshake_timeout: u64,
+    pub postgres_require_ssl: bool,
+    pub postgres_send_buffer_size: Option<usize>,
+    pub postgres_max_prepared_statements: usize,
+    pub postgres_max_portals: usize,
+    pub postgres_query_id_notice: bool,
+    pub postgres_timing_notice: bool,
+    pub postgres_meta_warmup: bool,
+    pub postgres_default_result_formats: Vec<(String, Format)>,
+    pub postgres_idle_timeout: Option<u64>,
+    pub postgres_token_auth: bool,
+    pub postgres_shutdown_grace_period: u64,
+    pub stream_flush_rows: usize,
+    pub noop_dml: bool,
+    pub max_query_expressions: usize,
+    pub max_subquery_depth: usize,
+    pub ungrouped_queries: bool,
+    pub cube_scan_page_size: usize,
+    pub continue_wait_timeout: u64,
     pub nonce: Option<Vec<u8>>,
     pub query_timeout: u64,
 }
@@ -141,6 +209,94 @@ impl ConfigObj for ConfigObjImpl {
         &self.postgres_bind_address
     }
 
+    fn postgres_tls_cert_path(&self) -> &Option<String> {
+        &self.postgres_tls_cert_path
+    }
+
+    fn postgres_tls_key_path(&self) -> &Option<String> {
+        &self.postgres_tls_key_path
+    }
+
+    fn postgres_tls_max_concurrent_handshakes(&self) -> usize {
+        self.postgres_tls_max_concurrent_handshakes
+    }
+
+    fn postgres_tls_handshake_timeout(&self) -> u64 {
+        self.postgres_tls_handshake_timeout
+    }
+
+    fn postgres_require_ssl(&self) -> bool {
+        self.postgres_require_ssl
+    }
+
+    fn postgres_send_buffer_size(&self) -> Option<usize> {
+        self.postgres_send_buffer_size
+    }
+
+    fn postgres_max_prepared_statements(&self) -> usize {
+        self.postgres_max_prepared_statements
+    }
+
+    fn postgres_max_portals(&self) -> usize {
+        self.postgres_max_portals
+    }
+
+    fn postgres_query_id_notice(&self) -> bool {
+        self.postgres_query_id_notice
+    }
+
+    fn postgres_timing_notice(&self) -> bool {
+        self.postgres_timing_notice
+    }
+
+    fn postgres_meta_warmup(&self) -> bool {
+        self.postgres_meta_warmup
+    }
+
+    fn postgres_default_result_formats(&self) -> &Vec<(String, Format)> {
+        &self.postgres_default_result_formats
+    }
+
+    fn postgres_idle_timeout(&self) -> Option<u64> {
+        self.postgres_idle_timeout
+    }
+
+    fn postgres_token_auth(&self) -> bool {
+        self.postgres_token_auth
+    }
+
+    fn postgres_shutdown_grace_period(&self) -> u64 {
+        self.postgres_shutdown_grace_period
+    }
+
+    fn stream_flush_rows(&self) -> usize {
+        self.stream_flush_rows
+    }
+
+    fn noop_dml(&self) -> bool {
+        self.noop_dml
+    }
+
+    fn max_query_expressions(&self) -> usize {
+        self.max_query_expressions
+    }
+
+    fn max_subquery_depth(&self) -> usize {
+        self.max_subquery_depth
+    }
+
+    fn ungrouped_queries(&self) -> bool {
+        self.ungrouped_queries
+    }
+
+    fn cube_scan_page_size(&self) -> usize {
+        self.cube_scan_page_size
+    }
+
+    fn continue_wait_timeout(&self) -> u64 {
+        self.continue_wait_timeout
+    }
+
     fn nonce(&self) -> &Option<Vec<u8>> {
         &self.nonce
     }
@@ -150,6 +306,61 @@ impl ConfigObj for ConfigObjImpl {
     }
 }
 
+/// Value of the ENV variable, panics if it's set to a value which can't be parsed
+fn env_parse<T: FromStr>(name: &str, expected: &str) -> Option<T> {
+    env::var(name).ok().map(|v| {
+        v.trim()
+            .parse::<T>()
+            .unwrap_or_else(|_| panic!("{} must be {}, got '{}'", name, expected, v))
+    })
+}
+
+fn env_bool(name: &str) -> Option<bool> {
+    env::var(name)
+        .ok()
+        .map(|v| match v.trim().to_lowercase().as_str() {
+            "true" => true,
+            "false" => false,
+            _ => panic!("{} must be true or false, got '{}'", name, v),
+        })
+}
+
+fn env_positive(name: &str) -> Option<usize> {
+    env_parse::<NonZeroUsize>(name, "a positive integer").map(|v| v.get())
+}
+
+fn env_secs(name: &str) -> Option<u64> {
+    env_parse::<u64>(name, "a number of seconds")
+}
+
+/// Mapping of drivers to formats, for example: Npgsql=binary,psql=text
+fn parse_default_result_formats(value: &str) -> Vec<(String, Format)> {
+    fn invalid(entry: &str) -> ! {
+        panic!(
+            "CUBESQL_PG_DEFAULT_RESULT_FORMATS must be a list of <application_name>=text|binary, got '{}'",
+            entry
+        )
+    }
+
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (name, format) = entry.split_once('=').unwrap_or_else(|| invalid(entry));
+            let format = match format.trim().to_lowercase().as_str() {
+                "text" => Format::Text,
+                "binary" => Format::Binary,
+                _ => invalid(entry),
+            };
+            if name.trim().is_empty() {
+                invalid(entry);
+            }
+
+            (name.trim().to_string(), format)
+        })
+        .collect()
+}
+
 lazy_static! {
     pub static ref TEST_LOGGING_INITIALIZED: tokio::sync::RwLock<bool> =
         tokio::sync::RwLock::new(false);
@@ -173,6 +384,42 @@ impl Config {
                 postgres_bind_address: env::var("CUBESQL_PG_PORT")
                     .ok()
                     .map(|port| format!("0.0.0.0:{}", port.parse::<u16>().unwrap())),
+                postgres_tls_cert_path: env::var("CUBESQL_PG_TLS_CERT").ok(),
+                postgres_tls_key_path: env::var("CUBESQL_PG_TLS_KEY").ok(),
+                postgres_tls_max_concurrent_handshakes: env_positive(
+                    "CUBESQL_PG_TLS_MAX_CONCURRENT_HANDSHAKES",
+                )
+                .unwrap_or(32),
+                postgres_tls_handshake_timeout: env_secs("CUBESQL_PG_TLS_HANDSHAKE_TIMEOUT")
+                    .unwrap_or(10),
+                postgres_require_ssl: env_bool("CUBESQL_PG_REQUIRE_SSL").unwrap_or(false),
+                postgres_send_buffer_size: env_positive("CUBESQL_PG_SEND_BUFFER_SIZE"),
+                postgres_max_prepared_statements: env_positive(
+                    "CUBESQL_PG_MAX_PREPARED_STATEMENTS",
+                )
+                .unwrap_or(10_000),
+                postgres_max_portals: env_positive("CUBESQL_PG_MAX_PORTALS").unwrap_or(1_000),
+                postgres_query_id_notice: env_bool("CUBESQL_PG_QUERY_ID_NOTICE").unwrap_or(false),
+                postgres_timing_notice: env_bool("CUBESQL_PG_TIMING_NOTICE").unwrap_or(false),
+                postgres_meta_warmup: env_bool("CUBESQL_PG_META_WARMUP").unwrap_or(false),
+                postgres_default_result_formats: env::var("CUBESQL_PG_DEFAULT_RESULT_FORMATS")
+                    .ok()
+                    .map(|v| parse_default_result_formats(&v))
+                    .unwrap_or_default(),
+                // 0 disables the timeout
+                postgres_idle_timeout: env_secs("CUBESQL_PG_IDLE_TIMEOUT").filter(|v| *v > 0),
+                postgres_token_auth: env_bool("CUBESQL_PG_TOKEN_AUTH").unwrap_or(false),
+                postgres_shutdown_grace_period: env_secs("CUBESQL_PG_SHUTDOWN_GRACE_PERIOD")
+                    .unwrap_or(30),
+                stream_flush_rows: env_positive("CUBESQL_STREAM_FLUSH_ROWS").unwrap_or(10_000),
+                noop_dml: env_bool("CUBESQL_NOOP_DML").unwrap_or(false),
+                max_query_expressions: env_positive("CUBESQL_MAX_QUERY_EXPRESSIONS")
+                    .unwrap_or(20_000),
+                max_subquery_depth: env_parse("CUBESQL_MAX_SUBQUERY_DEPTH", "a number")
+                    .unwrap_or(64),
+                ungrouped_queries: env_bool("CUBESQL_UNGROUPED_QUERIES").unwrap_or(false),
+                cube_scan_page_size: env_positive("CUBESQL_CUBE_SCAN_PAGE_SIZE").unwrap_or(50_000),
+                continue_wait_timeout: env_secs("CUBESQL_CONTINUE_WAIT_TIMEOUT").unwrap_or(300),
                 nonce: None,
                 query_timeout,
             }),
@@ -186,6 +433,28 @@ impl Config {
             config_obj: Arc::new(ConfigObjImpl {
                 bind_address: None,
                 postgres_bind_address: None,
+                postgres_tls_cert_path: None,
+                postgres_tls_key_path: None,
+                postgres_tls_max_concurrent_handshakes: 32,
+                postgres_tls_handshake_timeout: 10,
+                postgres_require_ssl: false,
+                postgres_send_buffer_size: None,
+                postgres_max_prepared_statements: 10_000,
+                postgres_max_portals: 1_000,
+                postgres_query_id_notice: false,
+                postgres_timing_notice: false,
+                postgres_meta_warmup: false,
+                postgres_default_result_formats: vec![],
+                postgres_idle_timeout: None,
+                postgres_token_auth: false,
+                postgres_shutdown_grace_period: 30,
+                stream_flush_rows: 10_000,
+                noop_dml: false,
+                max_query_expressions: 20_000,
+                max_subquery_depth: 64,
+                ungrouped_queries: false,
+                cube_scan_page_size: 50_000,
+                continue_wait_timeout: 300,
                 nonce: None,
                 query_timeout,
             }),
@@ -230,6 +499,7 @@ impl Config {
                     i.get_service_typed().await,
                     i.get_service_typed().await,
                     config.nonce().clone(),
+                    ServerConfiguration::from_config(config.as_ref()),
                 ))
             })
             .await;
@@ -262,8 +532,36 @@ impl Config {
             self.injector
                 .register_typed::<PostgresServer, _, _, _>(async move |i| {
                     let config = i.get_service_typed::<dyn ConfigObj>().await;
+                    let tls_config = match (
+                        config.postgres_tls_cert_path(),
+                        config.postgres_tls_key_path(),
+                    ) {
+                        (Some(cert_path), Some(key_path)) => Some(
+                            TlsConfig::new(
+                                cert_path,
+                                key_path,
+                                config.postgres_tls_max_concurrent_handshakes(),
+                                Duration::from_secs(config.postgres_tls_handshake_timeout()),
+                                config.postgres_require_ssl(),
+                            )
+                            .unwrap_or_else(|e| {
+                                panic!("Unable to load TLS certificate and key: {}", e)
+                            }),
+                        ),
+                        (None, None) if config.postgres_require_ssl() => panic!(
+                            "CUBESQL_PG_REQUIRE_SSL requires CUBESQL_PG_TLS_CERT and CUBESQL_PG_TLS_KEY"
+                        ),
+                        (None, None) => None,
+                        // Plaintext must not be a silent fallback of misconfigured TLS
+                        _ => panic!(
+                            "CUBESQL_PG_TLS_CERT and CUBESQL_PG_TLS_KEY must be set together"
+                        ),
+                    };
+
                     PostgresServer::new(
                         config.postgres_bind_address().as_ref().unwrap().to_string(),
+                        tls_config,
+                        config.postgres_send_buffer_size(),
                         i.get_service_typed().await,
                     )
                 })
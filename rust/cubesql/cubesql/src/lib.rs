@@ -43,6 +43,8 @@ pub mod transport;
 pub struct CubeError {
     pub message: String,
     pub cause: CubeErrorCauseType,
+    #[serde(default)]
+    pub kind: Option<CubeErrorKind>,
 }
 
 impl std::error::Error for CubeError {}
@@ -51,6 +53,21 @@ impl std::error::Error for CubeError {}
 pub enum CubeErrorCauseType {
     User,
     Internal,
+    /// Cube is still processing the query (for example, building pre-aggregations), the
+    /// request should be repeated later
+    ContinueWait,
+}
+
+/// Class of the error, which is reported to the client as SQLSTATE
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CubeErrorKind {
+    SyntaxError,
+    InsufficientPrivilege,
+    FeatureNotSupported,
+    UndefinedTable,
+    StatementTooComplex,
+    ProgramLimitExceeded,
+    AmbiguousColumn,
 }
 
 impl CubeError {
@@ -58,6 +75,7 @@ impl CubeError {
         CubeError {
             message,
             cause: CubeErrorCauseType::User,
+            kind: None,
         }
     }
 
@@ -65,13 +83,32 @@ impl CubeError {
         CubeError {
             message,
             cause: CubeErrorCauseType::Internal,
+            kind: None,
+        }
+    }
+
+    pub fn continue_wait() -> CubeError {
+        CubeError {
+            message: "Continue wait".to_string(),
+            cause: CubeErrorCauseType::ContinueWait,
+            kind: None,
         }
     }
 
+    pub fn with_kind(mut self, kind: CubeErrorKind) -> CubeError {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn is_continue_wait(&self) -> bool {
+        matches!(self.cause, CubeErrorCauseType::ContinueWait)
+    }
+
     pub fn from_error<E: fmt::Display>(error: E) -> CubeError {
         CubeError {
             message: format!("{}\n{}", error, Backtrace::capture()),
             cause: CubeErrorCauseType::Internal,
+            kind: None,
         }
     }
 
@@ -79,6 +116,7 @@ impl CubeError {
         CubeError {
             message: format!("{:?}\n{}", error, Backtrace::capture()),
             cause: CubeErrorCauseType::Internal,
+            kind: None,
         }
     }
 }
@@ -86,7 +124,9 @@ impl CubeError {
 impl fmt::Display for CubeError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self.cause {
-            CubeErrorCauseType::User => f.write_fmt(format_args!("{}", self.message)),
+            CubeErrorCauseType::User | CubeErrorCauseType::ContinueWait => {
+                f.write_fmt(format_args!("{}", self.message))
+            }
             CubeErrorCauseType::Internal => {
                 f.write_fmt(format_args!("{:?}: {}", self.cause, self.message))
             }
@@ -94,39 +134,67 @@ impl fmt::Display for CubeError {
     }
 }
 
+fn kind_of_response_status(status: reqwest::StatusCode) -> Option<CubeErrorKind> {
+    match status {
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+            Some(CubeErrorKind::InsufficientPrivilege)
+        }
+        _ => None,
+    }
+}
+
 impl From<cubeclient::apis::Error<LoadV1Error>> for CubeError {
     fn from(v: cubeclient::apis::Error<LoadV1Error>) -> Self {
+        let mut kind = None;
         let message: String = match v {
-            cubeclient::apis::Error::ResponseError(e) => match e.entity {
-                None => e.content,
-                Some(LoadV1Error::UnknownValue(_)) => e.content,
-                Some(LoadV1Error::Status4XX(unwrapped)) => unwrapped.error,
-                Some(LoadV1Error::Status5XX(unwrapped)) => unwrapped.error,
-            },
+            cubeclient::apis::Error::ResponseError(e) => {
+                kind = kind_of_response_status(e.status);
+                match e.entity {
+                    None => e.content,
+                    Some(LoadV1Error::UnknownValue(_)) => e.content,
+                    Some(LoadV1Error::Status4XX(unwrapped)) => unwrapped.error,
+                    Some(LoadV1Error::Status5XX(unwrapped)) => unwrapped.error,
+                    // Repeated by CubeScan till continue_wait_timeout
+                    Some(LoadV1Error::ContinueWait(_)) => return CubeError::continue_wait(),
+                }
+            }
             _ => v.to_string(),
         };
-        return CubeError::internal(message);
+        return CubeError {
+            kind,
+            ..CubeError::internal(message)
+        };
     }
 }
 
 impl From<cubeclient::apis::Error<MetaV1Error>> for CubeError {
     fn from(v: cubeclient::apis::Error<MetaV1Error>) -> Self {
+        let mut kind = None;
         let message: String = match v {
-            cubeclient::apis::Error::ResponseError(e) => match e.entity {
-                None => e.content,
-                Some(MetaV1Error::UnknownValue(_)) => e.content,
-                Some(MetaV1Error::Status4XX(unwrapped)) => unwrapped.error,
-                Some(MetaV1Error::Status5XX(unwrapped)) => unwrapped.error,
-            },
+            cubeclient::apis::Error::ResponseError(e) => {
+                kind = kind_of_response_status(e.status);
+                match e.entity {
+                    None => e.content,
+                    Some(MetaV1Error::UnknownValue(_)) => e.content,
+                    Some(MetaV1Error::Status4XX(unwrapped)) => unwrapped.error,
+                    Some(MetaV1Error::Status5XX(unwrapped)) => unwrapped.error,
+                }
+            }
             _ => v.to_string(),
         };
-        return CubeError::internal(message);
+        return CubeError {
+            kind,
+            ..CubeError::internal(message)
+        };
     }
 }
 
 impl From<crate::compile::CompilationError> for CubeError {
     fn from(v: crate::compile::CompilationError) -> Self {
-        CubeError::internal(format!("{:?}\n{}", v, Backtrace::capture()))
+        CubeError {
+            kind: v.kind(),
+            ..CubeError::internal(format!("{:?}\n{}", v, Backtrace::capture()))
+        }
     }
 }
 
@@ -138,7 +206,7 @@ impl From<std::io::Error> for CubeError {
 
 impl From<ParserError> for CubeError {
     fn from(v: ParserError) -> Self {
-        CubeError::internal(format!("{:?}", v))
+        CubeError::internal(format!("{:?}", v)).with_kind(CubeErrorKind::SyntaxError)
     }
 }
 
@@ -175,15 +243,52 @@ impl From<tokio::sync::broadcast::error::RecvError> for CubeError {
     }
 }
 
+/// Kind of the error, which is raised during planning or execution. CubeError from UDF
+/// reaches here as external one, possibly wrapped into ArrowError by the execution stream
+fn kind_of_df_error(v: &datafusion::error::DataFusionError) -> Option<CubeErrorKind> {
+    match v {
+        datafusion::error::DataFusionError::SQL(_) => Some(CubeErrorKind::SyntaxError),
+        datafusion::error::DataFusionError::NotImplemented(_) => {
+            Some(CubeErrorKind::FeatureNotSupported)
+        }
+        datafusion::error::DataFusionError::External(err) => kind_of_external_error(err.as_ref()),
+        datafusion::error::DataFusionError::ArrowError(err) => kind_of_arrow_error(err),
+        _ => None,
+    }
+}
+
+fn kind_of_arrow_error(v: &arrow::error::ArrowError) -> Option<CubeErrorKind> {
+    match v {
+        arrow::error::ArrowError::ExternalError(err) => kind_of_external_error(err.as_ref()),
+        _ => None,
+    }
+}
+
+fn kind_of_external_error(v: &(dyn std::error::Error + Send + Sync)) -> Option<CubeErrorKind> {
+    if let Some(err) = v.downcast_ref::<CubeError>() {
+        err.kind
+    } else if let Some(err) = v.downcast_ref::<datafusion::error::DataFusionError>() {
+        kind_of_df_error(err)
+    } else {
+        None
+    }
+}
+
 impl From<datafusion::error::DataFusionError> for CubeError {
     fn from(v: datafusion::error::DataFusionError) -> Self {
-        CubeError::internal(format!("{:?}\n{}", v, Backtrace::capture()))
+        CubeError {
+            kind: kind_of_df_error(&v),
+            ..CubeError::internal(format!("{:?}\n{}", v, Backtrace::capture()))
+        }
     }
 }
 
 impl From<arrow::error::ArrowError> for CubeError {
     fn from(v: arrow::error::ArrowError) -> Self {
-        CubeError::internal(format!("{:?}\n{}", v, Backtrace::capture()))
+        CubeError {
+            kind: kind_of_arrow_error(&v),
+            ..CubeError::internal(format!("{:?}\n{}", v, Backtrace::capture()))
+        }
     }
 }
 
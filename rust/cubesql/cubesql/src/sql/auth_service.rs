@@ -8,6 +8,10 @@ use crate::CubeError;
 pub struct AuthContext {
     pub access_token: String,
     pub base_path: String,
+    /// Claims of the verified token, when the client is authenticated by token
+    pub claims: Option<serde_json::Value>,
+    /// Allows administrative functions, like pg_terminate_backend
+    pub superuser: bool,
 }
 
 #[derive(Debug)]
@@ -25,6 +29,18 @@ impl AuthenticateResponse {
 #[async_trait]
 pub trait SqlAuthService: Send + Sync + Debug {
     async fn authenticate(&self, user: Option<String>) -> Result<AuthenticateResponse, CubeError>;
+
+    /// Verifies the token (for example, JWT), which the client sent instead of the password,
+    /// when token authentication is enabled
+    async fn authenticate_token(
+        &self,
+        _user: Option<String>,
+        _token: String,
+    ) -> Result<AuthContext, CubeError> {
+        Err(CubeError::user(
+            "Token authentication is not supported".to_string(),
+        ))
+    }
 }
 
 #[derive(Debug)]
@@ -43,6 +59,8 @@ impl SqlAuthService for SqlAuthDefaultImpl {
                 base_path: env::var("CUBESQL_CUBE_URL")
                     .ok()
                     .unwrap_or_else(|| panic!("CUBESQL_CUBE_URL is a required ENV variable")),
+                claims: None,
+                superuser: false,
             },
             password: None,
         })
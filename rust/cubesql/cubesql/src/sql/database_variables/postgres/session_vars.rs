@@ -42,5 +42,160 @@ pub fn defaults() -> DatabaseVariables {
         ),
     );
 
+    variables.insert(
+        "search_path".to_string(),
+        DatabaseVariable::system(
+            "search_path".to_string(),
+            ScalarValue::Utf8(Some("\"$user\", public".to_string())),
+            None,
+        ),
+    );
+
+    // Queries are canceled when execution takes longer, 0 - unlimited
+    variables.insert(
+        "statement_timeout".to_string(),
+        DatabaseVariable::system(
+            "statement_timeout".to_string(),
+            ScalarValue::Utf8(Some("0".to_string())),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "server_version".to_string(),
+        DatabaseVariable {
+            readonly: true,
+            ..DatabaseVariable::system(
+                "server_version".to_string(),
+                ScalarValue::Utf8(Some("14.2 (Cube SQL)".to_string())),
+                None,
+            )
+        },
+    );
+
+    variables.insert(
+        "server_encoding".to_string(),
+        DatabaseVariable {
+            readonly: true,
+            ..DatabaseVariable::system(
+                "server_encoding".to_string(),
+                ScalarValue::Utf8(Some("UTF8".to_string())),
+                None,
+            )
+        },
+    );
+
+    variables.insert(
+        "client_encoding".to_string(),
+        DatabaseVariable::system(
+            "client_encoding".to_string(),
+            ScalarValue::Utf8(Some("UTF8".to_string())),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "datestyle".to_string(),
+        DatabaseVariable::system(
+            "datestyle".to_string(),
+            ScalarValue::Utf8(Some("ISO".to_string())),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "intervalstyle".to_string(),
+        DatabaseVariable::system(
+            "intervalstyle".to_string(),
+            ScalarValue::Utf8(Some("postgres".to_string())),
+            None,
+        ),
+    );
+
+    // Timestamps are sent as int64 microseconds in binary format
+    variables.insert(
+        "integer_datetimes".to_string(),
+        DatabaseVariable {
+            readonly: true,
+            ..DatabaseVariable::system(
+                "integer_datetimes".to_string(),
+                ScalarValue::Utf8(Some("on".to_string())),
+                None,
+            )
+        },
+    );
+
+    // Backslashes in string literals are not escapes, it's how our parser works
+    variables.insert(
+        "standard_conforming_strings".to_string(),
+        DatabaseVariable {
+            readonly: true,
+            ..DatabaseVariable::system(
+                "standard_conforming_strings".to_string(),
+                ScalarValue::Utf8(Some("on".to_string())),
+                None,
+            )
+        },
+    );
+
+    variables.insert(
+        "session_authorization".to_string(),
+        DatabaseVariable::system(
+            "session_authorization".to_string(),
+            ScalarValue::Utf8(None),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "is_superuser".to_string(),
+        DatabaseVariable {
+            readonly: true,
+            ..DatabaseVariable::system(
+                "is_superuser".to_string(),
+                ScalarValue::Utf8(Some("on".to_string())),
+                None,
+            )
+        },
+    );
+
+    // Transaction characteristics are accepted for drivers (JDBC setReadOnly), but they don't
+    // change anything, because queries to Cube are read-only anyway
+    variables.insert(
+        "default_transaction_isolation".to_string(),
+        DatabaseVariable::system(
+            "default_transaction_isolation".to_string(),
+            ScalarValue::Utf8(Some("read committed".to_string())),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "default_transaction_read_only".to_string(),
+        DatabaseVariable::system(
+            "default_transaction_read_only".to_string(),
+            ScalarValue::Utf8(Some("off".to_string())),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "transaction_isolation".to_string(),
+        DatabaseVariable::system(
+            "transaction_isolation".to_string(),
+            ScalarValue::Utf8(Some("read committed".to_string())),
+            None,
+        ),
+    );
+
+    variables.insert(
+        "transaction_read_only".to_string(),
+        DatabaseVariable::system(
+            "transaction_read_only".to_string(),
+            ScalarValue::Utf8(Some("off".to_string())),
+            None,
+        ),
+    );
+
     variables
 }
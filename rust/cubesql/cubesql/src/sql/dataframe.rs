@@ -8,11 +8,11 @@ use chrono_tz::Tz;
 use comfy_table::{Cell, Table};
 use datafusion::arrow::{
     array::{
-        Array, ArrayRef, BooleanArray, Float16Array, Float32Array, Float64Array, Int16Array,
-        Int32Array, Int64Array, Int8Array, IntervalDayTimeArray, IntervalMonthDayNanoArray,
-        IntervalYearMonthArray, LargeStringArray, ListArray, StringArray,
-        TimestampMicrosecondArray, TimestampNanosecondArray, UInt16Array, UInt32Array, UInt64Array,
-        UInt8Array,
+        Array, ArrayRef, BooleanArray, Date32Array, Float16Array, Float32Array, Float64Array,
+        Int16Array, Int32Array, Int64Array, Int8Array, IntervalDayTimeArray,
+        IntervalMonthDayNanoArray, IntervalYearMonthArray, LargeStringArray, ListArray,
+        StringArray, TimestampMicrosecondArray, TimestampNanosecondArray, UInt16Array, UInt32Array,
+        UInt64Array, UInt8Array,
     },
     datatypes::{DataType, IntervalUnit, TimeUnit},
     record_batch::RecordBatch,
@@ -298,8 +298,10 @@ macro_rules! convert_array {
 pub fn arrow_to_column_type(arrow_type: DataType) -> Result<ColumnType, CubeError> {
     match arrow_type {
         DataType::Binary => Ok(ColumnType::Blob),
+        DataType::Null => Ok(ColumnType::String),
         DataType::Utf8 | DataType::LargeUtf8 => Ok(ColumnType::String),
         DataType::Timestamp(_, _) => Ok(ColumnType::String),
+        DataType::Date32 => Ok(ColumnType::String),
         DataType::Interval(_) => Ok(ColumnType::String),
         DataType::Float16 | DataType::Float64 => Ok(ColumnType::Double),
         DataType::Boolean => Ok(ColumnType::Boolean),
@@ -344,6 +346,11 @@ pub fn batch_to_dataframe(batches: &Vec<RecordBatch>) -> Result<DataFrame, CubeE
             let array = batch.column(column_index);
             let num_rows = batch.num_rows();
             match array.data_type() {
+                DataType::Null => {
+                    for i in 0..num_rows {
+                        rows[i].push(TableValue::Null);
+                    }
+                }
                 DataType::Int16 => convert_array!(array, num_rows, rows, Int16Array, Int64, i64),
                 DataType::Int32 => convert_array!(array, num_rows, rows, Int32Array, Int64, i64),
                 DataType::UInt32 => convert_array!(array, num_rows, rows, UInt32Array, Int64, i64),
@@ -370,6 +377,17 @@ pub fn batch_to_dataframe(batches: &Vec<RecordBatch>) -> Result<DataFrame, CubeE
                         });
                     }
                 }
+                DataType::Date32 => {
+                    let a = array.as_any().downcast_ref::<Date32Array>().unwrap();
+                    for i in 0..num_rows {
+                        rows[i].push(match a.value_as_date(i) {
+                            Some(date) if !a.is_null(i) => {
+                                TableValue::String(date.format("%Y-%m-%d").to_string())
+                            }
+                            _ => TableValue::Null,
+                        });
+                    }
+                }
                 DataType::Timestamp(TimeUnit::Microsecond, tz) => {
                     let a = array
                         .as_any()
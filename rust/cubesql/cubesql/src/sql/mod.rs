@@ -15,6 +15,9 @@ pub use mysql::*;
 pub use postgres::*;
 pub use server_manager::ServerManager;
 pub use service::*;
-pub use session::{Session, SessionProcessList, SessionProperties, SessionState};
+pub use session::{
+    Session, SessionChange, SessionProcessList, SessionProperties, SessionState, TransactionState,
+    VariablesChange,
+};
 pub use session_manager::SessionManager;
 pub use types::{ColumnFlags, ColumnType, StatusFlags};
@@ -204,6 +204,11 @@ impl MySqlConnection {
                 crate::compile::QueryPlan::MetaOk(status, _) => {
                     return Ok(QueryResponse::Ok(status));
                 },
+                crate::compile::QueryPlan::MetaChange(status, _, change) => {
+                    self.session.state.apply_change(change)?;
+
+                    return Ok(QueryResponse::Ok(status));
+                },
                 crate::compile::QueryPlan::MetaTabular(status, data_frame) => {
                     return Ok(QueryResponse::ResultSet(status, data_frame));
                 },
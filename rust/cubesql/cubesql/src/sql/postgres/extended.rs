@@ -1,11 +1,24 @@
 use crate::{
     compile::QueryPlan,
-    sql::dataframe::{batch_to_dataframe, DataFrame, TableValue},
+    sql::dataframe::{DataFrame, TableValue, TimestampValue},
     sql::statement::StatementParamsBinder,
-    sql::writer::BatchWriter,
+    sql::types::CommandCompletion,
+    sql::writer::{BatchWriter, CopyFormat, IntervalValue, NumericValue},
+    sql::{ColumnType, SessionChange},
     CubeError,
 };
-use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::{
+    array::{
+        Array, ArrayRef, BinaryArray, BooleanArray, Date32Array, Date64Array, DecimalArray,
+        Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array,
+        IntervalDayTimeArray, IntervalMonthDayNanoArray, IntervalYearMonthArray, LargeBinaryArray,
+        LargeStringArray, ListArray, StringArray, TimestampMicrosecondArray,
+        TimestampMillisecondArray, TimestampNanosecondArray, TimestampSecondArray, UInt16Array,
+        UInt32Array, UInt64Array, UInt8Array,
+    },
+    datatypes::{DataType, IntervalUnit, TimeUnit},
+    record_batch::RecordBatch,
+};
 use pg_srv::{protocol, BindValue};
 use sqlparser::ast;
 use std::fmt;
@@ -13,10 +26,29 @@ use std::fmt;
 use datafusion::dataframe::DataFrame as DFDataFrame;
 use datafusion::physical_plan::SendableRecordBatchStream;
 use futures::StreamExt;
+use tokio_util::sync::CancellationToken;
+
+/// Query of the prepared statement
+#[derive(Debug, Clone)]
+pub enum PreparedQuery {
+    Statement(ast::Statement),
+    // Statements which are not supported by the parser (CREATE FUNCTION, DROP FUNCTION, SET LOCAL,
+    // RELEASE, ROLLBACK TO) are planned by text
+    Text(String),
+}
+
+impl fmt::Display for PreparedQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreparedQuery::Statement(statement) => statement.fmt(f),
+            PreparedQuery::Text(query) => f.write_str(query),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct PreparedStatement {
-    pub query: ast::Statement,
+    pub query: PreparedQuery,
     pub parameters: protocol::ParameterDescription,
     // Fields which will be returned to the client, It can be None if server doesnt return any field
     // for example BEGIN
@@ -24,12 +56,17 @@ pub struct PreparedStatement {
 }
 
 impl PreparedStatement {
-    pub fn bind(&self, values: Vec<BindValue>) -> ast::Statement {
-        let binder = StatementParamsBinder::new(values);
-        let mut statement = self.query.clone();
-        binder.bind(&mut statement);
-
-        statement
+    pub fn bind(&self, values: Vec<BindValue>) -> PreparedQuery {
+        match &self.query {
+            PreparedQuery::Statement(statement) => {
+                let binder = StatementParamsBinder::new(values);
+                let mut statement = statement.clone();
+                binder.bind(&mut statement);
+
+                PreparedQuery::Statement(statement)
+            }
+            query => query.clone(),
+        }
     }
 }
 
@@ -72,10 +109,29 @@ impl fmt::Debug for InExecutionStreamState {
     }
 }
 
+/// Number of rows which can be returned by one execution of the portal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FetchSize {
+    /// All rows till the end of the result
+    All,
+    /// At most N rows, the portal is suspended when more rows are left
+    Limited(usize),
+}
+
+impl FetchSize {
+    /// max_rows of the Execute message, zero (or negative) denotes "no limit"
+    pub fn from_max_rows(max_rows: i32) -> Self {
+        if max_rows > 0 {
+            FetchSize::Limited(max_rows as usize)
+        } else {
+            FetchSize::All
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum PortalState {
     Prepared(PreparedState),
-    #[allow(dead_code)]
     InExecutionFrame(InExecutionFrameState),
     InExecutionStream(InExecutionStreamState),
     Finished,
@@ -83,8 +139,11 @@ pub enum PortalState {
 
 #[derive(Debug)]
 pub struct Portal {
-    // Format which is used to return data
-    format: protocol::Format,
+    // Formats which are used to return data: one per column, if the columns are known,
+    // otherwise as they were requested by Bind
+    formats: Vec<protocol::Format>,
+    // Rows are returned as CopyData in the format of COPY ... TO STDOUT
+    copy_format: Option<CopyFormat>,
     // State which holds corresponding data for each step. Option is used for dereferencing
     state: Option<PortalState>,
 }
@@ -95,15 +154,32 @@ unsafe impl Sync for Portal {}
 impl Portal {
     pub fn new(
         plan: QueryPlan,
-        format: protocol::Format,
+        formats: Vec<protocol::Format>,
         description: Option<protocol::RowDescription>,
     ) -> Self {
+        let description = description.map(|description| description.with_formats(&formats));
+        let formats = description
+            .as_ref()
+            .map(|description| description.formats())
+            .unwrap_or(formats);
+
         Self {
-            format,
+            formats,
+            copy_format: None,
             state: Some(PortalState::Prepared(PreparedState { plan, description })),
         }
     }
 
+    pub fn with_copy_format(mut self, copy_format: CopyFormat) -> Self {
+        self.copy_format = Some(copy_format);
+
+        self
+    }
+
+    pub fn get_copy_format(&self) -> Option<&CopyFormat> {
+        self.copy_format.as_ref()
+    }
+
     pub fn get_description(&self) -> Option<protocol::RowDescription> {
         match &self.state {
             Some(PortalState::Prepared(state)) => state.description.clone(),
@@ -111,33 +187,79 @@ impl Portal {
         }
     }
 
-    pub fn get_format(&self) -> protocol::Format {
-        self.format.clone()
+    pub fn get_formats(&self) -> Vec<protocol::Format> {
+        self.formats.clone()
+    }
+
+    /// Completion of the statement which doesn't return rows (BEGIN, SET), till it's executed
+    pub fn get_meta_completion(&self) -> Option<&CommandCompletion> {
+        match &self.state {
+            Some(PortalState::Prepared(PreparedState {
+                plan: QueryPlan::MetaOk(_, completion) | QueryPlan::MetaChange(_, completion, _),
+                ..
+            })) => Some(completion),
+            _ => None,
+        }
+    }
+
+    /// Change of the session by the statement, it's taken once by the connection to apply it
+    /// before the execution
+    pub fn take_session_change(&mut self) -> Option<SessionChange> {
+        match self.state.take() {
+            Some(PortalState::Prepared(PreparedState {
+                plan: QueryPlan::MetaChange(flags, completion, change),
+                description,
+            })) => {
+                self.state = Some(PortalState::Prepared(PreparedState {
+                    plan: QueryPlan::MetaOk(flags, completion),
+                    description,
+                }));
+
+                Some(change)
+            }
+            state => {
+                self.state = state;
+
+                None
+            }
+        }
+    }
+
+    /// Portal returned all rows, execution cannot be resumed
+    pub fn is_finished(&self) -> bool {
+        match &self.state {
+            Some(PortalState::Finished) => true,
+            _ => false,
+        }
     }
 
     async fn hand_execution_frame_state(
         &mut self,
         writer: &mut BatchWriter,
-        frame_state: InExecutionFrameState,
-        max_rows: usize,
+        mut frame_state: InExecutionFrameState,
+        fetch_size: FetchSize,
     ) -> Result<(PortalState, protocol::CommandComplete), CubeError> {
         let rows_read = frame_state.batch.len();
-        if max_rows > 0 && rows_read > 0 && rows_read > max_rows {
-            Err(CubeError::internal(format!(
-                "Cursor with limited max_rows: {} for DataFrame is not supported",
-                max_rows
-            )))
-        } else {
-            self.write_dataframe_to_writer(
-                writer,
-                frame_state.batch,
-                if max_rows == 0 { rows_read } else { max_rows },
-            )?;
-
-            Ok((
-                PortalState::Finished,
-                protocol::CommandComplete::Select(writer.num_rows() as u32),
-            ))
+        match fetch_size {
+            FetchSize::Limited(max_rows) if rows_read > max_rows => {
+                // Rows which were not returned will be used by the next execute
+                let rows = frame_state.batch.mut_rows().drain(0..max_rows).collect();
+                let frame = DataFrame::new(frame_state.batch.get_columns().clone(), rows);
+                self.write_dataframe_to_writer(writer, frame, max_rows)?;
+
+                Ok((
+                    PortalState::InExecutionFrame(frame_state),
+                    protocol::CommandComplete::Select(writer.num_rows() as u32),
+                ))
+            }
+            _ => {
+                self.write_dataframe_to_writer(writer, frame_state.batch, rows_read)?;
+
+                Ok((
+                    PortalState::Finished,
+                    protocol::CommandComplete::Select(writer.num_rows() as u32),
+                ))
+            }
         }
     }
 
@@ -147,19 +269,30 @@ impl Portal {
         frame: DataFrame,
         rows_to_read: usize,
     ) -> Result<(), CubeError> {
+        let column_types: Vec<ColumnType> =
+            frame.get_columns().iter().map(|c| c.get_type()).collect();
+
         for (idx, row) in frame.get_rows().iter().enumerate() {
-            // TODO: It's a hack, because we dont limit batch_to_dataframe by number of expected rows
             if idx >= rows_to_read {
                 break;
             }
 
-            for value in row.values() {
+            for (value, column_type) in row.values().iter().zip(column_types.iter()) {
                 match value {
                     TableValue::Null => writer.write_value::<Option<bool>>(None)?,
                     TableValue::String(v) => writer.write_value(v.clone())?,
-                    TableValue::Int64(v) => writer.write_value(*v)?,
+                    // Binary format requires the same width as the advertised type
+                    TableValue::Int64(v) => match column_type {
+                        ColumnType::Int8 => writer.write_value(*v as i16)?,
+                        ColumnType::Int32 => writer.write_value(*v as i32)?,
+                        _ => writer.write_value(*v)?,
+                    },
                     TableValue::Boolean(v) => writer.write_value(*v)?,
-                    TableValue::Float64(v) => writer.write_value(*v)?,
+                    // Double columns are advertised as NUMERIC
+                    TableValue::Float64(v) => match column_type {
+                        ColumnType::Double => writer.write_value(NumericValue::Float(*v))?,
+                        _ => writer.write_value(*v)?,
+                    },
                     TableValue::List(v) => writer.write_value(v.clone())?,
                     TableValue::Timestamp(v) => writer.write_value(v.clone())?,
                 };
@@ -171,20 +304,21 @@ impl Portal {
         Ok(())
     }
 
+    /// Writes rows of the batch, which fit into `left` rows. Rows which don't fit are returned
     fn iterate_stream_batch(
         &mut self,
         writer: &mut BatchWriter,
         batch: RecordBatch,
-        max_rows: usize,
-        left: &mut usize,
+        left: &mut FetchSize,
     ) -> Result<Option<RecordBatch>, CubeError> {
         let mut unused: Option<RecordBatch> = None;
 
-        let (batch_for_write, rows_to_read) = if max_rows == 0 {
-            let batch_num_rows = batch.num_rows();
-            (batch, batch_num_rows)
-        } else {
-            if batch.num_rows() > *left {
+        let (batch_for_write, rows_to_read) = match left {
+            FetchSize::All => {
+                let batch_num_rows = batch.num_rows();
+                (batch, batch_num_rows)
+            }
+            FetchSize::Limited(left) if batch.num_rows() > *left => {
                 let unused_batch = batch.slice(*left, batch.num_rows() - *left);
                 unused = Some(unused_batch);
 
@@ -192,34 +326,59 @@ impl Portal {
                 *left = 0;
 
                 r
-            } else {
+            }
+            FetchSize::Limited(left) => {
                 *left = *left - batch.num_rows();
                 let batch_num_rows = batch.num_rows();
                 (batch, batch_num_rows)
             }
         };
 
-        // TODO: Split doesn't split batches, it copy the part, lets dont convert whole batch to dataframe
-        let frame = batch_to_dataframe(&vec![batch_for_write])?;
-        self.write_dataframe_to_writer(writer, frame, rows_to_read)?;
+        self.write_batch_to_writer(writer, &batch_for_write, rows_to_read)?;
 
         Ok(unused)
     }
 
+    fn write_batch_to_writer(
+        &self,
+        writer: &mut BatchWriter,
+        batch: &RecordBatch,
+        rows_to_read: usize,
+    ) -> Result<(), CubeError> {
+        for idx in 0..std::cmp::min(rows_to_read, batch.num_rows()) {
+            for array in batch.columns() {
+                write_array_value(writer, array, idx)?;
+            }
+
+            writer.end_row()?;
+        }
+
+        Ok(())
+    }
+
+    fn canceled_error() -> CubeError {
+        CubeError::user("canceling statement due to user request".to_string())
+    }
+
     async fn hand_execution_stream_state(
         &mut self,
         writer: &mut BatchWriter,
         mut stream_state: InExecutionStreamState,
-        max_rows: usize,
+        fetch_size: FetchSize,
+        cancel: CancellationToken,
     ) -> Result<(PortalState, protocol::CommandComplete), CubeError> {
-        let mut left: usize = max_rows;
+        // Rows which can be returned yet, the portal is suspended when nothing is left
+        let mut left = fetch_size;
+
+        if cancel.is_cancelled() {
+            return Err(Self::canceled_error());
+        }
 
         if let Some(unused_batch) = stream_state.unused.take() {
-            stream_state.unused =
-                self.iterate_stream_batch(writer, unused_batch, max_rows, &mut left)?;
+            stream_state.unused = self.iterate_stream_batch(writer, unused_batch, &mut left)?;
         };
 
-        if max_rows > 0 && left == 0 {
+        if left == FetchSize::Limited(0) {
             return Ok((
                 PortalState::InExecutionStream(stream_state),
                 protocol::CommandComplete::Select(writer.num_rows() as u32),
@@ -227,7 +386,13 @@ impl Portal {
         }
 
         loop {
-            match stream_state.stream.next().await {
+            let next = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => return Err(Self::canceled_error()),
+                next = stream_state.stream.next() => next,
+            };
+
+            match next {
                 None => {
                     return Ok((
                         PortalState::Finished,
@@ -237,9 +402,9 @@ impl Portal {
                 Some(res) => match res {
                     Ok(batch) => {
                         stream_state.unused =
-                            self.iterate_stream_batch(writer, batch, max_rows, &mut left)?;
+                            self.iterate_stream_batch(writer, batch, &mut left)?;
 
-                        if max_rows > 0 && left == 0 {
+                        if left == FetchSize::Limited(0) {
                             return Ok((
                                 PortalState::InExecutionStream(stream_state),
                                 protocol::CommandComplete::Select(writer.num_rows() as u32),
@@ -257,12 +422,13 @@ impl Portal {
     pub async fn execute(
         &mut self,
         writer: &mut BatchWriter,
-        max_rows: usize,
+        fetch_size: FetchSize,
+        cancel: CancellationToken,
     ) -> Result<protocol::CommandComplete, CubeError> {
         if let Some(state) = self.state.take() {
             match state {
                 PortalState::Prepared(state) => match state.plan {
-                    QueryPlan::MetaOk(_, completion) => {
+                    QueryPlan::MetaOk(_, completion) | QueryPlan::MetaChange(_, completion, _) => {
                         self.state = Some(PortalState::Finished);
 
                         Ok(completion.clone().to_pg_command())
@@ -270,7 +436,7 @@ impl Portal {
                     QueryPlan::MetaTabular(_, batch) => {
                         let new_state = InExecutionFrameState { batch: *batch };
                         let (next_state, complete) = self
-                            .hand_execution_frame_state(writer, new_state, max_rows)
+                            .hand_execution_frame_state(writer, new_state, fetch_size)
                             .await?;
 
                         self.state = Some(next_state);
@@ -279,11 +445,16 @@ impl Portal {
                     }
                     QueryPlan::DataFusionSelect(_, plan, ctx) => {
                         let df = DFDataFrame::new(ctx.state.clone(), &plan);
-                        let stream = df.execute_stream().await?;
+                        // Physical planning and loading of data from Cube happens here
+                        let stream = tokio::select! {
+                            biased;
+                            _ = cancel.cancelled() => return Err(Self::canceled_error()),
+                            stream = df.execute_stream() => stream?,
+                        };
 
                         let new_state = InExecutionStreamState::new(stream);
                         let (next_state, complete) = self
-                            .hand_execution_stream_state(writer, new_state, max_rows)
+                            .hand_execution_stream_state(writer, new_state, fetch_size, cancel)
                             .await?;
                         self.state = Some(next_state);
 
@@ -292,7 +463,7 @@ impl Portal {
                 },
                 PortalState::InExecutionFrame(frame_state) => {
                     let (next_state, complete) = self
-                        .hand_execution_frame_state(writer, frame_state, max_rows)
+                        .hand_execution_frame_state(writer, frame_state, fetch_size)
                         .await?;
 
                     self.state = Some(next_state);
@@ -301,7 +472,7 @@ impl Portal {
                 }
                 PortalState::InExecutionStream(stream_state) => {
                     let (next_state, complete) = self
-                        .hand_execution_stream_state(writer, stream_state, max_rows)
+                        .hand_execution_stream_state(writer, stream_state, fetch_size, cancel)
                         .await?;
 
                     self.state = Some(next_state);
@@ -316,20 +487,168 @@ impl Portal {
     }
 }
 
+macro_rules! write_native_value {
+    ($WRITER:expr, $ARRAY:expr, $IDX:expr, $ARRAY_TYPE:ident, $NATIVE:ty) => {{
+        let arr = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        $WRITER.write_value(arr.value($IDX) as $NATIVE)?
+    }};
+}
+
+/// Writes value from the array without conversion to DataFrame. Value must have the width of
+/// the type which is advertised by df_type_to_pg_tid, because it matters for binary format
+fn write_array_value(
+    writer: &mut BatchWriter,
+    array: &ArrayRef,
+    idx: usize,
+) -> Result<(), CubeError> {
+    if array.data_type() == &DataType::Null || array.is_null(idx) {
+        writer.write_value::<Option<bool>>(None)?;
+
+        return Ok(());
+    }
+
+    match array.data_type() {
+        DataType::Boolean => write_native_value!(writer, array, idx, BooleanArray, bool),
+        // PG doesnt support i8, casting to i16
+        DataType::Int8 => write_native_value!(writer, array, idx, Int8Array, i16),
+        DataType::UInt8 => write_native_value!(writer, array, idx, UInt8Array, i16),
+        DataType::Int16 => write_native_value!(writer, array, idx, Int16Array, i16),
+        DataType::Int32 => write_native_value!(writer, array, idx, Int32Array, i32),
+        DataType::Int64 => write_native_value!(writer, array, idx, Int64Array, i64),
+        DataType::UInt16 => write_native_value!(writer, array, idx, UInt16Array, i64),
+        DataType::UInt32 => write_native_value!(writer, array, idx, UInt32Array, i64),
+        DataType::UInt64 => write_native_value!(writer, array, idx, UInt64Array, i64),
+        DataType::Float32 => write_native_value!(writer, array, idx, Float32Array, f32),
+        DataType::Float64 => write_native_value!(writer, array, idx, Float64Array, f64),
+        DataType::Utf8 => {
+            let arr = array.as_any().downcast_ref::<StringArray>().unwrap();
+            writer.write_value(arr.value(idx).to_string())?
+        }
+        DataType::LargeUtf8 => {
+            let arr = array.as_any().downcast_ref::<LargeStringArray>().unwrap();
+            writer.write_value(arr.value(idx).to_string())?
+        }
+        DataType::Binary => {
+            let arr = array.as_any().downcast_ref::<BinaryArray>().unwrap();
+            writer.write_value(arr.value(idx).to_vec())?
+        }
+        DataType::LargeBinary => {
+            let arr = array.as_any().downcast_ref::<LargeBinaryArray>().unwrap();
+            writer.write_value(arr.value(idx).to_vec())?
+        }
+        DataType::Decimal(_, _) => {
+            let arr = array.as_any().downcast_ref::<DecimalArray>().unwrap();
+            writer.write_value(NumericValue::Decimal(arr.value(idx), arr.scale()))?
+        }
+        DataType::Date32 => {
+            let arr = array.as_any().downcast_ref::<Date32Array>().unwrap();
+            writer.write_value(arr.value_as_date(idx))?
+        }
+        DataType::Date64 => {
+            let arr = array.as_any().downcast_ref::<Date64Array>().unwrap();
+            writer.write_value(arr.value_as_date(idx))?
+        }
+        DataType::Timestamp(TimeUnit::Second, tz) => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<TimestampSecondArray>()
+                .unwrap();
+            writer.write_value(TimestampValue::new(
+                arr.value(idx) * 1_000_000_000_i64,
+                tz.clone(),
+            ))?
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, tz) => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<TimestampMillisecondArray>()
+                .unwrap();
+            writer.write_value(TimestampValue::new(
+                arr.value(idx) * 1_000_000_i64,
+                tz.clone(),
+            ))?
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, tz) => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<TimestampMicrosecondArray>()
+                .unwrap();
+            writer.write_value(TimestampValue::new(arr.value(idx) * 1000_i64, tz.clone()))?
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, tz) => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<TimestampNanosecondArray>()
+                .unwrap();
+            writer.write_value(TimestampValue::new(arr.value(idx), tz.clone()))?
+        }
+        DataType::Interval(IntervalUnit::YearMonth) => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<IntervalYearMonthArray>()
+                .unwrap();
+            writer.write_value(IntervalValue::from_year_month(arr.value(idx)))?
+        }
+        DataType::Interval(IntervalUnit::DayTime) => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<IntervalDayTimeArray>()
+                .unwrap();
+            writer.write_value(IntervalValue::from_day_time(arr.value(idx)))?
+        }
+        DataType::Interval(IntervalUnit::MonthDayNano) => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<IntervalMonthDayNanoArray>()
+                .unwrap();
+            writer.write_value(IntervalValue::from_month_day_nano(arr.value(idx)))?
+        }
+        DataType::List(_) => {
+            let arr = array.as_any().downcast_ref::<ListArray>().unwrap();
+            writer.write_value(arr.value(idx))?
+        }
+        dt => {
+            return Err(CubeError::internal(format!(
+                "Unsupported data type for pg-wire: {:?}",
+                dt
+            )))
+        }
+    };
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         compile::engine::information_schema::postgres::testing_dataset::InfoSchemaTestingDatasetProvider,
         sql::dataframe::{Column, DataFrame, Row, TableValue},
-        sql::extended::{InExecutionFrameState, InExecutionStreamState, Portal, PortalState},
+        sql::extended::{
+            FetchSize, InExecutionFrameState, InExecutionStreamState, Portal, PortalState,
+        },
         sql::writer::BatchWriter,
         sql::{ColumnFlags, ColumnType},
         CubeError,
     };
-    use pg_srv::protocol::Format;
-
-    use datafusion::prelude::SessionContext;
-    use std::sync::Arc;
+    use pg_srv::{buffer, protocol::Format};
+
+    use datafusion::{
+        arrow::{
+            array::{BinaryArray, Date32Array, DecimalBuilder},
+            datatypes::{DataType, Field, Schema},
+            record_batch::RecordBatch,
+        },
+        prelude::SessionContext,
+    };
+    use std::{io::Cursor, sync::Arc};
+    use tokio_util::sync::CancellationToken;
+
+    #[test]
+    fn test_fetch_size_from_max_rows() {
+        assert_eq!(FetchSize::from_max_rows(0), FetchSize::All);
+        assert_eq!(FetchSize::from_max_rows(-1), FetchSize::All);
+        assert_eq!(FetchSize::from_max_rows(100), FetchSize::Limited(100));
+    }
 
     fn generate_testing_data_frame(cnt: usize) -> DataFrame {
         let mut rows = vec![];
@@ -350,16 +669,23 @@ mod tests {
 
     #[tokio::test]
     async fn test_portal_legacy_dataframe_limited_more() -> Result<(), CubeError> {
-        let mut writer = BatchWriter::new(Format::Binary);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
 
         let mut portal = Portal {
-            format: Format::Binary,
+            formats: vec![Format::Binary],
+            copy_format: None,
             state: Some(PortalState::InExecutionFrame(InExecutionFrameState {
                 batch: generate_testing_data_frame(3),
             })),
         };
 
-        portal.execute(&mut writer, 10).await?;
+        portal
+            .execute(
+                &mut writer,
+                FetchSize::Limited(10),
+                CancellationToken::new(),
+            )
+            .await?;
         // Batch will not be split, because clients wants more rows then in batch
         assert_eq!(3, writer.num_rows());
 
@@ -368,70 +694,201 @@ mod tests {
 
     #[tokio::test]
     async fn test_portal_legacy_dataframe_limited_less() -> Result<(), CubeError> {
-        let mut writer = BatchWriter::new(Format::Binary);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
 
         let mut portal = Portal {
-            format: Format::Binary,
+            formats: vec![Format::Binary],
+            copy_format: None,
             state: Some(PortalState::InExecutionFrame(InExecutionFrameState {
                 batch: generate_testing_data_frame(3),
             })),
         };
 
-        let res = portal.execute(&mut writer, 1).await;
-        match res {
-            Ok(_) => panic!("must panic"),
-            Err(e) => assert_eq!(
-                e.message,
-                "Cursor with limited max_rows: 1 for DataFrame is not supported"
-            ),
-        }
+        portal
+            .execute(&mut writer, FetchSize::Limited(1), CancellationToken::new())
+            .await?;
+        assert_eq!(1, writer.num_rows());
+        assert_eq!(portal.is_finished(), false);
+
+        // Rest of the frame is used by the next execute
+        portal
+            .execute(
+                &mut writer,
+                FetchSize::Limited(10),
+                CancellationToken::new(),
+            )
+            .await?;
+        assert_eq!(3, writer.num_rows());
+        assert_eq!(portal.is_finished(), true);
 
         Ok(())
     }
 
     #[tokio::test]
     async fn test_portal_legacy_dataframe_unlimited() -> Result<(), CubeError> {
-        let mut writer = BatchWriter::new(Format::Binary);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
 
         let mut portal = Portal {
-            format: Format::Binary,
+            formats: vec![Format::Binary],
+            copy_format: None,
             state: Some(PortalState::InExecutionFrame(InExecutionFrameState {
                 batch: generate_testing_data_frame(3),
             })),
         };
 
-        portal.execute(&mut writer, 0).await?;
+        portal
+            .execute(&mut writer, FetchSize::All, CancellationToken::new())
+            .await?;
         assert_eq!(3, writer.num_rows());
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_portal_legacy_dataframe_binary_int_width() -> Result<(), CubeError> {
+        let mut cursor = Cursor::new(vec![]);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
+
+        let mut portal = Portal {
+            formats: vec![Format::Binary],
+            copy_format: None,
+            state: Some(PortalState::InExecutionFrame(InExecutionFrameState {
+                batch: DataFrame::new(
+                    vec![
+                        Column::new("i2".to_string(), ColumnType::Int8, ColumnFlags::empty()),
+                        Column::new("i4".to_string(), ColumnType::Int32, ColumnFlags::empty()),
+                    ],
+                    vec![Row::new(vec![TableValue::Int64(1), TableValue::Int64(2)])],
+                ),
+            })),
+        };
+
+        portal
+            .execute(&mut writer, FetchSize::All, CancellationToken::new())
+            .await?;
+        buffer::write_direct(&mut cursor, writer).await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            vec![68, 0, 0, 0, 20, 0, 2, 0, 0, 0, 2, 0, 1, 0, 0, 0, 4, 0, 0, 0, 2]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_portal_legacy_dataframe_binary_numeric() -> Result<(), CubeError> {
+        let mut cursor = Cursor::new(vec![]);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
+
+        let mut portal = Portal {
+            formats: vec![Format::Binary],
+            copy_format: None,
+            state: Some(PortalState::InExecutionFrame(InExecutionFrameState {
+                batch: DataFrame::new(
+                    vec![Column::new(
+                        "n".to_string(),
+                        ColumnType::Double,
+                        ColumnFlags::empty(),
+                    )],
+                    vec![Row::new(vec![TableValue::Float64(1.5)])],
+                ),
+            })),
+        };
+
+        portal
+            .execute(&mut writer, FetchSize::All, CancellationToken::new())
+            .await?;
+        buffer::write_direct(&mut cursor, writer).await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            vec![68, 0, 0, 0, 22, 0, 1, 0, 0, 0, 12, 0, 2, 0, 0, 0, 0, 0, 1, 0, 1, 19, 136]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_portal_batch_binary_date_numeric_bytea() -> Result<(), CubeError> {
+        let mut cursor = Cursor::new(vec![]);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
+
+        let mut decimal = DecimalBuilder::new(1, 10, 2);
+        decimal.append_value(12345)?;
+
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("d", DataType::Date32, false),
+                Field::new("n", DataType::Decimal(10, 2), false),
+                Field::new("b", DataType::Binary, false),
+            ])),
+            vec![
+                // 2022-04-25
+                Arc::new(Date32Array::from(vec![19107])),
+                Arc::new(decimal.finish()),
+                Arc::new(BinaryArray::from(vec![&[10_u8, 255][..]])),
+            ],
+        )?;
+
+        let portal = Portal {
+            formats: vec![Format::Binary],
+            copy_format: None,
+            state: None,
+        };
+        portal.write_batch_to_writer(&mut writer, &batch, 1)?;
+        buffer::write_direct(&mut cursor, writer).await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            vec![
+                68, 0, 0, 0, 36, 0, 3, // date
+                0, 0, 0, 4, 0, 0, 31, 214, // numeric
+                0, 0, 0, 12, 0, 2, 0, 0, 0, 0, 0, 2, 0, 123, 17, 148, // bytea
+                0, 0, 0, 2, 10, 255
+            ]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_portal_df_stream_single_batch() -> Result<(), CubeError> {
-        let mut writer = BatchWriter::new(Format::Binary);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
 
         let ctx = SessionContext::new();
         let table = Arc::new(InfoSchemaTestingDatasetProvider::new(1, 250));
         let stream = ctx.read_table(table)?.execute_stream().await?;
 
         let mut portal = Portal {
-            format: Format::Binary,
+            formats: vec![Format::Binary],
+            copy_format: None,
             state: Some(PortalState::InExecutionStream(InExecutionStreamState {
                 stream,
                 unused: None,
             })),
         };
 
-        portal.execute(&mut writer, 1).await?;
+        portal
+            .execute(&mut writer, FetchSize::Limited(1), CancellationToken::new())
+            .await?;
         // batch 1 will be spited to 250 -1 (unused) and 1
         assert_eq!(1, writer.num_rows());
 
         // usage of unused batch, 249 - 6 (unused) and 6
-        portal.execute(&mut writer, 5).await?;
+        portal
+            .execute(&mut writer, FetchSize::Limited(5), CancellationToken::new())
+            .await?;
         assert_eq!(6, writer.num_rows());
 
         // usage of unused batch
-        portal.execute(&mut writer, 1000).await?;
+        portal
+            .execute(
+                &mut writer,
+                FetchSize::Limited(1000),
+                CancellationToken::new(),
+            )
+            .await?;
         assert_eq!(250, writer.num_rows());
 
         Ok(())
@@ -439,14 +896,15 @@ mod tests {
 
     #[tokio::test]
     async fn test_portal_df_stream_small_batches() -> Result<(), CubeError> {
-        let mut writer = BatchWriter::new(Format::Binary);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
 
         let ctx = SessionContext::new();
         let table = Arc::new(InfoSchemaTestingDatasetProvider::new(10, 15));
         let stream = ctx.read_table(table)?.execute_stream().await?;
 
         let mut portal = Portal {
-            format: Format::Binary,
+            formats: vec![Format::Binary],
+            copy_format: None,
             state: Some(PortalState::InExecutionStream(InExecutionStreamState {
                 stream,
                 unused: None,
@@ -454,24 +912,89 @@ mod tests {
         };
 
         // use 1 batch
-        portal.execute(&mut writer, 10).await.unwrap();
+        portal
+            .execute(
+                &mut writer,
+                FetchSize::Limited(10),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
         assert_eq!(10, writer.num_rows());
 
         // use 2 batch
-        portal.execute(&mut writer, 20).await.unwrap();
+        portal
+            .execute(
+                &mut writer,
+                FetchSize::Limited(20),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
         assert_eq!(30, writer.num_rows());
 
         // use 0.5 batch
-        portal.execute(&mut writer, 5).await.unwrap();
+        portal
+            .execute(&mut writer, FetchSize::Limited(5), CancellationToken::new())
+            .await
+            .unwrap();
         assert_eq!(35, writer.num_rows());
 
-        portal.execute(&mut writer, 15).await.unwrap();
+        portal
+            .execute(
+                &mut writer,
+                FetchSize::Limited(15),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
         assert_eq!(50, writer.num_rows());
 
         // use 7 batches
-        portal.execute(&mut writer, 1000).await.unwrap();
+        portal
+            .execute(
+                &mut writer,
+                FetchSize::Limited(1000),
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
         assert_eq!(150, writer.num_rows());
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_portal_df_stream_canceled() -> Result<(), CubeError> {
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
+
+        let ctx = SessionContext::new();
+        let table = Arc::new(InfoSchemaTestingDatasetProvider::new(10, 15));
+        let stream = ctx.read_table(table)?.execute_stream().await?;
+
+        let mut portal = Portal {
+            formats: vec![Format::Binary],
+            copy_format: None,
+            state: Some(PortalState::InExecutionStream(InExecutionStreamState {
+                stream,
+                unused: None,
+            })),
+        };
+
+        let cancel = CancellationToken::new();
+        portal
+            .execute(&mut writer, FetchSize::Limited(10), cancel.clone())
+            .await?;
+        assert_eq!(10, writer.num_rows());
+
+        cancel.cancel();
+
+        match portal.execute(&mut writer, FetchSize::All, cancel).await {
+            Err(err) => assert_eq!(err.message, "canceling statement due to user request"),
+            Ok(_) => panic!("Execution must be canceled"),
+        };
+        assert_eq!(10, writer.num_rows());
+
+        Ok(())
+    }
 }
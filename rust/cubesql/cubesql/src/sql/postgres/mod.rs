@@ -2,7 +2,9 @@ pub(crate) mod extended;
 pub(crate) mod pg_type;
 pub(crate) mod service;
 pub(crate) mod shim;
+pub(crate) mod tls;
 pub(crate) mod writer;
 
 pub use pg_type::*;
 pub use service::*;
+pub use tls::TlsConfig;
@@ -1,10 +1,13 @@
 use datafusion::arrow::datatypes::DataType;
 use pg_srv::PgTypeId;
-use std::io;
 
-pub fn df_type_to_pg_tid(dt: &DataType) -> Result<PgTypeId, io::Error> {
+use crate::CubeError;
+
+pub fn df_type_to_pg_tid(dt: &DataType) -> Result<PgTypeId, CubeError> {
     match dt {
         DataType::Boolean => Ok(PgTypeId::BOOL),
+        // PG doesnt support i8, it's written as i16
+        DataType::Int8 | DataType::UInt8 => Ok(PgTypeId::INT2),
         DataType::Int16 => Ok(PgTypeId::INT2),
         DataType::Int32 => Ok(PgTypeId::INT4),
         DataType::Int64 => Ok(PgTypeId::INT8),
@@ -14,11 +17,16 @@ pub fn df_type_to_pg_tid(dt: &DataType) -> Result<PgTypeId, io::Error> {
         DataType::Float32 => Ok(PgTypeId::FLOAT4),
         DataType::Float64 => Ok(PgTypeId::FLOAT8),
         DataType::Utf8 | DataType::LargeUtf8 => Ok(PgTypeId::TEXT),
+        DataType::Binary | DataType::LargeBinary => Ok(PgTypeId::BYTEA),
+        DataType::Date32 | DataType::Date64 => Ok(PgTypeId::DATE),
+        DataType::Decimal(_, _) => Ok(PgTypeId::NUMERIC),
         DataType::Timestamp(_, tz) => match tz {
             None => Ok(PgTypeId::TIMESTAMP),
             Some(_) => Ok(PgTypeId::TIMESTAMPTZ),
         },
-        DataType::Null => Ok(PgTypeId::BOOL),
+        DataType::Interval(_) => Ok(PgTypeId::INTERVAL),
+        // PostgreSQL resolves literals of unknown type (bare NULL) as text
+        DataType::Null => Ok(PgTypeId::TEXT),
         DataType::List(field) => match field.data_type() {
             DataType::Boolean => Ok(PgTypeId::ArrayBool),
             DataType::Int8 => Ok(PgTypeId::ArrayInt2),
@@ -33,15 +41,84 @@ pub fn df_type_to_pg_tid(dt: &DataType) -> Result<PgTypeId, io::Error> {
             DataType::Float32 => Ok(PgTypeId::ArrayFloat4),
             DataType::Float64 => Ok(PgTypeId::ArrayFloat8),
             DataType::Binary => Ok(PgTypeId::ArrayBytea),
-            DataType::Utf8 => Ok(PgTypeId::ArrayText),
-            dt => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Unsupported data type in List for pg-wire: {:?}", dt),
-            )),
+            DataType::Utf8 | DataType::LargeUtf8 => Ok(PgTypeId::ArrayText),
+            // Multidimensional arrays have the same type as arrays of their elements
+            DataType::List(_) => df_type_to_pg_tid(field.data_type()),
+            dt => Err(CubeError::internal(format!(
+                "Unsupported data type in List for pg-wire: {:?}",
+                dt
+            ))),
         },
-        dt => Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("Unsupported data type for pg-wire: {:?}", dt),
-        )),
+        dt => Err(CubeError::internal(format!(
+            "Unsupported data type for pg-wire: {:?}",
+            dt
+        ))),
+    }
+}
+
+/// Type modifier for RowDescription: precision and scale of NUMERIC, -1 for other types
+pub fn df_type_to_pg_type_modifier(dt: &DataType) -> i32 {
+    match dt {
+        // VARHDRSZ is added to (precision << 16) | scale
+        DataType::Decimal(precision, scale) => (((*precision as i32) << 16) | *scale as i32) + 4,
+        _ => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::datatypes::{Field, IntervalUnit, TimeUnit};
+
+    use super::*;
+
+    #[test]
+    fn test_df_type_to_pg_tid() {
+        for (dt, expected) in vec![
+            (DataType::Decimal(38, 10), PgTypeId::NUMERIC),
+            (
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                PgTypeId::TIMESTAMP,
+            ),
+            (
+                DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".to_string())),
+                PgTypeId::TIMESTAMPTZ,
+            ),
+            (DataType::Date32, PgTypeId::DATE),
+            (
+                DataType::Interval(IntervalUnit::DayTime),
+                PgTypeId::INTERVAL,
+            ),
+            (
+                DataType::Interval(IntervalUnit::MonthDayNano),
+                PgTypeId::INTERVAL,
+            ),
+            (
+                DataType::List(Box::new(Field::new("item", DataType::Int32, true))),
+                PgTypeId::ArrayInt4,
+            ),
+            (
+                DataType::List(Box::new(Field::new("item", DataType::Utf8, true))),
+                PgTypeId::ArrayText,
+            ),
+            (
+                DataType::List(Box::new(Field::new(
+                    "item",
+                    DataType::List(Box::new(Field::new("item", DataType::Int64, true))),
+                    true,
+                ))),
+                PgTypeId::ArrayInt8,
+            ),
+        ] {
+            assert_eq!(df_type_to_pg_tid(&dt).unwrap(), expected, "{:?}", dt);
+        }
+
+        assert_eq!(
+            df_type_to_pg_type_modifier(&DataType::Decimal(10, 2)),
+            655366
+        );
+        assert_eq!(df_type_to_pg_type_modifier(&DataType::Int64), -1);
+
+        let err = df_type_to_pg_tid(&DataType::Duration(TimeUnit::Second)).unwrap_err();
+        assert!(err.message.contains("Duration(Second)"), "{}", err.message);
     }
 }
@@ -1,23 +1,31 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use log::{error, trace};
+use log::{debug, error, trace};
 use tokio::{
     net::TcpListener,
-    sync::{watch, RwLock},
+    sync::{mpsc, watch, RwLock},
 };
 
 use crate::{
     config::processing_loop::ProcessingLoop,
-    sql::{session::DatabaseProtocol, SessionManager},
+    sql::{
+        session::{DatabaseProtocol, Session},
+        SessionManager,
+    },
     CubeError,
 };
 
-use super::shim::AsyncPostgresShim;
+use super::{shim::AsyncPostgresShim, tls::TlsConfig};
+
+/// Time for terminated connections to abort their queries and close sockets on shutdown
+const TERMINATED_CONNECTIONS_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct PostgresServer {
     // options
     address: String,
+    tls_config: Option<Arc<TlsConfig>>,
+    send_buffer_size: Option<usize>,
     close_socket_rx: RwLock<watch::Receiver<bool>>,
     close_socket_tx: watch::Sender<bool>,
     // reference
@@ -33,6 +41,9 @@ impl ProcessingLoop for PostgresServer {
 
         println!("🔗 Cube SQL (pg) is listening on {}", self.address);
 
+        // Every connection task holds a sender, the channel is closed when all of them are finished
+        let (connections_tx, connections_rx) = mpsc::channel::<()>(1);
+
         loop {
             let mut stop_receiver = self.close_socket_rx.write().await;
             let (socket, _) = tokio::select! {
@@ -40,6 +51,11 @@ impl ProcessingLoop for PostgresServer {
                     if res.is_err() || *stop_receiver.borrow() {
                         trace!("[pg] Stopping processing_loop via channel");
 
+                        // New connections are refused while existing ones are finishing
+                        drop(listener);
+                        drop(connections_tx);
+                        self.shutdown_sessions(connections_rx).await;
+
                         return Ok(());
                     } else {
                         continue;
@@ -63,10 +79,17 @@ impl ProcessingLoop for PostgresServer {
 
             trace!("[pg] New connection {}", session.state.connection_id);
 
+            let tls_config = self.tls_config.clone();
+            let send_buffer_size = self.send_buffer_size;
+            let connection = connections_tx.clone();
             tokio::spawn(async move {
-                if let Err(e) = AsyncPostgresShim::run_on(socket, session).await {
+                if let Err(e) =
+                    AsyncPostgresShim::run_on(socket, session, tls_config, send_buffer_size).await
+                {
                     error!("Error during processing PostgreSQL connection: {}", e);
                 }
+
+                drop(connection);
             });
         }
     }
@@ -78,10 +101,62 @@ impl ProcessingLoop for PostgresServer {
 }
 
 impl PostgresServer {
-    pub fn new(address: String, session_manager: Arc<SessionManager>) -> Arc<Self> {
+    /// Asks connections to close after their current messages. Connections which are still alive
+    /// after the grace period are terminated, including their queries
+    async fn shutdown_sessions(&self, mut connections: mpsc::Receiver<()>) {
+        let grace_period = self
+            .session_manager
+            .server
+            .configuration
+            .shutdown_grace_period;
+
+        // Accepting is stopped, so every connection already has its session
+        for session in self.sessions() {
+            session.state.shutdown();
+        }
+
+        if tokio::time::timeout(grace_period, connections.recv())
+            .await
+            .is_ok()
+        {
+            return;
+        }
+
+        let sessions = self.sessions();
+        debug!(
+            "[pg] Terminating {} connection(s) after shutdown grace period",
+            sessions.len()
+        );
+        for session in sessions {
+            session.state.terminate();
+        }
+
+        if tokio::time::timeout(TERMINATED_CONNECTIONS_TIMEOUT, connections.recv())
+            .await
+            .is_err()
+        {
+            error!("[pg] Terminated connections were not closed in time");
+        }
+    }
+
+    fn sessions(&self) -> Vec<Arc<Session>> {
+        self.session_manager
+            .sessions()
+            .filter(|session| session.state.protocol == DatabaseProtocol::PostgreSQL)
+            .collect()
+    }
+
+    pub fn new(
+        address: String,
+        tls_config: Option<TlsConfig>,
+        send_buffer_size: Option<usize>,
+        session_manager: Arc<SessionManager>,
+    ) -> Arc<Self> {
         let (close_socket_tx, close_socket_rx) = watch::channel(false);
         Arc::new(Self {
             address,
+            tls_config: tls_config.map(Arc::new),
+            send_buffer_size,
             session_manager,
             close_socket_rx: RwLock::new(close_socket_rx),
             close_socket_tx,
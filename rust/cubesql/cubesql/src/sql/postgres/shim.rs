@@ -1,32 +1,312 @@
 use std::{
     collections::HashMap,
-    io::{Error, ErrorKind},
+    io::{Cursor, Error, ErrorKind},
     sync::Arc,
+    time::Instant,
 };
 
-use super::extended::PreparedStatement;
+use super::{
+    extended::{PreparedQuery, PreparedStatement},
+    tls::{PostgresStream, TlsConfig},
+};
 use crate::{
     compile::{
-        convert_sql_to_cube_query, convert_statement_to_cube_query, parser::parse_sql_to_statement,
-        QueryPlan,
+        convert_sql_to_cube_query, convert_statement_to_cube_query,
+        macros::{parse_sql_macro_statement, type_oid_by_name},
+        parser::{
+            parse_copy_to_statement, parse_listen_command, parse_prepared_statement_command,
+            parse_sql_to_statement, parse_transaction_command, CopyToStatement, ListenCommand,
+            PreparedStatementCommand,
+        },
+        CompilationError, MetaContext, QueryPlan,
     },
-    sql::df_type_to_pg_tid,
-    sql::extended::Portal,
+    sql::database_variables::{DatabaseVariable, DatabaseVariables},
+    sql::extended::{FetchSize, Portal},
     sql::statement::StatementPlaceholderReplacer,
-    sql::writer::BatchWriter,
-    sql::{session::DatabaseProtocol, statement::StatementParamsFinder, AuthContext, Session},
-    CubeError,
+    sql::writer::{BatchWriter, CopyFormat},
+    sql::{df_type_to_pg_tid, df_type_to_pg_type_modifier},
+    sql::{
+        session::DatabaseProtocol, statement::StatementParamsTypesFinder, types::CommandCompletion,
+        AuthContext, Session, SessionState, TransactionState,
+    },
+    CubeError, CubeErrorKind,
 };
-use log::{debug, error, trace};
+use datafusion::scalar::ScalarValue;
+use log::{debug, error, trace, warn};
 use pg_srv::{buffer, protocol};
 use pg_srv::{protocol::Format, PgType, PgTypeId};
-use tokio::{io::AsyncWriteExt, net::TcpStream};
+use socket2::SockRef;
+use sqlparser::ast;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Parameters which are reported by ParameterStatus after startup and on change, drivers use
+/// them to configure codecs: (reported name, session variable)
+const STARTUP_PARAMETERS: [(&str, &str); 11] = [
+    ("server_version", "server_version"),
+    ("server_encoding", "server_encoding"),
+    ("client_encoding", "client_encoding"),
+    ("DateStyle", "datestyle"),
+    ("IntervalStyle", "intervalstyle"),
+    ("TimeZone", "timezone"),
+    ("integer_datetimes", "integer_datetimes"),
+    ("standard_conforming_strings", "standard_conforming_strings"),
+    ("application_name", "application_name"),
+    ("session_authorization", "session_authorization"),
+    ("is_superuser", "is_superuser"),
+];
+
+/// Startup parameters which are not session variables
+const STARTUP_CONNECTION_PARAMETERS: [&str; 4] = ["user", "database", "options", "replication"];
+
+fn is_utf8_encoding(encoding: &str) -> bool {
+    matches!(
+        encoding.trim().to_uppercase().replace('-', "").as_str(),
+        "UTF8" | "UNICODE"
+    )
+}
+
+/// Session variables from the startup parameters, including `-c name=value` and `--name=value`
+/// from the `options` parameter
+fn startup_parameters_to_variables(parameters: &HashMap<String, String>) -> DatabaseVariables {
+    let mut values = vec![];
+
+    if let Some(options) = parameters.get("options") {
+        let mut args = options.split_whitespace();
+        while let Some(arg) = args.next() {
+            let option = if arg == "-c" {
+                args.next()
+            } else if let Some(option) = arg.strip_prefix("-c") {
+                Some(option)
+            } else {
+                arg.strip_prefix("--")
+            };
+
+            if let Some((name, value)) = option.and_then(|option| option.split_once('=')) {
+                values.push((name.replace('-', "_"), value.to_string()));
+            }
+        }
+    }
+
+    for (name, value) in parameters.iter() {
+        if !STARTUP_CONNECTION_PARAMETERS.contains(&name.as_str()) {
+            values.push((name.clone(), value.clone()));
+        }
+    }
+
+    values
+        .into_iter()
+        .map(|(name, value)| {
+            let name = name.to_lowercase();
+            let value = if name == "client_encoding" {
+                "UTF8".to_string()
+            } else {
+                value
+            };
+
+            (
+                name.clone(),
+                DatabaseVariable::system(name, ScalarValue::Utf8(Some(value)), None),
+            )
+        })
+        .collect()
+}
+
+/// Format of results by application_name of the driver, see ServerConfiguration::default_result_formats
+fn default_result_format(
+    formats: &[(String, Format)],
+    parameters: &HashMap<String, String>,
+) -> Format {
+    let application_name = match parameters.get("application_name") {
+        Some(application_name) => application_name.to_lowercase(),
+        None => return Format::Text,
+    };
+
+    formats
+        .iter()
+        .find(|(prefix, _)| application_name.starts_with(&prefix.to_lowercase()))
+        .map(|(_, format)| *format)
+        .unwrap_or(Format::Text)
+}
+
+/// Values of STARTUP_PARAMETERS in the order of reporting
+fn reported_parameters(state: &SessionState) -> Vec<(String, String)> {
+    let variables = state.all_variables();
+
+    STARTUP_PARAMETERS
+        .iter()
+        .filter_map(|(key, variable_name)| {
+            let value = match variables.get(*variable_name).map(|v| &v.value) {
+                Some(ScalarValue::Utf8(value)) => value.clone().unwrap_or_default(),
+                Some(value) => value.to_string(),
+                None => return None,
+            };
+
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+/// CREATE FUNCTION, DROP FUNCTION, SET LOCAL, RELEASE and ROLLBACK TO are recognized by the text,
+/// as the simple query does
+fn parse_prepared_query(query: &String) -> Result<PreparedQuery, CompilationError> {
+    if parse_sql_macro_statement(query)?.is_some() || parse_transaction_command(query)?.is_some() {
+        return Ok(PreparedQuery::Text(query.clone()));
+    }
+
+    parse_sql_to_statement(query, DatabaseProtocol::PostgreSQL).map(PreparedQuery::Statement)
+}
+
+/// Text representation of the literal argument of EXECUTE, it's decoded as a text parameter of
+/// Bind. None is NULL
+fn execute_argument_to_text(expr: &ast::Expr) -> Result<Option<String>, CompilationError> {
+    match expr {
+        ast::Expr::Value(ast::Value::Number(number, _)) => Ok(Some(number.to_string())),
+        ast::Expr::Value(ast::Value::SingleQuotedString(value)) => Ok(Some(value.clone())),
+        ast::Expr::Value(ast::Value::Boolean(value)) => Ok(Some(value.to_string())),
+        ast::Expr::Value(ast::Value::Null) => Ok(None),
+        ast::Expr::UnaryOp {
+            op: ast::UnaryOperator::Minus,
+            expr,
+        } => match expr.as_ref() {
+            ast::Expr::Value(ast::Value::Number(number, _)) => Ok(Some(format!("-{}", number))),
+            _ => Err(CompilationError::Unsupported(format!(
+                "Unsupported argument of EXECUTE: {}",
+                expr
+            ))),
+        },
+        // Type of the parameter is defined by PREPARE
+        ast::Expr::Cast { expr, .. } => execute_argument_to_text(expr),
+        ast::Expr::Nested(expr) => execute_argument_to_text(expr),
+        _ => Err(CompilationError::Unsupported(format!(
+            "Unsupported argument of EXECUTE: {}",
+            expr
+        ))),
+    }
+}
+
+/// Format of rows of COPY ... TO STDOUT by its options, binary format is not supported
+fn copy_format_from_options(
+    options: &[ast::CopyOption],
+    legacy_options: &[ast::CopyLegacyOption],
+) -> Result<CopyFormat, CompilationError> {
+    let mut csv = false;
+    for option in options {
+        if let ast::CopyOption::Format(name) = option {
+            match name.value.to_lowercase().as_str() {
+                "csv" => csv = true,
+                "text" => csv = false,
+                "binary" => {
+                    return Err(CompilationError::Unsupported(
+                        "COPY in binary format is not supported".to_string(),
+                    ))
+                }
+                format => {
+                    return Err(CompilationError::User(format!(
+                        "COPY format \"{}\" not recognized",
+                        format
+                    )))
+                }
+            }
+        }
+    }
+    for option in legacy_options {
+        match option {
+            ast::CopyLegacyOption::Csv(_) => csv = true,
+            ast::CopyLegacyOption::Binary => {
+                return Err(CompilationError::Unsupported(
+                    "COPY in binary format is not supported".to_string(),
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    let mut format = if csv {
+        CopyFormat::csv()
+    } else {
+        CopyFormat::text()
+    };
+    let mut quote = None;
+    let mut escape = None;
+    for option in options {
+        match option {
+            ast::CopyOption::Format(_) | ast::CopyOption::Freeze(_) => {}
+            ast::CopyOption::Delimiter(delimiter) => format.delimiter = *delimiter,
+            ast::CopyOption::Null(null) => format.null = null.clone(),
+            ast::CopyOption::Header(header) => format.header = *header,
+            ast::CopyOption::Quote(value) => quote = Some(*value),
+            ast::CopyOption::Escape(value) => escape = Some(*value),
+            ast::CopyOption::Encoding(encoding) if is_utf8_encoding(encoding) => {}
+            option => {
+                return Err(CompilationError::Unsupported(format!(
+                    "COPY option is not supported: {}",
+                    option
+                )))
+            }
+        }
+    }
+    for option in legacy_options {
+        match option {
+            ast::CopyLegacyOption::Delimiter(delimiter) => format.delimiter = *delimiter,
+            ast::CopyLegacyOption::Null(null) => format.null = null.clone(),
+            ast::CopyLegacyOption::Csv(csv_options) => {
+                for csv_option in csv_options {
+                    match csv_option {
+                        ast::CopyLegacyCsvOption::Header => format.header = true,
+                        ast::CopyLegacyCsvOption::Quote(value) => quote = Some(*value),
+                        ast::CopyLegacyCsvOption::Escape(value) => escape = Some(*value),
+                        _ => {
+                            return Err(CompilationError::Unsupported(
+                                "COPY FORCE options are not supported".to_string(),
+                            ))
+                        }
+                    }
+                }
+            }
+            ast::CopyLegacyOption::Binary => {}
+        }
+    }
+
+    if !csv && (quote.is_some() || escape.is_some()) {
+        return Err(CompilationError::User(
+            "COPY quote and escape are available only in CSV mode".to_string(),
+        ));
+    }
+    if let Some(quote) = quote {
+        format.quote = quote;
+    }
+    // Escape is the same as quote by default
+    format.escape = escape.unwrap_or(format.quote);
+
+    if format.delimiter == '\n'
+        || format.delimiter == '\r'
+        || format.null.contains(format.delimiter)
+    {
+        return Err(CompilationError::User(
+            "COPY delimiter must not be a newline or appear in the NULL specification".to_string(),
+        ));
+    }
+
+    Ok(format)
+}
 
 pub struct AsyncPostgresShim {
-    socket: TcpStream,
+    socket: PostgresStream,
     // Extended query
     statements: HashMap<String, Option<PreparedStatement>>,
     portals: HashMap<String, Option<Portal>>,
+    // Last applied reset of statements and portals, see Session::reset_extended_state
+    extended_reset_generation: u64,
+    // Extended query messages are skipped till Sync after an error
+    ignore_till_sync: bool,
+    // Values of STARTUP_PARAMETERS which were reported to the client by ParameterStatus
+    reported_parameters: Vec<(String, String)>,
+    // Format of results when Bind doesn't request formats, resolved by startup parameters
+    default_result_format: Format,
     // Shared
     session: Arc<Session>,
 }
@@ -36,19 +316,56 @@ pub enum StartupState {
     // Initial parameters which client sends in the first message, we use it later in auth method
     Success(HashMap<String, String>),
     SslRequested,
+    // Out-of-band request to cancel query on another connection, connection must be closed
+    CancelRequested,
     Denied,
 }
 
+/// Stages of connection establishment. Client can send the next message without waiting for our
+/// response (startup and password in one packet), messages are read one by one by their length
+/// and processed in the order of arrival.
+enum HandshakeStage {
+    Startup { ssl_requested: bool },
+    // Waiting for PasswordMessage, with parameters from StartupMessage
+    Authentication(HashMap<String, String>),
+}
+
 impl AsyncPostgresShim {
-    pub async fn run_on(socket: TcpStream, session: Arc<Session>) -> Result<(), Error> {
-        let mut shim = Self {
+    fn new(socket: PostgresStream, session: Arc<Session>) -> Self {
+        Self {
             socket,
             portals: HashMap::new(),
             statements: HashMap::new(),
+            extended_reset_generation: session.state.extended_reset_generation(),
+            ignore_till_sync: false,
+            reported_parameters: reported_parameters(&session.state),
+            default_result_format: Format::Text,
             session,
+        }
+    }
+
+    pub async fn run_on(
+        socket: TcpStream,
+        session: Arc<Session>,
+        tls_config: Option<Arc<TlsConfig>>,
+        send_buffer_size: Option<usize>,
+    ) -> Result<(), Error> {
+        // Until the shim is created, its Drop is not responsible for the session
+        let guard = SessionGuard(Some(session));
+
+        Self::configure_socket(&socket, send_buffer_size)?;
+
+        let (socket, startup_buffer) = match Self::negotiate_tls(socket, tls_config).await {
+            Ok(Some(res)) => res,
+            // Plaintext connection was denied
+            Ok(None) => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
         };
 
-        match shim.run().await {
+        let mut shim = Self::new(socket, guard.release());
+
+        match shim.run(startup_buffer).await {
             Err(e) => {
                 if e.kind() == ErrorKind::UnexpectedEof
                     && shim.session.state.auth_context().is_none()
@@ -64,32 +381,110 @@ impl AsyncPostgresShim {
         }
     }
 
-    pub async fn run(&mut self) -> Result<(), Error> {
-        let initial_parameters = match self.process_startup_message().await? {
-            StartupState::Success(parameters) => parameters,
-            StartupState::SslRequested => match self.process_startup_message().await? {
-                StartupState::Success(parameters) => parameters,
-                _ => return Ok(()),
-            },
-            StartupState::Denied => return Ok(()),
+    /// Applies socket options from the configuration to the accepted connection, the size of send
+    /// buffer (SO_SNDBUF) limits how much of the result can be in flight
+    fn configure_socket(socket: &TcpStream, send_buffer_size: Option<usize>) -> Result<(), Error> {
+        if let Some(size) = send_buffer_size {
+            SockRef::from(socket).set_send_buffer_size(size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the first packet from the client when TLS is configured. On SSLRequest the server
+    /// answers with 'S' and performs the handshake, any other packet is returned back to be
+    /// processed as a regular startup message. Without TLS, SSLRequest is denied later in
+    /// `process_startup_message`. When TLS is required, plaintext startup is denied by FATAL
+    /// error and None is returned, CancelRequest is still accepted as libpq sends it unencrypted.
+    async fn negotiate_tls(
+        mut socket: TcpStream,
+        tls_config: Option<Arc<TlsConfig>>,
+    ) -> Result<Option<(PostgresStream, Option<Cursor<Vec<u8>>>)>, Error> {
+        let tls_config = match tls_config {
+            Some(tls_config) => tls_config,
+            None => return Ok(Some((PostgresStream::Plain(socket), None))),
         };
 
-        match buffer::read_message(&mut self.socket).await? {
-            protocol::FrontendMessage::PasswordMessage(password_message) => {
-                if !self
-                    .authenticate(password_message, initial_parameters)
-                    .await?
-                {
-                    return Ok(());
-                }
+        let mut buffer = buffer::read_contents(&mut socket, 0).await?;
+        let startup_message = protocol::StartupMessage::from(&mut buffer).await?;
+        if !startup_message.protocol_version.is_ssl_request() {
+            if tls_config.require_ssl && !startup_message.protocol_version.is_cancel_request() {
+                buffer::write_message(
+                    &mut socket,
+                    protocol::ErrorResponse::new(
+                        protocol::ErrorSeverity::Fatal,
+                        protocol::ErrorCode::InvalidAuthorizationSpecification,
+                        "SSL connection is required".to_string(),
+                    ),
+                )
+                .await?;
+
+                return Ok(None);
             }
-            _ => return Ok(()),
+
+            buffer.set_position(0);
+            return Ok(Some((PostgresStream::Plain(socket), Some(buffer))));
+        }
+
+        buffer::write_message(&mut socket, protocol::SSLResponse::Accepted).await?;
+
+        let tls_stream = tls_config.accept(socket).await?;
+        trace!("[pg] TLS handshake completed");
+
+        Ok(Some((PostgresStream::Tls(Box::new(tls_stream)), None)))
+    }
+
+    pub async fn run(&mut self, startup_buffer: Option<Cursor<Vec<u8>>>) -> Result<(), Error> {
+        if !self.process_handshake(startup_buffer).await? {
+            return Ok(());
         }
 
         self.ready().await?;
 
+        let idle_timeout = self.session.server.configuration.connection_idle_timeout;
+        let mut idle_deadline = idle_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+
         loop {
-            let result = match buffer::read_message(&mut self.socket).await? {
+            // Server is shutting down, messages which were sent after the current one are not processed
+            if self.session.state.is_shutting_down() {
+                return self.write_terminated().await;
+            }
+
+            // Only the tag is awaited in select, the rest of the message is read without interruption
+            let message_tag = tokio::select! {
+                message_tag = Self::read_message_tag(&mut self.socket, idle_deadline) => {
+                    match message_tag? {
+                        Some(message_tag) => message_tag,
+                        None => return self.write_idle_timeout().await,
+                    }
+                }
+                _ = self.session.state.notified() => {
+                    self.write_notifications().await?;
+                    self.flush().await?;
+                    continue;
+                }
+                _ = self.session.state.terminated() => return self.write_terminated().await,
+                _ = self.session.state.shutting_down() => return self.write_terminated().await,
+            };
+            let message = buffer::read_message_with_tag(&mut self.socket, message_tag).await?;
+            // Any message resets the idle timer, not only queries
+            idle_deadline = idle_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+            self.apply_extended_state_reset();
+
+            let is_extended_message = match message {
+                protocol::FrontendMessage::Parse(_)
+                | protocol::FrontendMessage::Bind(_)
+                | protocol::FrontendMessage::Execute(_)
+                | protocol::FrontendMessage::Close(_)
+                | protocol::FrontendMessage::Describe(_) => true,
+                _ => false,
+            };
+            if is_extended_message && self.ignore_till_sync {
+                trace!("[pg] Message is skipped till Sync: {:?}", message);
+                continue;
+            }
+
+            let result = match message {
                 protocol::FrontendMessage::Query(body) => self.process_query(body.query).await,
                 protocol::FrontendMessage::Parse(body) => self.parse(body).await,
                 protocol::FrontendMessage::Bind(body) => self.bind(body).await,
@@ -97,6 +492,8 @@ impl AsyncPostgresShim {
                 protocol::FrontendMessage::Close(body) => self.close(body).await,
                 protocol::FrontendMessage::Describe(body) => self.describe(body).await,
                 protocol::FrontendMessage::Sync => self.sync().await,
+                protocol::FrontendMessage::Flush => self.flush().await,
+                protocol::FrontendMessage::FunctionCall(body) => self.function_call(body).await,
                 protocol::FrontendMessage::Terminate => return Ok(()),
                 command_id => {
                     return Err(Error::new(
@@ -105,17 +502,177 @@ impl AsyncPostgresShim {
                     ))
                 }
             };
+            self.session
+                .state
+                .set_extended_state_counts(self.statements.len(), self.portals.len());
+
+            // Client has gone during execution or session was terminated by pg_terminate_backend
+            if self.session.state.is_terminated() {
+                return self.write_terminated().await;
+            }
+
             if let Err(err) = result {
-                self.write(protocol::ErrorResponse::new(
-                    protocol::ErrorSeverity::Error,
-                    protocol::ErrorCode::InternalError,
-                    err.to_string(),
-                ))
-                .await?;
+                if is_extended_message {
+                    self.ignore_till_sync = true;
+                }
+                self.write_error(Self::io_error_code(&err), err.to_string())
+                    .await?;
+            }
+        }
+    }
+
+    fn error_code(kind: Option<CubeErrorKind>) -> protocol::ErrorCode {
+        match kind {
+            Some(CubeErrorKind::SyntaxError) => protocol::ErrorCode::SyntaxError,
+            Some(CubeErrorKind::InsufficientPrivilege) => {
+                protocol::ErrorCode::InsufficientPrivilege
+            }
+            Some(CubeErrorKind::FeatureNotSupported) => protocol::ErrorCode::FeatureNotSupported,
+            Some(CubeErrorKind::UndefinedTable) => protocol::ErrorCode::UndefinedTable,
+            Some(CubeErrorKind::StatementTooComplex) => protocol::ErrorCode::StatementTooComplex,
+            Some(CubeErrorKind::ProgramLimitExceeded) => protocol::ErrorCode::ProgramLimitExceeded,
+            Some(CubeErrorKind::AmbiguousColumn) => protocol::ErrorCode::AmbiguousColumn,
+            None => protocol::ErrorCode::InternalError,
+        }
+    }
+
+    /// Wraps the compilation error with its kind, the message is kept as is
+    fn compilation_error(err: CompilationError) -> Error {
+        Error::new(
+            ErrorKind::Other,
+            CubeError {
+                kind: err.kind(),
+                ..CubeError::user(err.message().to_string())
+            },
+        )
+    }
+
+    /// Kind of CubeError is kept, when it is wrapped into io::Error
+    fn io_error_code(err: &Error) -> protocol::ErrorCode {
+        Self::error_code(
+            err.get_ref()
+                .and_then(|err| err.downcast_ref::<CubeError>())
+                .and_then(|err| err.kind),
+        )
+    }
+
+    /// Drops statements and portals, if they were reset from outside since the last message
+    fn apply_extended_state_reset(&mut self) {
+        let generation = self.session.state.extended_reset_generation();
+        if generation != self.extended_reset_generation {
+            self.statements.clear();
+            self.portals.clear();
+            self.extended_reset_generation = generation;
+        }
+    }
+
+    /// Returns None, if the client sent nothing till the deadline
+    async fn read_message_tag(
+        socket: &mut PostgresStream,
+        deadline: Option<tokio::time::Instant>,
+    ) -> Result<Option<u8>, Error> {
+        match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, socket.read_u8()).await {
+                Ok(message_tag) => message_tag.map(Some),
+                Err(_) => Ok(None),
+            },
+            None => socket.read_u8().await.map(Some),
+        }
+    }
+
+    async fn write_idle_timeout(&mut self) -> Result<(), Error> {
+        debug!(
+            "[pg] Connection {} is closed by idle timeout",
+            self.session.state.connection_id
+        );
+
+        // Client may be already disconnected
+        let _ = self
+            .write(protocol::ErrorResponse::new(
+                protocol::ErrorSeverity::Fatal,
+                protocol::ErrorCode::AdminShutdown,
+                "terminating connection due to idle-session timeout".to_string(),
+            ))
+            .await;
+
+        Ok(())
+    }
+
+    async fn write_terminated(&mut self) -> Result<(), Error> {
+        // Client may be already disconnected
+        let _ = self
+            .write(protocol::ErrorResponse::new(
+                protocol::ErrorSeverity::Fatal,
+                protocol::ErrorCode::AdminShutdown,
+                "terminating connection due to administrator command".to_string(),
+            ))
+            .await;
+
+        Ok(())
+    }
+
+    /// Processes startup (with optional SSLRequest) and authentication. Returns false, if the
+    /// connection must be closed.
+    async fn process_handshake(
+        &mut self,
+        mut startup_buffer: Option<Cursor<Vec<u8>>>,
+    ) -> Result<bool, Error> {
+        let mut stage = HandshakeStage::Startup {
+            ssl_requested: false,
+        };
+
+        loop {
+            stage = match stage {
+                HandshakeStage::Startup { ssl_requested } => {
+                    match self.process_startup_message(startup_buffer.take()).await? {
+                        StartupState::Success(parameters) => {
+                            HandshakeStage::Authentication(parameters)
+                        }
+                        StartupState::SslRequested if !ssl_requested => HandshakeStage::Startup {
+                            ssl_requested: true,
+                        },
+                        StartupState::SslRequested => {
+                            self.write_protocol_violation("duplicate SSLRequest")
+                                .await?;
+
+                            return Ok(false);
+                        }
+                        StartupState::CancelRequested | StartupState::Denied => return Ok(false),
+                    }
+                }
+                HandshakeStage::Authentication(parameters) => {
+                    return match buffer::read_message(&mut self.socket).await {
+                        Ok(protocol::FrontendMessage::PasswordMessage(password_message)) => {
+                            self.authenticate(password_message, parameters).await
+                        }
+                        Ok(protocol::FrontendMessage::Terminate) => Ok(false),
+                        Ok(_) => {
+                            self.write_protocol_violation("expected password response")
+                                .await?;
+
+                            Ok(false)
+                        }
+                        Err(err) if err.kind() == ErrorKind::InvalidData => {
+                            self.write_protocol_violation(&err.to_string()).await?;
+
+                            Ok(false)
+                        }
+                        Err(err) => Err(err),
+                    };
+                }
             }
         }
     }
 
+    async fn write_protocol_violation(&mut self, message: &str) -> Result<(), Error> {
+        self.write(protocol::ErrorResponse::new(
+            protocol::ErrorSeverity::Fatal,
+            protocol::ErrorCode::ProtocolViolation,
+            format!("invalid frontend message sequence: {}", message),
+        ))
+        .await
+    }
+
     pub async fn write<Message: protocol::Serialize>(
         &mut self,
         message: Message,
@@ -123,13 +680,35 @@ impl AsyncPostgresShim {
         buffer::write_message(&mut self.socket, message).await
     }
 
-    pub async fn process_startup_message(&mut self) -> Result<StartupState, Error> {
-        let mut buffer = buffer::read_contents(&mut self.socket, 0).await?;
+    pub async fn process_startup_message(
+        &mut self,
+        startup_buffer: Option<Cursor<Vec<u8>>>,
+    ) -> Result<StartupState, Error> {
+        let mut buffer = match startup_buffer {
+            Some(buffer) => buffer,
+            None => buffer::read_contents(&mut self.socket, 0).await?,
+        };
 
         let startup_message = protocol::StartupMessage::from(&mut buffer).await?;
 
-        if startup_message.protocol_version.major == protocol::SSL_REQUEST_PROTOCOL {
-            self.write(protocol::SSLResponse::new()).await?;
+        if startup_message.protocol_version.is_cancel_request() {
+            let cancel_request = protocol::CancelRequest::from(&mut buffer).await?;
+            let canceled = self
+                .session
+                .session_manager
+                .cancel_query(cancel_request.process_id, cancel_request.secret);
+
+            trace!(
+                "[pg] Cancel request for connection {}, canceled: {}",
+                cancel_request.process_id,
+                canceled
+            );
+
+            return Ok(StartupState::CancelRequested);
+        }
+
+        if startup_message.protocol_version.is_ssl_request() {
+            self.write(protocol::SSLResponse::Denied).await?;
             return Ok(StartupState::SslRequested);
         }
 
@@ -177,26 +756,31 @@ impl AsyncPostgresShim {
         parameters: HashMap<String, String>,
     ) -> Result<bool, Error> {
         let user = parameters.get("user").unwrap().clone();
-        let authenticate_response = self
-            .session
-            .server
-            .auth
-            .authenticate(Some(user.clone()))
-            .await;
+        let auth = self.session.server.auth.clone();
+
+        let auth_context = if self.session.server.configuration.token_auth {
+            match auth
+                .authenticate_token(Some(user.clone()), password_message.password)
+                .await
+            {
+                Ok(auth_context) => Some(auth_context),
+                Err(err) => {
+                    debug!("[pg] Token of user \"{}\" is rejected: {}", user, err);
 
-        let mut auth_context: Option<AuthContext> = None;
-        let auth_success = match authenticate_response {
-            Ok(authenticate_response) => {
-                auth_context = Some(authenticate_response.context);
-                match authenticate_response.password {
-                    None => true,
-                    Some(password) => password == password_message.password,
+                    None
                 }
             }
-            _ => false,
+        } else {
+            match auth.authenticate(Some(user.clone())).await {
+                Ok(authenticate_response) => match authenticate_response.password {
+                    Some(password) if password != password_message.password => None,
+                    _ => Some(authenticate_response.context),
+                },
+                _ => None,
+            }
         };
 
-        if !auth_success {
+        if auth_context.is_none() {
             let error_response = protocol::ErrorResponse::new(
                 protocol::ErrorSeverity::Fatal,
                 protocol::ErrorCode::InvalidPassword,
@@ -206,8 +790,41 @@ impl AsyncPostgresShim {
             return Ok(false);
         }
 
-        self.session.state.set_user(Some(user));
+        if let Some(encoding) = parameters.get("client_encoding") {
+            if !is_utf8_encoding(encoding) {
+                let error_response = protocol::ErrorResponse::new(
+                    protocol::ErrorSeverity::Fatal,
+                    protocol::ErrorCode::InvalidParameterValue,
+                    format!(
+                        "invalid value for parameter \"client_encoding\": \"{}\", only UTF8 is supported",
+                        encoding
+                    ),
+                );
+                buffer::write_message(&mut self.socket, error_response).await?;
+                return Ok(false);
+            }
+        }
+
+        self.session.state.set_user(Some(user.clone()));
+        self.session
+            .state
+            .set_database(parameters.get("database").cloned());
         self.session.state.set_auth_context(auth_context);
+        self.session
+            .state
+            .set_startup_variables(startup_parameters_to_variables(&parameters));
+        self.default_result_format = default_result_format(
+            &self.session.server.configuration.default_result_formats,
+            &parameters,
+        );
+        self.session.state.set_variables(HashMap::from([(
+            "session_authorization".to_string(),
+            DatabaseVariable::system(
+                "session_authorization".to_string(),
+                ScalarValue::Utf8(Some(user)),
+                None,
+            ),
+        )]));
 
         self.write(protocol::Authentication::new(
             protocol::AuthenticationRequest::Ok,
@@ -218,43 +835,135 @@ impl AsyncPostgresShim {
     }
 
     pub async fn ready(&mut self) -> Result<(), Error> {
-        let params = [
-            ("server_version".to_string(), "14.2 (Cube SQL)".to_string()),
-            ("server_encoding".to_string(), "UTF8".to_string()),
-            ("client_encoding".to_string(), "UTF8".to_string()),
-            ("DateStyle".to_string(), "ISO".to_string()),
-        ];
+        if self.session.server.configuration.meta_warmup {
+            self.warmup_meta().await;
+        }
 
-        for (key, value) in params {
-            self.write(protocol::ParameterStatus::new(key, value))
+        // All parameters are reported after startup
+        let parameters = reported_parameters(&self.session.state);
+        for (key, value) in parameters.iter() {
+            self.write(protocol::ParameterStatus::new(key.clone(), value.clone()))
                 .await?;
         }
+        self.reported_parameters = parameters;
 
-        self.write(protocol::ReadyForQuery::new(
-            protocol::TransactionStatus::Idle,
+        self.write(protocol::BackendKeyData::new(
+            self.session.state.connection_id,
+            self.session.state.secret,
         ))
         .await?;
 
+        self.write(protocol::ReadyForQuery::new(self.transaction_status()))
+            .await?;
+
         Ok(())
     }
 
+    /// Writes ParameterStatus for every reported parameter which was changed since the last
+    /// report, it's done once before ReadyForQuery
+    async fn report_changed_parameters(&mut self) -> Result<(), Error> {
+        let parameters = reported_parameters(&self.session.state);
+        for parameter in parameters.iter() {
+            if !self.reported_parameters.contains(parameter) {
+                let (key, value) = parameter.clone();
+                self.write(protocol::ParameterStatus::new(key, value))
+                    .await?;
+            }
+        }
+
+        self.reported_parameters = parameters;
+
+        Ok(())
+    }
+
+    /// Meta is cached by transport, the first query of connection will use it. Connection doesn't
+    /// depend on meta, so failure is only logged
+    async fn warmup_meta(&mut self) {
+        let result = match self.auth_context() {
+            Ok(auth_context) => self.session.server.transport.meta(auth_context).await,
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = result {
+            warn!(
+                "[pg] Unable to warm up meta for connection {}: {}",
+                self.session.state.connection_id, err
+            );
+        }
+    }
+
+    /// Flush doesn't end the extended query sequence, skipping of messages after an error
+    /// continues till Sync
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.socket.flush().await
+    }
+
     pub async fn sync(&mut self) -> Result<(), Error> {
-        self.write(protocol::ReadyForQuery::new(
-            protocol::TransactionStatus::Idle,
-        ))
-        .await?;
+        self.ignore_till_sync = false;
+
+        // Sync ends the implicit transaction, which destroys all portals. In a transaction block,
+        // portal which returned all rows cannot be resumed, let's release it. Suspended portals
+        // are kept for the next Execute, even if it comes after Sync, till Close
+        if self.session.state.transaction_state() == TransactionState::None {
+            self.portals.clear();
+        } else {
+            self.portals.retain(|_, portal| match portal {
+                Some(portal) => !portal.is_finished(),
+                None => true,
+            });
+        }
+
+        self.write_ready_for_query().await?;
 
         Ok(())
     }
 
+    /// Fast-path FunctionCall, it's answered only for functions known from the session state.
+    /// Other functions (lo_* of large objects) fail by ErrorResponse, the session is kept
+    pub async fn function_call(&mut self, body: protocol::FunctionCall) -> Result<(), Error> {
+        if self.session.state.transaction_state() == TransactionState::Failed {
+            self.write_in_failed_transaction().await?;
+            return self.write_ready_for_query().await;
+        }
+
+        let text = |value: Option<String>| value.map(String::into_bytes);
+        let value = match body.function_oid {
+            // pg_backend_pid
+            2026 => match body.result_format {
+                protocol::Format::Text => text(Some(self.session.state.connection_id.to_string())),
+                protocol::Format::Binary => Some(
+                    (self.session.state.connection_id as i32)
+                        .to_be_bytes()
+                        .to_vec(),
+                ),
+            },
+            // current_database, text and binary formats of name are the same
+            861 => text(self.session.state.database()),
+            // current_user, session_user
+            745 | 746 => text(self.session.state.user()),
+            oid => {
+                self.write_error(
+                    protocol::ErrorCode::UndefinedFunction,
+                    format!("function with OID {} does not exist", oid),
+                )
+                .await?;
+                return self.write_ready_for_query().await;
+            }
+        };
+
+        self.write(protocol::FunctionCallResponse::new(value))
+            .await?;
+        self.write_ready_for_query().await
+    }
+
     pub async fn describe_portal(&mut self, name: String) -> Result<(), Error> {
         match self.portals.get(&name) {
             None => {
-                self.write(protocol::ErrorResponse::new(
-                    protocol::ErrorSeverity::Error,
+                self.ignore_till_sync = true;
+                self.write_error(
                     protocol::ErrorCode::InvalidCursorName,
                     "missing cursor".to_string(),
-                ))
+                )
                 .await?;
 
                 return Ok(());
@@ -274,11 +983,11 @@ impl AsyncPostgresShim {
     pub async fn describe_statement(&mut self, name: String) -> Result<(), Error> {
         match self.statements.get(&name) {
             None => {
-                self.write(protocol::ErrorResponse::new(
-                    protocol::ErrorSeverity::Error,
+                self.ignore_till_sync = true;
+                self.write_error(
                     protocol::ErrorCode::InvalidSqlStatement,
                     "missing statement".to_string(),
-                ))
+                )
                 .await?;
 
                 return Ok(());
@@ -332,30 +1041,64 @@ impl AsyncPostgresShim {
     }
 
     pub async fn execute(&mut self, execute: protocol::Execute) -> Result<(), Error> {
-        match self.portals.get_mut(&execute.portal) {
-            Some(portal) => match portal {
-                // We use None for Statement on empty query
-                None => {
-                    self.write(protocol::EmptyQueryResponse::new()).await?;
+        match self.portals.get(&execute.portal) {
+            Some(Some(_)) => {
+                // Portal is taken out for execution and returned back, if it can be resumed
+                let mut portal = self.portals.remove(&execute.portal).flatten().unwrap();
+
+                if !self.apply_transaction_command(&portal).await? {
+                    self.ignore_till_sync = true;
+                    self.portals.insert(execute.portal, Some(portal));
+
+                    return Ok(());
                 }
-                Some(portal) => {
-                    let mut writer = BatchWriter::new(portal.get_format());
-                    let completion = portal
-                        .execute(&mut writer, execute.max_rows as usize)
-                        .await
-                        .unwrap();
 
-                    if writer.has_data() {
-                        buffer::write_direct(&mut self.socket, writer).await?
-                    }
+                let started = Instant::now();
+                let cancel = self.session.state.begin_query();
+                let result = self
+                    .execute_portal_with_timeout(
+                        &mut portal,
+                        FetchSize::from_max_rows(execute.max_rows),
+                        cancel.clone(),
+                    )
+                    .await;
+                self.session.state.end_query();
 
-                    self.write(completion).await?;
+                match result {
+                    Some(Ok(completion)) => {
+                        self.portals.insert(execute.portal, Some(portal));
+
+                        match completion {
+                            Some(completion) => self.write(completion).await?,
+                            None => self.write(protocol::PortalSuspended::new()).await?,
+                        }
+                        self.write_timing_notice(started).await?;
+                    }
+                    // FATAL error is written by the message loop
+                    Some(Err(_)) if self.session.state.is_terminated() => {}
+                    Some(Err(_)) if cancel.is_cancelled() => {
+                        self.ignore_till_sync = true;
+                        self.write_query_canceled().await?;
+                    }
+                    Some(Err(err)) => {
+                        return Err(Error::new(ErrorKind::Other, err));
+                    }
+                    None => {
+                        self.ignore_till_sync = true;
+                        self.write_statement_timeout().await?;
+                    }
                 }
-            },
+            }
+            // We use None for Statement on empty query
+            Some(None) => {
+                self.write(protocol::EmptyQueryResponse::new()).await?;
+            }
             None => {
-                self.write(protocol::ReadyForQuery::new(
-                    protocol::TransactionStatus::Idle,
-                ))
+                self.ignore_till_sync = true;
+                self.write_error(
+                    protocol::ErrorCode::InvalidCursorName,
+                    format!("portal \"{}\" does not exist", execute.portal),
+                )
                 .await?;
             }
         }
@@ -363,39 +1106,191 @@ impl AsyncPostgresShim {
         Ok(())
     }
 
-    pub async fn bind(&mut self, body: protocol::Bind) -> Result<(), Error> {
-        let source_statement = self
-            .statements
-            .get(&body.statement)
-            .ok_or_else(|| Error::new(ErrorKind::Other, "Unknown statement"))?;
+    /// Races execution of the portal with statement_timeout of the session. The query is
+    /// canceled on timeout and None is returned
+    async fn execute_portal_with_timeout(
+        &mut self,
+        portal: &mut Portal,
+        fetch_size: FetchSize,
+        cancel: CancellationToken,
+    ) -> Option<Result<Option<protocol::CommandComplete>, CubeError>> {
+        let timeout = self.session.state.statement_timeout();
+        let execution = self.execute_portal(portal, fetch_size, cancel.clone());
 
-        let portal = if let Some(statement) = source_statement {
-            let prepared_statement = statement.bind(body.to_bind_values());
+        match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, execution).await {
+                Ok(result) => Some(result),
+                Err(_) => {
+                    cancel.cancel();
 
-            let meta = self
-                .session
-                .server
+                    None
+                }
+            },
+            None => Some(execution.await),
+        }
+    }
+
+    /// Executes portal till the end or till `fetch_size` rows were returned.
+    /// DataRow messages are flushed to the socket every stream_flush_rows rows, without
+    /// buffering the whole result. None is returned when the portal was suspended.
+    async fn execute_portal(
+        &mut self,
+        portal: &mut Portal,
+        fetch_size: FetchSize,
+        cancel: CancellationToken,
+    ) -> Result<Option<protocol::CommandComplete>, CubeError> {
+        // Statement changes the session only by execution, which was allowed by the transaction
+        if let Some(change) = portal.take_session_change() {
+            self.session.state.apply_change(change)?;
+            self.write_warnings().await?;
+        }
+
+        let flush_rows = self.session.server.configuration.stream_flush_rows;
+        let mut rows_returned: usize = 0;
+
+        loop {
+            let rows_to_read = match fetch_size {
+                FetchSize::All => flush_rows,
+                FetchSize::Limited(max_rows) => std::cmp::min(flush_rows, max_rows - rows_returned),
+            };
+
+            let mut writer = match portal.get_copy_format() {
+                Some(format) => BatchWriter::copy(format.clone()),
+                None => BatchWriter::new(portal.get_formats()),
+            };
+            let execution = portal.execute(
+                &mut writer,
+                FetchSize::Limited(rows_to_read),
+                cancel.clone(),
+            );
+            let completion = tokio::select! {
+                result = execution => result?,
+                _ = self.socket.closed() => {
+                    self.session.state.terminate();
+
+                    return Err(CubeError::internal("Connection was closed by client".to_string()));
+                },
+            };
+
+            rows_returned += writer.num_rows() as usize;
+
+            if writer.has_data() {
+                buffer::write_direct(&mut self.socket, writer).await?;
+            }
+
+            if portal.is_finished() {
+                return Ok(Some(match completion {
+                    protocol::CommandComplete::Select(_) if portal.get_copy_format().is_some() => {
+                        protocol::CommandComplete::Plain(format!("COPY {}", rows_returned))
+                    }
+                    protocol::CommandComplete::Select(_) => {
+                        protocol::CommandComplete::Select(rows_returned as u32)
+                    }
+                    completion => completion,
+                }));
+            }
+
+            match fetch_size {
+                FetchSize::Limited(max_rows) if rows_returned >= max_rows => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    pub async fn bind(&mut self, body: protocol::Bind) -> Result<(), Error> {
+        // Every portal is a new query
+        let query_id = self.session.state.assign_query_id();
+        debug!(
+            "[pg] Query {}: bind of statement \"{}\" to portal \"{}\"",
+            query_id, body.statement, body.portal
+        );
+        self.write_query_id_notice(&query_id).await?;
+
+        // Unnamed portal is destroyed by the next Bind, even if the new one fails
+        if body.portal.is_empty() {
+            self.portals.remove("");
+        }
+
+        let max_portals = self.session.server.configuration.connection_max_pg_portals;
+        if !self.portals.contains_key(&body.portal) && self.portals.len() >= max_portals {
+            self.ignore_till_sync = true;
+
+            return self
+                .write_allocation_limit_exceeded("portal", max_portals)
+                .await;
+        }
+
+        let source_statement = self
+            .statements
+            .get(&body.statement)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "Unknown statement"))?;
+
+        let portal = if let Some(statement) = source_statement {
+            // Placeholder without a value can't be bound
+            if body.parameter_values.len() != statement.parameters.count() {
+                let message = format!(
+                    "bind message supplies {} parameters, but prepared statement \"{}\" requires {}",
+                    body.parameter_values.len(),
+                    body.statement,
+                    statement.parameters.count()
+                );
+                self.ignore_till_sync = true;
+                self.write_error(protocol::ErrorCode::ProtocolViolation, message)
+                    .await?;
+
+                return Ok(());
+            }
+
+            let prepared_statement = statement.bind(body.to_bind_values(&statement.parameters)?);
+            self.session
+                .state
+                .set_current_query(prepared_statement.to_string());
+
+            let meta = self
+                .session
+                .server
                 .transport
-                .meta(self.auth_context().unwrap())
+                .meta(
+                    self.auth_context()
+                        .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?,
+                )
                 .await
-                .unwrap();
+                .map_err(|err| Error::new(ErrorKind::Other, err))?;
 
-            let plan =
-                convert_statement_to_cube_query(&prepared_statement, meta, self.session.clone())
-                    .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?;
+            let plan = self.plan_prepared_query(&prepared_statement, meta);
+            self.write_warnings().await?;
+            let plan = plan.map_err(Self::compilation_error)?;
 
             let fields = self.query_plan_to_row_description(&plan).await?;
+            // Zero or one format is applied to all columns, otherwise there is a format per column
+            if body.result_formats.len() > 1 && body.result_formats.len() != fields.len() {
+                self.ignore_till_sync = true;
+                self.write_error(
+                    protocol::ErrorCode::ProtocolViolation,
+                    format!(
+                        "bind message has {} result formats but query has {} columns",
+                        body.result_formats.len(),
+                        fields.len()
+                    ),
+                )
+                .await?;
+
+                return Ok(());
+            }
+
             let description = if fields.len() > 0 {
-                Some(protocol::RowDescription::new(
-                    self.query_plan_to_row_description(&plan).await?,
-                ))
+                Some(protocol::RowDescription::new(fields))
             } else {
                 None
             };
 
-            let format = body.result_formats.first().unwrap_or(&Format::Text).clone();
+            let result_formats = if body.result_formats.is_empty() {
+                vec![self.default_result_format]
+            } else {
+                body.result_formats.clone()
+            };
 
-            Some(Portal::new(plan, format, description))
+            Some(Portal::new(plan, result_formats, description))
         } else {
             None
         };
@@ -411,7 +1306,7 @@ impl AsyncPostgresShim {
         plan: &QueryPlan,
     ) -> Result<Vec<protocol::RowDescriptionField>, Error> {
         match plan {
-            QueryPlan::MetaOk(_, _) => Ok(vec![]),
+            QueryPlan::MetaOk(_, _) | QueryPlan::MetaChange(_, _, _) => Ok(vec![]),
             QueryPlan::MetaTabular(_, frame) => {
                 let mut result = vec![];
 
@@ -428,10 +1323,12 @@ impl AsyncPostgresShim {
                 let mut result = vec![];
 
                 for field in logical_plan.schema().fields() {
-                    result.push(protocol::RowDescriptionField::new(
-                        field.name().clone(),
-                        df_type_to_pg_tid(field.data_type())?.to_type(),
-                    ));
+                    let pg_type = df_type_to_pg_tid(field.data_type())
+                        .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+                    result.push(
+                        protocol::RowDescriptionField::new(field.name().clone(), pg_type.to_type())
+                            .with_type_modifier(df_type_to_pg_type_modifier(field.data_type())),
+                    );
                 }
 
                 Ok(result)
@@ -440,45 +1337,38 @@ impl AsyncPostgresShim {
     }
 
     pub async fn parse(&mut self, parse: protocol::Parse) -> Result<(), Error> {
-        let prepared = if parse.query.trim() == "" {
-            None
-        } else {
-            let query = parse_sql_to_statement(&parse.query, DatabaseProtocol::PostgreSQL)
-                .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?;
+        // Unnamed statement is destroyed by the next Parse, even if the new one fails
+        if parse.name.is_empty() {
+            self.statements.remove("");
+        }
 
-            let stmt_finder = StatementParamsFinder::new();
-            let parameters: Vec<PgTypeId> = stmt_finder
-                .find(&query)
-                .into_iter()
-                .map(|_p| PgTypeId::TEXT)
-                .collect();
+        if !self.can_allocate_statement(&parse.name).await? {
+            self.ignore_till_sync = true;
 
-            let meta = self
-                .session
-                .server
-                .transport
-                .meta(self.auth_context().unwrap())
-                .await
-                .unwrap();
+            return Ok(());
+        }
 
-            let stmt_replacer = StatementPlaceholderReplacer::new();
-            let hacked_query = stmt_replacer.replace(&query);
+        let prepared = if parse.query.trim() == "" {
+            None
+        } else {
+            let query = match parse_prepared_query(&parse.query) {
+                Ok(query) => query,
+                Err(CompilationError::SyntaxError(message, position)) => {
+                    self.ignore_till_sync = true;
+                    self.write_syntax_error(message, position).await?;
 
-            let plan = convert_statement_to_cube_query(&hacked_query, meta, self.session.clone())
-                .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?;
-            let fields: Vec<protocol::RowDescriptionField> =
-                self.query_plan_to_row_description(&plan).await?;
-            let description = if fields.len() > 0 {
-                Some(protocol::RowDescription::new(fields))
-            } else {
-                None
+                    return Ok(());
+                }
+                Err(err) => return Err(Self::compilation_error(err)),
             };
+            // Zero oid means unspecified
+            let param_types = parse
+                .param_types
+                .iter()
+                .map(|oid| PgTypeId::from_oid(*oid))
+                .collect();
 
-            Some(PreparedStatement {
-                query,
-                parameters: protocol::ParameterDescription::new(parameters),
-                description,
-            })
+            Some(self.prepare_statement(query, param_types).await?)
         };
 
         self.statements.insert(parse.name, prepared);
@@ -488,7 +1378,135 @@ impl AsyncPostgresShim {
         Ok(())
     }
 
+    /// Named statement must be closed before it's parsed again, the unnamed one is removed by
+    /// `parse` beforehand. New statement is allowed only within the limit. Error is written, if
+    /// the statement cannot be stored under the name
+    async fn can_allocate_statement(&mut self, name: &str) -> Result<bool, Error> {
+        if !name.is_empty() && self.statements.contains_key(name) {
+            self.write_error(
+                protocol::ErrorCode::DuplicatePreparedStatement,
+                format!("prepared statement \"{}\" already exists", name),
+            )
+            .await?;
+
+            return Ok(false);
+        }
+
+        let max_statements = self
+            .session
+            .server
+            .configuration
+            .connection_max_pg_prepared_statements;
+        if !self.statements.contains_key(name) && self.statements.len() >= max_statements {
+            self.write_allocation_limit_exceeded("prepared statement", max_statements)
+                .await?;
+
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Prepares statement by Parse or by PREPARE. Types which are specified by client have
+    /// priority over the inferred ones, unknown types are TEXT
+    async fn prepare_statement(
+        &mut self,
+        query: PreparedQuery,
+        param_types: Vec<Option<PgTypeId>>,
+    ) -> Result<PreparedStatement, Error> {
+        let meta = self
+            .session
+            .server
+            .transport
+            .meta(
+                self.auth_context()
+                    .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?,
+            )
+            .await
+            .map_err(|err| Error::new(ErrorKind::Other, err))?;
+
+        let inferred = match &query {
+            PreparedQuery::Statement(statement) => {
+                StatementParamsTypesFinder::new(&meta).find(statement)
+            }
+            PreparedQuery::Text(_) => vec![],
+        };
+        let params_count = std::cmp::max(inferred.len(), param_types.len());
+
+        let parameters: Vec<PgTypeId> = (0..params_count)
+            .map(|i| {
+                param_types
+                    .get(i)
+                    .cloned()
+                    .flatten()
+                    .or_else(|| inferred.get(i).cloned().flatten())
+                    .unwrap_or(PgTypeId::TEXT)
+            })
+            .collect();
+
+        let plan = match &query {
+            PreparedQuery::Statement(statement) => {
+                let stmt_replacer = StatementPlaceholderReplacer::new(&parameters);
+                let hacked_query = stmt_replacer.replace(statement);
+
+                convert_statement_to_cube_query(&hacked_query, meta, self.session.clone())
+            }
+            query => self.plan_prepared_query(query, meta),
+        };
+        // Statement is planned again by Bind, warnings are reported for its plan
+        self.session.state.take_warnings();
+        let plan = plan.map_err(Self::compilation_error)?;
+        let fields: Vec<protocol::RowDescriptionField> =
+            self.query_plan_to_row_description(&plan).await?;
+        let description = if fields.len() > 0 {
+            Some(protocol::RowDescription::new(fields))
+        } else {
+            None
+        };
+
+        Ok(PreparedStatement {
+            query,
+            parameters: protocol::ParameterDescription::new(parameters),
+            description,
+        })
+    }
+
+    /// Statement is planned without changes of the session, they are applied by execution
+    fn plan_prepared_query(
+        &self,
+        query: &PreparedQuery,
+        meta: Arc<MetaContext>,
+    ) -> Result<QueryPlan, CompilationError> {
+        match query {
+            PreparedQuery::Statement(statement) => {
+                convert_statement_to_cube_query(statement, meta, self.session.clone())
+            }
+            PreparedQuery::Text(query) => {
+                convert_sql_to_cube_query(query, meta, self.session.clone())
+            }
+        }
+    }
+
     pub async fn execute_query(&mut self, query: &str) -> Result<(), CubeError> {
+        if let Some(command) = parse_listen_command(query) {
+            self.execute_listen_command(command).await?;
+
+            return Ok(());
+        }
+
+        let execute_arguments = match parse_prepared_statement_command(query)? {
+            Some(PreparedStatementCommand::Statement(ast::Statement::Execute {
+                name,
+                parameters,
+            })) => Some((name, parameters)),
+            Some(command) => {
+                self.execute_prepared_statement_command(command).await?;
+
+                return Ok(());
+            }
+            None => None,
+        };
+
         let meta = self
             .session
             .server
@@ -496,65 +1514,542 @@ impl AsyncPostgresShim {
             .meta(self.auth_context()?)
             .await?;
 
-        let plan = convert_sql_to_cube_query(&query.to_string(), meta, self.session.clone())?;
+        let mut copy_format = None;
+        let plan = match execute_arguments {
+            // EXECUTE runs the prepared statement with arguments, as Bind + Execute
+            Some((name, arguments)) => match self.statements.get(&name.value) {
+                Some(Some(statement)) => {
+                    if arguments.len() != statement.parameters.count() {
+                        let message = format!(
+                            "wrong number of parameters for prepared statement \"{}\", expected {} parameters but got {}",
+                            name.value,
+                            statement.parameters.count(),
+                            arguments.len()
+                        );
+                        self.write_error(protocol::ErrorCode::SyntaxError, message)
+                            .await?;
+
+                        return Ok(());
+                    }
+
+                    let bind = protocol::Bind {
+                        portal: "".to_string(),
+                        statement: name.value.clone(),
+                        parameter_formats: vec![],
+                        parameter_values: arguments
+                            .iter()
+                            .map(|arg| {
+                                execute_argument_to_text(arg).map(|v| v.map(String::into_bytes))
+                            })
+                            .collect::<Result<_, _>>()?,
+                        result_formats: vec![],
+                    };
+                    let values = bind.to_bind_values(&statement.parameters)?;
+
+                    self.plan_prepared_query(&statement.bind(values), meta)
+                }
+                Some(None) => {
+                    self.write(protocol::EmptyQueryResponse::new()).await?;
+
+                    return Ok(());
+                }
+                None => {
+                    self.write_error(
+                        protocol::ErrorCode::InvalidSqlStatement,
+                        format!("prepared statement \"{}\" does not exist", name.value),
+                    )
+                    .await?;
+
+                    return Ok(());
+                }
+            },
+            None => match parse_copy_to_statement(query) {
+                Ok(Some(copy_to)) => self.plan_copy_to(copy_to, meta).map(|(plan, format)| {
+                    copy_format = Some(format);
+
+                    plan
+                }),
+                Ok(None) => {
+                    convert_sql_to_cube_query(&query.to_string(), meta, self.session.clone())
+                }
+                Err(err) => Err(err),
+            },
+        };
+
+        self.write_warnings().await?;
+
+        let plan = match plan {
+            Err(CompilationError::SyntaxError(message, position)) => {
+                self.write_syntax_error(message, position).await?;
+
+                return Ok(());
+            }
+            // Errors of the query are reported with their SQLSTATE and the message as is
+            Err(err) if err.kind().is_some() => {
+                self.write_error(Self::error_code(err.kind()), err.message().to_string())
+                    .await?;
+
+                return Ok(());
+            }
+            plan => plan?,
+        };
 
         let description = self.query_plan_to_row_description(&plan).await?;
-        match description.len() {
-            0 => self.write(protocol::NoData::new()).await?,
-            _ => {
+
+        // Re-usage of Portal functionality
+        let mut portal = Portal::new(plan, vec![Format::Text], None);
+
+        // Nothing is written before the error, if the query cannot be executed in the transaction
+        if !self.apply_transaction_command(&portal).await? {
+            return Ok(());
+        }
+
+        match &copy_format {
+            Some(format) => {
+                self.write(protocol::CopyOutResponse::new(
+                    Format::Text,
+                    description.len() as u16,
+                ))
+                .await?;
+
+                if format.header {
+                    let names = description
+                        .iter()
+                        .map(|field| Some(field.name()))
+                        .collect::<Vec<_>>();
+                    let header = format.encode_line(&names);
+                    self.write(protocol::CopyData::new(header.into_bytes()))
+                        .await?;
+                }
+
+                portal = portal.with_copy_format(format.clone());
+            }
+            None if description.is_empty() => self.write(protocol::NoData::new()).await?,
+            None => {
                 self.write(protocol::RowDescription::new(description))
                     .await?
             }
         };
 
-        // Re-usage of Portal functionality
-        let mut portal = Portal::new(plan, Format::Text, None);
+        let cancel = self.session.state.begin_query();
+        let result = self
+            .execute_portal_with_timeout(&mut portal, FetchSize::All, cancel.clone())
+            .await;
+        self.session.state.end_query();
+
+        match result {
+            // Terminated session is closed by the message loop with FATAL error
+            Some(Err(_)) if self.session.state.is_terminated() => return Ok(()),
+            // Portal with FetchSize::All cannot be suspended
+            Some(Ok(completion)) => {
+                if copy_format.is_some() {
+                    self.write(protocol::CopyDone::new()).await?;
+                }
+                if let Some(completion) = completion {
+                    self.write(completion).await?;
+                }
+            }
+            Some(Err(_)) if cancel.is_cancelled() => {
+                self.write_query_canceled().await?;
+            }
+            Some(Err(err)) => return Err(err),
+            None => self.write_statement_timeout().await?,
+        };
+
+        Ok(())
+    }
+
+    /// Plans the query of COPY (query) TO STDOUT, its rows are encoded by the returned format
+    fn plan_copy_to(
+        &self,
+        copy_to: CopyToStatement,
+        meta: Arc<MetaContext>,
+    ) -> Result<(QueryPlan, CopyFormat), CompilationError> {
+        let format = copy_format_from_options(&copy_to.options, &copy_to.legacy_options)?;
+        if !matches!(copy_to.query, ast::Statement::Query(_)) {
+            return Err(CompilationError::Unsupported(format!(
+                "COPY is supported only for SELECT queries: {}",
+                copy_to.query
+            )));
+        }
+
+        let plan = convert_statement_to_cube_query(&copy_to.query, meta, self.session.clone())?;
+
+        Ok((plan, format))
+    }
+
+    /// Executes LISTEN, UNLISTEN and NOTIFY. Transactions are no-op, so NOTIFY is delivered
+    /// immediately instead of the commit
+    async fn execute_listen_command(&mut self, command: ListenCommand) -> Result<(), Error> {
+        if self.session.state.transaction_state() == TransactionState::Failed {
+            return self.write_in_failed_transaction().await;
+        }
+
+        let completion = match command {
+            ListenCommand::Listen(channel) => {
+                self.session.state.listen(channel);
+
+                CommandCompletion::Listen
+            }
+            ListenCommand::Unlisten(channel) => {
+                self.session.state.unlisten(channel.as_deref());
 
-        let mut writer = BatchWriter::new(portal.get_format());
-        let completion = portal.execute(&mut writer, 0).await?;
+                CommandCompletion::Unlisten
+            }
+            ListenCommand::Notify { channel, payload } => {
+                // The same limit as in PostgreSQL
+                if payload.len() >= 8000 {
+                    return self
+                        .write_error(
+                            protocol::ErrorCode::InvalidParameterValue,
+                            "payload string too long".to_string(),
+                        )
+                        .await;
+                }
 
-        if writer.has_data() {
-            buffer::write_direct(&mut self.socket, writer).await?;
+                self.session.session_manager.notify(
+                    self.session.state.connection_id,
+                    &channel,
+                    &payload,
+                );
+
+                CommandCompletion::Notify
+            }
         };
 
-        self.write(completion).await?;
+        self.write(completion.to_pg_command()).await
+    }
 
-        Ok(())
+    /// Executes statement which manages prepared statements of the connection (PREPARE,
+    /// DEALLOCATE, DISCARD), they are not available to the compiler
+    async fn execute_prepared_statement_command(
+        &mut self,
+        command: PreparedStatementCommand,
+    ) -> Result<(), Error> {
+        if self.session.state.transaction_state() == TransactionState::Failed {
+            return self.write_in_failed_transaction().await;
+        }
+
+        let completion = match command {
+            PreparedStatementCommand::Statement(ast::Statement::Prepare {
+                name,
+                data_types,
+                statement,
+            }) => {
+                if !self.can_allocate_statement(&name.value).await? {
+                    return Ok(());
+                }
+
+                let param_types = data_types
+                    .iter()
+                    .map(|data_type| {
+                        type_oid_by_name(&data_type.to_string())
+                            .map(PgTypeId::from_oid)
+                            .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))
+                    })
+                    .collect::<Result<_, _>>()?;
+                let prepared = self
+                    .prepare_statement(PreparedQuery::Statement(*statement), param_types)
+                    .await?;
+                self.statements.insert(name.value, Some(prepared));
+
+                CommandCompletion::Prepare
+            }
+            // Resets the session to the initial state, changed parameters are reported before
+            // ReadyForQuery
+            PreparedStatementCommand::DiscardAll => {
+                if self.session.state.transaction_state() != TransactionState::None {
+                    return self
+                        .write_error(
+                            protocol::ErrorCode::ActiveSqlTransaction,
+                            "DISCARD ALL cannot run inside a transaction block".to_string(),
+                        )
+                        .await;
+                }
+
+                self.session.state.reset_variables(None);
+                self.statements.clear();
+                self.portals.clear();
+
+                CommandCompletion::DiscardAll
+            }
+            // DEALLOCATE ALL releases all prepared statements, portals are kept
+            PreparedStatementCommand::Statement(ast::Statement::Deallocate { name, .. })
+                if name.quote_style.is_none() && name.value.eq_ignore_ascii_case("all") =>
+            {
+                self.statements.clear();
+
+                CommandCompletion::DeallocateAll
+            }
+            PreparedStatementCommand::Statement(ast::Statement::Deallocate { name, .. }) => {
+                if self.statements.remove(&name.value).is_none() {
+                    return self
+                        .write_error(
+                            protocol::ErrorCode::InvalidSqlStatement,
+                            format!("prepared statement \"{}\" does not exist", name.value),
+                        )
+                        .await;
+                }
+
+                CommandCompletion::Deallocate
+            }
+            PreparedStatementCommand::Statement(statement) => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("Unsupported prepared statement command: {}", statement),
+                ))
+            }
+        };
+
+        self.write(completion.to_pg_command()).await
     }
 
     pub async fn process_query(&mut self, query: String) -> Result<(), Error> {
-        debug!("Query: {}", query);
+        let query_id = self.session.state.assign_query_id();
+        let application_name = self.session.state.application_name();
+        debug!(
+            "[pg] Query {} of application \"{}\": {}",
+            query_id, application_name, query
+        );
+        self.session.state.set_current_query(query.clone());
+        self.write_query_id_notice(&query_id).await?;
+
+        // Simple query destroys the unnamed statement and portal
+        self.statements.remove("");
+        self.portals.remove("");
+
+        let started = Instant::now();
+        let result = self.execute_query(&query).await;
+        if self.session.state.is_terminated() {
+            return Ok(());
+        }
 
-        match self.execute_query(&query).await {
+        match result {
             Err(e) => {
                 let error_message = e.to_string();
-                error!("Error during processing {}: {}", query, error_message);
-                self.write(protocol::ErrorResponse::new(
-                    protocol::ErrorSeverity::Error,
-                    protocol::ErrorCode::InternalError,
-                    error_message,
-                ))
-                .await?;
+                error!(
+                    "Error during processing query {} of application \"{}\" {}: {}",
+                    query_id, application_name, query, error_message
+                );
+                self.write_error(Self::error_code(e.kind), error_message)
+                    .await?;
             }
-            Ok(_) => {}
+            Ok(_) => self.write_timing_notice(started).await?,
         }
 
-        self.write(protocol::ReadyForQuery::new(
-            protocol::TransactionStatus::Idle,
+        self.write_ready_for_query().await?;
+
+        Ok(())
+    }
+
+    /// Writes ErrorResponse. Any error aborts the transaction block, so the following
+    /// ReadyForQuery reports the failed status till ROLLBACK
+    async fn write_error(
+        &mut self,
+        code: protocol::ErrorCode,
+        message: String,
+    ) -> Result<(), Error> {
+        self.session.state.fail_transaction();
+        self.write(protocol::ErrorResponse::new(
+            protocol::ErrorSeverity::Error,
+            code,
+            message,
         ))
-        .await?;
+        .await
+    }
+
+    /// Writes ErrorResponse with the position of the error in the query, which is highlighted
+    /// by clients
+    async fn write_syntax_error(
+        &mut self,
+        message: String,
+        position: Option<usize>,
+    ) -> Result<(), Error> {
+        self.session.state.fail_transaction();
+        self.write(
+            protocol::ErrorResponse::new(
+                protocol::ErrorSeverity::Error,
+                protocol::ErrorCode::SyntaxError,
+                message,
+            )
+            .with_position(position),
+        )
+        .await
+    }
+
+    /// Ends the simple query or the extended query sequence. Status is taken from the session
+    /// transaction state, changed parameters are reported before it
+    async fn write_ready_for_query(&mut self) -> Result<(), Error> {
+        self.report_changed_parameters().await?;
+        self.write_notifications().await?;
+        self.write(protocol::ReadyForQuery::new(self.transaction_status()))
+            .await
+    }
+
+    /// Sends NotificationResponse for every NOTIFY which was received on the listened channels
+    async fn write_notifications(&mut self) -> Result<(), Error> {
+        for notification in self.session.state.take_notifications() {
+            self.write(protocol::NotificationResponse::new(
+                notification.process_id,
+                notification.channel,
+                notification.payload,
+            ))
+            .await?;
+        }
 
         Ok(())
     }
 
-    pub(crate) fn auth_context(&self) -> Result<Arc<AuthContext>, CubeError> {
-        if let Some(ctx) = self.session.state.auth_context() {
-            Ok(Arc::new(ctx))
-        } else {
-            Err(CubeError::internal("must be auth".to_string()))
+    /// Sends warnings of the query planning as notices, they're shown by clients before the result
+    async fn write_warnings(&mut self) -> Result<(), Error> {
+        for message in self.session.state.take_warnings() {
+            self.write(protocol::NoticeResponse::new(
+                protocol::NoticeSeverity::Warning,
+                protocol::ErrorCode::Warning,
+                message,
+            ))
+            .await?;
         }
+
+        Ok(())
     }
-}
+
+    /// Reports server execution time of the query, like psql \timing does, if it's enabled by
+    /// configuration
+    async fn write_timing_notice(&mut self, started: Instant) -> Result<(), Error> {
+        if self.session.server.configuration.timing_notice {
+            self.write(protocol::NoticeResponse::new(
+                protocol::NoticeSeverity::Notice,
+                protocol::ErrorCode::SuccessfulCompletion,
+                format!("Time: {:.3} ms", started.elapsed().as_secs_f64() * 1000.0),
+            ))
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports id of the query to the client, if it's enabled by configuration
+    async fn write_query_id_notice(&mut self, query_id: &str) -> Result<(), Error> {
+        if self.session.server.configuration.query_id_notice {
+            self.write(protocol::NoticeResponse::new(
+                protocol::NoticeSeverity::Notice,
+                protocol::ErrorCode::SuccessfulCompletion,
+                format!("query_id: {}", query_id),
+            ))
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    fn transaction_status(&self) -> protocol::TransactionStatus {
+        match self.session.state.transaction_state() {
+            TransactionState::None => protocol::TransactionStatus::Idle,
+            TransactionState::Active => protocol::TransactionStatus::InTransactionBlock,
+            TransactionState::Failed => protocol::TransactionStatus::InFailedTransactionBlock,
+        }
+    }
+
+    /// Applies BEGIN/COMMIT/ROLLBACK and savepoints of the portal to the transaction state.
+    /// Returns false, if the portal cannot be executed because the transaction was aborted or
+    /// there is no transaction for savepoint, error is written.
+    async fn apply_transaction_command(&mut self, portal: &Portal) -> Result<bool, Error> {
+        let state = self.session.state.transaction_state();
+        let next_state = match (state, portal.get_meta_completion()) {
+            (_, Some(CommandCompletion::Commit | CommandCompletion::Rollback)) => {
+                TransactionState::None
+            }
+            (
+                TransactionState::None,
+                Some(
+                    completion @ (CommandCompletion::Savepoint
+                    | CommandCompletion::Release
+                    | CommandCompletion::RollbackToSavepoint),
+                ),
+            ) => {
+                let command = match completion {
+                    CommandCompletion::Release => "RELEASE SAVEPOINT",
+                    CommandCompletion::RollbackToSavepoint => "ROLLBACK TO SAVEPOINT",
+                    _ => "SAVEPOINT",
+                };
+                self.write_error(
+                    protocol::ErrorCode::NoActiveSqlTransaction,
+                    format!("{} can only be used in transaction blocks", command),
+                )
+                .await?;
+
+                return Ok(false);
+            }
+            // Savepoints are not tracked, rollback to any of them recovers the aborted transaction
+            (_, Some(CommandCompletion::RollbackToSavepoint)) => TransactionState::Active,
+            (TransactionState::Failed, _) => {
+                self.write_in_failed_transaction().await?;
+
+                return Ok(false);
+            }
+            (_, Some(CommandCompletion::Begin)) => TransactionState::Active,
+            (state, _) => state,
+        };
+
+        // Portals are destroyed at the end of transaction
+        if state != TransactionState::None && next_state == TransactionState::None {
+            self.portals.clear();
+        }
+
+        self.session.state.set_transaction_state(next_state);
+
+        Ok(true)
+    }
+
+    async fn write_allocation_limit_exceeded(
+        &mut self,
+        object: &str,
+        max: usize,
+    ) -> Result<(), Error> {
+        self.write_error(
+            protocol::ErrorCode::ProgramLimitExceeded,
+            format!(
+                "Unable to allocate new {}, max allocation reached, max: {}",
+                object, max
+            ),
+        )
+        .await
+    }
+
+    async fn write_in_failed_transaction(&mut self) -> Result<(), Error> {
+        self.write_error(
+            protocol::ErrorCode::InFailedSqlTransaction,
+            "current transaction is aborted, commands ignored until end of transaction block"
+                .to_string(),
+        )
+        .await
+    }
+
+    async fn write_query_canceled(&mut self) -> Result<(), Error> {
+        self.write_error(
+            protocol::ErrorCode::QueryCanceled,
+            "canceling statement due to user request".to_string(),
+        )
+        .await
+    }
+
+    async fn write_statement_timeout(&mut self) -> Result<(), Error> {
+        self.write_error(
+            protocol::ErrorCode::QueryCanceled,
+            "canceling statement due to statement timeout".to_string(),
+        )
+        .await
+    }
+
+    pub(crate) fn auth_context(&self) -> Result<Arc<AuthContext>, CubeError> {
+        if let Some(ctx) = self.session.state.auth_context() {
+            Ok(Arc::new(ctx))
+        } else {
+            Err(CubeError::internal("must be auth".to_string()))
+        }
+    }
+}
 
 impl Drop for AsyncPostgresShim {
     fn drop(&mut self) {
@@ -563,8 +2058,3759 @@ impl Drop for AsyncPostgresShim {
             self.session.state.connection_id
         );
 
+        // Aborts the query, which may be still executing in background
         self.session
             .session_manager
             .drop_session(self.session.state.connection_id)
     }
 }
+
+/// Drops the session when the connection is closed before the shim takes ownership of it
+struct SessionGuard(Option<Arc<Session>>);
+
+impl SessionGuard {
+    fn release(mut self) -> Arc<Session> {
+        self.0.take().expect("session must be released only once")
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        if let Some(session) = self.0.take() {
+            session
+                .session_manager
+                .drop_session(session.state.connection_id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compile::engine::information_schema::postgres::testing_dataset::InfoSchemaTestingDatasetProvider,
+        config::processing_loop::ProcessingLoop,
+        sql::{
+            server_manager::ServerConfiguration, types::CommandCompletion, AuthenticateResponse,
+            ServerManager, SessionManager, SqlAuthService,
+        },
+        sql::{PostgresServer, StatusFlags},
+        transport::{HttpTransport, MetaContext, TransportService},
+    };
+    use async_trait::async_trait;
+    use cubeclient::models::{
+        V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure, V1LoadRequestQuery, V1LoadResponse,
+    };
+    use datafusion::execution::context::SessionContext as DFSessionContext;
+    use std::{convert::TryInto, time::Duration};
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    #[derive(Debug)]
+    struct TestSqlAuth {}
+
+    #[async_trait]
+    impl SqlAuthService for TestSqlAuth {
+        // Only admin is superuser
+        async fn authenticate(
+            &self,
+            user: Option<String>,
+        ) -> Result<AuthenticateResponse, CubeError> {
+            Ok(AuthenticateResponse {
+                context: AuthContext {
+                    access_token: "access_token".to_string(),
+                    base_path: "base_path".to_string(),
+                    claims: None,
+                    superuser: user.as_deref() == Some("admin"),
+                },
+                password: Some("password".to_string()),
+            })
+        }
+
+        // Tokens of three parts are valid, the subject is the user
+        async fn authenticate_token(
+            &self,
+            user: Option<String>,
+            token: String,
+        ) -> Result<AuthContext, CubeError> {
+            if token.split('.').count() != 3 {
+                return Err(CubeError::user("Invalid token".to_string()));
+            }
+
+            Ok(AuthContext {
+                access_token: token,
+                base_path: "base_path".to_string(),
+                claims: Some(serde_json::json!({ "sub": user })),
+                superuser: false,
+            })
+        }
+    }
+
+    /// Transport with a single cube, it records load queries and returns one row for each of them
+    #[derive(Debug, Default)]
+    struct TestCubeTransport {
+        queries: std::sync::Mutex<Vec<V1LoadRequestQuery>>,
+        meta_requests: std::sync::atomic::AtomicUsize,
+        load_delay: Option<Duration>,
+    }
+
+    #[async_trait]
+    impl TransportService for TestCubeTransport {
+        async fn meta(&self, _ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError> {
+            self.meta_requests
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+            Ok(Arc::new(MetaContext::new(vec![V1CubeMeta {
+                name: "KibanaSampleDataEcommerce".to_string(),
+                title: None,
+                meta: None,
+                joins: None,
+                dimensions: vec![
+                    V1CubeMetaDimension {
+                        name: "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                        _type: "string".to_string(),
+                    },
+                    V1CubeMetaDimension {
+                        name: "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+                        _type: "number".to_string(),
+                    },
+                ],
+                measures: vec![V1CubeMetaMeasure {
+                    name: "KibanaSampleDataEcommerce.count".to_string(),
+                    title: None,
+                    _type: "number".to_string(),
+                    agg_type: Some("count".to_string()),
+                }],
+                segments: vec![],
+            }])))
+        }
+
+        async fn load(
+            &self,
+            query: V1LoadRequestQuery,
+            _ctx: Arc<AuthContext>,
+        ) -> Result<V1LoadResponse, CubeError> {
+            self.queries.lock().unwrap().push(query);
+
+            if let Some(delay) = self.load_delay {
+                tokio::time::sleep(delay).await;
+            }
+
+            let result = serde_json::from_value(serde_json::json!({
+                "annotation": {
+                    "measures": [],
+                    "dimensions": [],
+                    "segments": [],
+                    "timeDimensions": []
+                },
+                "data": [{ "KibanaSampleDataEcommerce.count": 3 }],
+            }))
+            .unwrap();
+
+            Ok(V1LoadResponse {
+                pivot_query: None,
+                slow_query: None,
+                query_type: None,
+                results: vec![result],
+            })
+        }
+    }
+
+    async fn create_test_shim() -> Result<(AsyncPostgresShim, TcpStream), Error> {
+        create_test_shim_with_config(ServerConfiguration::default()).await
+    }
+
+    async fn create_test_shim_with_config(
+        configuration: ServerConfiguration,
+    ) -> Result<(AsyncPostgresShim, TcpStream), Error> {
+        create_test_shim_with_transport(configuration, Arc::new(HttpTransport::new())).await
+    }
+
+    async fn create_test_shim_with_transport(
+        configuration: ServerConfiguration,
+        transport: Arc<dyn TransportService>,
+    ) -> Result<(AsyncPostgresShim, TcpStream), Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let client = TcpStream::connect(listener.local_addr()?).await?;
+        let (socket, _) = listener.accept().await?;
+
+        let server = Arc::new(ServerManager {
+            auth: Arc::new(TestSqlAuth {}),
+            transport,
+            configuration,
+            nonce: None,
+        });
+        let session_manager = Arc::new(SessionManager::new(server));
+        let session =
+            session_manager.create_session(DatabaseProtocol::PostgreSQL, "127.0.0.1".to_string());
+
+        Ok((
+            AsyncPostgresShim::new(PostgresStream::Plain(socket), session),
+            client,
+        ))
+    }
+
+    /// Reads the next backend message: tag and body without length
+    async fn read_backend_message(client: &mut TcpStream) -> Result<(u8, Vec<u8>), Error> {
+        let tag = client.read_u8().await?;
+        let length = client.read_i32().await?;
+
+        let mut body = vec![0; length as usize - 4];
+        client.read_exact(&mut body).await?;
+
+        Ok((tag, body))
+    }
+
+    fn startup_message_packet() -> Vec<u8> {
+        startup_message_packet_with(&[("user", "test")])
+    }
+
+    fn startup_message_packet_with(parameters: &[(&str, &str)]) -> Vec<u8> {
+        let mut body = vec![0, 3, 0, 0];
+        for (name, value) in parameters {
+            body.extend_from_slice(name.as_bytes());
+            body.push(0);
+            body.extend_from_slice(value.as_bytes());
+            body.push(0);
+        }
+        body.push(0);
+
+        let mut packet = ((body.len() + 4) as u32).to_be_bytes().to_vec();
+        packet.extend(body);
+        packet
+    }
+
+    fn password_message_packet(password: &str) -> Vec<u8> {
+        let mut packet = vec![b'p'];
+        packet.extend(((password.len() + 5) as u32).to_be_bytes());
+        packet.extend_from_slice(password.as_bytes());
+        packet.push(0);
+        packet
+    }
+
+    fn query_packet(query: &str) -> Vec<u8> {
+        let mut packet = vec![b'Q'];
+        packet.extend(((query.len() + 5) as u32).to_be_bytes());
+        packet.extend_from_slice(query.as_bytes());
+        packet.push(0);
+        packet
+    }
+
+    /// Parse without types of parameters
+    fn parse_packet(name: &str, query: &str) -> Vec<u8> {
+        let mut packet = vec![b'P'];
+        packet.extend(((name.len() + query.len() + 8) as u32).to_be_bytes());
+        packet.extend_from_slice(name.as_bytes());
+        packet.push(0);
+        packet.extend_from_slice(query.as_bytes());
+        packet.push(0);
+        packet.extend([0, 0]);
+        packet
+    }
+
+    /// Bind without parameters, results are in text format
+    fn bind_packet(portal: &str, statement: &str) -> Vec<u8> {
+        let mut packet = vec![b'B'];
+        packet.extend(((portal.len() + statement.len() + 12) as u32).to_be_bytes());
+        packet.extend_from_slice(portal.as_bytes());
+        packet.push(0);
+        packet.extend_from_slice(statement.as_bytes());
+        packet.push(0);
+        packet.extend([0, 0, 0, 0, 0, 0]);
+        packet
+    }
+
+    const SSL_REQUEST_PACKET: [u8; 8] = [0, 0, 0, 8, 4, 210, 22, 47];
+
+    /// Runs the connection in background, it's finished by Terminate or by closing of the socket
+    fn spawn_shim_run(
+        mut shim: AsyncPostgresShim,
+    ) -> tokio::task::JoinHandle<(AsyncPostgresShim, Result<(), Error>)> {
+        tokio::spawn(async move {
+            let result = shim.run(None).await;
+            (shim, result)
+        })
+    }
+
+    /// Reads tags of messages till ReadyForQuery (inclusive) or FATAL error
+    async fn read_handshake_tags(client: &mut TcpStream) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let mut tags = vec![];
+        loop {
+            let (tag, body) = read_backend_message(client).await?;
+            tags.push(tag);
+
+            if tag == b'Z' || tag == b'E' {
+                return Ok((tags, body));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_pipelined_password() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim().await?;
+
+        // Password is sent without waiting for AuthenticationCleartextPassword
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+
+        let (tags, _) = read_handshake_tags(&mut client).await?;
+        assert_eq!(&tags[0..2], b"RR");
+        assert_eq!(&tags[tags.len() - 2..], b"KZ");
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        let (shim, result) = handle.await.unwrap();
+        result?;
+        assert_eq!(shim.session.state.user(), Some("test".to_string()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_meta_warmup() -> Result<(), Error> {
+        let handshake = |shim: AsyncPostgresShim, mut client: TcpStream| async move {
+            let mut packet = startup_message_packet();
+            packet.extend(password_message_packet("password"));
+            client.write_all(&packet).await?;
+
+            let handle = spawn_shim_run(shim);
+            let (tags, _) = read_handshake_tags(&mut client).await?;
+
+            client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+            handle.await.unwrap().1?;
+
+            Ok::<Vec<u8>, Error>(tags)
+        };
+
+        let mut configuration = ServerConfiguration::default();
+        configuration.meta_warmup = true;
+
+        let transport = Arc::new(TestCubeTransport::default());
+        let (shim, client) =
+            create_test_shim_with_transport(configuration, transport.clone()).await?;
+        handshake(shim, client).await?;
+        assert_eq!(
+            transport
+                .meta_requests
+                .load(std::sync::atomic::Ordering::SeqCst),
+            1
+        );
+
+        // Disabled by default
+        let transport = Arc::new(TestCubeTransport::default());
+        let (shim, client) =
+            create_test_shim_with_transport(ServerConfiguration::default(), transport.clone())
+                .await?;
+        handshake(shim, client).await?;
+        assert_eq!(
+            transport
+                .meta_requests
+                .load(std::sync::atomic::Ordering::SeqCst),
+            0
+        );
+
+        // Failed warmup doesn't fail the connection
+        let mut configuration = ServerConfiguration::default();
+        configuration.meta_warmup = true;
+
+        let (shim, client) = create_test_shim_with_config(configuration).await?;
+        let tags = handshake(shim, client).await?;
+        assert_eq!(tags.last(), Some(&b'Z'));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_pipelined_ssl_request() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim().await?;
+
+        let mut packet = SSL_REQUEST_PACKET.to_vec();
+        packet.extend(startup_message_packet());
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+
+        // SSLResponse is a single byte without length
+        assert_eq!(client.read_u8().await?, b'N');
+
+        let (tags, _) = read_handshake_tags(&mut client).await?;
+        assert_eq!(&tags[0..2], b"RR");
+        assert_eq!(tags.last(), Some(&b'Z'));
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_require_ssl_denies_plaintext() -> Result<(), Error> {
+        let tls_config = Arc::new(crate::sql::postgres::tls::tests::test_tls_config(
+            1,
+            Duration::from_secs(10),
+            true,
+        )?);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut client = TcpStream::connect(listener.local_addr()?).await?;
+        let (socket, _) = listener.accept().await?;
+
+        client.write_all(&startup_message_packet()).await?;
+        let negotiated = AsyncPostgresShim::negotiate_tls(socket, Some(tls_config.clone())).await?;
+        assert!(negotiated.is_none());
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        let body = String::from_utf8_lossy(&body).to_string();
+        assert!(body.contains("SFATAL\0"));
+        assert!(body.contains("C28000\0"));
+        assert!(body.contains("SSL connection is required"));
+
+        // Cancel request is accepted without TLS
+        let mut client = TcpStream::connect(listener.local_addr()?).await?;
+        let (socket, _) = listener.accept().await?;
+
+        client
+            .write_all(&[0, 0, 0, 16, 4, 210, 22, 46, 0, 0, 0, 1, 0, 0, 0, 2])
+            .await?;
+        let negotiated = AsyncPostgresShim::negotiate_tls(socket, Some(tls_config)).await?;
+        assert!(negotiated.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_denied_connection_drops_session() -> Result<(), Error> {
+        let tls_config = Arc::new(crate::sql::postgres::tls::tests::test_tls_config(
+            1,
+            Duration::from_secs(10),
+            true,
+        )?);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut client = TcpStream::connect(listener.local_addr()?).await?;
+        let (socket, _) = listener.accept().await?;
+
+        let server = Arc::new(ServerManager {
+            auth: Arc::new(TestSqlAuth {}),
+            transport: Arc::new(HttpTransport::new()),
+            configuration: ServerConfiguration::default(),
+            nonce: None,
+        });
+        let session_manager = Arc::new(SessionManager::new(server));
+        let session =
+            session_manager.create_session(DatabaseProtocol::PostgreSQL, "127.0.0.1".to_string());
+        assert_eq!(session_manager.sessions().count(), 1);
+
+        client.write_all(&startup_message_packet()).await?;
+        AsyncPostgresShim::run_on(socket, session, Some(tls_config), None).await?;
+
+        let (tag, _) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        assert_eq!(session_manager.sessions().count(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_out_of_order() -> Result<(), Error> {
+        // Query instead of password
+        let (shim, mut client) = create_test_shim().await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend([b'Q', 0, 0, 0, 13]);
+        packet.extend_from_slice(b"SELECT 1\0");
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+
+        let (tags, body) = read_handshake_tags(&mut client).await?;
+        assert_eq!(tags, b"RE".to_vec());
+        assert!(String::from_utf8_lossy(&body).contains("08P01"));
+        handle.await.unwrap().1?;
+
+        // Second SSLRequest
+        let (shim, mut client) = create_test_shim().await?;
+
+        let mut packet = SSL_REQUEST_PACKET.to_vec();
+        packet.extend(SSL_REQUEST_PACKET);
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+
+        assert_eq!(client.read_u8().await?, b'N');
+        assert_eq!(client.read_u8().await?, b'N');
+        let (tags, body) = read_handshake_tags(&mut client).await?;
+        assert_eq!(tags, b"E".to_vec());
+        assert!(String::from_utf8_lossy(&body).contains("08P01"));
+        handle.await.unwrap().1?;
+
+        // Wrong password
+        let (shim, mut client) = create_test_shim().await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("wrong"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+
+        let (tags, body) = read_handshake_tags(&mut client).await?;
+        assert_eq!(tags, b"RE".to_vec());
+        assert!(String::from_utf8_lossy(&body).contains("28P01"));
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    fn test_portal() -> Option<Portal> {
+        Some(Portal::new(
+            QueryPlan::MetaOk(StatusFlags::empty(), CommandCompletion::Select(0)),
+            vec![Format::Text],
+            None,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_reset_extended_state() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+
+        shim.portals.insert("p1".to_string(), test_portal());
+        shim.execute(protocol::Execute {
+            portal: "p1".to_string(),
+            max_rows: 0,
+        })
+        .await?;
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'C');
+        assert_eq!(body, b"SELECT 0\0".to_vec());
+
+        shim.portals.insert("p1".to_string(), test_portal());
+        let connection_id = shim.session.state.connection_id;
+        assert!(shim
+            .session
+            .session_manager
+            .reset_session_extended_state(connection_id));
+
+        // Reset is applied by the connection loop before processing the next message
+        shim.apply_extended_state_reset();
+        assert!(shim.portals.is_empty());
+
+        shim.execute(protocol::Execute {
+            portal: "p1".to_string(),
+            max_rows: 0,
+        })
+        .await?;
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        assert!(String::from_utf8_lossy(&body).contains("34000"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_startup_parameters() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+        shim.session.state.set_variables(HashMap::from([(
+            "session_authorization".to_string(),
+            DatabaseVariable::system(
+                "session_authorization".to_string(),
+                ScalarValue::Utf8(Some("test".to_string())),
+                None,
+            ),
+        )]));
+
+        shim.ready().await?;
+
+        let mut parameters = HashMap::new();
+        loop {
+            let (tag, body) = read_backend_message(&mut client).await?;
+            match tag {
+                b'S' => {
+                    let body = String::from_utf8(body).unwrap();
+                    let mut parts = body.split('\0');
+                    parameters.insert(
+                        parts.next().unwrap().to_string(),
+                        parts.next().unwrap().to_string(),
+                    );
+                }
+                b'Z' => break,
+                _ => {}
+            }
+        }
+
+        for (name, value) in [
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+            ("IntervalStyle", "postgres"),
+            ("DateStyle", "ISO"),
+            ("TimeZone", "GMT"),
+            ("application_name", ""),
+            ("session_authorization", "test"),
+            ("is_superuser", "on"),
+        ] {
+            assert_eq!(parameters.get(name).map(|v| v.as_str()), Some(value));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_startup_parameters_to_session() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+
+        let mut packet = startup_message_packet_with(&[
+            ("user", "test"),
+            ("database", "test_db"),
+            ("application_name", "psql"),
+            ("client_encoding", "utf-8"),
+            ("options", "-c extra_float_digits=3 --DateStyle=ISO"),
+        ]);
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+
+        let mut parameters = HashMap::new();
+        loop {
+            let (tag, body) = read_backend_message(&mut client).await?;
+            match tag {
+                b'S' => {
+                    let body = String::from_utf8(body).unwrap();
+                    let mut parts = body.split('\0');
+                    parameters.insert(
+                        parts.next().unwrap().to_string(),
+                        parts.next().unwrap().to_string(),
+                    );
+                }
+                b'Z' => break,
+                b'E' => panic!("Unexpected error: {}", String::from_utf8_lossy(&body)),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            parameters.get("application_name").map(|v| v.as_str()),
+            Some("psql")
+        );
+        assert_eq!(
+            parameters.get("client_encoding").map(|v| v.as_str()),
+            Some("UTF8")
+        );
+
+        client
+            .write_all(&query_packet("SHOW application_name"))
+            .await?;
+        let mut rows = vec![];
+        loop {
+            let (tag, body) = read_backend_message(&mut client).await?;
+            match tag {
+                b'D' => rows.push(body),
+                b'Z' => break,
+                b'E' => panic!("Unexpected error: {}", String::from_utf8_lossy(&body)),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            rows,
+            vec![[vec![0, 1, 0, 0, 0, 4], b"psql".to_vec()].concat()]
+        );
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        let (shim, result) = handle.await.unwrap();
+        result?;
+
+        assert_eq!(shim.session.state.database(), Some("test_db".to_string()));
+        assert_eq!(shim.session.state.application_name(), "psql");
+        let variables = shim.session.state.all_variables();
+        assert_eq!(
+            variables
+                .get("extra_float_digits")
+                .map(|v| v.value.to_string()),
+            Some("3".to_string())
+        );
+        assert_eq!(
+            variables.get("datestyle").map(|v| v.value.to_string()),
+            Some("ISO".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parameter_status_messages() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+
+        let mut packet =
+            startup_message_packet_with(&[("user", "test"), ("application_name", "psql")]);
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+
+        // Tags of messages till ReadyForQuery and reported parameters, the way libpq reads them
+        async fn read_till_ready(
+            client: &mut TcpStream,
+        ) -> Result<(String, Vec<(String, String)>), Error> {
+            let mut tags = String::new();
+            let mut parameters = vec![];
+            loop {
+                let (tag, body) = read_backend_message(client).await?;
+                tags.push(tag as char);
+                match tag {
+                    b'S' => {
+                        let body = String::from_utf8(body).unwrap();
+                        let mut parts = body.split('\0');
+                        parameters.push((
+                            parts.next().unwrap().to_string(),
+                            parts.next().unwrap().to_string(),
+                        ));
+                    }
+                    b'Z' => return Ok((tags, parameters)),
+                    b'E' => panic!("Unexpected error: {}", String::from_utf8_lossy(&body)),
+                    _ => {}
+                }
+            }
+        }
+
+        let parameter = |name: &str, value: &str| (name.to_string(), value.to_string());
+
+        // Every parameter is reported once after authentication, before BackendKeyData
+        let (tags, parameters) = read_till_ready(&mut client).await?;
+        assert_eq!(
+            tags,
+            format!("RR{}KZ", "S".repeat(STARTUP_PARAMETERS.len()))
+        );
+        assert_eq!(
+            parameters,
+            vec![
+                parameter("server_version", "14.2 (Cube SQL)"),
+                parameter("server_encoding", "UTF8"),
+                parameter("client_encoding", "UTF8"),
+                parameter("DateStyle", "ISO"),
+                parameter("IntervalStyle", "postgres"),
+                parameter("TimeZone", "GMT"),
+                parameter("integer_datetimes", "on"),
+                parameter("standard_conforming_strings", "on"),
+                parameter("application_name", "psql"),
+                parameter("session_authorization", "test"),
+                parameter("is_superuser", "on"),
+            ]
+        );
+
+        // SET by the simple query is reported before ReadyForQuery
+        client
+            .write_all(&query_packet("SET TimeZone = 'UTC'"))
+            .await?;
+        let (tags, parameters) = read_till_ready(&mut client).await?;
+        assert_eq!(tags, "nCSZ");
+        assert_eq!(parameters, vec![parameter("TimeZone", "UTC")]);
+
+        // SET by the extended query is reported after Execute only
+        let sync = [b'S', 0, 0, 0, 4];
+        let mut packet = parse_packet("s1", "SET application_name = 'app'");
+        packet.extend(bind_packet("p1", "s1"));
+        packet.extend(sync);
+        client.write_all(&packet).await?;
+        let (tags, parameters) = read_till_ready(&mut client).await?;
+        assert_eq!(tags, "12Z");
+        assert!(parameters.is_empty());
+
+        let mut packet = vec![b'E', 0, 0, 0, 11];
+        packet.extend_from_slice(b"p1\0");
+        packet.extend([0, 0, 0, 0]);
+        packet.extend(sync);
+        client.write_all(&packet).await?;
+        let (tags, parameters) = read_till_ready(&mut client).await?;
+        assert_eq!(tags, "CSZ");
+        assert_eq!(parameters, vec![parameter("application_name", "app")]);
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_startup_client_encoding_not_supported() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim().await?;
+
+        let mut packet =
+            startup_message_packet_with(&[("user", "test"), ("client_encoding", "LATIN1")]);
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        let (tags, body) = read_handshake_tags(&mut client).await?;
+        assert_eq!(tags.last(), Some(&b'E'));
+        assert!(String::from_utf8_lossy(&body).contains("22023"));
+
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ignore_till_sync() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim().await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        for _ in 0..2 {
+            // Bind of unknown statement fails, Execute must be skipped
+            let mut packet = vec![b'B', 0, 0, 0, 14, 0, b's', b'1', 0, 0, 0, 0, 0, 0, 0];
+            packet.extend([b'E', 0, 0, 0, 9, 0, 0, 0, 0, 0]);
+            packet.extend([b'D', 0, 0, 0, 6, b'P', 0]);
+            packet.extend([b'S', 0, 0, 0, 4]);
+            client.write_all(&packet).await?;
+
+            let (tag, _) = read_backend_message(&mut client).await?;
+            assert_eq!(tag, b'E');
+            let (tag, _) = read_backend_message(&mut client).await?;
+            assert_eq!(tag, b'Z');
+        }
+
+        // Messages are processed again after Sync
+        client
+            .write_all(&[b'D', 0, 0, 0, 6, b'P', 0, b'S', 0, 0, 0, 4])
+            .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        assert!(String::from_utf8_lossy(&body).contains("34000"));
+        let (tag, _) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'Z');
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_parse_ignored_till_sync() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Parse of invalid SQL, Bind and Execute of its portal are pipelined before Sync
+        let query = b"SELEC 1";
+        let mut packet = vec![b'P'];
+        packet.extend(((4 + 3 + query.len() + 1 + 2) as u32).to_be_bytes());
+        packet.extend_from_slice(b"s1\0");
+        packet.extend_from_slice(query);
+        packet.extend([0, 0, 0]);
+        packet.extend([b'B', 0, 0, 0, 14, 0, b's', b'1', 0, 0, 0, 0, 0, 0, 0]);
+        packet.extend([b'E', 0, 0, 0, 9, 0, 0, 0, 0, 0]);
+        packet.extend([b'S', 0, 0, 0, 4]);
+        client.write_all(&packet).await?;
+
+        let (tags, body) = read_handshake_tags(&mut client).await?;
+        assert_eq!(tags, b"EZ".to_vec());
+        assert_eq!(body, vec![b'I']);
+
+        // Error of simple query doesn't skip the next messages
+        client.write_all(&query_packet("SELEC 1")).await?;
+        let (tags, _) = read_handshake_tags(&mut client).await?;
+        assert_eq!(tags, b"E".to_vec());
+        let (tag, _) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'Z');
+
+        client
+            .write_all(&[b'D', 0, 0, 0, 6, b'P', 0, b'S', 0, 0, 0, 4])
+            .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        assert!(String::from_utf8_lossy(&body).contains("34000"));
+        let (tag, _) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'Z');
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flush_keeps_ignore_till_sync() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim().await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Error of Bind is pushed by Flush without ReadyForQuery
+        let mut packet = vec![b'B', 0, 0, 0, 14, 0, b's', b'1', 0, 0, 0, 0, 0, 0, 0];
+        packet.extend([b'H', 0, 0, 0, 4]);
+        client.write_all(&packet).await?;
+
+        let (tag, _) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+
+        // Describe after Flush is still skipped till Sync
+        client
+            .write_all(&[
+                b'D', 0, 0, 0, 6, b'P', 0, b'H', 0, 0, 0, 4, b'S', 0, 0, 0, 4,
+            ])
+            .await?;
+        let (tag, _) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'Z');
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_repeated_sync_reports_transaction_status() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Every Sync is answered by ReadyForQuery with the current transaction status
+        let syncs = |count: usize| [b'S', 0, 0, 0, 4].repeat(count);
+        let mut statuses = vec![];
+        for (query, count) in vec![
+            (None, 3),
+            (Some("BEGIN"), 2),
+            (Some("SELEC 1"), 2),
+            (Some("ROLLBACK"), 2),
+        ] {
+            if let Some(query) = query {
+                client.write_all(&query_packet(query)).await?;
+                loop {
+                    let (tag, body) = read_backend_message(&mut client).await?;
+                    if tag == b'Z' {
+                        statuses.push(body[0]);
+                        break;
+                    }
+                }
+            }
+
+            client.write_all(&syncs(count)).await?;
+            for _ in 0..count {
+                let (tag, body) = read_backend_message(&mut client).await?;
+                assert_eq!(tag, b'Z');
+                statuses.push(body[0]);
+            }
+        }
+        assert_eq!(statuses, b"IIITTTEEEIII".to_vec());
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_id_notice() -> Result<(), Error> {
+        let mut configuration = ServerConfiguration::default();
+        configuration.query_id_notice = true;
+
+        let (mut shim, mut client) = create_test_shim_with_config(configuration).await?;
+
+        let bind = |portal: &str| protocol::Bind {
+            portal: portal.to_string(),
+            statement: "s1".to_string(),
+            parameter_formats: vec![],
+            parameter_values: vec![],
+            result_formats: vec![],
+        };
+
+        // Empty query doesn't require meta
+        shim.statements.insert("s1".to_string(), None);
+
+        let mut query_ids = vec![];
+        for portal in ["p1", "p2"] {
+            shim.bind(bind(portal)).await?;
+
+            let (tag, body) = read_backend_message(&mut client).await?;
+            assert_eq!(tag, b'N');
+
+            let query_id = shim.session.state.query_id().unwrap();
+            let body = String::from_utf8(body).unwrap();
+            assert!(body.contains("NOTICE"));
+            assert!(body.contains(&format!("query_id: {}", query_id)));
+            query_ids.push(query_id);
+
+            let (tag, _) = read_backend_message(&mut client).await?;
+            assert_eq!(tag, b'2');
+        }
+
+        assert_ne!(query_ids[0], query_ids[1]);
+
+        // Disabled by default
+        let (mut shim, mut client) = create_test_shim().await?;
+        shim.statements.insert("s1".to_string(), None);
+        shim.bind(bind("p1")).await?;
+
+        let (tag, _) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'2');
+        assert!(shim.session.state.query_id().is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timing_notice() -> Result<(), Error> {
+        // Executes query and returns messages till ReadyForQuery
+        async fn execute(timing_notice: bool) -> Result<Vec<(u8, String)>, Error> {
+            let mut configuration = ServerConfiguration::default();
+            configuration.timing_notice = timing_notice;
+
+            let (mut shim, mut client) = create_test_shim_with_transport(
+                configuration,
+                Arc::new(TestCubeTransport::default()),
+            )
+            .await?;
+            shim.session.state.set_auth_context(Some(AuthContext {
+                access_token: "access_token".to_string(),
+                base_path: "base_path".to_string(),
+                claims: None,
+                superuser: false,
+            }));
+
+            shim.process_query("SET extra_float_digits = 3".to_string())
+                .await?;
+
+            let mut messages = vec![];
+            loop {
+                let (tag, body) = read_backend_message(&mut client).await?;
+                if tag == b'Z' {
+                    return Ok(messages);
+                }
+                messages.push((tag, String::from_utf8_lossy(&body).to_string()));
+            }
+        }
+
+        // Timing follows the result of query
+        let messages = execute(true).await?;
+        assert_eq!(
+            messages.iter().map(|(tag, _)| *tag).collect::<Vec<_>>(),
+            b"CN".to_vec()
+        );
+        let body = &messages[1].1;
+        assert!(body.contains("SNOTICE\0"));
+        assert!(
+            body.contains("MTime: ") && body.contains(" ms\0"),
+            "{}",
+            body
+        );
+
+        // Disabled by default
+        let messages = execute(false).await?;
+        assert_eq!(
+            messages.iter().map(|(tag, _)| *tag).collect::<Vec<_>>(),
+            b"C".to_vec()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_transaction_status() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+
+        let meta_portal = |completion| {
+            Some(Portal::new(
+                QueryPlan::MetaOk(StatusFlags::empty(), completion),
+                vec![Format::Text],
+                None,
+            ))
+        };
+        // Executes portal and returns tags of messages till ReadyForQuery with its status
+        async fn execute(
+            shim: &mut AsyncPostgresShim,
+            client: &mut TcpStream,
+            portal: Option<Option<Portal>>,
+        ) -> Result<(Vec<u8>, u8), Error> {
+            if let Some(portal) = portal {
+                shim.portals.insert("p1".to_string(), portal);
+            }
+            shim.execute(protocol::Execute {
+                portal: "p1".to_string(),
+                max_rows: 0,
+            })
+            .await?;
+            shim.portals.remove("p1");
+            shim.sync().await?;
+
+            let mut tags = vec![];
+            loop {
+                let (tag, body) = read_backend_message(client).await?;
+                if tag == b'Z' {
+                    return Ok((tags, body[0]));
+                }
+                tags.push(tag);
+            }
+        }
+
+        let portal = meta_portal(CommandCompletion::Begin);
+        assert_eq!(
+            execute(&mut shim, &mut client, Some(portal)).await?,
+            (b"C".to_vec(), b'T')
+        );
+
+        let portal = meta_portal(CommandCompletion::Select(0));
+        assert_eq!(
+            execute(&mut shim, &mut client, Some(portal)).await?,
+            (b"C".to_vec(), b'T')
+        );
+
+        // Missing portal fails the transaction, next commands are rejected till ROLLBACK
+        assert_eq!(
+            execute(&mut shim, &mut client, None).await?,
+            (b"E".to_vec(), b'E')
+        );
+
+        let portal = meta_portal(CommandCompletion::Select(0));
+        assert_eq!(
+            execute(&mut shim, &mut client, Some(portal)).await?,
+            (b"E".to_vec(), b'E')
+        );
+
+        let portal = meta_portal(CommandCompletion::Rollback);
+        assert_eq!(
+            execute(&mut shim, &mut client, Some(portal)).await?,
+            (b"C".to_vec(), b'I')
+        );
+
+        // Errors outside of transaction block don't change the status
+        assert_eq!(
+            execute(&mut shim, &mut client, None).await?,
+            (b"E".to_vec(), b'I')
+        );
+
+        let portal = meta_portal(CommandCompletion::Begin);
+        execute(&mut shim, &mut client, Some(portal)).await?;
+        let portal = meta_portal(CommandCompletion::Commit);
+        assert_eq!(
+            execute(&mut shim, &mut client, Some(portal)).await?,
+            (b"C".to_vec(), b'I')
+        );
+
+        // Savepoints require transaction block
+        let portal = meta_portal(CommandCompletion::Savepoint);
+        assert_eq!(
+            execute(&mut shim, &mut client, Some(portal)).await?,
+            (b"E".to_vec(), b'I')
+        );
+
+        // ROLLBACK TO SAVEPOINT recovers the failed transaction
+        let portal = meta_portal(CommandCompletion::Begin);
+        execute(&mut shim, &mut client, Some(portal)).await?;
+        let portal = meta_portal(CommandCompletion::Savepoint);
+        assert_eq!(
+            execute(&mut shim, &mut client, Some(portal)).await?,
+            (b"C".to_vec(), b'T')
+        );
+        assert_eq!(
+            execute(&mut shim, &mut client, None).await?,
+            (b"E".to_vec(), b'E')
+        );
+        let portal = meta_portal(CommandCompletion::RollbackToSavepoint);
+        assert_eq!(
+            execute(&mut shim, &mut client, Some(portal)).await?,
+            (b"C".to_vec(), b'T')
+        );
+        let portal = meta_portal(CommandCompletion::Release);
+        assert_eq!(
+            execute(&mut shim, &mut client, Some(portal)).await?,
+            (b"C".to_vec(), b'T')
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_function_call() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+        shim.session.state.set_user(Some("test".to_string()));
+
+        let call = |function_oid: u32, result_format: protocol::Format| protocol::FunctionCall {
+            function_oid,
+            argument_formats: vec![],
+            arguments: vec![],
+            result_format,
+        };
+
+        // pg_backend_pid in binary format
+        shim.function_call(call(2026, protocol::Format::Binary))
+            .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'V');
+        assert_eq!(body[0..4], 4_i32.to_be_bytes());
+        assert_eq!(
+            body[4..],
+            (shim.session.state.connection_id as i32).to_be_bytes()
+        );
+        assert_eq!(read_backend_message(&mut client).await?, (b'Z', vec![b'I']));
+
+        // current_user in text format
+        shim.function_call(call(745, protocol::Format::Text))
+            .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'V');
+        assert_eq!(body, vec![0, 0, 0, 4, b't', b'e', b's', b't']);
+        assert_eq!(read_backend_message(&mut client).await?, (b'Z', vec![b'I']));
+
+        // Unknown function (lo_open) fails, but the session is kept
+        shim.function_call(call(952, protocol::Format::Binary))
+            .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        let body = String::from_utf8_lossy(&body).to_string();
+        assert!(body.contains("C42883\0"));
+        assert!(body.contains("function with OID 952 does not exist"));
+        assert_eq!(read_backend_message(&mut client).await?, (b'Z', vec![b'I']));
+
+        shim.function_call(call(2026, protocol::Format::Text))
+            .await?;
+        let (tag, _) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'V');
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_describe_error_fails_transaction() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+
+        // Returns tags of messages till ReadyForQuery with its status
+        async fn read_till_ready(client: &mut TcpStream) -> Result<(Vec<u8>, u8), Error> {
+            let mut tags = vec![];
+            loop {
+                let (tag, body) = read_backend_message(client).await?;
+                if tag == b'Z' {
+                    return Ok((tags, body[0]));
+                }
+                tags.push(tag);
+            }
+        }
+
+        shim.describe_statement("missing".to_string()).await?;
+        shim.sync().await?;
+        assert_eq!(read_till_ready(&mut client).await?, (b"E".to_vec(), b'I'));
+
+        shim.session
+            .state
+            .set_transaction_state(TransactionState::Active);
+        shim.describe_portal("missing".to_string()).await?;
+        shim.sync().await?;
+        assert_eq!(read_till_ready(&mut client).await?, (b"E".to_vec(), b'E'));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_simple_query_transaction_block() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Sequence of psycopg2, which starts transaction before the first query of connection
+        for (query, expected_tags, status) in [
+            ("BEGIN", "nC", b'T'),
+            ("SELECT 1", "TDC", b'T'),
+            ("SAVEPOINT sp1", "nC", b'T'),
+            (
+                "SELECT unknown_column FROM KibanaSampleDataEcommerce",
+                "E",
+                b'E',
+            ),
+            ("SELECT 1", "E", b'E'),
+            ("ROLLBACK TO SAVEPOINT sp1", "nC", b'T'),
+            ("RELEASE SAVEPOINT sp1", "nC", b'T'),
+            ("END", "nC", b'I'),
+            ("BEGIN", "nC", b'T'),
+            ("ROLLBACK", "nC", b'I'),
+            // Failure outside of transaction block keeps the idle status
+            (
+                "SELECT unknown_column FROM KibanaSampleDataEcommerce",
+                "E",
+                b'I',
+            ),
+        ] {
+            client.write_all(&query_packet(query)).await?;
+
+            let mut tags = vec![];
+            loop {
+                let (tag, body) = read_backend_message(&mut client).await?;
+                if tag == b'Z' {
+                    assert_eq!(
+                        (String::from_utf8(tags).unwrap().as_str(), body[0]),
+                        (expected_tags, status),
+                        "{}",
+                        query
+                    );
+                    break;
+                }
+                tags.push(tag);
+            }
+        }
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_in_failed_transaction() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+        let session = shim.session.clone();
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        let extra_float_digits = || {
+            session
+                .state
+                .visible_variables(DatabaseVariables::new())
+                .get("extra_float_digits")
+                .map(|v| v.value.to_string())
+        };
+
+        // SET is rejected in the aborted transaction, the session is not changed
+        for (query, expected_tags) in [
+            ("BEGIN", "nC"),
+            ("SELECT unknown_column FROM KibanaSampleDataEcommerce", "E"),
+            ("SET extra_float_digits = 3", "E"),
+            ("SET LOCAL extra_float_digits = 3", "E"),
+            ("SET SESSION CHARACTERISTICS AS TRANSACTION READ ONLY", "E"),
+            ("ROLLBACK", "nC"),
+        ] {
+            client.write_all(&query_packet(query)).await?;
+
+            let mut tags = vec![];
+            loop {
+                let (tag, body) = read_backend_message(&mut client).await?;
+                if tag == b'Z' {
+                    break;
+                }
+                if tag == b'E' && query.starts_with("SET") {
+                    assert!(String::from_utf8_lossy(&body).contains("25P02"));
+                }
+                tags.push(tag);
+            }
+            assert_eq!(String::from_utf8(tags).unwrap(), expected_tags, "{}", query);
+        }
+
+        assert_eq!(extra_float_digits(), Some("1".to_string()));
+        assert_eq!(
+            session
+                .state
+                .all_variables()
+                .get("default_transaction_read_only")
+                .map(|v| v.value.to_string()),
+            Some("off".to_string())
+        );
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_psycopg2_savepoints() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Savepoints of psycopg2 (connection.savepoint() of Django), the command tag and the
+        // transaction status of ReadyForQuery
+        for (query, expected_completion, expected_status) in [
+            ("BEGIN", Some("BEGIN"), b'T'),
+            ("SAVEPOINT \"s1_x\"", Some("SAVEPOINT"), b'T'),
+            (
+                "SELECT unknown_column FROM KibanaSampleDataEcommerce",
+                None,
+                b'E',
+            ),
+            ("ROLLBACK TO SAVEPOINT \"s1_x\"", Some("ROLLBACK"), b'T'),
+            ("RELEASE SAVEPOINT \"s1_x\"", Some("RELEASE"), b'T'),
+            // Names of savepoints are not commands
+            (
+                "SAVEPOINT \"__cubesql_release.s2_x\"",
+                Some("SAVEPOINT"),
+                b'T',
+            ),
+            ("COMMIT", Some("COMMIT"), b'I'),
+            ("RELEASE SAVEPOINT \"s1_x\"", None, b'I'),
+        ] {
+            client.write_all(&query_packet(query)).await?;
+
+            let mut completion = None;
+            let status = loop {
+                let (tag, body) = read_backend_message(&mut client).await?;
+                match tag {
+                    b'C' => completion = Some(String::from_utf8(body).unwrap().replace('\0', "")),
+                    b'Z' => break body[0],
+                    _ => {}
+                }
+            };
+            assert_eq!(completion.as_deref(), expected_completion, "{}", query);
+            assert_eq!(status, expected_status, "{}", query);
+        }
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deallocate() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+
+        // Empty queries don't require meta
+        for name in ["s1", "s2", "s3"] {
+            shim.statements.insert(name.to_string(), None);
+        }
+        shim.portals.insert("p1".to_string(), None);
+
+        async fn expect_response(
+            client: &mut TcpStream,
+            expected: &[(u8, &str)],
+        ) -> Result<(), Error> {
+            for (expected_tag, expected_body) in expected {
+                let (tag, body) = read_backend_message(client).await?;
+                assert_eq!(tag, *expected_tag);
+                assert!(String::from_utf8_lossy(&body).contains(expected_body));
+            }
+
+            Ok(())
+        }
+
+        shim.process_query("DEALLOCATE s1".to_string()).await?;
+        expect_response(&mut client, &[(b'C', "DEALLOCATE"), (b'Z', "")]).await?;
+        assert!(!shim.statements.contains_key("s1"));
+        assert!(shim.statements.contains_key("s2"));
+
+        shim.process_query("DEALLOCATE PREPARE s1".to_string())
+            .await?;
+        expect_response(
+            &mut client,
+            &[
+                (b'E', "prepared statement \"s1\" does not exist"),
+                (b'Z', ""),
+            ],
+        )
+        .await?;
+
+        shim.process_query("DEALLOCATE ALL".to_string()).await?;
+        expect_response(&mut client, &[(b'C', "DEALLOCATE ALL"), (b'Z', "")]).await?;
+        assert!(shim.statements.is_empty());
+        assert!(shim.portals.contains_key("p1"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_statement_and_portal_lifetimes() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+
+        // Empty queries don't require meta
+        let parse = |name: &str| protocol::Parse {
+            name: name.to_string(),
+            query: "".to_string(),
+            param_types: vec![],
+        };
+        let bind = |portal: &str| protocol::Bind {
+            portal: portal.to_string(),
+            statement: "s1".to_string(),
+            parameter_formats: vec![],
+            parameter_values: vec![],
+            result_formats: vec![],
+        };
+
+        // Named statement cannot be redefined without Close
+        shim.parse(parse("s1")).await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'1');
+        shim.parse(parse("s1")).await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        assert!(String::from_utf8_lossy(&body).contains("42P05"));
+        assert!(shim.ignore_till_sync);
+        shim.sync().await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+
+        shim.close(protocol::Close {
+            typ: protocol::CloseType::Statement,
+            name: "s1".to_string(),
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'3');
+        shim.parse(parse("s1")).await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'1');
+
+        // Unnamed statement and portal are replaced
+        for _ in 0..2 {
+            shim.parse(parse("")).await?;
+            assert_eq!(read_backend_message(&mut client).await?.0, b'1');
+            shim.bind(bind("")).await?;
+            assert_eq!(read_backend_message(&mut client).await?.0, b'2');
+        }
+        assert!(shim.statements.contains_key(""));
+        assert!(shim.portals.contains_key(""));
+
+        // Sync ends the implicit transaction with its portals, statements are kept
+        shim.sync().await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+        assert!(shim.portals.is_empty());
+        assert!(shim.statements.contains_key("s1"));
+
+        // In a transaction block, portals are destroyed by COMMIT
+        shim.session
+            .state
+            .set_transaction_state(TransactionState::Active);
+        shim.bind(bind("p1")).await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'2');
+        shim.sync().await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+        assert!(shim.portals.contains_key("p1"));
+
+        let commit = Portal::new(
+            QueryPlan::MetaOk(StatusFlags::empty(), CommandCompletion::Commit),
+            vec![],
+            None,
+        );
+        assert!(shim.apply_transaction_command(&commit).await?);
+        assert!(shim.portals.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_discard_all_reports_parameters() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+
+        let mut packet =
+            startup_message_packet_with(&[("user", "test"), ("application_name", "psql")]);
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Tags of messages till ReadyForQuery and changed parameters
+        async fn query(
+            client: &mut TcpStream,
+            query: &str,
+        ) -> Result<(String, Vec<(String, String)>), Error> {
+            client.write_all(&query_packet(query)).await?;
+
+            let mut tags = vec![];
+            let mut parameters = vec![];
+            loop {
+                let (tag, body) = read_backend_message(client).await?;
+                match tag {
+                    b'Z' => return Ok((String::from_utf8(tags).unwrap(), parameters)),
+                    b'S' => {
+                        let body = String::from_utf8(body).unwrap();
+                        let mut parts = body.split('\0');
+                        parameters.push((
+                            parts.next().unwrap().to_string(),
+                            parts.next().unwrap().to_string(),
+                        ));
+                    }
+                    _ => {}
+                }
+                tags.push(tag);
+            }
+        }
+
+        let (tags, parameters) = query(&mut client, "SET DateStyle TO 'SQL'").await?;
+        assert_eq!(tags, "nCS");
+        assert_eq!(
+            parameters,
+            vec![("DateStyle".to_string(), "SQL".to_string())]
+        );
+
+        let (tags, _) = query(&mut client, "SET application_name TO 'app'").await?;
+        assert_eq!(tags, "nCS");
+
+        let (tags, parameters) = query(&mut client, "SELECT 1").await?;
+        assert_eq!(tags, "TDC");
+        assert!(parameters.is_empty());
+
+        // All changed parameters are reported together, before ReadyForQuery. Values from
+        // startup parameters are restored
+        let (tags, parameters) = query(&mut client, "DISCARD ALL").await?;
+        assert_eq!(tags, "CSS");
+        assert_eq!(
+            parameters,
+            vec![
+                ("DateStyle".to_string(), "ISO".to_string()),
+                ("application_name".to_string(), "psql".to_string()),
+            ]
+        );
+
+        // DISCARD ALL cannot be used in a transaction block
+        query(&mut client, "BEGIN").await?;
+        let (tags, _) = query(&mut client, "DISCARD ALL").await?;
+        assert_eq!(tags, "E");
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_and_reset_report_parameters() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+
+        let mut packet = startup_message_packet_with(&[
+            ("user", "test"),
+            ("application_name", "psql"),
+            ("options", "-c datestyle=SQL"),
+        ]);
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Tags of messages till ReadyForQuery and changed parameters
+        async fn query(
+            client: &mut TcpStream,
+            query: &str,
+        ) -> Result<(String, Vec<(String, String)>), Error> {
+            client.write_all(&query_packet(query)).await?;
+
+            let mut tags = vec![];
+            let mut parameters = vec![];
+            loop {
+                let (tag, body) = read_backend_message(client).await?;
+                match tag {
+                    b'Z' => return Ok((String::from_utf8(tags).unwrap(), parameters)),
+                    b'S' => {
+                        let body = String::from_utf8(body).unwrap();
+                        let mut parts = body.split('\0');
+                        parameters.push((
+                            parts.next().unwrap().to_string(),
+                            parts.next().unwrap().to_string(),
+                        ));
+                    }
+                    _ => {}
+                }
+                tags.push(tag);
+            }
+        }
+
+        let parameter = |name: &str, value: &str| (name.to_string(), value.to_string());
+
+        let (tags, parameters) = query(&mut client, "SET TimeZone = 'UTC'").await?;
+        assert_eq!(tags, "nCS");
+        assert_eq!(parameters, vec![parameter("TimeZone", "UTC")]);
+
+        // Variables which are not tracked by clients are not reported
+        let (tags, parameters) = query(&mut client, "SET search_path TO a, b").await?;
+        assert_eq!(tags, "nC");
+        assert!(parameters.is_empty());
+
+        let (_, parameters) = query(&mut client, "SET application_name = 'app'").await?;
+        assert_eq!(parameters, vec![parameter("application_name", "app")]);
+
+        let (tags, parameters) = query(&mut client, "RESET application_name").await?;
+        assert_eq!(tags, "nCS");
+        assert_eq!(parameters, vec![parameter("application_name", "psql")]);
+
+        let (_, parameters) = query(&mut client, "SET DateStyle TO ISO").await?;
+        assert_eq!(parameters, vec![parameter("DateStyle", "ISO")]);
+
+        let (tags, parameters) = query(&mut client, "RESET ALL").await?;
+        assert_eq!(tags, "nCSS");
+        assert_eq!(
+            parameters,
+            vec![parameter("DateStyle", "SQL"), parameter("TimeZone", "GMT")]
+        );
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prepared_statements_and_portals_limits() -> Result<(), Error> {
+        let mut configuration = ServerConfiguration::default();
+        configuration.connection_max_pg_prepared_statements = 2;
+        configuration.connection_max_pg_portals = 1;
+
+        let (shim, mut client) = create_test_shim_with_config(configuration).await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Tags of messages till ReadyForQuery, body of the last error
+        async fn send(client: &mut TcpStream, packet: Vec<u8>) -> Result<(String, String), Error> {
+            client.write_all(&packet).await?;
+
+            let mut tags = vec![];
+            let mut error = String::new();
+            loop {
+                let (tag, body) = read_backend_message(client).await?;
+                tags.push(tag);
+                match tag {
+                    b'E' => error = String::from_utf8_lossy(&body).to_string(),
+                    b'Z' => return Ok((String::from_utf8(tags).unwrap(), error)),
+                    _ => {}
+                }
+            }
+        }
+
+        // Empty queries don't require meta
+        let mut packet = parse_packet("s1", "");
+        packet.extend(parse_packet("s2", ""));
+        packet.extend(parse_packet("s3", ""));
+        packet.extend([b'S', 0, 0, 0, 4]);
+        let (tags, error) = send(&mut client, packet).await?;
+        assert_eq!(tags, "11EZ");
+        assert!(error.contains("54000"));
+
+        // Existing statement doesn't need a new allocation
+        let mut packet = vec![b'C', 0, 0, 0, 8, b'S', b's', b'2', 0];
+        packet.extend(parse_packet("s2", ""));
+        packet.extend([b'S', 0, 0, 0, 4]);
+        let (tags, _) = send(&mut client, packet).await?;
+        assert_eq!(tags, "31Z");
+
+        // Portals are kept till the end of the transaction block
+        send(&mut client, query_packet("BEGIN")).await?;
+        let mut packet = bind_packet("p1", "s1");
+        packet.extend(bind_packet("p2", "s1"));
+        packet.extend([b'S', 0, 0, 0, 4]);
+        let (tags, error) = send(&mut client, packet).await?;
+        assert_eq!(tags, "2EZ");
+        assert!(error.contains("54000"));
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        let (shim, result) = handle.await.unwrap();
+        result?;
+        assert_eq!(shim.session.state.extended_state_counts(), (2, 1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unnamed_statement_and_portal_reuse() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Messages till ReadyForQuery (inclusive)
+        async fn send(
+            client: &mut TcpStream,
+            packet: Vec<u8>,
+        ) -> Result<Vec<(u8, Vec<u8>)>, Error> {
+            client.write_all(&packet).await?;
+
+            let mut messages = vec![];
+            loop {
+                let message = read_backend_message(client).await?;
+                let tag = message.0;
+                messages.push(message);
+
+                if tag == b'Z' {
+                    return Ok(messages);
+                }
+            }
+        }
+
+        let tags = |messages: &[(u8, Vec<u8>)]| {
+            messages
+                .iter()
+                .map(|(tag, _)| *tag as char)
+                .collect::<String>()
+        };
+        let query = |alias: &str| {
+            format!(
+                "SELECT COUNT(*) AS {} FROM KibanaSampleDataEcommerce",
+                alias
+            )
+        };
+        let describe_unnamed_portal = [b'D', 0, 0, 0, 6, b'P', 0];
+        let execute_unnamed_portal = [b'E', 0, 0, 0, 9, 0, 0, 0, 0, 0];
+        let sync = [b'S', 0, 0, 0, 4];
+
+        // The second Parse replaces the unnamed statement without Close
+        let mut packet = parse_packet("", &query("first"));
+        packet.extend(parse_packet("", &query("second")));
+        packet.extend(bind_packet("", ""));
+        packet.extend(describe_unnamed_portal);
+        packet.extend(execute_unnamed_portal);
+        packet.extend(sync);
+        let messages = send(&mut client, packet).await?;
+        assert_eq!(tags(&messages), "112TDCZ");
+        assert!(String::from_utf8_lossy(&messages[3].1).contains("second"));
+
+        // Failed Parse destroys the previous unnamed statement too
+        let mut packet = parse_packet("", &query("first"));
+        packet.extend(sync);
+        assert_eq!(tags(&send(&mut client, packet).await?), "1Z");
+
+        let mut packet = parse_packet("", "SELEC 1");
+        packet.extend(sync);
+        assert_eq!(tags(&send(&mut client, packet).await?), "EZ");
+
+        let mut packet = bind_packet("", "");
+        packet.extend(sync);
+        assert_eq!(tags(&send(&mut client, packet).await?), "EZ");
+
+        // Simple query destroys the unnamed statement and portal
+        let mut packet = parse_packet("", &query("first"));
+        packet.extend(bind_packet("", ""));
+        packet.extend(sync);
+        assert_eq!(tags(&send(&mut client, packet).await?), "12Z");
+
+        let messages = send(&mut client, query_packet(&query("third"))).await?;
+        assert_eq!(tags(&messages), "TDCZ");
+
+        let mut packet = execute_unnamed_portal.to_vec();
+        packet.extend(sync);
+        let messages = send(&mut client, packet).await?;
+        assert_eq!(tags(&messages), "EZ");
+        assert!(String::from_utf8_lossy(&messages[0].1).contains("34000"));
+
+        let mut packet = bind_packet("", "");
+        packet.extend(sync);
+        assert_eq!(tags(&send(&mut client, packet).await?), "EZ");
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        let (shim, result) = handle.await.unwrap();
+        result?;
+        assert_eq!(shim.session.state.extended_state_counts(), (0, 0));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extended_set_applied_by_execute() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+        let session = shim.session.clone();
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        async fn send(client: &mut TcpStream, packet: Vec<u8>) -> Result<String, Error> {
+            client.write_all(&packet).await?;
+
+            let mut tags = String::new();
+            loop {
+                let (tag, _) = read_backend_message(client).await?;
+                tags.push(tag as char);
+
+                if tag == b'Z' {
+                    return Ok(tags);
+                }
+            }
+        }
+
+        let extra_float_digits = || {
+            session
+                .state
+                .visible_variables(DatabaseVariables::new())
+                .get("extra_float_digits")
+                .map(|v| v.value.to_string())
+        };
+        let execute_portal = |name: &str| {
+            let mut packet = vec![b'E'];
+            packet.extend(((name.len() + 9) as u32).to_be_bytes());
+            packet.extend_from_slice(name.as_bytes());
+            packet.extend([0, 0, 0, 0, 0]);
+            packet
+        };
+        let sync = [b'S', 0, 0, 0, 4];
+
+        // Parse and Bind plan the statement without changing the session
+        let mut packet = parse_packet("s1", "SET extra_float_digits = 3");
+        packet.extend(bind_packet("p1", "s1"));
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "12Z");
+        assert_eq!(extra_float_digits(), Some("1".to_string()));
+
+        let mut packet = execute_portal("p1");
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "CZ");
+        assert_eq!(extra_float_digits(), Some("3".to_string()));
+
+        // RESET is applied by Execute as well
+        let mut packet = parse_packet("s2", "RESET extra_float_digits");
+        packet.extend(bind_packet("p2", "s2"));
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "12Z");
+        assert_eq!(extra_float_digits(), Some("3".to_string()));
+
+        let mut packet = execute_portal("p2");
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "CZ");
+        assert_eq!(extra_float_digits(), Some("1".to_string()));
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_flush_rows() -> Result<(), Error> {
+        let mut configuration = ServerConfiguration::default();
+        configuration.stream_flush_rows = 2;
+        let (shim, mut client) =
+            create_test_shim_with_transport(configuration, Arc::new(TestCubeTransport::default()))
+                .await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Tags of messages till ReadyForQuery and the last command tag
+        async fn send(client: &mut TcpStream, packet: Vec<u8>) -> Result<(String, String), Error> {
+            client.write_all(&packet).await?;
+
+            let mut tags = String::new();
+            let mut completion = String::new();
+            loop {
+                let (tag, body) = read_backend_message(client).await?;
+                if tag == b'Z' {
+                    return Ok((tags, completion));
+                }
+                if tag == b'C' {
+                    completion = String::from_utf8(body).unwrap().replace('\0', "");
+                }
+                tags.push(tag as char);
+            }
+        }
+
+        let query = "SELECT 1 AS n UNION ALL SELECT 2 UNION ALL SELECT 3 UNION ALL SELECT 4 \
+            UNION ALL SELECT 5";
+
+        // Rows are written by chunks of stream_flush_rows, all of them are returned
+        assert_eq!(
+            send(&mut client, query_packet(query)).await?,
+            ("TDDDDDC".to_string(), "SELECT 5".to_string())
+        );
+
+        // max_rows which is not a multiple of the chunk suspends the portal after max_rows
+        let execute_portal = |name: &str, max_rows: u32| {
+            let mut packet = vec![b'E'];
+            packet.extend(((name.len() + 9) as u32).to_be_bytes());
+            packet.extend_from_slice(name.as_bytes());
+            packet.push(0);
+            packet.extend(max_rows.to_be_bytes());
+            packet
+        };
+        let mut packet = parse_packet("s1", query);
+        packet.extend(bind_packet("p1", "s1"));
+        packet.extend(execute_portal("p1", 3));
+        packet.extend(execute_portal("p1", 0));
+        packet.extend([b'S', 0, 0, 0, 4]);
+        assert_eq!(
+            send(&mut client, packet).await?,
+            ("12DDDsDDC".to_string(), "SELECT 2".to_string())
+        );
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extended_sql_macro() -> Result<(), Error> {
+        let (shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+        let session = shim.session.clone();
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        async fn send(client: &mut TcpStream, packet: Vec<u8>) -> Result<String, Error> {
+            client.write_all(&packet).await?;
+
+            let mut tags = String::new();
+            loop {
+                let (tag, _) = read_backend_message(client).await?;
+                tags.push(tag as char);
+
+                if tag == b'Z' {
+                    return Ok(tags);
+                }
+            }
+        }
+
+        let execute_unnamed_portal = [b'E', 0, 0, 0, 9, 0, 0, 0, 0, 0];
+        let sync = [b'S', 0, 0, 0, 4];
+
+        // CREATE FUNCTION is recognized by Parse and applied by Execute
+        let mut packet = parse_packet(
+            "",
+            "CREATE FUNCTION gender_is(gender text, value text) RETURNS boolean AS $$ SELECT gender = value $$ LANGUAGE SQL",
+        );
+        packet.extend(bind_packet("", ""));
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "12Z");
+        assert!(session.state.sql_macros().is_empty());
+
+        let mut packet = execute_unnamed_portal.to_vec();
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "CZ");
+        assert!(session.state.sql_macros().contains_key("gender_is"));
+
+        // Function is available to the following statements
+        let mut packet = parse_packet(
+            "",
+            "SELECT COUNT(*) FROM KibanaSampleDataEcommerce WHERE gender_is(customer_gender, 'female')",
+        );
+        packet.extend(bind_packet("", ""));
+        packet.extend(execute_unnamed_portal);
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "12DCZ");
+
+        let mut packet = parse_packet("", "DROP FUNCTION gender_is(text, text)");
+        packet.extend(bind_packet("", ""));
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "12Z");
+        assert!(session.state.sql_macros().contains_key("gender_is"));
+
+        let mut packet = execute_unnamed_portal.to_vec();
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "CZ");
+        assert!(session.state.sql_macros().is_empty());
+
+        // Function doesn't exist at the time of execution
+        let mut packet = parse_packet("", "DROP FUNCTION gender_is(text, text)");
+        packet.extend(bind_packet("", ""));
+        packet.extend(execute_unnamed_portal);
+        packet.extend(sync);
+        assert_eq!(send(&mut client, packet).await?, "12EZ");
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_portal_suspended_max_rows() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+
+        let rows = (0..5)
+            .map(|i| Row::new(vec![TableValue::Int64(i)]))
+            .collect();
+        let frame = DataFrame::new(
+            vec![Column::new(
+                "id".to_string(),
+                ColumnType::Int64,
+                ColumnFlags::empty(),
+            )],
+            rows,
+        );
+
+        shim.portals.insert(
+            "p1".to_string(),
+            Some(Portal::new(
+                QueryPlan::MetaTabular(StatusFlags::empty(), Box::new(frame)),
+                vec![Format::Text],
+                None,
+            )),
+        );
+
+        let mut tags = vec![];
+        for _ in 0..3 {
+            shim.execute(protocol::Execute {
+                portal: "p1".to_string(),
+                max_rows: 2,
+            })
+            .await?;
+
+            loop {
+                let (tag, body) = read_backend_message(&mut client).await?;
+                tags.push(tag);
+
+                match tag {
+                    b's' => break,
+                    b'C' => {
+                        // Rows which were returned by the last Execute
+                        assert_eq!(body, b"SELECT 1\0".to_vec());
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        assert_eq!(tags, b"DDsDDsDC".to_vec());
+
+        // Portal is released by Sync after completion
+        assert!(shim.portals.contains_key("p1"));
+        shim.sync().await?;
+        assert!(!shim.portals.contains_key("p1"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_portal_suspended_across_sync() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+
+        let rows = (0..5)
+            .map(|i| Row::new(vec![TableValue::Int64(i)]))
+            .collect();
+        let frame = DataFrame::new(
+            vec![Column::new(
+                "id".to_string(),
+                ColumnType::Int64,
+                ColumnFlags::empty(),
+            )],
+            rows,
+        );
+
+        shim.portals.insert(
+            "p1".to_string(),
+            Some(Portal::new(
+                QueryPlan::MetaTabular(StatusFlags::empty(), Box::new(frame)),
+                vec![Format::Text],
+                None,
+            )),
+        );
+
+        // Portals outlive Sync only in a transaction block
+        shim.session
+            .state
+            .set_transaction_state(TransactionState::Active);
+
+        // Every chunk is requested in its own Execute + Sync cycle
+        let mut ids = vec![];
+        let mut tags = vec![];
+        for _ in 0..3 {
+            shim.execute(protocol::Execute {
+                portal: "p1".to_string(),
+                max_rows: 2,
+            })
+            .await?;
+            shim.sync().await?;
+
+            loop {
+                let (tag, body) = read_backend_message(&mut client).await?;
+                tags.push(tag);
+
+                match tag {
+                    b'D' => ids.push(body[6..].to_vec()),
+                    b'Z' => break,
+                    _ => {}
+                }
+            }
+        }
+
+        assert_eq!(tags, b"DDsZDDsZDCZ".to_vec());
+        assert_eq!(
+            ids,
+            vec![
+                b"0".to_vec(),
+                b"1".to_vec(),
+                b"2".to_vec(),
+                b"3".to_vec(),
+                b"4".to_vec()
+            ]
+        );
+
+        // Drained portal was released by the last Sync
+        assert!(!shim.portals.contains_key("p1"));
+        shim.execute(protocol::Execute {
+            portal: "p1".to_string(),
+            max_rows: 2,
+        })
+        .await?;
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        assert!(String::from_utf8_lossy(&body).contains("34000"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_portal_suspended_stream_resume() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+
+        // 1000 rows in 4 batches
+        let ctx = DFSessionContext::new();
+        let plan = ctx
+            .read_table(Arc::new(InfoSchemaTestingDatasetProvider::new(4, 250)))
+            .unwrap()
+            .to_logical_plan();
+
+        shim.portals.insert(
+            "p1".to_string(),
+            Some(Portal::new(
+                QueryPlan::DataFusionSelect(StatusFlags::empty(), plan, ctx),
+                vec![Format::Text],
+                None,
+            )),
+        );
+
+        let mut ids = vec![];
+        for _ in 0..10 {
+            shim.execute(protocol::Execute {
+                portal: "p1".to_string(),
+                max_rows: 100,
+            })
+            .await?;
+
+            let mut rows = 0;
+            loop {
+                let (tag, body) = read_backend_message(&mut client).await?;
+                match tag {
+                    b'D' => {
+                        rows += 1;
+
+                        let length = i32::from_be_bytes(body[2..6].try_into().unwrap()) as usize;
+                        let id = String::from_utf8(body[6..6 + length].to_vec()).unwrap();
+                        ids.push(id.parse::<u32>().unwrap());
+                    }
+                    b's' => break,
+                    tag => panic!("Unexpected message: {}", tag as char),
+                }
+            }
+
+            assert_eq!(rows, 100);
+        }
+
+        // Each Execute continues from the row where the previous one stopped
+        assert_eq!(ids, (0..1000).collect::<Vec<u32>>());
+
+        // All rows were consumed by the previous Execute
+        shim.execute(protocol::Execute {
+            portal: "p1".to_string(),
+            max_rows: 100,
+        })
+        .await?;
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'C');
+        assert_eq!(body, b"SELECT 0\0".to_vec());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_portal_per_column_formats() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim().await?;
+
+        let frame = DataFrame::new(
+            vec![
+                Column::new("id".to_string(), ColumnType::Int64, ColumnFlags::empty()),
+                Column::new("name".to_string(), ColumnType::String, ColumnFlags::empty()),
+            ],
+            vec![Row::new(vec![
+                TableValue::Int64(1),
+                TableValue::String("ab".to_string()),
+            ])],
+        );
+        let description = protocol::RowDescription::new(vec![
+            protocol::RowDescriptionField::new(
+                "id".to_string(),
+                PgType::get_by_tid(PgTypeId::INT8),
+            ),
+            protocol::RowDescriptionField::new(
+                "name".to_string(),
+                PgType::get_by_tid(PgTypeId::VARCHAR),
+            ),
+        ]);
+
+        shim.portals.insert(
+            "p1".to_string(),
+            Some(Portal::new(
+                QueryPlan::MetaTabular(StatusFlags::empty(), Box::new(frame)),
+                vec![Format::Binary, Format::Text],
+                Some(description),
+            )),
+        );
+
+        shim.describe_portal("p1".to_string()).await?;
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'T');
+        assert_eq!(
+            body,
+            [
+                vec![0, 2],
+                // id: int8, binary
+                b"id\0".to_vec(),
+                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 8, 255, 255, 255, 255, 0, 1],
+                // name: varchar, text
+                b"name\0".to_vec(),
+                vec![0, 0, 0, 0, 0, 0, 0, 0, 4, 19, 255, 255, 255, 255, 255, 255, 0, 0],
+            ]
+            .concat()
+        );
+
+        shim.execute(protocol::Execute {
+            portal: "p1".to_string(),
+            max_rows: 0,
+        })
+        .await?;
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'D');
+        assert_eq!(
+            body,
+            vec![0, 2, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 97, 98]
+        );
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'C');
+        assert_eq!(body, b"SELECT 1\0".to_vec());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bind_result_formats() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        shim.parse(protocol::Parse {
+            name: "s1".to_string(),
+            query: "SELECT 1::int8 AS a, 'ab' AS b".to_string(),
+            param_types: vec![],
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'1');
+
+        // Number of formats must match the number of columns
+        shim.bind(protocol::Bind {
+            portal: "p1".to_string(),
+            statement: "s1".to_string(),
+            parameter_formats: vec![],
+            parameter_values: vec![],
+            result_formats: vec![Format::Binary, Format::Text, Format::Text],
+        })
+        .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        assert!(String::from_utf8_lossy(&body).contains("08P01"));
+        assert!(shim.portals.get("p1").is_none());
+        shim.sync().await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+
+        // The first column is binary, the second one is text
+        shim.bind(protocol::Bind {
+            portal: "p1".to_string(),
+            statement: "s1".to_string(),
+            parameter_formats: vec![],
+            parameter_values: vec![],
+            result_formats: vec![Format::Binary, Format::Text],
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'2');
+        assert_eq!(
+            shim.portals
+                .get("p1")
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .get_formats(),
+            vec![Format::Binary, Format::Text]
+        );
+
+        shim.execute(protocol::Execute {
+            portal: "p1".to_string(),
+            max_rows: 0,
+        })
+        .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'D');
+        assert_eq!(
+            body,
+            vec![0, 2, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 97, 98]
+        );
+
+        // Single format is resolved for all columns
+        shim.bind(protocol::Bind {
+            portal: "p2".to_string(),
+            statement: "s1".to_string(),
+            parameter_formats: vec![],
+            parameter_values: vec![],
+            result_formats: vec![Format::Binary],
+        })
+        .await?;
+        assert_eq!(
+            shim.portals
+                .get("p2")
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .get_formats(),
+            vec![Format::Binary, Format::Binary]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bind_parameter_values_count() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        shim.parse(protocol::Parse {
+            name: "s1".to_string(),
+            query: "SELECT $1 AS a, $2 AS b".to_string(),
+            param_types: vec![],
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'1');
+
+        // Number of values must match the number of parameters
+        for values in [vec![Some(b"1".to_vec())], vec![Some(b"1".to_vec()); 3]] {
+            shim.bind(protocol::Bind {
+                portal: "p1".to_string(),
+                statement: "s1".to_string(),
+                parameter_formats: vec![],
+                parameter_values: values,
+                result_formats: vec![],
+            })
+            .await?;
+            let (tag, body) = read_backend_message(&mut client).await?;
+            assert_eq!(tag, b'E');
+            let body = String::from_utf8_lossy(&body).to_string();
+            assert!(body.contains("08P01"));
+            assert!(body.contains("prepared statement \"s1\" requires 2"));
+            assert!(shim.portals.get("p1").is_none());
+            shim.sync().await?;
+            assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+        }
+
+        shim.bind(protocol::Bind {
+            portal: "p1".to_string(),
+            statement: "s1".to_string(),
+            parameter_formats: vec![],
+            parameter_values: vec![Some(b"1".to_vec()), Some(b"2".to_vec())],
+            result_formats: vec![],
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'2');
+        assert!(shim.portals.get("p1").is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_null_binary_result_format() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        shim.parse(protocol::Parse {
+            name: "s1".to_string(),
+            query: "SELECT NULL AS n, CASE WHEN 1 = 1 THEN NULL ELSE 1 END AS i".to_string(),
+            param_types: vec![],
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'1');
+
+        shim.bind(protocol::Bind {
+            portal: "p1".to_string(),
+            statement: "s1".to_string(),
+            parameter_formats: vec![],
+            parameter_values: vec![],
+            result_formats: vec![Format::Binary],
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'2');
+
+        shim.describe_portal("p1".to_string()).await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'T');
+        assert_eq!(
+            body,
+            [
+                vec![0, 2],
+                // n: text, binary
+                b"n\0".to_vec(),
+                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 25, 255, 255, 255, 255, 255, 255, 0, 1],
+                // i: int8 from the ELSE branch, binary
+                b"i\0".to_vec(),
+                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 8, 255, 255, 255, 255, 0, 1],
+            ]
+            .concat()
+        );
+
+        shim.execute(protocol::Execute {
+            portal: "p1".to_string(),
+            max_rows: 0,
+        })
+        .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'D');
+        assert_eq!(body, vec![0, 2, 255, 255, 255, 255, 255, 255, 255, 255]);
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'C');
+        assert_eq!(body, b"SELECT 1\0".to_vec());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_token_auth() -> Result<(), Error> {
+        let mut configuration = ServerConfiguration::default();
+        configuration.token_auth = true;
+
+        let (mut shim, mut client) = create_test_shim_with_config(configuration).await?;
+        let parameters = HashMap::from([("user".to_string(), "test".to_string())]);
+
+        // Password of the user isn't a valid token
+        assert!(
+            !shim
+                .authenticate(
+                    protocol::PasswordMessage {
+                        password: "password".to_string(),
+                    },
+                    parameters.clone(),
+                )
+                .await?
+        );
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        assert!(String::from_utf8_lossy(&body).contains("28P01"));
+        assert!(shim.session.state.auth_context().is_none());
+
+        assert!(
+            shim.authenticate(
+                protocol::PasswordMessage {
+                    password: "header.payload.signature".to_string(),
+                },
+                parameters,
+            )
+            .await?
+        );
+        assert_eq!(read_backend_message(&mut client).await?.0, b'R');
+
+        let auth_context = shim.session.state.auth_context().unwrap();
+        assert_eq!(auth_context.access_token, "header.payload.signature");
+        assert_eq!(
+            auth_context.claims,
+            Some(serde_json::json!({ "sub": "test" }))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_default_result_format_by_application_name() -> Result<(), Error> {
+        let mut configuration = ServerConfiguration::default();
+        configuration.default_result_formats = vec![
+            ("Npgsql".to_string(), Format::Binary),
+            ("psql".to_string(), Format::Text),
+        ];
+
+        let (mut shim, mut client) =
+            create_test_shim_with_transport(configuration, Arc::new(TestCubeTransport::default()))
+                .await?;
+
+        let parameters = HashMap::from([
+            ("user".to_string(), "test".to_string()),
+            ("application_name".to_string(), "npgsql 6.0".to_string()),
+        ]);
+        assert!(
+            shim.authenticate(
+                protocol::PasswordMessage {
+                    password: "password".to_string(),
+                },
+                parameters,
+            )
+            .await?
+        );
+        assert_eq!(read_backend_message(&mut client).await?.0, b'R');
+        assert_eq!(shim.default_result_format, Format::Binary);
+
+        shim.parse(protocol::Parse {
+            name: "s1".to_string(),
+            query: "SELECT 1::int8 AS a, 'ab' AS b".to_string(),
+            param_types: vec![],
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'1');
+
+        // Formats which are requested by Bind are preferred
+        for (result_formats, expected) in vec![
+            (vec![], vec![Format::Binary, Format::Binary]),
+            (vec![Format::Text], vec![Format::Text, Format::Text]),
+        ] {
+            shim.bind(protocol::Bind {
+                portal: "p1".to_string(),
+                statement: "s1".to_string(),
+                parameter_formats: vec![],
+                parameter_values: vec![],
+                result_formats,
+            })
+            .await?;
+            assert_eq!(read_backend_message(&mut client).await?.0, b'2');
+            assert_eq!(
+                shim.portals
+                    .get("p1")
+                    .unwrap()
+                    .as_ref()
+                    .unwrap()
+                    .get_formats(),
+                expected
+            );
+        }
+
+        let formats = vec![("Npgsql".to_string(), Format::Binary)];
+        for application_name in vec!["psql", "PostgreSQL JDBC Driver"] {
+            let parameters =
+                HashMap::from([("application_name".to_string(), application_name.to_string())]);
+            assert_eq!(default_result_format(&formats, &parameters), Format::Text);
+        }
+        assert_eq!(
+            default_result_format(&formats, &HashMap::new()),
+            Format::Text
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_default_result_format_npgsql() -> Result<(), Error> {
+        let mut configuration = ServerConfiguration::default();
+        configuration.default_result_formats = vec![("Npgsql".to_string(), Format::Binary)];
+        let (shim, mut client) =
+            create_test_shim_with_transport(configuration, Arc::new(TestCubeTransport::default()))
+                .await?;
+
+        let mut packet =
+            startup_message_packet_with(&[("user", "test"), ("application_name", "Npgsql 6.0")]);
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Bind doesn't request result formats, like Npgsql
+        let mut packet = parse_packet("s1", "SELECT 1::int8 AS a, 'ab' AS b");
+        packet.extend(bind_packet("p1", "s1"));
+        packet.extend([b'D', 0, 0, 0, 8, b'P', b'p', b'1', 0]);
+        packet.extend([b'E', 0, 0, 0, 11, b'p', b'1', 0, 0, 0, 0, 0]);
+        packet.extend([b'S', 0, 0, 0, 4]);
+        client.write_all(&packet).await?;
+
+        assert_eq!(read_backend_message(&mut client).await?.0, b'1');
+        assert_eq!(read_backend_message(&mut client).await?.0, b'2');
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'T');
+        assert_eq!(
+            body,
+            [
+                vec![0, 2],
+                // a: int8, binary
+                b"a\0".to_vec(),
+                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 8, 255, 255, 255, 255, 0, 1],
+                // b: text, binary
+                b"b\0".to_vec(),
+                vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 25, 255, 255, 255, 255, 255, 255, 0, 1],
+            ]
+            .concat()
+        );
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'D');
+        assert_eq!(
+            body,
+            vec![0, 2, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 97, 98]
+        );
+
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'C');
+        assert_eq!(body, b"SELECT 1\0".to_vec());
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+
+        client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    /// Runs Parse, Bind and Execute and returns messages after BindComplete till CommandComplete
+    async fn run_extended_query(
+        shim: &mut AsyncPostgresShim,
+        client: &mut TcpStream,
+        query: &str,
+        param_types: Vec<PgTypeId>,
+        parameter_formats: Vec<Format>,
+        parameter_values: Vec<Option<Vec<u8>>>,
+    ) -> Result<Vec<(u8, Vec<u8>)>, Error> {
+        shim.parse(protocol::Parse {
+            name: "s1".to_string(),
+            query: query.to_string(),
+            param_types: param_types.into_iter().map(|typ| typ as u32).collect(),
+        })
+        .await?;
+        assert_eq!(read_backend_message(client).await?.0, b'1');
+
+        shim.bind(protocol::Bind {
+            portal: "p1".to_string(),
+            statement: "s1".to_string(),
+            parameter_formats,
+            parameter_values,
+            result_formats: vec![Format::Text],
+        })
+        .await?;
+        assert_eq!(read_backend_message(client).await?.0, b'2');
+
+        shim.execute(protocol::Execute {
+            portal: "p1".to_string(),
+            max_rows: 0,
+        })
+        .await?;
+
+        let mut messages = vec![];
+        loop {
+            let (tag, body) = read_backend_message(client).await?;
+            messages.push((tag, body));
+
+            if tag == b'C' || tag == b'E' {
+                return Ok(messages);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extended_query_bind_values() -> Result<(), Error> {
+        let transport = Arc::new(TestCubeTransport::default());
+        let (mut shim, mut client) =
+            create_test_shim_with_transport(ServerConfiguration::default(), transport.clone())
+                .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        let messages = run_extended_query(
+            &mut shim,
+            &mut client,
+            "SELECT $1 AS a, $2 AS b, $3 AS c, $4 AS d",
+            vec![
+                PgTypeId::INT8,
+                PgTypeId::TEXT,
+                PgTypeId::TEXT,
+                PgTypeId::FLOAT8,
+            ],
+            vec![Format::Text, Format::Text, Format::Text, Format::Binary],
+            vec![
+                Some(b"-5".to_vec()),
+                Some(b"it's".to_vec()),
+                None,
+                Some((-2.5_f64).to_be_bytes().to_vec()),
+            ],
+        )
+        .await?;
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    b'D',
+                    [
+                        vec![0, 4],
+                        vec![0, 0, 0, 2],
+                        b"-5".to_vec(),
+                        vec![0, 0, 0, 4],
+                        b"it's".to_vec(),
+                        vec![255, 255, 255, 255],
+                        vec![0, 0, 0, 4],
+                        b"-2.5".to_vec(),
+                    ]
+                    .concat()
+                ),
+                (b'C', b"SELECT 1\0".to_vec()),
+            ]
+        );
+
+        let messages = run_extended_query(
+            &mut shim,
+            &mut client,
+            "SELECT COUNT(*) FROM KibanaSampleDataEcommerce WHERE customer_gender = $1 AND taxful_total_price > $2",
+            vec![PgTypeId::TEXT, PgTypeId::INT8],
+            vec![Format::Text, Format::Binary],
+            vec![
+                Some(b"it's".to_vec()),
+                Some((-10_i64).to_be_bytes().to_vec()),
+            ],
+        )
+        .await?;
+        assert_eq!(messages.last().unwrap(), &(b'C', b"SELECT 1\0".to_vec()));
+
+        let queries = transport.queries.lock().unwrap();
+        assert_eq!(queries.len(), 1);
+        let filters = queries[0]
+            .filters
+            .clone()
+            .unwrap()
+            .into_iter()
+            .map(|filter| {
+                (
+                    filter.member.unwrap(),
+                    filter.operator.unwrap(),
+                    filter.values.unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            filters,
+            vec![
+                (
+                    "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                    "equals".to_string(),
+                    vec!["it's".to_string()]
+                ),
+                (
+                    "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+                    "gt".to_string(),
+                    vec!["-10".to_string()]
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sql_prepare_and_execute() -> Result<(), Error> {
+        let transport = Arc::new(TestCubeTransport::default());
+        let (mut shim, mut client) =
+            create_test_shim_with_transport(ServerConfiguration::default(), transport.clone())
+                .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        // Messages till ReadyForQuery
+        async fn query(
+            shim: &mut AsyncPostgresShim,
+            client: &mut TcpStream,
+            query: &str,
+        ) -> Result<Vec<(u8, Vec<u8>)>, Error> {
+            shim.process_query(query.to_string()).await?;
+
+            let mut messages = vec![];
+            loop {
+                let (tag, body) = read_backend_message(client).await?;
+                if tag == b'Z' {
+                    return Ok(messages);
+                }
+                messages.push((tag, body));
+            }
+        }
+
+        let messages = query(
+            &mut shim,
+            &mut client,
+            "PREPARE s1 (text, int8) AS SELECT COUNT(*) FROM KibanaSampleDataEcommerce WHERE customer_gender = $1 AND taxful_total_price > $2",
+        )
+        .await?;
+        assert_eq!(messages, vec![(b'C', b"PREPARE\0".to_vec())]);
+        assert!(shim.statements.contains_key("s1"));
+
+        let messages = query(&mut shim, &mut client, "EXECUTE s1 ('female', -10)").await?;
+        assert_eq!(
+            messages.iter().map(|(tag, _)| *tag).collect::<Vec<_>>(),
+            b"TDC".to_vec()
+        );
+        assert_eq!(messages.last().unwrap(), &(b'C', b"SELECT 1\0".to_vec()));
+
+        {
+            let queries = transport.queries.lock().unwrap();
+            assert_eq!(queries.len(), 1);
+            let values = queries[0]
+                .filters
+                .clone()
+                .unwrap()
+                .into_iter()
+                .map(|filter| filter.values.unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                values,
+                vec![vec!["female".to_string()], vec!["-10".to_string()]]
+            );
+        }
+
+        // Argument is decoded by the type of parameter
+        let messages = query(&mut shim, &mut client, "EXECUTE s1 ('female', 'abc')").await?;
+        assert_eq!(messages[0].0, b'E');
+
+        // Every parameter must have an argument
+        let messages = query(&mut shim, &mut client, "EXECUTE s1 ('female')").await?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, b'E');
+        assert!(String::from_utf8_lossy(&messages[0].1).contains("42601"));
+
+        let messages = query(&mut shim, &mut client, "EXECUTE s2 ('female', 1)").await?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, b'E');
+        assert!(String::from_utf8_lossy(&messages[0].1).contains("26000"));
+
+        // Named statement cannot be redefined
+        let messages = query(&mut shim, &mut client, "PREPARE s1 AS SELECT 1").await?;
+        assert_eq!(messages[0].0, b'E');
+        assert!(String::from_utf8_lossy(&messages[0].1).contains("42P05"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_to_stdout() -> Result<(), Error> {
+        let transport = Arc::new(TestCubeTransport::default());
+        let (mut shim, mut client) =
+            create_test_shim_with_transport(ServerConfiguration::default(), transport.clone())
+                .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        // Messages till ReadyForQuery
+        async fn query(
+            shim: &mut AsyncPostgresShim,
+            client: &mut TcpStream,
+            query: &str,
+        ) -> Result<Vec<(u8, Vec<u8>)>, Error> {
+            shim.process_query(query.to_string()).await?;
+
+            let mut messages = vec![];
+            loop {
+                let (tag, body) = read_backend_message(client).await?;
+                if tag == b'Z' {
+                    return Ok(messages);
+                }
+                messages.push((tag, body));
+            }
+        }
+
+        let messages = query(
+            &mut shim,
+            &mut client,
+            "COPY (SELECT 1 AS id, 'a,\"b\"' AS str, NULL AS empty, true AS flag, 1.5 AS num) TO STDOUT WITH (FORMAT csv, HEADER true)",
+        )
+        .await?;
+        assert_eq!(
+            messages,
+            vec![
+                (b'H', vec![0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+                (b'd', b"id,str,empty,flag,num\n".to_vec()),
+                (b'd', b"1,\"a,\"\"b\"\"\",,t,1.5\n".to_vec()),
+                (b'c', vec![]),
+                (b'C', b"COPY 1\0".to_vec()),
+            ]
+        );
+
+        // Legacy options of psql, rows are loaded from Cube
+        let messages = query(
+            &mut shim,
+            &mut client,
+            "COPY (SELECT COUNT(*) FROM KibanaSampleDataEcommerce) TO STDOUT CSV",
+        )
+        .await?;
+        assert_eq!(
+            messages,
+            vec![
+                (b'H', vec![0, 0, 1, 0, 0]),
+                (b'd', b"3\n".to_vec()),
+                (b'c', vec![]),
+                (b'C', b"COPY 1\0".to_vec()),
+            ]
+        );
+        assert_eq!(transport.queries.lock().unwrap().len(), 1);
+
+        // Text format by default
+        let messages = query(
+            &mut shim,
+            &mut client,
+            "COPY (SELECT 'a\tb' AS str, NULL AS empty) TO STDOUT;",
+        )
+        .await?;
+        assert_eq!(messages[1], (b'd', b"a\\tb\t\\N\n".to_vec()));
+
+        let messages = query(
+            &mut shim,
+            &mut client,
+            "COPY (SELECT 1) TO STDOUT WITH (FORMAT binary)",
+        )
+        .await?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, b'E');
+        assert!(String::from_utf8_lossy(&messages[0].1).contains("0A000"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_planning_warnings_are_notices() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        shim.process_query("SET unknown_parameter TO 1".to_string())
+            .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'N');
+        let body = String::from_utf8_lossy(&body).to_string();
+        assert!(body.contains("SWARNING\0"));
+        assert!(body.contains("C01000\0"));
+        assert!(body.contains("unrecognized configuration parameter \"unknown_parameter\""));
+        assert_eq!(read_backend_message(&mut client).await?.0, b'C');
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+
+        // Warnings of the session change are reported once, by the execution
+        shim.parse(protocol::Parse {
+            name: "s1".to_string(),
+            query: "SET LOCAL statement_timeout = 0".to_string(),
+            param_types: vec![],
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'1');
+
+        shim.bind(protocol::Bind {
+            portal: "p1".to_string(),
+            statement: "s1".to_string(),
+            parameter_formats: vec![],
+            parameter_values: vec![],
+            result_formats: vec![],
+        })
+        .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'2');
+
+        shim.execute(protocol::Execute {
+            portal: "p1".to_string(),
+            max_rows: 0,
+        })
+        .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'N');
+        assert!(String::from_utf8_lossy(&body).contains("SET LOCAL can only be used"));
+        assert_eq!(read_backend_message(&mut client).await?.0, b'C');
+        assert!(shim.session.state.take_warnings().is_empty());
+
+        // Query without warnings doesn't send notices
+        shim.process_query("SET statement_timeout = 0".to_string())
+            .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'C');
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_measure_only_query() -> Result<(), Error> {
+        let transport = Arc::new(TestCubeTransport::default());
+        let (mut shim, mut client) =
+            create_test_shim_with_transport(ServerConfiguration::default(), transport.clone())
+                .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        shim.process_query("SELECT COUNT(*) FROM KibanaSampleDataEcommerce".to_string())
+            .await?;
+
+        {
+            let queries = transport.queries.lock().unwrap();
+            assert_eq!(queries.len(), 1);
+            assert_eq!(
+                queries[0].measures,
+                Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+            );
+            assert_eq!(queries[0].dimensions, Some(vec![]));
+            assert_eq!(queries[0].time_dimensions, None);
+        }
+
+        // Single column of the measure: int8 in text format
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'T');
+        assert_eq!(&body[0..2], &[0, 1]);
+        let name_end = body[2..].iter().position(|b| *b == 0).unwrap() + 3;
+        assert_eq!(
+            body[name_end..].to_vec(),
+            vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 8, 255, 255, 255, 255, 0, 0]
+        );
+
+        // Exactly one row for the whole cube
+        assert_eq!(
+            read_backend_message(&mut client).await?,
+            (b'D', vec![0, 1, 0, 0, 0, 1, b'3'])
+        );
+        assert_eq!(
+            read_backend_message(&mut client).await?,
+            (b'C', b"SELECT 1\0".to_vec())
+        );
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_statement_timeout() -> Result<(), Error> {
+        let transport = Arc::new(TestCubeTransport {
+            load_delay: Some(Duration::from_millis(500)),
+            ..Default::default()
+        });
+        let (mut shim, mut client) =
+            create_test_shim_with_transport(ServerConfiguration::default(), transport.clone())
+                .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        shim.process_query("SET statement_timeout = '50ms'".to_string())
+            .await?;
+        loop {
+            if read_backend_message(&mut client).await?.0 == b'Z' {
+                break;
+            }
+        }
+
+        let started = Instant::now();
+        shim.process_query("SELECT COUNT(*) FROM KibanaSampleDataEcommerce".to_string())
+            .await?;
+        assert!(started.elapsed() < Duration::from_millis(400));
+
+        assert_eq!(read_backend_message(&mut client).await?.0, b'T');
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("57014"));
+        assert!(body.contains("canceling statement due to statement timeout"));
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+
+        // 0 disables the timeout
+        shim.process_query("SET statement_timeout = 0".to_string())
+            .await?;
+        loop {
+            if read_backend_message(&mut client).await?.0 == b'Z' {
+                break;
+            }
+        }
+
+        shim.process_query("SELECT COUNT(*) FROM KibanaSampleDataEcommerce".to_string())
+            .await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'T');
+        assert_eq!(
+            read_backend_message(&mut client).await?,
+            (b'D', vec![0, 1, 0, 0, 0, 1, b'3'])
+        );
+        assert_eq!(
+            read_backend_message(&mut client).await?,
+            (b'C', b"SELECT 1\0".to_vec())
+        );
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_sqlstate() -> Result<(), Error> {
+        let (mut shim, mut client) = create_test_shim_with_transport(
+            ServerConfiguration::default(),
+            Arc::new(TestCubeTransport::default()),
+        )
+        .await?;
+        shim.session.state.set_auth_context(Some(AuthContext {
+            access_token: "access_token".to_string(),
+            base_path: "base_path".to_string(),
+            claims: None,
+            superuser: false,
+        }));
+
+        for (query, code) in [
+            ("SELEC 1", "C42601\0"),
+            ("SELECT * FROM unknown_table", "C42P01\0"),
+        ] {
+            shim.process_query(query.to_string()).await?;
+            let (tag, body) = read_backend_message(&mut client).await?;
+            assert_eq!(tag, b'E', "{}", query);
+            assert!(String::from_utf8_lossy(&body).contains(code), "{}", query);
+            assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+        }
+
+        // Position of the error is reported for Parse too
+        shim.parse(protocol::Parse {
+            name: "".to_string(),
+            query: "SELECT 1 +".to_string(),
+            param_types: vec![],
+        })
+        .await?;
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert_eq!(tag, b'E');
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("C42601\0"), "{}", body);
+        assert!(body.ends_with("P11\0\0"), "{}", body);
+        assert!(shim.ignore_till_sync);
+
+        // Kind of CubeError is kept in io::Error, which is reported by the message loop
+        let err = Error::new(
+            ErrorKind::Other,
+            CubeError::user("test".to_string()).with_kind(CubeErrorKind::SyntaxError),
+        );
+        assert_eq!(
+            AsyncPostgresShim::io_error_code(&err).to_string(),
+            "42601".to_string()
+        );
+        assert_eq!(
+            AsyncPostgresShim::io_error_code(&Error::new(ErrorKind::Other, "unknown")).to_string(),
+            "XX000".to_string()
+        );
+
+        // Errors of planning by Parse and Bind have the same codes as in the simple query
+        for (err, code) in [
+            (
+                CompilationError::ProgramLimitExceeded("too many expressions".to_string()),
+                "54000",
+            ),
+            (
+                CompilationError::AmbiguousColumn(
+                    "column reference \"a\" is ambiguous".to_string(),
+                ),
+                "42702",
+            ),
+        ] {
+            assert_eq!(
+                AsyncPostgresShim::io_error_code(&AsyncPostgresShim::compilation_error(err))
+                    .to_string(),
+                code.to_string()
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_configure_socket_send_buffer_size() -> Result<(), Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let _client = TcpStream::connect(listener.local_addr()?).await?;
+        let (socket, _) = listener.accept().await?;
+
+        AsyncPostgresShim::configure_socket(&socket, None)?;
+        let default_size = SockRef::from(&socket).send_buffer_size()?;
+
+        let size = default_size * 2 + 65536;
+        AsyncPostgresShim::configure_socket(&socket, Some(size))?;
+        // Kernel may round the value up, e.g. Linux doubles it for bookkeeping
+        assert!(SockRef::from(&socket).send_buffer_size()? >= size);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() -> Result<(), Error> {
+        let mut configuration = ServerConfiguration::default();
+        configuration.connection_idle_timeout = Some(Duration::from_millis(200));
+        let (shim, mut client) = create_test_shim_with_config(configuration).await?;
+
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+
+        let handle = spawn_shim_run(shim);
+        read_handshake_tags(&mut client).await?;
+
+        // Flush has no response, but it resets the timer as any other message
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            client.write_all(&[b'H', 0, 0, 0, 4]).await?;
+        }
+        client.write_all(&[b'S', 0, 0, 0, 4]).await?;
+        assert_eq!(read_backend_message(&mut client).await?.0, b'Z');
+
+        let started = Instant::now();
+        let (tag, body) = read_backend_message(&mut client).await?;
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(tag, b'E');
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("FATAL"), "{}", body);
+        assert!(body.contains("57P01"), "{}", body);
+        assert!(
+            body.contains("terminating connection due to idle-session timeout"),
+            "{}",
+            body
+        );
+
+        handle.await.unwrap().1?;
+        // Connection is closed by the server
+        assert_eq!(client.read(&mut [0; 1]).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pg_terminate_backend() -> Result<(), Error> {
+        let transport = Arc::new(TestCubeTransport {
+            load_delay: Some(Duration::from_secs(5)),
+            ..Default::default()
+        });
+        let (admin_shim, mut admin_client) =
+            create_test_shim_with_transport(ServerConfiguration::default(), transport).await?;
+
+        // Other connections of the same server
+        let session_manager = admin_shim.session.session_manager.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut shims = vec![(admin_shim, "admin")];
+        let mut clients = vec![];
+        for _ in 0..2 {
+            clients.push(TcpStream::connect(listener.local_addr()?).await?);
+            let (socket, _) = listener.accept().await?;
+            let session = session_manager
+                .create_session(DatabaseProtocol::PostgreSQL, "127.0.0.1".to_string());
+            shims.push((
+                AsyncPostgresShim::new(PostgresStream::Plain(socket), session),
+                "test",
+            ));
+        }
+        let (mut idle_client, mut busy_client) = (clients.remove(0), clients.remove(0));
+        let admin_id = shims[0].0.session.state.connection_id;
+        let idle_id = shims[1].0.session.state.connection_id;
+        let busy_id = shims[2].0.session.state.connection_id;
+
+        let mut handles = vec![];
+        for ((shim, user), client) in
+            shims
+                .into_iter()
+                .zip([&mut admin_client, &mut idle_client, &mut busy_client])
+        {
+            let mut packet = startup_message_packet_with(&[("user", user)]);
+            packet.extend(password_message_packet("password"));
+            client.write_all(&packet).await?;
+
+            handles.push(spawn_shim_run(shim));
+            read_handshake_tags(client).await?;
+        }
+        let (admin_handle, idle_handle, busy_handle) =
+            (handles.remove(0), handles.remove(0), handles.remove(0));
+
+        // Messages till ReadyForQuery (exclusive)
+        async fn query(client: &mut TcpStream, query: &str) -> Result<Vec<(u8, Vec<u8>)>, Error> {
+            client.write_all(&query_packet(query)).await?;
+
+            let mut messages = vec![];
+            loop {
+                match read_backend_message(client).await? {
+                    (b'Z', _) => return Ok(messages),
+                    message => messages.push(message),
+                }
+            }
+        }
+
+        // FATAL error is the last message, then the connection is closed
+        async fn read_admin_shutdown(
+            client: &mut TcpStream,
+            handle: tokio::task::JoinHandle<(AsyncPostgresShim, Result<(), Error>)>,
+        ) -> Result<(), Error> {
+            let body = loop {
+                if let (b'E', body) = read_backend_message(client).await? {
+                    break String::from_utf8(body).unwrap();
+                }
+            };
+            assert!(body.contains("FATAL"), "{}", body);
+            assert!(body.contains("57P01"), "{}", body);
+
+            handle.await.unwrap().1?;
+            assert_eq!(client.read(&mut [0; 1]).await?, 0);
+
+            Ok(())
+        }
+
+        let terminate = |pid: u32| format!("SELECT pg_terminate_backend({})", pid);
+        let result_row = |value: u8| (b'D', vec![0, 1, 0, 0, 0, 1, value]);
+
+        // Only superuser is allowed to terminate sessions
+        let messages = query(&mut idle_client, &terminate(admin_id)).await?;
+        let (tag, body) = messages.last().unwrap();
+        assert_eq!(*tag, b'E');
+        assert!(String::from_utf8_lossy(body).contains("42501"));
+
+        // Idle session
+        let messages = query(&mut admin_client, &terminate(idle_id)).await?;
+        assert_eq!(messages[1], result_row(b't'));
+        read_admin_shutdown(&mut idle_client, idle_handle).await?;
+
+        // Session in the middle of the query
+        busy_client
+            .write_all(&query_packet(
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce",
+            ))
+            .await?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let started = Instant::now();
+        let messages = query(&mut admin_client, &terminate(busy_id)).await?;
+        assert_eq!(messages[1], result_row(b't'));
+        read_admin_shutdown(&mut busy_client, busy_handle).await?;
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // Unknown session
+        let messages = query(&mut admin_client, &terminate(u32::MAX)).await?;
+        assert_eq!(messages[1], result_row(b'f'));
+
+        admin_client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        admin_handle.await.unwrap().1?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown() -> Result<(), Error> {
+        let server = Arc::new(ServerManager {
+            auth: Arc::new(TestSqlAuth {}),
+            transport: Arc::new(TestCubeTransport {
+                load_delay: Some(Duration::from_secs(1)),
+                ..Default::default()
+            }),
+            configuration: ServerConfiguration::default(),
+            nonce: None,
+        });
+        // Free port for the server
+        let address = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let pg_server = PostgresServer::new(
+            address.to_string(),
+            None,
+            None,
+            Arc::new(SessionManager::new(server)),
+        );
+        let loop_handle = {
+            let pg_server = pg_server.clone();
+            tokio::spawn(async move { pg_server.processing_loop().await })
+        };
+
+        let mut client = loop {
+            match TcpStream::connect(address).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+        read_handshake_tags(&mut client).await?;
+
+        // Query is started before shutdown
+        client
+            .write_all(&query_packet(
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce",
+            ))
+            .await?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        pg_server.stop_processing().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // New connections are refused while the query is executing
+        let err = TcpStream::connect(address).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+
+        // Result of the query is sent, then the connection is closed
+        let mut tags = vec![];
+        let body = loop {
+            let (tag, body) = read_backend_message(&mut client).await?;
+            tags.push(tag);
+
+            if tag == b'E' {
+                break String::from_utf8(body).unwrap();
+            }
+        };
+        assert_eq!(tags, b"TDCZE");
+        assert!(body.contains("FATAL"), "{}", body);
+        assert!(body.contains("57P01"), "{}", body);
+        assert_eq!(client.read(&mut [0; 1]).await?, 0);
+
+        loop_handle.await.unwrap().unwrap();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_forced_shutdown() -> Result<(), Error> {
+        let mut configuration = ServerConfiguration::default();
+        configuration.shutdown_grace_period = Duration::from_millis(300);
+        let server = Arc::new(ServerManager {
+            auth: Arc::new(TestSqlAuth {}),
+            transport: Arc::new(TestCubeTransport {
+                load_delay: Some(Duration::from_secs(10)),
+                ..Default::default()
+            }),
+            configuration,
+            nonce: None,
+        });
+        let address = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let pg_server = PostgresServer::new(
+            address.to_string(),
+            None,
+            None,
+            Arc::new(SessionManager::new(server)),
+        );
+        let loop_handle = {
+            let pg_server = pg_server.clone();
+            tokio::spawn(async move { pg_server.processing_loop().await })
+        };
+
+        let mut client = loop {
+            match TcpStream::connect(address).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        let mut packet = startup_message_packet();
+        packet.extend(password_message_packet("password"));
+        client.write_all(&packet).await?;
+        read_handshake_tags(&mut client).await?;
+
+        // Query doesn't finish in the grace period
+        client
+            .write_all(&query_packet(
+                "SELECT COUNT(*) FROM KibanaSampleDataEcommerce",
+            ))
+            .await?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let started = Instant::now();
+        pg_server.stop_processing().await.unwrap();
+
+        // Server stops after the terminated connection is closed
+        loop_handle.await.unwrap().unwrap();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+
+        let body = loop {
+            if let (b'E', body) = read_backend_message(&mut client).await? {
+                break String::from_utf8(body).unwrap();
+            }
+        };
+        assert!(body.contains("FATAL"), "{}", body);
+        assert!(body.contains("57P01"), "{}", body);
+        assert_eq!(client.read(&mut [0; 1]).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_listen_notify_between_sessions() -> Result<(), Error> {
+        let (listener_shim, mut listener_client) = create_test_shim().await?;
+
+        // The second connection of the same server
+        let session_manager = listener_shim.session.session_manager.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut notifier_client = TcpStream::connect(listener.local_addr()?).await?;
+        let (socket, _) = listener.accept().await?;
+        let notifier_session =
+            session_manager.create_session(DatabaseProtocol::PostgreSQL, "127.0.0.1".to_string());
+        let notifier_id = notifier_session.state.connection_id;
+        let notifier_shim = AsyncPostgresShim::new(PostgresStream::Plain(socket), notifier_session);
+
+        let mut handles = vec![];
+        for (shim, client) in [
+            (listener_shim, &mut listener_client),
+            (notifier_shim, &mut notifier_client),
+        ] {
+            let mut packet = startup_message_packet();
+            packet.extend(password_message_packet("password"));
+            client.write_all(&packet).await?;
+
+            handles.push(spawn_shim_run(shim));
+            read_handshake_tags(client).await?;
+        }
+
+        // Tags of messages till ReadyForQuery (exclusive)
+        async fn query(client: &mut TcpStream, query: &str) -> Result<String, Error> {
+            client.write_all(&query_packet(query)).await?;
+
+            let mut tags = vec![];
+            loop {
+                match read_backend_message(client).await?.0 {
+                    b'Z' => return Ok(String::from_utf8(tags).unwrap()),
+                    tag => tags.push(tag),
+                }
+            }
+        }
+
+        assert_eq!(query(&mut listener_client, "LISTEN Orders").await?, "C");
+        assert_eq!(
+            query(&mut notifier_client, "NOTIFY orders, 'new'").await?,
+            "C"
+        );
+
+        // Idle listener receives the notification without sending anything
+        let (tag, body) = read_backend_message(&mut listener_client).await?;
+        assert_eq!(tag, b'A');
+        let mut expected = notifier_id.to_be_bytes().to_vec();
+        expected.extend_from_slice(b"orders\0new\0");
+        assert_eq!(body, expected);
+
+        // Notification of the own channel is sent before ReadyForQuery
+        assert_eq!(query(&mut listener_client, "NOTIFY orders").await?, "CA");
+
+        assert_eq!(query(&mut listener_client, "UNLISTEN *").await?, "C");
+        assert_eq!(
+            query(&mut notifier_client, "NOTIFY orders, 'new'").await?,
+            "C"
+        );
+        assert_eq!(query(&mut listener_client, "LISTEN other").await?, "C");
+
+        for client in [&mut listener_client, &mut notifier_client] {
+            client.write_all(&[b'X', 0, 0, 0, 4]).await?;
+        }
+        for handle in handles {
+            handle.await.unwrap().1?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Error, ErrorKind},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    sync::{Semaphore, SemaphorePermit},
+};
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+pub struct TlsConfig {
+    /// Deny connections which don't send SSLRequest
+    pub require_ssl: bool,
+    // Certificate and key are loaded once at start, so bad files fail the startup
+    acceptor: TlsAcceptor,
+    // Handshakes are CPU-intensive, excess connections wait for a permit
+    handshake_limit: Semaphore,
+    // Stalled clients must not hold permits forever
+    handshake_timeout: Duration,
+}
+
+impl TlsConfig {
+    /// Loads certificate chain and private key (PKCS#8 or RSA) from PEM files
+    pub fn new(
+        cert_path: &str,
+        key_path: &str,
+        max_concurrent_handshakes: usize,
+        handshake_timeout: Duration,
+        require_ssl: bool,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            require_ssl,
+            acceptor: Self::load_acceptor(cert_path, key_path)?,
+            handshake_limit: Semaphore::new(max_concurrent_handshakes),
+            handshake_timeout,
+        })
+    }
+
+    /// Permit is released when the handshake is finished or timed out
+    pub async fn accept(&self, socket: TcpStream) -> Result<TlsStream<TcpStream>, Error> {
+        let _permit = self.acquire_handshake_permit().await?;
+
+        match tokio::time::timeout(self.handshake_timeout, self.acceptor.accept(socket)).await {
+            Ok(res) => res,
+            Err(_) => Err(Error::new(
+                ErrorKind::TimedOut,
+                "TLS handshake timed out".to_string(),
+            )),
+        }
+    }
+
+    async fn acquire_handshake_permit(&self) -> Result<SemaphorePermit<'_>, Error> {
+        self.handshake_limit
+            .acquire()
+            .await
+            .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))
+    }
+
+    fn load_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, Error> {
+        let certs = Self::load_certs(cert_path)?;
+        let key = Self::load_key(key_path)?;
+
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err.to_string()))?;
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+
+    fn load_certs(cert_path: &str) -> Result<Vec<Certificate>, Error> {
+        let mut reader = BufReader::new(File::open(cert_path)?);
+        let certs = rustls_pemfile::certs(&mut reader)?;
+        if certs.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("No certificates found in {}", cert_path),
+            ));
+        }
+
+        Ok(certs.into_iter().map(Certificate).collect())
+    }
+
+    fn load_key(key_path: &str) -> Result<PrivateKey, Error> {
+        let mut reader = BufReader::new(File::open(key_path)?);
+
+        for item in rustls_pemfile::read_all(&mut reader)? {
+            match item {
+                rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) => {
+                    return Ok(PrivateKey(key))
+                }
+                _ => {}
+            }
+        }
+
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("No private key found in {}", key_path),
+        ))
+    }
+}
+
+/// Client connection, which becomes encrypted after successful SSLRequest negotiation
+pub enum PostgresStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl PostgresStream {
+    /// Resolves when the client closes the connection, data is not consumed from the stream
+    pub async fn closed(&self) {
+        let socket = match self {
+            PostgresStream::Plain(stream) => stream,
+            PostgresStream::Tls(stream) => stream.get_ref().0,
+        };
+
+        let mut buf = [0; 1];
+        match socket.peek(&mut buf).await {
+            Ok(0) | Err(_) => {}
+            // Client has already sent the next message, connection is alive
+            Ok(_) => futures::future::pending().await,
+        }
+    }
+}
+
+impl AsyncRead for PostgresStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            PostgresStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            PostgresStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for PostgresStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            PostgresStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            PostgresStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            PostgresStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            PostgresStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            PostgresStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            PostgresStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_stream_closed() -> Result<(), Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let client = TcpStream::connect(listener.local_addr()?).await?;
+        let (server, _) = listener.accept().await?;
+
+        let stream = PostgresStream::Plain(server);
+        let res = tokio::time::timeout(Duration::from_millis(100), stream.closed()).await;
+        assert!(res.is_err());
+
+        drop(client);
+
+        let res = tokio::time::timeout(Duration::from_millis(1000), stream.closed()).await;
+        assert!(res.is_ok());
+
+        Ok(())
+    }
+
+    pub(crate) fn test_tls_config(
+        max_concurrent_handshakes: usize,
+        handshake_timeout: Duration,
+        require_ssl: bool,
+    ) -> Result<TlsConfig, Error> {
+        TlsConfig::new(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/sql/postgres/test_data/cert.pem"
+            ),
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/sql/postgres/test_data/key.pem"
+            ),
+            max_concurrent_handshakes,
+            handshake_timeout,
+            require_ssl,
+        )
+    }
+
+    #[test]
+    fn test_load_certificate_and_key() -> Result<(), Error> {
+        test_tls_config(1, Duration::from_secs(10), false)?;
+
+        // Files are checked at start, not by the first handshake
+        let key_path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/sql/postgres/test_data/key.pem"
+        );
+        let err = TlsConfig::new("unknown.pem", key_path, 1, Duration::from_secs(10), false)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        // Key file doesn't contain certificates
+        let err = TlsConfig::new(key_path, key_path, 1, Duration::from_secs(10), false)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_limit_saturation() -> Result<(), Error> {
+        let config = Arc::new(test_tls_config(2, Duration::from_secs(10), false)?);
+
+        let first = config.acquire_handshake_permit().await?;
+        let _second = config.acquire_handshake_permit().await?;
+
+        let waiting_config = config.clone();
+        let mut waiting = tokio::spawn(async move {
+            let _permit = waiting_config.acquire_handshake_permit().await?;
+
+            Ok::<(), Error>(())
+        });
+
+        // All permits are taken, third handshake must wait
+        let res = tokio::time::timeout(Duration::from_millis(100), &mut waiting).await;
+        assert!(res.is_err());
+
+        drop(first);
+
+        let res = tokio::time::timeout(Duration::from_millis(1000), waiting).await;
+        assert!(matches!(res, Ok(Ok(Ok(())))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout_releases_permit() -> Result<(), Error> {
+        let config = test_tls_config(1, Duration::from_millis(200), false)?;
+
+        // Client opens the connection and never sends ClientHello
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let _stalled_client = TcpStream::connect(listener.local_addr()?).await?;
+        let (socket, _) = listener.accept().await?;
+
+        let res = tokio::time::timeout(Duration::from_secs(5), config.accept(socket)).await;
+        match res {
+            Ok(Err(err)) => assert_eq!(err.kind(), ErrorKind::TimedOut),
+            Ok(Ok(_)) => panic!("Handshake with stalled client must fail"),
+            Err(_) => panic!("Handshake with stalled client must time out"),
+        }
+
+        // The only permit is available again
+        let res = tokio::time::timeout(
+            Duration::from_millis(100),
+            config.acquire_handshake_permit(),
+        )
+        .await;
+        assert!(matches!(res, Ok(Ok(_))));
+
+        Ok(())
+    }
+}
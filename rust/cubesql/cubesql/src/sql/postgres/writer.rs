@@ -1,6 +1,7 @@
 use crate::arrow::array::{
     ArrayRef, BooleanArray, Float16Array, Float32Array, Float64Array, Int16Array, Int32Array,
-    Int64Array, Int8Array, StringArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array,
+    Int64Array, Int8Array, LargeStringArray, ListArray, StringArray, UInt16Array, UInt32Array,
+    UInt64Array, UInt8Array,
 };
 use crate::arrow::datatypes::DataType;
 use crate::sql::dataframe::TimestampValue;
@@ -11,7 +12,7 @@ use chrono::format::Pad::Zero;
 use chrono::format::{Fixed, Item};
 use chrono::prelude::*;
 use pg_srv::protocol::{Format, Serialize};
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
 use std::io;
 use std::io::Error;
 use std::mem;
@@ -134,6 +135,264 @@ impl ToPostgresValue for TimestampValue {
     }
 }
 
+fn pg_base_date() -> NaiveDate {
+    NaiveDate::from_ymd(2000, 1, 1)
+}
+
+impl ToPostgresValue for NaiveDate {
+    fn to_text(&self, buf: &mut BytesMut) -> io::Result<()> {
+        self.format("%Y-%m-%d").to_string().to_text(buf)
+    }
+
+    // Number of days since 2000-01-01
+    fn to_binary(&self, buf: &mut BytesMut) -> io::Result<()> {
+        let days = self.signed_duration_since(pg_base_date()).num_days();
+
+        buf.put_i32(4_i32);
+        buf.put_i32(days as i32);
+
+        Ok(())
+    }
+}
+
+impl ToPostgresValue for Vec<u8> {
+    // Hex format, for example: \x0aff
+    fn to_text(&self, buf: &mut BytesMut) -> io::Result<()> {
+        let mut as_str = String::with_capacity(2 + self.len() * 2);
+        as_str.push_str("\\x");
+        for byte in self.iter() {
+            as_str.push_str(&format!("{:02x}", byte));
+        }
+
+        as_str.to_text(buf)
+    }
+
+    fn to_binary(&self, buf: &mut BytesMut) -> io::Result<()> {
+        buf.put_i32(self.len() as i32);
+        buf.extend_from_slice(&self[..]);
+
+        Ok(())
+    }
+}
+
+/// Value for NUMERIC columns
+#[derive(Debug, Clone)]
+pub enum NumericValue {
+    Float(f64),
+    // Unscaled value and scale, as it's stored in DecimalArray
+    Decimal(i128, usize),
+}
+
+impl NumericValue {
+    fn to_decimal_string(&self) -> String {
+        match self {
+            NumericValue::Float(v) => v.to_string(),
+            NumericValue::Decimal(v, scale) => {
+                let digits = v.unsigned_abs().to_string();
+                let digits = if digits.len() <= *scale {
+                    format!("{}{}", "0".repeat(scale + 1 - digits.len()), digits)
+                } else {
+                    digits
+                };
+
+                let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+                let sign = if *v < 0 { "-" } else { "" };
+                if frac_part.is_empty() {
+                    format!("{}{}", sign, int_part)
+                } else {
+                    format!("{}{}.{}", sign, int_part, frac_part)
+                }
+            }
+        }
+    }
+}
+
+const NUMERIC_POS: u16 = 0x0000;
+const NUMERIC_NEG: u16 = 0x4000;
+const NUMERIC_NAN: u16 = 0xC000;
+const NUMERIC_PINF: u16 = 0xD000;
+const NUMERIC_NINF: u16 = 0xF000;
+
+impl ToPostgresValue for NumericValue {
+    fn to_text(&self, buf: &mut BytesMut) -> io::Result<()> {
+        match self {
+            NumericValue::Float(v) if v.is_nan() => "NaN".to_string().to_text(buf),
+            NumericValue::Float(v) if v.is_infinite() => if v.is_sign_positive() {
+                "Infinity"
+            } else {
+                "-Infinity"
+            }
+            .to_string()
+            .to_text(buf),
+            _ => self.to_decimal_string().to_text(buf),
+        }
+    }
+
+    // ndigits, weight, sign and dscale followed by base-10000 digits
+    fn to_binary(&self, buf: &mut BytesMut) -> io::Result<()> {
+        let special = match self {
+            NumericValue::Float(v) if v.is_nan() => Some(NUMERIC_NAN),
+            NumericValue::Float(v) if v.is_infinite() => Some(if v.is_sign_positive() {
+                NUMERIC_PINF
+            } else {
+                NUMERIC_NINF
+            }),
+            _ => None,
+        };
+        if let Some(sign) = special {
+            buf.put_i32(8_i32);
+            buf.put_i16(0);
+            buf.put_i16(0);
+            buf.put_u16(sign);
+            buf.put_u16(0);
+
+            return Ok(());
+        }
+
+        let as_str = self.to_decimal_string();
+        let (sign, unsigned) = match as_str.strip_prefix('-') {
+            Some(rest) => (NUMERIC_NEG, rest),
+            None => (NUMERIC_POS, as_str.as_str()),
+        };
+        let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let dscale = frac_part.len();
+
+        let int_part = int_part.trim_start_matches('0');
+        let int_padded = format!("{}{}", "0".repeat((4 - int_part.len() % 4) % 4), int_part);
+        let frac_padded = format!("{}{}", frac_part, "0".repeat((4 - frac_part.len() % 4) % 4));
+
+        let mut digits = int_padded
+            .as_bytes()
+            .chunks(4)
+            .chain(frac_padded.as_bytes().chunks(4))
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .fold(0_i16, |acc, digit| acc * 10 + (digit - b'0') as i16)
+            })
+            .collect::<Vec<i16>>();
+        let mut weight = (int_padded.len() / 4) as i16 - 1;
+
+        let leading_zeros = digits.iter().take_while(|digit| **digit == 0).count();
+        digits.drain(0..leading_zeros);
+        weight -= leading_zeros as i16;
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        let (weight, sign) = if digits.is_empty() {
+            (0, NUMERIC_POS)
+        } else {
+            (weight, sign)
+        };
+
+        buf.put_i32(8 + digits.len() as i32 * 2);
+        buf.put_i16(digits.len() as i16);
+        buf.put_i16(weight);
+        buf.put_u16(sign);
+        buf.put_u16(dscale as u16);
+        for digit in digits {
+            buf.put_i16(digit);
+        }
+
+        Ok(())
+    }
+}
+
+/// Interval as PostgreSQL stores it: months, days and microseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntervalValue {
+    pub months: i32,
+    pub days: i32,
+    pub microseconds: i64,
+}
+
+impl IntervalValue {
+    pub fn new(months: i32, days: i32, microseconds: i64) -> Self {
+        Self {
+            months,
+            days,
+            microseconds,
+        }
+    }
+
+    /// Arrow IntervalYearMonth: number of months
+    pub fn from_year_month(value: i32) -> Self {
+        Self::new(value, 0, 0)
+    }
+
+    /// Arrow IntervalDayTime: days in the upper 32 bits, milliseconds in the lower 32 bits
+    pub fn from_day_time(value: i64) -> Self {
+        let days = (value >> 32) as i32;
+        let milliseconds = value as i32;
+
+        Self::new(0, days, milliseconds as i64 * 1000)
+    }
+
+    /// Arrow IntervalMonthDayNano: months, days and nanoseconds from the upper bits
+    pub fn from_month_day_nano(value: i128) -> Self {
+        let months = (value >> 96) as i32;
+        let days = (value >> 64) as i32;
+        let nanoseconds = value as i64;
+
+        Self::new(months, days, nanoseconds / 1000)
+    }
+}
+
+impl ToPostgresValue for IntervalValue {
+    // IntervalStyle postgres, for example: 1 year 2 mons 3 days 04:05:06.5
+    fn to_text(&self, buf: &mut BytesMut) -> io::Result<()> {
+        let plural = |value: i64, unit: &str| {
+            if value == 1 {
+                format!("{} {}", value, unit)
+            } else {
+                format!("{} {}s", value, unit)
+            }
+        };
+
+        let mut parts = vec![];
+        let (years, months) = (self.months / 12, self.months % 12);
+        if years != 0 {
+            parts.push(plural(years as i64, "year"));
+        }
+        if months != 0 {
+            parts.push(plural(months as i64, "mon"));
+        }
+        if self.days != 0 {
+            parts.push(plural(self.days as i64, "day"));
+        }
+
+        if self.microseconds != 0 || parts.is_empty() {
+            let sign = if self.microseconds < 0 { "-" } else { "" };
+            let micros = self.microseconds.unsigned_abs();
+            let mut time = format!(
+                "{}{:02}:{:02}:{:02}",
+                sign,
+                micros / 3_600_000_000,
+                micros / 60_000_000 % 60,
+                micros / 1_000_000 % 60
+            );
+            let fraction = micros % 1_000_000;
+            if fraction != 0 {
+                time.push_str(format!(".{:06}", fraction).trim_end_matches('0'));
+            }
+
+            parts.push(time);
+        }
+
+        parts.join(" ").to_text(buf)
+    }
+
+    fn to_binary(&self, buf: &mut BytesMut) -> io::Result<()> {
+        buf.put_i32(16_i32);
+        buf.put_i64(self.microseconds);
+        buf.put_i32(self.days);
+        buf.put_i32(self.months);
+
+        Ok(())
+    }
+}
+
 impl<T: ToPostgresValue> ToPostgresValue for Option<T> {
     fn to_text(&self, buf: &mut BytesMut) -> io::Result<()> {
         match &self {
@@ -154,49 +413,105 @@ impl<T: ToPostgresValue> ToPostgresValue for Option<T> {
     }
 }
 
-impl ToPostgresValue for ArrayRef {
-    fn to_text(&self, buf: &mut BytesMut) -> io::Result<()> {
-        let mut values: Vec<String> = Vec::with_capacity(self.len());
+/// Element of text array literal is quoted, if it's empty, NULL or contains special characters
+fn quote_array_element(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value.eq_ignore_ascii_case("null")
+        || value
+            .chars()
+            .any(|c| matches!(c, '{' | '}' | ',' | '"' | '\\') || c.is_whitespace());
+
+    if needs_quotes {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
 
-        macro_rules! write_native_array_to_buffer {
-            ($ARRAY:expr, $BUFF:expr, $ARRAY_TYPE: ident) => {{
-                let arr = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+/// Array in the text format of PostgreSQL, for example: {1,NULL,3}, {"a b",c} or {{1,2},{3}}
+fn array_to_text_literal(array: &ArrayRef) -> io::Result<String> {
+    let mut values: Vec<String> = Vec::with_capacity(array.len());
 
-                for i in 0..$ARRAY.len() {
-                    if self.is_null(i) {
-                        $BUFF.push("null".to_string());
-                    } else {
-                        $BUFF.push(arr.value(i).to_string());
-                    }
+    macro_rules! write_native_array_to_buffer {
+        ($ARRAY:expr, $BUFF:expr, $ARRAY_TYPE: ident) => {{
+            let arr = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+
+            for i in 0..$ARRAY.len() {
+                if $ARRAY.is_null(i) {
+                    $BUFF.push("NULL".to_string());
+                } else {
+                    $BUFF.push(arr.value(i).to_string());
                 }
-            }};
-        }
+            }
+        }};
+    }
 
-        match self.data_type() {
-            DataType::Float16 => write_native_array_to_buffer!(self, values, Float16Array),
-            DataType::Float32 => write_native_array_to_buffer!(self, values, Float32Array),
-            DataType::Float64 => write_native_array_to_buffer!(self, values, Float64Array),
-            // PG doesnt support i8, casting to i16
-            DataType::Int8 => write_native_array_to_buffer!(self, values, Int8Array),
-            DataType::Int16 => write_native_array_to_buffer!(self, values, Int16Array),
-            DataType::Int32 => write_native_array_to_buffer!(self, values, Int32Array),
-            DataType::Int64 => write_native_array_to_buffer!(self, values, Int64Array),
-            // PG doesnt support i8, casting to i16
-            DataType::UInt8 => write_native_array_to_buffer!(self, values, UInt8Array),
-            DataType::UInt16 => write_native_array_to_buffer!(self, values, UInt16Array),
-            DataType::UInt32 => write_native_array_to_buffer!(self, values, UInt32Array),
-            DataType::UInt64 => write_native_array_to_buffer!(self, values, UInt64Array),
-            DataType::Boolean => write_native_array_to_buffer!(self, values, BooleanArray),
-            DataType::Utf8 => write_native_array_to_buffer!(self, values, StringArray),
-            dt => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Unsupported type for list serializing: {}", dt),
-                ))
+    macro_rules! write_string_array_to_buffer {
+        ($ARRAY:expr, $BUFF:expr, $ARRAY_TYPE: ident) => {{
+            let arr = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+
+            for i in 0..$ARRAY.len() {
+                if $ARRAY.is_null(i) {
+                    $BUFF.push("NULL".to_string());
+                } else {
+                    $BUFF.push(quote_array_element(arr.value(i)));
+                }
             }
-        };
+        }};
+    }
+
+    match array.data_type() {
+        DataType::Float16 => write_native_array_to_buffer!(array, values, Float16Array),
+        DataType::Float32 => write_native_array_to_buffer!(array, values, Float32Array),
+        DataType::Float64 => write_native_array_to_buffer!(array, values, Float64Array),
+        // PG doesnt support i8, casting to i16
+        DataType::Int8 => write_native_array_to_buffer!(array, values, Int8Array),
+        DataType::Int16 => write_native_array_to_buffer!(array, values, Int16Array),
+        DataType::Int32 => write_native_array_to_buffer!(array, values, Int32Array),
+        DataType::Int64 => write_native_array_to_buffer!(array, values, Int64Array),
+        // PG doesnt support i8, casting to i16
+        DataType::UInt8 => write_native_array_to_buffer!(array, values, UInt8Array),
+        DataType::UInt16 => write_native_array_to_buffer!(array, values, UInt16Array),
+        DataType::UInt32 => write_native_array_to_buffer!(array, values, UInt32Array),
+        DataType::UInt64 => write_native_array_to_buffer!(array, values, UInt64Array),
+        DataType::Boolean => {
+            let arr = array.as_any().downcast_ref::<BooleanArray>().unwrap();
+
+            for i in 0..array.len() {
+                if array.is_null(i) {
+                    values.push("NULL".to_string());
+                } else {
+                    values.push(if arr.value(i) { "t" } else { "f" }.to_string());
+                }
+            }
+        }
+        DataType::Utf8 => write_string_array_to_buffer!(array, values, StringArray),
+        DataType::LargeUtf8 => write_string_array_to_buffer!(array, values, LargeStringArray),
+        DataType::List(_) => {
+            let arr = array.as_any().downcast_ref::<ListArray>().unwrap();
+
+            for i in 0..array.len() {
+                if array.is_null(i) {
+                    values.push("NULL".to_string());
+                } else {
+                    values.push(array_to_text_literal(&arr.value(i))?);
+                }
+            }
+        }
+        dt => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Unsupported type for list serializing: {}", dt),
+            ))
+        }
+    };
+
+    Ok("{".to_string() + &values.join(",") + "}")
+}
 
-        ("{".to_string() + &values.join(",") + "}").to_text(buf)
+impl ToPostgresValue for ArrayRef {
+    fn to_text(&self, buf: &mut BytesMut) -> io::Result<()> {
+        array_to_text_literal(self)?.to_text(buf)
     }
 
     // Example for ARRAY[1,2,3]::int8[]
@@ -216,7 +531,9 @@ impl ToPostgresValue for ArrayRef {
         column_data.put_i32(1);
         // has_nulls
         column_data.put_i32((self.null_count() > 0) as i32);
-        column_data.put_u32(df_type_to_pg_tid(self.data_type())? as u32);
+        let element_type = df_type_to_pg_tid(self.data_type())
+            .map_err(|e| Error::new(io::ErrorKind::Other, e.to_string()))?;
+        column_data.put_u32(element_type as u32);
         column_data.put_u32(self.len() as u32);
 
         // row2 from the comment
@@ -257,12 +574,14 @@ impl ToPostgresValue for ArrayRef {
 
                 for i in 0..self.len() {
                     if self.is_null(i) {
-                        "null".to_string().to_binary(&mut column_data)?
+                        let n: Option<String> = None;
+                        n.to_binary(&mut column_data)?
                     } else {
                         arr.value(i).to_string().to_binary(&mut column_data)?
                     }
                 }
             }
+            // Multidimensional arrays are not supported in binary format
             dt => {
                 return Err(io::Error::new(
                     io::ErrorKind::Other,
@@ -301,8 +620,105 @@ impl_primitive!(i64);
 impl_primitive!(f32);
 impl_primitive!(f64);
 
+/// Options of COPY ... TO STDOUT, which are used to encode rows in CSV or text format
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyFormat {
+    pub csv: bool,
+    pub delimiter: char,
+    pub null: String,
+    pub header: bool,
+    pub quote: char,
+    pub escape: char,
+}
+
+impl CopyFormat {
+    pub fn text() -> Self {
+        Self {
+            csv: false,
+            delimiter: '\t',
+            null: "\\N".to_string(),
+            header: false,
+            quote: '"',
+            escape: '"',
+        }
+    }
+
+    pub fn csv() -> Self {
+        Self {
+            csv: true,
+            delimiter: ',',
+            null: "".to_string(),
+            header: false,
+            quote: '"',
+            escape: '"',
+        }
+    }
+
+    /// Encodes values of row as line, None is written as NULL string
+    pub fn encode_line(&self, values: &[Option<&str>]) -> String {
+        let mut line = values
+            .iter()
+            .map(|value| match value {
+                None => self.null.clone(),
+                Some(value) if self.csv => self.encode_csv_value(value),
+                Some(value) => self.encode_text_value(value),
+            })
+            .collect::<Vec<String>>()
+            .join(&self.delimiter.to_string());
+        line.push('\n');
+
+        line
+    }
+
+    // Value is quoted, if it can be confused with NULL or contains special characters
+    fn encode_csv_value(&self, value: &str) -> String {
+        let needs_quotes = value == self.null
+            || value == "\\."
+            || value.chars().any(|c| {
+                c == self.delimiter || c == self.quote || c == self.escape || c == '\n' || c == '\r'
+            });
+        if !needs_quotes {
+            return value.to_string();
+        }
+
+        let mut result = String::with_capacity(value.len() + 2);
+        result.push(self.quote);
+        for c in value.chars() {
+            if c == self.quote || c == self.escape {
+                result.push(self.escape);
+            }
+            result.push(c);
+        }
+        result.push(self.quote);
+
+        result
+    }
+
+    fn encode_text_value(&self, value: &str) -> String {
+        let mut result = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '\\' => result.push_str("\\\\"),
+                '\n' => result.push_str("\\n"),
+                '\r' => result.push_str("\\r"),
+                '\t' => result.push_str("\\t"),
+                c if c == self.delimiter => {
+                    result.push('\\');
+                    result.push(c);
+                }
+                c => result.push(c),
+            }
+        }
+
+        result
+    }
+}
+
 pub struct BatchWriter {
-    format: Format,
+    // Formats of columns, as they were requested by Bind (see Format::by_column)
+    formats: Vec<Format>,
+    // Rows are written as CopyData messages instead of DataRow
+    copy: Option<CopyFormat>,
     // Data of whole rows
     data: BytesMut,
     // Current row
@@ -312,9 +728,10 @@ pub struct BatchWriter {
 }
 
 impl BatchWriter {
-    pub fn new(format: Format) -> Self {
+    pub fn new(formats: Vec<Format>) -> Self {
         Self {
-            format,
+            formats,
+            copy: None,
             data: BytesMut::new(),
             row: BytesMut::new(),
             current: 0,
@@ -322,10 +739,19 @@ impl BatchWriter {
         }
     }
 
+    /// Writer for COPY ... TO STDOUT, values are encoded in text format
+    pub fn copy(format: CopyFormat) -> Self {
+        Self {
+            copy: Some(format),
+            ..Self::new(vec![])
+        }
+    }
+
     pub fn write_value<T: ToPostgresValue>(&mut self, value: T) -> io::Result<()> {
+        let format = Format::by_column(&self.formats, self.current as usize);
         self.current += 1;
 
-        match self.format {
+        match format {
             Format::Text => value.to_text(&mut self.row)?,
             Format::Binary => value.to_binary(&mut self.row)?,
         };
@@ -334,6 +760,19 @@ impl BatchWriter {
     }
 
     pub fn end_row(&mut self) -> io::Result<()> {
+        if let Some(format) = &self.copy {
+            let buffer = self.row.split();
+            let line = format.encode_line(&decode_text_values(&buffer)?);
+
+            self.data.extend_from_slice(&b'd'.to_be_bytes());
+            self.data.put_i32(line.len() as i32 + 4);
+            self.data.extend_from_slice(line.as_bytes());
+            self.current = 0;
+            self.rows += 1;
+
+            return Ok(());
+        }
+
         self.data.extend_from_slice(&b'D'.to_be_bytes());
         let buffer = self.row.split();
 
@@ -358,6 +797,32 @@ impl BatchWriter {
     }
 }
 
+/// Splits raw values of row (length followed by bytes, -1 for NULL) into strings
+fn decode_text_values(buffer: &[u8]) -> io::Result<Vec<Option<&str>>> {
+    let invalid = || Error::new(io::ErrorKind::InvalidData, "Malformed value in row buffer");
+
+    let mut values = vec![];
+    let mut rest = buffer;
+    while !rest.is_empty() {
+        let (length, tail) = rest.split_at(4.min(rest.len()));
+        let length = i32::from_be_bytes(length.try_into().map_err(|_| invalid())?);
+        if length < 0 {
+            values.push(None);
+            rest = tail;
+            continue;
+        }
+
+        if tail.len() < length as usize {
+            return Err(invalid());
+        }
+        let (value, tail) = tail.split_at(length as usize);
+        values.push(Some(std::str::from_utf8(value).map_err(|_| invalid())?));
+        rest = tail;
+    }
+
+    Ok(values)
+}
+
 impl<'a> Serialize for BatchWriter {
     const CODE: u8 = b'D';
 
@@ -373,11 +838,12 @@ impl<'a> Serialize for BatchWriter {
 mod tests {
     use crate::sql::dataframe::TimestampValue;
     use crate::{
-        arrow::array::{ArrayRef, Int64Builder},
-        sql::writer::{BatchWriter, ToPostgresValue},
+        arrow::array::{ArrayRef, BooleanBuilder, Int64Builder, ListBuilder, StringBuilder},
+        sql::writer::{BatchWriter, CopyFormat, IntervalValue, NumericValue, ToPostgresValue},
         CubeError,
     };
     use bytes::BytesMut;
+    use chrono::NaiveDate;
     use pg_srv::buffer;
     use pg_srv::protocol::Format;
     use std::io::Cursor;
@@ -409,6 +875,27 @@ mod tests {
                 48, 49, 46, 54, 53, 48, 56, 57, 48, 43, 48, 48,
             ],
         );
+        assert_text_encode(
+            NaiveDate::from_ymd(2022, 4, 25),
+            &[0, 0, 0, 10, 50, 48, 50, 50, 45, 48, 52, 45, 50, 53],
+        );
+        assert_text_encode(vec![10_u8, 255], &[0, 0, 0, 6, 92, 120, 48, 97, 102, 102]);
+        assert_text_encode(
+            NumericValue::Decimal(-12345, 2),
+            &[0, 0, 0, 7, 45, 49, 50, 51, 46, 52, 53],
+        );
+        assert_text_encode(
+            NumericValue::Decimal(5, 3),
+            &[0, 0, 0, 5, 48, 46, 48, 48, 53],
+        );
+        assert_text_encode(NumericValue::Float(1.5), &[0, 0, 0, 3, 49, 46, 53]);
+        assert_text_encode(NumericValue::Float(f64::NAN), &[0, 0, 0, 3, 78, 97, 78]);
+        assert_text_encode(
+            IntervalValue::new(14, 3, 14_706_500_000),
+            b"\0\0\0\x1f1 year 2 mons 3 days 04:05:06.5",
+        );
+        assert_text_encode(IntervalValue::new(0, -1, 0), b"\0\0\0\x07-1 days");
+        assert_text_encode(IntervalValue::new(0, 0, 0), b"\0\0\0\x0800:00:00");
 
         Ok(())
     }
@@ -432,6 +919,42 @@ mod tests {
             TimestampValue::new(1650890322, Some("UTC".to_string())),
             &[0, 0, 0, 8, 255, 252, 162, 254, 196, 225, 80, 202],
         );
+        assert_bind_encode(
+            NaiveDate::from_ymd(2022, 4, 25),
+            &[0, 0, 0, 4, 0, 0, 31, 214],
+        );
+        assert_bind_encode(
+            NaiveDate::from_ymd(1999, 12, 31),
+            &[0, 0, 0, 4, 255, 255, 255, 255],
+        );
+        assert_bind_encode(vec![10_u8, 255], &[0, 0, 0, 2, 10, 255]);
+        // 123.45 => [123, 4500], weight 0, dscale 2
+        assert_bind_encode(
+            NumericValue::Decimal(12345, 2),
+            &[0, 0, 0, 12, 0, 2, 0, 0, 0, 0, 0, 2, 0, 123, 17, 148],
+        );
+        // -0.5 => [5000], weight -1, dscale 1
+        assert_bind_encode(
+            NumericValue::Float(-0.5),
+            &[0, 0, 0, 10, 0, 1, 255, 255, 64, 0, 0, 1, 19, 136],
+        );
+        // 10000 => [1], weight 1
+        assert_bind_encode(
+            NumericValue::Float(10000.0),
+            &[0, 0, 0, 10, 0, 1, 0, 1, 0, 0, 0, 0, 0, 1],
+        );
+        assert_bind_encode(
+            NumericValue::Decimal(0, 2),
+            &[0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 2],
+        );
+        assert_bind_encode(
+            NumericValue::Float(f64::NAN),
+            &[0, 0, 0, 8, 0, 0, 0, 0, 192, 0, 0, 0],
+        );
+        assert_bind_encode(
+            IntervalValue::new(1, 2, 3),
+            &[0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 1],
+        );
 
         Ok(())
     }
@@ -440,7 +963,7 @@ mod tests {
     async fn test_backend_writer_text_simple() -> Result<(), CubeError> {
         let mut cursor = Cursor::new(vec![]);
 
-        let mut writer = BatchWriter::new(Format::Text);
+        let mut writer = BatchWriter::new(vec![Format::Text]);
         writer.write_value("test1".to_string())?;
         writer.write_value(true)?;
         writer.end_row()?;
@@ -468,7 +991,7 @@ mod tests {
     async fn test_backend_writer_binary_simple() -> Result<(), CubeError> {
         let mut cursor = Cursor::new(vec![]);
 
-        let mut writer = BatchWriter::new(Format::Binary);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
         writer.write_value("test1".to_string())?;
         writer.write_value(true)?;
         writer.end_row()?;
@@ -492,10 +1015,97 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_backend_writer_per_column_formats() -> Result<(), CubeError> {
+        let mut cursor = Cursor::new(vec![]);
+
+        let mut writer = BatchWriter::new(vec![Format::Binary, Format::Text, Format::Binary]);
+        writer.write_value(1_i16)?;
+        writer.write_value(2_i32)?;
+        writer.write_value(3_i64)?;
+        writer.end_row()?;
+
+        writer.write_value(1.5_f32)?;
+        writer.write_value(true)?;
+        writer.write_value(-2.5_f64)?;
+        writer.end_row()?;
+
+        buffer::write_direct(&mut cursor, writer).await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            vec![
+                // row: int2 binary, int4 text, int8 binary
+                68, 0, 0, 0, 29, 0, 3, 0, 0, 0, 2, 0, 1, 0, 0, 0, 1, 50, 0, 0, 0, 8, 0, 0, 0, 0, 0,
+                0, 0, 3, // row: float4 binary, bool text, float8 binary
+                68, 0, 0, 0, 31, 0, 3, 0, 0, 0, 4, 63, 192, 0, 0, 0, 0, 0, 1, 116, 0, 0, 0, 8, 192,
+                4, 0, 0, 0, 0, 0, 0
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_format_encode_line() {
+        let csv = CopyFormat::csv();
+        assert_eq!(
+            csv.encode_line(&[Some("a"), None, Some(""), Some("b,c"), Some("say \"hi\"")]),
+            "a,,\"\",\"b,c\",\"say \"\"hi\"\"\"\n"
+        );
+        assert_eq!(
+            csv.encode_line(&[Some("x\ny"), Some("\\.")]),
+            "\"x\ny\",\"\\.\"\n"
+        );
+
+        let semicolon = CopyFormat {
+            delimiter: ';',
+            null: "NULL".to_string(),
+            escape: '\\',
+            ..CopyFormat::csv()
+        };
+        assert_eq!(
+            semicolon.encode_line(&[Some("a,b"), Some("NULL"), None, Some("c;\"\\")]),
+            "a,b;\"NULL\";NULL;\"c;\\\"\\\\\"\n"
+        );
+
+        let text = CopyFormat::text();
+        assert_eq!(
+            text.encode_line(&[Some("a\tb"), None, Some("c\\d\ne"), Some("")]),
+            "a\\tb\t\\N\tc\\\\d\\ne\t\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backend_writer_copy() -> Result<(), CubeError> {
+        let mut cursor = Cursor::new(vec![]);
+
+        let mut writer = BatchWriter::copy(CopyFormat::csv());
+        writer.write_value(1_i64)?;
+        writer.write_value(Some("a,b".to_string()))?;
+        writer.write_value(true)?;
+        writer.end_row()?;
+
+        writer.write_value(2_i64)?;
+        writer.write_value(None::<String>)?;
+        writer.write_value(false)?;
+        writer.end_row()?;
+
+        assert_eq!(writer.num_rows(), 2);
+        buffer::write_direct(&mut cursor, writer).await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            [&b"d\0\0\0\x0e1,\"a,b\",t\n"[..], &b"d\0\0\0\x092,,f\n"[..]].concat()
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_backend_writer_binary_int8_array() -> Result<(), CubeError> {
         let mut cursor = Cursor::new(vec![]);
-        let mut writer = BatchWriter::new(Format::Binary);
+        let mut writer = BatchWriter::new(vec![Format::Binary]);
 
         // Row 1
         let mut col = Int64Builder::new(3);
@@ -530,4 +1140,40 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_text_array_encoders() -> Result<(), CubeError> {
+        let mut col = StringBuilder::new(6);
+        col.append_value("a")?;
+        col.append_value("")?;
+        col.append_value("b c")?;
+        col.append_value("x\"y\\z")?;
+        col.append_null()?;
+        col.append_value("null")?;
+        assert_text_encode(
+            Arc::new(col.finish()) as ArrayRef,
+            b"\0\0\0\x22{a,\"\",\"b c\",\"x\\\"y\\\\z\",NULL,\"null\"}",
+        );
+
+        let mut col = BooleanBuilder::new(2);
+        col.append_value(true)?;
+        col.append_null()?;
+        assert_text_encode(Arc::new(col.finish()) as ArrayRef, b"\0\0\0\x08{t,NULL}");
+
+        let mut col = Int64Builder::new(0);
+        assert_text_encode(Arc::new(col.finish()) as ArrayRef, b"\0\0\0\x02{}");
+
+        let mut col = ListBuilder::new(Int64Builder::new(3));
+        col.values().append_value(1)?;
+        col.values().append_value(2)?;
+        col.append(true)?;
+        col.append(true)?;
+        col.append(false)?;
+        assert_text_encode(
+            Arc::new(col.finish()) as ArrayRef,
+            b"\0\0\0\x0f{{1,2},{},NULL}",
+        );
+
+        Ok(())
+    }
 }
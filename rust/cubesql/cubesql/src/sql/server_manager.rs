@@ -1,6 +1,12 @@
-use std::sync::{Arc, RwLock as RwLockSync};
+use std::{
+    sync::{Arc, RwLock as RwLockSync},
+    time::Duration,
+};
+
+use pg_srv::protocol::Format;
 
 use crate::{
+    config::ConfigObj,
     sql::{
         database_variables::{mysql_default_global_variables, postgres_default_global_variables},
         SqlAuthService,
@@ -15,12 +21,91 @@ use super::{database_variables::DatabaseVariables, session::DatabaseProtocol};
 pub struct ServerConfiguration {
     /// Max number of prepared statements which can be allocated per connection
     pub connection_max_prepared_statements: usize,
+    /// Max number of prepared statements (postgresql) per connection
+    pub connection_max_pg_prepared_statements: usize,
+    /// Max number of portals (postgresql) per connection
+    pub connection_max_pg_portals: usize,
+    /// Number of rows after which result rows are flushed to the socket while streaming
+    pub stream_flush_rows: usize,
+    /// Accept INSERT/UPDATE/DELETE (postgresql) without executing them, for tools which write
+    pub noop_dml: bool,
+    /// Max number of expressions (including column references) in a single query
+    pub max_query_expressions: usize,
+    /// Max nesting depth of subqueries, deeper queries may exhaust the stack during planning
+    pub max_subquery_depth: usize,
+    /// Report id of every query to the client by NoticeResponse (postgresql)
+    pub query_id_notice: bool,
+    /// Report server execution time of every query to the client by NoticeResponse (postgresql)
+    pub timing_notice: bool,
+    /// Fetch meta right after authentication (postgresql), so the first query doesn't wait for it
+    pub meta_warmup: bool,
+    /// Format of results (postgresql) by prefix of application_name from startup parameters,
+    /// it's applied when Bind doesn't request formats
+    pub default_result_formats: Vec<(String, Format)>,
+    /// Request SELECT without aggregation as ungrouped query to return rows as is, Cube must
+    /// support ungrouped queries
+    pub ungrouped_queries: bool,
+    /// Connection (postgresql) is closed, if the client sends nothing during this time
+    pub connection_idle_timeout: Option<Duration>,
+    /// Max number of rows in one load request, bigger results are loaded page by page. It
+    /// shouldn't exceed the row limit of Cube
+    pub cube_scan_page_size: usize,
+    /// Max time of waiting for Cube, which responds with "Continue wait" while the query is
+    /// processed (for example, pre-aggregations are built)
+    pub continue_wait_timeout: Duration,
+    /// Password (postgresql) is a bearer token (for example, JWT), which is verified by
+    /// SqlAuthService::authenticate_token
+    pub token_auth: bool,
+    /// Time (postgresql) for connections to finish their current messages on shutdown, then
+    /// they are closed forcibly
+    pub shutdown_grace_period: Duration,
+}
+
+impl ServerConfiguration {
+    /// Configuration of the server by the environment, see Config::default
+    pub fn from_config(config: &dyn ConfigObj) -> Self {
+        Self {
+            connection_max_pg_prepared_statements: config.postgres_max_prepared_statements(),
+            connection_max_pg_portals: config.postgres_max_portals(),
+            stream_flush_rows: config.stream_flush_rows(),
+            noop_dml: config.noop_dml(),
+            max_query_expressions: config.max_query_expressions(),
+            max_subquery_depth: config.max_subquery_depth(),
+            query_id_notice: config.postgres_query_id_notice(),
+            timing_notice: config.postgres_timing_notice(),
+            meta_warmup: config.postgres_meta_warmup(),
+            default_result_formats: config.postgres_default_result_formats().clone(),
+            ungrouped_queries: config.ungrouped_queries(),
+            connection_idle_timeout: config.postgres_idle_timeout().map(Duration::from_secs),
+            cube_scan_page_size: config.cube_scan_page_size(),
+            continue_wait_timeout: Duration::from_secs(config.continue_wait_timeout()),
+            token_auth: config.postgres_token_auth(),
+            shutdown_grace_period: Duration::from_secs(config.postgres_shutdown_grace_period()),
+            ..Self::default()
+        }
+    }
 }
 
 impl Default for ServerConfiguration {
     fn default() -> Self {
         Self {
             connection_max_prepared_statements: 50,
+            connection_max_pg_prepared_statements: 10_000,
+            connection_max_pg_portals: 1_000,
+            stream_flush_rows: 10_000,
+            noop_dml: false,
+            max_query_expressions: 20_000,
+            max_subquery_depth: 64,
+            query_id_notice: false,
+            timing_notice: false,
+            meta_warmup: false,
+            default_result_formats: vec![],
+            ungrouped_queries: false,
+            connection_idle_timeout: None,
+            cube_scan_page_size: 50_000,
+            continue_wait_timeout: Duration::from_secs(300),
+            token_auth: false,
+            shutdown_grace_period: Duration::from_secs(30),
         }
     }
 }
@@ -49,12 +134,13 @@ impl ServerManager {
         auth: Arc<dyn SqlAuthService>,
         transport: Arc<dyn TransportService>,
         nonce: Option<Vec<u8>>,
+        configuration: ServerConfiguration,
     ) -> Self {
         Self {
             auth,
             transport,
             nonce,
-            configuration: ServerConfiguration::default(),
+            configuration,
         }
     }
 
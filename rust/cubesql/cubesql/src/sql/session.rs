@@ -1,7 +1,22 @@
-use std::sync::{Arc, RwLock as RwLockSync};
+use datafusion::scalar::ScalarValue;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock as RwLockSync,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
-use crate::sql::database_variables::{
-    mysql_default_session_variables, postgres_default_session_variables,
+use crate::{
+    compile::macros::SqlMacro,
+    sql::database_variables::{
+        mysql_default_session_variables, postgres_default_session_variables,
+    },
+    CubeError,
 };
 
 use super::{
@@ -29,6 +44,54 @@ impl SessionProperties {
     }
 }
 
+/// Transaction block of the session (postgresql). Cube is read-only, transactions are no-op and
+/// are tracked only to report the status to the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionState {
+    None,
+    // BEGIN was issued
+    Active,
+    // Error happened inside transaction block, commands are rejected till ROLLBACK
+    Failed,
+}
+
+/// Message of NOTIFY (postgresql), which is delivered to sessions listening to the channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    // connection id of the session which sent NOTIFY
+    pub process_id: u32,
+    pub channel: String,
+    pub payload: String,
+}
+
+/// Change of the session by the statement (postgresql). It's planned with the statement and
+/// applied by the connection, when the statement is executed
+#[derive(Debug, Clone)]
+pub enum SessionChange {
+    Variables(VariablesChange),
+    // CREATE FUNCTION
+    CreateSqlMacro {
+        definition: Box<SqlMacro>,
+        or_replace: bool,
+    },
+    // DROP FUNCTION
+    DropSqlMacro {
+        name: String,
+        if_exists: bool,
+    },
+}
+
+/// Changes of session variables by SET and RESET
+#[derive(Debug, Clone, Default)]
+pub struct VariablesChange {
+    // RESET ALL
+    pub reset_all: bool,
+    pub reset: Vec<String>,
+    pub session: DatabaseVariables,
+    // SET LOCAL
+    pub local: DatabaseVariables,
+}
+
 lazy_static! {
     static ref POSTGRES_DEFAULT_VARIABLES: DatabaseVariables = postgres_default_session_variables();
     static ref MYSQL_DEFAULT_VARIABLES: DatabaseVariables = mysql_default_session_variables();
@@ -42,15 +105,54 @@ pub struct SessionState {
     pub host: String,
     // client protocol, mysql/postgresql, immutable
     pub protocol: DatabaseProtocol,
+    // secret key, which is used to verify CancelRequest (postgresql), immutable
+    pub secret: u32,
+    // start time of the session, immutable
+    pub started_at: SystemTime,
 
     // session db variables
     variables: RwLockSync<Option<DatabaseVariables>>,
+    // Variables from startup parameters (postgresql), they are restored by RESET instead of defaults
+    startup_variables: RwLockSync<DatabaseVariables>,
 
     properties: RwLockSync<SessionProperties>,
 
     // @todo Remove RWLock after split of Connection & SQLWorker
     // Context for Transport
     auth_context: RwLockSync<Option<AuthContext>>,
+
+    // Id of the last query, for correlation of logs
+    query_id: RwLockSync<Option<String>>,
+    // Cancellation token of the query which is executing right now
+    query_cancellation: RwLockSync<Option<CancellationToken>>,
+    // Text of the last query and start time of the query which is executing right now
+    current_query: RwLockSync<Option<String>>,
+    query_started_at: RwLockSync<Option<SystemTime>>,
+    // Canceled when session is dropped or terminated, parent for all query tokens
+    termination: CancellationToken,
+    // Canceled when server shuts down, the connection is closed after the current message
+    shutdown: CancellationToken,
+    // Incremented when prepared statements and portals (postgresql) must be dropped, the
+    // connection applies it before processing the next message
+    extended_reset_generation: AtomicU64,
+    // Number of prepared statements and portals (postgresql), reported by the connection
+    prepared_statements_count: AtomicUsize,
+    portals_count: AtomicUsize,
+
+    // SQL functions which were declared by CREATE FUNCTION (postgresql), by lowercased name
+    sql_macros: RwLockSync<HashMap<String, SqlMacro>>,
+
+    transaction: RwLockSync<TransactionState>,
+    // Variables which were set by SET LOCAL (postgresql), they are reverted at the end of transaction block
+    local_variables: RwLockSync<DatabaseVariables>,
+    // Non-fatal warnings of the query planning, the connection sends them to the client (postgresql)
+    warnings: RwLockSync<Vec<String>>,
+
+    // Channels of LISTEN (postgresql)
+    listen_channels: RwLockSync<HashSet<String>>,
+    // Notifications which were not sent to the client yet, the connection is woken up by signal
+    notifications: RwLockSync<Vec<Notification>>,
+    notifications_signal: Notify,
 }
 
 impl SessionState {
@@ -64,9 +166,354 @@ impl SessionState {
             connection_id,
             host,
             protocol,
+            secret: rand::random(),
+            started_at: SystemTime::now(),
             variables: RwLockSync::new(None),
+            startup_variables: RwLockSync::new(DatabaseVariables::new()),
             properties: RwLockSync::new(SessionProperties::new(None, None)),
             auth_context: RwLockSync::new(auth_context),
+            query_id: RwLockSync::new(None),
+            query_cancellation: RwLockSync::new(None),
+            current_query: RwLockSync::new(None),
+            query_started_at: RwLockSync::new(None),
+            termination: CancellationToken::new(),
+            shutdown: CancellationToken::new(),
+            extended_reset_generation: AtomicU64::new(0),
+            prepared_statements_count: AtomicUsize::new(0),
+            portals_count: AtomicUsize::new(0),
+            sql_macros: RwLockSync::new(HashMap::new()),
+            transaction: RwLockSync::new(TransactionState::None),
+            local_variables: RwLockSync::new(DatabaseVariables::new()),
+            warnings: RwLockSync::new(vec![]),
+            listen_channels: RwLockSync::new(HashSet::new()),
+            notifications: RwLockSync::new(vec![]),
+            notifications_signal: Notify::new(),
+        }
+    }
+
+    /// Generates unique id for the new query
+    pub fn assign_query_id(&self) -> String {
+        let query_id = Uuid::new_v4().to_string();
+
+        let mut guard = self
+            .query_id
+            .write()
+            .expect("failed to unlock query_id for writting");
+        *guard = Some(query_id.clone());
+
+        query_id
+    }
+
+    pub fn query_id(&self) -> Option<String> {
+        let guard = self
+            .query_id
+            .read()
+            .expect("failed to unlock query_id for reading");
+        guard.clone()
+    }
+
+    pub fn begin_query(&self) -> CancellationToken {
+        let token = self.termination.child_token();
+
+        let mut guard = self
+            .query_cancellation
+            .write()
+            .expect("failed to unlock query_cancellation for writting");
+        *guard = Some(token.clone());
+
+        let mut guard = self
+            .query_started_at
+            .write()
+            .expect("failed to unlock query_started_at for writting");
+        *guard = Some(SystemTime::now());
+
+        token
+    }
+
+    /// Text of the last query, it stays after the query is finished
+    pub fn current_query(&self) -> Option<String> {
+        let guard = self
+            .current_query
+            .read()
+            .expect("failed to unlock current_query for reading");
+        guard.clone()
+    }
+
+    pub fn set_current_query(&self, query: String) {
+        let mut guard = self
+            .current_query
+            .write()
+            .expect("failed to unlock current_query for writting");
+        *guard = Some(query);
+    }
+
+    /// Start time of the query which is executing right now, None if the session is idle
+    pub fn query_started_at(&self) -> Option<SystemTime> {
+        let guard = self
+            .query_started_at
+            .read()
+            .expect("failed to unlock query_started_at for reading");
+        *guard
+    }
+
+    /// Token of the query which is executing right now, or a child of the session token
+    pub fn query_cancellation(&self) -> CancellationToken {
+        let guard = self
+            .query_cancellation
+            .read()
+            .expect("failed to unlock query_cancellation for reading");
+
+        guard
+            .clone()
+            .unwrap_or_else(|| self.termination.child_token())
+    }
+
+    pub fn end_query(&self) {
+        let mut guard = self
+            .query_cancellation
+            .write()
+            .expect("failed to unlock query_cancellation for writting");
+        *guard = None;
+
+        let mut guard = self
+            .query_started_at
+            .write()
+            .expect("failed to unlock query_started_at for writting");
+        *guard = None;
+    }
+
+    pub fn cancel_query(&self) -> bool {
+        let guard = self
+            .query_cancellation
+            .read()
+            .expect("failed to unlock query_cancellation for reading");
+
+        match &*guard {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Aborts the query which is executing right now and all future queries of the session
+    pub fn terminate(&self) {
+        self.termination.cancel();
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.termination.is_cancelled()
+    }
+
+    /// Resolves when session is terminated
+    pub async fn terminated(&self) {
+        self.termination.cancelled().await
+    }
+
+    /// Asks the connection to close after the current message, the query is not aborted
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// Resolves when server shuts down
+    pub async fn shutting_down(&self) {
+        self.shutdown.cancelled().await
+    }
+
+    pub fn reset_extended_state(&self) {
+        self.extended_reset_generation
+            .fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn extended_reset_generation(&self) -> u64 {
+        self.extended_reset_generation.load(Ordering::SeqCst)
+    }
+
+    /// Number of prepared statements and portals of the connection
+    pub fn extended_state_counts(&self) -> (usize, usize) {
+        (
+            self.prepared_statements_count.load(Ordering::SeqCst),
+            self.portals_count.load(Ordering::SeqCst),
+        )
+    }
+
+    pub fn set_extended_state_counts(&self, prepared_statements: usize, portals: usize) {
+        self.prepared_statements_count
+            .store(prepared_statements, Ordering::SeqCst);
+        self.portals_count.store(portals, Ordering::SeqCst);
+    }
+
+    /// Warning is sent to the client as a notice before the result of the query
+    pub fn add_warning(&self, message: String) {
+        let mut guard = self
+            .warnings
+            .write()
+            .expect("failed to unlock warnings for writing");
+        guard.push(message);
+    }
+
+    /// Returns accumulated warnings and clears them
+    pub fn take_warnings(&self) -> Vec<String> {
+        let mut guard = self
+            .warnings
+            .write()
+            .expect("failed to unlock warnings for writing");
+        std::mem::take(&mut *guard)
+    }
+
+    pub fn listen(&self, channel: String) {
+        let mut guard = self
+            .listen_channels
+            .write()
+            .expect("failed to unlock listen_channels for writing");
+        guard.insert(channel);
+    }
+
+    /// Stops listening to the channel, or to all channels if it's None (UNLISTEN *)
+    pub fn unlisten(&self, channel: Option<&str>) {
+        let mut guard = self
+            .listen_channels
+            .write()
+            .expect("failed to unlock listen_channels for writing");
+        match channel {
+            Some(channel) => {
+                guard.remove(channel);
+            }
+            None => guard.clear(),
+        }
+    }
+
+    pub fn is_listening(&self, channel: &str) -> bool {
+        let guard = self
+            .listen_channels
+            .read()
+            .expect("failed to unlock listen_channels for reading");
+        guard.contains(channel)
+    }
+
+    /// Queues notification and wakes up the connection to send it
+    pub fn push_notification(&self, notification: Notification) {
+        let mut guard = self
+            .notifications
+            .write()
+            .expect("failed to unlock notifications for writing");
+        guard.push(notification);
+
+        self.notifications_signal.notify_one();
+    }
+
+    /// Returns queued notifications and clears them
+    pub fn take_notifications(&self) -> Vec<Notification> {
+        let mut guard = self
+            .notifications
+            .write()
+            .expect("failed to unlock notifications for writing");
+        std::mem::take(&mut *guard)
+    }
+
+    /// Resolves when notification is queued
+    pub async fn notified(&self) {
+        self.notifications_signal.notified().await
+    }
+
+    pub fn sql_macros(&self) -> HashMap<String, SqlMacro> {
+        let guard = self
+            .sql_macros
+            .read()
+            .expect("failed to unlock sql_macros for reading");
+        guard.clone()
+    }
+
+    /// Returns false if function with the same name exists and replace is not allowed
+    pub fn set_sql_macro(&self, mut definition: SqlMacro, replace: bool) -> bool {
+        let mut guard = self
+            .sql_macros
+            .write()
+            .expect("failed to unlock sql_macros for writting");
+
+        definition.oid = match guard.get(&definition.name) {
+            Some(_) if !replace => return false,
+            Some(existing) => existing.oid,
+            // The first OID which is available for user-defined objects
+            None => guard.values().map(|m| m.oid + 1).max().unwrap_or(16384),
+        };
+        guard.insert(definition.name.clone(), definition);
+
+        true
+    }
+
+    pub fn drop_sql_macro(&self, name: &str) -> bool {
+        let mut guard = self
+            .sql_macros
+            .write()
+            .expect("failed to unlock sql_macros for writting");
+        guard.remove(name).is_some()
+    }
+
+    pub fn transaction_state(&self) -> TransactionState {
+        let guard = self
+            .transaction
+            .read()
+            .expect("failed to unlock transaction for reading");
+        *guard
+    }
+
+    /// End of transaction block (COMMIT or ROLLBACK) reverts variables of SET LOCAL
+    pub fn set_transaction_state(&self, state: TransactionState) {
+        let mut guard = self
+            .transaction
+            .write()
+            .expect("failed to unlock transaction for writting");
+        *guard = state;
+
+        if state == TransactionState::None {
+            self.local_variables
+                .write()
+                .expect("failed to unlock local variables for writting")
+                .clear();
+        }
+    }
+
+    pub fn set_local_variables(&self, variables: DatabaseVariables) {
+        let mut guard = self
+            .local_variables
+            .write()
+            .expect("failed to unlock local variables for writting");
+        guard.extend(variables);
+    }
+
+    /// Overrides variables by values of SET LOCAL, read-only and unknown variables are not changed
+    pub fn apply_local_variables(&self, mut variables: DatabaseVariables) -> DatabaseVariables {
+        let guard = self
+            .local_variables
+            .read()
+            .expect("failed to unlock local variables for reading");
+
+        for (local_key, local_value) in guard.iter() {
+            for (key, value) in variables.iter_mut() {
+                if key.to_lowercase() == local_key.to_lowercase() && !value.readonly {
+                    *value = local_value.clone();
+                    break;
+                }
+            }
+        }
+
+        variables
+    }
+
+    /// Marks active transaction block as failed, it's no-op outside of transaction block
+    pub fn fail_transaction(&self) {
+        let mut guard = self
+            .transaction
+            .write()
+            .expect("failed to unlock transaction for writting");
+        if *guard == TransactionState::Active {
+            *guard = TransactionState::Failed;
         }
     }
 
@@ -127,10 +574,148 @@ impl SessionState {
 
         match guard {
             Some(vars) => vars,
-            _ => match self.protocol {
-                DatabaseProtocol::MySQL => return MYSQL_DEFAULT_VARIABLES.clone(),
-                DatabaseProtocol::PostgreSQL => return POSTGRES_DEFAULT_VARIABLES.clone(),
-            },
+            _ => self.default_variables(),
+        }
+    }
+
+    fn default_variables(&self) -> DatabaseVariables {
+        let mut variables = match self.protocol {
+            DatabaseProtocol::MySQL => MYSQL_DEFAULT_VARIABLES.clone(),
+            DatabaseProtocol::PostgreSQL => POSTGRES_DEFAULT_VARIABLES.clone(),
+        };
+        variables.extend(
+            self.startup_variables
+                .read()
+                .expect("failed to unlock startup variables for reading")
+                .clone(),
+        );
+
+        variables
+    }
+
+    /// Sets variables from startup parameters, they become defaults of the session
+    pub fn set_startup_variables(&self, variables: DatabaseVariables) {
+        {
+            let mut guard = self
+                .startup_variables
+                .write()
+                .expect("failed to unlock startup variables for writing");
+            guard.extend(variables.clone());
+        }
+
+        self.set_variables(variables);
+    }
+
+    /// application_name of the session, it's empty if it isn't set by the client
+    pub fn application_name(&self) -> String {
+        match self
+            .all_variables()
+            .get("application_name")
+            .map(|v| &v.value)
+        {
+            Some(ScalarValue::Utf8(Some(name))) => name.clone(),
+            _ => String::new(),
+        }
+    }
+
+    /// statement_timeout of the session (SET LOCAL included), None if queries are unlimited
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        match self
+            .apply_local_variables(self.all_variables())
+            .get("statement_timeout")
+            .map(|v| &v.value)
+        {
+            Some(ScalarValue::Utf8(Some(value))) => parse_timeout(value),
+            _ => None,
+        }
+    }
+
+    /// Variables visible in the session: server variables overridden by the session ones
+    /// and by SET LOCAL
+    pub fn visible_variables(&self, server_variables: DatabaseVariables) -> DatabaseVariables {
+        let mut variables = server_variables;
+        variables.extend(self.all_variables());
+
+        self.apply_local_variables(variables)
+    }
+
+    /// RESET of variables by names or all of them (None), variables without default are removed
+    pub fn reset_variables(&self, names: Option<Vec<String>>) {
+        let defaults = self.default_variables();
+        let mut variables = self.all_variables();
+
+        let keys = variables
+            .iter()
+            .filter(|(key, variable)| {
+                let is_requested = match &names {
+                    Some(names) => names.iter().any(|name| name.eq_ignore_ascii_case(key)),
+                    // Authorization is established by the connection, not by SET
+                    None => key.as_str() != "session_authorization",
+                };
+
+                is_requested && !variable.readonly
+            })
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            match defaults.get(&key) {
+                Some(default) => variables.insert(key, default.clone()),
+                None => variables.remove(&key),
+            };
+        }
+
+        let mut guard = self
+            .variables
+            .write()
+            .expect("failed to unlock variables for writing");
+
+        *guard = Some(variables);
+    }
+
+    /// Returns an error, if the change conflicts with the current state of the session
+    pub fn apply_change(&self, change: SessionChange) -> Result<(), CubeError> {
+        match change {
+            SessionChange::Variables(change) => self.apply_variables_change(change),
+            SessionChange::CreateSqlMacro {
+                definition,
+                or_replace,
+            } => {
+                let name = definition.name.clone();
+                if !self.set_sql_macro(*definition, or_replace) {
+                    return Err(CubeError::user(format!(
+                        "function \"{}\" already exists",
+                        name
+                    )));
+                }
+            }
+            SessionChange::DropSqlMacro { name, if_exists } => {
+                if !self.drop_sql_macro(&name) && !if_exists {
+                    return Err(CubeError::user(format!("function {} does not exist", name)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// SET LOCAL outside of transaction block has no effect
+    fn apply_variables_change(&self, change: VariablesChange) {
+        if change.reset_all {
+            self.reset_variables(None);
+        }
+        if !change.reset.is_empty() {
+            self.reset_variables(Some(change.reset));
+        }
+        if !change.session.is_empty() {
+            self.set_variables(change.session);
+        }
+        if !change.local.is_empty() {
+            if self.transaction_state() == TransactionState::None {
+                self.add_warning("SET LOCAL can only be used in transaction blocks".to_string());
+            } else {
+                self.set_local_variables(change.local);
+            }
         }
     }
 
@@ -168,6 +753,29 @@ impl SessionState {
     }
 }
 
+/// Timeout in the format of PostgreSQL: milliseconds or number with unit (ms, s, min, h, d),
+/// None for 0 and invalid values
+fn parse_timeout(value: &str) -> Option<Duration> {
+    let value = value.trim().to_lowercase();
+    let unit_pos = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let number = value[..unit_pos].parse::<f64>().ok()?;
+    let unit_ms = match value[unit_pos..].trim() {
+        "" | "ms" => 1.0,
+        "s" => 1_000.0,
+        "min" => 60_000.0,
+        "h" => 3_600_000.0,
+        "d" => 86_400_000.0,
+        _ => return None,
+    };
+
+    match (number * unit_ms) as u64 {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    }
+}
+
 #[derive(Debug)]
 pub struct Session {
     // Backref
@@ -184,8 +792,19 @@ impl Session {
             host: self.state.host.clone(),
             user: self.state.user(),
             database: self.state.database(),
+            application_name: self.state.application_name(),
+            started_at: self.state.started_at,
+            query: self.state.current_query(),
+            query_started_at: self.state.query_started_at(),
+            transaction: self.state.transaction_state(),
         }
     }
+
+    /// Drops all prepared statements and portals of the connection. It's safe to call while the
+    /// connection is idle or executing, state is cleared before the next message is processed
+    pub fn reset_extended_state(&self) {
+        self.state.reset_extended_state();
+    }
 }
 
 #[derive(Debug)]
@@ -194,4 +813,11 @@ pub struct SessionProcessList {
     pub user: Option<String>,
     pub host: String,
     pub database: Option<String>,
+    pub application_name: String,
+    pub started_at: SystemTime,
+    // Text of the last query
+    pub query: Option<String>,
+    // None if the session is idle
+    pub query_started_at: Option<SystemTime>,
+    pub transaction: TransactionState,
 }
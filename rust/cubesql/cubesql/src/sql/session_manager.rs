@@ -10,7 +10,7 @@ use crate::CubeError;
 
 use super::{
     server_manager::ServerManager,
-    session::{DatabaseProtocol, Session, SessionProcessList, SessionState},
+    session::{DatabaseProtocol, Notification, Session, SessionProcessList, SessionState},
 };
 
 #[derive(Debug)]
@@ -69,11 +69,94 @@ impl SessionManager {
             .collect::<Vec<SessionProcessList>>()
     }
 
+    /// Live sessions, the snapshot is taken at the time of the call
+    pub fn sessions(&self) -> impl Iterator<Item = Arc<Session>> {
+        let guard = self
+            .sessions
+            .read()
+            .expect("failed to unlock sessions for listing sessions");
+
+        guard.values().cloned().collect::<Vec<_>>().into_iter()
+    }
+
+    /// Cancels the query which is executing in the session, secret must match the one
+    /// which was sent to the client in BackendKeyData
+    pub fn cancel_query(&self, connection_id: u32, secret: u32) -> bool {
+        let guard = self
+            .sessions
+            .read()
+            .expect("failed to unlock sessions for canceling query");
+
+        match guard.get(&connection_id) {
+            Some(session) if session.state.secret == secret => session.state.cancel_query(),
+            _ => false,
+        }
+    }
+
+    /// Terminates the session, its connection will be closed; used by pg_terminate_backend
+    pub fn terminate_session(&self, connection_id: u32) -> bool {
+        let guard = self
+            .sessions
+            .read()
+            .expect("failed to unlock sessions for terminating session");
+
+        match guard.get(&connection_id) {
+            Some(session) => {
+                session.state.terminate();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops prepared statements and portals of the session, used by pools to reset connections
+    pub fn reset_session_extended_state(&self, connection_id: u32) -> bool {
+        let guard = self
+            .sessions
+            .read()
+            .expect("failed to unlock sessions for resetting session");
+
+        match guard.get(&connection_id) {
+            Some(session) => {
+                session.reset_extended_state();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Delivers NOTIFY to all sessions which listen to the channel, including the sender.
+    /// Returns the number of sessions which received the notification
+    pub fn notify(&self, process_id: u32, channel: &str, payload: &str) -> usize {
+        let guard = self
+            .sessions
+            .read()
+            .expect("failed to unlock sessions for notifying");
+
+        let mut delivered = 0;
+        for session in guard.values() {
+            if session.state.is_listening(channel) {
+                session.state.push_notification(Notification {
+                    process_id,
+                    channel: channel.to_string(),
+                    payload: payload.to_string(),
+                });
+                delivered += 1;
+            }
+        }
+
+        delivered
+    }
+
     pub fn drop_session(&self, connection_id: u32) {
         let mut guard = self
             .sessions
             .write()
             .expect("failed to unlock sessions for droping session");
-        guard.remove(&connection_id);
+
+        // Execution which may be still running in background (spawned by DataFusion) must stop
+        if let Some(session) = guard.remove(&connection_id) {
+            session.state.terminate();
+        }
     }
 }
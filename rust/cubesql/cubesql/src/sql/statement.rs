@@ -1,14 +1,28 @@
 use msql_srv::{Column, ColumnFlags, ColumnType};
-use pg_srv::BindValue;
+use pg_srv::{protocol::Format, BindValue, PgTypeId};
 use sqlparser::ast;
 use sqlparser::ast::Value;
+use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+
+use crate::{
+    compile::{engine::df::datetime, macros::type_oid_by_name},
+    transport::MetaContext,
+};
 
 trait Visitor<'ast> {
     fn visit_value(&mut self, _val: &mut ast::Value) {}
 
     fn visit_identifier(&mut self, _identifier: &mut ast::Ident) {}
 
+    /// Called for every expression before its children are visited
+    fn enter_expr(&mut self, _expr: &mut ast::Expr) {}
+
+    /// Called for every query before its body, LIMIT and OFFSET are visited
+    fn enter_query(&mut self, _query: &mut ast::Query) {}
+
     fn visit_expr(&mut self, expr: &mut ast::Expr) {
+        self.enter_expr(expr);
+
         match expr {
             ast::Expr::Value(value) => self.visit_value(value),
             ast::Expr::Identifier(identifier) => self.visit_identifier(identifier),
@@ -35,6 +49,24 @@ trait Visitor<'ast> {
                     self.visit_expr(v);
                 }
             }
+            ast::Expr::UnaryOp { expr, .. }
+            | ast::Expr::Extract { expr, .. }
+            | ast::Expr::IsNull(expr)
+            | ast::Expr::IsNotNull(expr) => self.visit_expr(&mut *expr),
+            ast::Expr::Function(fun) => {
+                for arg in fun.args.iter_mut() {
+                    match arg {
+                        ast::FunctionArg::Named {
+                            arg: ast::FunctionArgExpr::Expr(expr),
+                            ..
+                        }
+                        | ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(expr)) => {
+                            self.visit_expr(expr)
+                        }
+                        _ => {}
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -80,6 +112,14 @@ trait Visitor<'ast> {
         for from in &mut select.from {
             self.visit_table_with_joins(from);
         }
+
+        for group_by in &mut select.group_by {
+            self.visit_expr(group_by);
+        }
+
+        if let Some(having) = &mut select.having {
+            self.visit_expr(having);
+        };
     }
 
     fn visit_set_expr(&mut self, body: &mut ast::SetExpr) {
@@ -95,7 +135,21 @@ trait Visitor<'ast> {
     }
 
     fn visit_query(&mut self, query: &mut Box<ast::Query>) {
+        self.enter_query(query);
+
         self.visit_set_expr(&mut query.body);
+
+        for order_by in &mut query.order_by {
+            self.visit_expr(&mut order_by.expr);
+        }
+
+        if let Some(limit) = &mut query.limit {
+            self.visit_expr(limit);
+        }
+
+        if let Some(offset) = &mut query.offset {
+            self.visit_expr(&mut offset.value);
+        }
     }
 
     fn visit_statement(&mut self, statement: &mut ast::Statement) {
@@ -146,6 +200,736 @@ impl<'ast> Visitor<'ast> for StatementParamsFinder {
     }
 }
 
+/// Counts expressions of the statement, every column reference is counted as an expression
+#[derive(Debug)]
+pub struct StatementExpressionsCounter {
+    count: usize,
+}
+
+impl StatementExpressionsCounter {
+    pub fn new() -> Self {
+        Self { count: 0 }
+    }
+
+    pub fn count(mut self, stmt: &ast::Statement) -> usize {
+        self.visit_statement(&mut stmt.clone());
+
+        self.count
+    }
+}
+
+impl<'ast> Visitor<'ast> for StatementExpressionsCounter {
+    fn enter_expr(&mut self, _expr: &mut ast::Expr) {
+        self.count += 1;
+    }
+}
+
+/// Finds the max nesting depth of subqueries, the depth of the top-level query is 0. Subqueries
+/// deeper than the limit are not visited, so the result is at most limit + 1
+#[derive(Debug)]
+pub struct StatementSubqueryDepthFinder {
+    limit: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl StatementSubqueryDepthFinder {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            depth: 0,
+            max_depth: 0,
+        }
+    }
+
+    pub fn find(mut self, stmt: &ast::Statement) -> usize {
+        self.visit_statement(&mut stmt.clone());
+
+        self.max_depth
+    }
+}
+
+impl<'ast> Visitor<'ast> for StatementSubqueryDepthFinder {
+    fn enter_expr(&mut self, expr: &mut ast::Expr) {
+        // Subqueries of expressions are not visited by default
+        match expr {
+            ast::Expr::Subquery(subquery) | ast::Expr::Exists(subquery) => {
+                self.visit_query(subquery)
+            }
+            ast::Expr::InSubquery { subquery, .. } => self.visit_query(subquery),
+            _ => {}
+        }
+    }
+
+    fn visit_query(&mut self, query: &mut Box<ast::Query>) {
+        let depth = self.depth;
+        self.max_depth = self.max_depth.max(depth);
+        if depth > self.limit {
+            return;
+        }
+
+        self.depth += 1;
+        self.visit_set_expr(&mut query.body);
+        for order_by in &mut query.order_by {
+            self.visit_expr(&mut order_by.expr);
+        }
+        self.depth -= 1;
+    }
+}
+
+/// Finds LATERAL subqueries of the statement, they're reported by alias or by the subquery
+#[derive(Debug)]
+pub struct StatementLateralFinder {
+    found: Option<String>,
+}
+
+impl StatementLateralFinder {
+    pub fn new() -> Self {
+        Self { found: None }
+    }
+
+    pub fn find(mut self, stmt: &ast::Statement) -> Option<String> {
+        self.visit_statement(&mut stmt.clone());
+
+        self.found
+    }
+}
+
+impl<'ast> Visitor<'ast> for StatementLateralFinder {
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) {
+        if let ast::TableFactor::Derived {
+            lateral,
+            subquery,
+            alias,
+        } = factor
+        {
+            if *lateral && self.found.is_none() {
+                self.found = Some(match alias {
+                    Some(alias) => alias.name.to_string(),
+                    None => format!("({})", subquery),
+                });
+            }
+
+            self.visit_query(subquery);
+        }
+    }
+}
+
+/// PostgreSQL functions which are called without parentheses (SQL value functions) and
+/// the functions which implement them: SELECT current_user -> SELECT current_user()
+const SQL_VALUE_FUNCTIONS: [(&str, &str); 4] = [
+    ("current_user", "current_user"),
+    ("session_user", "session_user"),
+    ("current_schema", "current_schema"),
+    ("current_catalog", "current_database"),
+];
+
+#[derive(Debug)]
+pub struct StatementSqlValueFunctionsReplacer {}
+
+impl StatementSqlValueFunctionsReplacer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result);
+
+        result
+    }
+
+    fn function_name(ident: &ast::Ident) -> Option<&'static str> {
+        if ident.quote_style.is_some() {
+            return None;
+        }
+
+        SQL_VALUE_FUNCTIONS
+            .iter()
+            .find(|(name, _)| ident.value.eq_ignore_ascii_case(name))
+            .map(|(_, function)| *function)
+    }
+}
+
+impl<'ast> Visitor<'ast> for StatementSqlValueFunctionsReplacer {
+    fn visit_select_item(&mut self, select: &mut ast::SelectItem) {
+        // Column keeps the name of the value function, as it's in PostgreSQL
+        if let ast::SelectItem::UnnamedExpr(ast::Expr::Identifier(ident)) = select {
+            if Self::function_name(ident).is_some() {
+                *select = ast::SelectItem::ExprWithAlias {
+                    alias: ast::Ident::new(ident.value.to_lowercase()),
+                    expr: ast::Expr::Identifier(ident.clone()),
+                };
+            }
+        }
+
+        match select {
+            ast::SelectItem::ExprWithAlias { expr, .. } => self.visit_expr(expr),
+            ast::SelectItem::UnnamedExpr(expr) => self.visit_expr(expr),
+            _ => {}
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut ast::Expr) {
+        let function = match expr {
+            ast::Expr::Identifier(ident) => Self::function_name(ident),
+            _ => None,
+        };
+
+        if let Some(function) = function {
+            *expr = ast::Expr::Function(ast::Function {
+                name: ast::ObjectName(vec![ast::Ident::new(function)]),
+                args: vec![],
+                over: None,
+                distinct: false,
+            });
+        }
+    }
+}
+
+/// Set-returning functions which can be used in FROM, they're planned as projection of subquery:
+/// FROM generate_series(1, 3) AS s(n) -> FROM (SELECT generate_series(1, 3) AS n) AS s
+const TABLE_FUNCTIONS: [&str; 3] = ["generate_series", "generate_subscripts", "unnest"];
+
+#[derive(Debug)]
+pub struct StatementTableFunctionsReplacer {}
+
+impl StatementTableFunctionsReplacer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result);
+
+        result
+    }
+
+    fn subquery(
+        function: &ast::Ident,
+        args: &Vec<ast::FunctionArg>,
+        alias: &Option<ast::TableAlias>,
+    ) -> Option<ast::TableFactor> {
+        // Column is named by the column alias, the table alias or the function, as in PostgreSQL
+        let (table, column) = match alias {
+            Some(alias) => (
+                alias.name.clone(),
+                alias.columns.first().unwrap_or(&alias.name).clone(),
+            ),
+            None => (function.clone(), function.clone()),
+        };
+
+        let sql = format!(
+            "SELECT * FROM (SELECT {}({}) AS {}) AS {}",
+            function,
+            args.iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            column,
+            table
+        );
+        let mut stmts = Parser::parse_sql(&PostgreSqlDialect {}, &sql).ok()?;
+        match stmts.pop() {
+            Some(ast::Statement::Query(query)) => match query.body {
+                ast::SetExpr::Select(mut select) => select.from.pop().map(|from| from.relation),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast> for StatementTableFunctionsReplacer {
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) {
+        if let ast::TableFactor::Table {
+            name, alias, args, ..
+        } = factor
+        {
+            let function = match name.0.last() {
+                Some(ident)
+                    if !args.is_empty()
+                        && ident.quote_style.is_none()
+                        && TABLE_FUNCTIONS
+                            .iter()
+                            .any(|f| ident.value.eq_ignore_ascii_case(f)) =>
+                {
+                    ast::Ident::new(ident.value.to_lowercase())
+                }
+                _ => return,
+            };
+
+            if let Some(subquery) = Self::subquery(&function, args, alias) {
+                *factor = subquery;
+            }
+        }
+
+        if let ast::TableFactor::Derived { subquery, .. } = factor {
+            self.visit_query(subquery);
+        }
+    }
+}
+
+/// Fields which DataFusion's date_trunc supports, it compares them case-sensitively
+const DF_DATE_TRUNC_FIELDS: [&str; 8] = [
+    "second", "minute", "hour", "day", "week", "month", "quarter", "year",
+];
+
+/// Fields which DataFusion's date_part and EXTRACT support
+const DF_DATE_PART_FIELDS: [&str; 7] = ["year", "month", "week", "day", "hour", "minute", "second"];
+
+/// Built-in functions of DataFusion can't be overridden by UDFs, so date functions whose
+/// PostgreSQL semantics differ are redirected to UDFs: date_trunc and date_part with fields
+/// missing in DataFusion (EXTRACT included) and to_timestamp. Supported fields keep built-ins,
+/// as rewrites to Cube granularities match them.
+#[derive(Debug)]
+pub struct StatementDateTimeFunctionsReplacer {}
+
+impl StatementDateTimeFunctionsReplacer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result);
+
+        result
+    }
+
+    fn field_literal(arg: Option<&ast::FunctionArg>) -> Option<String> {
+        match arg {
+            Some(ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(ast::Expr::Value(
+                ast::Value::SingleQuotedString(field),
+            )))) => Some(field.clone()),
+            _ => None,
+        }
+    }
+
+    /// Column name as in PostgreSQL and the expression calling UDF
+    fn replacement(expr: &ast::Expr) -> Option<(&'static str, ast::Expr)> {
+        let udf_call = |name: &str, args: Vec<ast::FunctionArg>| {
+            ast::Expr::Function(ast::Function {
+                name: ast::ObjectName(vec![ast::Ident::new(name)]),
+                args,
+                over: None,
+                distinct: false,
+            })
+        };
+
+        match expr {
+            ast::Expr::Extract { field, expr } => {
+                let field = field.to_string().to_lowercase();
+                if DF_DATE_PART_FIELDS.contains(&field.as_str()) {
+                    return None;
+                }
+
+                Some((
+                    "extract",
+                    udf_call(
+                        "pg_date_part",
+                        vec![
+                            ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(
+                                ast::Expr::Value(ast::Value::SingleQuotedString(field)),
+                            )),
+                            ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(*expr.clone())),
+                        ],
+                    ),
+                ))
+            }
+            ast::Expr::Function(fun) if fun.over.is_none() => {
+                let name = match fun.name.0.as_slice() {
+                    [name] => name,
+                    [schema, name] if schema.value.eq_ignore_ascii_case("pg_catalog") => name,
+                    _ => return None,
+                };
+                if name.quote_style.is_some() {
+                    return None;
+                }
+
+                let field = Self::field_literal(fun.args.first());
+                // Spellings of supported fields are canonicalized to keep the built-in function,
+                // so it can be pushed down to Cube as a time dimension granularity
+                let canonical_call = |name: &str, field: &str| {
+                    let mut args = fun.args.clone();
+                    args[0] = ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(
+                        ast::Expr::Value(ast::Value::SingleQuotedString(field.to_string())),
+                    ));
+
+                    udf_call(name, args)
+                };
+
+                match name.value.to_lowercase().as_str() {
+                    "date_trunc" => match field {
+                        Some(field) if DF_DATE_TRUNC_FIELDS.contains(&field.as_str()) => None,
+                        Some(field) => match datetime::timestamp_field(&field) {
+                            Some(canonical) if DF_DATE_TRUNC_FIELDS.contains(&canonical) => {
+                                Some(("date_trunc", canonical_call("date_trunc", canonical)))
+                            }
+                            _ => Some(("date_trunc", udf_call("pg_date_trunc", fun.args.clone()))),
+                        },
+                        _ => None,
+                    },
+                    "date_part" => match field {
+                        Some(field) if DF_DATE_PART_FIELDS.contains(&field.as_str()) => None,
+                        Some(field) => match datetime::timestamp_field(&field) {
+                            Some(canonical) if DF_DATE_PART_FIELDS.contains(&canonical) => {
+                                Some(("date_part", canonical_call("date_part", canonical)))
+                            }
+                            _ => Some(("date_part", udf_call("pg_date_part", fun.args.clone()))),
+                        },
+                        _ => None,
+                    },
+                    "to_timestamp" => Some((
+                        "to_timestamp",
+                        udf_call("pg_to_timestamp", fun.args.clone()),
+                    )),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast> for StatementDateTimeFunctionsReplacer {
+    fn visit_select_item(&mut self, select: &mut ast::SelectItem) {
+        // Column keeps the name of the replaced function, as it's in PostgreSQL
+        if let ast::SelectItem::UnnamedExpr(expr) = select {
+            if let Some((column, _)) = Self::replacement(expr) {
+                *select = ast::SelectItem::ExprWithAlias {
+                    alias: ast::Ident::new(column),
+                    expr: expr.clone(),
+                };
+            }
+        }
+
+        match select {
+            ast::SelectItem::ExprWithAlias { expr, .. } => self.visit_expr(expr),
+            ast::SelectItem::UnnamedExpr(expr) => self.visit_expr(expr),
+            _ => {}
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut ast::Expr) {
+        if let Some((_, replacement)) = Self::replacement(expr) {
+            *expr = replacement;
+        }
+    }
+}
+
+/// PostgreSQL types which aren't known to DataFusion and the types they are cast to instead:
+/// '18000'::oid -> CAST('18000' AS BIGINT), which is printed as the integer
+const CUSTOM_CAST_TYPES: [(&str, ast::DataType); 1] = [("oid", ast::DataType::BigInt(None))];
+
+#[derive(Debug)]
+pub struct StatementCastsReplacer {}
+
+impl StatementCastsReplacer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result);
+
+        result
+    }
+}
+
+impl<'ast> Visitor<'ast> for StatementCastsReplacer {
+    fn enter_expr(&mut self, expr: &mut ast::Expr) {
+        if let ast::Expr::Cast { data_type, .. } = expr {
+            let cast_type = match data_type {
+                ast::DataType::Custom(name) => match name.0.as_slice() {
+                    [name] | [_, name] if name.quote_style.is_none() => CUSTOM_CAST_TYPES
+                        .iter()
+                        .find(|(typname, _)| name.value.eq_ignore_ascii_case(typname)),
+                    _ => None,
+                },
+                _ => None,
+            };
+
+            if let Some((_, cast_type)) = cast_type {
+                *data_type = cast_type.clone();
+            }
+        }
+    }
+}
+
+/// Comparisons with arrays, which are used by clients to bind lists of values, are replaced with
+/// IN to push them down to Cube as filters: x = ANY(ARRAY[1, 2]) -> x IN (1, 2),
+/// x <> ALL('{a,b}') -> x NOT IN ('a', 'b')
+#[derive(Debug)]
+pub struct StatementArrayComparisonsReplacer {}
+
+impl StatementArrayComparisonsReplacer {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
+        let mut result = stmt.clone();
+
+        self.visit_statement(&mut result);
+
+        result
+    }
+
+    /// Argument of ANY(...) or ALL(...)
+    fn quantifier_arg<'e>(expr: &'e ast::Expr, quantifier: &str) -> Option<&'e ast::Expr> {
+        match expr {
+            ast::Expr::Function(fun) if fun.over.is_none() && !fun.distinct => {
+                match (fun.name.0.as_slice(), fun.args.as_slice()) {
+                    ([name], [ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(arg))])
+                        if name.quote_style.is_none()
+                            && name.value.eq_ignore_ascii_case(quantifier) =>
+                    {
+                        Some(arg)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Elements of ARRAY[...] or of text array literal '{...}'
+    fn array_elements(expr: &ast::Expr) -> Option<Vec<ast::Expr>> {
+        match expr {
+            ast::Expr::Array(array) => Some(array.elem.clone()),
+            ast::Expr::Value(ast::Value::SingleQuotedString(value)) => {
+                match BindValue::decode(value.as_bytes(), PgTypeId::ArrayText, Format::Text) {
+                    Ok(BindValue::Array(values)) => {
+                        Some(values.iter().map(bind_value_to_expr).collect())
+                    }
+                    _ => None,
+                }
+            }
+            ast::Expr::Cast { expr, .. } | ast::Expr::Nested(expr) => Self::array_elements(expr),
+            _ => None,
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast> for StatementArrayComparisonsReplacer {
+    fn enter_expr(&mut self, expr: &mut ast::Expr) {
+        let replacement = match expr {
+            ast::Expr::BinaryOp { left, op, right } => {
+                let (arg, negated) = match op {
+                    ast::BinaryOperator::Eq => (Self::quantifier_arg(right, "any"), false),
+                    ast::BinaryOperator::NotEq => (Self::quantifier_arg(right, "all"), true),
+                    _ => (None, false),
+                };
+
+                match arg.and_then(Self::array_elements) {
+                    // Comparison with any element of empty array is false, with all is true
+                    Some(list) if list.is_empty() => {
+                        Some(ast::Expr::Value(ast::Value::Boolean(negated)))
+                    }
+                    Some(list) => Some(ast::Expr::InList {
+                        expr: left.clone(),
+                        list,
+                        negated,
+                    }),
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(replacement) = replacement {
+            *expr = replacement;
+        }
+    }
+}
+
+/// Zero-based position of numbered placeholder: $1 -> 0
+fn placeholder_position(name: &str) -> Option<usize> {
+    match name.strip_prefix('$').map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) if n > 0 => Some(n - 1),
+        _ => None,
+    }
+}
+
+/// Infers types of placeholders ($1, $2) from the query in which they are used:
+/// explicit casts ($1::int) and comparisons with known columns (col = $1).
+/// Unknown types are reported as None.
+#[derive(Debug)]
+pub struct StatementParamsTypesFinder<'a> {
+    meta: &'a MetaContext,
+    position: usize,
+    types: Vec<Option<PgTypeId>>,
+}
+
+impl<'a> StatementParamsTypesFinder<'a> {
+    pub fn new(meta: &'a MetaContext) -> Self {
+        Self {
+            meta,
+            position: 0,
+            types: vec![],
+        }
+    }
+
+    pub fn find(mut self, stmt: &ast::Statement) -> Vec<Option<PgTypeId>> {
+        self.visit_statement(&mut stmt.clone());
+
+        self.types
+    }
+
+    fn placeholder_index(&self, expr: &ast::Expr) -> Option<usize> {
+        match expr {
+            ast::Expr::Value(Value::Placeholder(name)) => placeholder_position(name),
+            ast::Expr::Nested(v) => self.placeholder_index(v),
+            _ => None,
+        }
+    }
+
+    fn column_type(&self, expr: &ast::Expr) -> Option<PgTypeId> {
+        let name = match expr {
+            ast::Expr::Identifier(ident) => &ident.value,
+            ast::Expr::CompoundIdentifier(idents) => &idents.last()?.value,
+            ast::Expr::Nested(v) => return self.column_type(v),
+            _ => return None,
+        };
+
+        let mut types = self
+            .meta
+            .tables
+            .iter()
+            .flat_map(|table| table.columns.iter())
+            .filter(|column| column.name.eq_ignore_ascii_case(name))
+            .map(|column| column.column_type.to_pg_tid());
+
+        let first = types.next()?;
+        // Same column name with different types in different cubes, we cannot choose
+        if types.any(|t| t != first) {
+            None
+        } else {
+            Some(first)
+        }
+    }
+
+    fn reserve(&mut self, index: usize) {
+        if self.types.len() <= index {
+            self.types.resize(index + 1, None);
+        }
+    }
+
+    fn infer(&mut self, placeholder: &ast::Expr, typ: Option<PgTypeId>) {
+        if let (Some(index), Some(typ)) = (self.placeholder_index(placeholder), typ) {
+            self.reserve(index);
+
+            // First usage wins
+            if self.types[index].is_none() {
+                self.types[index] = Some(typ);
+            }
+        }
+    }
+
+    fn infer_from_column(&mut self, column: &ast::Expr, placeholder: &ast::Expr) {
+        let typ = self.column_type(column);
+        self.infer(placeholder, typ);
+    }
+}
+
+impl<'a, 'ast> Visitor<'ast> for StatementParamsTypesFinder<'a> {
+    fn enter_query(&mut self, query: &mut ast::Query) {
+        if let Some(limit) = &query.limit {
+            self.infer(limit, Some(PgTypeId::INT8));
+        }
+
+        if let Some(offset) = &query.offset {
+            self.infer(&offset.value, Some(PgTypeId::INT8));
+        }
+    }
+
+    fn visit_value(&mut self, v: &mut ast::Value) {
+        match v {
+            Value::Placeholder(name) => {
+                let index = placeholder_position(name).unwrap_or(self.position);
+                self.position += 1;
+
+                self.reserve(index);
+            }
+            _ => {}
+        }
+    }
+
+    fn enter_expr(&mut self, expr: &mut ast::Expr) {
+        match expr {
+            ast::Expr::Cast {
+                expr, data_type, ..
+            } => {
+                let typ = type_oid_by_name(&data_type.to_string())
+                    .ok()
+                    .and_then(PgTypeId::from_oid);
+                self.infer(expr, typ);
+            }
+            ast::Expr::BinaryOp { left, op, right } => match op {
+                ast::BinaryOperator::Eq
+                | ast::BinaryOperator::NotEq
+                | ast::BinaryOperator::Lt
+                | ast::BinaryOperator::LtEq
+                | ast::BinaryOperator::Gt
+                | ast::BinaryOperator::GtEq => {
+                    self.infer_from_column(left, right);
+                    self.infer_from_column(right, left);
+
+                    // Array of the column type for col = ANY($1) and col <> ALL($1)
+                    let quantified = StatementArrayComparisonsReplacer::quantifier_arg(
+                        right, "any",
+                    )
+                    .or_else(|| StatementArrayComparisonsReplacer::quantifier_arg(right, "all"));
+                    if let Some(arg) = quantified {
+                        let typ = self.column_type(left).and_then(PgTypeId::array_type);
+                        self.infer(arg, typ);
+                    }
+                }
+                _ => {}
+            },
+            ast::Expr::Between {
+                expr, low, high, ..
+            } => {
+                self.infer_from_column(expr, low);
+                self.infer_from_column(expr, high);
+            }
+            ast::Expr::InList { expr, list, .. } => {
+                for item in list.iter() {
+                    self.infer_from_column(expr, item);
+                }
+            }
+            ast::Expr::Extract { expr, .. } => {
+                self.infer(expr, Some(PgTypeId::TIMESTAMP));
+            }
+            ast::Expr::Function(fun) => {
+                let name = fun.name.to_string().to_lowercase();
+                // Second argument of date functions is a date: date_trunc('day', $1)
+                let typ = match name.as_str() {
+                    "date_trunc"
+                    | "pg_catalog.date_trunc"
+                    | "date_part"
+                    | "pg_catalog.date_part" => Some(PgTypeId::TIMESTAMP),
+                    _ => None,
+                };
+
+                if let Some(ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(arg))) =
+                    fun.args.get(1)
+                {
+                    self.infer(arg, typ);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct StatementParamsBinder {
     position: usize,
@@ -166,50 +950,66 @@ impl StatementParamsBinder {
 }
 
 impl<'ast> Visitor<'ast> for StatementParamsBinder {
-    fn visit_value(&mut self, value: &mut ast::Value) {
-        match &value {
-            ast::Value::Placeholder(_) => {
-                let to_replace = self.values.get(self.position).expect(
-                    format!(
-                        "Unable to find value for placeholder at position: {}",
-                        self.position
-                    )
-                    .as_str(),
-                );
-                self.position += 1;
+    fn enter_expr(&mut self, expr: &mut ast::Expr) {
+        if let ast::Expr::Value(ast::Value::Placeholder(name)) = &expr {
+            let index = placeholder_position(name).unwrap_or(self.position);
+            let to_replace = self.values.get(index).expect(
+                format!(
+                    "Unable to find value for placeholder at position: {}",
+                    index
+                )
+                .as_str(),
+            );
+            self.position += 1;
 
-                match to_replace {
-                    BindValue::String(v) => {
-                        *value = ast::Value::SingleQuotedString(v.clone());
-                    }
-                    BindValue::Bool(v) => {
-                        *value = ast::Value::Boolean(*v);
-                    }
-                    BindValue::UInt64(v) => {
-                        *value = ast::Value::Number(v.to_string(), false);
-                    }
-                    BindValue::Int64(v) => {
-                        *value = ast::Value::Number(v.to_string(), *v < 0_i64);
-                    }
-                    BindValue::Float64(v) => {
-                        *value = ast::Value::Number(v.to_string(), *v < 0_f64);
-                    }
-                    BindValue::Null => {
-                        *value = ast::Value::Null;
-                    }
-                }
-            }
-            _ => {}
+            *expr = bind_value_to_expr(to_replace);
         }
     }
 }
 
+/// Typed literal for the bound value, negative numbers are represented as unary minus,
+/// the same way as they are parsed from SQL
+fn bind_value_to_expr(value: &BindValue) -> ast::Expr {
+    let negative = |expr: ast::Expr| ast::Expr::UnaryOp {
+        op: ast::UnaryOperator::Minus,
+        expr: Box::new(expr),
+    };
+    let number = |n: String| ast::Expr::Value(ast::Value::Number(n, false));
+
+    match value {
+        BindValue::String(v) => ast::Expr::Value(ast::Value::SingleQuotedString(v.clone())),
+        BindValue::Bool(v) => ast::Expr::Value(ast::Value::Boolean(*v)),
+        BindValue::UInt64(v) => number(v.to_string()),
+        BindValue::Int64(v) if *v < 0 => negative(number(v.unsigned_abs().to_string())),
+        BindValue::Int64(v) => number(v.to_string()),
+        // NaN and Infinity don't have literals
+        BindValue::Float64(v) if !v.is_finite() => ast::Expr::Cast {
+            expr: Box::new(ast::Expr::Value(ast::Value::SingleQuotedString(
+                v.to_string(),
+            ))),
+            data_type: ast::DataType::Double,
+        },
+        // Debug representation keeps the fractional part: 2.0 instead of 2
+        BindValue::Float64(v) if *v < 0.0 => negative(number(format!("{:?}", -v))),
+        BindValue::Float64(v) => number(format!("{:?}", v)),
+        BindValue::Array(values) => ast::Expr::Array(ast::Array {
+            elem: values.iter().map(bind_value_to_expr).collect(),
+            named: true,
+        }),
+        BindValue::Null => ast::Expr::Value(ast::Value::Null),
+    }
+}
+
+/// Replaces placeholders with dummy values of their types, to plan the statement before Bind
 #[derive(Debug)]
-pub struct StatementPlaceholderReplacer {}
+pub struct StatementPlaceholderReplacer<'a> {
+    types: &'a [PgTypeId],
+    position: usize,
+}
 
-impl StatementPlaceholderReplacer {
-    pub fn new() -> Self {
-        Self {}
+impl<'a> StatementPlaceholderReplacer<'a> {
+    pub fn new(types: &'a [PgTypeId]) -> Self {
+        Self { types, position: 0 }
     }
 
     pub fn replace(mut self, stmt: &ast::Statement) -> ast::Statement {
@@ -219,15 +1019,33 @@ impl StatementPlaceholderReplacer {
 
         result
     }
-}
 
-impl<'ast> Visitor<'ast> for StatementPlaceholderReplacer {
-    fn visit_value(&mut self, value: &mut ast::Value) {
-        match &value {
-            ast::Value::Placeholder(_) => {
-                *value = ast::Value::SingleQuotedString("replaced_placeholder".to_string());
+    fn placeholder_value(typ: Option<PgTypeId>) -> BindValue {
+        match typ {
+            Some(PgTypeId::INT2 | PgTypeId::INT4 | PgTypeId::INT8 | PgTypeId::OID) => {
+                BindValue::Int64(0)
             }
-            _ => {}
+            Some(PgTypeId::FLOAT4 | PgTypeId::FLOAT8 | PgTypeId::NUMERIC) => {
+                BindValue::Float64(0.0)
+            }
+            Some(PgTypeId::BOOL) => BindValue::Bool(false),
+            Some(typ) if typ.array_element().is_some() => {
+                BindValue::Array(vec![Self::placeholder_value(typ.array_element())])
+            }
+            _ => BindValue::String("replaced_placeholder".to_string()),
+        }
+    }
+}
+
+impl<'a, 'ast> Visitor<'ast> for StatementPlaceholderReplacer<'a> {
+    fn enter_expr(&mut self, expr: &mut ast::Expr) {
+        if let ast::Expr::Value(ast::Value::Placeholder(name)) = &expr {
+            let index = placeholder_position(name).unwrap_or(self.position);
+            self.position += 1;
+
+            let value = Self::placeholder_value(self.types.get(index).cloned());
+
+            *expr = bind_value_to_expr(&value);
         }
     }
 }
@@ -236,7 +1054,7 @@ impl<'ast> Visitor<'ast> for StatementPlaceholderReplacer {
 mod tests {
     use super::*;
     use crate::CubeError;
-    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+    use cubeclient::models::{V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure};
 
     fn test_binder(input: &str, output: &str, values: Vec<BindValue>) -> Result<(), CubeError> {
         let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
@@ -271,7 +1089,7 @@ mod tests {
                 FROM testdata
                 WHERE fieldA = $1 AND fieldB = $2 OR (fieldC = $3 AND fieldD = $4)
             "#,
-            "SELECT * FROM testdata WHERE fieldA = 'test' AND fieldB = 1 OR (fieldC = 2 AND fieldD = 2)",
+            "SELECT * FROM testdata WHERE fieldA = 'test' AND fieldB = 1 OR (fieldC = 2 AND fieldD = 2.0)",
             vec![
                 BindValue::String("test".to_string()),
                 BindValue::Int64(1),
@@ -295,6 +1113,42 @@ mod tests {
             ],
         )?;
 
+        // Typed literals
+        test_binder(
+            "SELECT $1, $2, $3, $4, $5, $6",
+            "SELECT - 5, - 2.5, 'it''s', NULL, CAST('NaN' AS DOUBLE), - 9223372036854775808",
+            vec![
+                BindValue::Int64(-5),
+                BindValue::Float64(-2.5),
+                BindValue::String("it's".to_string()),
+                BindValue::Null,
+                BindValue::Float64(f64::NAN),
+                BindValue::Int64(i64::MIN),
+            ],
+        )?;
+
+        // Arrays
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA = ANY($1)",
+            "SELECT * FROM testdata WHERE fieldA = ANY(ARRAY['test1', NULL])",
+            vec![BindValue::Array(vec![
+                BindValue::String("test1".to_string()),
+                BindValue::Null,
+            ])],
+        )?;
+
+        // HAVING
+        test_binder(
+            r#"
+                SELECT fieldA, COUNT(*)
+                FROM testdata
+                GROUP BY fieldA
+                HAVING COUNT(*) > $1
+            "#,
+            "SELECT fieldA, COUNT(*) FROM testdata GROUP BY fieldA HAVING COUNT(*) > 5",
+            vec![BindValue::Int64(5)],
+        )?;
+
         // BETWEEN
         test_binder(
             r#"
@@ -342,6 +1196,21 @@ mod tests {
             vec![BindValue::String("test1".to_string())],
         )?;
 
+        // function arguments, numbered placeholders are bound by number
+        test_binder(
+            r#"
+                SELECT LOWER($3)
+                FROM testdata
+                WHERE fieldA = $2 AND fieldB = $1
+            "#,
+            "SELECT LOWER('TEST') FROM testdata WHERE fieldA = 'test2' AND fieldB = 1",
+            vec![
+                BindValue::Int64(1),
+                BindValue::String("test2".to_string()),
+                BindValue::String("TEST".to_string()),
+            ],
+        )?;
+
         Ok(())
     }
 
@@ -364,10 +1233,144 @@ mod tests {
         Ok(())
     }
 
-    fn assert_placeholder_replacer(input: &str, output: &str) -> Result<(), CubeError> {
+    fn assert_params_types_finder(
+        input: &str,
+        expected: Vec<Option<PgTypeId>>,
+    ) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let meta = MetaContext::new(vec![V1CubeMeta {
+            name: "KibanaSampleDataEcommerce".to_string(),
+            title: None,
+            meta: None,
+            joins: None,
+            dimensions: vec![
+                V1CubeMetaDimension {
+                    name: "KibanaSampleDataEcommerce.order_date".to_string(),
+                    _type: "time".to_string(),
+                },
+                V1CubeMetaDimension {
+                    name: "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                    _type: "string".to_string(),
+                },
+            ],
+            measures: vec![V1CubeMetaMeasure {
+                name: "KibanaSampleDataEcommerce.count".to_string(),
+                title: None,
+                _type: "number".to_string(),
+                agg_type: Some("count".to_string()),
+            }],
+            segments: vec![],
+        }]);
+
+        let finder = StatementParamsTypesFinder::new(&meta);
+        let result = finder.find(&stmts[0]);
+
+        assert_eq!(result, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_types_finder() -> Result<(), CubeError> {
+        assert_params_types_finder("SELECT $1", vec![None])?;
+        assert_params_types_finder(
+            "SELECT $1::int, CAST($2 AS bigint), $3::text",
+            vec![
+                Some(PgTypeId::INT4),
+                Some(PgTypeId::INT8),
+                Some(PgTypeId::TEXT),
+            ],
+        )?;
+        assert_params_types_finder(
+            r#"
+                SELECT COUNT(*)
+                FROM KibanaSampleDataEcommerce
+                WHERE customer_gender = $2 AND count > $1 AND $3 < KibanaSampleDataEcommerce.order_date
+            "#,
+            vec![
+                Some(PgTypeId::INT8),
+                Some(PgTypeId::TEXT),
+                Some(PgTypeId::TIMESTAMP),
+            ],
+        )?;
+        assert_params_types_finder(
+            r#"
+                SELECT *
+                FROM KibanaSampleDataEcommerce
+                WHERE order_date BETWEEN $1 AND $2 OR count IN ($3, unknown_column)
+            "#,
+            vec![
+                Some(PgTypeId::TIMESTAMP),
+                Some(PgTypeId::TIMESTAMP),
+                Some(PgTypeId::INT8),
+            ],
+        )?;
+        // Unknown column
+        assert_params_types_finder(
+            "SELECT * FROM KibanaSampleDataEcommerce WHERE unknown_column = $1",
+            vec![None],
+        )?;
+        assert_params_types_finder(
+            r#"
+                SELECT DATE_TRUNC('day', $1), EXTRACT(YEAR FROM $2)
+                FROM KibanaSampleDataEcommerce
+                WHERE customer_gender = LOWER($3)
+            "#,
+            vec![Some(PgTypeId::TIMESTAMP), Some(PgTypeId::TIMESTAMP), None],
+        )?;
+        // Arrays of the column type
+        assert_params_types_finder(
+            r#"
+                SELECT *
+                FROM KibanaSampleDataEcommerce
+                WHERE customer_gender = ANY($1) AND count <> ALL($2)
+            "#,
+            vec![Some(PgTypeId::ArrayText), Some(PgTypeId::ArrayInt8)],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_types_finder_limit() -> Result<(), CubeError> {
+        // Parser accepts only numbers in LIMIT and OFFSET, placeholders are set in AST directly
+        let mut stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata LIMIT 1 OFFSET 2",
+        )
+        .unwrap();
+        if let ast::Statement::Query(query) = &mut stmts[0] {
+            query.limit = Some(ast::Expr::Value(Value::Placeholder("$1".to_string())));
+            if let Some(offset) = &mut query.offset {
+                offset.value = ast::Expr::Value(Value::Placeholder("$2".to_string()));
+            }
+        }
+
+        let meta = MetaContext::new(vec![]);
+        assert_eq!(
+            StatementParamsTypesFinder::new(&meta).find(&stmts[0]),
+            vec![Some(PgTypeId::INT8), Some(PgTypeId::INT8)]
+        );
+
+        let binder = StatementParamsBinder::new(vec![BindValue::Int64(10), BindValue::Int64(20)]);
+        binder.bind(&mut stmts[0]);
+        assert_eq!(
+            stmts[0].to_string(),
+            "SELECT * FROM testdata LIMIT 10 OFFSET 20"
+        );
+
+        Ok(())
+    }
+
+    fn assert_placeholder_replacer(
+        input: &str,
+        types: &[PgTypeId],
+        output: &str,
+    ) -> Result<(), CubeError> {
         let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
 
-        let binder = StatementPlaceholderReplacer::new();
+        let binder = StatementPlaceholderReplacer::new(types);
         let result = binder.replace(&stmts[0]);
 
         assert_eq!(result.to_string(), output);
@@ -375,9 +1378,209 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_expressions_counter() -> Result<(), CubeError> {
+        let count = |input: &str| {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+            StatementExpressionsCounter::new().count(&stmts[0])
+        };
+
+        assert_eq!(count("SELECT 1"), 1);
+        assert_eq!(count("SELECT a, b + 1 FROM t WHERE c = $1"), 7);
+        assert_eq!(
+            count("SELECT a, COUNT(*) FROM t GROUP BY a HAVING COUNT(*) > 1 ORDER BY a"),
+            7
+        );
+        assert_eq!(count("SELECT * FROM (SELECT a FROM t) AS q LIMIT 10"), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_subquery_depth_finder() -> Result<(), CubeError> {
+        let depth = |input: &str, limit: usize| {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+            StatementSubqueryDepthFinder::new(limit).find(&stmts[0])
+        };
+
+        assert_eq!(depth("SELECT 1", 10), 0);
+        assert_eq!(depth("SELECT * FROM (SELECT a FROM t) AS q", 10), 1);
+        assert_eq!(
+            depth(
+                "SELECT (SELECT MAX(a) FROM t) FROM t WHERE b IN (SELECT b FROM t) \
+                AND EXISTS (SELECT * FROM (SELECT 1) AS q)",
+                10
+            ),
+            2
+        );
+        assert_eq!(
+            depth(
+                "SELECT * FROM (SELECT * FROM (SELECT * FROM (SELECT 1) AS a) AS b) AS c",
+                1
+            ),
+            2
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_placeholder_replacer() -> Result<(), CubeError> {
-        assert_placeholder_replacer("SELECT ?", "SELECT 'replaced_placeholder'")?;
+        assert_placeholder_replacer("SELECT ?", &[], "SELECT 'replaced_placeholder'")?;
+        assert_placeholder_replacer(
+            "SELECT * FROM t WHERE a > $2 AND b = $1 AND c = $3 AND d = $4",
+            &[PgTypeId::INT8, PgTypeId::FLOAT8, PgTypeId::BOOL],
+            "SELECT * FROM t WHERE a > 0.0 AND b = 0 AND c = false AND d = 'replaced_placeholder'",
+        )?;
+        assert_placeholder_replacer(
+            "SELECT * FROM t WHERE a = ANY($1) AND b = ANY($2)",
+            &[PgTypeId::ArrayInt8, PgTypeId::ArrayText],
+            "SELECT * FROM t WHERE a = ANY(ARRAY[0]) AND b = ANY(ARRAY['replaced_placeholder'])",
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sql_value_functions_replacer() -> Result<(), CubeError> {
+        let replace = |input: &str| {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+            StatementSqlValueFunctionsReplacer::new()
+                .replace(&stmts[0])
+                .to_string()
+        };
+
+        assert_eq!(
+            replace("SELECT current_user, CURRENT_SCHEMA, current_catalog AS db"),
+            "SELECT current_user() AS current_user, current_schema() AS current_schema, current_database() AS db"
+        );
+        assert_eq!(
+            replace("SELECT \"current_user\" FROM t WHERE session_user = 'ovr'"),
+            "SELECT \"current_user\" FROM t WHERE session_user() = 'ovr'"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_functions_replacer() -> Result<(), CubeError> {
+        let replace = |input: &str| {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+            StatementTableFunctionsReplacer::new()
+                .replace(&stmts[0])
+                .to_string()
+        };
+
+        assert_eq!(
+            replace("SELECT * FROM generate_series(1, 3)"),
+            "SELECT * FROM (SELECT generate_series(1, 3) AS generate_series) AS generate_series"
+        );
+        assert_eq!(
+            replace("SELECT s.n FROM pg_catalog.generate_series(1, 5, 2) AS s(n)"),
+            "SELECT s.n FROM (SELECT generate_series(1, 5, 2) AS n) AS s"
+        );
+        assert_eq!(
+            replace("SELECT * FROM t CROSS JOIN unnest(ARRAY[1, 2]) u"),
+            "SELECT * FROM t CROSS JOIN (SELECT unnest(ARRAY[1, 2]) AS u) AS u"
+        );
+        assert_eq!(
+            replace("SELECT * FROM (SELECT * FROM UNNEST(ARRAY['a']) AS x) AS t"),
+            "SELECT * FROM (SELECT * FROM (SELECT unnest(ARRAY['a']) AS x) AS x) AS t"
+        );
+        assert_eq!(replace("SELECT * FROM t"), "SELECT * FROM t");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_casts_replacer() -> Result<(), CubeError> {
+        let replace = |input: &str| {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+            StatementCastsReplacer::new().replace(&stmts[0]).to_string()
+        };
+
+        assert_eq!(
+            replace("SELECT '18000'::oid, CAST(t.a AS pg_catalog.OID) FROM t WHERE b = 1::oid"),
+            "SELECT CAST('18000' AS BIGINT), CAST(t.a AS BIGINT) FROM t WHERE b = CAST(1 AS BIGINT)"
+        );
+        assert_eq!(
+            replace("SELECT a::\"oid\", a::int FROM t"),
+            "SELECT CAST(a AS \"oid\"), CAST(a AS INT) FROM t"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_array_comparisons_replacer() -> Result<(), CubeError> {
+        let replace = |input: &str| {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+            StatementArrayComparisonsReplacer::new()
+                .replace(&stmts[0])
+                .to_string()
+        };
+
+        assert_eq!(
+            replace("SELECT * FROM t WHERE a = ANY(ARRAY['x', 'y']) AND b <> ALL(ARRAY[1, 2])"),
+            "SELECT * FROM t WHERE a IN ('x', 'y') AND b NOT IN (1, 2)"
+        );
+        assert_eq!(
+            replace("SELECT * FROM t WHERE a = any('{x,\"y z\"}'::text[]) OR b = ANY('{}')"),
+            "SELECT * FROM t WHERE a IN ('x', 'y z') OR false"
+        );
+        // Other comparisons and columns of arrays are kept
+        assert_eq!(
+            replace("SELECT * FROM t WHERE a > ANY(ARRAY[1]) AND a = ANY(b) AND a <> ANY('{1}')"),
+            "SELECT * FROM t WHERE a > ANY(ARRAY[1]) AND a = ANY(b) AND a <> ANY('{1}')"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_time_functions_replacer() -> Result<(), CubeError> {
+        let replace = |input: &str| {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+            StatementDateTimeFunctionsReplacer::new()
+                .replace(&stmts[0])
+                .to_string()
+        };
+
+        assert_eq!(
+            replace("SELECT date_trunc('decade', t.d), date_trunc('week', t.d) FROM t"),
+            "SELECT pg_date_trunc('decade', t.d) AS date_trunc, date_trunc('week', t.d) FROM t"
+        );
+        assert_eq!(
+            replace("SELECT EXTRACT(EPOCH FROM d) AS e, EXTRACT(YEAR FROM d) FROM t"),
+            "SELECT pg_date_part('epoch', d) AS e, EXTRACT(YEAR FROM d) FROM t"
+        );
+        assert_eq!(
+            replace("SELECT * FROM t WHERE pg_catalog.date_part('dow', d) = 1"),
+            "SELECT * FROM t WHERE pg_date_part('dow', d) = 1"
+        );
+        assert_eq!(
+            replace("SELECT to_timestamp(1650000000)"),
+            "SELECT pg_to_timestamp(1650000000) AS to_timestamp"
+        );
+        assert_eq!(
+            replace("SELECT date_trunc('MONTH', d), date_trunc('qtr', d) AS q FROM t"),
+            "SELECT date_trunc('month', d) AS date_trunc, date_trunc('quarter', d) AS q FROM t"
+        );
+        assert_eq!(
+            replace("SELECT * FROM t GROUP BY date_part('years', d)"),
+            "SELECT * FROM t GROUP BY date_part('year', d)"
+        );
+        assert_eq!(
+            replace("SELECT date_trunc($1, d) FROM t"),
+            "SELECT date_trunc($1, d) FROM t"
+        );
 
         Ok(())
     }
@@ -90,8 +90,25 @@ pub enum CommandCompletion {
     Commit,
     Use,
     Rollback,
+    Savepoint,
+    Release,
+    // ROLLBACK TO SAVEPOINT
+    RollbackToSavepoint,
     Set,
     Select(u32),
+    CreateFunction,
+    DropFunction,
+    Prepare,
+    Deallocate,
+    DeallocateAll,
+    DiscardAll,
+    Listen,
+    Unlisten,
+    Notify,
+    // No-op DML, affected rows
+    Insert(u32),
+    Update(u32),
+    Delete(u32),
 }
 
 impl CommandCompletion {
@@ -100,9 +117,31 @@ impl CommandCompletion {
             CommandCompletion::Begin => CommandComplete::Plain("BEGIN".to_string()),
             CommandCompletion::Commit => CommandComplete::Plain("COMMIT".to_string()),
             CommandCompletion::Rollback => CommandComplete::Plain("ROLLBACK".to_string()),
+            CommandCompletion::Savepoint => CommandComplete::Plain("SAVEPOINT".to_string()),
+            CommandCompletion::Release => CommandComplete::Plain("RELEASE".to_string()),
+            CommandCompletion::RollbackToSavepoint => {
+                CommandComplete::Plain("ROLLBACK".to_string())
+            }
             CommandCompletion::Set => CommandComplete::Plain("SET".to_string()),
             CommandCompletion::Use => CommandComplete::Plain("USE".to_string()),
             CommandCompletion::Select(rows) => CommandComplete::Select(rows),
+            CommandCompletion::CreateFunction => {
+                CommandComplete::Plain("CREATE FUNCTION".to_string())
+            }
+            CommandCompletion::DropFunction => CommandComplete::Plain("DROP FUNCTION".to_string()),
+            CommandCompletion::Prepare => CommandComplete::Plain("PREPARE".to_string()),
+            CommandCompletion::Deallocate => CommandComplete::Plain("DEALLOCATE".to_string()),
+            CommandCompletion::DeallocateAll => {
+                CommandComplete::Plain("DEALLOCATE ALL".to_string())
+            }
+            CommandCompletion::DiscardAll => CommandComplete::Plain("DISCARD ALL".to_string()),
+            CommandCompletion::Listen => CommandComplete::Plain("LISTEN".to_string()),
+            CommandCompletion::Unlisten => CommandComplete::Plain("UNLISTEN".to_string()),
+            CommandCompletion::Notify => CommandComplete::Plain("NOTIFY".to_string()),
+            // oid of inserted row is always 0, because tables with oids are not supported
+            CommandCompletion::Insert(rows) => CommandComplete::Plain(format!("INSERT 0 {}", rows)),
+            CommandCompletion::Update(rows) => CommandComplete::Plain(format!("UPDATE {}", rows)),
+            CommandCompletion::Delete(rows) => CommandComplete::Plain(format!("DELETE {}", rows)),
         }
     }
 }
@@ -67,6 +67,20 @@ impl MetaContext {
         None
     }
 
+    /// Cubes can be queried together if one of them has a join to the other one in the data model
+    pub fn cubes_joined(&self, left: &str, right: &str) -> bool {
+        let has_join = |from: &str, to: &str| {
+            self.cubes
+                .iter()
+                .find(|cube| cube.name.eq_ignore_ascii_case(from))
+                .and_then(|cube| cube.joins.as_ref())
+                .map(|joins| joins.iter().any(|join| join.name.eq_ignore_ascii_case(to)))
+                .unwrap_or(false)
+        };
+
+        has_join(left, right) || has_join(right, left)
+    }
+
     pub fn find_df_data_type(&self, member_name: String) -> Option<DataType> {
         self.find_cube_with_name(member_name.split(".").next()?.to_string())?
             .df_data_type(member_name.as_str())
@@ -91,6 +105,8 @@ mod tests {
             V1CubeMeta {
                 name: "test1".to_string(),
                 title: None,
+                meta: None,
+                joins: None,
                 dimensions: vec![],
                 measures: vec![],
                 segments: vec![],
@@ -98,6 +114,8 @@ mod tests {
             V1CubeMeta {
                 name: "test2".to_string(),
                 title: None,
+                meta: None,
+                joins: None,
                 dimensions: vec![],
                 measures: vec![],
                 segments: vec![],
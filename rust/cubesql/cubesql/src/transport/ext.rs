@@ -150,6 +150,9 @@ pub trait V1CubeMetaExt {
     fn df_data_type(&self, member_name: &str) -> Option<DataType>;
 
     fn member_type(&self, member_name: &str) -> Option<MemberType>;
+
+    /// Cube is flagged by `meta: { large: true }`, queries without filter or LIMIT are rejected
+    fn is_large(&self) -> bool;
 }
 
 pub enum MemberType {
@@ -306,6 +309,14 @@ impl V1CubeMetaExt for V1CubeMeta {
         }
         None
     }
+
+    fn is_large(&self) -> bool {
+        self.meta
+            .as_ref()
+            .and_then(|meta| meta.get("large"))
+            .and_then(|large| large.as_bool())
+            .unwrap_or(false)
+    }
 }
 
 pub fn df_data_type_by_column_type(column_type: ColumnType) -> DataType {
@@ -17,7 +17,7 @@ pub trait TransportService: Send + Sync + Debug {
     // Load meta information about cubes
     async fn meta(&self, ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError>;
 
-    // Execute load query
+    // Execute load query, CubeError::continue_wait() makes CubeScan repeat the request later
     async fn load(
         &self,
         query: V1LoadRequestQuery,
@@ -106,3 +106,41 @@ impl TransportService for HttpTransport {
         Ok(response)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[tokio::test]
+    async fn test_load_continue_wait() -> Result<(), CubeError> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/load"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"error":"Continue wait"}"#),
+            )
+            // Request is repeated by CubeScan, not by the transport
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let ctx = Arc::new(AuthContext {
+            access_token: "token".to_string(),
+            base_path: server.uri(),
+            claims: None,
+            superuser: false,
+        });
+
+        let err = HttpTransport::new()
+            .load(V1LoadRequestQuery::new(), ctx)
+            .await
+            .expect_err("must be continue wait");
+        assert!(err.is_continue_wait());
+
+        Ok(())
+    }
+}
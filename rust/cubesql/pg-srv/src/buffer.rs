@@ -14,6 +14,16 @@ pub async fn read_message<Reader: AsyncReadExt + Unpin + Send>(
 ) -> Result<FrontendMessage, Error> {
     // https://www.postgresql.org/docs/14/protocol-message-formats.html
     let message_tag = reader.read_u8().await?;
+
+    read_message_with_tag(reader, message_tag).await
+}
+
+/// Reads the message after its tag, the tag can be awaited separately in select, because reading
+/// of a single byte is cancel safe
+pub async fn read_message_with_tag<Reader: AsyncReadExt + Unpin + Send>(
+    reader: &mut Reader,
+    message_tag: u8,
+) -> Result<FrontendMessage, Error> {
     let cursor = read_contents(reader, message_tag).await?;
 
     let message = match message_tag {
@@ -23,11 +33,13 @@ pub async fn read_message<Reader: AsyncReadExt + Unpin + Send>(
         b'D' => FrontendMessage::Describe(protocol::Describe::deserialize(cursor).await?),
         b'E' => FrontendMessage::Execute(protocol::Execute::deserialize(cursor).await?),
         b'C' => FrontendMessage::Close(protocol::Close::deserialize(cursor).await?),
+        b'F' => FrontendMessage::FunctionCall(protocol::FunctionCall::deserialize(cursor).await?),
         b'p' => {
             FrontendMessage::PasswordMessage(protocol::PasswordMessage::deserialize(cursor).await?)
         }
         b'X' => FrontendMessage::Terminate,
         b'S' => FrontendMessage::Sync,
+        b'H' => FrontendMessage::Flush,
         identifier => {
             return Err(Error::new(
                 ErrorKind::InvalidData,
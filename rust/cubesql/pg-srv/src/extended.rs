@@ -1,4 +1,11 @@
-#[derive(Debug)]
+use std::{
+    convert::TryInto,
+    io::{Error, ErrorKind},
+};
+
+use crate::{protocol::Format, PgTypeId};
+
+#[derive(Debug, PartialEq)]
 pub enum BindValue {
     String(String),
     Int64(i64),
@@ -6,5 +13,496 @@ pub enum BindValue {
     UInt64(u64),
     Float64(f64),
     Bool(bool),
+    /// One-dimensional array, elements are decoded by the element type
+    Array(Vec<BindValue>),
     Null,
 }
+
+impl BindValue {
+    /// Decodes raw value of parameter from Bind by the type of parameter (from Parse or inferred)
+    /// and its format. Unknown types are passed as strings.
+    pub fn decode(raw: &[u8], typ: PgTypeId, format: Format) -> Result<Self, Error> {
+        if let Some(element) = typ.array_element() {
+            return match format {
+                Format::Text => Self::decode_text_array(raw, element),
+                Format::Binary => Self::decode_binary_array(raw, element),
+            };
+        }
+
+        match format {
+            Format::Text => Self::decode_text(raw, typ),
+            Format::Binary => Self::decode_binary(raw, typ),
+        }
+    }
+
+    /// Array literal: {1,2,NULL} or {"a b","c\"d"}, double quotes keep separators and spaces
+    fn decode_text_array(raw: &[u8], element: PgTypeId) -> Result<Self, Error> {
+        let value = String::from_utf8(raw.to_vec()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "Unable to decode parameter as UTF-8 string",
+            )
+        })?;
+
+        let items = value
+            .trim()
+            .strip_prefix('{')
+            .and_then(|v| v.strip_suffix('}'))
+            .ok_or_else(|| invalid_input("array", &value))?;
+        if items.trim().is_empty() {
+            return Ok(BindValue::Array(vec![]));
+        }
+
+        let mut elements = vec![];
+        let mut current = String::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        let mut chars = items.chars();
+        loop {
+            let c = chars.next();
+            match c {
+                Some('"') => {
+                    in_quotes = !in_quotes;
+                    quoted = true;
+                }
+                Some('\\') if in_quotes => match chars.next() {
+                    Some(escaped) => current.push(escaped),
+                    None => return Err(invalid_input("array", &value)),
+                },
+                Some('{') | Some('}') if !in_quotes => {
+                    return Err(Error::new(
+                        ErrorKind::Unsupported,
+                        "Multidimensional arrays are not supported for parameters",
+                    ))
+                }
+                Some(',') | None if !in_quotes => {
+                    let item = if quoted {
+                        current.as_str()
+                    } else {
+                        current.trim()
+                    };
+                    elements.push(if !quoted && item.eq_ignore_ascii_case("NULL") {
+                        BindValue::Null
+                    } else {
+                        Self::decode_text(item.as_bytes(), element)?
+                    });
+
+                    if c.is_none() {
+                        break;
+                    }
+                    current.clear();
+                    quoted = false;
+                }
+                Some(c) => current.push(c),
+                None => return Err(invalid_input("array", &value)),
+            }
+        }
+
+        Ok(BindValue::Array(elements))
+    }
+
+    /// Binary array: number of dimensions, flags and element type followed by size and lower
+    /// bound of every dimension and by elements prefixed with their lengths (-1 for NULL)
+    fn decode_binary_array(raw: &[u8], element: PgTypeId) -> Result<Self, Error> {
+        let typ = element.array_type().unwrap_or(PgTypeId::ANYARRAY);
+        let mut offset = 0;
+        let next_i32 = |offset: &mut usize| -> Result<i32, Error> {
+            let value = raw.get(*offset..*offset + 4).unwrap_or(&[]);
+            *offset += 4;
+
+            Ok(i32::from_be_bytes(fixed(value, typ)?))
+        };
+
+        let ndim = next_i32(&mut offset)?;
+        let _flags = next_i32(&mut offset)?;
+        let _element_oid = next_i32(&mut offset)?;
+        let len = match ndim {
+            0 => 0,
+            1 => {
+                let len = next_i32(&mut offset)?;
+                let _lower_bound = next_i32(&mut offset)?;
+                len
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "Multidimensional arrays are not supported for parameters",
+                ))
+            }
+        };
+
+        let mut elements = vec![];
+        for _ in 0..len {
+            let element_len = next_i32(&mut offset)?;
+            if element_len < 0 {
+                elements.push(BindValue::Null);
+                continue;
+            }
+
+            let start = offset;
+            offset += element_len as usize;
+            let element_raw = raw
+                .get(start..offset)
+                .ok_or_else(|| invalid_length(typ, raw))?;
+            elements.push(Self::decode_binary(element_raw, element)?);
+        }
+
+        Ok(BindValue::Array(elements))
+    }
+
+    fn decode_text(raw: &[u8], typ: PgTypeId) -> Result<Self, Error> {
+        let value = String::from_utf8(raw.to_vec()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "Unable to decode parameter as UTF-8 string",
+            )
+        })?;
+
+        match typ {
+            PgTypeId::INT2 | PgTypeId::INT4 | PgTypeId::INT8 | PgTypeId::OID => value
+                .trim()
+                .parse::<i64>()
+                .map(BindValue::Int64)
+                .map_err(|_| invalid_input("integer", &value)),
+            PgTypeId::FLOAT4 | PgTypeId::FLOAT8 | PgTypeId::NUMERIC => value
+                .trim()
+                .parse::<f64>()
+                .map(BindValue::Float64)
+                .map_err(|_| invalid_input("numeric", &value)),
+            PgTypeId::BOOL => match value.trim().to_lowercase().as_str() {
+                "t" | "true" | "y" | "yes" | "on" | "1" => Ok(BindValue::Bool(true)),
+                "f" | "false" | "n" | "no" | "off" | "0" => Ok(BindValue::Bool(false)),
+                _ => Err(invalid_input("boolean", &value)),
+            },
+            _ => Ok(BindValue::String(value)),
+        }
+    }
+
+    fn decode_binary(raw: &[u8], typ: PgTypeId) -> Result<Self, Error> {
+        match typ {
+            PgTypeId::BOOL => match raw {
+                [v] => Ok(BindValue::Bool(*v != 0)),
+                _ => Err(invalid_length(typ, raw)),
+            },
+            PgTypeId::INT2 => Ok(BindValue::Int64(i16::from_be_bytes(fixed(raw, typ)?) as i64)),
+            PgTypeId::INT4 => Ok(BindValue::Int64(i32::from_be_bytes(fixed(raw, typ)?) as i64)),
+            PgTypeId::OID => Ok(BindValue::Int64(u32::from_be_bytes(fixed(raw, typ)?) as i64)),
+            PgTypeId::INT8 => Ok(BindValue::Int64(i64::from_be_bytes(fixed(raw, typ)?))),
+            PgTypeId::FLOAT4 => Ok(BindValue::Float64(
+                f32::from_be_bytes(fixed(raw, typ)?) as f64
+            )),
+            PgTypeId::FLOAT8 => Ok(BindValue::Float64(f64::from_be_bytes(fixed(raw, typ)?))),
+            PgTypeId::NUMERIC => decode_numeric(raw)?
+                .parse::<f64>()
+                .map(BindValue::Float64)
+                .map_err(|_| invalid_length(typ, raw)),
+            // Number of days since 2000-01-01
+            PgTypeId::DATE => {
+                let days = i32::from_be_bytes(fixed(raw, typ)?) as i64;
+                let (year, month, day) = civil_from_days(days + PG_EPOCH_DAYS);
+
+                Ok(BindValue::String(format!(
+                    "{:04}-{:02}-{:02}",
+                    year, month, day
+                )))
+            }
+            // Number of microseconds since 2000-01-01 00:00:00
+            PgTypeId::TIMESTAMP | PgTypeId::TIMESTAMPTZ => {
+                let micros = i64::from_be_bytes(fixed(raw, typ)?);
+                let days = micros.div_euclid(MICROS_PER_DAY);
+                let time = micros.rem_euclid(MICROS_PER_DAY);
+                let (year, month, day) = civil_from_days(days + PG_EPOCH_DAYS);
+
+                Ok(BindValue::String(format!(
+                    "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
+                    year,
+                    month,
+                    day,
+                    time / 3_600_000_000,
+                    time / 60_000_000 % 60,
+                    time / 1_000_000 % 60,
+                    time % 1_000_000
+                )))
+            }
+            PgTypeId::TEXT
+            | PgTypeId::VARCHAR
+            | PgTypeId::BPCHAR
+            | PgTypeId::NAME
+            | PgTypeId::UNSPECIFIED => Self::decode_text(raw, PgTypeId::TEXT),
+            typ => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "Binary format is not supported for parameter of type {}",
+                    typ as u32
+                ),
+            )),
+        }
+    }
+}
+
+// Days between 1970-01-01 and 2000-01-01
+const PG_EPOCH_DAYS: i64 = 10957;
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+fn invalid_input(type_name: &str, value: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid input syntax for type {}: \"{}\"", type_name, value),
+    )
+}
+
+fn invalid_length(typ: PgTypeId, raw: &[u8]) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!(
+            "Invalid binary value for parameter of type {}, length: {}",
+            typ as u32,
+            raw.len()
+        ),
+    )
+}
+
+fn fixed<const N: usize>(raw: &[u8], typ: PgTypeId) -> Result<[u8; N], Error> {
+    raw.try_into().map_err(|_| invalid_length(typ, raw))
+}
+
+/// Date (year, month, day) by the number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Decimal string by binary NUMERIC: ndigits, weight, sign and dscale followed by base-10000 digits
+fn decode_numeric(raw: &[u8]) -> Result<String, Error> {
+    let header = |idx: usize| -> Result<i16, Error> {
+        Ok(i16::from_be_bytes(fixed(
+            raw.get(idx * 2..idx * 2 + 2).unwrap_or(&[]),
+            PgTypeId::NUMERIC,
+        )?))
+    };
+
+    let ndigits = header(0)? as usize;
+    let weight = header(1)? as i64;
+    let sign = header(2)? as u16;
+    let dscale = header(3)? as usize;
+
+    match sign {
+        0xC000 => return Ok("NaN".to_string()),
+        0xD000 => return Ok("inf".to_string()),
+        0xF000 => return Ok("-inf".to_string()),
+        _ => {}
+    }
+
+    let digits = (0..ndigits)
+        .map(|i| header(4 + i))
+        .collect::<Result<Vec<i16>, Error>>()?;
+
+    let mut int_part = String::new();
+    let mut frac_part = String::new();
+    for (i, digit) in digits.iter().enumerate() {
+        if (i as i64) <= weight {
+            int_part.push_str(&format!("{:04}", digit));
+        } else {
+            frac_part.push_str(&format!("{:04}", digit));
+        }
+    }
+
+    // Trailing zero groups of integer part are not sent
+    for _ in (ndigits as i64)..(weight + 1) {
+        int_part.push_str("0000");
+    }
+    // Leading zero groups of fractional part are not sent
+    if weight < -1 {
+        frac_part = "0000".repeat((-weight - 1) as usize) + &frac_part;
+    }
+
+    let int_part = int_part.trim_start_matches('0');
+    let int_part = if int_part.is_empty() { "0" } else { int_part };
+
+    let frac_part = if frac_part.len() >= dscale {
+        frac_part[..dscale].to_string()
+    } else {
+        format!("{}{}", frac_part, "0".repeat(dscale - frac_part.len()))
+    };
+
+    let sign = if sign == 0x4000 { "-" } else { "" };
+    if frac_part.is_empty() {
+        Ok(format!("{}{}", sign, int_part))
+    } else {
+        Ok(format!("{}{}.{}", sign, int_part, frac_part))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_value_decode_text() -> Result<(), Error> {
+        assert_eq!(
+            BindValue::decode(b"42", PgTypeId::INT4, Format::Text)?,
+            BindValue::Int64(42)
+        );
+        assert_eq!(
+            BindValue::decode(b"-1.5", PgTypeId::NUMERIC, Format::Text)?,
+            BindValue::Float64(-1.5)
+        );
+        assert_eq!(
+            BindValue::decode(b"t", PgTypeId::BOOL, Format::Text)?,
+            BindValue::Bool(true)
+        );
+        assert_eq!(
+            BindValue::decode(b"2022-04-25", PgTypeId::DATE, Format::Text)?,
+            BindValue::String("2022-04-25".to_string())
+        );
+        assert!(BindValue::decode(b"abc", PgTypeId::INT8, Format::Text).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_value_decode_binary() -> Result<(), Error> {
+        assert_eq!(
+            BindValue::decode(&[0, 1], PgTypeId::INT2, Format::Binary)?,
+            BindValue::Int64(1)
+        );
+        assert_eq!(
+            BindValue::decode(&[255, 255, 255, 254], PgTypeId::INT4, Format::Binary)?,
+            BindValue::Int64(-2)
+        );
+        assert_eq!(
+            BindValue::decode(&[0, 0, 0, 0, 0, 0, 0, 3], PgTypeId::INT8, Format::Binary)?,
+            BindValue::Int64(3)
+        );
+        assert_eq!(
+            BindValue::decode(&[63, 192, 0, 0], PgTypeId::FLOAT4, Format::Binary)?,
+            BindValue::Float64(1.5)
+        );
+        assert_eq!(
+            BindValue::decode(&[1], PgTypeId::BOOL, Format::Binary)?,
+            BindValue::Bool(true)
+        );
+        assert_eq!(
+            BindValue::decode(b"test", PgTypeId::TEXT, Format::Binary)?,
+            BindValue::String("test".to_string())
+        );
+        assert_eq!(
+            BindValue::decode(&[0, 0, 31, 214], PgTypeId::DATE, Format::Binary)?,
+            BindValue::String("2022-04-25".to_string())
+        );
+        assert_eq!(
+            BindValue::decode(&[255, 255, 255, 255], PgTypeId::DATE, Format::Binary)?,
+            BindValue::String("1999-12-31".to_string())
+        );
+        // 2022-04-25 16:25:01.164774
+        assert_eq!(
+            BindValue::decode(
+                &[0, 2, 128, 123, 201, 94, 116, 230],
+                PgTypeId::TIMESTAMP,
+                Format::Binary
+            )?,
+            BindValue::String("2022-04-25 16:25:01.164774".to_string())
+        );
+        // 123.45
+        assert_eq!(
+            BindValue::decode(
+                &[0, 2, 0, 0, 0, 0, 0, 2, 0, 123, 17, 148],
+                PgTypeId::NUMERIC,
+                Format::Binary
+            )?,
+            BindValue::Float64(123.45)
+        );
+        // -0.0005
+        assert_eq!(
+            BindValue::decode(
+                &[0, 1, 255, 255, 64, 0, 0, 4, 0, 5],
+                PgTypeId::NUMERIC,
+                Format::Binary
+            )?,
+            BindValue::Float64(-0.0005)
+        );
+        // 20000
+        assert_eq!(
+            BindValue::decode(
+                &[0, 1, 0, 1, 0, 0, 0, 0, 0, 2],
+                PgTypeId::NUMERIC,
+                Format::Binary
+            )?,
+            BindValue::Float64(20000.0)
+        );
+        assert!(BindValue::decode(&[0, 1, 2], PgTypeId::INT4, Format::Binary).is_err());
+        assert!(BindValue::decode(&[0], PgTypeId::INTERVAL, Format::Binary).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_value_decode_array() -> Result<(), Error> {
+        assert_eq!(
+            BindValue::decode(b"{1, 2,NULL}", PgTypeId::ArrayInt8, Format::Text)?,
+            BindValue::Array(vec![
+                BindValue::Int64(1),
+                BindValue::Int64(2),
+                BindValue::Null
+            ])
+        );
+        assert_eq!(
+            BindValue::decode(
+                br#"{female,"a, b","say \"hi\"","NULL"}"#,
+                PgTypeId::ArrayText,
+                Format::Text
+            )?,
+            BindValue::Array(vec![
+                BindValue::String("female".to_string()),
+                BindValue::String("a, b".to_string()),
+                BindValue::String("say \"hi\"".to_string()),
+                BindValue::String("NULL".to_string()),
+            ])
+        );
+        assert_eq!(
+            BindValue::decode(b"{}", PgTypeId::ArrayText, Format::Text)?,
+            BindValue::Array(vec![])
+        );
+        assert!(BindValue::decode(b"{{1},{2}}", PgTypeId::ArrayInt4, Format::Text).is_err());
+        assert!(BindValue::decode(b"1,2", PgTypeId::ArrayInt4, Format::Text).is_err());
+
+        // int4[] of 2 elements: 5 and NULL
+        assert_eq!(
+            BindValue::decode(
+                &[
+                    0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 23, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0,
+                    0, 5, 255, 255, 255, 255
+                ],
+                PgTypeId::ArrayInt4,
+                Format::Binary
+            )?,
+            BindValue::Array(vec![BindValue::Int64(5), BindValue::Null])
+        );
+        assert_eq!(
+            BindValue::decode(
+                &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 25],
+                PgTypeId::ArrayText,
+                Format::Binary
+            )?,
+            BindValue::Array(vec![])
+        );
+        assert!(BindValue::decode(
+            &[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 25, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 9, 1],
+            PgTypeId::ArrayText,
+            Format::Binary
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
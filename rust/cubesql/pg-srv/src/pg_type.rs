@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum PgTypeId {
     UNSPECIFIED = 0,
@@ -74,9 +74,82 @@ pub struct PgType<'a> {
 }
 
 impl PgTypeId {
+    pub fn from_oid(oid: u32) -> Option<Self> {
+        match oid {
+            0 => Some(Self::UNSPECIFIED),
+            16 => Some(Self::BOOL),
+            17 => Some(Self::BYTEA),
+            19 => Some(Self::NAME),
+            20 => Some(Self::INT8),
+            21 => Some(Self::INT2),
+            23 => Some(Self::INT4),
+            25 => Some(Self::TEXT),
+            26 => Some(Self::OID),
+            27 => Some(Self::TID),
+            700 => Some(Self::FLOAT4),
+            701 => Some(Self::FLOAT8),
+            790 => Some(Self::MONEY),
+            869 => Some(Self::INET),
+            1000 => Some(Self::ArrayBool),
+            1001 => Some(Self::ArrayBytea),
+            1005 => Some(Self::ArrayInt2),
+            1007 => Some(Self::ArrayInt4),
+            1009 => Some(Self::ArrayText),
+            1016 => Some(Self::ArrayInt8),
+            1021 => Some(Self::ArrayFloat4),
+            1022 => Some(Self::ArrayFloat8),
+            1042 => Some(Self::BPCHAR),
+            1043 => Some(Self::VARCHAR),
+            1082 => Some(Self::DATE),
+            1083 => Some(Self::TIME),
+            1114 => Some(Self::TIMESTAMP),
+            1184 => Some(Self::TIMESTAMPTZ),
+            1186 => Some(Self::INTERVAL),
+            1266 => Some(Self::TIMETZ),
+            1700 => Some(Self::NUMERIC),
+            2249 => Some(Self::RECORD),
+            2277 => Some(Self::ANYARRAY),
+            2283 => Some(Self::ANYELEMENT),
+            3904 => Some(Self::INT4RANGE),
+            3906 => Some(Self::NUMRANGE),
+            3908 => Some(Self::TSRANGE),
+            3220 => Some(Self::PGLSN),
+            3500 => Some(Self::ANYENUM),
+            3831 => Some(Self::ANYRANGE),
+            3910 => Some(Self::TSTZRANGE),
+            3912 => Some(Self::DATERANGE),
+            3926 => Some(Self::INT8RANGE),
+            4532 => Some(Self::NUMMULTIRANGE),
+            4533 => Some(Self::TSMULTIRANGE),
+            4535 => Some(Self::DATEMULTIRANGE),
+            4536 => Some(Self::INT8MULTIRANGE),
+            4451 => Some(Self::INT4MULTIRANGE),
+            13408 => Some(Self::CHARACTERDATA),
+            13410 => Some(Self::SQLIDENTIFIER),
+            _ => None,
+        }
+    }
+
     pub fn to_type(self) -> &'static PgType<'static> {
         PgType::get_by_tid(self)
     }
+
+    /// Type of elements for array types
+    pub fn array_element(self) -> Option<Self> {
+        let typ = self.to_type();
+        match typ.typcategory {
+            "A" => Self::from_oid(typ.typelem),
+            _ => None,
+        }
+    }
+
+    /// Array type whose elements are of this type
+    pub fn array_type(self) -> Option<Self> {
+        match self.to_type().typarray {
+            0 => None,
+            oid => Self::from_oid(oid),
+        }
+    }
 }
 
 impl<'a> PgType<'a> {
@@ -1047,7 +1120,7 @@ const PG_TYPE_ARRAY_INT2: &PgType = &PgType {
 };
 
 const PG_TYPE_ARRAY_INT4: &PgType = &PgType {
-    oid: PgTypeId::ArrayInt2 as u32,
+    oid: PgTypeId::ArrayInt4 as u32,
     typname: "_int4",
     typnamespace: 11,
     typowner: 10,
@@ -1140,3 +1213,19 @@ const PG_TYPE_ARRAY_FLOAT8: &PgType = &PgType {
     typalign: "d",
     typstorage: "x",
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_oid() {
+        for pg_type in PgType::get_all() {
+            let tid = PgTypeId::from_oid(pg_type.oid).unwrap();
+            assert_eq!(tid.to_type().oid, pg_type.oid);
+        }
+
+        assert!(matches!(PgTypeId::from_oid(0), Some(PgTypeId::UNSPECIFIED)));
+        assert!(PgTypeId::from_oid(123456).is_none());
+    }
+}
@@ -15,6 +15,9 @@ use crate::{buffer, BindValue, PgType, PgTypeId};
 const DEFAULT_CAPACITY: usize = 64;
 
 pub const SSL_REQUEST_PROTOCOL: u16 = 1234;
+// Minor versions of special startup packets, which are sent with SSL_REQUEST_PROTOCOL major version
+pub const SSL_REQUEST_CODE: u16 = 5679;
+pub const CANCEL_REQUEST_CODE: u16 = 5678;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct StartupMessage {
@@ -67,11 +70,28 @@ impl Serialize for StartupMessage {
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct CancelRequest {
+    pub process_id: u32,
+    pub secret: u32,
+}
+
+impl CancelRequest {
+    pub async fn from(buffer: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
+        let process_id = buffer.read_u32().await?;
+        let secret = buffer.read_u32().await?;
+
+        Ok(Self { process_id, secret })
+    }
+}
+
 pub struct ErrorResponse {
     // https://www.postgresql.org/docs/14/protocol-error-fields.html
     pub severity: ErrorSeverity,
     pub code: ErrorCode,
     pub message: String,
+    /// Position of the error in the query, 1-based index in characters
+    pub position: Option<usize>,
 }
 
 impl ErrorResponse {
@@ -80,8 +100,14 @@ impl ErrorResponse {
             severity,
             code,
             message,
+            position: None,
         }
     }
+
+    pub fn with_position(mut self, position: Option<usize>) -> Self {
+        self.position = position;
+        self
+    }
 }
 
 impl Serialize for ErrorResponse {
@@ -99,26 +125,97 @@ impl Serialize for ErrorResponse {
         buffer::write_string(&mut buffer, &self.code.to_string());
         buffer.push(b'M');
         buffer::write_string(&mut buffer, &self.message);
+        if let Some(position) = self.position {
+            buffer.push(b'P');
+            buffer::write_string(&mut buffer, &position.to_string());
+        }
         buffer.push(0);
 
         Some(buffer)
     }
 }
 
-pub struct SSLResponse {}
+/// Message which doesn't affect the processing of query, it can be sent at any moment
+pub struct NoticeResponse {
+    // https://www.postgresql.org/docs/14/protocol-error-fields.html
+    pub severity: NoticeSeverity,
+    pub code: ErrorCode,
+    pub message: String,
+}
 
-impl SSLResponse {
-    pub fn new() -> Self {
-        Self {}
+impl NoticeResponse {
+    pub fn new(severity: NoticeSeverity, code: ErrorCode, message: String) -> Self {
+        Self {
+            severity,
+            code,
+            message,
+        }
     }
 }
 
-impl Serialize for SSLResponse {
+impl Serialize for NoticeResponse {
     const CODE: u8 = b'N';
 
+    fn serialize(&self) -> Option<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(DEFAULT_CAPACITY);
+
+        let severity = self.severity.to_string();
+        buffer.push(b'S');
+        buffer::write_string(&mut buffer, &severity);
+        buffer.push(b'V');
+        buffer::write_string(&mut buffer, &severity);
+        buffer.push(b'C');
+        buffer::write_string(&mut buffer, &self.code.to_string());
+        buffer.push(b'M');
+        buffer::write_string(&mut buffer, &self.message);
+        buffer.push(0);
+
+        Some(buffer)
+    }
+}
+
+/// Single-byte answer on SSLRequest, which is sent without length
+pub enum SSLResponse {
+    Accepted,
+    Denied,
+}
+
+impl Serialize for SSLResponse {
+    const CODE: u8 = 0x00;
+
     fn serialize(&self) -> Option<Vec<u8>> {
         None
     }
+
+    fn code(&self) -> u8 {
+        match self {
+            SSLResponse::Accepted => b'S',
+            SSLResponse::Denied => b'N',
+        }
+    }
+}
+
+pub struct BackendKeyData {
+    process_id: u32,
+    secret: u32,
+}
+
+impl BackendKeyData {
+    pub fn new(process_id: u32, secret: u32) -> Self {
+        Self { process_id, secret }
+    }
+}
+
+impl Serialize for BackendKeyData {
+    const CODE: u8 = b'K';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(8);
+        buffer.put_u32(self.process_id);
+        buffer.put_u32(self.secret);
+
+        Some(buffer)
+    }
 }
 
 pub struct Authentication {
@@ -179,6 +276,35 @@ impl Serialize for ParameterStatus {
     }
 }
 
+/// Asynchronous message of NOTIFY on the channel, which the connection listens to
+pub struct NotificationResponse {
+    process_id: u32,
+    channel: String,
+    payload: String,
+}
+
+impl NotificationResponse {
+    pub fn new(process_id: u32, channel: String, payload: String) -> Self {
+        Self {
+            process_id,
+            channel,
+            payload,
+        }
+    }
+}
+
+impl Serialize for NotificationResponse {
+    const CODE: u8 = b'A';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(DEFAULT_CAPACITY);
+        buffer.put_u32(self.process_id);
+        buffer::write_string(&mut buffer, &self.channel);
+        buffer::write_string(&mut buffer, &self.payload);
+        Some(buffer)
+    }
+}
+
 pub struct BindComplete {}
 
 impl BindComplete {
@@ -283,6 +409,119 @@ impl Serialize for EmptyQueryResponse {
     }
 }
 
+/// Portal reached max_rows limit from Execute, it can be resumed by the next Execute
+#[derive(Default)]
+pub struct PortalSuspended {}
+
+impl PortalSuspended {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Serialize for PortalSuspended {
+    const CODE: u8 = b's';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+}
+
+/// Result of the fast-path function call, None is NULL
+pub struct FunctionCallResponse {
+    value: Option<Vec<u8>>,
+}
+
+impl FunctionCallResponse {
+    pub fn new(value: Option<Vec<u8>>) -> Self {
+        Self { value }
+    }
+}
+
+impl Serialize for FunctionCallResponse {
+    const CODE: u8 = b'V';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(DEFAULT_CAPACITY);
+        match &self.value {
+            Some(value) => {
+                buffer.put_i32(value.len() as i32);
+                buffer.extend_from_slice(value);
+            }
+            None => buffer.put_i32(-1),
+        }
+
+        Some(buffer)
+    }
+}
+
+/// Start of COPY ... TO STDOUT, followed by CopyData messages and CopyDone
+pub struct CopyOutResponse {
+    format: Format,
+    columns: u16,
+}
+
+impl CopyOutResponse {
+    pub fn new(format: Format, columns: u16) -> Self {
+        Self { format, columns }
+    }
+}
+
+impl Serialize for CopyOutResponse {
+    const CODE: u8 = b'H';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        let format = match self.format {
+            Format::Text => 0,
+            Format::Binary => 1,
+        };
+
+        let mut buffer = Vec::with_capacity(3 + self.columns as usize * 2);
+        buffer.put_i8(format);
+        buffer.put_u16(self.columns);
+        for _ in 0..self.columns {
+            buffer.put_i16(format as i16);
+        }
+
+        Some(buffer)
+    }
+}
+
+pub struct CopyData {
+    data: Vec<u8>,
+}
+
+impl CopyData {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+}
+
+impl Serialize for CopyData {
+    const CODE: u8 = b'd';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(self.data.clone())
+    }
+}
+
+#[derive(Default)]
+pub struct CopyDone {}
+
+impl CopyDone {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Serialize for CopyDone {
+    const CODE: u8 = b'c';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ParameterDescription {
     parameters: Vec<PgTypeId>,
@@ -292,6 +531,11 @@ impl ParameterDescription {
     pub fn new(parameters: Vec<PgTypeId>) -> Self {
         Self { parameters }
     }
+
+    /// Number of parameters, Bind must supply a value for each of them
+    pub fn count(&self) -> usize {
+        self.parameters.len()
+    }
 }
 
 impl Serialize for ParameterDescription {
@@ -320,6 +564,20 @@ impl RowDescription {
     pub fn new(fields: Vec<RowDescriptionField>) -> Self {
         Self { fields }
     }
+
+    /// Sets formats which will be used to return data, by result formats from Bind
+    pub fn with_formats(mut self, formats: &[Format]) -> Self {
+        for (idx, field) in self.fields.iter_mut().enumerate() {
+            field.format = Format::by_column(formats, idx);
+        }
+
+        self
+    }
+
+    /// Format of each column
+    pub fn formats(&self) -> Vec<Format> {
+        self.fields.iter().map(|field| field.format).collect()
+    }
 }
 
 impl Serialize for RowDescription {
@@ -338,7 +596,7 @@ impl Serialize for RowDescription {
             buffer.extend_from_slice(&field.data_type_oid.to_be_bytes());
             buffer.extend_from_slice(&field.data_type_size.to_be_bytes());
             buffer.extend_from_slice(&field.type_modifier.to_be_bytes());
-            buffer.extend_from_slice(&0_i16.to_be_bytes());
+            buffer.extend_from_slice(&(field.format as i16).to_be_bytes());
         }
 
         Some(buffer)
@@ -354,6 +612,7 @@ pub struct RowDescriptionField {
     data_type_oid: i32,
     data_type_size: i16,
     type_modifier: i32,
+    format: Format,
 }
 
 impl RowDescriptionField {
@@ -365,8 +624,20 @@ impl RowDescriptionField {
             data_type_oid: typ.oid as i32,
             data_type_size: typ.typlen,
             type_modifier: -1,
+            format: Format::Text,
         }
     }
+
+    /// Type specific modifier, for example: precision and scale of NUMERIC
+    pub fn with_type_modifier(mut self, type_modifier: i32) -> Self {
+        self.type_modifier = type_modifier;
+
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -494,22 +765,30 @@ pub struct Bind {
 }
 
 impl Bind {
-    pub fn to_bind_values(&self) -> Vec<BindValue> {
+    /// Decodes parameter values by types of the prepared statement and formats from Bind
+    pub fn to_bind_values(
+        &self,
+        parameters: &ParameterDescription,
+    ) -> Result<Vec<BindValue>, Error> {
         let mut values = vec![];
 
-        for param_value in &self.parameter_values {
+        for (idx, param_value) in self.parameter_values.iter().enumerate() {
             values.push(match param_value {
                 None => BindValue::Null,
                 Some(raw_value) => {
-                    let decoded = String::from_utf8(raw_value.clone())
-                        .expect("Unable to unpack raw parameter to string");
-
-                    BindValue::String(decoded)
+                    let typ = parameters
+                        .parameters
+                        .get(idx)
+                        .cloned()
+                        .unwrap_or(PgTypeId::TEXT);
+                    let format = Format::by_column(&self.parameter_formats, idx);
+
+                    BindValue::decode(raw_value, typ, format)?
                 }
             })
         }
 
-        values
+        Ok(values)
     }
 }
 
@@ -601,6 +880,64 @@ impl Deserialize for Describe {
     }
 }
 
+/// Fast-path function call, used by legacy clients for lo_* functions
+#[derive(Debug, PartialEq)]
+pub struct FunctionCall {
+    /// OID of the function to call
+    pub function_oid: u32,
+    /// Format for arguments
+    pub argument_formats: Vec<Format>,
+    /// Raw values for arguments
+    pub arguments: Vec<Option<Vec<u8>>>,
+    /// Format for the result
+    pub result_format: Format,
+}
+
+#[async_trait]
+impl Deserialize for FunctionCall {
+    async fn deserialize(mut buffer: Cursor<Vec<u8>>) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let function_oid = buffer.read_u32().await?;
+
+        let mut argument_formats = Vec::new();
+        {
+            let total = buffer.read_i16().await?;
+            for _ in 0..total {
+                argument_formats.push(buffer::read_format(&mut buffer).await?);
+            }
+        }
+
+        let mut arguments = Vec::new();
+        {
+            let total = buffer.read_i16().await?;
+            for _ in 0..total {
+                let len = buffer.read_i32().await?;
+                if len == -1 {
+                    arguments.push(None);
+                } else {
+                    let mut value = Vec::with_capacity(len as usize);
+                    for _ in 0..len {
+                        value.push(buffer.read_u8().await?);
+                    }
+
+                    arguments.push(Some(value));
+                }
+            }
+        }
+
+        let result_format = buffer::read_format(&mut buffer).await?;
+
+        Ok(Self {
+            function_oid,
+            argument_formats,
+            arguments,
+            result_format,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Query {
     pub query: String,
@@ -625,6 +962,18 @@ pub enum Format {
     Binary,
 }
 
+impl Format {
+    /// Resolves format of the column by result formats from Bind: no formats means text for all
+    /// columns, single format is applied to all columns, otherwise format is set per column
+    pub fn by_column(formats: &[Format], idx: usize) -> Format {
+        match formats {
+            [] => Format::Text,
+            [format] => *format,
+            formats => formats.get(idx).cloned().unwrap_or(Format::Text),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct ProtocolVersion {
     pub major: u16,
@@ -635,6 +984,14 @@ impl ProtocolVersion {
     pub fn new(major: u16, minor: u16) -> Self {
         Self { major, minor }
     }
+
+    pub fn is_ssl_request(&self) -> bool {
+        self.major == SSL_REQUEST_PROTOCOL && self.minor == SSL_REQUEST_CODE
+    }
+
+    pub fn is_cancel_request(&self) -> bool {
+        self.major == SSL_REQUEST_PROTOCOL && self.minor == CANCEL_REQUEST_CODE
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -646,16 +1003,25 @@ pub enum FrontendMessage {
     Describe(Describe),
     Execute(Execute),
     Close(Close),
+    FunctionCall(FunctionCall),
     /// Close connection
     Terminate,
     /// Finish
     Sync,
+    /// Push pending output to the client, without ReadyForQuery
+    Flush,
 }
 
 /// https://www.postgresql.org/docs/14/errcodes-appendix.html
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum ErrorCode {
+    // 00 - Successful Completion, used by notices
+    SuccessfulCompletion,
+    // 01 - Warning
+    Warning,
+    // 08 - Connection Exception
+    ProtocolViolation,
     // 0A — Feature Not Supported
     FeatureNotSupported,
     // 28 - Invalid Authorization Specification
@@ -663,10 +1029,28 @@ pub enum ErrorCode {
     InvalidPassword,
     // 22
     DataException,
+    InvalidParameterValue,
+    // 25 - Invalid Transaction State
+    ActiveSqlTransaction,
+    NoActiveSqlTransaction,
+    InFailedSqlTransaction,
     // 26
     InvalidSqlStatement,
     // 34
     InvalidCursorName,
+    // 42 - Syntax Error or Access Rule Violation
+    SyntaxError,
+    InsufficientPrivilege,
+    AmbiguousColumn,
+    UndefinedFunction,
+    UndefinedTable,
+    DuplicatePreparedStatement,
+    // 54 - Program Limit Exceeded
+    ProgramLimitExceeded,
+    StatementTooComplex,
+    // 57 - Operator Intervention
+    QueryCanceled,
+    AdminShutdown,
     // XX - Internal Error
     InternalError,
 }
@@ -674,12 +1058,29 @@ pub enum ErrorCode {
 impl Display for ErrorCode {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let string = match self {
+            Self::SuccessfulCompletion => "00000",
+            Self::Warning => "01000",
+            Self::ProtocolViolation => "08P01",
             Self::FeatureNotSupported => "0A000",
             Self::InvalidAuthorizationSpecification => "28000",
             Self::InvalidPassword => "28P01",
             Self::DataException => "22000",
+            Self::InvalidParameterValue => "22023",
+            Self::ActiveSqlTransaction => "25001",
+            Self::NoActiveSqlTransaction => "25P01",
+            Self::InFailedSqlTransaction => "25P02",
             Self::InvalidSqlStatement => "26000",
             Self::InvalidCursorName => "34000",
+            Self::SyntaxError => "42601",
+            Self::InsufficientPrivilege => "42501",
+            Self::AmbiguousColumn => "42702",
+            Self::UndefinedFunction => "42883",
+            Self::UndefinedTable => "42P01",
+            Self::DuplicatePreparedStatement => "42P05",
+            Self::ProgramLimitExceeded => "54000",
+            Self::StatementTooComplex => "54001",
+            Self::QueryCanceled => "57014",
+            Self::AdminShutdown => "57P01",
             Self::InternalError => "XX000",
         };
         write!(f, "{}", string)
@@ -704,18 +1105,40 @@ impl Display for ErrorSeverity {
     }
 }
 
+pub enum NoticeSeverity {
+    // https://www.postgresql.org/docs/14/protocol-error-fields.html
+    Warning,
+    Notice,
+    Debug,
+    Info,
+    Log,
+}
+
+impl Display for NoticeSeverity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let string = match self {
+            Self::Warning => "WARNING",
+            Self::Notice => "NOTICE",
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Log => "LOG",
+        };
+        write!(f, "{}", string)
+    }
+}
+
 pub enum TransactionStatus {
     Idle,
-    // InTransactionBlock,
-    // InFailedTransactionBlock,
+    InTransactionBlock,
+    InFailedTransactionBlock,
 }
 
 impl TransactionStatus {
     pub fn to_byte(&self) -> u8 {
         match self {
             Self::Idle => b'I',
-            // Self::InTransactionBlock => b'T',
-            // Self::InFailedTransactionBlock => b'E',
+            Self::InTransactionBlock => b'T',
+            Self::InFailedTransactionBlock => b'E',
         }
     }
 }
@@ -916,6 +1339,47 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_bind_to_bind_values() -> Result<(), io::Error> {
+        let bind = Bind {
+            portal: "".to_string(),
+            statement: "".to_string(),
+            parameter_formats: vec![Format::Binary, Format::Text, Format::Binary],
+            parameter_values: vec![
+                Some(vec![0, 0, 0, 0, 0, 0, 0, 5]),
+                Some(b"t".to_vec()),
+                Some(b"test".to_vec()),
+                None,
+            ],
+            result_formats: vec![],
+        };
+        let parameters = ParameterDescription::new(vec![PgTypeId::INT8, PgTypeId::BOOL]);
+
+        assert_eq!(
+            bind.to_bind_values(&parameters)?,
+            vec![
+                BindValue::Int64(5),
+                BindValue::Bool(true),
+                BindValue::String("test".to_string()),
+                BindValue::Null,
+            ]
+        );
+
+        // Single format is applied to all parameters
+        let bind = Bind {
+            parameter_formats: vec![Format::Text],
+            parameter_values: vec![Some(b"1".to_vec()), Some(b"2.5".to_vec())],
+            ..bind
+        };
+        let parameters = ParameterDescription::new(vec![PgTypeId::INT2, PgTypeId::FLOAT8]);
+        assert_eq!(
+            bind.to_bind_values(&parameters)?,
+            vec![BindValue::Int64(1), BindValue::Float64(2.5)]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_frontend_message_parse_describe() -> Result<(), io::Error> {
         let buffer = parse_hex_dump(
@@ -996,6 +1460,54 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_frontend_message_function_call() -> Result<(), io::Error> {
+        let buffer = parse_hex_dump(
+            r#"
+            46 00 00 00 1c 00 00 03 ba 00 01 00 01 00 02 00   F...............
+            00 00 04 00 00 00 2a ff ff ff ff 00 00            ......*......
+            "#
+            .to_string(),
+        );
+        let mut cursor = Cursor::new(buffer);
+
+        let message = read_message(&mut cursor).await?;
+        match message {
+            FrontendMessage::FunctionCall(body) => {
+                assert_eq!(
+                    body,
+                    FunctionCall {
+                        function_oid: 954,
+                        argument_formats: vec![Format::Binary],
+                        arguments: vec![Some(vec![0, 0, 0, 42]), None],
+                        result_format: Format::Text,
+                    },
+                )
+            }
+            _ => panic!("Wrong message, must be FunctionCall"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frontend_message_write_function_call_response() -> Result<(), io::Error> {
+        let mut cursor = Cursor::new(vec![]);
+        buffer::write_message(&mut cursor, FunctionCallResponse::new(Some(b"42".to_vec()))).await?;
+        buffer::write_message(&mut cursor, FunctionCallResponse::new(None)).await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            vec![
+                // FunctionCallResponse with value
+                86, 0, 0, 0, 10, 0, 0, 0, 2, 52, 50, // FunctionCallResponse with NULL
+                86, 0, 0, 0, 8, 255, 255, 255, 255
+            ]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_frontend_message_parse_sequence_sync() -> Result<(), io::Error> {
         let buffer = parse_hex_dump(
@@ -1015,6 +1527,24 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_frontend_message_parse_flush() -> Result<(), io::Error> {
+        let buffer = parse_hex_dump(
+            r#"
+            48 00 00 00 04                                    H....
+            "#
+            .to_string(),
+        );
+        let mut cursor = Cursor::new(buffer);
+
+        match read_message(&mut cursor).await? {
+            FrontendMessage::Flush => {}
+            _ => panic!("Wrong message, must be Flush"),
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_frontend_message_write_complete_parse() -> Result<(), io::Error> {
         let mut cursor = Cursor::new(vec![]);
@@ -1026,6 +1556,121 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_startup_message_cancel_request() -> Result<(), io::Error> {
+        let mut buffer = Cursor::new(vec![4, 210, 22, 46, 0, 0, 0, 7, 0, 0, 48, 57]);
+
+        let startup_message = StartupMessage::from(&mut buffer).await?;
+        assert!(startup_message.protocol_version.is_cancel_request());
+        assert!(!startup_message.protocol_version.is_ssl_request());
+
+        let cancel_request = CancelRequest::from(&mut buffer).await?;
+        assert_eq!(
+            cancel_request,
+            CancelRequest {
+                process_id: 7,
+                secret: 12345
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frontend_message_write_backend_key_data() -> Result<(), io::Error> {
+        let mut cursor = Cursor::new(vec![]);
+
+        buffer::write_message(&mut cursor, BackendKeyData::new(7, 12345)).await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            vec![75, 0, 0, 0, 12, 0, 0, 0, 7, 0, 0, 48, 57]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frontend_message_write_notification_response() -> Result<(), io::Error> {
+        let mut cursor = Cursor::new(vec![]);
+
+        buffer::write_message(
+            &mut cursor,
+            NotificationResponse::new(7, "ch".to_string(), "hi".to_string()),
+        )
+        .await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            vec![65, 0, 0, 0, 14, 0, 0, 0, 7, 99, 104, 0, 104, 105, 0]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frontend_message_write_ssl_response() -> Result<(), io::Error> {
+        let mut cursor = Cursor::new(vec![]);
+
+        buffer::write_message(&mut cursor, SSLResponse::Accepted).await?;
+        buffer::write_message(&mut cursor, SSLResponse::Denied).await?;
+
+        assert_eq!(cursor.get_ref()[0..], vec![b'S', b'N']);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frontend_message_write_notice_response() -> Result<(), io::Error> {
+        let mut cursor = Cursor::new(vec![]);
+        buffer::write_message(
+            &mut cursor,
+            NoticeResponse::new(
+                NoticeSeverity::Notice,
+                ErrorCode::SuccessfulCompletion,
+                "test".to_string(),
+            ),
+        )
+        .await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            [
+                b'N', 0, 0, 0, 34, b'S', b'N', b'O', b'T', b'I', b'C', b'E', 0, b'V', b'N', b'O',
+                b'T', b'I', b'C', b'E', 0, b'C', b'0', b'0', b'0', b'0', b'0', 0, b'M', b't', b'e',
+                b's', b't', 0, 0
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frontend_message_write_error_response_with_position() -> Result<(), io::Error> {
+        let mut cursor = Cursor::new(vec![]);
+        buffer::write_message(
+            &mut cursor,
+            ErrorResponse::new(
+                ErrorSeverity::Error,
+                ErrorCode::SyntaxError,
+                "test".to_string(),
+            )
+            .with_position(Some(8)),
+        )
+        .await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            [
+                b'E', 0, 0, 0, 35, b'S', b'E', b'R', b'R', b'O', b'R', 0, b'V', b'E', b'R', b'R',
+                b'O', b'R', 0, b'C', b'4', b'2', b'6', b'0', b'1', 0, b'M', b't', b'e', b's', b't',
+                0, b'P', b'8', 0, 0
+            ]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_frontend_message_write_row_description() -> Result<(), io::Error> {
         let mut cursor = Cursor::new(vec![]);
@@ -1048,4 +1693,60 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_frontend_message_write_row_description_with_formats() -> Result<(), io::Error> {
+        let mut cursor = Cursor::new(vec![]);
+        let desc = RowDescription::new(vec![
+            RowDescriptionField::new("num".to_string(), PgType::get_by_tid(PgTypeId::INT8)),
+            RowDescriptionField::new("str".to_string(), PgType::get_by_tid(PgTypeId::TEXT)),
+        ])
+        .with_formats(&[Format::Binary, Format::Text]);
+        buffer::write_message(&mut cursor, desc).await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            vec![
+                84, 0, 0, 0, 50, 0, 2, 110, 117, 109, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 8, 255,
+                255, 255, 255, 0, 1, 115, 116, 114, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 25, 255, 255,
+                255, 255, 255, 255, 0, 0
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frontend_message_write_copy_out() -> Result<(), io::Error> {
+        let mut cursor = Cursor::new(vec![]);
+        buffer::write_message(&mut cursor, CopyOutResponse::new(Format::Text, 2)).await?;
+        buffer::write_message(&mut cursor, CopyData::new(b"1,a\n".to_vec())).await?;
+        buffer::write_message(&mut cursor, CopyDone::new()).await?;
+
+        assert_eq!(
+            cursor.get_ref()[0..],
+            vec![
+                // CopyOutResponse
+                72, 0, 0, 0, 11, 0, 0, 2, 0, 0, 0, 0, // CopyData
+                100, 0, 0, 0, 8, 49, 44, 97, 10, // CopyDone
+                99, 0, 0, 0, 4
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_by_column() {
+        assert_eq!(Format::by_column(&[], 1), Format::Text);
+        assert_eq!(Format::by_column(&[Format::Binary], 5), Format::Binary);
+        assert_eq!(
+            Format::by_column(&[Format::Text, Format::Binary], 1),
+            Format::Binary
+        );
+        assert_eq!(
+            Format::by_column(&[Format::Text, Format::Binary], 2),
+            Format::Text
+        );
+    }
 }